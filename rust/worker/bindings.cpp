@@ -1,5 +1,7 @@
 // Assumes that chroma-hnswlib is checked out at the same level as chroma
 #include "../../../hnswlib/hnswlib/hnswlib.h"
+#include <cstdint>
+#include <cstring>
 
 class AllowAndDisallowListFilterFunctor : public hnswlib::BaseFilterFunctor
 {
@@ -23,6 +25,82 @@ public:
     }
 };
 
+// Binary vectors are packed into 64 bit words, the first dimension in the least
+// significant bit. The distance parameter is the size of a vector in floats so
+// that getDataByLabel copies out the packed words.
+static size_t binary_words(const void *param)
+{
+    return *((size_t *)param) * sizeof(float) / sizeof(uint64_t);
+}
+
+static uint64_t binary_word(const void *vector, size_t i)
+{
+    // The vectors hnswlib stores are not aligned to words
+    uint64_t word;
+    memcpy(&word, (const char *)vector + i * sizeof(uint64_t), sizeof(uint64_t));
+    return word;
+}
+
+static float HammingDistance(const void *a, const void *b, const void *param)
+{
+    size_t words = binary_words(param);
+    size_t differ = 0;
+    for (size_t i = 0; i < words; i++)
+    {
+        differ += __builtin_popcountll(binary_word(a, i) ^ binary_word(b, i));
+    }
+    return (float)differ;
+}
+
+static float JaccardDistance(const void *a, const void *b, const void *param)
+{
+    size_t words = binary_words(param);
+    size_t both = 0;
+    size_t either = 0;
+    for (size_t i = 0; i < words; i++)
+    {
+        uint64_t word_a = binary_word(a, i);
+        uint64_t word_b = binary_word(b, i);
+        both += __builtin_popcountll(word_a & word_b);
+        either += __builtin_popcountll(word_a | word_b);
+    }
+    if (either == 0)
+    {
+        return 0.0f;
+    }
+    return 1.0f - (float)both / (float)either;
+}
+
+class BinarySpace : public hnswlib::SpaceInterface<float>
+{
+    hnswlib::DISTFUNC<float> fstdistfunc_;
+    size_t data_size_;
+    size_t float_size_;
+
+public:
+    BinarySpace(size_t dim, bool jaccard)
+    {
+        fstdistfunc_ = jaccard ? JaccardDistance : HammingDistance;
+        data_size_ = (dim + 63) / 64 * sizeof(uint64_t);
+        float_size_ = data_size_ / sizeof(float);
+    }
+
+    size_t get_data_size()
+    {
+        return data_size_;
+    }
+
+    hnswlib::DISTFUNC<float> get_dist_func()
+    {
+        return fstdistfunc_;
+    }
+
+    void *get_dist_func_param()
+    {
+        return &float_size_;
+    }
+};
+
 template <typename dist_t, typename data_t = float>
 class Index
 {
@@ -55,6 +133,11 @@ public:
             l2space = new hnswlib::InnerProductSpace(dim);
             normalize = true;
         }
+        if (space_name == "hamming" || space_name == "jaccard")
+        {
+            l2space = new BinarySpace(dim, space_name == "jaccard");
+            normalize = false;
+        }
         appr_alg = NULL;
         index_inited = false;
     }
@@ -114,7 +197,8 @@ public:
             std::runtime_error("Index not inited");
         }
         std::vector<data_t> ret_data = appr_alg->template getDataByLabel<data_t>(id); // This checks if id is deleted
-        for (int i = 0; i < dim; i++)
+        // Binary spaces return their packed words, which are shorter than dim
+        for (size_t i = 0; i < ret_data.size(); i++)
         {
             data[i] = ret_data[i];
         }
@@ -130,7 +214,59 @@ public:
         return 0;
     }
 
-    size_t knn_query(const data_t *query_vector, const size_t k, hnswlib::labeltype *ids, data_t *distance, const hnswlib::labeltype *allowed_ids, const size_t allowed_id_length, const hnswlib::labeltype *disallowed_ids, const size_t disallowed_id_length)
+    // Searches like searchKnn, but explores ef candidates instead of the ef_ of
+    // the index, so queries with their own ef don't change it for the others.
+    std::priority_queue<std::pair<dist_t, hnswlib::labeltype>> search_knn(const data_t *query_vector, const size_t k, const size_t ef, hnswlib::BaseFilterFunctor *filter)
+    {
+        std::priority_queue<std::pair<dist_t, hnswlib::labeltype>> result;
+        if (appr_alg->cur_element_count == 0)
+        {
+            return result;
+        }
+
+        hnswlib::tableint curr_obj = appr_alg->enterpoint_node_;
+        dist_t curr_dist = appr_alg->fstdistfunc_(query_vector, appr_alg->getDataByInternalId(curr_obj), appr_alg->dist_func_param_);
+        for (int level = appr_alg->maxlevel_; level > 0; level--)
+        {
+            bool changed = true;
+            while (changed)
+            {
+                changed = false;
+                unsigned int *data = (unsigned int *)appr_alg->get_linklist(curr_obj, level);
+                int size = appr_alg->getListCount(data);
+                hnswlib::tableint *datal = (hnswlib::tableint *)(data + 1);
+                for (int i = 0; i < size; i++)
+                {
+                    hnswlib::tableint candidate = datal[i];
+                    dist_t d = appr_alg->fstdistfunc_(query_vector, appr_alg->getDataByInternalId(candidate), appr_alg->dist_func_param_);
+                    if (d < curr_dist)
+                    {
+                        curr_dist = d;
+                        curr_obj = candidate;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        auto top_candidates = appr_alg->num_deleted_
+                                  ? appr_alg->template searchBaseLayerST<true>(curr_obj, query_vector, std::max(ef, k), filter)
+                                  : appr_alg->template searchBaseLayerST<false>(curr_obj, query_vector, std::max(ef, k), filter);
+        while (top_candidates.size() > k)
+        {
+            top_candidates.pop();
+        }
+        while (top_candidates.size() > 0)
+        {
+            std::pair<dist_t, hnswlib::tableint> candidate = top_candidates.top();
+            result.push(std::pair<dist_t, hnswlib::labeltype>(candidate.first, appr_alg->getExternalLabel(candidate.second)));
+            top_candidates.pop();
+        }
+        return result;
+    }
+
+    // An ef of 0 searches with the ef of the index.
+    size_t knn_query(const data_t *query_vector, const size_t k, const size_t ef, hnswlib::labeltype *ids, data_t *distance, const hnswlib::labeltype *allowed_ids, const size_t allowed_id_length, const hnswlib::labeltype *disallowed_ids, const size_t disallowed_id_length)
     {
         if (!index_inited)
         {
@@ -154,7 +290,7 @@ public:
             }
         }
         AllowAndDisallowListFilterFunctor filter = AllowAndDisallowListFilterFunctor(allow_list, disallow_list);
-        std::priority_queue<std::pair<dist_t, hnswlib::labeltype>> res = appr_alg->searchKnn(query_vector, k, &filter);
+        std::priority_queue<std::pair<dist_t, hnswlib::labeltype>> res = ef == 0 ? appr_alg->searchKnn(query_vector, k, &filter) : search_knn(query_vector, k, ef, &filter);
         if (res.size() < k)
         {
             // TODO: This is ok and we should return < K results, but for maintining compatibility with the old API we throw an error for now
@@ -206,6 +342,11 @@ extern "C"
         return new Index<float>(space_name, dim);
     }
 
+    void free_index(Index<float> *index)
+    {
+        delete index;
+    }
+
     void init_index(Index<float> *index, const size_t max_elements, const size_t M, const size_t ef_construction, const size_t random_seed, const bool allow_replace_deleted, const bool is_persistent_index, const char *persistence_location)
     {
         index->init_index(max_elements, M, ef_construction, random_seed, allow_replace_deleted, is_persistent_index, persistence_location);
@@ -236,9 +377,9 @@ extern "C"
         return index->mark_deleted(id);
     }
 
-    size_t knn_query(Index<float> *index, const float *query_vector, const size_t k, hnswlib::labeltype *ids, float *distance, const hnswlib::labeltype *allowed_ids, const size_t allowed_id_length, const hnswlib::labeltype *disallowed_ids, const size_t disallowed_id_length)
+    size_t knn_query(Index<float> *index, const float *query_vector, const size_t k, const size_t ef, hnswlib::labeltype *ids, float *distance, const hnswlib::labeltype *allowed_ids, const size_t allowed_id_length, const hnswlib::labeltype *disallowed_ids, const size_t disallowed_id_length)
     {
-        return index->knn_query(query_vector, k, ids, distance, allowed_ids, allowed_id_length, disallowed_ids, disallowed_id_length);
+        return index->knn_query(query_vector, k, ef, ids, distance, allowed_ids, allowed_id_length, disallowed_ids, disallowed_id_length);
     }
 
     int get_ef(Index<float> *index)
@@ -256,6 +397,11 @@ extern "C"
         return index->appr_alg->getCurrentElementCount() - index->appr_alg->getDeletedCount();
     }
 
+    int deleted_count(Index<float> *index)
+    {
+        return index->appr_alg->getDeletedCount();
+    }
+
     size_t capacity(Index<float> *index)
     {
         return index->appr_alg->max_elements_;
@@ -1,6 +1,6 @@
 use super::{
     delta::BlockDelta,
-    delta_storage::{BlockStorage, DataRecordStorage},
+    delta_storage::{BlockStorage, DataRecordStorage, BINARY_DIMENSIONALITY_KEY},
 };
 use crate::{
     blockstore::{
@@ -8,14 +8,23 @@ use crate::{
         key::{CompositeKey, KeyWrapper},
     },
     chroma_proto::UpdateMetadata,
+    distance::unpack_bits,
     segment::DataRecord,
+    types::ScalarEncoding,
 };
 use arrow::array::BinaryArray;
 use arrow::{
-    array::{Array, FixedSizeListArray, Float32Array, StringArray, StructArray},
+    array::{
+        Array, FixedSizeListArray, Float16Array, Float32Array, ListArray, StringArray, StructArray,
+        UInt16Array, UInt8Array,
+    },
+    datatypes::DataType,
     util::bit_util,
 };
+use half::bf16;
+use half::slice::{HalfBitsSliceExt, HalfFloatSliceExt};
 use prost::Message;
+use std::borrow::Cow;
 use std::sync::Arc;
 
 impl ArrowWriteableValue for &DataRecord<'_> {
@@ -46,7 +55,10 @@ impl ArrowWriteableValue for &DataRecord<'_> {
                 };
 
                 id_storage.insert(composite_key.clone(), value.id.to_string());
-                embedding_storage.insert(composite_key.clone(), value.embedding.to_vec());
+                embedding_storage.insert(
+                    composite_key.clone(),
+                    (value.embedding.to_vec(), value.encoding.clone()),
+                );
 
                 match &value.metadata {
                     Some(metadata) => {
@@ -116,21 +128,89 @@ impl<'referred_data> ArrowReadableValue<'referred_data> for DataRecord<'referred
             .unwrap();
 
         // Read out embedding
-        let embedding_arr = as_struct_array
-            .column(1)
-            .as_any()
-            .downcast_ref::<FixedSizeListArray>()
-            .unwrap();
-        let target_vec = embedding_arr.value(index);
-        let embedding_len = target_vec.len();
-        let embedding_values = embedding_arr
-            .values()
-            .as_any()
-            .downcast_ref::<Float32Array>()
-            .unwrap()
-            .values();
-        let embedding =
-            &embedding_values[(index * embedding_len)..(index * embedding_len) + embedding_len];
+        // Blocks whose embeddings all have the same length store them as a fixed
+        // size list, blocks of multi vector embeddings as a list with offsets
+        let embedding_column = as_struct_array.column(1);
+        let (embedding_values, embedding_range) = match embedding_column.data_type() {
+            DataType::List(_) => {
+                let embedding_arr = embedding_column
+                    .as_any()
+                    .downcast_ref::<ListArray>()
+                    .unwrap();
+                let offsets = embedding_arr.value_offsets();
+                (
+                    embedding_arr.values(),
+                    offsets[index] as usize..offsets[index + 1] as usize,
+                )
+            }
+            _ => {
+                let embedding_arr = embedding_column
+                    .as_any()
+                    .downcast_ref::<FixedSizeListArray>()
+                    .unwrap();
+                let embedding_len = embedding_arr.value_length() as usize;
+                (
+                    embedding_arr.values(),
+                    (index * embedding_len)..(index * embedding_len) + embedding_len,
+                )
+            }
+        };
+        let embedding_len = embedding_range.len();
+        let (embedding, encoding) = match embedding_values.data_type() {
+            DataType::Float16 => {
+                let values = embedding_values
+                    .as_any()
+                    .downcast_ref::<Float16Array>()
+                    .unwrap()
+                    .values();
+                (
+                    Cow::Owned(values[embedding_range].to_f32_vec()),
+                    ScalarEncoding::FLOAT16,
+                )
+            }
+            // bfloat16 embeddings are stored as their bits
+            DataType::UInt16 => {
+                let values = embedding_values
+                    .as_any()
+                    .downcast_ref::<UInt16Array>()
+                    .unwrap()
+                    .values();
+                let values: &[bf16] = values[embedding_range].reinterpret_cast();
+                (Cow::Owned(values.to_f32_vec()), ScalarEncoding::BFLOAT16)
+            }
+            // Binary embeddings are packed bits, the dimensionality is kept in
+            // the metadata of the list items
+            DataType::UInt8 => {
+                let values = embedding_values
+                    .as_any()
+                    .downcast_ref::<UInt8Array>()
+                    .unwrap()
+                    .values();
+                let dimensionality = match embedding_column.data_type() {
+                    DataType::FixedSizeList(field, _) => field
+                        .metadata()
+                        .get(BINARY_DIMENSIONALITY_KEY)
+                        .and_then(|dimensionality| dimensionality.parse().ok())
+                        .unwrap_or(embedding_len * 8),
+                    _ => embedding_len * 8,
+                };
+                (
+                    Cow::Owned(unpack_bits(&values[embedding_range], dimensionality)),
+                    ScalarEncoding::BINARY,
+                )
+            }
+            _ => {
+                let values = embedding_values
+                    .as_any()
+                    .downcast_ref::<Float32Array>()
+                    .unwrap()
+                    .values();
+                (
+                    Cow::Borrowed(&values[embedding_range]),
+                    ScalarEncoding::FLOAT32,
+                )
+            }
+        };
 
         // Read out metadata
         let metadata_arr = as_struct_array
@@ -162,6 +242,7 @@ impl<'referred_data> ArrowReadableValue<'referred_data> for DataRecord<'referred
         DataRecord {
             id: &id_arr.value(index),
             embedding,
+            encoding,
             metadata,
             document,
         }
@@ -191,11 +191,12 @@ mod test {
         blockstore::arrow::{block::Block, provider::BlockManager},
         segment::DataRecord,
         storage::{local::LocalStorage, Storage},
-        types::MetadataValue,
+        types::{MetadataValue, ScalarEncoding},
     };
     use arrow::array::Int32Array;
     use rand::{random, Rng};
     use roaring::RoaringBitmap;
+    use std::borrow::Cow;
     use std::collections::HashMap;
 
     #[tokio::test]
@@ -345,19 +346,22 @@ mod test {
         let data = vec![
             DataRecord {
                 id: ids[0],
-                embedding: &embeddings[0],
+                embedding: Cow::Borrowed(&embeddings[0]),
+                encoding: ScalarEncoding::FLOAT32,
                 metadata: metadatas[0].clone(),
                 document: documents[0],
             },
             DataRecord {
                 id: ids[1],
-                embedding: &embeddings[1],
+                embedding: Cow::Borrowed(&embeddings[1]),
+                encoding: ScalarEncoding::FLOAT32,
                 metadata: metadatas[1].clone(),
                 document: documents[1],
             },
             DataRecord {
                 id: ids[2],
-                embedding: &embeddings[2],
+                embedding: Cow::Borrowed(&embeddings[2]),
+                encoding: ScalarEncoding::FLOAT32,
                 metadata: metadatas[2].clone(),
                 document: documents[2],
             },
@@ -373,13 +377,114 @@ mod test {
         for i in 0..3 {
             let read = block.get::<&str, DataRecord>("", ids[i]).unwrap();
             assert_eq!(read.id, ids[i]);
-            assert_eq!(read.embedding, &embeddings[i]);
+            assert_eq!(&*read.embedding, &embeddings[i][..]);
             assert_eq!(read.metadata, metadatas[i]);
             assert_eq!(read.document, documents[i]);
         }
         assert_eq!(size, block.get_size());
     }
 
+    #[tokio::test]
+    async fn test_data_record_half_precision() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let block_manager = BlockManager::new(storage);
+        // Values that are exactly representable in both half precision encodings
+        let embeddings = vec![vec![1.0, -2.5, 0.125], vec![4.0, 0.5, -6.0]];
+        for encoding in [ScalarEncoding::FLOAT16, ScalarEncoding::BFLOAT16] {
+            let delta = block_manager.create::<&str, &DataRecord>();
+            let ids = vec!["embedding_id_0", "embedding_id_1"];
+            for (id, embedding) in ids.iter().zip(embeddings.iter()) {
+                let record = DataRecord {
+                    id,
+                    embedding: Cow::Borrowed(embedding),
+                    encoding: encoding.clone(),
+                    metadata: None,
+                    document: None,
+                };
+                delta.add("", *id, &record);
+            }
+
+            let size = delta.get_size::<&str, &DataRecord>();
+            block_manager.commit::<&str, &DataRecord>(&delta);
+            let block = block_manager.get(&delta.id).await.unwrap();
+            for (id, embedding) in ids.iter().zip(embeddings.iter()) {
+                let read = block.get::<&str, DataRecord>("", id).unwrap();
+                assert_eq!(&*read.embedding, &embedding[..]);
+                assert_eq!(read.encoding, encoding);
+            }
+            assert_eq!(size, block.get_size());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_data_record_binary() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let block_manager = BlockManager::new(storage);
+        // Ten dimensions do not fill the last packed byte
+        let embeddings = vec![
+            vec![1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0],
+            vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        ];
+        let delta = block_manager.create::<&str, &DataRecord>();
+        let ids = vec!["embedding_id_0", "embedding_id_1"];
+        for (id, embedding) in ids.iter().zip(embeddings.iter()) {
+            let record = DataRecord {
+                id,
+                embedding: Cow::Borrowed(embedding),
+                encoding: ScalarEncoding::BINARY,
+                metadata: None,
+                document: None,
+            };
+            delta.add("", *id, &record);
+        }
+
+        let size = delta.get_size::<&str, &DataRecord>();
+        block_manager.commit::<&str, &DataRecord>(&delta);
+        let block = block_manager.get(&delta.id).await.unwrap();
+        for (id, embedding) in ids.iter().zip(embeddings.iter()) {
+            let read = block.get::<&str, DataRecord>("", id).unwrap();
+            assert_eq!(&*read.embedding, &embedding[..]);
+            assert_eq!(read.encoding, ScalarEncoding::BINARY);
+        }
+        assert_eq!(size, block.get_size());
+    }
+
+    #[tokio::test]
+    async fn test_data_record_variable_length() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let block_manager = BlockManager::new(storage);
+        // Multi vector embeddings with one, three and two vectors of two dimensions
+        let embeddings = vec![
+            vec![1.0, 2.0],
+            vec![3.0, 4.0, 5.0, 6.0, 7.0, 8.0],
+            vec![9.0, 10.0, 11.0, 12.0],
+        ];
+        let delta = block_manager.create::<&str, &DataRecord>();
+        let ids = vec!["embedding_id_0", "embedding_id_1", "embedding_id_2"];
+        for (id, embedding) in ids.iter().zip(embeddings.iter()) {
+            let record = DataRecord {
+                id,
+                embedding: Cow::Borrowed(embedding),
+                encoding: ScalarEncoding::FLOAT32,
+                metadata: None,
+                document: None,
+            };
+            delta.add("", *id, &record);
+        }
+
+        let size = delta.get_size::<&str, &DataRecord>();
+        block_manager.commit::<&str, &DataRecord>(&delta);
+        let block = block_manager.get(&delta.id).await.unwrap();
+        for (id, embedding) in ids.iter().zip(embeddings.iter()) {
+            let read = block.get::<&str, DataRecord>("", id).unwrap();
+            assert_eq!(&*read.embedding, &embedding[..]);
+        }
+        assert_eq!(size, block.get_size());
+    }
+
     // #[test]
     // fn test_sizing_uint_key_val() {
     //     let block_provider = ArrowBlockProvider::new();
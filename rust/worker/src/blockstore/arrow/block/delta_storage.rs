@@ -3,23 +3,30 @@ use crate::blockstore::{
     key::{CompositeKey, KeyWrapper},
     Value,
 };
+use crate::distance::pack_bits;
+use crate::types::ScalarEncoding;
 use arrow::{
     array::{
-        Array, ArrayRef, BinaryBuilder, BooleanBuilder, FixedSizeListBuilder, Float32Builder,
-        Int32Array, Int32Builder, ListBuilder, RecordBatch, StringBuilder, StructArray,
-        UInt32Builder,
+        Array, ArrayRef, BinaryBuilder, BooleanBuilder, FixedSizeListArray, Float16Builder,
+        Float32Builder, Int32Array, Int32Builder, Int64Builder, ListArray, ListBuilder,
+        RecordBatch, StringBuilder, StructArray, UInt16Builder, UInt32Builder, UInt8Builder,
     },
+    buffer::OffsetBuffer,
     datatypes::{Field, Fields},
     util::bit_util,
 };
+use half::{bf16, f16};
 use parking_lot::RwLock;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
     fmt,
     fmt::{Debug, Formatter},
     sync::Arc,
 };
 
+// The field metadata key of the dimensionality of packed binary embeddings.
+pub(super) const BINARY_DIMENSIONALITY_KEY: &str = "dimensionality";
+
 #[derive(Clone)]
 pub enum BlockStorage {
     String(StringValueStorage),
@@ -46,6 +53,7 @@ pub enum BlockKeyArrowBuilder {
     String((StringBuilder, StringBuilder)),
     Float32((StringBuilder, Float32Builder)),
     UInt32((StringBuilder, UInt32Builder)),
+    Int64((StringBuilder, Int64Builder)),
 }
 
 impl BlockKeyArrowBuilder {
@@ -91,6 +99,16 @@ impl BlockKeyArrowBuilder {
                 builder.0.append_value(key.prefix);
                 builder.1.append_value(value);
             }
+            KeyWrapper::Int64(value) => {
+                let builder = match self {
+                    BlockKeyArrowBuilder::Int64(builder) => builder,
+                    _ => {
+                        unreachable!("Invariant violation. BlockKeyArrowBuilder should be Int64.")
+                    }
+                };
+                builder.0.append_value(key.prefix);
+                builder.1.append_value(value);
+            }
         }
     }
 
@@ -144,6 +162,18 @@ impl BlockKeyArrowBuilder {
                     (&key_arr as &dyn Array).slice(0, key_arr.len()),
                 )
             }
+            BlockKeyArrowBuilder::Int64((ref mut prefix_builder, ref mut key_builder)) => {
+                let prefix_field = Field::new("prefix", arrow::datatypes::DataType::Utf8, false);
+                let key_field = Field::new("key", arrow::datatypes::DataType::Int64, false);
+                let prefix_arr = prefix_builder.finish();
+                let key_arr = key_builder.finish();
+                (
+                    prefix_field,
+                    (&prefix_arr as &dyn Array).slice(0, prefix_arr.len()),
+                    key_field,
+                    (&key_arr as &dyn Array).slice(0, key_arr.len()),
+                )
+            }
         }
     }
 }
@@ -561,10 +591,20 @@ impl RoaringBitmapStorage {
     }
 }
 
+// An embedding and the encoding it should be stored with.
+type EncodedEmbedding = (Vec<f32>, ScalarEncoding);
+
+// The encoding and shape a range of embeddings is stored with.
+struct EmbeddingLayout {
+    encoding: ScalarEncoding,
+    // Whether every embedding has the same length.
+    fixed_len: bool,
+}
+
 #[derive(Clone)]
 pub(super) struct DataRecordStorage {
     pub(super) id_storage: Arc<RwLock<BTreeMap<CompositeKey, String>>>,
-    pub(super) embedding_storage: Arc<RwLock<BTreeMap<CompositeKey, Vec<f32>>>>,
+    pub(super) embedding_storage: Arc<RwLock<BTreeMap<CompositeKey, EncodedEmbedding>>>,
     pub(super) metadata_storage: Arc<RwLock<BTreeMap<CompositeKey, Option<Vec<u8>>>>>,
     pub(super) document_storage: Arc<RwLock<BTreeMap<CompositeKey, Option<String>>>>,
 }
@@ -610,13 +650,59 @@ impl DataRecordStorage {
     }
 
     pub(super) fn get_embedding_size(&self, start: usize, end: usize) -> usize {
+        let layout = self.get_embedding_layout(start, end);
         let embedding_storage = self.embedding_storage.read();
         let embedding_stream = embedding_storage
             .iter()
             .skip(start)
             .take(end - start)
-            .map(|(_, value)| value);
-        embedding_stream.fold(0, |acc, value| acc + value.len() * 4)
+            .map(|(_, (value, _))| value);
+        embedding_stream.fold(0, |acc, value| {
+            acc + layout.encoding.encoded_size(value.len())
+        })
+    }
+
+    // How the embeddings in the range are stored. Sizing a range and building
+    // a block both go through here so that they always agree. Half precision
+    // and binary encodings are only used when every embedding in the range asks
+    // for the same one, otherwise they are stored as f32 so that no embedding
+    // loses precision. Binary embeddings of different lengths are stored as f32
+    // as well, since the packed bits only record a single dimensionality.
+    // Multi vector embeddings can have any number of vectors, ranges that mix
+    // lengths are stored as a variable size list with offsets.
+    fn get_embedding_layout(&self, start: usize, end: usize) -> EmbeddingLayout {
+        let embedding_storage = self.embedding_storage.read();
+        let mut embeddings = embedding_storage
+            .iter()
+            .skip(start)
+            .take(end - start)
+            .map(|(_, (value, encoding))| (value.len(), encoding));
+        let (first_len, first_encoding) = match embeddings.next() {
+            Some(first) => first,
+            None => {
+                return EmbeddingLayout {
+                    encoding: ScalarEncoding::FLOAT32,
+                    fixed_len: true,
+                }
+            }
+        };
+        let mut fixed_len = true;
+        let mut same_encoding = true;
+        for (len, encoding) in embeddings {
+            fixed_len &= len == first_len;
+            same_encoding &= encoding == first_encoding;
+        }
+        let encoding = match first_encoding {
+            ScalarEncoding::BINARY if same_encoding && fixed_len => ScalarEncoding::BINARY,
+            ScalarEncoding::FLOAT16 | ScalarEncoding::BFLOAT16 if same_encoding => {
+                first_encoding.clone()
+            }
+            _ => ScalarEncoding::FLOAT32,
+        };
+        EmbeddingLayout {
+            encoding,
+            fixed_len,
+        }
     }
 
     pub(super) fn get_metadata_size(&self, start: usize, end: usize) -> usize {
@@ -643,7 +729,7 @@ impl DataRecordStorage {
         let embedding_storage = self.embedding_storage.read();
         embedding_storage
             .iter()
-            .fold(0, |acc, (_, value)| acc + value.len())
+            .fold(0, |acc, (_, (value, _))| acc + value.len())
     }
 
     fn get_value_size(&self, start: usize, end: usize) -> usize {
@@ -652,7 +738,12 @@ impl DataRecordStorage {
             bit_util::round_upto_multiple_of_64(self.get_embedding_size(start, end));
         let metadata_size = bit_util::round_upto_multiple_of_64(self.get_metadata_size(start, end));
         let document_size = bit_util::round_upto_multiple_of_64(self.get_document_size(start, end));
-        let total_size = id_size + embedding_size + metadata_size + document_size;
+        let embedding_offset_size = match self.get_embedding_layout(start, end).fixed_len {
+            true => 0,
+            false => bit_util::round_upto_multiple_of_64((end - start + 1) * 4),
+        };
+        let total_size =
+            id_size + embedding_size + embedding_offset_size + metadata_size + document_size;
 
         total_size
     }
@@ -706,14 +797,30 @@ impl DataRecordStorage {
 
     fn to_arrow(&self) -> (Field, ArrayRef) {
         let item_capacity = self.len();
-        let embedding_len = self.embedding_storage.read().iter().next().unwrap().1.len() as i32;
+        let embedding_len = self
+            .embedding_storage
+            .read()
+            .iter()
+            .next()
+            .unwrap()
+            .1
+             .0
+            .len() as i32;
+        let EmbeddingLayout {
+            encoding: embedding_encoding,
+            fixed_len: fixed_embedding_len,
+        } = self.get_embedding_layout(0, self.len());
+        let mut embedding_offsets_builder = match fixed_embedding_len {
+            true => None,
+            false => {
+                let mut builder = Int32Builder::with_capacity(item_capacity + 1);
+                builder.append_value(0);
+                Some(builder)
+            }
+        };
         let mut id_builder =
             StringBuilder::with_capacity(item_capacity, self.get_id_size(0, self.len()));
-        let mut embedding_builder = FixedSizeListBuilder::with_capacity(
-            Float32Builder::with_capacity(self.get_total_embedding_count()),
-            embedding_len,
-            item_capacity,
-        );
+        let mut embedding_values = Vec::with_capacity(self.get_total_embedding_count());
         let mut metadata_builder =
             BinaryBuilder::with_capacity(item_capacity, self.get_metadata_size(0, self.len()));
         let mut document_builder =
@@ -730,42 +837,109 @@ impl DataRecordStorage {
             .zip(document_storage.iter());
         for ((((_, id), (_, embedding)), (_, metadata)), (_, document)) in iter {
             id_builder.append_value(id);
-            let embedding_arr = embedding_builder.values();
-            for entry in embedding.iter() {
-                embedding_arr.append_value(*entry);
+            embedding_values.extend_from_slice(&embedding.0);
+            if let Some(builder) = embedding_offsets_builder.as_mut() {
+                builder.append_value(embedding_values.len() as i32);
             }
-            embedding_builder.append(true);
             metadata_builder.append_option(metadata.as_deref());
             document_builder.append_option(document.as_deref());
         }
 
         let id_field = Field::new("id", arrow::datatypes::DataType::Utf8, true);
-        let embedding_field = Field::new(
-            "embedding",
-            arrow::datatypes::DataType::FixedSizeList(
-                Arc::new(Field::new(
-                    "item",
-                    arrow::datatypes::DataType::Float32,
+        // bfloat16 has no arrow type so its bits are stored as u16s.
+        let embedding_values: ArrayRef = match embedding_encoding {
+            ScalarEncoding::FLOAT16 => {
+                let mut builder = Float16Builder::with_capacity(embedding_values.len());
+                for value in embedding_values.iter() {
+                    builder.append_value(f16::from_f32(*value));
+                }
+                Arc::new(builder.finish())
+            }
+            ScalarEncoding::BFLOAT16 => {
+                let mut builder = UInt16Builder::with_capacity(embedding_values.len());
+                for value in embedding_values.iter() {
+                    builder.append_value(bf16::from_f32(*value).to_bits());
+                }
+                Arc::new(builder.finish())
+            }
+            // Binary embeddings are packed into bytes, eight dimensions each
+            ScalarEncoding::BINARY => {
+                let mut builder = UInt8Builder::with_capacity(
+                    item_capacity * embedding_encoding.encoded_size(embedding_len as usize),
+                );
+                for embedding in embedding_values.chunks(embedding_len as usize) {
+                    builder.append_slice(&pack_bits(embedding));
+                }
+                Arc::new(builder.finish())
+            }
+            _ => {
+                let mut builder = Float32Builder::with_capacity(embedding_values.len());
+                builder.append_slice(&embedding_values);
+                Arc::new(builder.finish())
+            }
+        };
+        // Packed bits do not record how many of the bits in the last byte are
+        // dimensions, so the dimensionality is kept in the field metadata.
+        let (embedding_item_field, embedding_list_len) = match embedding_encoding {
+            ScalarEncoding::BINARY => (
+                Field::new("item", embedding_values.data_type().clone(), true).with_metadata(
+                    HashMap::from([(
+                        BINARY_DIMENSIONALITY_KEY.to_string(),
+                        embedding_len.to_string(),
+                    )]),
+                ),
+                embedding_encoding.encoded_size(embedding_len as usize) as i32,
+            ),
+            _ => (
+                Field::new("item", embedding_values.data_type().clone(), true),
+                embedding_len,
+            ),
+        };
+        let embedding_item_field = Arc::new(embedding_item_field);
+        let (embedding_field, embedding_arr): (Field, ArrayRef) = match embedding_offsets_builder {
+            Some(mut builder) => {
+                let (_, offsets, _) = builder.finish().into_parts();
+                (
+                    Field::new(
+                        "embedding",
+                        arrow::datatypes::DataType::List(embedding_item_field.clone()),
+                        true,
+                    ),
+                    Arc::new(ListArray::new(
+                        embedding_item_field,
+                        OffsetBuffer::new(offsets),
+                        embedding_values,
+                        None,
+                    )),
+                )
+            }
+            None => (
+                Field::new(
+                    "embedding",
+                    arrow::datatypes::DataType::FixedSizeList(
+                        embedding_item_field.clone(),
+                        embedding_list_len,
+                    ),
                     true,
+                ),
+                Arc::new(FixedSizeListArray::new(
+                    embedding_item_field,
+                    embedding_list_len,
+                    embedding_values,
+                    None,
                 )),
-                embedding_len,
             ),
-            true,
-        );
+        };
         let metadata_field = Field::new("metadata", arrow::datatypes::DataType::Binary, true);
         let document_field = Field::new("document", arrow::datatypes::DataType::Utf8, true);
 
         let id_arr = id_builder.finish();
-        let embedding_arr = embedding_builder.finish();
         let metadata_arr = metadata_builder.finish();
         let document_arr = document_builder.finish();
 
         let struct_arr = StructArray::from(vec![
             (Arc::new(id_field.clone()), Arc::new(id_arr) as ArrayRef),
-            (
-                Arc::new(embedding_field.clone()),
-                Arc::new(embedding_arr) as ArrayRef,
-            ),
+            (Arc::new(embedding_field.clone()), embedding_arr),
             (
                 Arc::new(metadata_field.clone()),
                 Arc::new(metadata_arr) as ArrayRef,
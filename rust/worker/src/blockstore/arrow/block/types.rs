@@ -72,6 +72,39 @@ impl Block {
         None
     }
 
+    /// Returns the values of the keys that are in the block, in key order.
+    /// `keys` must be sorted. Only the values of matching rows are read.
+    pub fn get_many<'me, K: ArrowReadableKey<'me>, V: ArrowReadableValue<'me>>(
+        &'me self,
+        prefix: &str,
+        keys: &[K],
+    ) -> Vec<(K, V)> {
+        let prefix_array = self
+            .data
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let mut res: Vec<(K, V)> = vec![];
+        let mut keys = keys.iter().peekable();
+        for i in 0..self.data.num_rows() {
+            if prefix_array.value(i) != prefix {
+                continue;
+            }
+            let curr_key = K::get(self.data.column(1), i);
+            while keys.next_if(|key| **key < curr_key).is_some() {}
+            match keys.peek() {
+                Some(key) if **key == curr_key => {
+                    res.push((curr_key, V::get(self.data.column(2), i)));
+                    keys.next();
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        res
+    }
+
     pub fn get_prefix<'me, K: ArrowReadableKey<'me>, V: ArrowReadableValue<'me>>(
         &'me self,
         prefix: &str,
@@ -288,6 +288,31 @@ impl<'me, K: ArrowReadableKey<'me> + Into<KeyWrapper>, V: ArrowReadableValue<'me
         }
     }
 
+    /// Returns the values of the keys that are present, in key order. `keys`
+    /// must be sorted. Only the blocks that can hold one of the keys are read,
+    /// each of them once.
+    pub(crate) async fn get_many(
+        &'me self,
+        prefix: &str,
+        keys: &[K],
+    ) -> Result<Vec<(K, V)>, Box<dyn ChromaError>> {
+        let search_keys: Vec<CompositeKey> = keys
+            .iter()
+            .map(|key| CompositeKey::new(prefix.to_string(), key.clone()))
+            .collect();
+        let mut result = Vec::with_capacity(keys.len());
+        for (block_id, range) in self.sparse_index.get_block_ids_for_keys(&search_keys) {
+            let block = match self.get_block(block_id).await {
+                Some(block) => block,
+                None => {
+                    return Err(Box::new(ArrowBlockfileError::BlockNotFound));
+                }
+            };
+            result.extend(block.get_many(prefix, &keys[range]));
+        }
+        Ok(result)
+    }
+
     pub(crate) async fn get_at_index(
         &'me self,
         index: usize,
@@ -450,28 +475,39 @@ impl<'me, K: ArrowReadableKey<'me> + Into<KeyWrapper>, V: ArrowReadableValue<'me
         &'me self,
         prefix: &str,
     ) -> Result<Vec<(&str, K, V)>, Box<dyn ChromaError>> {
-        let block_ids = self.sparse_index.get_block_ids_prefix(prefix);
+        let block_ids = self.get_block_ids_prefix(prefix);
         let mut result: Vec<(&str, K, V)> = vec![];
         for block_id in block_ids {
-            let block_opt = self.get_block(block_id).await;
-            let block = match block_opt {
-                Some(b) => b,
-                None => {
-                    return Err(Box::new(ArrowBlockfileError::BlockNotFound));
-                }
-            };
-            match block.get_prefix(prefix) {
-                Some(data) => {
-                    result.extend(data);
-                }
-                None => {
-                    return Err(Box::new(BlockfileError::NotFoundError));
-                }
-            };
+            result.extend(self.get_prefix_in_block(block_id, prefix).await?);
         }
         Ok(result)
     }
 
+    /// Returns the ids of the blocks that can hold keys with the prefix, in
+    /// key order.
+    pub(crate) fn get_block_ids_prefix(&self, prefix: &str) -> Vec<Uuid> {
+        self.sparse_index.get_block_ids_prefix(prefix)
+    }
+
+    /// Returns the arrow records of one block whose prefix is same as supplied
+    /// prefix.
+    pub(crate) async fn get_prefix_in_block(
+        &'me self,
+        block_id: Uuid,
+        prefix: &str,
+    ) -> Result<Vec<(&str, K, V)>, Box<dyn ChromaError>> {
+        let block = match self.get_block(block_id).await {
+            Some(b) => b,
+            None => {
+                return Err(Box::new(ArrowBlockfileError::BlockNotFound));
+            }
+        };
+        match block.get_prefix(prefix) {
+            Some(data) => Ok(data),
+            None => Err(Box::new(BlockfileError::NotFoundError)),
+        }
+    }
+
     pub(crate) async fn contains(&'me self, prefix: &str, key: K) -> bool {
         let search_key = CompositeKey::new(prefix.to_string(), key.clone());
         let target_block_id = self.sparse_index.get_target_block_id(&search_key);
@@ -523,13 +559,14 @@ mod tests {
         log::config::{self, GrpcLogConfig},
         segment::DataRecord,
         storage::{local::LocalStorage, Storage},
-        types::MetadataValue,
+        types::{MetadataValue, ScalarEncoding},
     };
     use arrow::array::Int32Array;
     use proptest::prelude::*;
     use proptest::test_runner::Config;
     use rand::seq::IteratorRandom;
     use std::{
+        borrow::Cow,
         collections::HashMap,
         time::{SystemTime, UNIX_EPOCH},
     };
@@ -1015,6 +1052,38 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_int64_key() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider = ArrowBlockfileProvider::new(storage);
+
+        let writer = blockfile_provider.create::<i64, u32>().unwrap();
+        let id = writer.id();
+
+        // Keys on both sides of zero and beyond the 32-bit range
+        let n = 2000;
+        for i in 0..n {
+            let key = (i as i64 - 1000) << 32;
+            writer.set("key", key, i as u32).await.unwrap();
+        }
+
+        writer.commit::<i64, u32>().unwrap();
+
+        let reader = blockfile_provider.open::<i64, u32>(&id).await.unwrap();
+        for i in 0..n {
+            let key = (i as i64 - 1000) << 32;
+            let value = reader.get("key", key).await.unwrap();
+            assert_eq!(value, i as u32);
+        }
+        let negatives = reader.get_lt("key", 0).await.unwrap();
+        assert_eq!(negatives.len(), 1000);
+        assert!(negatives.iter().all(|(_, key, _)| *key < 0));
+        let large = reader.get_gte("key", 999 << 32).await.unwrap();
+        assert_eq!(large.len(), 1);
+        assert_eq!(large[0].2, 1999);
+    }
+
     #[tokio::test]
     async fn test_data_record_val() {
         let tmp_dir = tempfile::tempdir().unwrap();
@@ -1029,9 +1098,11 @@ mod tests {
             let key = format!("{:04}", i);
             let mut metdata = HashMap::new();
             metdata.insert("key".to_string(), MetadataValue::Str("value".to_string()));
+            let embedding = [i as f32];
             let value = DataRecord {
                 id: &key,
-                embedding: &[i as f32],
+                embedding: Cow::Borrowed(&embedding),
+                encoding: ScalarEncoding::FLOAT32,
                 document: None,
                 metadata: Some(metdata),
             };
@@ -1048,7 +1119,7 @@ mod tests {
             let key = format!("{:04}", i);
             let value = reader.get("key", &key).await.unwrap();
             assert_eq!(value.id, key);
-            assert_eq!(value.embedding, &[i as f32]);
+            assert_eq!(&*value.embedding, &[i as f32]);
             let metadata = value.metadata.unwrap();
             assert_eq!(metadata.len(), 1);
             assert_eq!(
@@ -1167,4 +1238,104 @@ mod tests {
             assert_eq!(res.2, expected_value);
         }
     }
+
+    #[tokio::test]
+    async fn test_get_many() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider = ArrowBlockfileProvider::new(storage);
+        let writer = blockfile_provider.create::<&str, &Int32Array>().unwrap();
+        let id = writer.id();
+
+        let n = 1200;
+        for i in 0..n {
+            let key = format!("{:04}", i);
+            let value = Int32Array::from(vec![i]);
+            writer.set("key", key.as_str(), &value).await.unwrap();
+        }
+        writer.commit::<&str, &Int32Array>().unwrap();
+
+        let reader = match blockfile_provider
+            .open::<&str, Int32Array>(&id)
+            .await
+            .unwrap()
+        {
+            crate::blockstore::BlockfileReader::ArrowBlockfileReader(reader) => reader,
+            _ => panic!("Unexpected reader type"),
+        };
+        assert_eq!(reader.sparse_index.len(), 3);
+
+        // Keys in the first block only read that block
+        let res = reader.get_many("key", &["0000", "0003"]).await.unwrap();
+        assert_eq!(
+            res,
+            vec![
+                ("0000", Int32Array::from(vec![0])),
+                ("0003", Int32Array::from(vec![3]))
+            ]
+        );
+        assert_eq!(reader.loaded_blocks.lock().len(), 1);
+
+        // Missing keys and keys of other prefixes are skipped
+        let res = reader
+            .get_many("key", &["0005", "0599", "0600", "1199", "1300"])
+            .await
+            .unwrap();
+        assert_eq!(
+            res.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec!["0005", "0599", "0600", "1199"]
+        );
+        for (key, value) in res {
+            assert_eq!(value, Int32Array::from(vec![key.parse::<i32>().unwrap()]));
+        }
+        assert!(reader
+            .get_many("other", &["0005"])
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(reader.get_many("key", &[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_by_prefix_chunks() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider = ArrowBlockfileProvider::new(storage);
+        let writer = blockfile_provider.create::<&str, &Int32Array>().unwrap();
+        let id = writer.id();
+
+        let n = 1200;
+        for i in 0..n {
+            let key = format!("{:04}", i);
+            let value = Int32Array::from(vec![i]);
+            writer.set("key", key.as_str(), &value).await.unwrap();
+        }
+        writer.commit::<&str, &Int32Array>().unwrap();
+
+        for descending in [false, true] {
+            let reader = blockfile_provider
+                .open::<&str, Int32Array>(&id)
+                .await
+                .unwrap();
+            let arrow_reader = match &reader {
+                crate::blockstore::BlockfileReader::ArrowBlockfileReader(reader) => reader,
+                _ => panic!("Unexpected reader type"),
+            };
+            let mut chunks = reader.get_by_prefix_chunks("key", descending);
+            let mut keys = vec![];
+            let mut chunk_count = 0;
+            while let Some(chunk) = chunks.next_chunk().await.unwrap() {
+                chunk_count += 1;
+                // Blocks are read as the chunks are consumed
+                assert_eq!(arrow_reader.loaded_blocks.lock().len(), chunk_count);
+                keys.extend(chunk.into_iter().map(|(key, _)| key.to_string()));
+            }
+            assert_eq!(chunk_count, 3);
+            let mut expected: Vec<String> = (0..n).map(|i| format!("{:04}", i)).collect();
+            if descending {
+                expected.reverse();
+            }
+            assert_eq!(keys, expected);
+        }
+    }
 }
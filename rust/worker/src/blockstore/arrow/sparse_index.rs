@@ -4,6 +4,7 @@ use core::panic;
 use parking_lot::Mutex;
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
+use std::ops::Range;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -117,6 +118,35 @@ impl SparseIndex {
         panic!("No blocks in the sparse index");
     }
 
+    /// Groups sorted search keys by the block that can hold them. Each such block
+    /// is returned once, in order, with the range of `search_keys` it can hold.
+    pub(super) fn get_block_ids_for_keys(
+        &self,
+        search_keys: &[CompositeKey],
+    ) -> Vec<(Uuid, Range<usize>)> {
+        let forward = self.forward.lock();
+        let mut blocks = forward.iter().peekable();
+        let mut current_block_id = None;
+        let mut block_ids: Vec<(Uuid, Range<usize>)> = vec![];
+        for (i, search_key) in search_keys.iter().enumerate() {
+            while let Some((_, block_id)) = blocks.next_if(|(delimiter, _)| match delimiter {
+                SparseIndexDelimiter::Start => true,
+                SparseIndexDelimiter::Key(key) => key <= search_key,
+            }) {
+                current_block_id = Some(*block_id);
+            }
+            let block_id = match current_block_id {
+                Some(block_id) => block_id,
+                None => panic!("No blocks in the sparse index"),
+            };
+            match block_ids.last_mut() {
+                Some((last_block_id, range)) if *last_block_id == block_id => range.end = i + 1,
+                _ => block_ids.push((block_id, i..i + 1)),
+            }
+        }
+        block_ids
+    }
+
     pub(super) fn get_block_ids_prefix(&self, prefix: &str) -> Vec<Uuid> {
         let lock_guard = self.forward.lock();
         let mut curr_iter = lock_guard.iter();
@@ -418,6 +448,9 @@ impl SparseIndex {
                     KeyWrapper::Uint32(u) => {
                         delta.add(&k.prefix, *u, block_id.to_string().as_str());
                     }
+                    KeyWrapper::Int64(i) => {
+                        delta.add(&k.prefix, *i, block_id.to_string().as_str());
+                    }
                 },
             }
         }
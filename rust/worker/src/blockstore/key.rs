@@ -8,6 +8,7 @@ pub(crate) enum KeyWrapper {
     Float32(f32),
     Bool(bool),
     Uint32(u32),
+    Int64(i64),
 }
 
 impl KeyWrapper {
@@ -18,6 +19,7 @@ impl KeyWrapper {
             KeyWrapper::Float32(_) => 4,
             KeyWrapper::Bool(_) => 1,
             KeyWrapper::Uint32(_) => 4,
+            KeyWrapper::Int64(_) => 8,
         }
     }
 }
@@ -82,6 +84,21 @@ impl From<&KeyWrapper> for u32 {
     }
 }
 
+impl Into<KeyWrapper> for i64 {
+    fn into(self) -> KeyWrapper {
+        KeyWrapper::Int64(self)
+    }
+}
+
+impl From<&KeyWrapper> for i64 {
+    fn from(key: &KeyWrapper) -> Self {
+        match key {
+            KeyWrapper::Int64(i) => *i,
+            _ => panic!("Invalid conversion"),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct CompositeKey {
     pub(super) prefix: String,
@@ -142,6 +159,10 @@ impl Ord for CompositeKey {
                     KeyWrapper::Uint32(u2) => u1.cmp(u2),
                     _ => panic!("Invalid comparison"),
                 },
+                KeyWrapper::Int64(i1) => match &other.key {
+                    KeyWrapper::Int64(i2) => i1.cmp(i2),
+                    _ => panic!("Invalid comparison"),
+                },
             }
         } else {
             self.prefix.cmp(&other.prefix)
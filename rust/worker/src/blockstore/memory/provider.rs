@@ -62,8 +62,9 @@ mod tests {
     use crate::{
         execution::data::data_chunk::Chunk,
         segment::DataRecord,
-        types::{LogRecord, Operation, OperationRecord},
+        types::{LogRecord, Operation, OperationRecord, ScalarEncoding},
     };
+    use std::borrow::Cow;
 
     use super::*;
 
@@ -76,6 +77,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![1.0, 2.0, 3.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -87,6 +89,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: Some(vec![4.0, 5.0, 6.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -98,6 +101,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -109,7 +113,8 @@ mod tests {
             .iter()
             .map(|record| DataRecord {
                 id: &record.0.record.id,
-                embedding: record.0.record.embedding.as_ref().unwrap(),
+                embedding: Cow::Borrowed(record.0.record.embedding.as_ref().unwrap()),
+                encoding: ScalarEncoding::FLOAT32,
                 document: None,
                 metadata: None,
             })
@@ -95,6 +95,20 @@ impl<
         }
     }
 
+    pub(crate) fn get_many(
+        &'storage self,
+        prefix: &str,
+        keys: &[K],
+    ) -> Result<Vec<(K, V)>, Box<dyn ChromaError>> {
+        Ok(keys
+            .iter()
+            .filter_map(|key| {
+                V::read_from_storage(prefix, key.clone().into(), &self.storage)
+                    .map(|value| (key.clone(), value))
+            })
+            .collect())
+    }
+
     pub(crate) fn get_by_prefix(
         &'storage self,
         prefix: &str,
@@ -208,7 +222,8 @@ mod tests {
     use super::*;
     use crate::execution::data::data_chunk::Chunk;
     use crate::segment::DataRecord;
-    use crate::types::{LogRecord, Operation, OperationRecord};
+    use crate::types::{LogRecord, Operation, OperationRecord, ScalarEncoding};
+    use std::borrow::Cow;
 
     #[test]
     fn test_blockfile_string() {
@@ -251,7 +266,8 @@ mod tests {
         let embedding = vec![1.0, 2.0, 3.0];
         let record = DataRecord {
             id: &id,
-            embedding: &embedding,
+            embedding: Cow::Borrowed(&embedding),
+            encoding: ScalarEncoding::FLOAT32,
             metadata: None,
             document: None,
         };
@@ -263,6 +279,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![1.0, 2.0, 3.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -274,6 +291,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: Some(vec![4.0, 5.0, 6.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -285,6 +303,7 @@ mod tests {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -296,7 +315,8 @@ mod tests {
             .iter()
             .map(|record| DataRecord {
                 id: &record.0.record.id,
-                embedding: record.0.record.embedding.as_ref().unwrap(),
+                embedding: Cow::Borrowed(record.0.record.embedding.as_ref().unwrap()),
+                encoding: ScalarEncoding::FLOAT32,
                 document: None,
                 metadata: None,
             })
@@ -333,7 +353,7 @@ mod tests {
     fn test_u32_key() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
+        let _ = writer.set("prefix", 1u32, "value1");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -380,9 +400,9 @@ mod tests {
     fn test_get_gt_int_none_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -395,9 +415,9 @@ mod tests {
     fn test_get_gt_int_all_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -419,9 +439,9 @@ mod tests {
     fn test_get_gt_int_some_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -500,9 +520,9 @@ mod tests {
     fn test_get_gte_int_none_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -515,9 +535,9 @@ mod tests {
     fn test_get_gte_int_all_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -539,9 +559,9 @@ mod tests {
     fn test_get_gte_int_some_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -620,9 +640,9 @@ mod tests {
     fn test_get_lt_int_none_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -635,9 +655,9 @@ mod tests {
     fn test_get_lt_int_all_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -659,9 +679,9 @@ mod tests {
     fn test_get_lt_int_some_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -740,9 +760,9 @@ mod tests {
     fn test_get_lte_int_none_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -755,9 +775,9 @@ mod tests {
     fn test_get_lte_int_all_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -779,9 +799,9 @@ mod tests {
     fn test_get_lte_int_some_returned() {
         let storage_manager = StorageManager::new();
         let writer = MemoryBlockfileWriter::new(storage_manager.clone());
-        let _ = writer.set("prefix", 1, "value1");
-        let _ = writer.set("prefix", 2, "value2");
-        let _ = writer.set("prefix", 3, "value3");
+        let _ = writer.set("prefix", 1u32, "value1");
+        let _ = writer.set("prefix", 2u32, "value2");
+        let _ = writer.set("prefix", 3u32, "value3");
         let _ = writer.commit();
 
         let reader: MemoryBlockfileReader<u32, &str> =
@@ -2,11 +2,13 @@ use crate::{
     blockstore::key::{CompositeKey, KeyWrapper},
     errors::ChromaError,
     segment::DataRecord,
+    types::ScalarEncoding,
 };
 use arrow::array::Int32Array;
 use parking_lot::RwLock;
 use roaring::RoaringBitmap;
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, HashMap},
     sync::Arc,
 };
@@ -891,7 +893,8 @@ impl<'referred_data> Readable<'referred_data> for DataRecord<'referred_data> {
         // TODO: don't unwrap
         Some(DataRecord {
             id: &id.unwrap(),
-            embedding: &embedding.unwrap(),
+            embedding: Cow::Borrowed(embedding.unwrap()),
+            encoding: ScalarEncoding::FLOAT32,
             metadata: None,
             document: None,
         })
@@ -912,7 +915,8 @@ impl<'referred_data> Readable<'referred_data> for DataRecord<'referred_data> {
                     k,
                     DataRecord {
                         id,
-                        embedding,
+                        embedding: Cow::Borrowed(embedding),
+                        encoding: ScalarEncoding::FLOAT32,
                         metadata: None,
                         document: None,
                     },
@@ -937,7 +941,8 @@ impl<'referred_data> Readable<'referred_data> for DataRecord<'referred_data> {
                     k,
                     DataRecord {
                         id,
-                        embedding,
+                        embedding: Cow::Borrowed(embedding),
+                        encoding: ScalarEncoding::FLOAT32,
                         metadata: None,
                         document: None,
                     },
@@ -962,7 +967,8 @@ impl<'referred_data> Readable<'referred_data> for DataRecord<'referred_data> {
                     k,
                     DataRecord {
                         id,
-                        embedding,
+                        embedding: Cow::Borrowed(embedding),
+                        encoding: ScalarEncoding::FLOAT32,
                         metadata: None,
                         document: None,
                     },
@@ -987,7 +993,8 @@ impl<'referred_data> Readable<'referred_data> for DataRecord<'referred_data> {
                     k,
                     DataRecord {
                         id,
-                        embedding,
+                        embedding: Cow::Borrowed(embedding),
+                        encoding: ScalarEncoding::FLOAT32,
                         metadata: None,
                         document: None,
                     },
@@ -1012,7 +1019,8 @@ impl<'referred_data> Readable<'referred_data> for DataRecord<'referred_data> {
                     k,
                     DataRecord {
                         id,
-                        embedding,
+                        embedding: Cow::Borrowed(embedding),
+                        encoding: ScalarEncoding::FLOAT32,
                         metadata: None,
                         document: None,
                     },
@@ -1032,7 +1040,8 @@ impl<'referred_data> Readable<'referred_data> for DataRecord<'referred_data> {
             k,
             DataRecord {
                 id,
-                embedding,
+                embedding: Cow::Borrowed(embedding),
+                encoding: ScalarEncoding::FLOAT32,
                 metadata: None,
                 document: None,
             },
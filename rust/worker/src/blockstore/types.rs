@@ -77,6 +77,12 @@ impl Key for u32 {
     }
 }
 
+impl Key for i64 {
+    fn get_size(&self) -> usize {
+        8
+    }
+}
+
 pub(crate) trait Value: Clone {
     fn get_size(&self) -> usize;
 }
@@ -257,6 +263,19 @@ impl<
         }
     }
 
+    /// Returns the values of the keys that are present, in key order. `keys`
+    /// must be sorted.
+    pub(crate) async fn get_many(
+        &'referred_data self,
+        prefix: &str,
+        keys: &[K],
+    ) -> Result<Vec<(K, V)>, Box<dyn ChromaError>> {
+        match self {
+            BlockfileReader::MemoryBlockfileReader(reader) => reader.get_many(prefix, keys),
+            BlockfileReader::ArrowBlockfileReader(reader) => reader.get_many(prefix, keys).await,
+        }
+    }
+
     pub(crate) async fn contains(&'referred_data self, prefix: &str, key: K) -> bool {
         match self {
             BlockfileReader::ArrowBlockfileReader(reader) => reader.contains(prefix, key).await,
@@ -346,6 +365,30 @@ impl<
         }
     }
 
+    /// Returns the records with the prefix in key order, or in reverse, one
+    /// block at a time. Blocks are only read as the chunks are consumed.
+    pub(crate) fn get_by_prefix_chunks(
+        &'referred_data self,
+        prefix: &str,
+        descending: bool,
+    ) -> PrefixChunks<'referred_data, K, V> {
+        let mut block_ids = match self {
+            BlockfileReader::MemoryBlockfileReader(_) => vec![],
+            BlockfileReader::ArrowBlockfileReader(reader) => reader.get_block_ids_prefix(prefix),
+        };
+        // The next block to read is popped from the end.
+        if !descending {
+            block_ids.reverse();
+        }
+        PrefixChunks {
+            reader: self,
+            prefix: prefix.to_string(),
+            descending,
+            block_ids,
+            done: false,
+        }
+    }
+
     pub(crate) fn id(&self) -> uuid::Uuid {
         match self {
             BlockfileReader::MemoryBlockfileReader(reader) => reader.id(),
@@ -353,3 +396,63 @@ impl<
         }
     }
 }
+
+/// The records of one prefix of a blockfile, read a block at a time by
+/// `BlockfileReader::get_by_prefix_chunks`.
+pub(crate) struct PrefixChunks<
+    'me,
+    K: Key + Into<KeyWrapper> + ArrowReadableKey<'me>,
+    V: Value + ArrowReadableValue<'me>,
+> {
+    reader: &'me BlockfileReader<'me, K, V>,
+    prefix: String,
+    descending: bool,
+    block_ids: Vec<uuid::Uuid>,
+    done: bool,
+}
+
+impl<
+        'referred_data,
+        K: Key
+            + Into<KeyWrapper>
+            + From<&'referred_data KeyWrapper>
+            + ArrowReadableKey<'referred_data>,
+        V: Value + Readable<'referred_data> + ArrowReadableValue<'referred_data>,
+    > PrefixChunks<'referred_data, K, V>
+{
+    /// Returns the records of the next block, none once every block was read.
+    /// A memory blockfile is returned in a single chunk.
+    pub(crate) async fn next_chunk(&mut self) -> Result<Option<Vec<(K, V)>>, Box<dyn ChromaError>> {
+        let mut records: Vec<(K, V)> = match self.reader {
+            BlockfileReader::MemoryBlockfileReader(reader) => {
+                if self.done {
+                    return Ok(None);
+                }
+                self.done = true;
+                // The memory reader returns an error when no key has the prefix.
+                reader
+                    .get_by_prefix(&self.prefix)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(_, key, value)| (key, value))
+                    .collect()
+            }
+            BlockfileReader::ArrowBlockfileReader(reader) => {
+                let block_id = match self.block_ids.pop() {
+                    Some(block_id) => block_id,
+                    None => return Ok(None),
+                };
+                reader
+                    .get_prefix_in_block(block_id, &self.prefix)
+                    .await?
+                    .into_iter()
+                    .map(|(_, key, value)| (key, value))
+                    .collect()
+            }
+        };
+        if self.descending {
+            records.reverse();
+        }
+        Ok(Some(records))
+    }
+}
@@ -47,6 +47,7 @@ pub(crate) struct CompactionManager {
     compaction_manager_queue_size: usize,
     compaction_interval: Duration,
     min_compaction_size: usize,
+    hnsw_rebuild_tombstone_ratio: f32,
 }
 
 #[derive(Error, Debug)]
@@ -74,6 +75,7 @@ impl CompactionManager {
         compaction_manager_queue_size: usize,
         compaction_interval: Duration,
         min_compaction_size: usize,
+        hnsw_rebuild_tombstone_ratio: f32,
     ) -> Self {
         CompactionManager {
             system: None,
@@ -87,6 +89,7 @@ impl CompactionManager {
             compaction_manager_queue_size,
             compaction_interval,
             min_compaction_size,
+            hnsw_rebuild_tombstone_ratio,
         }
     }
 
@@ -116,6 +119,7 @@ impl CompactionManager {
                     None,
                     None,
                     Arc::new(AtomicU32::new(0)),
+                    self.hnsw_rebuild_tombstone_ratio,
                 );
 
                 match orchestrator.run().await {
@@ -203,6 +207,7 @@ impl Configurable<CompactionServiceConfig> for CompactionManager {
         let max_concurrent_jobs = config.compactor.max_concurrent_jobs;
         let compaction_manager_queue_size = config.compactor.compaction_manager_queue_size;
         let min_compaction_size = config.compactor.min_compaction_size;
+        let hnsw_rebuild_tombstone_ratio = config.compactor.hnsw_rebuild_tombstone_ratio;
 
         let assignment_policy_config = &config.assignment_policy;
         let assignment_policy = match crate::assignment::from_config(assignment_policy_config).await
@@ -222,8 +227,8 @@ impl Configurable<CompactionServiceConfig> for CompactionManager {
             assignment_policy,
         );
 
-        // TODO: real path
-        let path = PathBuf::from("~/tmp");
+        let hnsw_provider_config = &config.hnsw_provider;
+        let path = PathBuf::from(&hnsw_provider_config.hnsw_temporary_path);
         // TODO: blockfile proivder should be injected somehow
         // TODO: hnsw index provider should be injected somehow
         Ok(CompactionManager::new(
@@ -232,10 +237,15 @@ impl Configurable<CompactionServiceConfig> for CompactionManager {
             sysdb,
             storage.clone(),
             BlockfileProvider::new_arrow(storage.clone()),
-            HnswIndexProvider::new(storage.clone(), path),
+            HnswIndexProvider::new(
+                storage.clone(),
+                path,
+                hnsw_provider_config.hnsw_cache_size_bytes,
+            ),
             compaction_manager_queue_size,
             Duration::from_secs(compaction_interval_sec),
             min_compaction_size,
+            hnsw_rebuild_tombstone_ratio,
         ))
     }
 }
@@ -338,6 +348,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: Some(vec![1.0, 2.0, 3.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -359,6 +370,7 @@ mod tests {
                         id: "embedding_id_2".to_string(),
                         embedding: Some(vec![4.0, 5.0, 6.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -498,10 +510,15 @@ mod tests {
             sysdb,
             storage.clone(),
             BlockfileProvider::new_arrow(storage.clone()),
-            HnswIndexProvider::new(storage, PathBuf::from(tmpdir.path().to_str().unwrap())),
+            HnswIndexProvider::new(
+                storage,
+                PathBuf::from(tmpdir.path().to_str().unwrap()),
+                1024 * 1024 * 1024,
+            ),
             compaction_manager_queue_size,
             compaction_interval,
             min_compaction_size,
+            0.2,
         );
 
         let system = System::new();
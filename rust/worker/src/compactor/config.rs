@@ -6,4 +6,7 @@ pub(crate) struct CompactorConfig {
     pub(crate) max_concurrent_jobs: usize,
     pub(crate) compaction_interval_sec: u64,
     pub(crate) min_compaction_size: usize,
+    // The fraction of deleted elements in an hnsw index above which compaction
+    // rebuilds the index from the record segment.
+    pub(crate) hnsw_rebuild_tombstone_ratio: f32,
 }
@@ -227,6 +227,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: None,
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -248,6 +249,7 @@ mod tests {
                         id: "embedding_id_2".to_string(),
                         embedding: None,
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -379,6 +381,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: None,
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -398,6 +401,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: None,
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -417,6 +421,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: None,
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -436,6 +441,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: None,
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -103,6 +103,7 @@ pub(crate) struct QueryServiceConfig {
     pub(crate) storage: crate::storage::config::StorageConfig,
     pub(crate) log: crate::log::config::LogConfig,
     pub(crate) dispatcher: crate::execution::config::DispatcherConfig,
+    pub(crate) hnsw_provider: crate::index::config::HnswProviderConfig,
 }
 
 #[derive(Deserialize)]
@@ -127,6 +128,7 @@ pub(crate) struct CompactionServiceConfig {
     pub(crate) storage: crate::storage::config::StorageConfig,
     pub(crate) log: crate::log::config::LogConfig,
     pub(crate) dispatcher: crate::execution::config::DispatcherConfig,
+    pub(crate) hnsw_provider: crate::index::config::HnswProviderConfig,
     pub(crate) compactor: crate::compactor::config::CompactorConfig,
 }
 
@@ -188,6 +190,9 @@ mod tests {
                         num_worker_threads: 4
                         dispatcher_queue_size: 100
                         worker_queue_size: 100
+                    hnsw_provider:
+                        hnsw_temporary_path: "/tmp/chroma/hnsw"
+                        hnsw_cache_size_bytes: 1073741824
 
                 compaction_service:
                     service_name: "compaction-service"
@@ -224,11 +229,15 @@ mod tests {
                         num_worker_threads: 4
                         dispatcher_queue_size: 100
                         worker_queue_size: 100
+                    hnsw_provider:
+                        hnsw_temporary_path: "/tmp/chroma/hnsw"
+                        hnsw_cache_size_bytes: 1073741824
                     compactor:
                         compaction_manager_queue_size: 1000
                         max_concurrent_jobs: 100
                         compaction_interval_sec: 60
                         min_compaction_size: 10
+                        hnsw_rebuild_tombstone_ratio: 0.2
                 "#,
             );
             let config = RootConfig::load();
@@ -285,6 +294,9 @@ mod tests {
                         num_worker_threads: 4
                         dispatcher_queue_size: 100
                         worker_queue_size: 100
+                    hnsw_provider:
+                        hnsw_temporary_path: "/tmp/chroma/hnsw"
+                        hnsw_cache_size_bytes: 1073741824
 
                 compaction_service:
                     service_name: "compaction-service"
@@ -321,11 +333,15 @@ mod tests {
                         num_worker_threads: 4
                         dispatcher_queue_size: 100
                         worker_queue_size: 100
+                    hnsw_provider:
+                        hnsw_temporary_path: "/tmp/chroma/hnsw"
+                        hnsw_cache_size_bytes: 1073741824
                     compactor:
                         compaction_manager_queue_size: 1000
                         max_concurrent_jobs: 100
                         compaction_interval_sec: 60
                         min_compaction_size: 10
+                        hnsw_rebuild_tombstone_ratio: 0.2
                 "#,
             );
             let config = RootConfig::load_from_path("random_path.yaml");
@@ -400,6 +416,9 @@ mod tests {
                         num_worker_threads: 4
                         dispatcher_queue_size: 100
                         worker_queue_size: 100
+                    hnsw_provider:
+                        hnsw_temporary_path: "/tmp/chroma/hnsw"
+                        hnsw_cache_size_bytes: 1073741824
 
                 compaction_service:
                     service_name: "compaction-service"
@@ -436,11 +455,15 @@ mod tests {
                         num_worker_threads: 4
                         dispatcher_queue_size: 100
                         worker_queue_size: 100
+                    hnsw_provider:
+                        hnsw_temporary_path: "/tmp/chroma/hnsw"
+                        hnsw_cache_size_bytes: 1073741824
                     compactor:
                         compaction_manager_queue_size: 1000
                         max_concurrent_jobs: 100
                         compaction_interval_sec: 60
                         min_compaction_size: 10
+                        hnsw_rebuild_tombstone_ratio: 0.2
                 "#,
             );
             let config = RootConfig::load();
@@ -509,6 +532,9 @@ mod tests {
                         num_worker_threads: 4
                         dispatcher_queue_size: 100
                         worker_queue_size: 100
+                    hnsw_provider:
+                        hnsw_temporary_path: "/tmp/chroma/hnsw"
+                        hnsw_cache_size_bytes: 1073741824
 
                 compaction_service:
                     service_name: "compaction-service"
@@ -537,11 +563,15 @@ mod tests {
                         num_worker_threads: 4
                         dispatcher_queue_size: 100
                         worker_queue_size: 100
+                    hnsw_provider:
+                        hnsw_temporary_path: "/tmp/chroma/hnsw"
+                        hnsw_cache_size_bytes: 1073741824
                     compactor:
                         compaction_manager_queue_size: 1000
                         max_concurrent_jobs: 100
                         compaction_interval_sec: 60
                         min_compaction_size: 10
+                        hnsw_rebuild_tombstone_ratio: 0.2
                 "#,
             );
             let config = RootConfig::load();
@@ -0,0 +1,119 @@
+// Binary vectors pack eight dimensions into each byte, the first dimension in the
+// least significant bit. Any nonzero value is a set bit.
+
+/// Packs a vector into bits, eight dimensions per byte.
+pub fn pack_bits(vector: &[f32]) -> Vec<u8> {
+    vector
+        .chunks(8)
+        .map(|chunk| {
+            chunk.iter().enumerate().fold(0_u8, |byte, (i, value)| {
+                byte | (((*value != 0.0) as u8) << i)
+            })
+        })
+        .collect()
+}
+
+/// Unpacks the first `dimensionality` bits into a vector of zeros and ones.
+pub fn unpack_bits(bytes: &[u8], dimensionality: usize) -> Vec<f32> {
+    (0..dimensionality)
+        .map(|i| ((bytes[i / 8] >> (i % 8)) & 1) as f32)
+        .collect()
+}
+
+/// Packs a vector into 64 bit words, the layout of the binary hnsw spaces. The
+/// little endian bytes of the words are `pack_bits` padded to a whole word.
+pub fn pack_words(vector: &[f32]) -> Vec<u64> {
+    vector.chunks(64).map(pack_word).collect()
+}
+
+/// Unpacks the first `dimensionality` bits of words packed with `pack_words`.
+pub fn unpack_words(words: &[u64], dimensionality: usize) -> Vec<f32> {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    unpack_bits(&bytes, dimensionality)
+}
+
+#[inline(always)]
+fn pack_word(chunk: &[f32]) -> u64 {
+    chunk.iter().enumerate().fold(0_u64, |word, (i, value)| {
+        word | (((*value != 0.0) as u64) << i)
+    })
+}
+
+/// Sets every nonzero value of a vector to one.
+pub fn binarize(vector: &[f32]) -> Vec<f32> {
+    vector
+        .iter()
+        .map(|value| (*value != 0.0) as u8 as f32)
+        .collect()
+}
+
+/// Counts the bits set in both `a` and `b` and the bits set in either of them.
+#[inline(always)]
+pub(crate) fn and_or_count(a: &[u8], b: &[u8]) -> (u32, u32) {
+    let mut and = 0;
+    let mut or = 0;
+    let mut words_a = a.chunks_exact(8);
+    let mut words_b = b.chunks_exact(8);
+    for (word_a, word_b) in (&mut words_a).zip(&mut words_b) {
+        let word_a = u64::from_le_bytes(word_a.try_into().unwrap());
+        let word_b = u64::from_le_bytes(word_b.try_into().unwrap());
+        and += (word_a & word_b).count_ones();
+        or += (word_a | word_b).count_ones();
+    }
+    for (byte_a, byte_b) in words_a.remainder().iter().zip(words_b.remainder()) {
+        and += (byte_a & byte_b).count_ones();
+        or += (byte_a | byte_b).count_ones();
+    }
+    (and, or)
+}
+
+/// Counts like `and_or_count` for vectors that are not packed. The vectors are
+/// packed a word at a time as they are compared, so nothing is allocated.
+pub(crate) fn and_or_count_unpacked(a: &[f32], b: &[f32]) -> (u32, u32) {
+    let mut and = 0;
+    let mut or = 0;
+    for (chunk_a, chunk_b) in a.chunks(64).zip(b.chunks(64)) {
+        let word_a = pack_word(chunk_a);
+        let word_b = pack_word(chunk_b);
+        and += (word_a & word_b).count_ones();
+        or += (word_a | word_b).count_ones();
+    }
+    (and, or)
+}
+
+/// # Safety
+/// The cpu must support popcnt.
+// The body is the scalar kernel, the target feature lets count_ones compile to
+// the popcnt instruction instead of a bit twiddling sequence.
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+#[target_feature(enable = "popcnt")]
+pub unsafe fn and_or_count_popcnt(a: &[u8], b: &[u8]) -> (u32, u32) {
+    and_or_count(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_bits_round_trip() {
+        let vector = vec![1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, -2.5];
+        let packed = pack_bits(&vector);
+        assert_eq!(packed, vec![0b0101_1001, 0b0000_0110]);
+        assert_eq!(unpack_bits(&packed, vector.len()), binarize(&vector));
+
+        let words = pack_words(&vector);
+        assert_eq!(words, vec![0b0000_0110_0101_1001]);
+        assert_eq!(unpack_words(&words, vector.len()), binarize(&vector));
+    }
+
+    #[test]
+    fn test_and_or_count_unpacked() {
+        let a: Vec<f32> = (0..130).map(|i| (i % 3 == 0) as u8 as f32).collect();
+        let b: Vec<f32> = (0..130).map(|i| (i % 2 == 0) as u8 as f32).collect();
+        assert_eq!(
+            and_or_count_unpacked(&a, &b),
+            and_or_count(&pack_bits(&a), &pack_bits(&b))
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::OnceLock;
+
+type Kernel = unsafe fn(&[f32], &[f32]) -> f32;
+type BinaryKernel = unsafe fn(&[u8], &[u8]) -> (u32, u32);
+
+/// The DistanceKernels struct.
+/// # Description
+/// A table of distance kernels that were compiled for one instruction set.
+/// # Notes
+/// The kernels read both slices up to the length of the first one, callers must
+/// check that the lengths match before calling them.
+pub(crate) struct DistanceKernels {
+    pub(crate) name: &'static str,
+    pub(crate) euclidean: Kernel,
+    pub(crate) cosine: Kernel,
+    pub(crate) inner_product: Kernel,
+}
+
+static SCALAR: DistanceKernels = DistanceKernels {
+    name: "scalar",
+    euclidean: euclidean_distance,
+    cosine: cosine_distance,
+    inner_product,
+};
+
+#[cfg(target_arch = "x86_64")]
+static AVX512: DistanceKernels = DistanceKernels {
+    name: "avx512",
+    euclidean: super::distance_avx512::euclidean_distance,
+    cosine: super::distance_avx512::cosine_distance,
+    inner_product: super::distance_avx512::inner_product,
+};
+
+#[cfg(target_arch = "x86_64")]
+static AVX: DistanceKernels = DistanceKernels {
+    name: "avx",
+    euclidean: super::distance_avx::euclidean_distance,
+    cosine: super::distance_avx::cosine_distance,
+    inner_product: super::distance_avx::inner_product,
+};
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+static SSE: DistanceKernels = DistanceKernels {
+    name: "sse",
+    euclidean: super::distance_sse::euclidean_distance,
+    cosine: super::distance_sse::cosine_distance,
+    inner_product: super::distance_sse::inner_product,
+};
+
+#[cfg(target_arch = "aarch64")]
+static NEON: DistanceKernels = DistanceKernels {
+    name: "neon",
+    euclidean: super::distance_neon::euclidean_distance,
+    cosine: super::distance_neon::cosine_distance,
+    inner_product: super::distance_neon::inner_product,
+};
+
+/// Returns the kernels the cpu supports, fastest first. The scalar kernels are
+/// always last.
+pub(crate) fn supported_kernels() -> Vec<&'static DistanceKernels> {
+    let mut kernels = Vec::new();
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::arch::is_x86_feature_detected!("avx512f") {
+            kernels.push(&AVX512);
+        }
+        if std::arch::is_x86_feature_detected!("avx") && std::arch::is_x86_feature_detected!("fma")
+        {
+            kernels.push(&AVX);
+        }
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    {
+        if std::arch::is_x86_feature_detected!("sse") {
+            kernels.push(&SSE);
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            kernels.push(&NEON);
+        }
+    }
+    kernels.push(&SCALAR);
+    kernels
+}
+
+/// Returns the fastest kernels the cpu supports. The cpu features are detected on
+/// the first call and the choice is cached for the lifetime of the process.
+pub(crate) fn kernels() -> &'static DistanceKernels {
+    static KERNELS: OnceLock<&'static DistanceKernels> = OnceLock::new();
+    KERNELS.get_or_init(|| supported_kernels()[0])
+}
+
+/// Returns the name of the instruction set the distance functions use.
+pub fn distance_kernels_name() -> &'static str {
+    kernels().name
+}
+
+/// The BinaryKernels struct.
+/// # Description
+/// The popcount kernel for packed binary vectors that was compiled for one
+/// instruction set. The kernel returns the number of bits set in both vectors and
+/// the number of bits set in either of them.
+/// # Notes
+/// Binary kernels are selected separately from the float kernels, the popcount
+/// instructions are extensions that are not implied by avx or avx512f.
+pub(crate) struct BinaryKernels {
+    pub(crate) name: &'static str,
+    pub(crate) and_or_count: BinaryKernel,
+}
+
+static SCALAR_BINARY: BinaryKernels = BinaryKernels {
+    name: "scalar",
+    and_or_count: scalar_and_or_count,
+};
+
+#[cfg(target_arch = "x86_64")]
+static AVX512_BINARY: BinaryKernels = BinaryKernels {
+    name: "avx512",
+    and_or_count: super::distance_avx512::and_or_count,
+};
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+static POPCNT_BINARY: BinaryKernels = BinaryKernels {
+    name: "popcnt",
+    and_or_count: super::binary::and_or_count_popcnt,
+};
+
+/// Returns the binary kernels the cpu supports, fastest first. The scalar kernel
+/// is always last.
+pub(crate) fn supported_binary_kernels() -> Vec<&'static BinaryKernels> {
+    let mut kernels = Vec::new();
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::arch::is_x86_feature_detected!("avx512f")
+            && std::arch::is_x86_feature_detected!("avx512vpopcntdq")
+        {
+            kernels.push(&AVX512_BINARY);
+        }
+    }
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+    {
+        if std::arch::is_x86_feature_detected!("popcnt") {
+            kernels.push(&POPCNT_BINARY);
+        }
+    }
+    kernels.push(&SCALAR_BINARY);
+    kernels
+}
+
+/// Returns the fastest binary kernel the cpu supports, cached like `kernels`.
+pub(crate) fn binary_kernels() -> &'static BinaryKernels {
+    static KERNELS: OnceLock<&'static BinaryKernels> = OnceLock::new();
+    KERNELS.get_or_init(|| supported_binary_kernels()[0])
+}
+
+/// Returns the name of the instruction set the binary distance functions use.
+pub fn binary_distance_kernels_name() -> &'static str {
+    binary_kernels().name
+}
+
+// Neon has no wider popcount than the one count_ones already compiles to on
+// aarch64, so arm uses the scalar kernel.
+fn scalar_and_or_count(a: &[u8], b: &[u8]) -> (u32, u32) {
+    super::binary::and_or_count(a, b)
+}
+
+fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum()
+}
+
+// For cosine we just assume the vectors have been normalized, since that
+// is what our indices expect.
+fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    1.0_f32 - a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>()
+}
+
+fn inner_product(a: &[f32], b: &[f32]) -> f32 {
+    1.0_f32 - a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernels_agree_with_scalar() {
+        // Cover the unrolled loops as well as every kind of remainder
+        for dimensionality in [1, 3, 15, 16, 17, 31, 64, 100, 128, 385, 1536] {
+            let a: Vec<f32> = (0..dimensionality)
+                .map(|i| ((i * 7) % 13) as f32 / 13.0 - 0.5)
+                .collect();
+            let b: Vec<f32> = (0..dimensionality)
+                .map(|i| ((i * 5) % 11) as f32 / 11.0 - 0.5)
+                .collect();
+            for kernels in supported_kernels() {
+                let results = unsafe {
+                    [
+                        ((kernels.euclidean)(&a, &b), euclidean_distance(&a, &b)),
+                        ((kernels.cosine)(&a, &b), cosine_distance(&a, &b)),
+                        ((kernels.inner_product)(&a, &b), inner_product(&a, &b)),
+                    ]
+                };
+                for (result, expected) in results {
+                    assert!(
+                        (result - expected).abs() <= 1e-3 * expected.abs().max(1.0),
+                        "{} kernels returned {} instead of {} for dimensionality {}",
+                        kernels.name,
+                        result,
+                        expected,
+                        dimensionality
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernels_are_selected_once() {
+        assert!(std::ptr::eq(kernels(), supported_kernels()[0]));
+        assert!(std::ptr::eq(
+            binary_kernels(),
+            supported_binary_kernels()[0]
+        ));
+    }
+
+    #[test]
+    fn test_binary_kernels_agree_with_scalar() {
+        // Cover the 64 byte loop, whole words and trailing bytes
+        for bytes in [1, 7, 8, 9, 63, 64, 65, 130, 200] {
+            let a: Vec<u8> = (0..bytes).map(|i| (i * 37 % 251) as u8).collect();
+            let b: Vec<u8> = (0..bytes).map(|i| (i * 91 % 241) as u8).collect();
+            let expected = a.iter().zip(&b).fold((0, 0), |(and, or), (a, b)| {
+                (and + (a & b).count_ones(), or + (a | b).count_ones())
+            });
+            for kernels in supported_binary_kernels() {
+                let result = unsafe { (kernels.and_or_count)(&a, &b) };
+                assert_eq!(
+                    result, expected,
+                    "{} binary kernel disagrees for {} bytes",
+                    kernels.name, bytes
+                );
+            }
+        }
+    }
+}
@@ -203,7 +203,10 @@ copyright Qdrant, licensed under the Apache 2.0 license.
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
-#[cfg(all(target_feature = "avx", target_feature = "fma"))]
+/// # Safety
+/// The cpu must support avx and fma.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,fma")]
 pub unsafe fn hsum256_ps_avx(x: __m256) -> f32 {
     let x128: __m128 = _mm_add_ps(_mm256_extractf128_ps(x, 1), _mm256_castps256_ps128(x));
     let x64: __m128 = _mm_add_ps(x128, _mm_movehl_ps(x128, x128));
@@ -211,7 +214,10 @@ pub unsafe fn hsum256_ps_avx(x: __m256) -> f32 {
     _mm_cvtss_f32(x32)
 }
 
-#[cfg(all(target_feature = "avx", target_feature = "fma"))]
+/// # Safety
+/// The cpu must support avx and fma and `b` must be at least as long as `a`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,fma")]
 pub unsafe fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
     let n = a.len();
     let m = n - (n % 32);
@@ -256,7 +262,10 @@ pub unsafe fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
     1.0_f32 - result
 }
 
-#[cfg(all(target_feature = "avx", target_feature = "fma"))]
+/// # Safety
+/// The cpu must support avx and fma and `b` must be at least as long as `a`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,fma")]
 pub unsafe fn inner_product(a: &[f32], b: &[f32]) -> f32 {
     let n = a.len();
     let m = n - (n % 32);
@@ -301,7 +310,10 @@ pub unsafe fn inner_product(a: &[f32], b: &[f32]) -> f32 {
     1.0_f32 - result
 }
 
-#[cfg(all(target_feature = "avx", target_feature = "fma"))]
+/// # Safety
+/// The cpu must support avx and fma and `b` must be at least as long as `a`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx,fma")]
 pub unsafe fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
     let n = a.len();
     let m = n - (n % 32);
@@ -0,0 +1,226 @@
+#[cfg(target_arch = "x86_64")]
+use std::arch::asm;
+
+// The AVX-512 intrinsics are only stable from Rust 1.89, newer than the
+// toolchain the worker builds with, so these kernels are written with inline
+// assembly. The callers check the cpu features at runtime.
+//
+// Each iteration of the main loops consumes 64 floats, four 512 bit registers.
+// The remainder is handled 16 floats at a time and then one float at a time.
+// The kernels only use zmm0 to zmm7, which are clobbered as xmm0 to xmm7, and
+// end with vzeroupper so the sse code that follows does not pay for the dirty
+// upper halves.
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len();
+    let m = n - (n % 16);
+    let result: f32;
+    asm!(
+        "vxorps xmm0, xmm0, xmm0",
+        "vxorps xmm1, xmm1, xmm1",
+        "vxorps xmm2, xmm2, xmm2",
+        "vxorps xmm3, xmm3, xmm3",
+        "xor {i}, {i}",
+        "2:",
+        "lea {next}, [{i} + 64]",
+        "cmp {next}, {m}",
+        "ja 3f",
+        "vmovups zmm4, [{a} + 4 * {i}]",
+        "vmovups zmm5, [{a} + 4 * {i} + 64]",
+        "vmovups zmm6, [{a} + 4 * {i} + 128]",
+        "vmovups zmm7, [{a} + 4 * {i} + 192]",
+        "vfmadd231ps zmm0, zmm4, [{b} + 4 * {i}]",
+        "vfmadd231ps zmm1, zmm5, [{b} + 4 * {i} + 64]",
+        "vfmadd231ps zmm2, zmm6, [{b} + 4 * {i} + 128]",
+        "vfmadd231ps zmm3, zmm7, [{b} + 4 * {i} + 192]",
+        "mov {i}, {next}",
+        "jmp 2b",
+        "3:",
+        "cmp {i}, {m}",
+        "jae 4f",
+        "vmovups zmm4, [{a} + 4 * {i}]",
+        "vfmadd231ps zmm0, zmm4, [{b} + 4 * {i}]",
+        "add {i}, 16",
+        "jmp 3b",
+        "4:",
+        "vaddps zmm0, zmm0, zmm1",
+        "vaddps zmm2, zmm2, zmm3",
+        "vaddps zmm0, zmm0, zmm2",
+        "vextractf64x4 ymm1, zmm0, 1",
+        "vaddps ymm0, ymm0, ymm1",
+        "vextractf128 xmm1, ymm0, 1",
+        "vaddps xmm0, xmm0, xmm1",
+        "vmovhlps xmm1, xmm0, xmm0",
+        "vaddps xmm0, xmm0, xmm1",
+        "vmovshdup xmm1, xmm0",
+        "vaddss xmm0, xmm0, xmm1",
+        "vzeroupper",
+        a = in(reg) a.as_ptr(),
+        b = in(reg) b.as_ptr(),
+        m = in(reg) m,
+        i = out(reg) _,
+        next = out(reg) _,
+        out("xmm0") result,
+        out("xmm1") _,
+        out("xmm2") _,
+        out("xmm3") _,
+        out("xmm4") _,
+        out("xmm5") _,
+        out("xmm6") _,
+        out("xmm7") _,
+        options(nostack, readonly),
+    );
+    result + a[m..].iter().zip(&b[m..]).map(|(a, b)| a * b).sum::<f32>()
+}
+
+/// # Safety
+/// The cpu must support avx512f and `b` must be at least as long as `a`.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    1.0_f32 - dot_product(a, b)
+}
+
+/// # Safety
+/// The cpu must support avx512f and `b` must be at least as long as `a`.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn inner_product(a: &[f32], b: &[f32]) -> f32 {
+    1.0_f32 - dot_product(a, b)
+}
+
+/// # Safety
+/// The cpu must support avx512f and `b` must be at least as long as `a`.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len();
+    let m = n - (n % 16);
+    let result: f32;
+    asm!(
+        "vxorps xmm0, xmm0, xmm0",
+        "vxorps xmm1, xmm1, xmm1",
+        "vxorps xmm2, xmm2, xmm2",
+        "vxorps xmm3, xmm3, xmm3",
+        "xor {i}, {i}",
+        "2:",
+        "lea {next}, [{i} + 64]",
+        "cmp {next}, {m}",
+        "ja 3f",
+        "vmovups zmm4, [{a} + 4 * {i}]",
+        "vmovups zmm5, [{a} + 4 * {i} + 64]",
+        "vmovups zmm6, [{a} + 4 * {i} + 128]",
+        "vmovups zmm7, [{a} + 4 * {i} + 192]",
+        "vsubps zmm4, zmm4, [{b} + 4 * {i}]",
+        "vsubps zmm5, zmm5, [{b} + 4 * {i} + 64]",
+        "vsubps zmm6, zmm6, [{b} + 4 * {i} + 128]",
+        "vsubps zmm7, zmm7, [{b} + 4 * {i} + 192]",
+        "vfmadd231ps zmm0, zmm4, zmm4",
+        "vfmadd231ps zmm1, zmm5, zmm5",
+        "vfmadd231ps zmm2, zmm6, zmm6",
+        "vfmadd231ps zmm3, zmm7, zmm7",
+        "mov {i}, {next}",
+        "jmp 2b",
+        "3:",
+        "cmp {i}, {m}",
+        "jae 4f",
+        "vmovups zmm4, [{a} + 4 * {i}]",
+        "vsubps zmm4, zmm4, [{b} + 4 * {i}]",
+        "vfmadd231ps zmm0, zmm4, zmm4",
+        "add {i}, 16",
+        "jmp 3b",
+        "4:",
+        "vaddps zmm0, zmm0, zmm1",
+        "vaddps zmm2, zmm2, zmm3",
+        "vaddps zmm0, zmm0, zmm2",
+        "vextractf64x4 ymm1, zmm0, 1",
+        "vaddps ymm0, ymm0, ymm1",
+        "vextractf128 xmm1, ymm0, 1",
+        "vaddps xmm0, xmm0, xmm1",
+        "vmovhlps xmm1, xmm0, xmm0",
+        "vaddps xmm0, xmm0, xmm1",
+        "vmovshdup xmm1, xmm0",
+        "vaddss xmm0, xmm0, xmm1",
+        "vzeroupper",
+        a = in(reg) a.as_ptr(),
+        b = in(reg) b.as_ptr(),
+        m = in(reg) m,
+        i = out(reg) _,
+        next = out(reg) _,
+        out("xmm0") result,
+        out("xmm1") _,
+        out("xmm2") _,
+        out("xmm3") _,
+        out("xmm4") _,
+        out("xmm5") _,
+        out("xmm6") _,
+        out("xmm7") _,
+        options(nostack, readonly),
+    );
+    result
+        + a[m..]
+            .iter()
+            .zip(&b[m..])
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+}
+
+/// Counts the bits set in both `a` and `b` and the bits set in either of them,
+/// 64 bytes at a time.
+/// # Safety
+/// The cpu must support avx512f and avx512vpopcntdq and `b` must be at least as
+/// long as `a`.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn and_or_count(a: &[u8], b: &[u8]) -> (u32, u32) {
+    let n = a.len();
+    let m = n - (n % 64);
+    let and: u64;
+    let or: u64;
+    asm!(
+        "vpxor xmm0, xmm0, xmm0",
+        "vpxor xmm1, xmm1, xmm1",
+        "xor {i}, {i}",
+        "2:",
+        "cmp {i}, {m}",
+        "jae 3f",
+        "vmovdqu64 zmm2, [{a} + {i}]",
+        "vmovdqu64 zmm3, [{b} + {i}]",
+        "vpandq zmm4, zmm2, zmm3",
+        "vporq zmm5, zmm2, zmm3",
+        "vpopcntq zmm4, zmm4",
+        "vpopcntq zmm5, zmm5",
+        "vpaddq zmm0, zmm0, zmm4",
+        "vpaddq zmm1, zmm1, zmm5",
+        "add {i}, 64",
+        "jmp 2b",
+        "3:",
+        "vextracti64x4 ymm2, zmm0, 1",
+        "vpaddq ymm0, ymm0, ymm2",
+        "vextracti128 xmm2, ymm0, 1",
+        "vpaddq xmm0, xmm0, xmm2",
+        "vpshufd xmm2, xmm0, 0x4e",
+        "vpaddq xmm0, xmm0, xmm2",
+        "vmovq {and}, xmm0",
+        "vextracti64x4 ymm3, zmm1, 1",
+        "vpaddq ymm1, ymm1, ymm3",
+        "vextracti128 xmm3, ymm1, 1",
+        "vpaddq xmm1, xmm1, xmm3",
+        "vpshufd xmm3, xmm1, 0x4e",
+        "vpaddq xmm1, xmm1, xmm3",
+        "vmovq {or}, xmm1",
+        "vzeroupper",
+        a = in(reg) a.as_ptr(),
+        b = in(reg) b.as_ptr(),
+        m = in(reg) m,
+        i = out(reg) _,
+        and = out(reg) and,
+        or = out(reg) or,
+        out("xmm0") _,
+        out("xmm1") _,
+        out("xmm2") _,
+        out("xmm3") _,
+        out("xmm4") _,
+        out("xmm5") _,
+        options(nostack, readonly),
+    );
+    let (tail_and, tail_or) = super::binary::and_or_count(&a[m..], &b[m..]);
+    (and as u32 + tail_and, or as u32 + tail_or)
+}
@@ -200,10 +200,11 @@ copyright Qdrant, licensed under the Apache 2.0 license.
    limitations under the License.
 */
 
-#[cfg(target_feature = "neon")]
+#[cfg(target_arch = "aarch64")]
 use std::arch::aarch64::*;
 
-#[cfg(target_feature = "neon")]
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
 pub unsafe fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
     let n = a.len();
     let m = n - (n % 16);
@@ -231,7 +232,8 @@ pub unsafe fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
     1.0_f32 - result
 }
 
-#[cfg(target_feature = "neon")]
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
 pub unsafe fn inner_product(a: &[f32], b: &[f32]) -> f32 {
     let n = a.len();
     let m = n - (n % 16);
@@ -259,7 +261,8 @@ pub unsafe fn inner_product(a: &[f32], b: &[f32]) -> f32 {
     1.0_f32 - result
 }
 
-#[cfg(target_feature = "neon")]
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
 pub unsafe fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
     let n = a.len();
     let m = n - (n % 16);
@@ -1,9 +1,11 @@
+pub mod binary;
+mod dispatch;
 pub mod distance_avx;
+pub mod distance_avx512;
 pub mod distance_neon;
 pub mod distance_sse;
 pub mod types;
 
-pub use distance_avx::*;
-pub use distance_neon::*;
-pub use distance_sse::*;
+pub use binary::{binarize, pack_bits, unpack_bits};
+pub use dispatch::{binary_distance_kernels_name, distance_kernels_name};
 pub use types::*;
@@ -200,6 +200,8 @@ copyright Qdrant, licensed under the Apache 2.0 license.
    limitations under the License.
 */
 
+use super::binary::and_or_count_unpacked;
+use super::dispatch::{binary_kernels, kernels};
 use crate::errors::{ChromaError, ErrorCodes};
 use thiserror::Error;
 
@@ -210,122 +212,125 @@ use thiserror::Error;
 /// - `Euclidean` - The Euclidean or l2 norm.
 /// - `Cosine` - The cosine distance. Specifically, 1 - cosine.
 /// - `InnerProduct` - The inner product. Specifically, 1 - inner product.
+/// - `Hamming` - The number of dimensions that differ between binary vectors.
+/// - `Jaccard` - The Jaccard distance between binary vectors. Specifically,
+///   1 - intersection / union of the set bits.
 /// # Notes
 /// See https://docs.trychroma.com/guides#changing-the-distance-function
+/// The binary distance functions treat every nonzero value as a set bit.
 #[derive(Clone, Debug, PartialEq)]
 pub enum DistanceFunction {
     Euclidean,
     Cosine,
     InnerProduct,
+    Hamming,
+    Jaccard,
 }
 
 impl DistanceFunction {
-    // TOOD: Should we error if mismatched dimensions?
-    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
-        // TODO: Figure out why the compiler is not auto vectorizing these
-        // by default.
-        match self {
-            DistanceFunction::Euclidean => {
-                #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
-                {
-                    if std::arch::is_aarch64_feature_detected!("neon") {
-                        return unsafe { crate::distance::distance_neon::euclidean_distance(a, b) };
-                    }
-                }
-                #[cfg(all(
-                    any(target_arch = "x86_64", target_arch = "x86"),
-                    target_feature = "sse"
-                ))]
-                {
-                    if std::arch::is_x86_feature_detected!("sse") {
-                        return unsafe { crate::distance::distance_sse::euclidean_distance(a, b) };
-                    }
-                }
-                #[cfg(all(
-                    target_arch = "x86_64",
-                    all(target_feature = "avx", target_feature = "fma")
-                ))]
-                {
-                    if std::arch::is_x86_feature_detected!("avx")
-                        && std::arch::is_x86_feature_detected!("fma")
-                    {
-                        return unsafe { crate::distance::distance_avx::euclidean_distance(a, b) };
-                    }
-                }
-                let mut sum = 0.0;
-                for i in 0..a.len() {
-                    sum += (a[i] - b[i]).powi(2);
-                }
-                sum
+    /// Computes the distance between two vectors of the same dimensionality.
+    /// # Notes
+    /// The SIMD kernels are chosen from the cpu features detected at runtime, see
+    /// `distance_kernels_name`.
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> Result<f32, DistanceFunctionError> {
+        if a.len() != b.len() {
+            return Err(DistanceFunctionError::DimensionMismatch(a.len(), b.len()));
+        }
+        if self.is_binary() {
+            let (and, or) = and_or_count_unpacked(a, b);
+            return self.binary_distance(and, or);
+        }
+        let kernels = kernels();
+        // Safety: The kernels were selected for features the cpu supports and
+        // the slices have the same length.
+        let distance = unsafe {
+            match self {
+                DistanceFunction::Euclidean => (kernels.euclidean)(a, b),
+                DistanceFunction::Cosine => (kernels.cosine)(a, b),
+                DistanceFunction::InnerProduct => (kernels.inner_product)(a, b),
+                DistanceFunction::Hamming | DistanceFunction::Jaccard => unreachable!(),
             }
-            DistanceFunction::Cosine => {
-                #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
-                {
-                    if std::arch::is_aarch64_feature_detected!("neon") {
-                        return unsafe { crate::distance::distance_neon::cosine_distance(a, b) };
-                    }
-                }
-                #[cfg(all(
-                    any(target_arch = "x86_64", target_arch = "x86"),
-                    target_feature = "sse"
-                ))]
-                {
-                    if std::arch::is_x86_feature_detected!("sse") {
-                        return unsafe { crate::distance::distance_sse::cosine_distance(a, b) };
-                    }
-                }
-                #[cfg(all(
-                    target_arch = "x86_64",
-                    all(target_feature = "avx", target_feature = "fma")
-                ))]
-                {
-                    if std::arch::is_x86_feature_detected!("avx")
-                        && std::arch::is_x86_feature_detected!("fma")
-                    {
-                        return unsafe { crate::distance::distance_avx::cosine_distance(a, b) };
-                    }
-                }
-                // For cosine we just assume the vectors have been normalized, since that
-                // is what our indices expect.
-                let mut sum = 0.0;
-                for i in 0..a.len() {
-                    sum += a[i] * b[i];
-                }
-                1.0_f32 - sum
+        };
+        Ok(distance)
+    }
+
+    /// Computes the distance between two binary vectors packed with `pack_bits`.
+    /// Callers that compare one query against many vectors should pack the query
+    /// once and use this directly.
+    pub fn packed_distance(&self, a: &[u8], b: &[u8]) -> Result<f32, DistanceFunctionError> {
+        if a.len() != b.len() {
+            return Err(DistanceFunctionError::DimensionMismatch(
+                a.len() * 8,
+                b.len() * 8,
+            ));
+        }
+        // Safety: The kernel was selected for features the cpu supports and the
+        // slices have the same length.
+        let (and, or) = unsafe { (binary_kernels().and_or_count)(a, b) };
+        self.binary_distance(and, or)
+    }
+
+    fn binary_distance(&self, and: u32, or: u32) -> Result<f32, DistanceFunctionError> {
+        match self {
+            DistanceFunction::Hamming => Ok((or - and) as f32),
+            DistanceFunction::Jaccard => match or {
+                0 => Ok(0.0),
+                _ => Ok(1.0 - and as f32 / or as f32),
+            },
+            _ => Err(DistanceFunctionError::NotBinary(self.clone().into())),
+        }
+    }
+
+    /// Computes the late interaction (MaxSim) distance between two multi-vectors,
+    /// each a concatenation of vectors of `dimensionality` values.
+    /// # Notes
+    /// Every query vector is matched with its closest document vector and the
+    /// distances are summed, so for inner product this is the number of query
+    /// vectors minus the MaxSim score.
+    pub fn max_sim_distance(
+        &self,
+        query: &[f32],
+        document: &[f32],
+        dimensionality: usize,
+    ) -> Result<f32, DistanceFunctionError> {
+        for multi_vector in [query, document] {
+            if dimensionality == 0
+                || multi_vector.is_empty()
+                || multi_vector.len() % dimensionality != 0
+            {
+                return Err(DistanceFunctionError::InvalidMultiVector(
+                    multi_vector.len(),
+                    dimensionality,
+                ));
             }
-            DistanceFunction::InnerProduct => {
-                #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
-                {
-                    if std::arch::is_aarch64_feature_detected!("neon") {
-                        return unsafe { crate::distance::distance_neon::inner_product(a, b) };
-                    }
-                }
-                #[cfg(all(
-                    any(target_arch = "x86_64", target_arch = "x86"),
-                    target_feature = "sse"
-                ))]
-                {
-                    if std::arch::is_x86_feature_detected!("sse") {
-                        return unsafe { crate::distance::distance_sse::inner_product(a, b) };
-                    }
-                }
-                #[cfg(all(
-                    target_arch = "x86_64",
-                    all(target_feature = "avx", target_feature = "fma")
-                ))]
-                {
-                    if std::arch::is_x86_feature_detected!("avx")
-                        && std::arch::is_x86_feature_detected!("fma")
-                    {
-                        return unsafe { crate::distance::distance_avx::inner_product(a, b) };
-                    }
-                }
-                let mut sum = 0.0;
-                for i in 0..a.len() {
-                    sum += a[i] * b[i];
-                }
-                1.0_f32 - sum
+        }
+        let mut total = 0.0;
+        for query_vector in query.chunks_exact(dimensionality) {
+            let mut closest = f32::INFINITY;
+            for document_vector in document.chunks_exact(dimensionality) {
+                closest = closest.min(self.distance(query_vector, document_vector)?);
             }
+            total += closest;
+        }
+        Ok(total)
+    }
+
+    /// Whether the distance function compares binary vectors.
+    pub fn is_binary(&self) -> bool {
+        matches!(self, DistanceFunction::Hamming | DistanceFunction::Jaccard)
+    }
+
+    /// The hnswlib space that indexes vectors for this distance function.
+    /// # Notes
+    /// The hamming and jaccard spaces are defined in bindings.cpp and store
+    /// vectors packed with `pack_words`.
+    pub(crate) fn hnsw_space(&self) -> &'static str {
+        match self {
+            DistanceFunction::Euclidean => "l2",
+            DistanceFunction::Cosine => "cosine",
+            DistanceFunction::InnerProduct => "ip",
+            DistanceFunction::Hamming => "hamming",
+            DistanceFunction::Jaccard => "jaccard",
         }
     }
 }
@@ -334,12 +339,21 @@ impl DistanceFunction {
 pub enum DistanceFunctionError {
     #[error("Invalid distance function `{0}`")]
     InvalidDistanceFunction(String),
+    #[error("Vectors have mismatched dimensions {0} and {1}")]
+    DimensionMismatch(usize, usize),
+    #[error("Distance function `{0}` does not compare binary vectors")]
+    NotBinary(String),
+    #[error("Multi-vector of {0} values is not a whole number of vectors of dimensionality {1}")]
+    InvalidMultiVector(usize, usize),
 }
 
 impl ChromaError for DistanceFunctionError {
     fn code(&self) -> ErrorCodes {
         match self {
             DistanceFunctionError::InvalidDistanceFunction(_) => ErrorCodes::InvalidArgument,
+            DistanceFunctionError::DimensionMismatch(_, _) => ErrorCodes::InvalidArgument,
+            DistanceFunctionError::NotBinary(_) => ErrorCodes::InvalidArgument,
+            DistanceFunctionError::InvalidMultiVector(_, _) => ErrorCodes::InvalidArgument,
         }
     }
 }
@@ -352,6 +366,8 @@ impl TryFrom<&str> for DistanceFunction {
             "l2" => Ok(DistanceFunction::Euclidean),
             "cosine" => Ok(DistanceFunction::Cosine),
             "ip" => Ok(DistanceFunction::InnerProduct),
+            "hamming" => Ok(DistanceFunction::Hamming),
+            "jaccard" => Ok(DistanceFunction::Jaccard),
             _ => Err(DistanceFunctionError::InvalidDistanceFunction(
                 value.to_string(),
             )),
@@ -365,6 +381,8 @@ impl Into<String> for DistanceFunction {
             DistanceFunction::Euclidean => "l2".to_string(),
             DistanceFunction::Cosine => "cosine".to_string(),
             DistanceFunction::InnerProduct => "ip".to_string(),
+            DistanceFunction::Hamming => "hamming".to_string(),
+            DistanceFunction::Jaccard => "jaccard".to_string(),
         }
     }
 }
@@ -381,6 +399,10 @@ mod tests {
         assert_eq!(distance_function, DistanceFunction::Cosine);
         let distance_function: DistanceFunction = "ip".try_into().unwrap();
         assert_eq!(distance_function, DistanceFunction::InnerProduct);
+        let distance_function: DistanceFunction = "hamming".try_into().unwrap();
+        assert_eq!(distance_function, DistanceFunction::Hamming);
+        let distance_function: DistanceFunction = "jaccard".try_into().unwrap();
+        assert_eq!(distance_function, DistanceFunction::Jaccard);
     }
 
     #[test]
@@ -391,6 +413,10 @@ mod tests {
         assert_eq!(distance_function, "cosine");
         let distance_function: String = DistanceFunction::InnerProduct.into();
         assert_eq!(distance_function, "ip");
+        let distance_function: String = DistanceFunction::Hamming.into();
+        assert_eq!(distance_function, "hamming");
+        let distance_function: String = DistanceFunction::Jaccard.into();
+        assert_eq!(distance_function, "jaccard");
     }
 
     #[test]
@@ -411,11 +437,65 @@ mod tests {
                 .sum::<f32>();
 
         let distance_function: DistanceFunction = "l2".try_into().unwrap();
-        assert_eq!(distance_function.distance(&a, &b), l2_sqr);
+        assert_eq!(distance_function.distance(&a, &b).unwrap(), l2_sqr);
         let distance_function: DistanceFunction = "ip".try_into().unwrap();
         assert_eq!(
-            distance_function.distance(&a_norm, &b_norm),
+            distance_function.distance(&a_norm, &b_norm).unwrap(),
             inner_product_sim
         );
     }
+
+    #[test]
+    fn test_distance_function_dimension_mismatch() {
+        let distance_function = DistanceFunction::Euclidean;
+        let result = distance_function.distance(&[1.0, 2.0, 3.0], &[1.0, 2.0]);
+        assert!(matches!(
+            result,
+            Err(DistanceFunctionError::DimensionMismatch(3, 2))
+        ));
+    }
+
+    #[test]
+    fn test_distance_function_binary() {
+        let a = vec![1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0];
+        let b = vec![1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        // Intersection of 3 and union of 6
+        assert_eq!(DistanceFunction::Hamming.distance(&a, &b).unwrap(), 3.0);
+        assert_eq!(DistanceFunction::Jaccard.distance(&a, &b).unwrap(), 0.5);
+        // The hamming distance is the squared l2 distance the hnsw index uses
+        assert_eq!(DistanceFunction::Euclidean.distance(&a, &b).unwrap(), 3.0);
+        let zeros = vec![0.0; 9];
+        assert_eq!(
+            DistanceFunction::Jaccard.distance(&zeros, &zeros).unwrap(),
+            0.0
+        );
+        assert!(matches!(
+            DistanceFunction::Cosine.packed_distance(&[1], &[1]),
+            Err(DistanceFunctionError::NotBinary(_))
+        ));
+    }
+
+    #[test]
+    fn test_max_sim_distance() {
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+        let document = vec![0.5, 0.0, 0.0, 2.0, 1.0, 1.0];
+        // The first query vector is closest to [1, 1] with a dot product of 1 and
+        // the second to [0, 2] with a dot product of 2
+        assert_eq!(
+            DistanceFunction::InnerProduct
+                .max_sim_distance(&query, &document, 2)
+                .unwrap(),
+            2.0 - 3.0
+        );
+        assert_eq!(
+            DistanceFunction::Euclidean
+                .max_sim_distance(&query, &document, 2)
+                .unwrap(),
+            0.25 + 1.0
+        );
+        assert!(matches!(
+            DistanceFunction::InnerProduct.max_sim_distance(&query, &document[..5], 2),
+            Err(DistanceFunctionError::InvalidMultiVector(5, 2))
+        ));
+    }
 }
@@ -120,6 +120,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -131,6 +132,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
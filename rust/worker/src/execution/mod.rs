@@ -2,6 +2,6 @@ pub(crate) mod config;
 pub(crate) mod data;
 pub(crate) mod dispatcher;
 pub(crate) mod operator;
-mod operators;
+pub(crate) mod operators;
 pub(crate) mod orchestration;
 mod worker_thread;
@@ -0,0 +1,423 @@
+use crate::{
+    blockstore::provider::BlockfileProvider,
+    errors::{ChromaError, ErrorCodes},
+    execution::{data::data_chunk::Chunk, operator::Operator},
+    segment::{
+        metadata_segment::{MetadataSegmentError, MetadataSegmentReader},
+        record_segment::{RecordSegmentReader, RecordSegmentReaderCreationError},
+        LogMaterializer, LogMaterializerError,
+    },
+    types::{LogRecord, MetadataAggregate, MetadataAggregator, Operation, Segment},
+};
+use async_trait::async_trait;
+use roaring::RoaringBitmap;
+use thiserror::Error;
+use tracing::trace;
+
+#[derive(Debug)]
+pub(crate) struct AggregateMetadataOperator {}
+
+impl AggregateMetadataOperator {
+    pub(crate) fn new() -> Box<Self> {
+        Box::new(AggregateMetadataOperator {})
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct AggregateMetadataInput {
+    // Result of PullLogs.
+    filtered_log: Chunk<LogRecord>,
+    // The offset ids filtered by the where and where_document clause.
+    filtered_offset_ids: Option<Vec<u32>>,
+    record_segment_definition: Segment,
+    metadata_segment_definition: Segment,
+    blockfile_provider: BlockfileProvider,
+    keys: Vec<String>,
+    histogram_buckets: u32,
+}
+
+impl AggregateMetadataInput {
+    pub(crate) fn new(
+        filtered_log: Chunk<LogRecord>,
+        filtered_offset_ids: Option<Vec<u32>>,
+        record_segment_definition: Segment,
+        metadata_segment_definition: Segment,
+        blockfile_provider: BlockfileProvider,
+        keys: Vec<String>,
+        histogram_buckets: u32,
+    ) -> Self {
+        Self {
+            filtered_log,
+            filtered_offset_ids,
+            record_segment_definition,
+            metadata_segment_definition,
+            blockfile_provider,
+            keys,
+            histogram_buckets,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct AggregateMetadataOutput {
+    // One aggregate per requested key, in the order of the request.
+    pub(crate) aggregates: Vec<MetadataAggregate>,
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum AggregateMetadataError {
+    #[error("Error creating Record Segment")]
+    RecordSegmentCreationError(#[from] RecordSegmentReaderCreationError),
+    #[error("Error reading Record Segment")]
+    RecordSegmentReadError(Box<dyn ChromaError>),
+    #[error("Error materializing logs")]
+    LogMaterializationError(#[from] LogMaterializerError),
+    #[error("Error reading Metadata Segment")]
+    MetadataSegmentReadError(#[from] MetadataSegmentError),
+}
+
+impl ChromaError for AggregateMetadataError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            AggregateMetadataError::RecordSegmentCreationError(e) => e.code(),
+            AggregateMetadataError::RecordSegmentReadError(e) => e.code(),
+            AggregateMetadataError::LogMaterializationError(e) => e.code(),
+            AggregateMetadataError::MetadataSegmentReadError(e) => e.code(),
+        }
+    }
+}
+
+async fn open_record_segment_reader<'me>(
+    input: &AggregateMetadataInput,
+) -> Result<Option<RecordSegmentReader<'me>>, AggregateMetadataError> {
+    match RecordSegmentReader::from_segment(
+        &input.record_segment_definition,
+        &input.blockfile_provider,
+    )
+    .await
+    {
+        Ok(reader) => Ok(Some(reader)),
+        Err(e) => match *e {
+            // This means no compaction has occured, so only the log has records.
+            RecordSegmentReaderCreationError::UninitializedSegment => Ok(None),
+            e => {
+                tracing::error!("Error creating Record Segment: {:?}", e);
+                Err(AggregateMetadataError::RecordSegmentCreationError(e))
+            }
+        },
+    }
+}
+
+#[async_trait]
+impl Operator<AggregateMetadataInput, AggregateMetadataOutput> for AggregateMetadataOperator {
+    type Error = AggregateMetadataError;
+
+    async fn run(
+        &self,
+        input: &AggregateMetadataInput,
+    ) -> Result<AggregateMetadataOutput, Self::Error> {
+        trace!(
+            "[AggregateMetadataOperator] segment id: {}",
+            input.metadata_segment_definition.id.to_string()
+        );
+
+        // Step 1: Materialize the logs.
+        let record_segment_reader = open_record_segment_reader(input).await?;
+        let materializer =
+            LogMaterializer::new(record_segment_reader, input.filtered_log.clone(), None);
+        let mat_records = materializer.materialize().await?;
+
+        // Step 2: Split the matching records between the log and the record
+        // segment. The log shadows the record segment, including deletes.
+        let filtered_offset_ids: Option<RoaringBitmap> = input
+            .filtered_offset_ids
+            .as_ref()
+            .map(|offset_ids| offset_ids.iter().cloned().collect());
+        let mut log_offset_ids = RoaringBitmap::new();
+        let mut log_records = Vec::new();
+        for (log, _) in mat_records.iter() {
+            log_offset_ids.insert(log.offset_id);
+            if log.final_operation == Operation::Delete {
+                continue;
+            }
+            if let Some(offset_ids) = filtered_offset_ids.as_ref() {
+                if !offset_ids.contains(log.offset_id) {
+                    continue;
+                }
+            }
+            log_records.push(log);
+        }
+        let segment_offset_ids = match (&materializer.record_segment_reader, filtered_offset_ids) {
+            (None, _) => RoaringBitmap::new(),
+            (Some(_), Some(offset_ids)) => offset_ids - &log_offset_ids,
+            (Some(reader), None) => match reader.get_all_offset_ids().await {
+                Ok(offset_ids) => offset_ids - &log_offset_ids,
+                Err(e) => {
+                    tracing::error!("Error reading Record Segment: {:?}", e);
+                    return Err(AggregateMetadataError::RecordSegmentReadError(e));
+                }
+            },
+        };
+
+        // Step 3: Aggregate the postings of the metadata indices, restricted
+        // to the matching records of the segment, then the log records.
+        let metadata_segment_reader = MetadataSegmentReader::from_segment(
+            &input.metadata_segment_definition,
+            &input.blockfile_provider,
+        )
+        .await?;
+        let index_readers = [
+            &metadata_segment_reader.bool_metadata_index_reader,
+            &metadata_segment_reader.i64_metadata_index_reader,
+            &metadata_segment_reader.f32_metadata_index_reader,
+            &metadata_segment_reader.string_metadata_index_reader,
+        ];
+        let mut aggregates = Vec::with_capacity(input.keys.len());
+        for key in input.keys.iter() {
+            let mut aggregator = MetadataAggregator::new(key.clone());
+            if !segment_offset_ids.is_empty() {
+                for index_reader in index_readers.into_iter().flatten() {
+                    let mut values = index_reader
+                        .sorted_values(key, false)
+                        .await
+                        .map_err(MetadataSegmentError::from)?;
+                    while let Some((value, offset_ids)) =
+                        values.next().await.map_err(MetadataSegmentError::from)?
+                    {
+                        let count = offset_ids.intersection_len(&segment_offset_ids);
+                        aggregator.add(&value, count as u32);
+                    }
+                }
+            }
+            for log in log_records.iter() {
+                if let Some(value) = log.merged_metadata_ref().get(key.as_str()) {
+                    aggregator.add(value, 1);
+                }
+            }
+            aggregates.push(aggregator.finish(input.histogram_buckets));
+        }
+
+        Ok(AggregateMetadataOutput { aggregates })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use uuid::Uuid;
+
+    use crate::{
+        blockstore::{arrow::provider::ArrowBlockfileProvider, provider::BlockfileProvider},
+        execution::{
+            data::data_chunk::Chunk,
+            operator::Operator,
+            operators::aggregate_metadata::{AggregateMetadataInput, AggregateMetadataOperator},
+        },
+        segment::{
+            metadata_segment::MetadataSegmentWriter, record_segment::RecordSegmentWriter,
+            types::SegmentFlusher, LogMaterializer, SegmentWriter,
+        },
+        storage::{local::LocalStorage, Storage},
+        types::{
+            FacetCount, LogRecord, MetadataValue, Operation, OperationRecord, UpdateMetadataValue,
+        },
+    };
+
+    #[tokio::test]
+    async fn aggregate_log_and_segment() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileRecord,
+            scope: crate::types::SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileMetadata,
+            scope: crate::types::SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let log_record = |log_offset: i64,
+                          id: &str,
+                          metadata: Vec<(&str, UpdateMetadataValue)>,
+                          operation| LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding: Some(vec![1.0, 2.0, 3.0]),
+                encoding: None,
+                sparse_vector: None,
+                metadata: Some(
+                    metadata
+                        .into_iter()
+                        .map(|(key, value)| (key.to_string(), value))
+                        .collect(),
+                ),
+                document: Some(String::from("This is a document.")),
+                operation,
+            },
+        };
+        let color = |value: &str| UpdateMetadataValue::Str(value.to_string());
+        let data: Chunk<LogRecord> = Chunk::new(
+            vec![
+                log_record(
+                    1,
+                    "embedding_id_1",
+                    vec![
+                        ("color", color("red")),
+                        ("size", UpdateMetadataValue::Int(1)),
+                    ],
+                    Operation::Add,
+                ),
+                log_record(
+                    2,
+                    "embedding_id_2",
+                    vec![
+                        ("color", color("red")),
+                        ("size", UpdateMetadataValue::Int(3)),
+                    ],
+                    Operation::Add,
+                ),
+                log_record(
+                    3,
+                    "embedding_id_3",
+                    vec![
+                        ("color", color("green")),
+                        ("size", UpdateMetadataValue::Int(5)),
+                    ],
+                    Operation::Add,
+                ),
+            ]
+            .into(),
+        );
+        {
+            let segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let materializer = LogMaterializer::new(None, data, None);
+            let mat_records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .expect("Apply materialized log to metadata segment failed");
+            metadata_writer
+                .write_to_blockfiles()
+                .await
+                .expect("Write to blockfiles for metadata writer failed");
+            segment_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .expect("Apply materialized log to record segment failed");
+            record_segment.file_path = segment_writer
+                .commit()
+                .expect("Commit for segment writer failed")
+                .flush()
+                .await
+                .expect("Flush record segment writer failed");
+            metadata_segment.file_path = metadata_writer
+                .commit()
+                .expect("Commit for metadata writer failed")
+                .flush()
+                .await
+                .expect("Flush metadata segment writer failed");
+        }
+        // The log recolors the first record, deletes the second one and adds
+        // a fourth one.
+        let data: Chunk<LogRecord> = Chunk::new(
+            vec![
+                log_record(
+                    4,
+                    "embedding_id_1",
+                    vec![("color", color("blue"))],
+                    Operation::Update,
+                ),
+                log_record(5, "embedding_id_2", vec![], Operation::Delete),
+                log_record(
+                    6,
+                    "embedding_id_4",
+                    vec![
+                        ("color", color("red")),
+                        ("size", UpdateMetadataValue::Int(7)),
+                    ],
+                    Operation::Add,
+                ),
+            ]
+            .into(),
+        );
+        let facet = |value: &str| FacetCount {
+            value: MetadataValue::Str(value.to_string()),
+            count: 1,
+        };
+        let operator = AggregateMetadataOperator::new();
+
+        let input = AggregateMetadataInput::new(
+            data.clone(),
+            None,
+            record_segment.clone(),
+            metadata_segment.clone(),
+            blockfile_provider.clone(),
+            vec![String::from("color"), String::from("size")],
+            2,
+        );
+        let res = operator
+            .run(&input)
+            .await
+            .expect("Error during running of operator");
+        assert_eq!(
+            res.aggregates[0].facets,
+            vec![facet("blue"), facet("green"), facet("red")]
+        );
+        assert_eq!(res.aggregates[0].numeric, None);
+        let numeric = res.aggregates[1].numeric.clone().unwrap();
+        assert!(res.aggregates[1].facets.is_empty());
+        assert_eq!((numeric.min, numeric.max), (1.0, 7.0));
+        assert_eq!((numeric.sum, numeric.count), (13.0, 3));
+        assert_eq!(
+            numeric
+                .buckets
+                .iter()
+                .map(|bucket| bucket.count)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        // Only the first and the third record match the filter.
+        let input = AggregateMetadataInput::new(
+            data,
+            Some(vec![1, 3]),
+            record_segment,
+            metadata_segment,
+            blockfile_provider,
+            vec![String::from("color"), String::from("missing")],
+            2,
+        );
+        let res = operator
+            .run(&input)
+            .await
+            .expect("Error during running of operator");
+        assert_eq!(
+            res.aggregates[0].facets,
+            vec![facet("blue"), facet("green")]
+        );
+        assert_eq!(res.aggregates[1].key, "missing");
+        assert!(res.aggregates[1].facets.is_empty());
+        assert_eq!(res.aggregates[1].numeric, None);
+    }
+}
@@ -1,8 +1,9 @@
 use crate::blockstore::provider::BlockfileProvider;
+use crate::distance::DistanceFunctionError;
 use crate::errors::ChromaError;
 use crate::errors::ErrorCodes;
 use crate::execution::data::data_chunk::Chunk;
-use crate::execution::operators::normalize_vectors::normalize;
+use crate::execution::operators::normalize_vectors::{normalize, normalize_multi_vector};
 use crate::segment::record_segment::RecordSegmentReader;
 use crate::segment::LogMaterializer;
 use crate::segment::LogMaterializerError;
@@ -32,12 +33,16 @@ pub struct BruteForceKnnOperator {}
 /// * `query` - The query vector.
 /// * `k` - The number of nearest neighbors to find.
 /// * `distance_metric` - The distance metric to use.
+/// * `multi_vector_dimensionality` - The dimensionality of each vector of a multi-vector collection.
 #[derive(Debug)]
 pub struct BruteForceKnnOperatorInput {
     pub log: Chunk<LogRecord>,
     pub query: Vec<f32>,
     pub k: usize,
     pub distance_metric: DistanceFunction,
+    // Set for multi-vector collections to the dimensionality of each vector.
+    // Records and the query are then compared by their MaxSim distance.
+    pub multi_vector_dimensionality: Option<usize>,
     pub allowed_ids: Arc<[String]>,
     // This is just a subset of allowed_ids containing
     // only the ids that are allowed and present in the log.
@@ -103,6 +108,8 @@ pub enum BruteForceKnnOperatorError {
     ),
     #[error("Error while materializing log records: {0}")]
     LogMaterializationError(#[from] LogMaterializerError),
+    #[error("Error computing distance: {0}")]
+    DistanceFunction(#[from] DistanceFunctionError),
 }
 
 impl ChromaError for BruteForceKnnOperatorError {
@@ -110,6 +117,7 @@ impl ChromaError for BruteForceKnnOperatorError {
         match self {
             BruteForceKnnOperatorError::RecordSegmentReaderCreationError(e) => e.code(),
             BruteForceKnnOperatorError::LogMaterializationError(e) => e.code(),
+            BruteForceKnnOperatorError::DistanceFunction(e) => e.code(),
         }
     }
 }
@@ -176,19 +184,40 @@ impl Operator<BruteForceKnnOperatorInput, BruteForceKnnOperatorOutput> for Brute
                 continue;
             }
             let embedding = &log_record.merged_embeddings();
-            if should_normalize {
+            if let Some(dimensionality) = input.multi_vector_dimensionality {
+                // The query is already normalized by the orchestrator for cosine distance
+                let distance = match should_normalize {
+                    true => input.distance_metric.max_sim_distance(
+                        &input.query,
+                        &normalize_multi_vector(embedding, dimensionality),
+                        dimensionality,
+                    )?,
+                    false => input.distance_metric.max_sim_distance(
+                        &input.query,
+                        embedding,
+                        dimensionality,
+                    )?,
+                };
+                heap.push(Entry {
+                    user_id: log_record.merged_user_id_ref(),
+                    embedding,
+                    distance,
+                });
+            } else if should_normalize {
                 let normalized_query = normalized_query.as_ref().expect("Invariant violation. Should have set normalized query if should_normalize is true.");
                 let normalized_embedding = normalize(&embedding[..]);
                 let distance = input
                     .distance_metric
-                    .distance(&normalized_embedding[..], &normalized_query[..]);
+                    .distance(&normalized_embedding[..], &normalized_query[..])?;
                 heap.push(Entry {
                     user_id: log_record.merged_user_id_ref(),
                     embedding,
                     distance,
                 });
             } else {
-                let distance = input.distance_metric.distance(&embedding[..], &input.query);
+                let distance = input
+                    .distance_metric
+                    .distance(&embedding[..], &input.query)?;
                 heap.push(Entry {
                     user_id: log_record.merged_user_id_ref(),
                     embedding,
@@ -261,6 +290,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![0.0, 0.0, 0.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -272,6 +302,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: Some(vec![0.0, 1.0, 1.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -283,6 +314,7 @@ mod tests {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -296,6 +328,7 @@ mod tests {
             query: vec![0.0, 0.0, 0.0],
             k: 2,
             distance_metric: DistanceFunction::Euclidean,
+            multi_vector_dimensionality: None,
             allowed_ids: Arc::new([]),
             allowed_ids_brute_force: Arc::new([]),
             blockfile_provider,
@@ -330,6 +363,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![0.0, 1.0, 0.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -341,6 +375,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: Some(data_1.clone()),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -352,6 +387,7 @@ mod tests {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(data_2.clone()),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -365,6 +401,7 @@ mod tests {
             query: vec![0.0, 1.0, 0.0],
             k: 2,
             distance_metric: DistanceFunction::InnerProduct,
+            multi_vector_dimensionality: None,
             allowed_ids: Arc::new([]),
             allowed_ids_brute_force: Arc::new([]),
             blockfile_provider,
@@ -397,6 +434,7 @@ mod tests {
                 id: "embedding_id_1".to_string(),
                 embedding: Some(vec![0.0, 0.0, 0.0]),
                 encoding: None,
+                sparse_vector: None,
                 metadata: None,
                 document: None,
                 operation: Operation::Add,
@@ -410,6 +448,7 @@ mod tests {
             query: vec![0.0, 0.0, 0.0],
             k: 2,
             distance_metric: DistanceFunction::Euclidean,
+            multi_vector_dimensionality: None,
             allowed_ids: Arc::new([]),
             allowed_ids_brute_force: Arc::new([]),
             blockfile_provider,
@@ -434,6 +473,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -445,6 +485,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -456,6 +497,7 @@ mod tests {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -469,6 +511,7 @@ mod tests {
             query: vec![0.0, 0.0, 0.0],
             k: 2,
             distance_metric: DistanceFunction::Euclidean,
+            multi_vector_dimensionality: None,
             allowed_ids: Arc::new([]),
             allowed_ids_brute_force: Arc::new([]),
             blockfile_provider,
@@ -498,6 +541,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![0.0, 0.0, 0.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -509,6 +553,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Delete,
@@ -520,6 +565,7 @@ mod tests {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![0.0, 0.0, 0.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -533,6 +579,7 @@ mod tests {
             query: vec![0.0, 0.0, 0.0],
             k: 2,
             distance_metric: DistanceFunction::Euclidean,
+            multi_vector_dimensionality: None,
             allowed_ids: Arc::new([]),
             allowed_ids_brute_force: Arc::new([]),
             blockfile_provider,
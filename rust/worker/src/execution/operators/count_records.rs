@@ -230,6 +230,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: Some(vec![1.0, 2.0, 3.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -241,6 +242,7 @@ mod tests {
                         id: "embedding_id_2".to_string(),
                         embedding: Some(vec![4.0, 5.0, 6.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Add,
@@ -252,6 +254,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: None,
                         encoding: None,
+                        sparse_vector: None,
                         metadata: None,
                         document: None,
                         operation: Operation::Delete,
@@ -303,6 +306,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![1.0, 2.0, 3.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -314,6 +318,7 @@ mod tests {
                     id: "embedding_id_4".to_string(),
                     embedding: Some(vec![4.0, 5.0, 6.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -325,6 +330,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Update,
@@ -367,6 +373,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: Some(vec![1.0, 2.0, 3.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -378,6 +385,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: Some(vec![4.0, 5.0, 6.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -389,6 +397,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Delete,
@@ -400,6 +409,7 @@ mod tests {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![1.0, 2.0, 3.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Add,
@@ -411,6 +421,7 @@ mod tests {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![4.0, 5.0, 6.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Upsert,
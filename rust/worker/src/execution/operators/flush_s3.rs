@@ -2,14 +2,13 @@ use std::sync::Arc;
 
 use crate::errors::ChromaError;
 use crate::segment::metadata_segment::MetadataSegmentWriter;
+use crate::segment::sparse_vector_segment::SparseVectorSegmentWriter;
 use crate::segment::SegmentFlusher;
+use crate::segment::VectorSegmentWriter;
 use crate::types::SegmentFlushInfo;
 use crate::{
     execution::operator::Operator,
-    segment::{
-        distributed_hnsw_segment::DistributedHNSWSegmentWriter,
-        record_segment::RecordSegmentWriter, SegmentWriter,
-    },
+    segment::{record_segment::RecordSegmentWriter, SegmentWriter},
 };
 use async_trait::async_trait;
 
@@ -25,20 +24,23 @@ impl FlushS3Operator {
 #[derive(Debug)]
 pub struct FlushS3Input {
     record_segment_writer: RecordSegmentWriter,
-    hnsw_segment_writer: Box<DistributedHNSWSegmentWriter>,
+    vector_segment_writer: VectorSegmentWriter,
     metadata_segment_writer: MetadataSegmentWriter<'static>,
+    sparse_vector_segment_writer: Option<SparseVectorSegmentWriter<'static>>,
 }
 
 impl FlushS3Input {
     pub fn new(
         record_segment_writer: RecordSegmentWriter,
-        hnsw_segment_writer: Box<DistributedHNSWSegmentWriter>,
+        vector_segment_writer: VectorSegmentWriter,
         metadata_segment_writer: MetadataSegmentWriter<'static>,
+        sparse_vector_segment_writer: Option<SparseVectorSegmentWriter<'static>>,
     ) -> Self {
         Self {
             record_segment_writer,
-            hnsw_segment_writer,
+            vector_segment_writer,
             metadata_segment_writer,
+            sparse_vector_segment_writer,
         }
     }
 }
@@ -78,30 +80,21 @@ impl Operator<FlushS3Input, FlushS3Output> for FlushS3Operator {
             }
         };
 
-        let hnsw_segment_flusher = input.hnsw_segment_writer.clone().commit();
-        let hnsw_segment_flush_info = match hnsw_segment_flusher {
-            Ok(flusher) => {
-                let segment_id = input.hnsw_segment_writer.id;
-                let res = flusher.flush().await;
-                match res {
-                    Ok(res) => {
-                        println!("HNSW Segment Flushed");
-                        SegmentFlushInfo {
-                            segment_id,
-                            file_paths: res,
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Error Flushing HNSW Segment: {:?}", e);
-                        return Err(e);
+        let segment_id = input.vector_segment_writer.id();
+        let vector_segment_flush_info =
+            match input.vector_segment_writer.clone().commit_and_flush().await {
+                Ok(res) => {
+                    tracing::info!("Vector Segment Flushed");
+                    SegmentFlushInfo {
+                        segment_id,
+                        file_paths: res,
                     }
                 }
-            }
-            Err(e) => {
-                tracing::error!("Error Commiting HNSW Segment: {:?}", e);
-                return Err(e);
-            }
-        };
+                Err(e) => {
+                    tracing::error!("Error Flushing Vector Segment: {:?}", e);
+                    return Err(e);
+                }
+            };
 
         let metadata_segment_flusher = input.metadata_segment_writer.clone().commit();
         let metadata_segment_flush_info = match metadata_segment_flusher {
@@ -128,13 +121,41 @@ impl Operator<FlushS3Input, FlushS3Output> for FlushS3Operator {
             }
         };
 
+        let mut segment_flush_info = vec![
+            record_segment_flush_info,
+            vector_segment_flush_info,
+            metadata_segment_flush_info,
+        ];
+
+        if let Some(sparse_vector_segment_writer) = &input.sparse_vector_segment_writer {
+            let sparse_vector_segment_flusher = sparse_vector_segment_writer.clone().commit();
+            match sparse_vector_segment_flusher {
+                Ok(flusher) => {
+                    let segment_id = sparse_vector_segment_writer.id;
+                    match flusher.flush().await {
+                        Ok(res) => {
+                            tracing::info!("Sparse Vector Segment Flushed");
+                            segment_flush_info.push(SegmentFlushInfo {
+                                segment_id,
+                                file_paths: res,
+                            });
+                        }
+                        Err(e) => {
+                            tracing::error!("Error Flushing Sparse Vector Segment: {:?}", e);
+                            return Err(e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Error Commiting Sparse Vector Segment: {:?}", e);
+                    return Err(e);
+                }
+            }
+        }
+
         tracing::info!("Flush to S3 complete");
         Ok(FlushS3Output {
-            segment_flush_info: Arc::new([
-                record_segment_flush_info,
-                hnsw_segment_flush_info,
-                metadata_segment_flush_info,
-            ]),
+            segment_flush_info: segment_flush_info.into(),
         })
     }
 }
@@ -1,5 +1,7 @@
+use crate::distance::{DistanceFunction, DistanceFunctionError};
 use crate::execution::data::data_chunk::Chunk;
-use crate::types::{LogRecord, Operation};
+use crate::execution::operators::normalize_vectors::{normalize, normalize_multi_vector};
+use crate::types::{LogRecord, Operation, SearchOptions};
 use crate::{
     blockstore::provider::BlockfileProvider,
     errors::{ChromaError, ErrorCodes},
@@ -10,6 +12,8 @@ use crate::{
     types::Segment,
 };
 use async_trait::async_trait;
+use roaring::RoaringBitmap;
+use std::collections::HashSet;
 use std::sync::Arc;
 use thiserror::Error;
 
@@ -21,6 +25,8 @@ pub struct HnswKnnOperatorInput {
     pub segment: Box<DistributedHNSWSegmentReader>,
     pub query: Vec<f32>,
     pub k: usize,
+    pub distance_function: DistanceFunction,
+    pub search_options: SearchOptions,
     pub record_segment: Segment,
     pub blockfile_provider: BlockfileProvider,
     pub allowed_ids: Arc<[String]>,
@@ -84,12 +90,145 @@ impl HnswKnnOperator {
         Ok(disallowed_ids)
     }
 
+    // Computes exact distances to every record in the segment that passes the
+    // allowed and disallowed ids and returns the k closest.
+    async fn brute_force_query(
+        &self,
+        input: &HnswKnnOperatorInput,
+        record_segment_reader: &RecordSegmentReader<'_>,
+        allowed_offset_ids: &[usize],
+        disallowed_offset_ids: &[usize],
+    ) -> Result<(Vec<usize>, Vec<f32>), Box<dyn ChromaError>> {
+        let records = match allowed_offset_ids.is_empty() {
+            true => record_segment_reader.get_all_data_with_offset_ids().await?,
+            false => {
+                record_segment_reader
+                    .get_data_for_offset_ids(&to_bitmap(allowed_offset_ids))
+                    .await?
+            }
+        };
+        let disallowed_offset_ids: HashSet<usize> = disallowed_offset_ids.iter().cloned().collect();
+        let mut results = Vec::new();
+        for (offset_id, record) in records.iter() {
+            let offset_id = *offset_id as usize;
+            if disallowed_offset_ids.contains(&offset_id) {
+                continue;
+            }
+            let distance = match self.distance(input, &record.embedding) {
+                Ok(distance) => distance,
+                Err(e) => return Err(Box::new(e)),
+            };
+            results.push((offset_id, distance));
+        }
+        Ok(self.top_k(results, input.k))
+    }
+
+    // Recomputes the distances of the candidates from the embeddings in the record
+    // segment and returns the k closest.
+    async fn rerank(
+        &self,
+        input: &HnswKnnOperatorInput,
+        record_segment_reader: &RecordSegmentReader<'_>,
+        candidates: Vec<usize>,
+    ) -> Result<(Vec<usize>, Vec<f32>), Box<dyn ChromaError>> {
+        let records = record_segment_reader
+            .get_data_for_offset_ids(&to_bitmap(&candidates))
+            .await?;
+        let mut results = Vec::with_capacity(records.len());
+        for (offset_id, record) in records {
+            let distance = match self.distance(input, &record.embedding) {
+                Ok(distance) => distance,
+                Err(e) => return Err(Box::new(e)),
+            };
+            results.push((offset_id as usize, distance));
+        }
+        Ok(self.top_k(results, input.k))
+    }
+
+    // The query is already normalized by the orchestrator for cosine distance
+    fn distance(
+        &self,
+        input: &HnswKnnOperatorInput,
+        embedding: &[f32],
+    ) -> Result<f32, DistanceFunctionError> {
+        if input.segment.is_multi_vector() {
+            let dimensionality = input.segment.dimensionality();
+            return match input.distance_function {
+                DistanceFunction::Cosine => input.distance_function.max_sim_distance(
+                    &input.query,
+                    &normalize_multi_vector(embedding, dimensionality),
+                    dimensionality,
+                ),
+                _ => input.distance_function.max_sim_distance(
+                    &input.query,
+                    embedding,
+                    dimensionality,
+                ),
+            };
+        }
+        match input.distance_function {
+            DistanceFunction::Cosine => input
+                .distance_function
+                .distance(&normalize(embedding), &input.query),
+            _ => input.distance_function.distance(embedding, &input.query),
+        }
+    }
+
+    // Each record takes up one element of a multi-vector index per vector
+    async fn labels(
+        &self,
+        input: &HnswKnnOperatorInput,
+        record_segment_reader: &RecordSegmentReader<'_>,
+        offset_ids: &[usize],
+    ) -> Result<Vec<usize>, Box<dyn ChromaError>> {
+        let records = record_segment_reader
+            .get_data_for_offset_ids(&to_bitmap(offset_ids))
+            .await?;
+        let mut labels = Vec::with_capacity(offset_ids.len());
+        for (offset_id, record) in records {
+            labels.extend(input.segment.labels(offset_id as usize, &record.embedding));
+        }
+        Ok(labels)
+    }
+
+    // Candidates are the records with a vector close to any vector of the query,
+    // they are ranked by their exact MaxSim distance.
+    async fn multi_vector_query(
+        &self,
+        input: &HnswKnnOperatorInput,
+        record_segment_reader: &RecordSegmentReader<'_>,
+        allowed_offset_ids: &[usize],
+        disallowed_offset_ids: &[usize],
+    ) -> Result<(Vec<usize>, Vec<f32>), Box<dyn ChromaError>> {
+        let allowed_labels = self
+            .labels(input, record_segment_reader, allowed_offset_ids)
+            .await?;
+        let disallowed_labels = self
+            .labels(input, record_segment_reader, disallowed_offset_ids)
+            .await?;
+        let candidates = input.segment.query_multi_vector(
+            &input.query,
+            input.search_options.num_candidates(input.k),
+            input.search_options.ef_search,
+            &allowed_labels,
+            &disallowed_labels,
+        );
+        self.rerank(input, record_segment_reader, candidates).await
+    }
+
+    fn top_k(&self, mut results: Vec<(usize, f32)>, k: usize) -> (Vec<usize>, Vec<f32>) {
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results.truncate(k);
+        results.into_iter().unzip()
+    }
+
     // Validate that the allowed ids are not in the disallowed ids
     fn validate_allowed_and_disallowed_ids(
         &self,
         allowed_ids: &[u32],
         disallowed_ids: &[u32],
     ) -> Result<(), Box<dyn ChromaError>> {
+        let disallowed_ids: HashSet<&u32> = disallowed_ids.iter().collect();
         for allowed_id in allowed_ids {
             if disallowed_ids.contains(allowed_id) {
                 return Err(Box::new(
@@ -101,6 +240,13 @@ impl HnswKnnOperator {
     }
 }
 
+fn to_bitmap(offset_ids: &[usize]) -> RoaringBitmap {
+    offset_ids
+        .iter()
+        .map(|offset_id| *offset_id as u32)
+        .collect()
+}
+
 #[async_trait]
 impl Operator<HnswKnnOperatorInput, HnswKnnOperatorOutput> for HnswKnnOperator {
     type Error = Box<dyn ChromaError>;
@@ -184,15 +330,269 @@ impl Operator<HnswKnnOperatorInput, HnswKnnOperatorOutput> for HnswKnnOperator {
         let disallowed_offset_ids: Vec<usize> =
             disallowed_offset_ids.iter().map(|&x| x as usize).collect();
 
-        let (offset_ids, distances) = input.segment.query(
-            &input.query,
-            input.k,
-            &allowed_offset_ids,
-            &disallowed_offset_ids,
-        );
+        let (offset_ids, distances) = if input.search_options.brute_force {
+            match self
+                .brute_force_query(
+                    input,
+                    &record_segment_reader,
+                    &allowed_offset_ids,
+                    &disallowed_offset_ids,
+                )
+                .await
+            {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::error!(
+                        "[HnswKnnOperation]: Error running brute force query {:?}",
+                        e
+                    );
+                    return Err(Box::new(HnswKnnOperatorError::RecordSegmentReadError));
+                }
+            }
+        } else if input.segment.is_multi_vector() {
+            match self
+                .multi_vector_query(
+                    input,
+                    &record_segment_reader,
+                    &allowed_offset_ids,
+                    &disallowed_offset_ids,
+                )
+                .await
+            {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::error!(
+                        "[HnswKnnOperation]: Error running multi-vector query {:?}",
+                        e
+                    );
+                    return Err(Box::new(HnswKnnOperatorError::RecordSegmentReadError));
+                }
+            }
+        } else {
+            let num_candidates = input.search_options.num_candidates(input.k);
+            let (offset_ids, distances) = input.segment.query(
+                &input.query,
+                num_candidates,
+                input.search_options.ef_search,
+                &allowed_offset_ids,
+                &disallowed_offset_ids,
+            );
+            match input.search_options.oversampling {
+                Some(_) => match self.rerank(input, &record_segment_reader, offset_ids).await {
+                    Ok(result) => result,
+                    Err(e) => {
+                        tracing::error!("[HnswKnnOperation]: Error re-ranking candidates {:?}", e);
+                        return Err(Box::new(HnswKnnOperatorError::RecordSegmentReadError));
+                    }
+                },
+                None => {
+                    // Results are sorted by distance
+                    let mut offset_ids = offset_ids;
+                    let mut distances = distances;
+                    offset_ids.truncate(input.k);
+                    distances.truncate(input.k);
+                    (offset_ids, distances)
+                }
+            }
+        };
         Ok(HnswKnnOperatorOutput {
             offset_ids,
             distances,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockstore::arrow::provider::ArrowBlockfileProvider;
+    use crate::index::hnsw_provider::HnswIndexProvider;
+    use crate::segment::distributed_hnsw_segment::DistributedHNSWSegmentWriter;
+    use crate::segment::record_segment::RecordSegmentWriter;
+    use crate::segment::{LogMaterializer, SegmentFlusher, SegmentWriter};
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::types::{Metadata, MetadataValue, OperationRecord, SegmentScope, SegmentType};
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    // Writes the records to a record segment and an hnsw segment and returns a
+    // query input over them.
+    async fn setup(
+        tmp_dir: &std::path::Path,
+        dimensionality: usize,
+        embeddings: Vec<Vec<f32>>,
+        hnsw_metadata: Option<Metadata>,
+    ) -> HnswKnnOperatorInput {
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage.clone()));
+        let hnsw_index_provider =
+            HnswIndexProvider::new(storage, tmp_dir.join("hnsw"), 1024 * 1024 * 1024);
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut hnsw_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::HnswDistributed,
+            scope: SegmentScope::VECTOR,
+            collection: record_segment.collection,
+            metadata: hnsw_metadata,
+            file_path: HashMap::new(),
+        };
+        let logs: Vec<LogRecord> = embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(i, embedding)| LogRecord {
+                log_offset: i as i64 + 1,
+                record: OperationRecord {
+                    id: format!("embedding_id_{}", i),
+                    embedding: Some(embedding),
+                    encoding: None,
+                    sparse_vector: None,
+                    metadata: None,
+                    document: None,
+                    operation: Operation::Add,
+                },
+            })
+            .collect();
+        let record_segment_writer =
+            RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                .await
+                .unwrap();
+        let hnsw_segment_writer = DistributedHNSWSegmentWriter::from_segment(
+            &hnsw_segment,
+            dimensionality,
+            hnsw_index_provider.clone(),
+        )
+        .await
+        .unwrap();
+        let materializer = LogMaterializer::new(None, Chunk::new(logs.into()), None);
+        let records = materializer.materialize().await.unwrap();
+        record_segment_writer
+            .apply_materialized_log_chunk(records.clone())
+            .await
+            .unwrap();
+        hnsw_segment_writer
+            .apply_materialized_log_chunk(records)
+            .await
+            .unwrap();
+        record_segment.file_path = record_segment_writer
+            .commit()
+            .unwrap()
+            .flush()
+            .await
+            .unwrap();
+        hnsw_segment.file_path = hnsw_segment_writer.commit().unwrap().flush().await.unwrap();
+        let segment = DistributedHNSWSegmentReader::from_segment(
+            &hnsw_segment,
+            dimensionality,
+            hnsw_index_provider,
+        )
+        .await
+        .unwrap();
+        HnswKnnOperatorInput {
+            segment,
+            query: vec![0.0; dimensionality],
+            k: 2,
+            distance_function: DistanceFunction::Euclidean,
+            search_options: SearchOptions::default(),
+            record_segment,
+            blockfile_provider,
+            allowed_ids: Arc::new([]),
+            allowed_ids_hnsw: Arc::new([]),
+            logs: Chunk::new(Vec::new().into()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_options_return_the_same_neighbors() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let embeddings = (0..20).map(|i| vec![i as f32, 1.0, 2.0]).collect();
+        let mut input = setup(tmp_dir.path(), 3, embeddings, None).await;
+        let operator = HnswKnnOperator {};
+
+        let default_output = operator.run(&input).await.unwrap();
+        assert_eq!(default_output.offset_ids.len(), 2);
+
+        input.search_options = SearchOptions {
+            ef_search: Some(50),
+            oversampling: Some(3.0),
+            brute_force: false,
+            nprobe: None,
+        };
+        let reranked_output = operator.run(&input).await.unwrap();
+        assert_eq!(reranked_output.offset_ids, default_output.offset_ids);
+        assert_eq!(reranked_output.distances, default_output.distances);
+
+        input.search_options = SearchOptions {
+            ef_search: None,
+            oversampling: None,
+            brute_force: true,
+            nprobe: None,
+        };
+        let brute_force_output = operator.run(&input).await.unwrap();
+        assert_eq!(brute_force_output.offset_ids, default_output.offset_ids);
+        assert_eq!(brute_force_output.distances, default_output.distances);
+    }
+
+    #[tokio::test]
+    async fn test_brute_force_respects_allowed_ids() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let embeddings = (0..20).map(|i| vec![i as f32, 1.0, 2.0]).collect();
+        let mut input = setup(tmp_dir.path(), 3, embeddings, None).await;
+        let allowed_ids: Arc<[String]> = ["embedding_id_9", "embedding_id_5", "embedding_id_7"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        input.allowed_ids = allowed_ids.clone();
+        input.allowed_ids_hnsw = allowed_ids;
+        let operator = HnswKnnOperator {};
+
+        let output = operator.run(&input).await.unwrap();
+        assert_eq!(output.distances, vec![30.0, 54.0]);
+
+        input.search_options.brute_force = true;
+        let brute_force_output = operator.run(&input).await.unwrap();
+        assert_eq!(brute_force_output.offset_ids, output.offset_ids);
+        assert_eq!(brute_force_output.distances, output.distances);
+    }
+
+    #[tokio::test]
+    async fn test_multi_vector_query_ranks_by_max_sim() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let mut metadata = Metadata::new();
+        metadata.insert(
+            "hnsw:space".to_string(),
+            MetadataValue::Str("ip".to_string()),
+        );
+        metadata.insert("hnsw:multi_vector".to_string(), MetadataValue::Bool(true));
+        metadata.insert("hnsw:M".to_string(), MetadataValue::Int(16));
+        metadata.insert("hnsw:construction_ef".to_string(), MetadataValue::Int(100));
+        metadata.insert("hnsw:search_ef".to_string(), MetadataValue::Int(100));
+        let embeddings = vec![
+            vec![1.0, 0.0, 0.0, 1.0],
+            vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0],
+            vec![0.0, 0.9],
+            vec![0.4, 0.4],
+        ];
+        let mut input = setup(tmp_dir.path(), 2, embeddings, Some(metadata)).await;
+        // Every vector of every record is an element of the index
+        assert_eq!(input.segment.len(), 7);
+        input.distance_function = DistanceFunction::InnerProduct;
+        input.query = vec![1.0, 0.0, 0.0, 1.0];
+        let operator = HnswKnnOperator {};
+
+        let output = operator.run(&input).await.unwrap();
+        assert_eq!(output.distances, vec![0.0, 1.0]);
+
+        input.search_options.brute_force = true;
+        let brute_force_output = operator.run(&input).await.unwrap();
+        assert_eq!(brute_force_output.offset_ids, output.offset_ids);
+        assert_eq!(brute_force_output.distances, output.distances);
+    }
+}
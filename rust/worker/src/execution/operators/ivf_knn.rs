@@ -0,0 +1,296 @@
+use crate::blockstore::provider::BlockfileProvider;
+use crate::errors::{ChromaError, ErrorCodes};
+use crate::execution::data::data_chunk::Chunk;
+use crate::execution::operator::Operator;
+use crate::segment::ivf_segment::{IvfSegmentError, IvfSegmentReader};
+use crate::segment::record_segment::RecordSegmentReader;
+use crate::types::{LogRecord, Operation, SearchOptions, Segment};
+use async_trait::async_trait;
+use roaring::RoaringBitmap;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// The ivf k-nearest neighbors operator finds the k records of an ivf segment
+/// closest to a query vector.
+/// # Description
+/// The `nprobe` inverted lists with the centroids closest to the query are
+/// searched exactly. The search options set `nprobe`, it defaults to the
+/// `ivf:nprobe` of the segment, and brute force searches every list.
+#[derive(Debug)]
+pub struct IvfKnnOperator {}
+
+/// The input to the ivf k-nearest neighbors operator.
+/// # Parameters
+/// * `segment` - The ivf segment to search.
+/// * `dimensionality` - The dimensionality of the collection.
+/// * `query` - The query vector, normalized for cosine distance.
+/// * `k` - The number of nearest neighbors to find.
+/// * `search_options` - The search options of the query.
+/// * `record_segment` - The record segment, to map between user and offset ids.
+/// * `allowed_ids` - The user ids to search, empty when there is no filter.
+/// * `allowed_ids_ivf` - The allowed ids that are not in the log.
+/// * `logs` - The records that have not been compacted into the segments yet.
+#[derive(Debug)]
+pub struct IvfKnnOperatorInput {
+    pub segment: Segment,
+    pub dimensionality: usize,
+    pub query: Vec<f32>,
+    pub k: usize,
+    pub search_options: SearchOptions,
+    pub record_segment: Segment,
+    pub blockfile_provider: BlockfileProvider,
+    pub allowed_ids: Arc<[String]>,
+    pub allowed_ids_ivf: Arc<[String]>,
+    pub logs: Chunk<LogRecord>,
+}
+
+/// The output of the ivf k-nearest neighbors operator, sorted by distance.
+#[derive(Debug)]
+pub struct IvfKnnOperatorOutput {
+    pub offset_ids: Vec<usize>,
+    pub distances: Vec<f32>,
+}
+
+#[derive(Error, Debug)]
+pub enum IvfKnnOperatorError {
+    #[error("Error creating Record Segment")]
+    RecordSegmentError,
+    #[error("Error reading Record Segment")]
+    RecordSegmentReadError,
+    #[error("Error creating IVF Segment")]
+    IvfSegment(#[from] IvfSegmentError),
+}
+
+impl ChromaError for IvfKnnOperatorError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            IvfKnnOperatorError::RecordSegmentError => ErrorCodes::Internal,
+            IvfKnnOperatorError::RecordSegmentReadError => ErrorCodes::Internal,
+            IvfKnnOperatorError::IvfSegment(e) => e.code(),
+        }
+    }
+}
+
+impl IvfKnnOperator {
+    // Records that the log deletes or updates are skipped since the log has the
+    // latest version of them.
+    async fn get_disallowed_ids(
+        &self,
+        logs: &Chunk<LogRecord>,
+        record_segment_reader: &RecordSegmentReader<'_>,
+    ) -> Result<RoaringBitmap, Box<dyn ChromaError>> {
+        let mut disallowed_ids = RoaringBitmap::new();
+        for (log, _) in logs.iter() {
+            let operation_record = &log.record;
+            if operation_record.operation == Operation::Delete
+                || operation_record.operation == Operation::Update
+            {
+                let offset_id = record_segment_reader
+                    .get_offset_id_for_user_id(&operation_record.id)
+                    .await?;
+                disallowed_ids.insert(offset_id);
+            }
+        }
+        Ok(disallowed_ids)
+    }
+}
+
+#[async_trait]
+impl Operator<IvfKnnOperatorInput, IvfKnnOperatorOutput> for IvfKnnOperator {
+    type Error = Box<dyn ChromaError>;
+
+    async fn run(&self, input: &IvfKnnOperatorInput) -> Result<IvfKnnOperatorOutput, Self::Error> {
+        let empty_output = IvfKnnOperatorOutput {
+            offset_ids: vec![],
+            distances: vec![],
+        };
+        // If the filter only allows records in the log there is nothing to search
+        if !input.allowed_ids.is_empty() && input.allowed_ids_ivf.is_empty() {
+            return Ok(empty_output);
+        }
+        let ivf_segment_reader = match IvfSegmentReader::from_segment(
+            &input.segment,
+            input.dimensionality,
+            &input.blockfile_provider,
+        )
+        .await
+        {
+            Ok(reader) => reader,
+            Err(e) => match *e {
+                IvfSegmentError::UninitializedSegment => return Ok(empty_output),
+                e => return Err(Box::new(IvfKnnOperatorError::from(e))),
+            },
+        };
+        let record_segment_reader = match RecordSegmentReader::from_segment(
+            &input.record_segment,
+            &input.blockfile_provider,
+        )
+        .await
+        {
+            Ok(reader) => reader,
+            Err(e) => {
+                tracing::error!("[IvfKnnOperation]: Error creating record segment {:?}", e);
+                return Err(Box::new(IvfKnnOperatorError::RecordSegmentError));
+            }
+        };
+        let mut allowed_offset_ids = RoaringBitmap::new();
+        for user_id in input.allowed_ids_ivf.iter() {
+            match record_segment_reader
+                .get_offset_id_for_user_id(user_id)
+                .await
+            {
+                Ok(offset_id) => {
+                    allowed_offset_ids.insert(offset_id);
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "[IvfKnnOperation]: Record segment read error for allowed ids {:?}",
+                        e
+                    );
+                    return Err(Box::new(IvfKnnOperatorError::RecordSegmentReadError));
+                }
+            }
+        }
+        let disallowed_offset_ids = match self
+            .get_disallowed_ids(&input.logs, &record_segment_reader)
+            .await
+        {
+            Ok(disallowed_offset_ids) => disallowed_offset_ids,
+            Err(e) => {
+                tracing::error!("[IvfKnnOperation]: Error fetching disallowed ids {:?}", e);
+                return Err(Box::new(IvfKnnOperatorError::RecordSegmentReadError));
+            }
+        };
+        let nprobe = match input.search_options.brute_force {
+            true => usize::MAX,
+            false => input
+                .search_options
+                .nprobe
+                .unwrap_or(ivf_segment_reader.nprobe()),
+        };
+        let (offset_ids, distances) = ivf_segment_reader
+            .query(
+                &input.query,
+                input.k,
+                nprobe,
+                &allowed_offset_ids,
+                &disallowed_offset_ids,
+            )
+            .await?;
+        Ok(IvfKnnOperatorOutput {
+            offset_ids,
+            distances,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockstore::arrow::provider::ArrowBlockfileProvider;
+    use crate::segment::ivf_segment::IvfSegmentWriter;
+    use crate::segment::record_segment::RecordSegmentWriter;
+    use crate::segment::{LogMaterializer, SegmentFlusher, SegmentWriter};
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::types::{MetadataValue, OperationRecord, SegmentScope, SegmentType};
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_ivf_knn_nprobe() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut ivf_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileIvf,
+            scope: SegmentScope::VECTOR,
+            collection: record_segment.collection,
+            metadata: Some(HashMap::from([
+                ("ivf:nlist".to_string(), MetadataValue::Int(2)),
+                ("ivf:nprobe".to_string(), MetadataValue::Int(1)),
+            ])),
+            file_path: HashMap::new(),
+        };
+        // Two clusters, around the origin and around (10, 10)
+        let embeddings = [[0.0, 0.0], [0.5, 0.0], [10.0, 10.0], [10.5, 10.0]];
+        let logs: Vec<LogRecord> = embeddings
+            .iter()
+            .enumerate()
+            .map(|(i, embedding)| LogRecord {
+                log_offset: i as i64 + 1,
+                record: OperationRecord {
+                    id: format!("embedding_id_{}", i),
+                    embedding: Some(embedding.to_vec()),
+                    encoding: None,
+                    sparse_vector: None,
+                    metadata: None,
+                    document: None,
+                    operation: Operation::Add,
+                },
+            })
+            .collect();
+        let record_segment_writer =
+            RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                .await
+                .unwrap();
+        let ivf_segment_writer =
+            IvfSegmentWriter::from_segment(&ivf_segment, 2, &blockfile_provider)
+                .await
+                .unwrap();
+        let materializer = LogMaterializer::new(None, Chunk::new(logs.into()), None);
+        let records = materializer.materialize().await.unwrap();
+        record_segment_writer
+            .apply_materialized_log_chunk(records.clone())
+            .await
+            .unwrap();
+        ivf_segment_writer
+            .apply_materialized_log_chunk(records)
+            .await
+            .unwrap();
+        record_segment.file_path = record_segment_writer
+            .commit()
+            .unwrap()
+            .flush()
+            .await
+            .unwrap();
+        ivf_segment.file_path = ivf_segment_writer.commit().unwrap().flush().await.unwrap();
+
+        let operator = IvfKnnOperator {};
+        let mut input = IvfKnnOperatorInput {
+            segment: ivf_segment,
+            dimensionality: 2,
+            query: vec![1.0, 1.0],
+            k: 3,
+            search_options: SearchOptions::default(),
+            record_segment,
+            blockfile_provider,
+            allowed_ids: Arc::new([]),
+            allowed_ids_ivf: Arc::new([]),
+            logs: Chunk::new(Vec::new().into()),
+        };
+        // One list is probed, the one around the origin
+        let output = operator.run(&input).await.unwrap();
+        assert_eq!(output.offset_ids, vec![2, 1]);
+
+        input.search_options.nprobe = Some(2);
+        let output = operator.run(&input).await.unwrap();
+        assert_eq!(output.offset_ids, vec![2, 1, 3]);
+
+        input.search_options = SearchOptions {
+            brute_force: true,
+            ..Default::default()
+        };
+        let output = operator.run(&input).await.unwrap();
+        assert_eq!(output.offset_ids, vec![2, 1, 3]);
+    }
+}
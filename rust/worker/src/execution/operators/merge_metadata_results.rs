@@ -2,17 +2,23 @@ use crate::{
     blockstore::provider::BlockfileProvider,
     errors::{ChromaError, ErrorCodes},
     execution::{data::data_chunk::Chunk, operator::Operator},
+    index::fulltext::types::{match_score, FullTextIndexError},
     segment::{
+        metadata_segment::{MetadataSegmentError, MetadataSegmentReader},
         record_segment::{RecordSegmentReader, RecordSegmentReaderCreationError},
-        LogMaterializer, LogMaterializerError,
+        LogMaterializer, LogMaterializerError, MaterializedLogRecord,
+    },
+    types::{
+        DocumentHighlight, LogRecord, Metadata, MetadataValue, MetadataValueConversionError,
+        Operation, OrderBy, Segment,
     },
-    types::{LogRecord, Metadata, MetadataValueConversionError, Operation, Segment},
     utils::merge_sorted_vecs_conjunction,
 };
 use async_trait::async_trait;
+use roaring::RoaringBitmap;
 use std::{
+    cmp::Ordering,
     collections::{HashMap, HashSet},
-    sync::{atomic::AtomicU32, Arc},
 };
 use thiserror::Error;
 use tracing::{error, trace};
@@ -35,23 +41,43 @@ pub struct MergeMetadataResultsOperatorInput {
     // The offset ids filtered by the where and where_document clause.
     filtered_offset_ids: Option<Vec<u32>>,
     record_segment_definition: Segment,
+    // Only read when the results are ordered by a metadata key.
+    metadata_segment_definition: Segment,
     blockfile_provider: BlockfileProvider,
+    // The page of results to return.
+    limit: Option<u32>,
+    offset: u32,
+    order_by: Option<OrderBy>,
+    // The queries to highlight in the documents of the page, none if the
+    // user did not ask for snippets.
+    highlight_queries: Vec<String>,
 }
 
 impl MergeMetadataResultsOperatorInput {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         filtered_log: Chunk<LogRecord>,
         user_offset_ids: Option<Vec<u32>>,
         filtered_offset_ids: Option<Vec<u32>>,
         record_segment_definition: Segment,
+        metadata_segment_definition: Segment,
         blockfile_provider: BlockfileProvider,
+        limit: Option<u32>,
+        offset: u32,
+        order_by: Option<OrderBy>,
+        highlight_queries: Vec<String>,
     ) -> Self {
         Self {
             filtered_log,
             user_offset_ids,
             filtered_offset_ids,
             record_segment_definition,
+            metadata_segment_definition,
             blockfile_provider: blockfile_provider,
+            limit,
+            offset,
+            order_by,
+            highlight_queries,
         }
     }
 }
@@ -61,6 +87,7 @@ pub struct MergeMetadataResultsOperatorOutput {
     pub ids: Vec<String>,
     pub metadata: Vec<Option<Metadata>>,
     pub documents: Vec<Option<String>>,
+    pub highlights: Vec<Option<DocumentHighlight>>,
 }
 
 #[derive(Error, Debug)]
@@ -73,6 +100,10 @@ pub enum MergeMetadataResultsOperatorError {
     MetadataConversionError(#[from] MetadataValueConversionError),
     #[error("Error materializing logs")]
     LogMaterializationError(#[from] LogMaterializerError),
+    #[error("Error reading Metadata Segment")]
+    MetadataSegmentReadError(#[from] MetadataSegmentError),
+    #[error("Error searching the full text index")]
+    FullTextIndexError(#[from] FullTextIndexError),
 }
 
 impl ChromaError for MergeMetadataResultsOperatorError {
@@ -82,6 +113,8 @@ impl ChromaError for MergeMetadataResultsOperatorError {
             MergeMetadataResultsOperatorError::RecordSegmentReadError => ErrorCodes::Internal,
             MergeMetadataResultsOperatorError::MetadataConversionError(e) => e.code(),
             MergeMetadataResultsOperatorError::LogMaterializationError(e) => e.code(),
+            MergeMetadataResultsOperatorError::MetadataSegmentReadError(e) => e.code(),
+            MergeMetadataResultsOperatorError::FullTextIndexError(e) => e.code(),
         }
     }
 }
@@ -172,192 +205,337 @@ impl Operator<MergeMetadataResultsOperatorInput, MergeMetadataResultsOperatorOut
             }
         };
 
-        // Step 2: Hydrate data.
-        let mut ids: Vec<String> = Vec::new();
-        let mut metadata = Vec::new();
-        let mut documents = Vec::new();
-        let mut visited_ids: HashSet<u32> = HashSet::new();
-        match merged_offset_ids {
-            Some(merged_ids) => {
-                // Hydrate from the logs first.
-                for (log, _) in mat_records.iter() {
-                    if merged_ids.contains(&log.offset_id) {
-                        // It's important to account for the records that are
-                        // deleted also here so that we can subsequently ignore
-                        // them when reading the record segment.
-                        visited_ids.insert(log.offset_id);
-                        if log.final_operation != Operation::Delete {
-                            documents.push(log.merged_document());
-                            let final_metadata = log.merged_metadata();
-                            if !final_metadata.is_empty() {
-                                metadata.push(Some(final_metadata));
-                            } else {
-                                metadata.push(None);
-                            }
-                            ids.push(log.merged_user_id());
-                        }
-                    }
+        // Step 2: Collect the offset ids of the matching records. The log
+        // shadows the record segment.
+        let mut log_records: HashMap<u32, &MaterializedLogRecord> = HashMap::new();
+        let mut log_offset_ids: HashSet<u32> = HashSet::new();
+        for (log, _) in mat_records.iter() {
+            // It's important to account for the records that are deleted
+            // also here so that we can subsequently ignore them when reading
+            // the record segment.
+            log_offset_ids.insert(log.offset_id);
+            if log.final_operation == Operation::Delete {
+                continue;
+            }
+            let matches = match &merged_offset_ids {
+                Some(merged_ids) => merged_ids.contains(&log.offset_id),
+                None => true,
+            };
+            if matches {
+                log_records.insert(log.offset_id, log);
+            }
+        }
+        let record_segment_reader = match RecordSegmentReader::from_segment(
+            &input.record_segment_definition,
+            &input.blockfile_provider,
+        )
+        .await
+        {
+            Ok(reader) => Some(reader),
+            Err(e) => match *e {
+                // This means no compaction has occured, so only the log has records.
+                RecordSegmentReaderCreationError::UninitializedSegment => None,
+                e => {
+                    error!("Error creating Record Segment: {:?}", e);
+                    return Err(MergeMetadataResultsOperatorError::RecordSegmentCreationError(e));
                 }
-                // Hydrate remaining from record segment.
-                let record_segment_reader = match RecordSegmentReader::from_segment(
-                    &input.record_segment_definition,
+            },
+        };
+        let segment_offset_ids: HashSet<u32> = match (&record_segment_reader, merged_offset_ids) {
+            (None, _) => HashSet::new(),
+            (Some(_), Some(merged_ids)) => merged_ids
+                .into_iter()
+                .filter(|offset_id| !log_offset_ids.contains(offset_id))
+                .collect(),
+            // User supplied neither ids nor a where clause so we return everything.
+            (Some(reader), None) => match reader.get_all_offset_ids().await {
+                Ok(offset_ids) => offset_ids
+                    .into_iter()
+                    .filter(|offset_id| !log_offset_ids.contains(offset_id))
+                    .collect(),
+                Err(e) => {
+                    tracing::error!("[Mergemetadata]: Error reading Record Segment: {:?}", e);
+                    return Err(MergeMetadataResultsOperatorError::RecordSegmentReadError);
+                }
+            },
+        };
+
+        // Step 3: Order the records and select the page. Records are ordered
+        // by offset id unless the user asked for an order, so pages are stable.
+        let ordered_offset_ids = match &input.order_by {
+            Some(order_by) => {
+                order_by_metadata(
+                    order_by,
+                    &log_records,
+                    &segment_offset_ids,
+                    input
+                        .limit
+                        .map(|limit| input.offset as usize + limit as usize),
+                    &input.metadata_segment_definition,
                     &input.blockfile_provider,
                 )
-                .await
-                {
-                    Ok(reader) => reader,
-                    Err(e) => {
-                        match *e {
-                            RecordSegmentReaderCreationError::UninitializedSegment => {
-                                // This means no compaction has occured, so we can just return whats on the log.
-                                return Ok(MergeMetadataResultsOperatorOutput {
-                                    ids,
-                                    metadata,
-                                    documents,
-                                });
-                            }
-                            RecordSegmentReaderCreationError::BlockfileOpenError(_) => {
-                                error!("Error creating Record Segment: {:?}", e);
-                                return Err(
-                                    MergeMetadataResultsOperatorError::RecordSegmentCreationError(
-                                        *e,
-                                    ),
-                                );
-                            }
-                            RecordSegmentReaderCreationError::InvalidNumberOfFiles => {
-                                error!("Error creating Record Segment: {:?}", e);
-                                return Err(
-                                    MergeMetadataResultsOperatorError::RecordSegmentCreationError(
-                                        *e,
-                                    ),
-                                );
-                            }
-                        }
-                    }
-                };
-                for merged_id in merged_ids {
-                    // Skip already taken from log.
-                    if visited_ids.contains(&merged_id) {
-                        continue;
-                    }
-                    let record = match record_segment_reader
-                        .get_data_for_offset_id(merged_id)
-                        .await
-                    {
-                        Ok(record) => record,
-                        Err(e) => {
-                            tracing::error!("Error reading Record Segment: {:?}", e);
-                            return Err(MergeMetadataResultsOperatorError::RecordSegmentReadError);
-                        }
-                    };
+                .await?
+            }
+            None => {
+                let mut offset_ids: Vec<u32> = log_records
+                    .keys()
+                    .chain(segment_offset_ids.iter())
+                    .cloned()
+                    .collect();
+                offset_ids.sort();
+                offset_ids
+            }
+        };
+        let page: Vec<u32> = ordered_offset_ids
+            .into_iter()
+            .skip(input.offset as usize)
+            .take(input.limit.map_or(usize::MAX, |limit| limit as usize))
+            .collect();
 
-                    let user_id = match record_segment_reader
-                        .get_user_id_for_offset_id(merged_id)
-                        .await
-                    {
-                        Ok(user_id) => user_id,
-                        Err(e) => {
-                            println!("Error reading Record Segment: {:?}", e);
-                            return Err(MergeMetadataResultsOperatorError::RecordSegmentReadError);
-                        }
-                    };
+        // Step 4: Look up the matches of the highlighted queries in the
+        // documents of the segment records on the page.
+        let page_segment_offset_ids: RoaringBitmap = page
+            .iter()
+            .filter(|offset_id| !log_records.contains_key(offset_id))
+            .cloned()
+            .collect();
+        let indexed_matches = if input.highlight_queries.is_empty() {
+            vec![]
+        } else {
+            search_full_text_index(
+                &input.highlight_queries,
+                &page_segment_offset_ids,
+                &input.metadata_segment_definition,
+                &input.blockfile_provider,
+            )
+            .await?
+        };
 
-                    ids.push(user_id.to_string());
-                    metadata.push(record.metadata.clone());
-                    match record.document {
-                        Some(document) => documents.push(Some(document.to_string())),
-                        None => documents.push(None),
-                    }
+        // Step 5: Hydrate the page.
+        let mut ids: Vec<String> = Vec::new();
+        let mut metadata = Vec::new();
+        let mut documents = Vec::new();
+        let mut highlights = Vec::new();
+        for offset_id in page {
+            if let Some(log) = log_records.get(&offset_id) {
+                ids.push(log.merged_user_id());
+                let document = log.merged_document();
+                // Log records are not in the full text index yet.
+                highlights.push(document.as_deref().and_then(|document| {
+                    highlight_document(document, &input.highlight_queries, &[], offset_id)
+                }));
+                documents.push(document);
+                let final_metadata = log.merged_metadata();
+                if !final_metadata.is_empty() {
+                    metadata.push(Some(final_metadata));
+                } else {
+                    metadata.push(None);
                 }
+                continue;
             }
-            // Full scan over the log record and segment.
-            None => {
-                // ids_in_log is used to ignore processing the records
-                // present in log when reading from the segment.
-                let mut ids_in_log = HashSet::new();
-                // First: full scan over the log record.
-                for (log, _) in mat_records.iter() {
-                    // It's important to insert even the deleted records here
-                    // so that they can be ignored when reading from the segment.
-                    ids_in_log.insert(log.merged_user_id());
-                    if log.final_operation != Operation::Delete {
-                        documents.push(log.merged_document());
-                        let final_metadata = log.merged_metadata();
-                        if !final_metadata.is_empty() {
-                            metadata.push(Some(final_metadata));
-                        } else {
-                            metadata.push(None);
-                        }
-                        ids.push(log.merged_user_id());
-                    }
-                }
-                // Second: Hydrate remaining records from record segment.
-                let record_segment_reader = match RecordSegmentReader::from_segment(
-                    &input.record_segment_definition,
-                    &input.blockfile_provider,
-                )
+            let record = match record_segment_reader
+                .as_ref()
+                .expect("Offset ids that are not in the log come from the record segment")
+                .get_data_for_offset_id(offset_id)
                 .await
-                {
-                    Ok(reader) => reader,
-                    Err(e) => {
-                        match *e {
-                            RecordSegmentReaderCreationError::UninitializedSegment => {
-                                // This means no compaction has occured, so we can just return whats on the log.
-                                return Ok(MergeMetadataResultsOperatorOutput {
-                                    ids,
-                                    metadata,
-                                    documents,
-                                });
-                            }
-                            RecordSegmentReaderCreationError::BlockfileOpenError(_) => {
-                                error!("Error creating Record Segment: {:?}", e);
-                                return Err(
-                                    MergeMetadataResultsOperatorError::RecordSegmentCreationError(
-                                        *e,
-                                    ),
-                                );
-                            }
-                            RecordSegmentReaderCreationError::InvalidNumberOfFiles => {
-                                error!("Error creating Record Segment: {:?}", e);
-                                return Err(
-                                    MergeMetadataResultsOperatorError::RecordSegmentCreationError(
-                                        *e,
-                                    ),
-                                );
-                            }
-                        }
-                    }
-                };
-                let data = match record_segment_reader.get_all_data().await {
-                    Ok(data) => data,
-                    Err(e) => {
-                        tracing::error!("[Mergemetadata]: Error reading Record Segment: {:?}", e);
-                        return Err(MergeMetadataResultsOperatorError::RecordSegmentReadError);
-                    }
-                };
-                for record in data.iter() {
-                    // Ignore records processed from the log.
-                    if ids_in_log.contains(record.id) {
-                        continue;
-                    }
-                    ids.push(record.id.to_string());
-                    metadata.push(record.metadata.clone());
-                    match record.document {
-                        Some(document) => documents.push(Some(document.to_string())),
-                        None => documents.push(None),
-                    }
+            {
+                Ok(record) => record,
+                Err(e) => {
+                    tracing::error!("Error reading Record Segment: {:?}", e);
+                    return Err(MergeMetadataResultsOperatorError::RecordSegmentReadError);
                 }
-            }
+            };
+            ids.push(record.id.to_string());
+            metadata.push(record.metadata.clone());
+            highlights.push(record.document.and_then(|document| {
+                highlight_document(
+                    document,
+                    &input.highlight_queries,
+                    &indexed_matches,
+                    offset_id,
+                )
+            }));
+            documents.push(record.document.map(|document| document.to_string()));
         }
 
         Ok(MergeMetadataResultsOperatorOutput {
             ids,
             metadata,
             documents,
+            highlights,
         })
     }
 }
 
+// Searches the full text index for every query, checking positions only in
+// the given records. None for a query the index can't look up, because it is
+// shorter than an n-gram or nothing has been indexed yet.
+async fn search_full_text_index(
+    queries: &[String],
+    offset_ids: &RoaringBitmap,
+    metadata_segment_definition: &Segment,
+    blockfile_provider: &BlockfileProvider,
+) -> Result<Vec<Option<HashMap<u32, Vec<u32>>>>, MergeMetadataResultsOperatorError> {
+    if offset_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let metadata_segment_reader =
+        MetadataSegmentReader::from_segment(metadata_segment_definition, blockfile_provider)
+            .await?;
+    let reader = match &metadata_segment_reader.full_text_index_reader {
+        Some(reader) => reader,
+        None => return Ok(vec![]),
+    };
+    let mut indexed_matches = Vec::with_capacity(queries.len());
+    for query in queries {
+        if reader.encode_tokens(query).get_tokens().is_empty() {
+            indexed_matches.push(None);
+            continue;
+        }
+        indexed_matches.push(Some(
+            reader.search_positions(query, Some(offset_ids)).await?,
+        ));
+    }
+    Ok(indexed_matches)
+}
+
+// Highlights the matches of the queries in a document. The positions come
+// from the full text index when it has them, otherwise the document is
+// scanned for the query.
+fn highlight_document(
+    document: &str,
+    queries: &[String],
+    indexed_matches: &[Option<HashMap<u32, Vec<u32>>>],
+    offset_id: u32,
+) -> Option<DocumentHighlight> {
+    let mut ranges = Vec::new();
+    let mut score = 0.0;
+    for (index, query) in queries.iter().enumerate() {
+        if query.is_empty() {
+            continue;
+        }
+        let positions: Vec<usize> = match indexed_matches.get(index) {
+            Some(Some(matches)) => matches.get(&offset_id).map_or(vec![], |positions| {
+                positions
+                    .iter()
+                    .map(|position| *position as usize)
+                    .collect()
+            }),
+            _ => document
+                .match_indices(query.as_str())
+                .map(|(position, _)| position)
+                .collect(),
+        };
+        score += match_score(positions.len());
+        ranges.extend(
+            positions
+                .into_iter()
+                .map(|position| (position, position + query.len())),
+        );
+    }
+    DocumentHighlight::new(document, ranges, score)
+}
+
+// Orders the offset ids by the value of the metadata key, up to the end of
+// the page. The order of the records in the segment comes from the sorted
+// metadata index, so only the records on the page have to be read from the
+// record segment.
+async fn order_by_metadata(
+    order_by: &OrderBy,
+    log_records: &HashMap<u32, &MaterializedLogRecord<'_>>,
+    segment_offset_ids: &HashSet<u32>,
+    page_end: Option<usize>,
+    metadata_segment_definition: &Segment,
+    blockfile_provider: &BlockfileProvider,
+) -> Result<Vec<u32>, MergeMetadataResultsOperatorError> {
+    let mut log_values: Vec<(MetadataValue, u32)> = vec![];
+    for (offset_id, log) in log_records.iter() {
+        if let Some(value) = log.merged_metadata().get(&order_by.key) {
+            for element in value.elements() {
+                log_values.push((element, *offset_id));
+            }
+        }
+    }
+    log_values.sort_by(|a, b| order_by.compare(&a.0, &b.0));
+    let metadata_segment_reader = match segment_offset_ids.is_empty() {
+        true => None,
+        false => Some(
+            MetadataSegmentReader::from_segment(metadata_segment_definition, blockfile_provider)
+                .await?,
+        ),
+    };
+    let mut segment_values = match &metadata_segment_reader {
+        Some(reader) => Some(reader.sorted_values(order_by).await?),
+        None => None,
+    };
+
+    // Walk the values of the log and of the segment in order, stopping once
+    // the page is full so the rest of the segment's index is never read.
+    // Records with the same value are ordered by offset id. A record with a
+    // list value is ordered by whichever of its elements comes first, its
+    // smallest element ascending and its largest descending.
+    let page_end = page_end.unwrap_or(usize::MAX);
+    let mut seen = HashSet::new();
+    let mut ordered_offset_ids: Vec<u32> = vec![];
+    let mut log_values = log_values.into_iter().peekable();
+    while ordered_offset_ids.len() < page_end {
+        let segment_value = match &mut segment_values {
+            Some(values) => values.peek().await?.map(|(value, _)| value.clone()),
+            None => None,
+        };
+        let value = match (log_values.peek(), segment_value) {
+            (Some((log_value, _)), Some(segment_value)) => {
+                match order_by.compare(log_value, &segment_value) {
+                    Ordering::Greater => segment_value,
+                    _ => log_value.clone(),
+                }
+            }
+            (Some((log_value, _)), None) => log_value.clone(),
+            (None, Some(segment_value)) => segment_value,
+            (None, None) => break,
+        };
+        let mut offset_ids = vec![];
+        while let Some((_, offset_id)) =
+            log_values.next_if(|(log_value, _)| order_by.compare(log_value, &value).is_eq())
+        {
+            offset_ids.push(offset_id);
+        }
+        if let Some(values) = &mut segment_values {
+            while let Some((segment_value, _)) = values.peek().await? {
+                if !order_by.compare(segment_value, &value).is_eq() {
+                    break;
+                }
+                if let Some((_, segment_offset_ids_of_value)) = values.next().await? {
+                    offset_ids.extend(
+                        segment_offset_ids_of_value
+                            .into_iter()
+                            .filter(|offset_id| segment_offset_ids.contains(offset_id)),
+                    );
+                }
+            }
+        }
+        offset_ids.sort();
+        ordered_offset_ids.extend(
+            offset_ids
+                .into_iter()
+                .filter(|offset_id| seen.insert(*offset_id)),
+        );
+    }
+    if ordered_offset_ids.len() >= page_end {
+        return Ok(ordered_offset_ids);
+    }
+    // Records without the key come last.
+    let mut missing_offset_ids: Vec<u32> = log_records
+        .keys()
+        .chain(segment_offset_ids.iter())
+        .filter(|offset_id| !seen.contains(offset_id))
+        .cloned()
+        .collect();
+    missing_offset_ids.sort();
+    ordered_offset_ids.extend(missing_offset_ids);
+    Ok(ordered_offset_ids)
+}
+
 #[cfg(test)]
 mod test {
     use std::{
@@ -377,6 +555,7 @@ mod test {
                 MergeMetadataResultsOperator, MergeMetadataResultsOperatorInput,
             },
         },
+        index::fulltext::types::match_score,
         segment::{
             metadata_segment::MetadataSegmentWriter,
             record_segment::{
@@ -385,7 +564,10 @@ mod test {
             LogMaterializer, SegmentFlusher, SegmentWriter,
         },
         storage::{local::LocalStorage, Storage},
-        types::{LogRecord, MetadataValue, Operation, OperationRecord, UpdateMetadataValue},
+        types::{
+            DocumentHighlight, LogRecord, MetadataValue, Operation, OperationRecord, OrderBy,
+            UpdateMetadataValue,
+        },
     };
 
     #[tokio::test]
@@ -440,6 +622,7 @@ mod test {
                         id: "embedding_id_1".to_string(),
                         embedding: Some(vec![1.0, 2.0, 3.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata.clone()),
                         document: Some(String::from("This is a document about cats.")),
                         operation: Operation::Add,
@@ -451,6 +634,7 @@ mod test {
                         id: "embedding_id_2".to_string(),
                         embedding: Some(vec![4.0, 5.0, 6.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata),
                         document: Some(String::from("This is a document about dogs.")),
                         operation: Operation::Add,
@@ -527,6 +711,7 @@ mod test {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata.clone()),
                     document: Some(String::from("This is a document about dogs.")),
                     operation: Operation::Add,
@@ -538,6 +723,7 @@ mod test {
                     id: "embedding_id_1".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata.clone()),
                     document: None,
                     operation: Operation::Update,
@@ -551,7 +737,12 @@ mod test {
             Some(vec![1, 3]),
             Some(vec![1, 2, 3]),
             record_segment,
+            metadata_segment,
             blockfile_provider,
+            None,
+            0,
+            None,
+            vec![],
         );
         let output = op.run(&input).await.expect("Error running operator");
         assert_eq!(2, output.ids.len());
@@ -728,6 +919,7 @@ mod test {
                         id: "embedding_id_1".to_string(),
                         embedding: Some(vec![1.0, 2.0, 3.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata.clone()),
                         document: Some(String::from("This is a document about cats.")),
                         operation: Operation::Add,
@@ -739,6 +931,7 @@ mod test {
                         id: "embedding_id_2".to_string(),
                         embedding: Some(vec![4.0, 5.0, 6.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata),
                         document: Some(String::from("This is a document about dogs.")),
                         operation: Operation::Add,
@@ -815,6 +1008,7 @@ mod test {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata.clone()),
                     document: Some(String::from("This is a document about dogs.")),
                     operation: Operation::Add,
@@ -826,6 +1020,7 @@ mod test {
                     id: "embedding_id_1".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata.clone()),
                     document: None,
                     operation: Operation::Update,
@@ -839,7 +1034,12 @@ mod test {
             None,
             None,
             record_segment,
+            metadata_segment,
             blockfile_provider,
+            None,
+            0,
+            None,
+            vec![],
         );
         let output = op.run(&input).await.expect("Error running operator");
         assert_eq!(3, output.ids.len());
@@ -1012,4 +1212,305 @@ mod test {
             &String::from("This is a document about dogs.")
         );
     }
+
+    #[tokio::test]
+    async fn test_pagination_and_order_by() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileRecord,
+            scope: crate::types::SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileMetadata,
+            scope: crate::types::SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let log_record = |log_offset: i64,
+                          id: &str,
+                          rank: Option<UpdateMetadataValue>,
+                          operation| {
+            let metadata = match rank {
+                Some(rank) => HashMap::from([(String::from("rank"), rank)]),
+                None => HashMap::from([(String::from("other"), UpdateMetadataValue::Bool(true))]),
+            };
+            LogRecord {
+                log_offset,
+                record: OperationRecord {
+                    id: id.to_string(),
+                    embedding: Some(vec![1.0, 2.0, 3.0]),
+                    encoding: None,
+                    sparse_vector: None,
+                    metadata: Some(metadata),
+                    document: Some(String::from("This is a document.")),
+                    operation,
+                },
+            }
+        };
+        {
+            let segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let data = vec![
+                log_record(
+                    1,
+                    "embedding_id_1",
+                    Some(UpdateMetadataValue::Int(3)),
+                    Operation::Add,
+                ),
+                log_record(
+                    2,
+                    "embedding_id_2",
+                    Some(UpdateMetadataValue::Int(1)),
+                    Operation::Add,
+                ),
+                log_record(
+                    3,
+                    "embedding_id_3",
+                    Some(UpdateMetadataValue::Float(2.5)),
+                    Operation::Add,
+                ),
+                log_record(4, "embedding_id_4", None, Operation::Add),
+            ];
+            let materializer = LogMaterializer::new(None, Chunk::new(data.into()), None);
+            let mat_records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .expect("Apply materialized log to metadata segment failed");
+            metadata_writer
+                .write_to_blockfiles()
+                .await
+                .expect("Write to blockfiles for metadata writer failed");
+            segment_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .expect("Apply materialized log to record segment failed");
+            record_segment.file_path = segment_writer
+                .commit()
+                .expect("Commit for segment writer failed")
+                .flush()
+                .await
+                .expect("Flush record segment writer failed");
+            metadata_segment.file_path = metadata_writer
+                .commit()
+                .expect("Commit for metadata writer failed")
+                .flush()
+                .await
+                .expect("Flush metadata segment writer failed");
+        }
+        let data: Chunk<LogRecord> = Chunk::new(
+            vec![
+                log_record(
+                    5,
+                    "embedding_id_5",
+                    Some(UpdateMetadataValue::Int(2)),
+                    Operation::Add,
+                ),
+                log_record(
+                    6,
+                    "embedding_id_2",
+                    Some(UpdateMetadataValue::Int(10)),
+                    Operation::Update,
+                ),
+                log_record(7, "embedding_id_3", None, Operation::Delete),
+            ]
+            .into(),
+        );
+        let op = MergeMetadataResultsOperator::new();
+        let order_by = |descending| {
+            Some(OrderBy {
+                key: String::from("rank"),
+                descending,
+            })
+        };
+        let cases = vec![
+            (None, None, 0, None, vec!["1", "2", "4", "5"]),
+            (None, Some(2), 1, None, vec!["2", "4"]),
+            (None, None, 0, order_by(false), vec!["5", "1", "2", "4"]),
+            (None, Some(2), 1, order_by(false), vec!["1", "2"]),
+            (None, None, 0, order_by(true), vec!["2", "1", "5", "4"]),
+            (None, Some(1), 0, order_by(true), vec!["2"]),
+            (None, Some(1), 3, order_by(true), vec!["4"]),
+            (
+                Some(vec![1, 2, 5]),
+                Some(2),
+                0,
+                order_by(false),
+                vec!["5", "1"],
+            ),
+            (None, None, 10, None, vec![]),
+        ];
+        for (user_offset_ids, limit, offset, order_by, expected) in cases {
+            let input = MergeMetadataResultsOperatorInput::new(
+                data.clone(),
+                user_offset_ids,
+                None,
+                record_segment.clone(),
+                metadata_segment.clone(),
+                blockfile_provider.clone(),
+                limit,
+                offset,
+                order_by,
+                vec![],
+            );
+            let output = op.run(&input).await.expect("Error running operator");
+            let expected: Vec<String> = expected
+                .into_iter()
+                .map(|id| format!("embedding_id_{}", id))
+                .collect();
+            assert_eq!(expected, output.ids);
+            assert_eq!(output.ids.len(), output.metadata.len());
+            assert_eq!(output.ids.len(), output.documents.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_highlights() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileRecord,
+            scope: crate::types::SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileMetadata,
+            scope: crate::types::SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let log_record = |log_offset: i64, id: &str, document: &str, operation| LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding: Some(vec![1.0, 2.0, 3.0]),
+                encoding: None,
+                sparse_vector: None,
+                metadata: None,
+                document: Some(document.to_string()),
+                operation,
+            },
+        };
+        {
+            let segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let data = vec![
+                log_record(
+                    1,
+                    "embedding_id_1",
+                    "the cat sat on the cat mat",
+                    Operation::Add,
+                ),
+                log_record(2, "embedding_id_2", "dogs only", Operation::Add),
+            ];
+            let materializer = LogMaterializer::new(None, Chunk::new(data.into()), None);
+            let mat_records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .expect("Apply materialized log to metadata segment failed");
+            metadata_writer
+                .write_to_blockfiles()
+                .await
+                .expect("Write to blockfiles for metadata writer failed");
+            segment_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .expect("Apply materialized log to record segment failed");
+            record_segment.file_path = segment_writer
+                .commit()
+                .expect("Commit for segment writer failed")
+                .flush()
+                .await
+                .expect("Flush record segment writer failed");
+            metadata_segment.file_path = metadata_writer
+                .commit()
+                .expect("Commit for metadata writer failed")
+                .flush()
+                .await
+                .expect("Flush metadata segment writer failed");
+        }
+        let data: Chunk<LogRecord> =
+            Chunk::new(vec![log_record(3, "embedding_id_3", "a cat", Operation::Add)].into());
+        let op = MergeMetadataResultsOperator::new();
+        // "at" is shorter than an n-gram, so the documents are scanned for it.
+        let input = MergeMetadataResultsOperatorInput::new(
+            data.clone(),
+            None,
+            None,
+            record_segment.clone(),
+            metadata_segment.clone(),
+            blockfile_provider.clone(),
+            None,
+            0,
+            None,
+            vec![String::from("cat"), String::from("at")],
+        );
+        let output = op.run(&input).await.expect("Error running operator");
+        assert_eq!(
+            output.highlights,
+            vec![
+                Some(DocumentHighlight {
+                    snippet: String::from("the cat sat on the cat mat"),
+                    highlights: vec![(4, 7), (9, 11), (19, 22), (24, 26)],
+                    score: match_score(2) + match_score(4),
+                }),
+                None,
+                Some(DocumentHighlight {
+                    snippet: String::from("a cat"),
+                    highlights: vec![(2, 5)],
+                    score: match_score(1) + match_score(1),
+                }),
+            ]
+        );
+
+        let input = MergeMetadataResultsOperatorInput::new(
+            data,
+            None,
+            None,
+            record_segment,
+            metadata_segment,
+            blockfile_provider,
+            None,
+            0,
+            None,
+            vec![],
+        );
+        let output = op.run(&input).await.expect("Error running operator");
+        assert_eq!(output.highlights, vec![None, None, None]);
+    }
 }
@@ -3,7 +3,7 @@ use crate::{
     errors::{ChromaError, ErrorCodes},
     execution::{data::data_chunk::Chunk, operator::Operator},
     index::{
-        fulltext::types::process_where_document_clause_with_callback,
+        fulltext::{pattern::DocumentPattern, types::process_where_document_clause_with_callback},
         metadata::types::{process_where_clause_with_callback, MetadataIndexError},
     },
     segment::{
@@ -13,15 +13,14 @@ use crate::{
     },
     types::{
         LogRecord, MetadataValue, Operation, Segment, Where, WhereClauseComparator, WhereDocument,
-        WhereDocumentOperator,
     },
     utils::{merge_sorted_vecs_conjunction, merge_sorted_vecs_disjunction},
 };
 use core::panic;
 use futures::stream::Count;
-use regex::Regex;
 use roaring::RoaringBitmap;
 use std::{
+    cmp::Ordering,
     collections::{HashMap, HashSet},
     sync::{
         atomic::{AtomicU16, AtomicU32},
@@ -115,6 +114,47 @@ impl ChromaError for MetadataFilteringError {
     }
 }
 
+// An uninitialized record segment is fine and means that the record
+// segment is not yet initialized in storage.
+async fn open_record_segment_reader<'me>(
+    input: &MetadataFilteringInput,
+) -> Result<Option<RecordSegmentReader<'me>>, MetadataFilteringError> {
+    match RecordSegmentReader::from_segment(&input.record_segment, &input.blockfile_provider).await
+    {
+        Ok(reader) => Ok(Some(reader)),
+        Err(e) => match *e {
+            RecordSegmentReaderCreationError::UninitializedSegment => Ok(None),
+            e => {
+                tracing::error!("Error creating record segment reader {}", e);
+                Err(MetadataFilteringError::MetadataFilteringRecordSegmentReaderCreationError(e))
+            }
+        },
+    }
+}
+
+// Compares the metadata value of a record with the operand of a where
+// comparison, once per element of a list value. Values of a different type
+// are not comparable.
+fn compare_metadata_value(value: &MetadataValue, operand: &KeyWrapper) -> Vec<Option<Ordering>> {
+    value
+        .elements()
+        .iter()
+        .map(|element| compare_scalar_metadata_value(element, operand))
+        .collect()
+}
+
+fn compare_scalar_metadata_value(value: &MetadataValue, operand: &KeyWrapper) -> Option<Ordering> {
+    match (value, operand) {
+        (MetadataValue::Str(value), KeyWrapper::String(operand)) => Some(value.cmp(operand)),
+        (MetadataValue::Int(value), KeyWrapper::Int64(operand)) => Some(value.cmp(operand)),
+        (MetadataValue::Float(value), KeyWrapper::Float32(operand)) => {
+            (*value as f32).partial_cmp(operand)
+        }
+        (MetadataValue::Bool(value), KeyWrapper::Bool(operand)) => Some(value.cmp(operand)),
+        _ => None,
+    }
+}
+
 #[async_trait]
 impl Operator<MetadataFilteringInput, MetadataFilteringOutput> for MetadataFilteringOperator {
     type Error = MetadataFilteringError;
@@ -123,35 +163,7 @@ impl Operator<MetadataFilteringInput, MetadataFilteringOutput> for MetadataFilte
         input: &MetadataFilteringInput,
     ) -> Result<MetadataFilteringOutput, MetadataFilteringError> {
         // Step 0: Create the record segment reader.
-        let record_segment_reader: Option<RecordSegmentReader>;
-        match RecordSegmentReader::from_segment(&input.record_segment, &input.blockfile_provider)
-            .await
-        {
-            Ok(reader) => {
-                record_segment_reader = Some(reader);
-            }
-            Err(e) => {
-                match *e {
-                    // Uninitialized segment is fine and means that the record
-                    // segment is not yet initialized in storage.
-                    RecordSegmentReaderCreationError::UninitializedSegment => {
-                        record_segment_reader = None;
-                    }
-                    RecordSegmentReaderCreationError::BlockfileOpenError(e) => {
-                        tracing::error!("Error creating record segment reader {}", e);
-                        return Err(MetadataFilteringError::MetadataFilteringRecordSegmentReaderCreationError(
-                            RecordSegmentReaderCreationError::BlockfileOpenError(e),
-                        ));
-                    }
-                    RecordSegmentReaderCreationError::InvalidNumberOfFiles => {
-                        tracing::error!("Error creating record segment reader {}", e);
-                        return Err(MetadataFilteringError::MetadataFilteringRecordSegmentReaderCreationError(
-                            RecordSegmentReaderCreationError::InvalidNumberOfFiles,
-                        ));
-                    }
-                };
-            }
-        };
+        let record_segment_reader = open_record_segment_reader(input).await?;
         // Step 1: Materialize the logs.
         let materializer =
             LogMaterializer::new(record_segment_reader, input.log_record.clone(), None);
@@ -176,366 +188,98 @@ impl Operator<MetadataFilteringInput, MetadataFilteringOutput> for MetadataFilte
         }
         let clo = |metadata_key: &str,
                    metadata_value: &crate::blockstore::key::KeyWrapper,
-                   metadata_type: crate::types::MetadataType,
+                   _metadata_type: crate::types::MetadataType,
                    comparator: WhereClauseComparator| {
-            match metadata_type {
-                crate::types::MetadataType::StringType => match comparator {
-                    WhereClauseComparator::Equal => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key equal to this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Str(string_value) => {
-                                        if let KeyWrapper::String(where_value) = metadata_value {
-                                            if *string_value == *where_value {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::NotEqual => {
-                        todo!();
-                    }
-                    // We don't allow these comparators for strings.
-                    WhereClauseComparator::LessThan => {
-                        unimplemented!();
-                    }
-                    WhereClauseComparator::LessThanOrEqual => {
-                        unimplemented!();
-                    }
-                    WhereClauseComparator::GreaterThan => {
-                        unimplemented!();
-                    }
-                    WhereClauseComparator::GreaterThanOrEqual => {
-                        unimplemented!();
-                    }
-                },
-                crate::types::MetadataType::BoolType => match comparator {
-                    WhereClauseComparator::Equal => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key equal to this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Bool(bool_value) => {
-                                        if let KeyWrapper::Bool(where_value) = metadata_value {
-                                            if *bool_value == *where_value {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::NotEqual => {
-                        todo!();
-                    }
-                    // We don't allow these comparators for booleans.
-                    WhereClauseComparator::LessThan => {
-                        unimplemented!();
-                    }
-                    WhereClauseComparator::LessThanOrEqual => {
-                        unimplemented!();
-                    }
-                    WhereClauseComparator::GreaterThan => {
-                        unimplemented!();
-                    }
-                    WhereClauseComparator::GreaterThanOrEqual => {
-                        unimplemented!();
-                    }
-                },
-                crate::types::MetadataType::IntType => match comparator {
-                    WhereClauseComparator::Equal => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key equal to this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Int(int_value) => {
-                                        if let KeyWrapper::Uint32(where_value) = metadata_value {
-                                            if *int_value as u32 == *where_value {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::NotEqual => {
-                        todo!();
-                    }
-                    WhereClauseComparator::LessThan => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key less than this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Int(int_value) => {
-                                        if let KeyWrapper::Uint32(where_value) = metadata_value {
-                                            if ((*int_value) as u32) < (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::LessThanOrEqual => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key <= this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Int(int_value) => {
-                                        if let KeyWrapper::Uint32(where_value) = metadata_value {
-                                            if ((*int_value) as u32) <= (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::GreaterThan => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key > this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Int(int_value) => {
-                                        if let KeyWrapper::Uint32(where_value) = metadata_value {
-                                            if ((*int_value) as u32) > (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::GreaterThanOrEqual => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key >= this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Int(int_value) => {
-                                        if let KeyWrapper::Uint32(where_value) = metadata_value {
-                                            if ((*int_value) as u32) >= (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                },
-                crate::types::MetadataType::DoubleType => match comparator {
-                    WhereClauseComparator::Equal => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key equal to this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Float(float_value) => {
-                                        if let KeyWrapper::Float32(where_value) = metadata_value {
-                                            if ((*float_value) as f32) == (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::NotEqual => {
-                        todo!();
-                    }
-                    WhereClauseComparator::LessThan => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key < this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Float(float_value) => {
-                                        if let KeyWrapper::Float32(where_value) = metadata_value {
-                                            if ((*float_value) as f32) < (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::LessThanOrEqual => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key <= this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Float(float_value) => {
-                                        if let KeyWrapper::Float32(where_value) = metadata_value {
-                                            if ((*float_value) as f32) <= (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
+            let mut result = RoaringBitmap::new();
+            // Construct a bitmap consisting of all offset ids whose value for
+            // this key satisfies the comparator. A list value satisfies it when
+            // one of its elements does, and every record in the log without an
+            // equal element satisfies a negation.
+            for (offset_id, meta_map) in &ids_to_metadata {
+                let orderings = meta_map
+                    .get(metadata_key)
+                    .map(|val| compare_metadata_value(val, metadata_value))
+                    .unwrap_or_default();
+                let any = |f: fn(Ordering) -> bool| orderings.iter().flatten().any(|o| f(*o));
+                let satisfied = match comparator {
+                    WhereClauseComparator::Equal | WhereClauseComparator::Contains => {
+                        any(Ordering::is_eq)
                     }
-                    WhereClauseComparator::GreaterThan => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key > this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Float(float_value) => {
-                                        if let KeyWrapper::Float32(where_value) = metadata_value {
-                                            if ((*float_value) as f32) > (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                    WhereClauseComparator::GreaterThanOrEqual => {
-                        let mut result = RoaringBitmap::new();
-                        // Construct a bitmap consisting of all offset ids
-                        // that have this key >= this value.
-                        for (offset_id, meta_map) in &ids_to_metadata {
-                            if let Some(val) = meta_map.get(metadata_key) {
-                                match *val {
-                                    MetadataValue::Float(float_value) => {
-                                        if let KeyWrapper::Float32(where_value) = metadata_value {
-                                            if ((*float_value) as f32) >= (*where_value) {
-                                                result.insert(*offset_id);
-                                            }
-                                        }
-                                    }
-                                    _ => (),
-                                }
-                            }
-                        }
-                        return result;
-                    }
-                },
-                crate::types::MetadataType::StringListType => {
-                    todo!();
-                }
-                crate::types::MetadataType::IntListType => {
-                    todo!();
-                }
-                crate::types::MetadataType::DoubleListType => {
-                    todo!();
-                }
-                crate::types::MetadataType::BoolListType => {
-                    todo!();
+                    WhereClauseComparator::NotEqual => !any(Ordering::is_eq),
+                    WhereClauseComparator::LessThan => any(Ordering::is_lt),
+                    WhereClauseComparator::LessThanOrEqual => any(Ordering::is_le),
+                    WhereClauseComparator::GreaterThan => any(Ordering::is_gt),
+                    WhereClauseComparator::GreaterThanOrEqual => any(Ordering::is_ge),
+                };
+                if satisfied {
+                    result.insert(*offset_id);
                 }
             }
+            result
+        };
+        let exists_clo = |metadata_key: &str, exists: bool| {
+            ids_to_metadata
+                .iter()
+                .filter(|(_, meta_map)| meta_map.contains_key(metadata_key) == exists)
+                .map(|(offset_id, _)| *offset_id)
+                .collect::<RoaringBitmap>()
         };
         // This will be sorted by offset ids since rbms.insert() insert in sorted order.
         let mtsearch_res = match &input.where_clause {
-            Some(where_clause) => match process_where_clause_with_callback(where_clause, &clo) {
-                Ok(r) => {
-                    let ids_as_u32: Vec<u32> = r.into_iter().map(|index| index as u32).collect();
-                    tracing::info!(
-                        "Filtered {} results from log based on where clause filtering",
-                        ids_as_u32.len()
-                    );
-                    Some(ids_as_u32)
-                }
-                Err(e) => {
-                    tracing::error!("Error filtering logs based on where clause {:?}", e);
-                    return Err(MetadataFilteringError::MetadataFilteringIndexError(e));
+            Some(where_clause) => {
+                match process_where_clause_with_callback(where_clause, &clo, &exists_clo) {
+                    Ok(r) => {
+                        let ids_as_u32: Vec<u32> =
+                            r.into_iter().map(|index| index as u32).collect();
+                        tracing::info!(
+                            "Filtered {} results from log based on where clause filtering",
+                            ids_as_u32.len()
+                        );
+                        Some(ids_as_u32)
+                    }
+                    Err(e) => {
+                        tracing::error!("Error filtering logs based on where clause {:?}", e);
+                        return Err(MetadataFilteringError::MetadataFilteringIndexError(e));
+                    }
                 }
-            },
+            }
             None => {
                 tracing::info!("Where clause not supplied by the user");
                 None
             }
         };
         // AND this with where_document clause.
-        let cb = |query: &str, op: WhereDocumentOperator| {
-            match op {
-                WhereDocumentOperator::Contains => {
-                    // Note matching_contains is sorted (which is needed for correctness)
-                    // because materialized log record is sorted by offset id.
-                    let mut matching_contains = vec![];
-                    // Upstream sorts materialized records by offset id so matching_contains
-                    // will be sorted.
-                    // Emulate sqlite behavior. _ and % match to any character in sqlite.
-                    let normalized_query = query.replace("_", ".").replace("%", ".");
-                    let re = Regex::new(normalized_query.as_str()).unwrap();
-                    for (record, _) in mat_records.iter() {
-                        if record.final_operation == Operation::Delete {
-                            continue;
-                        }
-                        match record.merged_document_ref() {
-                            Some(doc) => {
-                                if re.is_match(doc) {
-                                    matching_contains.push(record.offset_id as i32);
-                                }
-                            }
-                            None => {}
-                        };
-                    }
-                    return matching_contains;
+        let cb = |pattern: &DocumentPattern| {
+            // Note matching_documents is sorted (which is needed for correctness)
+            // because materialized log record is sorted by offset id.
+            let mut matching_documents = vec![];
+            for (record, _) in mat_records.iter() {
+                if record.final_operation == Operation::Delete {
+                    continue;
                 }
-                WhereDocumentOperator::NotContains => {
-                    todo!()
+                if let Some(doc) = record.merged_document_ref() {
+                    if pattern.is_match(doc) {
+                        matching_documents.push(record.offset_id as i32);
+                    }
                 }
             }
+            matching_documents
+        };
+        let universe_cb = || {
+            mat_records
+                .iter()
+                .filter(|(record, _)| record.final_operation != Operation::Delete)
+                .map(|(record, _)| record.offset_id as i32)
+                .collect::<Vec<_>>()
         };
         // fts_result will be sorted by offset id.
         let fts_result = match &input.where_document_clause {
             Some(where_doc_clause) => {
-                match process_where_document_clause_with_callback(where_doc_clause, &cb) {
+                match process_where_document_clause_with_callback(
+                    where_doc_clause,
+                    &cb,
+                    &universe_cb,
+                ) {
                     Ok(res) => {
                         let ids_as_u32: Vec<u32> =
                             res.into_iter().map(|index| index as u32).collect();
@@ -567,7 +311,9 @@ impl Operator<MetadataFilteringInput, MetadataFilteringOutput> for MetadataFilte
                 &fts_result.expect("Already validated that it is not none"),
             ));
         }
-        // Get offset ids that satisfy where conditions from storage.
+        // Get offset ids that satisfy where conditions from storage. The record
+        // segment provides the universe of offset ids for negations.
+        let record_segment_reader_2 = open_record_segment_reader(input).await?;
         let metadata_segment_reader =
             MetadataSegmentReader::from_segment(&input.metadata_segment, &input.blockfile_provider)
                 .await;
@@ -578,9 +324,7 @@ impl Operator<MetadataFilteringInput, MetadataFilteringOutput> for MetadataFilte
                     .query(
                         input.where_clause.as_ref(),
                         input.where_document_clause.as_ref(),
-                        Some(&vec![]),
-                        0,
-                        0,
+                        record_segment_reader_2.as_ref(),
                     )
                     .await
             }
@@ -653,38 +397,6 @@ impl Operator<MetadataFilteringInput, MetadataFilteringOutput> for MetadataFilte
                     user_supplied_offset_ids.len(),
                     remaining_id_set.len()
                 );
-                let record_segment_reader_2: Option<RecordSegmentReader>;
-                match RecordSegmentReader::from_segment(
-                    &input.record_segment,
-                    &input.blockfile_provider,
-                )
-                .await
-                {
-                    Ok(reader) => {
-                        record_segment_reader_2 = Some(reader);
-                    }
-                    Err(e) => {
-                        match *e {
-                            // Uninitialized segment is fine and means that the record
-                            // segment is not yet initialized in storage.
-                            RecordSegmentReaderCreationError::UninitializedSegment => {
-                                record_segment_reader_2 = None;
-                            }
-                            RecordSegmentReaderCreationError::BlockfileOpenError(e) => {
-                                tracing::error!("Error creating record segment reader {}", e);
-                                return Err(MetadataFilteringError::MetadataFilteringRecordSegmentReaderCreationError(
-                            RecordSegmentReaderCreationError::BlockfileOpenError(e),
-                        ));
-                            }
-                            RecordSegmentReaderCreationError::InvalidNumberOfFiles => {
-                                tracing::error!("Error creating record segment reader {}", e);
-                                return Err(MetadataFilteringError::MetadataFilteringRecordSegmentReaderCreationError(
-                            RecordSegmentReaderCreationError::InvalidNumberOfFiles,
-                        ));
-                            }
-                        };
-                    }
-                };
                 match &record_segment_reader_2 {
                     Some(r) => {
                         // Now read the remaining ids from storage.
@@ -736,6 +448,7 @@ mod test {
 
     use crate::{
         blockstore::{arrow::provider::ArrowBlockfileProvider, provider::BlockfileProvider},
+        errors::{ChromaError, ErrorCodes},
         execution::{
             data::data_chunk::Chunk,
             operator::Operator,
@@ -751,8 +464,10 @@ mod test {
         },
         storage::{local::LocalStorage, Storage},
         types::{
-            DirectComparison, DirectDocumentComparison, LogRecord, Operation, OperationRecord,
-            UpdateMetadataValue, Where, WhereComparison, WhereDocument,
+            BooleanOperator, DirectComparison, DirectDocumentComparison, LogRecord, Operation,
+            OperationRecord, UpdateMetadataValue, Where, WhereClauseComparator,
+            WhereClauseListOperator, WhereComparison, WhereDocument, WhereDocumentChildren,
+            WhereDocumentOperator,
         },
     };
 
@@ -808,6 +523,7 @@ mod test {
                         id: "embedding_id_1".to_string(),
                         embedding: Some(vec![1.0, 2.0, 3.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata.clone()),
                         document: Some(String::from("This is a document about cats.")),
                         operation: Operation::Add,
@@ -819,6 +535,7 @@ mod test {
                         id: "embedding_id_2".to_string(),
                         embedding: Some(vec![4.0, 5.0, 6.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata),
                         document: Some(String::from("This is a document about dogs.")),
                         operation: Operation::Add,
@@ -895,6 +612,7 @@ mod test {
                     id: "embedding_id_1".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata.clone()),
                     document: None,
                     operation: Operation::Update,
@@ -906,6 +624,7 @@ mod test {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata),
                     document: Some(String::from("This is a document about dogs.")),
                     operation: Operation::Add,
@@ -917,6 +636,7 @@ mod test {
                     id: "embedding_id_2".to_string(),
                     embedding: Some(vec![10.0, 11.0, 12.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Update,
@@ -1020,6 +740,7 @@ mod test {
                         id: "embedding_id_1".to_string(),
                         embedding: Some(vec![1.0, 2.0, 3.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata.clone()),
                         document: Some(String::from("This is a document about cats.")),
                         operation: Operation::Add,
@@ -1031,6 +752,7 @@ mod test {
                         id: "embedding_id_2".to_string(),
                         embedding: Some(vec![4.0, 5.0, 6.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata),
                         document: Some(String::from("This is a document about dogs.")),
                         operation: Operation::Add,
@@ -1106,6 +828,7 @@ mod test {
                 id: "embedding_id_3".to_string(),
                 embedding: Some(vec![7.0, 8.0, 9.0]),
                 encoding: None,
+                sparse_vector: None,
                 metadata: Some(update_metadata),
                 document: Some(String::from("This is a document about dogs.")),
                 operation: Operation::Add,
@@ -1151,6 +874,847 @@ mod test {
         assert_eq!(2, *where_res.get(1).expect("Expected not none value"));
     }
 
+    #[tokio::test]
+    async fn negations_and_string_ranges() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileRecord,
+            scope: crate::types::SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileMetadata,
+            scope: crate::types::SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let log_record = |log_offset: i64, id: &str, color: Option<&str>, operation| LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding: Some(vec![1.0, 2.0, 3.0]),
+                encoding: None,
+                sparse_vector: None,
+                metadata: color.map(|color| {
+                    HashMap::from([(
+                        String::from("color"),
+                        UpdateMetadataValue::Str(color.to_string()),
+                    )])
+                }),
+                document: None,
+                operation,
+            },
+        };
+        {
+            let segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            // The third record has no metadata at all.
+            let data = vec![
+                log_record(1, "embedding_id_1", Some("blue"), Operation::Add),
+                log_record(2, "embedding_id_2", Some("green"), Operation::Add),
+                log_record(3, "embedding_id_3", None, Operation::Add),
+            ];
+            let materializer = LogMaterializer::new(None, Chunk::new(data.into()), None);
+            let mat_records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .expect("Apply materialized log to metadata segment failed");
+            metadata_writer
+                .write_to_blockfiles()
+                .await
+                .expect("Write to blockfiles for metadata writer failed");
+            segment_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .expect("Apply materialized log to record segment failed");
+            record_segment.file_path = segment_writer
+                .commit()
+                .expect("Commit for segment writer failed")
+                .flush()
+                .await
+                .expect("Flush record segment writer failed");
+            metadata_segment.file_path = metadata_writer
+                .commit()
+                .expect("Commit for metadata writer failed")
+                .flush()
+                .await
+                .expect("Flush metadata segment writer failed");
+        }
+        // The log is the source of truth for the second record.
+        let data: Chunk<LogRecord> = Chunk::new(
+            vec![
+                log_record(4, "embedding_id_4", Some("red"), Operation::Add),
+                log_record(5, "embedding_id_2", Some("amber"), Operation::Update),
+            ]
+            .into(),
+        );
+        let operator = MetadataFilteringOperator::new();
+        let input = |comparison| {
+            MetadataFilteringInput::new(
+                data.clone(),
+                record_segment.clone(),
+                metadata_segment.clone(),
+                blockfile_provider.clone(),
+                Some(Where::DirectWhereComparison(DirectComparison {
+                    key: String::from("color"),
+                    comparison,
+                })),
+                None,
+                None,
+            )
+        };
+        let cases = vec![
+            (
+                WhereComparison::SingleStringComparison(
+                    String::from("blue"),
+                    WhereClauseComparator::NotEqual,
+                ),
+                vec![2, 3, 4],
+            ),
+            (
+                WhereComparison::StringListComparison(
+                    vec![String::from("blue"), String::from("red")],
+                    WhereClauseListOperator::NotIn,
+                ),
+                vec![2, 3],
+            ),
+            (
+                WhereComparison::StringListComparison(
+                    vec![String::from("blue"), String::from("red")],
+                    WhereClauseListOperator::In,
+                ),
+                vec![1, 4],
+            ),
+            (
+                WhereComparison::SingleStringComparison(
+                    String::from("blue"),
+                    WhereClauseComparator::GreaterThanOrEqual,
+                ),
+                vec![1, 4],
+            ),
+            (
+                WhereComparison::SingleStringComparison(
+                    String::from("c"),
+                    WhereClauseComparator::LessThan,
+                ),
+                vec![1, 2],
+            ),
+        ];
+        for (comparison, expected) in cases {
+            let res = operator
+                .run(&input(comparison))
+                .await
+                .expect("Error during running of operator");
+            assert_eq!(Some(expected), res.where_condition_filtered_offset_ids);
+        }
+
+        let unsupported = vec![
+            WhereComparison::SingleBoolComparison(true, WhereClauseComparator::GreaterThan),
+            WhereComparison::StringListComparison(vec![], WhereClauseListOperator::NotIn),
+            WhereComparison::SingleDoubleComparison(f64::NAN, WhereClauseComparator::Equal),
+        ];
+        for comparison in unsupported {
+            let err = operator
+                .run(&input(comparison))
+                .await
+                .expect_err("Unsupported where clause should fail");
+            assert_eq!(ErrorCodes::InvalidArgument, err.code());
+        }
+    }
+
+    #[tokio::test]
+    async fn signed_64_bit_ints() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileRecord,
+            scope: crate::types::SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileMetadata,
+            scope: crate::types::SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let log_record = |log_offset: i64, id: &str, size: i64, operation| LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding: Some(vec![1.0, 2.0, 3.0]),
+                encoding: None,
+                sparse_vector: None,
+                metadata: Some(HashMap::from([(
+                    String::from("size"),
+                    UpdateMetadataValue::Int(size),
+                )])),
+                document: None,
+                operation,
+            },
+        };
+        {
+            let segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let data = vec![
+                log_record(1, "embedding_id_1", -5, Operation::Add),
+                log_record(2, "embedding_id_2", 3_000_000_000, Operation::Add),
+                log_record(3, "embedding_id_3", 0, Operation::Add),
+            ];
+            let materializer = LogMaterializer::new(None, Chunk::new(data.into()), None);
+            let mat_records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .expect("Apply materialized log to metadata segment failed");
+            metadata_writer
+                .write_to_blockfiles()
+                .await
+                .expect("Write to blockfiles for metadata writer failed");
+            segment_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .expect("Apply materialized log to record segment failed");
+            record_segment.file_path = segment_writer
+                .commit()
+                .expect("Commit for segment writer failed")
+                .flush()
+                .await
+                .expect("Flush record segment writer failed");
+            metadata_segment.file_path = metadata_writer
+                .commit()
+                .expect("Commit for metadata writer failed")
+                .flush()
+                .await
+                .expect("Flush metadata segment writer failed");
+        }
+        let data: Chunk<LogRecord> = Chunk::new(
+            vec![
+                log_record(4, "embedding_id_4", i64::MIN, Operation::Add),
+                log_record(5, "embedding_id_3", 1 << 40, Operation::Update),
+            ]
+            .into(),
+        );
+        let operator = MetadataFilteringOperator::new();
+        let input = |comparison| {
+            MetadataFilteringInput::new(
+                data.clone(),
+                record_segment.clone(),
+                metadata_segment.clone(),
+                blockfile_provider.clone(),
+                Some(Where::DirectWhereComparison(DirectComparison {
+                    key: String::from("size"),
+                    comparison,
+                })),
+                None,
+                None,
+            )
+        };
+        let cases = vec![
+            (
+                WhereComparison::SingleIntComparison(0, WhereClauseComparator::LessThan),
+                vec![1, 4],
+            ),
+            (
+                WhereComparison::SingleIntComparison(
+                    i32::MAX as i64,
+                    WhereClauseComparator::GreaterThan,
+                ),
+                vec![2, 3],
+            ),
+            (
+                WhereComparison::SingleIntComparison(3_000_000_000, WhereClauseComparator::Equal),
+                vec![2],
+            ),
+            (
+                WhereComparison::SingleIntComparison(-5, WhereClauseComparator::GreaterThanOrEqual),
+                vec![1, 2, 3],
+            ),
+            (
+                WhereComparison::IntListComparison(vec![-5, 1 << 40], WhereClauseListOperator::In),
+                vec![1, 3],
+            ),
+        ];
+        for (comparison, expected) in cases {
+            let res = operator
+                .run(&input(comparison))
+                .await
+                .expect("Error during running of operator");
+            assert_eq!(Some(expected), res.where_condition_filtered_offset_ids);
+        }
+    }
+
+    #[tokio::test]
+    async fn list_metadata_contains() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileRecord,
+            scope: crate::types::SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileMetadata,
+            scope: crate::types::SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let tags = |tags: &[&str]| {
+            UpdateMetadataValue::StrList(tags.iter().map(|tag| tag.to_string()).collect())
+        };
+        let log_record =
+            |log_offset: i64, id: &str, tags: UpdateMetadataValue, operation| LogRecord {
+                log_offset,
+                record: OperationRecord {
+                    id: id.to_string(),
+                    embedding: Some(vec![1.0, 2.0, 3.0]),
+                    encoding: None,
+                    sparse_vector: None,
+                    metadata: Some(HashMap::from([(String::from("tags"), tags)])),
+                    document: Some(String::from("This is a document about tags.")),
+                    operation,
+                },
+            };
+        // The second compaction exercises the update and delete diffs.
+        let compactions = vec![
+            vec![
+                log_record(1, "embedding_id_1", tags(&["a", "b"]), Operation::Add),
+                log_record(2, "embedding_id_2", tags(&["b"]), Operation::Add),
+                log_record(
+                    3,
+                    "embedding_id_3",
+                    UpdateMetadataValue::Str("b".to_string()),
+                    Operation::Add,
+                ),
+            ],
+            vec![
+                log_record(4, "embedding_id_1", tags(&["b", "c"]), Operation::Update),
+                log_record(5, "embedding_id_2", tags(&[]), Operation::Delete),
+                log_record(6, "embedding_id_3", tags(&["a"]), Operation::Update),
+            ],
+        ];
+        for data in compactions {
+            let record_segment_reader =
+                match RecordSegmentReader::from_segment(&record_segment, &blockfile_provider).await
+                {
+                    Ok(reader) => Some(reader),
+                    Err(_) => None,
+                };
+            let segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let materializer =
+                LogMaterializer::new(record_segment_reader, Chunk::new(data.into()), None);
+            let mat_records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .expect("Apply materialized log to metadata segment failed");
+            metadata_writer
+                .write_to_blockfiles()
+                .await
+                .expect("Write to blockfiles for metadata writer failed");
+            segment_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .expect("Apply materialized log to record segment failed");
+            record_segment.file_path = segment_writer
+                .commit()
+                .expect("Commit for segment writer failed")
+                .flush()
+                .await
+                .expect("Flush record segment writer failed");
+            metadata_segment.file_path = metadata_writer
+                .commit()
+                .expect("Commit for metadata writer failed")
+                .flush()
+                .await
+                .expect("Flush metadata segment writer failed");
+        }
+        let data: Chunk<LogRecord> = Chunk::new(
+            vec![log_record(
+                7,
+                "embedding_id_4",
+                tags(&["a", "c"]),
+                Operation::Add,
+            )]
+            .into(),
+        );
+        let operator = MetadataFilteringOperator::new();
+        let input = |comparison| {
+            MetadataFilteringInput::new(
+                data.clone(),
+                record_segment.clone(),
+                metadata_segment.clone(),
+                blockfile_provider.clone(),
+                Some(Where::DirectWhereComparison(DirectComparison {
+                    key: String::from("tags"),
+                    comparison,
+                })),
+                None,
+                None,
+            )
+        };
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        let cases = vec![
+            (
+                WhereComparison::SingleStringComparison(
+                    String::from("a"),
+                    WhereClauseComparator::Contains,
+                ),
+                vec![3, 4],
+            ),
+            (
+                WhereComparison::SingleStringComparison(
+                    String::from("b"),
+                    WhereClauseComparator::NotEqual,
+                ),
+                vec![3, 4],
+            ),
+            (
+                WhereComparison::StringListComparison(
+                    strings(&["b", "x"]),
+                    WhereClauseListOperator::ContainsAny,
+                ),
+                vec![1],
+            ),
+            (
+                WhereComparison::StringListComparison(
+                    strings(&["a", "c"]),
+                    WhereClauseListOperator::ContainsAll,
+                ),
+                vec![4],
+            ),
+            (
+                WhereComparison::StringListComparison(strings(&["c"]), WhereClauseListOperator::In),
+                vec![1, 4],
+            ),
+        ];
+        for (comparison, expected) in cases {
+            let res = operator
+                .run(&input(comparison))
+                .await
+                .expect("Error during running of operator");
+            assert_eq!(Some(expected), res.where_condition_filtered_offset_ids);
+        }
+    }
+
+    #[tokio::test]
+    async fn exists_in_log_and_segment() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileRecord,
+            scope: crate::types::SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileMetadata,
+            scope: crate::types::SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let log_record =
+            |log_offset: i64, id: &str, author: Option<UpdateMetadataValue>, operation| LogRecord {
+                log_offset,
+                record: OperationRecord {
+                    id: id.to_string(),
+                    embedding: Some(vec![1.0, 2.0, 3.0]),
+                    encoding: None,
+                    sparse_vector: None,
+                    metadata: author
+                        .map(|author| HashMap::from([(String::from("author"), author)])),
+                    document: Some(String::from("This is a document.")),
+                    operation,
+                },
+            };
+        let data: Chunk<LogRecord> = Chunk::new(
+            vec![
+                log_record(
+                    1,
+                    "embedding_id_1",
+                    Some(UpdateMetadataValue::Str(String::from("ann"))),
+                    Operation::Add,
+                ),
+                log_record(2, "embedding_id_2", None, Operation::Add),
+            ]
+            .into(),
+        );
+        {
+            let segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let materializer = LogMaterializer::new(None, data, None);
+            let mat_records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .expect("Apply materialized log to metadata segment failed");
+            metadata_writer
+                .write_to_blockfiles()
+                .await
+                .expect("Write to blockfiles for metadata writer failed");
+            segment_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .expect("Apply materialized log to record segment failed");
+            record_segment.file_path = segment_writer
+                .commit()
+                .expect("Commit for segment writer failed")
+                .flush()
+                .await
+                .expect("Flush record segment writer failed");
+            metadata_segment.file_path = metadata_writer
+                .commit()
+                .expect("Commit for metadata writer failed")
+                .flush()
+                .await
+                .expect("Flush metadata segment writer failed");
+        }
+        // The log removes the author of the compacted record and adds a
+        // record with an author and one without.
+        let data: Chunk<LogRecord> = Chunk::new(
+            vec![
+                log_record(
+                    3,
+                    "embedding_id_1",
+                    Some(UpdateMetadataValue::None),
+                    Operation::Update,
+                ),
+                log_record(
+                    4,
+                    "embedding_id_3",
+                    Some(UpdateMetadataValue::Bool(true)),
+                    Operation::Add,
+                ),
+                log_record(5, "embedding_id_4", None, Operation::Add),
+            ]
+            .into(),
+        );
+        let operator = MetadataFilteringOperator::new();
+        for (exists, expected) in [(true, vec![3]), (false, vec![1, 2, 4])] {
+            let input = MetadataFilteringInput::new(
+                data.clone(),
+                record_segment.clone(),
+                metadata_segment.clone(),
+                blockfile_provider.clone(),
+                Some(Where::DirectWhereComparison(DirectComparison {
+                    key: String::from("author"),
+                    comparison: WhereComparison::Exists(exists),
+                })),
+                None,
+                None,
+            );
+            let res = operator
+                .run(&input)
+                .await
+                .expect("Error during running of operator");
+            assert_eq!(Some(expected), res.where_condition_filtered_offset_ids);
+        }
+    }
+
+    #[tokio::test]
+    async fn where_document_patterns() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileRecord,
+            scope: crate::types::SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = crate::types::Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileMetadata,
+            scope: crate::types::SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let log_record = |log_offset: i64, id: &str, document: &str| LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding: Some(vec![1.0, 2.0, 3.0]),
+                encoding: None,
+                sparse_vector: None,
+                metadata: None,
+                document: Some(document.to_string()),
+                operation: Operation::Add,
+            },
+        };
+        {
+            let segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating segment writer");
+            let data = vec![
+                log_record(1, "embedding_id_1", "Version 1.5 (beta) is out"),
+                log_record(2, "embedding_id_2", "héllo wörld, the final release"),
+                log_record(3, "embedding_id_3", "plain text"),
+            ];
+            let materializer = LogMaterializer::new(None, Chunk::new(data.into()), None);
+            let mat_records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .expect("Apply materialized log to metadata segment failed");
+            metadata_writer
+                .write_to_blockfiles()
+                .await
+                .expect("Write to blockfiles for metadata writer failed");
+            segment_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .expect("Apply materialized log to record segment failed");
+            record_segment.file_path = segment_writer
+                .commit()
+                .expect("Commit for segment writer failed")
+                .flush()
+                .await
+                .expect("Flush record segment writer failed");
+            metadata_segment.file_path = metadata_writer
+                .commit()
+                .expect("Commit for metadata writer failed")
+                .flush()
+                .await
+                .expect("Flush metadata segment writer failed");
+        }
+        let data: Chunk<LogRecord> =
+            Chunk::new(vec![log_record(4, "embedding_id_4", "Version 2 [stable] (beta)")].into());
+        let operator = MetadataFilteringOperator::new();
+        let input = |where_document_clause| {
+            MetadataFilteringInput::new(
+                data.clone(),
+                record_segment.clone(),
+                metadata_segment.clone(),
+                blockfile_provider.clone(),
+                None,
+                Some(where_document_clause),
+                None,
+            )
+        };
+        let direct = |document: &str, operator| {
+            WhereDocument::DirectWhereDocumentComparison(DirectDocumentComparison {
+                document: document.to_string(),
+                operator,
+            })
+        };
+        let cases = vec![
+            (
+                direct("(beta)", WhereDocumentOperator::Contains),
+                vec![1, 4],
+            ),
+            (direct("[stable]", WhereDocumentOperator::Contains), vec![4]),
+            (
+                direct("(beta)", WhereDocumentOperator::NotContains),
+                vec![2, 3],
+            ),
+            (
+                direct("plain", WhereDocumentOperator::NotContains),
+                vec![1, 2, 4],
+            ),
+            (direct("1_5", WhereDocumentOperator::Contains), vec![]),
+            (direct("wörld", WhereDocumentOperator::Contains), vec![2]),
+            (direct("ö", WhereDocumentOperator::Contains), vec![2]),
+            (
+                direct(r"Version \d+(\.\d+)? ", WhereDocumentOperator::Regex),
+                vec![1, 4],
+            ),
+            (direct("^pl", WhereDocumentOperator::Regex), vec![3]),
+            (direct("%release", WhereDocumentOperator::Like), vec![2]),
+            (direct("Version _._%", WhereDocumentOperator::Like), vec![1]),
+            (direct("plain", WhereDocumentOperator::Like), vec![]),
+            (
+                WhereDocument::WhereDocumentChildren(WhereDocumentChildren {
+                    children: vec![
+                        direct("Version", WhereDocumentOperator::Contains),
+                        direct("stable|out", WhereDocumentOperator::Regex),
+                    ],
+                    operator: BooleanOperator::And,
+                }),
+                vec![1, 4],
+            ),
+            (direct("beta", WhereDocumentOperator::Search), vec![1, 4]),
+            // "1.5" is between the words in the segment, "2 [stable]" in the
+            // log.
+            (
+                direct("\"Version beta\"~2", WhereDocumentOperator::Search),
+                vec![1, 4],
+            ),
+            (
+                direct("\"Version beta\"~1", WhereDocumentOperator::Search),
+                vec![1],
+            ),
+            (
+                direct("\"Version beta\"~0", WhereDocumentOperator::Search),
+                vec![],
+            ),
+            (
+                direct("\"wörld, the\"", WhereDocumentOperator::Search),
+                vec![2],
+            ),
+            (
+                direct("\"wörld the\"", WhereDocumentOperator::Search),
+                vec![],
+            ),
+            (
+                direct("\"beta)\" NOT final", WhereDocumentOperator::Search),
+                vec![1, 4],
+            ),
+            // Terms are whole words unless they end with *.
+            (direct("Vers", WhereDocumentOperator::Search), vec![]),
+            (direct("eta", WhereDocumentOperator::Search), vec![]),
+            (direct("rel", WhereDocumentOperator::Search), vec![]),
+            (direct("rel*", WhereDocumentOperator::Search), vec![2]),
+            (
+                direct("\"Version be\"~1", WhereDocumentOperator::Search),
+                vec![],
+            ),
+            (
+                direct("NOT 1", WhereDocumentOperator::Search),
+                vec![2, 3, 4],
+            ),
+            (
+                direct("rel* OR plain", WhereDocumentOperator::Search),
+                vec![2, 3],
+            ),
+            (
+                direct("NOT Version", WhereDocumentOperator::Search),
+                vec![2, 3],
+            ),
+            (
+                direct("Vers* NOT stable", WhereDocumentOperator::Search),
+                vec![1],
+            ),
+            (
+                direct("Versiom", WhereDocumentOperator::Fuzzy(1)),
+                vec![1, 4],
+            ),
+            (
+                direct("finel release", WhereDocumentOperator::Fuzzy(1)),
+                vec![2],
+            ),
+            (
+                direct("finel relaese", WhereDocumentOperator::Fuzzy(1)),
+                vec![],
+            ),
+            (
+                direct("hello world", WhereDocumentOperator::Fuzzy(1)),
+                vec![],
+            ),
+            (
+                direct("hello world", WhereDocumentOperator::Fuzzy(2)),
+                vec![2],
+            ),
+            (
+                direct("plain text", WhereDocumentOperator::Fuzzy(0)),
+                vec![3],
+            ),
+            (direct("[stabl]", WhereDocumentOperator::Fuzzy(1)), vec![4]),
+            // Two edits can change every n-gram of these queries, so every
+            // document is checked.
+            (
+                direct("Versoin", WhereDocumentOperator::Fuzzy(2)),
+                vec![1, 4],
+            ),
+            (direct("ta", WhereDocumentOperator::Fuzzy(0)), vec![1, 4]),
+        ];
+        for (where_document_clause, expected) in cases {
+            let res = operator
+                .run(&input(where_document_clause))
+                .await
+                .expect("Error during running of operator");
+            assert_eq!(Some(expected), res.where_condition_filtered_offset_ids);
+        }
+
+        let err = operator
+            .run(&input(direct("(beta", WhereDocumentOperator::Regex)))
+            .await
+            .expect_err("Invalid regex should fail");
+        assert_eq!(err.code(), ErrorCodes::InvalidArgument);
+
+        let err = operator
+            .run(&input(direct("(beta", WhereDocumentOperator::Search)))
+            .await
+            .expect_err("Invalid text query should fail");
+        assert_eq!(err.code(), ErrorCodes::InvalidArgument);
+    }
+
     #[tokio::test]
     async fn query_ids_only() {
         let tmp_dir = tempfile::tempdir().unwrap();
@@ -1203,6 +1767,7 @@ mod test {
                         id: "embedding_id_1".to_string(),
                         embedding: Some(vec![1.0, 2.0, 3.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata.clone()),
                         document: Some(String::from("This is a document about cats.")),
                         operation: Operation::Add,
@@ -1214,6 +1779,7 @@ mod test {
                         id: "embedding_id_2".to_string(),
                         embedding: Some(vec![4.0, 5.0, 6.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata),
                         document: Some(String::from("This is a document about dogs.")),
                         operation: Operation::Add,
@@ -1286,6 +1852,7 @@ mod test {
                     id: "embedding_id_1".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata.clone()),
                     document: None,
                     operation: Operation::Update,
@@ -1297,6 +1864,7 @@ mod test {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata),
                     document: Some(String::from("This is a document about dogs.")),
                     operation: Operation::Add,
@@ -1308,6 +1876,7 @@ mod test {
                     id: "embedding_id_2".to_string(),
                     embedding: Some(vec![10.0, 11.0, 12.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Update,
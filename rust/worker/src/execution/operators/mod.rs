@@ -1,13 +1,16 @@
+pub(super) mod aggregate_metadata;
 pub(super) mod brute_force_knn;
 pub(super) mod count_records;
 pub(super) mod flush_s3;
 pub(super) mod get_vectors_operator;
 pub(super) mod hnsw_knn;
+pub(super) mod ivf_knn;
 pub(super) mod merge_knn_results;
 pub(super) mod merge_metadata_results;
 pub(super) mod metadata_filtering;
-pub(super) mod normalize_vectors;
+pub(crate) mod normalize_vectors;
 pub(super) mod partition;
 pub(super) mod pull_log;
 pub(super) mod register;
+pub(super) mod sparse_knn;
 pub(super) mod write_segments;
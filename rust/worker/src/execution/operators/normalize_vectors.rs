@@ -23,6 +23,15 @@ pub fn normalize(vector: &[f32]) -> Vec<f32> {
     vector.iter().map(|x| x * norm).collect()
 }
 
+/// Normalizes each vector of a multi-vector, a concatenation of vectors of
+/// `dimensionality` values. Values past the last whole vector are dropped.
+pub fn normalize_multi_vector(multi_vector: &[f32], dimensionality: usize) -> Vec<f32> {
+    multi_vector
+        .chunks_exact(dimensionality)
+        .flat_map(normalize)
+        .collect()
+}
+
 #[async_trait]
 impl Operator<NormalizeVectorOperatorInput, NormalizeVectorOperatorOutput>
     for NormalizeVectorOperator
@@ -168,6 +168,7 @@ mod tests {
                                 id: "embedding_id_1".to_string(),
                                 embedding: None,
                                 encoding: None,
+                                sparse_vector: None,
                                 metadata: None,
                                 document: None,
                                 operation: Operation::Add,
@@ -187,6 +188,7 @@ mod tests {
                                 id: "embedding_id_2".to_string(),
                                 embedding: None,
                                 encoding: None,
+                                sparse_vector: None,
                                 metadata: None,
                                 document: None,
                                 operation: Operation::Add,
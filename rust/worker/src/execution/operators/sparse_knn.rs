@@ -0,0 +1,738 @@
+use crate::blockstore::provider::BlockfileProvider;
+use crate::errors::{ChromaError, ErrorCodes};
+use crate::execution::data::data_chunk::Chunk;
+use crate::execution::operator::Operator;
+use crate::segment::record_segment::{RecordSegmentReader, RecordSegmentReaderCreationError};
+use crate::segment::sparse_vector_segment::{
+    PostingBlock, SparseVectorSegmentError, SparseVectorSegmentReader, POSTING_BLOCK_SIZE,
+};
+use crate::segment::LogMaterializer;
+use crate::types::{LogRecord, Operation, Segment, SparseVector};
+use async_trait::async_trait;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// The sparse k-nearest neighbors operator finds the k records with the largest
+/// dot product with a sparse query vector.
+/// # Description
+/// Records in the sparse vector segment are scored with block-max WAND. The
+/// posting list of every query dimension is split into blocks of offset ids
+/// with the largest value of each, and a block is only read when the bounds of
+/// the blocks that can hold a record beat the k-th best score so far. Records
+/// in the log are scored directly. The distance of a record is one minus its
+/// dot product, as for inner product.
+#[derive(Debug)]
+pub struct SparseKnnOperator {}
+
+/// The input to the sparse k-nearest neighbors operator.
+/// # Parameters
+/// * `logs` - The records that have not been compacted into the segments yet.
+/// * `query` - The sparse query vector.
+/// * `k` - The number of nearest neighbors to find.
+/// * `allowed_ids` - The user ids to search, empty when there is no filter.
+/// * `sparse_segment` - The sparse vector segment to search.
+/// * `record_segment` - The record segment, to map between user and offset ids.
+#[derive(Debug)]
+pub struct SparseKnnOperatorInput {
+    pub logs: Chunk<LogRecord>,
+    pub query: SparseVector,
+    pub k: usize,
+    pub allowed_ids: Arc<[String]>,
+    pub sparse_segment: Segment,
+    pub record_segment: Segment,
+    pub blockfile_provider: BlockfileProvider,
+}
+
+/// The output of the sparse k-nearest neighbors operator, sorted by distance.
+#[derive(Debug)]
+pub struct SparseKnnOperatorOutput {
+    pub user_ids: Vec<String>,
+    pub distances: Vec<f32>,
+}
+
+#[derive(Error, Debug)]
+pub enum SparseKnnOperatorError {
+    #[error("Error creating Record Segment")]
+    RecordSegmentError(#[from] RecordSegmentReaderCreationError),
+    #[error("Error creating Sparse Vector Segment")]
+    SparseVectorSegmentError(#[from] SparseVectorSegmentError),
+}
+
+impl ChromaError for SparseKnnOperatorError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            SparseKnnOperatorError::RecordSegmentError(e) => e.code(),
+            SparseKnnOperatorError::SparseVectorSegmentError(e) => e.code(),
+        }
+    }
+}
+
+// Reads the postings of one block of the posting list of a dimension.
+#[async_trait]
+trait PostingBlockReader {
+    async fn read_block(
+        &self,
+        dimension: u32,
+        block: u32,
+    ) -> Result<Vec<(u32, f32)>, Box<dyn ChromaError>>;
+}
+
+// The postings of the segment without the records that the log replaces or
+// the filter leaves out.
+struct SegmentPostings<'me> {
+    reader: &'me SparseVectorSegmentReader<'me>,
+    allowed_offset_ids: &'me HashSet<u32>,
+    disallowed_offset_ids: &'me HashSet<u32>,
+}
+
+#[async_trait]
+impl PostingBlockReader for SegmentPostings<'_> {
+    async fn read_block(
+        &self,
+        dimension: u32,
+        block: u32,
+    ) -> Result<Vec<(u32, f32)>, Box<dyn ChromaError>> {
+        let mut postings = self.reader.get_posting_block(dimension, block).await?;
+        postings.retain(|(offset_id, _)| {
+            !self.disallowed_offset_ids.contains(offset_id)
+                && (self.allowed_offset_ids.is_empty()
+                    || self.allowed_offset_ids.contains(offset_id))
+        });
+        Ok(postings)
+    }
+}
+
+// The posting list of one query dimension and the position of the next posting.
+// A block is only read once the search reaches it.
+struct Cursor {
+    dimension: u32,
+    query_value: f32,
+    blocks: Vec<PostingBlock>,
+    // The block the cursor is in, with its postings once they are read.
+    block: usize,
+    postings: Option<Vec<(u32, f32)>>,
+    position: usize,
+    // No posting before this offset id is left.
+    target: u32,
+    // The largest contribution of any posting to a score.
+    upper_bound: f32,
+}
+
+impl Cursor {
+    fn new(dimension: u32, query_value: f32, blocks: Vec<PostingBlock>) -> Self {
+        let upper_bound = blocks
+            .iter()
+            .map(|block| block.upper_bound(query_value))
+            .fold(0.0, f32::max);
+        Cursor {
+            dimension,
+            query_value,
+            blocks,
+            block: 0,
+            postings: None,
+            position: 0,
+            target: 0,
+            upper_bound,
+        }
+    }
+
+    // The offset id of the next posting, or the first offset id it can have
+    // while its block is unread.
+    fn current(&self) -> Option<u32> {
+        let block = self.blocks.get(self.block)?;
+        match &self.postings {
+            Some(postings) => postings.get(self.position).map(|(offset_id, _)| *offset_id),
+            None => Some(self.target.max(block.start())),
+        }
+    }
+
+    // The value of the next posting if its block is read.
+    fn value(&self) -> Option<f32> {
+        self.postings
+            .as_ref()
+            .and_then(|postings| postings.get(self.position))
+            .map(|(_, value)| *value)
+    }
+
+    fn block_upper_bound(&self) -> f32 {
+        self.blocks[self.block].upper_bound(self.query_value)
+    }
+
+    fn block_end(&self) -> u32 {
+        self.blocks[self.block].end()
+    }
+
+    fn next_block(&mut self) {
+        self.block += 1;
+        self.postings = None;
+        self.position = 0;
+    }
+
+    // Moves past the postings before the offset id without reading any block.
+    fn advance_to(&mut self, offset_id: u32) {
+        self.target = self.target.max(offset_id);
+        while self.block < self.blocks.len() && self.block_end() <= self.target {
+            self.next_block();
+        }
+        if let Some(postings) = &self.postings {
+            self.position += postings[self.position..].partition_point(|(id, _)| *id < offset_id);
+            if self.position == postings.len() {
+                self.next_block();
+            }
+        }
+    }
+
+    // Reads the block the cursor is in, and the blocks after it while they
+    // have no posting left.
+    async fn read(&mut self, reader: &impl PostingBlockReader) -> Result<(), Box<dyn ChromaError>> {
+        while self.postings.is_none() {
+            let block = match self.blocks.get(self.block) {
+                Some(block) => block.block,
+                None => return Ok(()),
+            };
+            let postings = reader.read_block(self.dimension, block).await?;
+            let position = postings.partition_point(|(offset_id, _)| *offset_id < self.target);
+            if position < postings.len() {
+                self.postings = Some(postings);
+                self.position = position;
+            } else {
+                self.next_block();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Scored {
+    score: f32,
+    offset_id: u32,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(self.offset_id.cmp(&other.offset_id))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Returns the k offset ids with the largest scores, largest first. The upper
+// bounds of the posting lists pick the pivot, the first offset id that can beat
+// the threshold. The blocks that can hold the pivot are only read when their
+// bounds can beat it too, otherwise the search skips to the end of the first
+// of them.
+async fn wand(
+    reader: &impl PostingBlockReader,
+    mut cursors: Vec<Cursor>,
+    k: usize,
+) -> Result<Vec<Scored>, Box<dyn ChromaError>> {
+    let mut heap: BinaryHeap<Reverse<Scored>> = BinaryHeap::with_capacity(k + 1);
+    if k == 0 {
+        return Ok(vec![]);
+    }
+    loop {
+        cursors.retain(|cursor| cursor.current().is_some());
+        cursors.sort_by_key(|cursor| cursor.current());
+        // Until the heap is full every record is a candidate
+        let threshold = match heap.len() < k {
+            true => f32::NEG_INFINITY,
+            false => heap.peek().unwrap().0.score,
+        };
+        // The pivot is the first cursor where the bounds of the cursors up to
+        // it can beat the threshold. No record before its offset id can.
+        let mut bound = 0.0;
+        let pivot = cursors.iter().position(|cursor| {
+            bound += cursor.upper_bound;
+            bound > threshold
+        });
+        let pivot = match pivot {
+            Some(pivot) => pivot,
+            None => break,
+        };
+        let pivot_id = cursors[pivot].current().unwrap();
+        // The cursors after the pivot at its offset id can have it too.
+        let last = pivot
+            + cursors[pivot + 1..]
+                .iter()
+                .take_while(|cursor| cursor.current() == Some(pivot_id))
+                .count();
+        for cursor in cursors[..=last].iter_mut() {
+            cursor.advance_to(pivot_id);
+        }
+
+        // Only the cursors still at the pivot can have it. Up to the end of
+        // the first of their blocks no other cursor has a record, so the
+        // bounds of the blocks cover every record until then.
+        let mut at_pivot = false;
+        let mut block_bound = 0.0;
+        let mut next_id = cursors
+            .get(last + 1)
+            .and_then(Cursor::current)
+            .unwrap_or(u32::MAX);
+        for cursor in cursors[..=last].iter() {
+            match cursor.current() {
+                Some(offset_id) if offset_id == pivot_id => {
+                    at_pivot = true;
+                    block_bound += cursor.block_upper_bound();
+                    next_id = next_id.min(cursor.block_end());
+                }
+                Some(offset_id) => next_id = next_id.min(offset_id),
+                None => {}
+            }
+        }
+        if !at_pivot || block_bound <= threshold {
+            for cursor in cursors[..=last].iter_mut() {
+                cursor.advance_to(next_id);
+            }
+            continue;
+        }
+
+        let mut unread = false;
+        for cursor in cursors[..=last].iter_mut() {
+            if cursor.current() == Some(pivot_id) && cursor.value().is_none() {
+                cursor.read(reader).await?;
+                unread = true;
+            }
+        }
+        // The postings that were read can start after the pivot.
+        if unread {
+            continue;
+        }
+
+        let mut score = 0.0;
+        for cursor in cursors[..=last].iter_mut() {
+            if let (Some(offset_id), Some(value)) = (cursor.current(), cursor.value()) {
+                if offset_id == pivot_id {
+                    score += cursor.query_value * value;
+                    cursor.advance_to(pivot_id.saturating_add(1));
+                }
+            }
+        }
+        if heap.len() < k || score > threshold {
+            heap.push(Reverse(Scored {
+                score,
+                offset_id: pivot_id,
+            }));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+    }
+    let mut results: Vec<Scored> = heap.into_iter().map(|Reverse(scored)| scored).collect();
+    results.sort_by(|a, b| b.cmp(a));
+    Ok(results)
+}
+
+impl SparseKnnOperator {
+    // Searches the compacted records. Records that the log deletes or gives a new
+    // sparse vector are skipped since the log has the latest version of them.
+    async fn search_segment(
+        &self,
+        input: &SparseKnnOperatorInput,
+        record_segment_reader: &RecordSegmentReader<'_>,
+        disallowed_offset_ids: &HashSet<u32>,
+    ) -> Result<Vec<(String, f32)>, Box<dyn ChromaError>> {
+        let sparse_segment_reader = match SparseVectorSegmentReader::from_segment(
+            &input.sparse_segment,
+            &input.blockfile_provider,
+        )
+        .await
+        {
+            Ok(reader) => reader,
+            Err(e) => match *e {
+                SparseVectorSegmentError::UninitializedSegment => return Ok(vec![]),
+                e => return Err(Box::new(SparseKnnOperatorError::from(e))),
+            },
+        };
+        let mut allowed_offset_ids = HashSet::new();
+        for user_id in input.allowed_ids.iter() {
+            // Allowed ids that are only in the log are not in the record segment
+            if let Ok(offset_id) = record_segment_reader
+                .get_offset_id_for_user_id(user_id)
+                .await
+            {
+                allowed_offset_ids.insert(offset_id);
+            }
+        }
+        if !input.allowed_ids.is_empty() && allowed_offset_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // A filter leaves out the blocks without allowed records unread.
+        let allowed_blocks: HashSet<u32> = allowed_offset_ids
+            .iter()
+            .map(|offset_id| offset_id / POSTING_BLOCK_SIZE)
+            .collect();
+        let mut cursors = Vec::with_capacity(input.query.indices.len());
+        for (dimension, query_value) in input.query.iter() {
+            let mut blocks = sparse_segment_reader.get_posting_blocks(dimension).await?;
+            if !allowed_blocks.is_empty() {
+                blocks.retain(|block| allowed_blocks.contains(&block.block));
+            }
+            if blocks.is_empty() {
+                continue;
+            }
+            cursors.push(Cursor::new(dimension, query_value, blocks));
+        }
+
+        let segment_postings = SegmentPostings {
+            reader: &sparse_segment_reader,
+            allowed_offset_ids: &allowed_offset_ids,
+            disallowed_offset_ids,
+        };
+        let mut results = Vec::new();
+        for scored in wand(&segment_postings, cursors, input.k).await? {
+            let user_id = record_segment_reader
+                .get_user_id_for_offset_id(scored.offset_id)
+                .await?;
+            results.push((user_id.to_string(), 1.0 - scored.score));
+        }
+        Ok(results)
+    }
+}
+
+#[async_trait]
+impl Operator<SparseKnnOperatorInput, SparseKnnOperatorOutput> for SparseKnnOperator {
+    type Error = Box<dyn ChromaError>;
+
+    async fn run(
+        &self,
+        input: &SparseKnnOperatorInput,
+    ) -> Result<SparseKnnOperatorOutput, Self::Error> {
+        let record_segment_reader = match RecordSegmentReader::from_segment(
+            &input.record_segment,
+            &input.blockfile_provider,
+        )
+        .await
+        {
+            Ok(reader) => Some(reader),
+            Err(e) => match *e {
+                // Nothing has been compacted yet, all the records are in the log
+                RecordSegmentReaderCreationError::UninitializedSegment => None,
+                e => return Err(Box::new(SparseKnnOperatorError::from(e))),
+            },
+        };
+        let log_materializer =
+            LogMaterializer::new(record_segment_reader, input.logs.clone(), None);
+        let logs = match log_materializer.materialize().await {
+            Ok(logs) => logs,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let mut results = Vec::new();
+        let mut disallowed_offset_ids = HashSet::new();
+        for (log_record, _) in logs.iter() {
+            if log_record.final_operation == Operation::Delete {
+                disallowed_offset_ids.insert(log_record.offset_id);
+                continue;
+            }
+            let sparse_vector = match log_record.final_sparse_vector {
+                Some(sparse_vector) => sparse_vector,
+                None => continue,
+            };
+            disallowed_offset_ids.insert(log_record.offset_id);
+            let user_id = log_record.merged_user_id_ref();
+            if !input.allowed_ids.is_empty() && !input.allowed_ids.iter().any(|id| id == user_id) {
+                continue;
+            }
+            results.push((user_id.to_string(), 1.0 - sparse_vector.dot(&input.query)));
+        }
+
+        if let Some(record_segment_reader) = &log_materializer.record_segment_reader {
+            results.extend(
+                self.search_segment(input, record_segment_reader, &disallowed_offset_ids)
+                    .await?,
+            );
+        }
+
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results.truncate(input.k);
+        let (user_ids, distances) = results.into_iter().unzip();
+        Ok(SparseKnnOperatorOutput {
+            user_ids,
+            distances,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::record_segment::RecordSegmentWriter;
+    use crate::segment::sparse_vector_segment::SparseVectorSegmentWriter;
+    use crate::segment::{SegmentFlusher, SegmentWriter};
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::types::{OperationRecord, SegmentScope, SegmentType};
+    use std::collections::HashMap;
+    use std::sync::atomic::{self, AtomicUsize};
+    use uuid::Uuid;
+
+    fn log_record(
+        log_offset: i64,
+        id: &str,
+        operation: Operation,
+        sparse_vector: Option<(Vec<u32>, Vec<f32>)>,
+    ) -> LogRecord {
+        LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding: match operation {
+                    Operation::Delete => None,
+                    _ => Some(vec![1.0]),
+                },
+                encoding: None,
+                sparse_vector: sparse_vector
+                    .map(|(indices, values)| SparseVector { indices, values }),
+                metadata: None,
+                document: None,
+                operation,
+            },
+        }
+    }
+
+    // Posting lists in memory, split into blocks like in the segment.
+    struct MemoryPostings {
+        blocks: HashMap<(u32, u32), Vec<(u32, f32)>>,
+        reads: AtomicUsize,
+    }
+
+    impl MemoryPostings {
+        fn new(postings: Vec<(u32, u32, f32)>) -> Self {
+            let mut blocks: HashMap<(u32, u32), Vec<(u32, f32)>> = HashMap::new();
+            for (dimension, offset_id, value) in postings {
+                blocks
+                    .entry((dimension, offset_id / POSTING_BLOCK_SIZE))
+                    .or_default()
+                    .push((offset_id, value));
+            }
+            for block in blocks.values_mut() {
+                block.sort_by_key(|(offset_id, _)| *offset_id);
+            }
+            MemoryPostings {
+                blocks,
+                reads: AtomicUsize::new(0),
+            }
+        }
+
+        fn cursors(&self, query: &SparseVector) -> Vec<Cursor> {
+            query
+                .iter()
+                .filter_map(|(dimension, query_value)| {
+                    let mut blocks: Vec<PostingBlock> = self
+                        .blocks
+                        .iter()
+                        .filter(|((d, _), _)| *d == dimension)
+                        .map(|((_, block), postings)| PostingBlock {
+                            block: *block,
+                            max_value: postings.iter().map(|(_, v)| *v).fold(0.0, f32::max),
+                            max_negated_value: postings.iter().map(|(_, v)| -v).fold(0.0, f32::max),
+                        })
+                        .collect();
+                    blocks.sort_by_key(|block| block.block);
+                    match blocks.is_empty() {
+                        true => None,
+                        false => Some(Cursor::new(dimension, query_value, blocks)),
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl PostingBlockReader for MemoryPostings {
+        async fn read_block(
+            &self,
+            dimension: u32,
+            block: u32,
+        ) -> Result<Vec<(u32, f32)>, Box<dyn ChromaError>> {
+            self.reads.fetch_add(1, atomic::Ordering::Relaxed);
+            Ok(self
+                .blocks
+                .get(&(dimension, block))
+                .cloned()
+                .unwrap_or_default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wand_matches_exhaustive() {
+        // The records are spread over many blocks.
+        let vectors: Vec<(u32, Vec<(u32, f32)>)> = (0..200_u32)
+            .map(|index| {
+                let vector = (0..8_u32)
+                    .filter(|dimension| (index * 7 + dimension * 3) % 5 < 2)
+                    .map(|dimension| {
+                        let value = ((index * 31 + dimension * 17) % 23) as f32 / 10.0 - 0.5;
+                        (dimension, value)
+                    })
+                    .collect();
+                (index * 97, vector)
+            })
+            .collect();
+        let query = SparseVector {
+            indices: vec![0, 2, 3, 6, 7],
+            values: vec![1.0, 0.5, -0.25, 2.0, 0.75],
+        };
+        let postings = MemoryPostings::new(
+            vectors
+                .iter()
+                .flat_map(|(offset_id, vector)| {
+                    vector
+                        .iter()
+                        .map(|(dimension, value)| (*dimension, *offset_id, *value))
+                })
+                .collect(),
+        );
+
+        let mut expected: Vec<(u32, f32)> = vectors
+            .iter()
+            .filter(|(_, vector)| vector.iter().any(|(d, _)| query.indices.contains(d)))
+            .map(|(offset_id, vector)| {
+                let (indices, values) = vector.iter().copied().unzip();
+                (*offset_id, query.dot(&SparseVector { indices, values }))
+            })
+            .collect();
+        expected.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+        expected.truncate(10);
+
+        let results = wand(&postings, postings.cursors(&query), 10).await.unwrap();
+        assert_eq!(results.len(), 10);
+        for (scored, (offset_id, score)) in results.iter().zip(expected.iter()) {
+            assert_eq!(scored.offset_id, *offset_id);
+            assert!((scored.score - score).abs() < 1e-5);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wand_skips_blocks() {
+        // Dimension 0 has a posting in each of ten blocks and dimension 1 in
+        // all but the first. Only the first block of 0 and the last block of 1
+        // have large values, so the blocks between them can't beat the record
+        // in the first block even though the bounds of the posting lists can.
+        let mut posting_list = Vec::new();
+        for block in 0..10 {
+            let offset_id = block * POSTING_BLOCK_SIZE + 1;
+            let value = if block == 0 { 10.0 } else { 0.1 };
+            posting_list.push((0, offset_id, value));
+            if block > 0 {
+                let value = if block == 9 { 10.0 } else { 0.1 };
+                posting_list.push((1, offset_id, value));
+            }
+        }
+        let postings = MemoryPostings::new(posting_list);
+        let query = SparseVector {
+            indices: vec![0, 1],
+            values: vec![1.0, 1.0],
+        };
+        let results = wand(&postings, postings.cursors(&query), 1).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].offset_id, 9 * POSTING_BLOCK_SIZE + 1);
+        // The first block of 0 and the last blocks of both dimensions.
+        assert_eq!(postings.reads.load(atomic::Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_sparse_knn_merges_log() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider = BlockfileProvider::new_arrow(storage);
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut sparse_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileSparseVector,
+            scope: SegmentScope::VECTOR,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        {
+            let record_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .unwrap();
+            let sparse_writer =
+                SparseVectorSegmentWriter::from_segment(&sparse_segment, &blockfile_provider)
+                    .await
+                    .unwrap();
+            let logs = Chunk::new(
+                vec![
+                    log_record(1, "a", Operation::Add, Some((vec![1, 2], vec![1.0, 1.0]))),
+                    log_record(2, "b", Operation::Add, Some((vec![1], vec![0.5]))),
+                    log_record(3, "c", Operation::Add, Some((vec![2], vec![0.25]))),
+                    log_record(4, "d", Operation::Add, Some((vec![3], vec![5.0]))),
+                ]
+                .into(),
+            );
+            let materializer = LogMaterializer::new(None, logs, None);
+            let records = materializer.materialize().await.unwrap();
+            record_writer
+                .apply_materialized_log_chunk(records.clone())
+                .await
+                .unwrap();
+            sparse_writer
+                .apply_materialized_log_chunk(records)
+                .await
+                .unwrap();
+            record_segment.file_path = record_writer.commit().unwrap().flush().await.unwrap();
+            sparse_segment.file_path = sparse_writer.commit().unwrap().flush().await.unwrap();
+        }
+
+        // The log deletes a, lowers c and adds e
+        let logs = Chunk::new(
+            vec![
+                log_record(5, "a", Operation::Delete, None),
+                log_record(6, "c", Operation::Update, Some((vec![2], vec![0.1]))),
+                log_record(7, "e", Operation::Add, Some((vec![1, 2], vec![2.0, 2.0]))),
+            ]
+            .into(),
+        );
+        let query = SparseVector {
+            indices: vec![1, 2],
+            values: vec![1.0, 1.0],
+        };
+        let operator = SparseKnnOperator {};
+        let input = SparseKnnOperatorInput {
+            logs: logs.clone(),
+            query: query.clone(),
+            k: 3,
+            allowed_ids: Arc::new([]),
+            sparse_segment: sparse_segment.clone(),
+            record_segment: record_segment.clone(),
+            blockfile_provider: blockfile_provider.clone(),
+        };
+        let output = operator.run(&input).await.unwrap();
+        assert_eq!(output.user_ids, vec!["e", "b", "c"]);
+        assert_eq!(output.distances, vec![-3.0, 0.5, 0.9]);
+
+        let input = SparseKnnOperatorInput {
+            logs,
+            query,
+            k: 3,
+            allowed_ids: Arc::new(["c".to_string(), "d".to_string()]),
+            sparse_segment,
+            record_segment,
+            blockfile_provider,
+        };
+        let output = operator.run(&input).await.unwrap();
+        assert_eq!(output.user_ids, vec!["c"]);
+    }
+}
@@ -8,15 +8,15 @@ use crate::segment::metadata_segment::MetadataSegmentWriter;
 use crate::segment::record_segment::ApplyMaterializedLogError;
 use crate::segment::record_segment::RecordSegmentReader;
 use crate::segment::record_segment::RecordSegmentReaderCreationError;
+use crate::segment::sparse_vector_segment::SparseVectorSegmentWriter;
 use crate::segment::LogMaterializer;
 use crate::segment::LogMaterializerError;
 use crate::segment::SegmentWriter;
+use crate::segment::VectorSegmentWriter;
 use crate::types::Segment;
 use crate::{
     execution::{data::data_chunk::Chunk, operator::Operator},
-    segment::{
-        distributed_hnsw_segment::DistributedHNSWSegmentWriter, record_segment::RecordSegmentWriter,
-    },
+    segment::record_segment::RecordSegmentWriter,
     types::LogRecord,
 };
 use async_trait::async_trait;
@@ -57,8 +57,10 @@ impl WriteSegmentsOperator {
 #[derive(Debug)]
 pub struct WriteSegmentsInput {
     record_segment_writer: RecordSegmentWriter,
-    hnsw_segment_writer: Box<DistributedHNSWSegmentWriter>,
+    vector_segment_writer: VectorSegmentWriter,
     metadata_segment_writer: MetadataSegmentWriter<'static>,
+    // Only collections with sparse vectors have this segment
+    sparse_vector_segment_writer: Option<SparseVectorSegmentWriter<'static>>,
     chunk: Chunk<LogRecord>,
     provider: BlockfileProvider,
     record_segment: Segment,
@@ -66,10 +68,12 @@ pub struct WriteSegmentsInput {
 }
 
 impl WriteSegmentsInput {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         record_segment_writer: RecordSegmentWriter,
-        hnsw_segment_writer: Box<DistributedHNSWSegmentWriter>,
+        vector_segment_writer: VectorSegmentWriter,
         metadata_segment_writer: MetadataSegmentWriter<'static>,
+        sparse_vector_segment_writer: Option<SparseVectorSegmentWriter<'static>>,
         chunk: Chunk<LogRecord>,
         provider: BlockfileProvider,
         record_segment: Segment,
@@ -77,8 +81,9 @@ impl WriteSegmentsInput {
     ) -> Self {
         WriteSegmentsInput {
             record_segment_writer,
-            hnsw_segment_writer,
+            vector_segment_writer,
             metadata_segment_writer,
+            sparse_vector_segment_writer,
             chunk,
             provider,
             record_segment,
@@ -90,8 +95,9 @@ impl WriteSegmentsInput {
 #[derive(Debug)]
 pub struct WriteSegmentsOutput {
     pub(crate) record_segment_writer: RecordSegmentWriter,
-    pub(crate) hnsw_segment_writer: Box<DistributedHNSWSegmentWriter>,
+    pub(crate) vector_segment_writer: VectorSegmentWriter,
     pub(crate) metadata_segment_writer: MetadataSegmentWriter<'static>,
+    pub(crate) sparse_vector_segment_writer: Option<SparseVectorSegmentWriter<'static>>,
 }
 
 #[async_trait]
@@ -168,8 +174,20 @@ impl Operator<WriteSegmentsInput, WriteSegmentsOutput> for WriteSegmentsOperator
             }
         }
         tracing::debug!("Applied materialized records to metadata segment");
+        if let Some(sparse_vector_segment_writer) = &input.sparse_vector_segment_writer {
+            match sparse_vector_segment_writer
+                .apply_materialized_log_chunk(res.clone())
+                .await
+            {
+                Ok(()) => (),
+                Err(e) => {
+                    return Err(WriteSegmentsOperatorError::ApplyMaterializatedLogsError(e));
+                }
+            }
+            tracing::debug!("Applied materialized records to sparse vector segment");
+        }
         match input
-            .hnsw_segment_writer
+            .vector_segment_writer
             .apply_materialized_log_chunk(res)
             .await
         {
@@ -178,11 +196,12 @@ impl Operator<WriteSegmentsInput, WriteSegmentsOutput> for WriteSegmentsOperator
                 return Err(WriteSegmentsOperatorError::ApplyMaterializatedLogsError(e));
             }
         }
-        tracing::debug!("Applied Materialized Records to HNSW Segment");
+        tracing::debug!("Applied Materialized Records to Vector Segment");
         Ok(WriteSegmentsOutput {
             record_segment_writer: input.record_segment_writer.clone(),
-            hnsw_segment_writer: input.hnsw_segment_writer.clone(),
+            vector_segment_writer: input.vector_segment_writer.clone(),
             metadata_segment_writer: input.metadata_segment_writer.clone(),
+            sparse_vector_segment_writer: input.sparse_vector_segment_writer.clone(),
         })
     }
 }
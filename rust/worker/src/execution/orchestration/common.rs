@@ -45,7 +45,9 @@ pub(super) async fn get_hnsw_segment_by_id(
         }
     };
 
-    if segment.r#type != SegmentType::HnswDistributed {
+    // The vector segment of a collection is either an hnsw or an ivf segment
+    if segment.r#type != SegmentType::HnswDistributed && segment.r#type != SegmentType::BlockfileIvf
+    {
         return Err(Box::new(GetHnswSegmentByIdError::HnswSegmentNotFound(
             *hnsw_segment_id,
         )));
@@ -53,6 +55,56 @@ pub(super) async fn get_hnsw_segment_by_id(
     Ok(segment)
 }
 
+#[derive(Debug, Error)]
+pub(super) enum GetSparseVectorSegmentByIdError {
+    #[error("Sparse vector segment with id: {0} not found")]
+    SparseVectorSegmentNotFound(Uuid),
+    #[error("Get segments error")]
+    GetSegmentsError(#[from] GetSegmentsError),
+}
+
+impl ChromaError for GetSparseVectorSegmentByIdError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            GetSparseVectorSegmentByIdError::SparseVectorSegmentNotFound(_) => ErrorCodes::NotFound,
+            GetSparseVectorSegmentByIdError::GetSegmentsError(e) => e.code(),
+        }
+    }
+}
+
+pub(super) async fn get_sparse_vector_segment_by_id(
+    mut sysdb: Box<SysDb>,
+    sparse_segment_id: &Uuid,
+) -> Result<Segment, Box<GetSparseVectorSegmentByIdError>> {
+    let segments = sysdb
+        .get_segments(Some(*sparse_segment_id), None, None, None)
+        .await;
+    let segment = match segments {
+        Ok(segments) => {
+            if segments.is_empty() {
+                return Err(Box::new(
+                    GetSparseVectorSegmentByIdError::SparseVectorSegmentNotFound(
+                        *sparse_segment_id,
+                    ),
+                ));
+            }
+            segments[0].clone()
+        }
+        Err(e) => {
+            return Err(Box::new(GetSparseVectorSegmentByIdError::GetSegmentsError(
+                e,
+            )));
+        }
+    };
+
+    if segment.r#type != SegmentType::BlockfileSparseVector {
+        return Err(Box::new(
+            GetSparseVectorSegmentByIdError::SparseVectorSegmentNotFound(*sparse_segment_id),
+        ));
+    }
+    Ok(segment)
+}
+
 #[derive(Debug, Error)]
 pub(super) enum GetCollectionByIdError {
     #[error("Collection with id: {0} not found")]
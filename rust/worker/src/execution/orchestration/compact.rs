@@ -26,9 +26,12 @@ use crate::index::hnsw_provider::HnswIndexProvider;
 use crate::log::log::Log;
 use crate::log::log::PullLogsError;
 use crate::segment::distributed_hnsw_segment::DistributedHNSWSegmentWriter;
+use crate::segment::ivf_segment::IvfSegmentWriter;
 use crate::segment::metadata_segment::MetadataSegmentWriter;
 use crate::segment::record_segment::RecordSegmentReader;
 use crate::segment::record_segment::RecordSegmentWriter;
+use crate::segment::sparse_vector_segment::SparseVectorSegmentWriter;
+use crate::segment::VectorSegmentWriter;
 use crate::sysdb::sysdb::GetCollectionsError;
 use crate::sysdb::sysdb::GetSegmentsError;
 use crate::sysdb::sysdb::SysDb;
@@ -100,8 +103,17 @@ pub struct CompactOrchestrator {
         Option<tokio::sync::oneshot::Sender<Result<CompactionResponse, Box<dyn ChromaError>>>>,
     // Current max offset id.
     curr_max_offset_id: Arc<AtomicU32>,
+    // The fraction of deleted elements above which the hnsw index is rebuilt
+    hnsw_rebuild_tombstone_ratio: f32,
 }
 
+type SegmentWriters = (
+    RecordSegmentWriter,
+    VectorSegmentWriter,
+    MetadataSegmentWriter<'static>,
+    Option<SparseVectorSegmentWriter<'static>>,
+);
+
 #[derive(Error, Debug)]
 enum GetSegmentWritersError {
     #[error("No segments found for collection")]
@@ -122,8 +134,14 @@ enum GetSegmentWritersError {
     CollectionNotFound,
     #[error("Error getting collection")]
     GetCollectionError(#[from] GetCollectionsError),
-    #[error("No hnsw segment found for collection")]
-    NoHnswSegmentFound,
+    #[error("No vector segment found for collection")]
+    NoVectorSegmentFound,
+    #[error("Error rebuilding HNSW Segment")]
+    HnswSegmentRebuildError,
+    #[error("Error creating Sparse Vector Segment Writer")]
+    SparseVectorSegmentWriterError,
+    #[error("Error creating IVF Segment Writer")]
+    IvfSegmentWriterError,
 }
 
 impl ChromaError for GetSegmentWritersError {
@@ -155,6 +173,7 @@ impl CompactOrchestrator {
         >,
         record_segment: Option<Segment>,
         curr_max_offset_id: Arc<AtomicU32>,
+        hnsw_rebuild_tombstone_ratio: f32,
     ) -> Self {
         CompactOrchestrator {
             id: Uuid::new_v4(),
@@ -172,6 +191,7 @@ impl CompactOrchestrator {
             result_channel,
             record_segment,
             curr_max_offset_id,
+            hnsw_rebuild_tombstone_ratio,
         }
     }
 
@@ -242,8 +262,12 @@ impl CompactOrchestrator {
         self.state = ExecutionState::Write;
 
         let writer_res = self.get_segment_writers().await;
-        let (record_segment_writer, hnsw_segment_writer, metadata_segment_writer) = match writer_res
-        {
+        let (
+            record_segment_writer,
+            vector_segment_writer,
+            metadata_segment_writer,
+            sparse_vector_segment_writer,
+        ) = match writer_res {
             Ok(writers) => writers,
             Err(e) => {
                 tracing::error!("Error creating writers for compaction {:?}", e);
@@ -256,8 +280,9 @@ impl CompactOrchestrator {
             let operator = WriteSegmentsOperator::new();
             let input = WriteSegmentsInput::new(
                 record_segment_writer.clone(),
-                hnsw_segment_writer.clone(),
+                vector_segment_writer.clone(),
                 metadata_segment_writer.clone(),
+                sparse_vector_segment_writer.clone(),
                 parition.clone(),
                 self.blockfile_provider.clone(),
                 self.record_segment
@@ -280,8 +305,9 @@ impl CompactOrchestrator {
     async fn flush_s3(
         &mut self,
         record_segment_writer: RecordSegmentWriter,
-        hnsw_segment_writer: Box<DistributedHNSWSegmentWriter>,
+        vector_segment_writer: VectorSegmentWriter,
         metadata_segment_writer: MetadataSegmentWriter<'static>,
+        sparse_vector_segment_writer: Option<SparseVectorSegmentWriter<'static>>,
         self_address: Box<dyn Receiver<TaskResult<FlushS3Output, Box<dyn ChromaError>>>>,
     ) {
         self.state = ExecutionState::Flush;
@@ -289,8 +315,9 @@ impl CompactOrchestrator {
         let operator = FlushS3Operator::new();
         let input = FlushS3Input::new(
             record_segment_writer,
-            hnsw_segment_writer,
+            vector_segment_writer,
             metadata_segment_writer,
+            sparse_vector_segment_writer,
         );
 
         let task = wrap(operator, input, self_address);
@@ -329,16 +356,53 @@ impl CompactOrchestrator {
         }
     }
 
-    async fn get_segment_writers(
-        &mut self,
-    ) -> Result<
-        (
-            RecordSegmentWriter,
-            Box<DistributedHNSWSegmentWriter>,
-            MetadataSegmentWriter<'static>,
-        ),
-        Box<dyn ChromaError>,
-    > {
+    async fn get_hnsw_segment_writer(
+        &self,
+        hnsw_segment: &Segment,
+        dimension: usize,
+        record_segment_reader: Option<&RecordSegmentReader<'_>>,
+    ) -> Result<Box<DistributedHNSWSegmentWriter>, Box<dyn ChromaError>> {
+        let hnsw_segment_writer = match DistributedHNSWSegmentWriter::from_segment(
+            hnsw_segment,
+            dimension,
+            self.hnsw_index_provider.clone(),
+        )
+        .await
+        {
+            Ok(writer) => writer,
+            Err(e) => {
+                println!("Error creating HNSW Segment Writer: {:?}", e);
+                return Err(Box::new(GetSegmentWritersError::HnswSegmentWriterError));
+            }
+        };
+
+        // Deletes only mark elements in the hnsw graph, so once enough of them build up
+        // we rebuild the index from the live records before applying this compaction.
+        // The forked index and the record segment are at the same version at this point.
+        let tombstone_ratio = hnsw_segment_writer.tombstone_ratio();
+        match record_segment_reader {
+            Some(reader) if tombstone_ratio > self.hnsw_rebuild_tombstone_ratio => {
+                tracing::info!(
+                    "Rebuilding HNSW segment {} with tombstone ratio {}",
+                    hnsw_segment.id,
+                    tombstone_ratio
+                );
+                match hnsw_segment_writer
+                    .rebuild_from_record_segment(hnsw_segment, dimension, reader)
+                    .await
+                {
+                    Ok(writer) => Ok(writer),
+                    Err(e) => {
+                        println!("Error rebuilding HNSW Segment: {:?}", e);
+                        Err(Box::new(GetSegmentWritersError::HnswSegmentRebuildError))
+                    }
+                }
+            }
+            _ => Ok(hnsw_segment_writer),
+        }
+    }
+
+    async fn get_segment_writers(&mut self) -> Result<SegmentWriters, Box<dyn ChromaError>> {
         // Care should be taken to use the same writers across the compaction process
         // Since the segment writers are stateful, we should not create new writers for each partition
         // Nor should we create new writers across different tasks
@@ -387,14 +451,18 @@ impl CompactOrchestrator {
             };
 
         tracing::debug!("Record Segment Writer created");
-        match RecordSegmentReader::from_segment(record_segment, &self.blockfile_provider).await {
-            Ok(reader) => {
-                self.curr_max_offset_id = reader.get_current_max_offset_id();
-            }
-            Err(_) => {
-                self.curr_max_offset_id = Arc::new(AtomicU32::new(0));
-            }
-        };
+        let record_segment_reader =
+            match RecordSegmentReader::from_segment(record_segment, &self.blockfile_provider).await
+            {
+                Ok(reader) => {
+                    self.curr_max_offset_id = reader.get_current_max_offset_id();
+                    Some(reader)
+                }
+                Err(_) => {
+                    self.curr_max_offset_id = Arc::new(AtomicU32::new(0));
+                    None
+                }
+            };
         self.record_segment = Some(record_segment.clone()); // auto deref.
 
         let metadata_segment = segments
@@ -438,35 +506,71 @@ impl CompactOrchestrator {
         };
         let collection = &collection_res[0];
 
-        let hnsw_segment = segments
-            .iter()
-            .find(|segment| segment.r#type == SegmentType::HnswDistributed);
-        if hnsw_segment.is_none() {
-            return Err(Box::new(GetSegmentWritersError::NoHnswSegmentFound));
-        }
-        let hnsw_segment = hnsw_segment.unwrap();
         let dimension = collection
             .dimension
             .expect("Dimension is required in the compactor");
 
-        let hnsw_segment_writer = match DistributedHNSWSegmentWriter::from_segment(
-            hnsw_segment,
-            dimension as usize,
-            self.hnsw_index_provider.clone(),
-        )
-        .await
+        // The vector segment of a collection is either an hnsw or an ivf segment
+        let vector_segment_writer = match segments.iter().find(|segment| {
+            segment.r#type == SegmentType::HnswDistributed
+                || segment.r#type == SegmentType::BlockfileIvf
+        }) {
+            Some(ivf_segment) if ivf_segment.r#type == SegmentType::BlockfileIvf => {
+                match IvfSegmentWriter::from_segment(
+                    ivf_segment,
+                    dimension as usize,
+                    &self.blockfile_provider,
+                )
+                .await
+                {
+                    Ok(writer) => VectorSegmentWriter::Ivf(Box::new(writer)),
+                    Err(e) => {
+                        println!("Error creating IVF Segment Writer: {:?}", e);
+                        return Err(Box::new(GetSegmentWritersError::IvfSegmentWriterError));
+                    }
+                }
+            }
+            Some(hnsw_segment) => VectorSegmentWriter::Hnsw(
+                self.get_hnsw_segment_writer(
+                    hnsw_segment,
+                    dimension as usize,
+                    record_segment_reader.as_ref(),
+                )
+                .await?,
+            ),
+            None => return Err(Box::new(GetSegmentWritersError::NoVectorSegmentFound)),
+        };
+
+        // The sparse vector segment is optional, only collections with sparse
+        // vectors have one.
+        let sparse_vector_segment_writer = match segments
+            .iter()
+            .find(|segment| segment.r#type == SegmentType::BlockfileSparseVector)
         {
-            Ok(writer) => writer,
-            Err(e) => {
-                println!("Error creating HNSW Segment Writer: {:?}", e);
-                return Err(Box::new(GetSegmentWritersError::HnswSegmentWriterError));
+            Some(sparse_vector_segment) => {
+                match SparseVectorSegmentWriter::from_segment(
+                    sparse_vector_segment,
+                    &self.blockfile_provider,
+                )
+                .await
+                {
+                    Ok(writer) => Some(writer),
+                    Err(e) => {
+                        println!("Error creating Sparse Vector Segment Writer: {:?}", e);
+                        return Err(Box::new(
+                            GetSegmentWritersError::SparseVectorSegmentWriterError,
+                        ));
+                    }
+                }
             }
+            None => None,
         };
 
         Ok((
             record_segment_writer,
-            hnsw_segment_writer,
+            vector_segment_writer,
             mt_segment_writer,
+            sparse_vector_segment_writer,
         ))
     }
 
@@ -600,8 +704,9 @@ impl Handler<TaskResult<WriteSegmentsOutput, WriteSegmentsOperatorError>> for Co
             }
             self.flush_s3(
                 output.record_segment_writer,
-                output.hnsw_segment_writer,
+                output.vector_segment_writer,
                 output.metadata_segment_writer,
+                output.sparse_vector_segment_writer,
                 _ctx.sender.as_receiver(),
             )
             .await;
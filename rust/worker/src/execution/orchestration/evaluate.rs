@@ -0,0 +1,427 @@
+use super::common::{
+    get_collection_by_id, get_hnsw_segment_by_id, get_record_segment_by_collection_id,
+};
+use crate::blockstore::provider::BlockfileProvider;
+use crate::distance::DistanceFunction;
+use crate::errors::{ChromaError, ErrorCodes};
+use crate::execution::data::data_chunk::Chunk;
+use crate::execution::operator::Operator;
+use crate::execution::operators::hnsw_knn::{HnswKnnOperator, HnswKnnOperatorInput};
+use crate::execution::operators::normalize_vectors::{normalize, normalize_multi_vector};
+use crate::index::hnsw_provider::HnswIndexProvider;
+use crate::index::{is_valid_multi_vector, IndexConfig};
+use crate::segment::distributed_hnsw_segment::DistributedHNSWSegmentReader;
+use crate::segment::record_segment::RecordSegmentReader;
+use crate::sysdb::sysdb::SysDb;
+use crate::types::{SearchOptions, SegmentType};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// The queries to evaluate a segment with.
+#[derive(Debug)]
+pub(crate) enum EvaluationQueries {
+    /// Use this many vectors stored in the segment, spread evenly across it.
+    Sample(usize),
+    /// Use the supplied vectors.
+    Vectors(Vec<Vec<f32>>),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct LatencyPercentiles {
+    pub(crate) p50: Duration,
+    pub(crate) p90: Duration,
+    pub(crate) p99: Duration,
+}
+
+impl LatencyPercentiles {
+    fn from_latencies(mut latencies: Vec<Duration>) -> Self {
+        if latencies.is_empty() {
+            return LatencyPercentiles::default();
+        }
+        latencies.sort();
+        let percentile = |p: f64| {
+            let rank = (p * latencies.len() as f64).ceil() as usize;
+            latencies[rank.clamp(1, latencies.len()) - 1]
+        };
+        LatencyPercentiles {
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+/// The result of evaluating an hnsw segment.
+/// # Fields
+/// - recall: The mean recall@k of the hnsw results against an exact scan of the record segment.
+/// - num_elements: The number of live elements in the index.
+/// - num_deleted: The number of deleted elements that are still present in the index.
+#[derive(Debug)]
+pub(crate) struct HnswEvaluationReport {
+    pub(crate) num_queries: usize,
+    pub(crate) k: usize,
+    pub(crate) recall: f32,
+    pub(crate) hnsw_latency: LatencyPercentiles,
+    pub(crate) brute_force_latency: LatencyPercentiles,
+    pub(crate) num_elements: usize,
+    pub(crate) num_deleted: usize,
+}
+
+#[derive(Error, Debug)]
+enum HnswEvaluationError {
+    #[error("Collection has no dimension set")]
+    CollectionHasNoDimension,
+    #[error("Query vector has dimension {0} but the collection has dimension {1}")]
+    InvalidQueryDimension(usize, usize),
+    #[error("k must be positive")]
+    InvalidK,
+    #[error("Segment {0} is not an hnsw segment")]
+    NotHnswSegment(Uuid),
+}
+
+impl ChromaError for HnswEvaluationError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            HnswEvaluationError::CollectionHasNoDimension => ErrorCodes::InvalidArgument,
+            HnswEvaluationError::InvalidQueryDimension(_, _) => ErrorCodes::InvalidArgument,
+            HnswEvaluationError::InvalidK => ErrorCodes::InvalidArgument,
+            HnswEvaluationError::NotHnswSegment(_) => ErrorCodes::InvalidArgument,
+        }
+    }
+}
+
+/// The HnswSegmentEvaluator struct.
+/// # Description
+/// Measures the quality of an hnsw segment by running every query through the
+/// HnswKnnOperator and through an exact brute force scan of the record segment, and
+/// comparing the results. This is used to tune the hnsw parameters of a collection.
+/// # Notes
+/// The segment is evaluated as of its last compaction, records that are only in the
+/// log are not considered. Operators are run inline rather than through the dispatcher
+/// so that the reported latencies do not include queueing.
+pub(crate) struct HnswSegmentEvaluator {
+    sysdb: Box<SysDb>,
+    hnsw_index_provider: HnswIndexProvider,
+    blockfile_provider: BlockfileProvider,
+}
+
+impl HnswSegmentEvaluator {
+    pub(crate) fn new(
+        sysdb: Box<SysDb>,
+        hnsw_index_provider: HnswIndexProvider,
+        blockfile_provider: BlockfileProvider,
+    ) -> Self {
+        HnswSegmentEvaluator {
+            sysdb,
+            hnsw_index_provider,
+            blockfile_provider,
+        }
+    }
+
+    pub(crate) async fn evaluate(
+        &self,
+        hnsw_segment_id: &Uuid,
+        queries: EvaluationQueries,
+        k: usize,
+        search_options: SearchOptions,
+    ) -> Result<HnswEvaluationReport, Box<dyn ChromaError>> {
+        if k == 0 {
+            return Err(Box::new(HnswEvaluationError::InvalidK));
+        }
+        let hnsw_segment = match get_hnsw_segment_by_id(self.sysdb.clone(), hnsw_segment_id).await {
+            Ok(segment) => segment,
+            Err(e) => return Err(e),
+        };
+        if hnsw_segment.r#type != SegmentType::HnswDistributed {
+            return Err(Box::new(HnswEvaluationError::NotHnswSegment(
+                hnsw_segment.id,
+            )));
+        }
+        let collection_id = match hnsw_segment.collection {
+            Some(collection_id) => collection_id,
+            None => {
+                return Err(Box::new(HnswEvaluationError::CollectionHasNoDimension));
+            }
+        };
+        let collection = match get_collection_by_id(self.sysdb.clone(), &collection_id).await {
+            Ok(collection) => collection,
+            Err(e) => return Err(e),
+        };
+        let dimensionality = match collection.dimension {
+            Some(dimension) => dimension,
+            None => {
+                return Err(Box::new(HnswEvaluationError::CollectionHasNoDimension));
+            }
+        };
+        let record_segment =
+            match get_record_segment_by_collection_id(self.sysdb.clone(), &collection_id).await {
+                Ok(segment) => segment,
+                Err(e) => return Err(e),
+            };
+        let index_config = match IndexConfig::from_segment(&hnsw_segment, dimensionality) {
+            Ok(index_config) => index_config,
+            Err(e) => return Err(e),
+        };
+        let distance_function = index_config.distance_function;
+        let hnsw_segment_reader = match DistributedHNSWSegmentReader::from_segment(
+            &hnsw_segment,
+            dimensionality as usize,
+            self.hnsw_index_provider.clone(),
+        )
+        .await
+        {
+            Ok(reader) => reader,
+            Err(e) => return Err(e),
+        };
+
+        let mut query_vectors = match queries {
+            EvaluationQueries::Vectors(vectors) => vectors,
+            EvaluationQueries::Sample(num_samples) => {
+                let record_segment_reader = match RecordSegmentReader::from_segment(
+                    &record_segment,
+                    &self.blockfile_provider,
+                )
+                .await
+                {
+                    Ok(reader) => reader,
+                    Err(e) => return Err(e),
+                };
+                let records = record_segment_reader.get_all_data_with_offset_ids().await?;
+                let step = std::cmp::max(1, records.len() / std::cmp::max(1, num_samples));
+                records
+                    .iter()
+                    .step_by(step)
+                    .take(num_samples)
+                    .map(|(_, record)| record.embedding.to_vec())
+                    .collect()
+            }
+        };
+        for query_vector in query_vectors.iter_mut() {
+            // Queries of multi-vector collections are bags of vectors
+            let valid_dimension = match index_config.multi_vector {
+                true => is_valid_multi_vector(query_vector.len(), dimensionality as usize),
+                false => query_vector.len() == dimensionality as usize,
+            };
+            if !valid_dimension {
+                return Err(Box::new(HnswEvaluationError::InvalidQueryDimension(
+                    query_vector.len(),
+                    dimensionality as usize,
+                )));
+            }
+            if distance_function == DistanceFunction::Cosine {
+                *query_vector = match index_config.multi_vector {
+                    true => normalize_multi_vector(query_vector, dimensionality as usize),
+                    false => normalize(query_vector),
+                };
+            }
+        }
+
+        let operator = HnswKnnOperator {};
+        let brute_force_options = SearchOptions {
+            brute_force: true,
+            ..Default::default()
+        };
+        let mut total_recall = 0.0;
+        let mut hnsw_latencies = Vec::with_capacity(query_vectors.len());
+        let mut brute_force_latencies = Vec::with_capacity(query_vectors.len());
+        for query_vector in query_vectors.iter() {
+            let mut input = HnswKnnOperatorInput {
+                segment: hnsw_segment_reader.clone(),
+                query: query_vector.clone(),
+                k,
+                distance_function: distance_function.clone(),
+                search_options: search_options.clone(),
+                record_segment: record_segment.clone(),
+                blockfile_provider: self.blockfile_provider.clone(),
+                allowed_ids: Arc::new([]),
+                allowed_ids_hnsw: Arc::new([]),
+                logs: Chunk::new(Vec::new().into()),
+            };
+            let start = Instant::now();
+            let hnsw_output = operator.run(&input).await?;
+            hnsw_latencies.push(start.elapsed());
+
+            input.search_options = brute_force_options.clone();
+            let start = Instant::now();
+            let exact_output = operator.run(&input).await?;
+            brute_force_latencies.push(start.elapsed());
+
+            total_recall += recall(&hnsw_output.offset_ids, &exact_output.offset_ids);
+        }
+
+        let num_queries = query_vectors.len();
+        Ok(HnswEvaluationReport {
+            num_queries,
+            k,
+            recall: match num_queries {
+                0 => 0.0,
+                _ => total_recall / num_queries as f32,
+            },
+            hnsw_latency: LatencyPercentiles::from_latencies(hnsw_latencies),
+            brute_force_latency: LatencyPercentiles::from_latencies(brute_force_latencies),
+            num_elements: hnsw_segment_reader.len(),
+            num_deleted: hnsw_segment_reader.deleted_count(),
+        })
+    }
+}
+
+// The fraction of the exact results that were also returned by the index. A query
+// with no exact results is trivially answered in full.
+fn recall(results: &[usize], exact_results: &[usize]) -> f32 {
+    if exact_results.is_empty() {
+        return 1.0;
+    }
+    let exact_results: HashSet<&usize> = exact_results.iter().collect();
+    let found = results
+        .iter()
+        .filter(|offset_id| exact_results.contains(offset_id))
+        .count();
+    found as f32 / exact_results.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockstore::arrow::provider::ArrowBlockfileProvider;
+    use crate::segment::distributed_hnsw_segment::DistributedHNSWSegmentWriter;
+    use crate::segment::record_segment::RecordSegmentWriter;
+    use crate::segment::{LogMaterializer, SegmentFlusher, SegmentWriter};
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::sysdb::test_sysdb::TestSysDb;
+    use crate::types::{
+        Collection, LogRecord, Operation, OperationRecord, Segment, SegmentScope, SegmentType,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_recall() {
+        assert_eq!(recall(&[1, 2, 3, 4], &[1, 2, 3, 5]), 0.75);
+        assert_eq!(recall(&[], &[]), 1.0);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let latencies = (1..=100).map(Duration::from_millis).collect();
+        let percentiles = LatencyPercentiles::from_latencies(latencies);
+        assert_eq!(percentiles.p50, Duration::from_millis(50));
+        assert_eq!(percentiles.p90, Duration::from_millis(90));
+        assert_eq!(percentiles.p99, Duration::from_millis(99));
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_sampled_queries() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage.clone()));
+        let hnsw_index_provider =
+            HnswIndexProvider::new(storage, tmp_dir.path().join("hnsw"), 1024 * 1024 * 1024);
+        let collection_id = Uuid::new_v4();
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(collection_id),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut hnsw_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::HnswDistributed,
+            scope: SegmentScope::VECTOR,
+            collection: Some(collection_id),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+
+        let logs: Vec<LogRecord> = (0..50)
+            .map(|i| LogRecord {
+                log_offset: i + 1,
+                record: OperationRecord {
+                    id: format!("embedding_id_{}", i),
+                    embedding: Some(vec![i as f32, (i % 7) as f32, 1.0]),
+                    encoding: None,
+                    sparse_vector: None,
+                    metadata: None,
+                    document: None,
+                    operation: Operation::Add,
+                },
+            })
+            .collect();
+        let record_segment_writer =
+            RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                .await
+                .unwrap();
+        let hnsw_segment_writer = DistributedHNSWSegmentWriter::from_segment(
+            &hnsw_segment,
+            3,
+            hnsw_index_provider.clone(),
+        )
+        .await
+        .unwrap();
+        let materializer = LogMaterializer::new(None, Chunk::new(logs.into()), None);
+        let records = materializer.materialize().await.unwrap();
+        record_segment_writer
+            .apply_materialized_log_chunk(records.clone())
+            .await
+            .unwrap();
+        hnsw_segment_writer
+            .apply_materialized_log_chunk(records)
+            .await
+            .unwrap();
+        record_segment.file_path = record_segment_writer
+            .commit()
+            .unwrap()
+            .flush()
+            .await
+            .unwrap();
+        hnsw_segment.file_path = hnsw_segment_writer.commit().unwrap().flush().await.unwrap();
+
+        let mut sysdb = TestSysDb::new();
+        sysdb.add_collection(Collection {
+            id: collection_id,
+            name: "test".to_string(),
+            metadata: None,
+            dimension: Some(3),
+            tenant: "tenant".to_string(),
+            database: "database".to_string(),
+            log_position: 0,
+            version: 0,
+        });
+        sysdb.add_segment(record_segment);
+        sysdb.add_segment(hnsw_segment.clone());
+
+        let evaluator = HnswSegmentEvaluator::new(
+            Box::new(SysDb::Test(sysdb)),
+            hnsw_index_provider,
+            blockfile_provider,
+        );
+        let report = evaluator
+            .evaluate(
+                &hnsw_segment.id,
+                EvaluationQueries::Sample(10),
+                5,
+                SearchOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.num_queries, 10);
+        assert_eq!(report.k, 5);
+        assert_eq!(report.num_elements, 50);
+        assert_eq!(report.num_deleted, 0);
+        assert!(report.recall > 0.9);
+
+        let res = evaluator
+            .evaluate(
+                &hnsw_segment.id,
+                EvaluationQueries::Vectors(vec![vec![1.0, 2.0]]),
+                5,
+                SearchOptions::default(),
+            )
+            .await;
+        assert!(res.is_err());
+    }
+}
@@ -15,20 +15,23 @@ use crate::execution::operators::brute_force_knn::{
 use crate::execution::operators::hnsw_knn::{
     HnswKnnOperator, HnswKnnOperatorInput, HnswKnnOperatorOutput,
 };
+use crate::execution::operators::ivf_knn::{
+    IvfKnnOperator, IvfKnnOperatorInput, IvfKnnOperatorOutput,
+};
 use crate::execution::operators::merge_knn_results::{
     MergeKnnResultsOperator, MergeKnnResultsOperatorInput, MergeKnnResultsOperatorOutput,
 };
-use crate::execution::operators::normalize_vectors::normalize;
+use crate::execution::operators::normalize_vectors::{normalize, normalize_multi_vector};
 use crate::execution::operators::pull_log::PullLogsOutput;
 use crate::index::hnsw_provider::HnswIndexProvider;
-use crate::index::IndexConfig;
+use crate::index::{is_valid_multi_vector, IndexConfig};
 use crate::log::log::PullLogsError;
 use crate::segment::distributed_hnsw_segment::{
     DistributedHNSWSegmentFromSegmentError, DistributedHNSWSegmentReader,
 };
 use crate::sysdb::sysdb::{GetCollectionsError, GetSegmentsError, SysDb};
 use crate::system::{ComponentContext, System};
-use crate::types::{Collection, LogRecord, Segment, SegmentType, VectorQueryResult};
+use crate::types::{Collection, LogRecord, SearchOptions, Segment, SegmentType, VectorQueryResult};
 use crate::{
     log::log::Log,
     system::{Component, Handler, Receiver},
@@ -83,6 +86,10 @@ enum HnswSegmentQueryError {
     HnswSegmentHasNoCollection,
     #[error("Collection has no dimension set")]
     CollectionHasNoDimension,
+    #[error(
+        "Multi-vector query of {0} values is not a whole number of vectors of dimensionality {1}"
+    )]
+    InvalidMultiVectorQuery(usize, usize),
 }
 
 impl ChromaError for HnswSegmentQueryError {
@@ -95,6 +102,7 @@ impl ChromaError for HnswSegmentQueryError {
             HnswSegmentQueryError::RecordSegmentNotFound(_) => ErrorCodes::NotFound,
             HnswSegmentQueryError::HnswSegmentHasNoCollection => ErrorCodes::InvalidArgument,
             HnswSegmentQueryError::CollectionHasNoDimension => ErrorCodes::InvalidArgument,
+            HnswSegmentQueryError::InvalidMultiVectorQuery(_, _) => ErrorCodes::InvalidArgument,
         }
     }
 }
@@ -112,6 +120,7 @@ pub(crate) struct HnswQueryOrchestrator {
     allowed_ids_brute_force: Arc<[String]>,
     include_embeddings: bool,
     hnsw_segment_id: Uuid,
+    search_options: SearchOptions,
     // State fetched or created for query execution
     hnsw_segment: Option<Segment>,
     record_segment: Option<Segment>,
@@ -152,6 +161,7 @@ impl HnswQueryOrchestrator {
         allowed_ids: Vec<String>,
         include_embeddings: bool,
         segment_id: Uuid,
+        search_options: SearchOptions,
         log: Box<Log>,
         sysdb: Box<SysDb>,
         hnsw_index_provider: HnswIndexProvider,
@@ -167,10 +177,11 @@ impl HnswQueryOrchestrator {
         // pre-allocate the result vectors
         let results = Some(Vec::with_capacity(query_vectors.len()));
         tracing::info!(
-            "Performing KNN for k = {}, allowed_ids = {:?}, num query vectors = {:?}",
+            "Performing KNN for k = {}, allowed_ids = {:?}, num query vectors = {:?}, search options = {:?}",
             k,
             allowed_ids,
-            query_vectors.len()
+            query_vectors.len(),
+            search_options
         );
 
         HnswQueryOrchestrator {
@@ -185,6 +196,7 @@ impl HnswQueryOrchestrator {
             allowed_ids_hnsw_segment: Arc::new([]),
             include_embeddings,
             hnsw_segment_id: segment_id,
+            search_options,
             hnsw_segment: None,
             record_segment: None,
             collection: None,
@@ -255,11 +267,15 @@ impl HnswQueryOrchestrator {
         >,
     ) {
         self.state = ExecutionState::QueryKnn;
-        let distance_function = &self
+        let index_config = self
             .index_config
             .as_ref()
-            .expect("Invariant violation. Index config is not set")
-            .distance_function;
+            .expect("Invariant violation. Index config is not set");
+        let distance_function = &index_config.distance_function;
+        let multi_vector_dimensionality = match index_config.multi_vector {
+            true => Some(index_config.dimensionality as usize),
+            false => None,
+        };
 
         // TODO: We shouldn't have to clone query vectors here. We should be able to pass a Arc<[f32]>-like to the input
         for (i, query_vector) in self.query_vectors.iter().enumerate() {
@@ -268,6 +284,7 @@ impl HnswQueryOrchestrator {
                 query: query_vector.clone(),
                 k: self.k as usize,
                 distance_metric: distance_function.clone(),
+                multi_vector_dimensionality,
                 allowed_ids: self.allowed_ids.clone(),
                 allowed_ids_brute_force: self.allowed_ids_brute_force.clone(),
                 record_segment_definition: self
@@ -290,6 +307,52 @@ impl HnswQueryOrchestrator {
         }
     }
 
+    // The results of an ivf segment query take the place of the hnsw results
+    async fn ivf_segment_query(&mut self, logs: Chunk<LogRecord>, ctx: &ComponentContext<Self>) {
+        self.state = ExecutionState::QueryKnn;
+
+        let ivf_segment = self
+            .hnsw_segment
+            .as_ref()
+            .expect("Invariant violation. IVF Segment is not set");
+        let dimensionality = self
+            .collection
+            .as_ref()
+            .expect("Invariant violation. Collection is not set")
+            .dimension
+            .expect("Invariant violation. Collection dimension is not set");
+        let record_segment = self
+            .record_segment
+            .as_ref()
+            .expect("Invariant violation. Record Segment is not set");
+
+        // Dispatch a query task per query vector
+        for (i, query_vector) in self.query_vectors.iter().enumerate() {
+            let operator = Box::new(IvfKnnOperator {});
+            let input = IvfKnnOperatorInput {
+                segment: ivf_segment.clone(),
+                dimensionality: dimensionality as usize,
+                query: query_vector.clone(),
+                k: self.k as usize,
+                search_options: self.search_options.clone(),
+                record_segment: record_segment.clone(),
+                blockfile_provider: self.blockfile_provider.clone(),
+                allowed_ids: self.allowed_ids.clone(),
+                allowed_ids_ivf: self.allowed_ids_hnsw_segment.clone(),
+                logs: logs.clone(),
+            };
+            let task = wrap(operator, input, ctx.sender.as_receiver());
+            self.hnsw_task_id_to_query_index.insert(task.id(), i);
+            match self.dispatcher.send(task, Some(Span::current())).await {
+                Ok(_) => (),
+                Err(e) => {
+                    // Log an error
+                    println!("Error sending IVF KNN task: {:?}", e);
+                }
+            }
+        }
+    }
+
     async fn hnsw_segment_query(&mut self, logs: Chunk<LogRecord>, ctx: &ComponentContext<Self>) {
         self.state = ExecutionState::QueryKnn;
 
@@ -342,6 +405,12 @@ impl HnswQueryOrchestrator {
             .as_ref()
             .expect("Invariant violation. Record Segment is not set");
 
+        let distance_function = &self
+            .index_config
+            .as_ref()
+            .expect("Invariant violation. Index config is not set")
+            .distance_function;
+
         // Dispatch a query task per query vector
         for (i, query_vector) in self.query_vectors.iter().enumerate() {
             let operator = Box::new(HnswKnnOperator {});
@@ -349,6 +418,8 @@ impl HnswQueryOrchestrator {
                 segment: hnsw_segment_reader.clone(),
                 query: query_vector.clone(),
                 k: self.k as usize,
+                distance_function: distance_function.clone(),
+                search_options: self.search_options.clone(),
                 record_segment: record_segment.clone(),
                 blockfile_provider: self.blockfile_provider.clone(),
                 allowed_ids: self.allowed_ids.clone(),
@@ -557,13 +628,34 @@ impl Component for HnswQueryOrchestrator {
 
         match IndexConfig::from_segment(&hnsw_segment, collection.dimension.unwrap()) {
             Ok(index_config) => {
+                // Queries of multi-vector collections are bags of whole vectors
+                let dimensionality = index_config.dimensionality as usize;
+                if let Some(query_vector) = self.query_vectors.iter().find(|query_vector| {
+                    index_config.multi_vector
+                        && !is_valid_multi_vector(query_vector.len(), dimensionality)
+                }) {
+                    self.terminate_with_error(
+                        Box::new(HnswSegmentQueryError::InvalidMultiVectorQuery(
+                            query_vector.len(),
+                            dimensionality,
+                        )),
+                        ctx,
+                    );
+                    return;
+                }
                 self.index_config = Some(index_config);
 
                 // Normalize the query vectors if we are using the cosine similarity
-                if self.index_config.as_ref().unwrap().distance_function == DistanceFunction::Cosine
-                {
+                let index_config = self.index_config.as_ref().unwrap();
+                if index_config.distance_function == DistanceFunction::Cosine {
                     for query_vector in self.query_vectors.iter_mut() {
-                        *query_vector = normalize(query_vector);
+                        *query_vector = match index_config.multi_vector {
+                            true => normalize_multi_vector(
+                                query_vector,
+                                index_config.dimensionality as usize,
+                            ),
+                            false => normalize(query_vector),
+                        };
                     }
                 }
             }
@@ -617,7 +709,14 @@ impl Handler<TaskResult<PullLogsOutput, PullLogsError>> for HnswQueryOrchestrato
                 self.allowed_ids_hnsw_segment = allowed_ids_hnsw.into();
                 self.brute_force_query(logs.clone(), ctx.sender.as_receiver())
                     .await;
-                self.hnsw_segment_query(logs, ctx).await;
+                let is_ivf_segment = self
+                    .hnsw_segment
+                    .as_ref()
+                    .is_some_and(|segment| segment.r#type == SegmentType::BlockfileIvf);
+                match is_ivf_segment {
+                    true => self.ivf_segment_query(logs, ctx).await,
+                    false => self.hnsw_segment_query(logs, ctx).await,
+                }
             }
             Err(e) => {
                 self.terminate_with_error(Box::new(e), ctx);
@@ -704,6 +803,39 @@ impl Handler<TaskResult<HnswKnnOperatorOutput, Box<dyn ChromaError>>> for HnswQu
     }
 }
 
+#[async_trait]
+impl Handler<TaskResult<IvfKnnOperatorOutput, Box<dyn ChromaError>>> for HnswQueryOrchestrator {
+    async fn handle(
+        &mut self,
+        message: TaskResult<IvfKnnOperatorOutput, Box<dyn ChromaError>>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        let task_id = message.id();
+        let message = message.into_inner();
+        let query_index = self
+            .hnsw_task_id_to_query_index
+            .remove(&task_id)
+            .expect("Invariant violation. IVF task id is not set for query vector index");
+        match message {
+            Ok(output) => {
+                self.hnsw_result_offset_ids
+                    .insert(query_index, output.offset_ids);
+                self.hnsw_result_distances
+                    .insert(query_index, output.distances);
+            }
+            Err(e) => {
+                self.terminate_with_error(e, ctx);
+            }
+        }
+
+        self.merge_dependency_count -= 1;
+
+        if self.merge_dependency_count == 0 {
+            self.merge_results(ctx).await;
+        }
+    }
+}
+
 #[async_trait]
 impl Handler<TaskResult<MergeKnnResultsOperatorOutput, Box<dyn ChromaError>>>
     for HnswQueryOrchestrator
@@ -1,6 +1,10 @@
 use crate::errors::{ChromaError, ErrorCodes};
 use crate::execution::data::data_chunk::Chunk;
 use crate::execution::operator::{wrap, TaskResult};
+use crate::execution::operators::aggregate_metadata::{
+    AggregateMetadataError, AggregateMetadataInput, AggregateMetadataOperator,
+    AggregateMetadataOutput,
+};
 use crate::execution::operators::count_records::{
     CountRecordsError, CountRecordsInput, CountRecordsOperator, CountRecordsOutput,
 };
@@ -13,13 +17,16 @@ use crate::execution::operators::metadata_filtering::{
     MetadataFilteringOutput,
 };
 use crate::execution::operators::pull_log::{PullLogsInput, PullLogsOperator, PullLogsOutput};
+use crate::index::fulltext::types::contains_queries;
 use crate::index::metadata::types::MetadataIndexError;
 use crate::log::log::PullLogsError;
 use crate::segment::metadata_segment::MetadataSegmentReader;
 use crate::sysdb::sysdb::{GetCollectionsError, GetSegmentsError};
 use crate::system::{Component, ComponentContext, Handler};
-use crate::types::{Collection, LogRecord, Metadata, SegmentType};
-use crate::types::{Where, WhereDocument};
+use crate::types::{
+    Collection, DocumentHighlight, LogRecord, Metadata, MetadataAggregate, SegmentType,
+};
+use crate::types::{OrderBy, Where, WhereDocument};
 use crate::{
     blockstore::provider::BlockfileProvider,
     execution::operator::TaskMessage,
@@ -43,9 +50,16 @@ enum ExecutionState {
     MergeResults,
 }
 
-// Returns the ids, metadata, and documents
-type MetadataQueryOrchestratorResult =
-    Result<(Vec<String>, Vec<Option<Metadata>>, Vec<Option<String>>), Box<dyn ChromaError>>;
+// Returns the ids, metadata, documents, and highlights of the documents
+type MetadataQueryOrchestratorResult = Result<
+    (
+        Vec<String>,
+        Vec<Option<Metadata>>,
+        Vec<Option<String>>,
+        Vec<Option<DocumentHighlight>>,
+    ),
+    Box<dyn ChromaError>,
+>;
 
 #[derive(Debug)]
 pub(crate) struct MetadataQueryOrchestrator {
@@ -69,6 +83,10 @@ pub(crate) struct MetadataQueryOrchestrator {
     // Query params
     where_clause: Option<Where>,
     where_document_clause: Option<WhereDocument>,
+    limit: Option<u32>,
+    offset: u32,
+    order_by: Option<OrderBy>,
+    include_snippets: bool,
     // Result channel
     result_channel: Option<tokio::sync::oneshot::Sender<MetadataQueryOrchestratorResult>>,
 }
@@ -81,12 +99,16 @@ pub(crate) struct CountQueryOrchestrator {
     metadata_segment_id: Uuid,
     // State fetched or created for query execution
     record_segment: Option<Segment>,
+    metadata_segment: Option<Segment>,
     collection: Option<Collection>,
     // Services
     log: Box<Log>,
     sysdb: Box<SysDb>,
     dispatcher: Box<dyn Receiver<TaskMessage>>,
     blockfile_provider: BlockfileProvider,
+    // Query params
+    where_clause: Option<Where>,
+    where_document_clause: Option<WhereDocument>,
     // Result channel
     result_channel: Option<tokio::sync::oneshot::Sender<Result<usize, Box<dyn ChromaError>>>>,
 }
@@ -125,7 +147,94 @@ impl ChromaError for MetadataSegmentQueryError {
     }
 }
 
+async fn get_metadata_segment_from_id(
+    mut sysdb: Box<SysDb>,
+    metadata_segment_id: &Uuid,
+) -> Result<Segment, Box<dyn ChromaError>> {
+    let segments = sysdb
+        .get_segments(Some(*metadata_segment_id), None, None, None)
+        .await;
+    let segment = match segments {
+        Ok(segments) => {
+            if segments.is_empty() {
+                return Err(Box::new(
+                    MetadataSegmentQueryError::BlockfileMetadataSegmentNotFound(
+                        *metadata_segment_id,
+                    ),
+                ));
+            }
+            segments[0].clone()
+        }
+        Err(e) => {
+            return Err(Box::new(MetadataSegmentQueryError::GetSegmentsError(e)));
+        }
+    };
+
+    if segment.r#type != SegmentType::BlockfileMetadata {
+        return Err(Box::new(
+            MetadataSegmentQueryError::BlockfileMetadataSegmentNotFound(*metadata_segment_id),
+        ));
+    }
+    Ok(segment)
+}
+
+async fn get_record_segment_from_collection_id(
+    mut sysdb: Box<SysDb>,
+    collection_id: &Uuid,
+) -> Result<Segment, Box<dyn ChromaError>> {
+    let segments = sysdb
+        .get_segments(
+            None,
+            Some(SegmentType::BlockfileRecord.into()),
+            None,
+            Some(*collection_id),
+        )
+        .await;
+
+    match segments {
+        Ok(segments) => {
+            if segments.is_empty() {
+                return Err(Box::new(MetadataSegmentQueryError::RecordSegmentNotFound(
+                    *collection_id,
+                )));
+            }
+            // Unwrap is safe as we know at least one segment exists from
+            // the check above
+            return Ok(segments.into_iter().next().unwrap());
+        }
+        Err(e) => {
+            return Err(Box::new(MetadataSegmentQueryError::GetSegmentsError(e)));
+        }
+    };
+}
+
+async fn get_collection_from_id(
+    mut sysdb: Box<SysDb>,
+    collection_id: &Uuid,
+) -> Result<Collection, Box<dyn ChromaError>> {
+    let collections = sysdb
+        .get_collections(Some(*collection_id), None, None, None)
+        .await;
+
+    match collections {
+        Ok(collections) => {
+            if collections.is_empty() {
+                return Err(Box::new(MetadataSegmentQueryError::CollectionNotFound(
+                    *collection_id,
+                )));
+            }
+            // Unwrap is safe as we know at least one collection exists from
+            // the check above
+            return Ok(collections.into_iter().next().unwrap());
+        }
+        Err(e) => {
+            return Err(Box::new(MetadataSegmentQueryError::GetCollectionError(e)));
+        }
+    };
+}
+
 impl CountQueryOrchestrator {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         system: System,
         metadata_segment_id: &Uuid,
@@ -133,16 +242,21 @@ impl CountQueryOrchestrator {
         sysdb: Box<SysDb>,
         dispatcher: Box<dyn Receiver<TaskMessage>>,
         blockfile_provider: BlockfileProvider,
+        where_clause: Option<Where>,
+        where_document_clause: Option<WhereDocument>,
     ) -> Self {
         Self {
             system,
             metadata_segment_id: *metadata_segment_id,
             record_segment: None,
+            metadata_segment: None,
             collection: None,
             log,
             sysdb,
             dispatcher,
             blockfile_provider,
+            where_clause,
+            where_document_clause,
             result_channel: None,
         }
     }
@@ -150,9 +264,8 @@ impl CountQueryOrchestrator {
     async fn start(&mut self, ctx: &ComponentContext<Self>) {
         println!("Starting Count Query Orchestrator");
         // Populate the orchestrator with the initial state - The Record Segment and the Collection
-        let metdata_segment = self
-            .get_metadata_segment_from_id(self.sysdb.clone(), &self.metadata_segment_id)
-            .await;
+        let metdata_segment =
+            get_metadata_segment_from_id(self.sysdb.clone(), &self.metadata_segment_id).await;
 
         let metadata_segment = match metdata_segment {
             Ok(segment) => segment,
@@ -175,9 +288,8 @@ impl CountQueryOrchestrator {
             }
         };
 
-        let record_segment = self
-            .get_record_segment_from_collection_id(self.sysdb.clone(), &collection_id)
-            .await;
+        let record_segment =
+            get_record_segment_from_collection_id(self.sysdb.clone(), &collection_id).await;
 
         let record_segment = match record_segment {
             Ok(segment) => segment,
@@ -188,10 +300,7 @@ impl CountQueryOrchestrator {
             }
         };
 
-        let collection = match self
-            .get_collection_from_id(self.sysdb.clone(), &collection_id, ctx)
-            .await
-        {
+        let collection = match get_collection_from_id(self.sysdb.clone(), &collection_id).await {
             Ok(collection) => collection,
             Err(e) => {
                 tracing::error!("Error getting collection: {:?}", e);
@@ -201,6 +310,7 @@ impl CountQueryOrchestrator {
         };
 
         self.record_segment = Some(record_segment);
+        self.metadata_segment = Some(metadata_segment);
         self.collection = Some(collection);
     }
 
@@ -245,94 +355,35 @@ impl CountQueryOrchestrator {
         }
     }
 
-    async fn get_metadata_segment_from_id(
-        &self,
-        mut sysdb: Box<SysDb>,
-        metadata_segment_id: &Uuid,
-    ) -> Result<Segment, Box<dyn ChromaError>> {
-        let segments = sysdb
-            .get_segments(Some(*metadata_segment_id), None, None, None)
-            .await;
-        let segment = match segments {
-            Ok(segments) => {
-                if segments.is_empty() {
-                    return Err(Box::new(
-                        MetadataSegmentQueryError::BlockfileMetadataSegmentNotFound(
-                            *metadata_segment_id,
-                        ),
-                    ));
-                }
-                segments[0].clone()
-            }
-            Err(e) => {
-                return Err(Box::new(MetadataSegmentQueryError::GetSegmentsError(e)));
-            }
-        };
+    async fn filter(&mut self, logs: Chunk<LogRecord>, ctx: &ComponentContext<Self>) {
+        tracing::debug!("Filtering logs and searching metadata segment");
 
-        if segment.r#type != SegmentType::BlockfileMetadata {
-            return Err(Box::new(
-                MetadataSegmentQueryError::BlockfileMetadataSegmentNotFound(*metadata_segment_id),
-            ));
-        }
-        Ok(segment)
-    }
-
-    async fn get_record_segment_from_collection_id(
-        &self,
-        mut sysdb: Box<SysDb>,
-        collection_id: &Uuid,
-    ) -> Result<Segment, Box<dyn ChromaError>> {
-        let segments = sysdb
-            .get_segments(
-                None,
-                Some(SegmentType::BlockfileRecord.into()),
-                None,
-                Some(*collection_id),
-            )
-            .await;
-
-        match segments {
-            Ok(segments) => {
-                if segments.is_empty() {
-                    return Err(Box::new(MetadataSegmentQueryError::RecordSegmentNotFound(
-                        *collection_id,
-                    )));
-                }
-                // Unwrap is safe as we know at least one segment exists from
-                // the check above
-                return Ok(segments.into_iter().next().unwrap());
-            }
-            Err(e) => {
-                return Err(Box::new(MetadataSegmentQueryError::GetSegmentsError(e)));
-            }
-        };
-    }
+        let input = MetadataFilteringInput::new(
+            logs,
+            self.record_segment
+                .as_ref()
+                .expect("Expected record segment to be set")
+                .clone(),
+            self.metadata_segment
+                .as_ref()
+                .expect("Expected metadata segment to be set")
+                .clone(),
+            self.blockfile_provider.clone(),
+            self.where_clause.clone(),
+            self.where_document_clause.clone(),
+            None,
+        );
 
-    async fn get_collection_from_id(
-        &self,
-        mut sysdb: Box<SysDb>,
-        collection_id: &Uuid,
-        ctx: &ComponentContext<Self>,
-    ) -> Result<Collection, Box<dyn ChromaError>> {
-        let collections = sysdb
-            .get_collections(Some(*collection_id), None, None, None)
-            .await;
-
-        match collections {
-            Ok(collections) => {
-                if collections.is_empty() {
-                    return Err(Box::new(MetadataSegmentQueryError::CollectionNotFound(
-                        *collection_id,
-                    )));
-                }
-                // Unwrap is safe as we know at least one collection exists from
-                // the check above
-                return Ok(collections.into_iter().next().unwrap());
-            }
+        let op = MetadataFilteringOperator::new();
+        let task = wrap(op, input, ctx.sender.as_receiver());
+        match self.dispatcher.send(task, Some(Span::current())).await {
+            Ok(_) => (),
             Err(e) => {
-                return Err(Box::new(MetadataSegmentQueryError::GetCollectionError(e)));
+                // Log an error - this implies the dispatcher was dropped somehow
+                // and is likely fatal
+                println!("Error sending Count Query task: {:?}", e);
             }
-        };
+        }
     }
 
     fn terminate_with_error(&mut self, error: Box<dyn ChromaError>, ctx: &ComponentContext<Self>) {
@@ -391,6 +442,12 @@ impl Handler<TaskResult<PullLogsOutput, PullLogsError>> for CountQueryOrchestrat
         let message = message.into_inner();
         match message {
             Ok(logs) => {
+                // A filtered count is the number of offset ids that match the
+                // filters, the records themselves are never read.
+                if self.where_clause.is_some() || self.where_document_clause.is_some() {
+                    self.filter(logs.logs(), ctx).await;
+                    return;
+                }
                 let operator = CountRecordsOperator::new();
                 let input = CountRecordsInput::new(
                     self.record_segment
@@ -445,7 +502,41 @@ impl Handler<TaskResult<CountRecordsOutput, CountRecordsError>> for CountQueryOr
     }
 }
 
+#[async_trait]
+impl Handler<TaskResult<MetadataFilteringOutput, MetadataFilteringError>>
+    for CountQueryOrchestrator
+{
+    async fn handle(
+        &mut self,
+        message: TaskResult<MetadataFilteringOutput, MetadataFilteringError>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        let message = message.into_inner();
+        let output = match message {
+            Ok(output) => output,
+            Err(e) => {
+                return self.terminate_with_error(Box::new(e), ctx);
+            }
+        };
+        let count = output
+            .where_condition_filtered_offset_ids
+            .map_or(0, |offset_ids| offset_ids.len());
+        let channel = self
+            .result_channel
+            .take()
+            .expect("Expect channel to be present");
+        match channel.send(Ok(count)) {
+            Ok(_) => (),
+            Err(_) => {
+                // Log an error - this implied the listener was dropped
+                println!("[CountQueryOrchestrator] Result channel dropped before sending result");
+            }
+        }
+    }
+}
+
 impl MetadataQueryOrchestrator {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         system: System,
         metadata_segment_id: &Uuid,
@@ -456,6 +547,10 @@ impl MetadataQueryOrchestrator {
         blockfile_provider: BlockfileProvider,
         where_clause: Option<Where>,
         where_document_clause: Option<WhereDocument>,
+        limit: Option<u32>,
+        offset: u32,
+        order_by: Option<OrderBy>,
+        include_snippets: bool,
     ) -> Self {
         Self {
             state: ExecutionState::Pending,
@@ -472,6 +567,10 @@ impl MetadataQueryOrchestrator {
             blockfile_provider,
             where_clause,
             where_document_clause,
+            limit,
+            offset,
+            order_by,
+            include_snippets,
             result_channel: None,
         }
     }
@@ -479,9 +578,8 @@ impl MetadataQueryOrchestrator {
     async fn start(&mut self, ctx: &ComponentContext<Self>) {
         tracing::info!("Starting Metadata Query Orchestrator");
         // Populate the orchestrator with the initial state - The Metadata Segment, The Record Segment and the Collection
-        let metdata_segment = self
-            .get_metadata_segment_from_id(self.sysdb.clone(), &self.metadata_segment_id)
-            .await;
+        let metdata_segment =
+            get_metadata_segment_from_id(self.sysdb.clone(), &self.metadata_segment_id).await;
 
         let metadata_segment = match metdata_segment {
             Ok(segment) => segment,
@@ -503,9 +601,8 @@ impl MetadataQueryOrchestrator {
         };
         self.metadata_segment = Some(metadata_segment);
 
-        let record_segment = self
-            .get_record_segment_from_collection_id(self.sysdb.clone(), &collection_id)
-            .await;
+        let record_segment =
+            get_record_segment_from_collection_id(self.sysdb.clone(), &collection_id).await;
 
         let record_segment = match record_segment {
             Ok(segment) => segment,
@@ -515,10 +612,7 @@ impl MetadataQueryOrchestrator {
             }
         };
 
-        let collection = match self
-            .get_collection_from_id(self.sysdb.clone(), &collection_id, ctx)
-            .await
-        {
+        let collection = match get_collection_from_id(self.sysdb.clone(), &collection_id).await {
             Ok(collection) => collection,
             Err(e) => {
                 self.terminate_with_error(e, ctx);
@@ -603,96 +697,6 @@ impl MetadataQueryOrchestrator {
         }
     }
 
-    async fn get_metadata_segment_from_id(
-        &self,
-        mut sysdb: Box<SysDb>,
-        metadata_segment_id: &Uuid,
-    ) -> Result<Segment, Box<dyn ChromaError>> {
-        let segments = sysdb
-            .get_segments(Some(*metadata_segment_id), None, None, None)
-            .await;
-        let segment = match segments {
-            Ok(segments) => {
-                if segments.is_empty() {
-                    return Err(Box::new(
-                        MetadataSegmentQueryError::BlockfileMetadataSegmentNotFound(
-                            *metadata_segment_id,
-                        ),
-                    ));
-                }
-                segments[0].clone()
-            }
-            Err(e) => {
-                return Err(Box::new(MetadataSegmentQueryError::GetSegmentsError(e)));
-            }
-        };
-
-        if segment.r#type != SegmentType::BlockfileMetadata {
-            return Err(Box::new(
-                MetadataSegmentQueryError::BlockfileMetadataSegmentNotFound(*metadata_segment_id),
-            ));
-        }
-        Ok(segment)
-    }
-
-    async fn get_record_segment_from_collection_id(
-        &self,
-        mut sysdb: Box<SysDb>,
-        collection_id: &Uuid,
-    ) -> Result<Segment, Box<dyn ChromaError>> {
-        let segments = sysdb
-            .get_segments(
-                None,
-                Some(SegmentType::BlockfileRecord.into()),
-                None,
-                Some(*collection_id),
-            )
-            .await;
-
-        match segments {
-            Ok(segments) => {
-                if segments.is_empty() {
-                    return Err(Box::new(MetadataSegmentQueryError::RecordSegmentNotFound(
-                        *collection_id,
-                    )));
-                }
-                // Unwrap is safe as we know at least one segment exists from
-                // the check above
-                return Ok(segments.into_iter().next().unwrap());
-            }
-            Err(e) => {
-                return Err(Box::new(MetadataSegmentQueryError::GetSegmentsError(e)));
-            }
-        };
-    }
-
-    async fn get_collection_from_id(
-        &self,
-        mut sysdb: Box<SysDb>,
-        collection_id: &Uuid,
-        ctx: &ComponentContext<Self>,
-    ) -> Result<Collection, Box<dyn ChromaError>> {
-        let collections = sysdb
-            .get_collections(Some(*collection_id), None, None, None)
-            .await;
-
-        match collections {
-            Ok(collections) => {
-                if collections.is_empty() {
-                    return Err(Box::new(MetadataSegmentQueryError::CollectionNotFound(
-                        *collection_id,
-                    )));
-                }
-                // Unwrap is safe as we know at least one collection exists from
-                // the check above
-                return Ok(collections.into_iter().next().unwrap());
-            }
-            Err(e) => {
-                return Err(Box::new(MetadataSegmentQueryError::GetCollectionError(e)));
-            }
-        };
-    }
-
     fn terminate_with_error(&mut self, error: Box<dyn ChromaError>, ctx: &ComponentContext<Self>) {
         let result_channel = self
             .result_channel
@@ -780,6 +784,12 @@ impl Handler<TaskResult<MetadataFilteringOutput, MetadataFilteringError>>
 
         self.state = ExecutionState::MergeResults;
 
+        let highlight_queries = match &self.where_document_clause {
+            Some(where_document_clause) if self.include_snippets => {
+                contains_queries(where_document_clause)
+            }
+            _ => vec![],
+        };
         let operator = MergeMetadataResultsOperator::new();
         let input = MergeMetadataResultsOperatorInput::new(
             output.log_records,
@@ -789,7 +799,15 @@ impl Handler<TaskResult<MetadataFilteringOutput, MetadataFilteringError>>
                 .as_ref()
                 .expect("Invariant violation. Record segment is not set.")
                 .clone(),
+            self.metadata_segment
+                .as_ref()
+                .expect("Invariant violation. Metadata segment is not set.")
+                .clone(),
             self.blockfile_provider.clone(),
+            self.limit,
+            self.offset,
+            self.order_by.clone(),
+            highlight_queries,
         );
 
         let task = wrap(operator, input, ctx.sender.as_receiver());
@@ -827,7 +845,12 @@ impl Handler<TaskResult<MergeMetadataResultsOperatorOutput, MergeMetadataResults
             .take()
             .expect("Invariant violation. Result channel is not set.");
 
-        let output = (output.ids, output.metadata, output.documents);
+        let output = (
+            output.ids,
+            output.metadata,
+            output.documents,
+            output.highlights,
+        );
         tracing::trace!("Merged metadata results: {:?}", output);
 
         match result_channel.send(Ok(output)) {
@@ -841,3 +864,652 @@ impl Handler<TaskResult<MergeMetadataResultsOperatorOutput, MergeMetadataResults
         }
     }
 }
+
+type AggregateQueryOrchestratorResult = Result<Vec<MetadataAggregate>, Box<dyn ChromaError>>;
+
+/// Aggregates the values of metadata keys over the records that match the
+/// where and where document clauses. The records are filtered like a metadata
+/// query, then the matching records of the segment are aggregated from the
+/// postings of the metadata indices and merged with the log.
+#[derive(Debug)]
+pub(crate) struct AggregateQueryOrchestrator {
+    // Component Execution
+    system: System,
+    // Query state
+    metadata_segment_id: Uuid,
+    // State fetched or created for query execution
+    record_segment: Option<Segment>,
+    metadata_segment: Option<Segment>,
+    collection: Option<Collection>,
+    // Services
+    log: Box<Log>,
+    sysdb: Box<SysDb>,
+    dispatcher: Box<dyn Receiver<TaskMessage>>,
+    blockfile_provider: BlockfileProvider,
+    // Query params
+    where_clause: Option<Where>,
+    where_document_clause: Option<WhereDocument>,
+    keys: Vec<String>,
+    histogram_buckets: u32,
+    // Result channel
+    result_channel: Option<tokio::sync::oneshot::Sender<AggregateQueryOrchestratorResult>>,
+}
+
+impl AggregateQueryOrchestrator {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        system: System,
+        metadata_segment_id: &Uuid,
+        log: Box<Log>,
+        sysdb: Box<SysDb>,
+        dispatcher: Box<dyn Receiver<TaskMessage>>,
+        blockfile_provider: BlockfileProvider,
+        where_clause: Option<Where>,
+        where_document_clause: Option<WhereDocument>,
+        keys: Vec<String>,
+        histogram_buckets: u32,
+    ) -> Self {
+        Self {
+            system,
+            metadata_segment_id: *metadata_segment_id,
+            record_segment: None,
+            metadata_segment: None,
+            collection: None,
+            log,
+            sysdb,
+            dispatcher,
+            blockfile_provider,
+            where_clause,
+            where_document_clause,
+            keys,
+            histogram_buckets,
+            result_channel: None,
+        }
+    }
+
+    async fn start(&mut self, ctx: &ComponentContext<Self>) {
+        tracing::info!("Starting Aggregate Query Orchestrator");
+        // Populate the orchestrator with the initial state - The Metadata Segment, The Record Segment and the Collection
+        let metadata_segment =
+            match get_metadata_segment_from_id(self.sysdb.clone(), &self.metadata_segment_id).await
+            {
+                Ok(segment) => segment,
+                Err(e) => {
+                    self.terminate_with_error(e, ctx);
+                    return;
+                }
+            };
+
+        let collection_id = match metadata_segment.collection {
+            Some(collection_id) => collection_id,
+            None => {
+                self.terminate_with_error(
+                    Box::new(MetadataSegmentQueryError::MetadataSegmentHasNoCollection),
+                    ctx,
+                );
+                return;
+            }
+        };
+        self.metadata_segment = Some(metadata_segment);
+
+        let record_segment =
+            match get_record_segment_from_collection_id(self.sysdb.clone(), &collection_id).await {
+                Ok(segment) => segment,
+                Err(e) => {
+                    self.terminate_with_error(e, ctx);
+                    return;
+                }
+            };
+
+        let collection = match get_collection_from_id(self.sysdb.clone(), &collection_id).await {
+            Ok(collection) => collection,
+            Err(e) => {
+                self.terminate_with_error(e, ctx);
+                return;
+            }
+        };
+
+        self.record_segment = Some(record_segment);
+        self.collection = Some(collection);
+    }
+
+    async fn pull_logs(&mut self, ctx: &ComponentContext<Self>) {
+        tracing::debug!("Aggregate query orchestrator pulling logs");
+
+        let operator = PullLogsOperator::new(self.log.clone());
+        let end_timestamp = SystemTime::now().duration_since(UNIX_EPOCH);
+        let end_timestamp = match end_timestamp {
+            Ok(end_timestamp) => end_timestamp.as_nanos() as i64,
+            Err(e) => {
+                self.terminate_with_error(
+                    Box::new(MetadataSegmentQueryError::SystemTimeError(e)),
+                    ctx,
+                );
+                return;
+            }
+        };
+
+        let collection = self
+            .collection
+            .as_ref()
+            .expect("Invariant violation. Collection is not set before pull logs state.");
+        let input = PullLogsInput::new(
+            collection.id,
+            // The collection log position is inclusive, and we want to start from the next log.
+            collection.log_position + 1,
+            100,
+            None,
+            Some(end_timestamp),
+        );
+
+        let task = wrap(operator, input, ctx.sender.as_receiver());
+        match self.dispatcher.send(task, Some(Span::current())).await {
+            Ok(_) => (),
+            Err(e) => {
+                // Log an error - this implies the dispatcher was dropped somehow
+                // and is likely fatal
+                tracing::error!("Error sending Aggregate Query task: {:?}", e);
+            }
+        }
+    }
+
+    async fn filter(&mut self, logs: Chunk<LogRecord>, ctx: &ComponentContext<Self>) {
+        tracing::debug!("Filtering logs and searching metadata segment");
+
+        let input = MetadataFilteringInput::new(
+            logs,
+            self.record_segment
+                .as_ref()
+                .expect("Expected record segment to be set")
+                .clone(),
+            self.metadata_segment
+                .as_ref()
+                .expect("Expected metadata segment to be set")
+                .clone(),
+            self.blockfile_provider.clone(),
+            self.where_clause.clone(),
+            self.where_document_clause.clone(),
+            None,
+        );
+
+        let op = MetadataFilteringOperator::new();
+        let task = wrap(op, input, ctx.sender.as_receiver());
+        match self.dispatcher.send(task, Some(Span::current())).await {
+            Ok(_) => (),
+            Err(e) => {
+                // Log an error - this implies the dispatcher was dropped somehow
+                // and is likely fatal
+                tracing::error!("Error sending Aggregate Query task: {:?}", e);
+            }
+        }
+    }
+
+    fn terminate_with_error(&mut self, error: Box<dyn ChromaError>, ctx: &ComponentContext<Self>) {
+        let result_channel = self
+            .result_channel
+            .take()
+            .expect("Invariant violation. Result channel is not set.");
+        match result_channel.send(Err(error)) {
+            Ok(_) => (),
+            Err(_) => {
+                // Log an error - this implied the listener was dropped
+                tracing::error!(
+                    "[AggregateQueryOrchestrator] Result channel dropped before sending error"
+                );
+            }
+        }
+        // Cancel the orchestrator so it stops processing
+        ctx.cancellation_token.cancel();
+    }
+
+    ///  Run the orchestrator and return the result.
+    ///  # Note
+    ///  Use this over spawning the component directly. This method will start the component and
+    ///  wait for it to finish before returning the result.
+    pub(crate) async fn run(mut self) -> AggregateQueryOrchestratorResult {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.result_channel = Some(tx);
+        let mut handle = self.system.clone().start_component(self);
+        let result = rx.await;
+        handle.stop();
+        result.unwrap()
+    }
+}
+
+#[async_trait]
+impl Component for AggregateQueryOrchestrator {
+    fn get_name() -> &'static str {
+        "Aggregate Query Orchestrator"
+    }
+
+    fn queue_size(&self) -> usize {
+        1000 // TODO: make this configurable
+    }
+
+    async fn on_start(&mut self, ctx: &crate::system::ComponentContext<Self>) -> () {
+        self.start(ctx).await;
+        self.pull_logs(ctx).await;
+    }
+}
+
+#[async_trait]
+impl Handler<TaskResult<PullLogsOutput, PullLogsError>> for AggregateQueryOrchestrator {
+    async fn handle(
+        &mut self,
+        message: TaskResult<PullLogsOutput, PullLogsError>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        let message = message.into_inner();
+        match message {
+            Ok(logs) => {
+                let logs = logs.logs();
+                self.filter(logs, ctx).await;
+            }
+            Err(e) => {
+                tracing::error!("Error pulling logs: {:?}", e);
+                self.terminate_with_error(Box::new(e), ctx);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<TaskResult<MetadataFilteringOutput, MetadataFilteringError>>
+    for AggregateQueryOrchestrator
+{
+    async fn handle(
+        &mut self,
+        message: TaskResult<MetadataFilteringOutput, MetadataFilteringError>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        let message = message.into_inner();
+        let output = match message {
+            Ok(output) => output,
+            Err(e) => {
+                tracing::error!("Error filtering records: {:?}", e);
+                return self.terminate_with_error(Box::new(e), ctx);
+            }
+        };
+
+        let operator = AggregateMetadataOperator::new();
+        let input = AggregateMetadataInput::new(
+            output.log_records,
+            output.where_condition_filtered_offset_ids,
+            self.record_segment
+                .as_ref()
+                .expect("Invariant violation. Record segment is not set.")
+                .clone(),
+            self.metadata_segment
+                .as_ref()
+                .expect("Invariant violation. Metadata segment is not set.")
+                .clone(),
+            self.blockfile_provider.clone(),
+            self.keys.clone(),
+            self.histogram_buckets,
+        );
+
+        let task = wrap(operator, input, ctx.sender.as_receiver());
+        match self.dispatcher.send(task, Some(Span::current())).await {
+            Ok(_) => (),
+            Err(e) => {
+                // Log an error - this implies the dispatcher was dropped somehow
+                // and is likely fatal
+                tracing::error!("Error sending Aggregate Query task: {:?}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<TaskResult<AggregateMetadataOutput, AggregateMetadataError>>
+    for AggregateQueryOrchestrator
+{
+    async fn handle(
+        &mut self,
+        message: TaskResult<AggregateMetadataOutput, AggregateMetadataError>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        let message = message.into_inner();
+        let output = match message {
+            Ok(output) => output,
+            Err(e) => {
+                tracing::error!("Error aggregating metadata: {:?}", e);
+                return self.terminate_with_error(Box::new(e), ctx);
+            }
+        };
+
+        let result_channel = self
+            .result_channel
+            .take()
+            .expect("Invariant violation. Result channel is not set.");
+        match result_channel.send(Ok(output.aggregates)) {
+            Ok(_) => (),
+            Err(_) => {
+                // Log an error - this implied the listener was dropped
+                tracing::error!(
+                    "[AggregateQueryOrchestrator] Result channel dropped before sending result"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::dispatcher::Dispatcher;
+    use crate::execution::operator::Operator;
+    use crate::log::log::{InMemoryLog, InternalLogRecord};
+    use crate::segment::metadata_segment::MetadataSegmentWriter;
+    use crate::segment::record_segment::RecordSegmentWriter;
+    use crate::segment::{LogMaterializer, SegmentFlusher, SegmentWriter};
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::sysdb::test_sysdb::TestSysDb;
+    use crate::system::ComponentHandle;
+    use crate::types::{
+        DirectComparison, DirectDocumentComparison, Operation, OperationRecord, SegmentScope,
+        UpdateMetadataValue, WhereClauseComparator, WhereComparison, WhereDocumentOperator,
+    };
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    // A collection whose compacted records are in its segments and whose logged
+    // records are only in the log.
+    struct TestCollection {
+        _tmp_dir: TempDir,
+        system: System,
+        dispatcher: ComponentHandle<Dispatcher>,
+        log: Box<Log>,
+        sysdb: Box<SysDb>,
+        blockfile_provider: BlockfileProvider,
+        record_segment: Segment,
+        metadata_segment: Segment,
+        logged: Vec<LogRecord>,
+    }
+
+    impl TestCollection {
+        async fn new(compacted: Vec<OperationRecord>, logged: Vec<OperationRecord>) -> Self {
+            let tmp_dir = tempfile::tempdir().unwrap();
+            let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+            let blockfile_provider = BlockfileProvider::new_arrow(storage);
+            let collection_id = Uuid::new_v4();
+            let mut record_segment = Segment {
+                id: Uuid::new_v4(),
+                r#type: SegmentType::BlockfileRecord,
+                scope: SegmentScope::RECORD,
+                collection: Some(collection_id),
+                metadata: None,
+                file_path: HashMap::new(),
+            };
+            let mut metadata_segment = Segment {
+                id: Uuid::new_v4(),
+                r#type: SegmentType::BlockfileMetadata,
+                scope: SegmentScope::METADATA,
+                collection: Some(collection_id),
+                metadata: None,
+                file_path: HashMap::new(),
+            };
+
+            let num_compacted = compacted.len();
+            let logs: Vec<LogRecord> = compacted
+                .into_iter()
+                .chain(logged)
+                .enumerate()
+                .map(|(offset, record)| LogRecord {
+                    log_offset: offset as i64,
+                    record,
+                })
+                .collect();
+            if num_compacted > 0 {
+                let record_writer =
+                    RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                        .await
+                        .unwrap();
+                let mut metadata_writer =
+                    MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                        .await
+                        .unwrap();
+                let materializer = LogMaterializer::new(
+                    None,
+                    Chunk::new(logs[..num_compacted].to_vec().into()),
+                    None,
+                );
+                let records = materializer.materialize().await.unwrap();
+                metadata_writer
+                    .apply_materialized_log_chunk(records.clone())
+                    .await
+                    .unwrap();
+                record_writer
+                    .apply_materialized_log_chunk(records)
+                    .await
+                    .unwrap();
+                metadata_writer.write_to_blockfiles().await.unwrap();
+                record_segment.file_path = record_writer.commit().unwrap().flush().await.unwrap();
+                metadata_segment.file_path =
+                    metadata_writer.commit().unwrap().flush().await.unwrap();
+            }
+
+            let mut log = InMemoryLog::new();
+            for record in logs.iter() {
+                log.add_log(
+                    collection_id,
+                    Box::new(InternalLogRecord {
+                        collection_id,
+                        log_offset: record.log_offset,
+                        log_ts: record.log_offset + 1,
+                        record: record.clone(),
+                    }),
+                );
+            }
+            let mut sysdb = TestSysDb::new();
+            sysdb.add_collection(Collection {
+                id: collection_id,
+                name: "test".to_string(),
+                metadata: None,
+                dimension: Some(3),
+                tenant: "tenant".to_string(),
+                database: "database".to_string(),
+                log_position: num_compacted as i64 - 1,
+                version: 0,
+            });
+            sysdb.add_segment(record_segment.clone());
+            sysdb.add_segment(metadata_segment.clone());
+
+            let system = System::new();
+            let dispatcher = system.start_component(Dispatcher::new(4, 10, 10));
+            TestCollection {
+                _tmp_dir: tmp_dir,
+                system,
+                dispatcher,
+                log: Box::new(Log::InMemory(log)),
+                sysdb: Box::new(SysDb::Test(sysdb)),
+                blockfile_provider,
+                record_segment,
+                metadata_segment,
+                logged: logs[num_compacted..].to_vec(),
+            }
+        }
+
+        // Counts with the orchestrator and checks that a filtered count is the
+        // number of offset ids the filter matches, each counted once.
+        async fn count(
+            &self,
+            where_clause: Option<Where>,
+            where_document_clause: Option<WhereDocument>,
+        ) -> usize {
+            let count = CountQueryOrchestrator::new(
+                self.system.clone(),
+                &self.metadata_segment.id,
+                self.log.clone(),
+                self.sysdb.clone(),
+                self.dispatcher.receiver(),
+                self.blockfile_provider.clone(),
+                where_clause.clone(),
+                where_document_clause.clone(),
+            )
+            .run()
+            .await
+            .unwrap();
+            if where_clause.is_none() && where_document_clause.is_none() {
+                return count;
+            }
+
+            let filtered = MetadataFilteringOperator::new()
+                .run(&MetadataFilteringInput::new(
+                    Chunk::new(self.logged.clone().into()),
+                    self.record_segment.clone(),
+                    self.metadata_segment.clone(),
+                    self.blockfile_provider.clone(),
+                    where_clause,
+                    where_document_clause,
+                    None,
+                ))
+                .await
+                .unwrap();
+            let offset_ids = filtered.where_condition_filtered_offset_ids.unwrap();
+            let unique_offset_ids: HashSet<u32> = offset_ids.iter().copied().collect();
+            assert_eq!(unique_offset_ids.len(), offset_ids.len());
+            assert_eq!(count, offset_ids.len());
+            count
+        }
+    }
+
+    fn record(
+        id: &str,
+        operation: Operation,
+        color: Option<&str>,
+        document: Option<&str>,
+    ) -> OperationRecord {
+        OperationRecord {
+            id: id.to_string(),
+            embedding: match operation {
+                Operation::Add | Operation::Upsert => Some(vec![1.0, 2.0, 3.0]),
+                _ => None,
+            },
+            encoding: None,
+            sparse_vector: None,
+            metadata: color.map(|color| {
+                HashMap::from([(
+                    "color".to_string(),
+                    UpdateMetadataValue::Str(color.to_string()),
+                )])
+            }),
+            document: document.map(|document| document.to_string()),
+            operation,
+        }
+    }
+
+    fn compacted_records() -> Vec<OperationRecord> {
+        vec![
+            record("id1", Operation::Add, Some("red"), Some("the cat sat")),
+            record("id2", Operation::Add, Some("blue"), Some("the dog ran")),
+            record("id3", Operation::Add, Some("red"), Some("a dog and a cat")),
+        ]
+    }
+
+    fn color_is(color: &str) -> Option<Where> {
+        Some(Where::DirectWhereComparison(DirectComparison {
+            key: "color".to_string(),
+            comparison: WhereComparison::SingleStringComparison(
+                color.to_string(),
+                WhereClauseComparator::Equal,
+            ),
+        }))
+    }
+
+    fn document_contains(text: &str) -> Option<WhereDocument> {
+        Some(WhereDocument::DirectWhereDocumentComparison(
+            DirectDocumentComparison {
+                document: text.to_string(),
+                operator: WhereDocumentOperator::Contains,
+            },
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_count_with_where() {
+        let collection = TestCollection::new(
+            compacted_records(),
+            vec![
+                record("id1", Operation::Update, Some("blue"), None),
+                record("id2", Operation::Update, Some("red"), None),
+                record("id4", Operation::Add, Some("red"), Some("a cat nap")),
+                record("id4", Operation::Update, Some("red"), None),
+            ],
+        )
+        .await;
+        assert_eq!(collection.count(None, None).await, 4);
+        assert_eq!(collection.count(color_is("red"), None).await, 3);
+        assert_eq!(collection.count(color_is("blue"), None).await, 1);
+        assert_eq!(collection.count(color_is("green"), None).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_count_with_where_document() {
+        let collection = TestCollection::new(
+            compacted_records(),
+            vec![
+                record("id2", Operation::Update, None, Some("the dog sat")),
+                record("id4", Operation::Add, Some("blue"), Some("a cat nap")),
+            ],
+        )
+        .await;
+        assert_eq!(collection.count(None, document_contains("cat")).await, 3);
+        assert_eq!(collection.count(None, document_contains("sat")).await, 2);
+        assert_eq!(
+            collection
+                .count(color_is("blue"), document_contains("cat"))
+                .await,
+            1
+        );
+        assert_eq!(collection.count(None, document_contains("bird")).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_count_log_only_records() {
+        let collection = TestCollection::new(
+            vec![],
+            vec![
+                record("id1", Operation::Add, Some("red"), Some("the cat sat")),
+                record("id2", Operation::Add, Some("red"), Some("the dog ran")),
+                record("id2", Operation::Update, Some("blue"), None),
+                record(
+                    "id3",
+                    Operation::Upsert,
+                    Some("red"),
+                    Some("a dog and a cat"),
+                ),
+                record("id3", Operation::Upsert, Some("red"), Some("a cat")),
+            ],
+        )
+        .await;
+        assert_eq!(collection.count(None, None).await, 3);
+        assert_eq!(collection.count(color_is("red"), None).await, 2);
+        assert_eq!(collection.count(None, document_contains("cat")).await, 2);
+        assert_eq!(
+            collection
+                .count(color_is("red"), document_contains("dog"))
+                .await,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_count_log_deletes_segment_records() {
+        let collection = TestCollection::new(
+            compacted_records(),
+            vec![
+                record("id1", Operation::Delete, None, None),
+                record("id2", Operation::Upsert, Some("red"), Some("the dog sat")),
+                record("id3", Operation::Delete, None, None),
+                record("id3", Operation::Delete, None, None),
+            ],
+        )
+        .await;
+        assert_eq!(collection.count(None, None).await, 1);
+        assert_eq!(collection.count(color_is("red"), None).await, 1);
+        assert_eq!(collection.count(None, document_contains("cat")).await, 0);
+        assert_eq!(collection.count(None, document_contains("dog")).await, 1);
+        assert_eq!(collection.count(None, document_contains("sat")).await, 1);
+    }
+}
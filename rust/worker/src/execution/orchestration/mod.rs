@@ -1,9 +1,13 @@
 mod common;
 mod compact;
+mod evaluate;
 mod get_vectors;
 mod hnsw;
 mod metadata;
+mod sparse;
 pub(crate) use compact::*;
+pub(crate) use evaluate::*;
 pub(crate) use get_vectors::*;
 pub(crate) use hnsw::*;
 pub(crate) use metadata::*;
+pub(crate) use sparse::*;
@@ -0,0 +1,384 @@
+use super::common::{
+    get_collection_by_id, get_record_segment_by_collection_id, get_sparse_vector_segment_by_id,
+};
+use crate::{
+    blockstore::provider::BlockfileProvider,
+    errors::{ChromaError, ErrorCodes},
+    execution::{
+        data::data_chunk::Chunk,
+        operator::{wrap, TaskMessage, TaskResult},
+        operators::{
+            pull_log::{PullLogsInput, PullLogsOperator, PullLogsOutput},
+            sparse_knn::{SparseKnnOperator, SparseKnnOperatorInput, SparseKnnOperatorOutput},
+        },
+    },
+    log::log::{Log, PullLogsError},
+    sysdb::sysdb::SysDb,
+    system::{ChannelError, Component, ComponentContext, Handler, Receiver, System},
+    types::{Collection, LogRecord, Segment, SparseVector, VectorQueryResult},
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tracing::{trace, Span};
+use uuid::Uuid;
+
+#[derive(Debug)]
+enum ExecutionState {
+    Pending,
+    PullLogs,
+    QuerySparseVectors,
+}
+
+#[derive(Debug, Error)]
+enum SparseQueryOrchestratorError {
+    #[error("Sparse vector segment has no collection")]
+    SparseVectorSegmentHasNoCollection,
+    #[error("Error sending task to dispatcher")]
+    TaskSendError(#[from] ChannelError),
+    #[error("System time error")]
+    SystemTimeError(#[from] std::time::SystemTimeError),
+}
+
+impl ChromaError for SparseQueryOrchestratorError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            SparseQueryOrchestratorError::SparseVectorSegmentHasNoCollection => {
+                ErrorCodes::Internal
+            }
+            SparseQueryOrchestratorError::TaskSendError(e) => e.code(),
+            SparseQueryOrchestratorError::SystemTimeError(_) => ErrorCodes::Internal,
+        }
+    }
+}
+
+type SparseQueryResultSender =
+    tokio::sync::oneshot::Sender<Result<Vec<Vec<VectorQueryResult>>, Box<dyn ChromaError>>>;
+
+/// Queries a sparse vector segment and the log of its collection with one
+/// sparse knn task per query vector.
+#[derive(Debug)]
+pub struct SparseQueryOrchestrator {
+    state: ExecutionState,
+    // Component Execution
+    system: System,
+    // Query state
+    query_vectors: Vec<SparseVector>,
+    k: i32,
+    allowed_ids: Arc<[String]>,
+    sparse_segment_id: Uuid,
+    // State fetched or created for query execution
+    sparse_segment: Option<Segment>,
+    record_segment: Option<Segment>,
+    collection: Option<Collection>,
+    task_id_to_query_index: HashMap<Uuid, usize>,
+    results: Vec<Option<Vec<VectorQueryResult>>>,
+    // Services
+    log: Box<Log>,
+    sysdb: Box<SysDb>,
+    dispatcher: Box<dyn Receiver<TaskMessage>>,
+    blockfile_provider: BlockfileProvider,
+    // Result channel
+    result_channel: Option<SparseQueryResultSender>,
+}
+
+impl SparseQueryOrchestrator {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        system: System,
+        query_vectors: Vec<SparseVector>,
+        k: i32,
+        allowed_ids: Vec<String>,
+        sparse_segment_id: Uuid,
+        log: Box<Log>,
+        sysdb: Box<SysDb>,
+        dispatcher: Box<dyn Receiver<TaskMessage>>,
+        blockfile_provider: BlockfileProvider,
+    ) -> Self {
+        let num_queries = query_vectors.len();
+        Self {
+            state: ExecutionState::Pending,
+            system,
+            query_vectors,
+            k,
+            allowed_ids: allowed_ids.into(),
+            sparse_segment_id,
+            sparse_segment: None,
+            record_segment: None,
+            collection: None,
+            task_id_to_query_index: HashMap::new(),
+            results: (0..num_queries).map(|_| None).collect(),
+            log,
+            sysdb,
+            dispatcher,
+            blockfile_provider,
+            result_channel: None,
+        }
+    }
+
+    async fn pull_logs(
+        &mut self,
+        self_address: Box<dyn Receiver<TaskResult<PullLogsOutput, PullLogsError>>>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        self.state = ExecutionState::PullLogs;
+        let operator = PullLogsOperator::new(self.log.clone());
+        let end_timestamp = SystemTime::now().duration_since(UNIX_EPOCH);
+        let end_timestamp = match end_timestamp {
+            // TODO: change protobuf definition to use u64 instead of i64
+            Ok(end_timestamp) => end_timestamp.as_nanos() as i64,
+            Err(e) => {
+                self.terminate_with_error(
+                    Box::new(SparseQueryOrchestratorError::SystemTimeError(e)),
+                    ctx,
+                );
+                return;
+            }
+        };
+
+        let collection = self
+            .collection
+            .as_ref()
+            .expect("State machine invariant violation. The collection is not set when pulling logs. This should never happen.");
+
+        let input = PullLogsInput::new(
+            collection.id,
+            // The collection log position is inclusive, and we want to start from the next log
+            collection.log_position + 1,
+            100,
+            None,
+            Some(end_timestamp),
+        );
+
+        let task = wrap(operator, input, self_address);
+        match self.dispatcher.send(task, Some(Span::current())).await {
+            Ok(_) => (),
+            Err(e) => {
+                self.terminate_with_error(
+                    Box::new(SparseQueryOrchestratorError::TaskSendError(e)),
+                    ctx,
+                );
+            }
+        }
+    }
+
+    async fn query_sparse_vectors(
+        &mut self,
+        self_address: Box<dyn Receiver<TaskResult<SparseKnnOperatorOutput, Box<dyn ChromaError>>>>,
+        logs: Chunk<LogRecord>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        self.state = ExecutionState::QuerySparseVectors;
+        if self.query_vectors.is_empty() {
+            self.send_results(ctx);
+            return;
+        }
+        let sparse_segment = self
+            .sparse_segment
+            .clone()
+            .expect("Invariant violation. Sparse vector segment is not set.");
+        let record_segment = self
+            .record_segment
+            .clone()
+            .expect("Invariant violation. Record segment is not set.");
+        for i in 0..self.query_vectors.len() {
+            let input = SparseKnnOperatorInput {
+                logs: logs.clone(),
+                query: self.query_vectors[i].clone(),
+                k: self.k as usize,
+                allowed_ids: self.allowed_ids.clone(),
+                sparse_segment: sparse_segment.clone(),
+                record_segment: record_segment.clone(),
+                blockfile_provider: self.blockfile_provider.clone(),
+            };
+            let task = wrap(Box::new(SparseKnnOperator {}), input, self_address.clone());
+            self.task_id_to_query_index.insert(task.id(), i);
+            match self.dispatcher.send(task, Some(Span::current())).await {
+                Ok(_) => (),
+                Err(e) => {
+                    self.terminate_with_error(
+                        Box::new(SparseQueryOrchestratorError::TaskSendError(e)),
+                        ctx,
+                    );
+                    return;
+                }
+            }
+        }
+    }
+
+    fn send_results(&mut self, ctx: &ComponentContext<Self>) {
+        let results = self
+            .results
+            .drain(..)
+            .map(Option::unwrap_or_default)
+            .collect();
+        let result_channel = match self.result_channel.take() {
+            Some(result_channel) => result_channel,
+            None => return,
+        };
+        match result_channel.send(Ok(results)) {
+            Ok(_) => (),
+            Err(_e) => {
+                // Log an error - this implied the listener was dropped
+                trace!("[SparseQueryOrchestrator] Result channel dropped before sending result");
+            }
+        }
+        // Cancel the orchestrator so it stops processing
+        ctx.cancellation_token.cancel();
+    }
+
+    fn terminate_with_error(&mut self, error: Box<dyn ChromaError>, ctx: &ComponentContext<Self>) {
+        let result_channel = match self.result_channel.take() {
+            Some(result_channel) => result_channel,
+            // An earlier task already failed the query
+            None => return,
+        };
+        match result_channel.send(Err(error)) {
+            Ok(_) => (),
+            Err(_e) => {
+                // Log an error - this implied the listener was dropped
+                trace!("[SparseQueryOrchestrator] Result channel dropped before sending error");
+            }
+        }
+        // Cancel the orchestrator so it stops processing
+        ctx.cancellation_token.cancel();
+    }
+
+    ///  Run the orchestrator and return the result.
+    ///  # Note
+    ///  Use this over spawning the component directly. This method will start the component and
+    ///  wait for it to finish before returning the result.
+    pub(crate) async fn run(mut self) -> Result<Vec<Vec<VectorQueryResult>>, Box<dyn ChromaError>> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.result_channel = Some(tx);
+        let mut handle = self.system.clone().start_component(self);
+        let result = rx.await;
+        handle.stop();
+        result.unwrap()
+    }
+}
+
+// ============== Component Implementation ==============
+
+#[async_trait]
+impl Component for SparseQueryOrchestrator {
+    fn get_name() -> &'static str {
+        "SparseQueryOrchestrator"
+    }
+
+    fn queue_size(&self) -> usize {
+        1000
+    }
+
+    async fn on_start(&mut self, ctx: &ComponentContext<Self>) {
+        // Populate the orchestrator with the initial state - The Sparse Vector Segment, The Record Segment and the Collection
+        let sparse_segment = match get_sparse_vector_segment_by_id(
+            self.sysdb.clone(),
+            &self.sparse_segment_id,
+        )
+        .await
+        {
+            Ok(segment) => segment,
+            Err(e) => {
+                self.terminate_with_error(e, ctx);
+                return;
+            }
+        };
+
+        let collection_id = match &sparse_segment.collection {
+            Some(collection_id) => *collection_id,
+            None => {
+                self.terminate_with_error(
+                    Box::new(SparseQueryOrchestratorError::SparseVectorSegmentHasNoCollection),
+                    ctx,
+                );
+                return;
+            }
+        };
+
+        let collection = match get_collection_by_id(self.sysdb.clone(), &collection_id).await {
+            Ok(collection) => collection,
+            Err(e) => {
+                self.terminate_with_error(e, ctx);
+                return;
+            }
+        };
+
+        let record_segment =
+            match get_record_segment_by_collection_id(self.sysdb.clone(), &collection_id).await {
+                Ok(segment) => segment,
+                Err(e) => {
+                    self.terminate_with_error(e, ctx);
+                    return;
+                }
+            };
+
+        self.sparse_segment = Some(sparse_segment);
+        self.record_segment = Some(record_segment);
+        self.collection = Some(collection);
+
+        self.pull_logs(ctx.sender.as_receiver(), ctx).await;
+    }
+}
+
+// ============== Handlers ==============
+
+#[async_trait]
+impl Handler<TaskResult<PullLogsOutput, PullLogsError>> for SparseQueryOrchestrator {
+    async fn handle(
+        &mut self,
+        message: TaskResult<PullLogsOutput, PullLogsError>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        let message = message.into_inner();
+        match message {
+            Ok(output) => {
+                let logs = output.logs();
+                self.query_sparse_vectors(ctx.sender.as_receiver(), logs, ctx)
+                    .await;
+            }
+            Err(e) => {
+                self.terminate_with_error(Box::new(e), ctx);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<TaskResult<SparseKnnOperatorOutput, Box<dyn ChromaError>>>
+    for SparseQueryOrchestrator
+{
+    async fn handle(
+        &mut self,
+        message: TaskResult<SparseKnnOperatorOutput, Box<dyn ChromaError>>,
+        ctx: &ComponentContext<Self>,
+    ) {
+        let query_index = self
+            .task_id_to_query_index
+            .remove(&message.id())
+            .expect("Invariant violation. Sparse knn task id is not mapped to a query.");
+        let output = match message.into_inner() {
+            Ok(output) => output,
+            Err(e) => {
+                self.terminate_with_error(e, ctx);
+                return;
+            }
+        };
+        let results = output
+            .user_ids
+            .into_iter()
+            .zip(output.distances)
+            .map(|(id, distance)| VectorQueryResult {
+                id,
+                distance,
+                vector: None,
+            })
+            .collect();
+        self.results[query_index] = Some(results);
+        if self.task_id_to_query_index.is_empty() {
+            self.send_results(ctx);
+        }
+    }
+}
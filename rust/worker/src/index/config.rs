@@ -0,0 +1,13 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+/// # Description
+/// The configuration for the HnswIndexProvider.
+/// ## Description of parameters
+/// - hnsw_temporary_path: The local directory hnsw indexes are loaded into and persisted from.
+/// - hnsw_cache_size_bytes: The approximate number of bytes of hnsw indexes to keep resident in memory.
+///   Least recently used indexes beyond this budget are evicted.
+pub(crate) struct HnswProviderConfig {
+    pub(crate) hnsw_temporary_path: String,
+    pub(crate) hnsw_cache_size_bytes: usize,
+}
@@ -0,0 +1,66 @@
+/// Whether the document contains a substring within `max_edits` edits of the
+/// query, where an edit inserts, deletes or substitutes a character.
+/// # Description
+/// This is the dynamic program of Sellers for approximate substring
+/// matching. Row `i` holds the fewest edits between the first `i` characters
+/// of the query and any substring that ends at the current character of the
+/// document. The first row is zero because a substring can start anywhere.
+pub(crate) fn contains_within_edits(document: &str, query: &[char], max_edits: u32) -> bool {
+    let max_edits = max_edits as usize;
+    // Deleting the whole query matches the empty substring.
+    if query.len() <= max_edits {
+        return true;
+    }
+    let mut previous: Vec<usize> = (0..=query.len()).collect();
+    let mut current = vec![0; query.len() + 1];
+    for c in document.chars() {
+        for (i, query_char) in query.iter().enumerate() {
+            let substitution = previous[i] + usize::from(*query_char != c);
+            current[i + 1] = substitution.min(previous[i + 1] + 1).min(current[i] + 1);
+        }
+        if current[query.len()] <= max_edits {
+            return true;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(query: &str) -> Vec<char> {
+        query.chars().collect()
+    }
+
+    #[test]
+    fn test_contains_within_edits() {
+        let document = "the quick brown fox";
+        assert!(contains_within_edits(document, &chars("quick"), 0));
+        assert!(!contains_within_edits(document, &chars("qxxck"), 1));
+        assert!(contains_within_edits(document, &chars("qxxck"), 2));
+        // A substitution, a deletion and an insertion.
+        assert!(contains_within_edits(document, &chars("brawn"), 1));
+        assert!(contains_within_edits(document, &chars("bown"), 1));
+        assert!(contains_within_edits(document, &chars("brownn"), 1));
+        assert!(!contains_within_edits(document, &chars("crane"), 1));
+        assert!(contains_within_edits(document, &chars("ab"), 2));
+        assert!(!contains_within_edits("", &chars("ab"), 1));
+    }
+
+    #[test]
+    fn test_contains_within_edits_multibyte() {
+        assert!(contains_within_edits("héllo wörld", &chars("world"), 1));
+        assert!(contains_within_edits(
+            "héllo wörld",
+            &chars("hello world"),
+            2
+        ));
+        assert!(!contains_within_edits(
+            "héllo wörld",
+            &chars("hello world"),
+            1
+        ));
+    }
+}
@@ -1,2 +1,5 @@
+pub mod fuzzy;
+pub mod pattern;
+pub mod query;
 pub mod tokenizer;
 pub mod types;
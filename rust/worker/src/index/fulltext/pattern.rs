@@ -0,0 +1,260 @@
+use crate::index::fulltext::fuzzy::contains_within_edits;
+use crate::index::fulltext::query::{LiteralPlan, TextQuery};
+use crate::index::metadata::types::MetadataIndexError;
+use crate::types::WhereDocumentOperator;
+use regex::Regex;
+use regex_syntax::hir::{Hir, HirKind};
+
+/// A where document query compiled for matching documents.
+/// # Description
+/// `$contains` is a literal substring match. `$regex` matches anywhere in the
+/// document and `$like` matches the whole document, where `%` is any sequence
+/// of characters and `_` is any single character. `$search` is a boolean
+/// text query of words and phrases, see `TextQuery`. `$fuzzy` matches documents with a substring
+/// within an edit budget of the query. All matching is case sensitive, like
+/// the n-gram index.
+///
+/// The literal plan holds the substrings that matching documents have, the
+/// full text index uses it to prune candidates before they are verified.
+#[derive(Debug)]
+pub(crate) struct DocumentPattern {
+    matcher: Matcher,
+    literal_plan: LiteralPlan,
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Literal(String),
+    Regex(Regex),
+    Query(TextQuery),
+    Fuzzy { query: Vec<char>, max_edits: u32 },
+}
+
+impl DocumentPattern {
+    pub(crate) fn new(
+        query: &str,
+        operator: &WhereDocumentOperator,
+    ) -> Result<Self, MetadataIndexError> {
+        match operator {
+            WhereDocumentOperator::Contains | WhereDocumentOperator::NotContains => {
+                Ok(DocumentPattern {
+                    matcher: Matcher::Literal(query.to_string()),
+                    literal_plan: LiteralPlan::Literal(query.to_string()),
+                })
+            }
+            WhereDocumentOperator::Regex => {
+                let hir = regex_syntax::parse(query)
+                    .map_err(|e| MetadataIndexError::InvalidOperand(e.to_string()))?;
+                let mut required_literals = Vec::new();
+                push_required_literals(&hir, &mut required_literals);
+                Ok(DocumentPattern {
+                    matcher: Matcher::Regex(compile(query)?),
+                    literal_plan: all_of(required_literals),
+                })
+            }
+            WhereDocumentOperator::Like => {
+                let mut pattern = String::from("(?s)^");
+                let mut required_literals = Vec::new();
+                let mut literal = String::new();
+                for c in query.chars() {
+                    let wildcard = match c {
+                        '%' => ".*",
+                        '_' => ".",
+                        c => {
+                            literal.push(c);
+                            continue;
+                        }
+                    };
+                    pattern.push_str(&regex::escape(&literal));
+                    pattern.push_str(wildcard);
+                    push_literal(std::mem::take(&mut literal), &mut required_literals);
+                }
+                pattern.push_str(&regex::escape(&literal));
+                pattern.push('$');
+                push_literal(literal, &mut required_literals);
+                Ok(DocumentPattern {
+                    matcher: Matcher::Regex(compile(&pattern)?),
+                    literal_plan: all_of(required_literals),
+                })
+            }
+            WhereDocumentOperator::Search => {
+                let text_query = TextQuery::parse(query)?;
+                Ok(DocumentPattern {
+                    literal_plan: text_query.literal_plan(),
+                    matcher: Matcher::Query(text_query),
+                })
+            }
+            WhereDocumentOperator::Fuzzy(max_edits) => Ok(DocumentPattern {
+                matcher: Matcher::Fuzzy {
+                    query: query.chars().collect(),
+                    max_edits: *max_edits,
+                },
+                literal_plan: LiteralPlan::Fuzzy(query.to_string(), *max_edits),
+            }),
+        }
+    }
+
+    pub(crate) fn is_match(&self, document: &str) -> bool {
+        match &self.matcher {
+            Matcher::Literal(literal) => document.contains(literal.as_str()),
+            Matcher::Regex(regex) => regex.is_match(document),
+            Matcher::Query(text_query) => text_query.is_match(document),
+            Matcher::Fuzzy { query, max_edits } => {
+                contains_within_edits(document, query, *max_edits)
+            }
+        }
+    }
+
+    /// Whether the pattern is a literal substring, which the full text index
+    /// matches exactly without verification.
+    pub(crate) fn is_literal(&self) -> bool {
+        matches!(self.matcher, Matcher::Literal(_))
+    }
+
+    pub(crate) fn literal_plan(&self) -> &LiteralPlan {
+        &self.literal_plan
+    }
+
+    /// The text query of a `$search` pattern, which the full text index
+    /// evaluates from the positions of its words.
+    pub(crate) fn text_query(&self) -> Option<&TextQuery> {
+        match &self.matcher {
+            Matcher::Query(text_query) => Some(text_query),
+            _ => None,
+        }
+    }
+}
+
+fn all_of(literals: Vec<String>) -> LiteralPlan {
+    LiteralPlan::And(literals.into_iter().map(LiteralPlan::Literal).collect())
+}
+
+fn compile(pattern: &str) -> Result<Regex, MetadataIndexError> {
+    Regex::new(pattern).map_err(|e| MetadataIndexError::InvalidOperand(e.to_string()))
+}
+
+fn push_literal(literal: String, literals: &mut Vec<String>) {
+    if !literal.is_empty() {
+        literals.push(literal);
+    }
+}
+
+// Collects the runs of literal characters that every match of the expression
+// contains. Alternations and optional repetitions require nothing.
+fn push_required_literals(hir: &Hir, literals: &mut Vec<String>) {
+    match hir.kind() {
+        HirKind::Literal(literal) => {
+            push_literal(String::from_utf8_lossy(&literal.0).into_owned(), literals)
+        }
+        HirKind::Capture(capture) => push_required_literals(&capture.sub, literals),
+        HirKind::Repetition(repetition) if repetition.min > 0 => {
+            push_required_literals(&repetition.sub, literals)
+        }
+        HirKind::Concat(hirs) => {
+            let mut run = Vec::new();
+            for hir in hirs {
+                match hir.kind() {
+                    HirKind::Literal(literal) => run.extend_from_slice(&literal.0),
+                    _ => {
+                        push_literal(String::from_utf8_lossy(&run).into_owned(), literals);
+                        run.clear();
+                        push_required_literals(hir, literals);
+                    }
+                }
+            }
+            push_literal(String::from_utf8_lossy(&run).into_owned(), literals);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{ChromaError, ErrorCodes};
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_contains_is_literal() {
+        let pattern = DocumentPattern::new("a(b[", &WhereDocumentOperator::Contains).unwrap();
+        assert!(pattern.is_match("xa(b[y"));
+        assert!(!pattern.is_match("ab"));
+        assert_eq!(
+            pattern.literal_plan(),
+            &LiteralPlan::Literal("a(b[".to_string())
+        );
+    }
+
+    #[test]
+    fn test_like() {
+        let pattern = DocumentPattern::new("hello%wor_d", &WhereDocumentOperator::Like).unwrap();
+        assert!(pattern.is_match("hello, world"));
+        assert!(pattern.is_match("helloworld"));
+        assert!(!pattern.is_match("say hello world"));
+        assert!(!pattern.is_match("hello world!"));
+        assert_eq!(
+            pattern.literal_plan(),
+            &all_of(strings(&["hello", "wor", "d"]))
+        );
+
+        let pattern = DocumentPattern::new("%1.5 (beta)%", &WhereDocumentOperator::Like).unwrap();
+        assert!(pattern.is_match("version 1.5 (beta) released"));
+        assert!(!pattern.is_match("version 105 (beta) released"));
+    }
+
+    #[test]
+    fn test_regex() {
+        let pattern =
+            DocumentPattern::new(r"hello\s+(world|there)!+", &WhereDocumentOperator::Regex)
+                .unwrap();
+        assert!(pattern.is_match("oh hello  there!!"));
+        assert!(!pattern.is_match("hello world"));
+        assert_eq!(pattern.literal_plan(), &all_of(strings(&["hello", "!"])));
+
+        let pattern = DocumentPattern::new("(?i)cat", &WhereDocumentOperator::Regex).unwrap();
+        assert!(pattern.is_match("CAT"));
+        assert_eq!(pattern.literal_plan(), &all_of(vec![]));
+
+        let err = DocumentPattern::new("a(b[", &WhereDocumentOperator::Regex).unwrap_err();
+        assert_eq!(err.code(), ErrorCodes::InvalidArgument);
+    }
+
+    #[test]
+    fn test_search() {
+        let pattern =
+            DocumentPattern::new("cat* NOT \"black dog\"", &WhereDocumentOperator::Search).unwrap();
+        assert!(pattern.is_match("a catalog of pets"));
+        assert!(!pattern.is_match("a cat and a black dog"));
+        assert!(!pattern.is_match("concatenate"));
+        assert!(!pattern.is_literal());
+        assert_eq!(
+            pattern.literal_plan(),
+            &LiteralPlan::And(vec![
+                LiteralPlan::Literal("cat".to_string()),
+                LiteralPlan::All
+            ])
+        );
+
+        let pattern = DocumentPattern::new("cat", &WhereDocumentOperator::Search).unwrap();
+        assert!(pattern.is_match("a (cat)."));
+        assert!(!pattern.is_match("a catalog of pets"));
+
+        let err = DocumentPattern::new("(cat", &WhereDocumentOperator::Search).unwrap_err();
+        assert_eq!(err.code(), ErrorCodes::InvalidArgument);
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let pattern = DocumentPattern::new("relaese", &WhereDocumentOperator::Fuzzy(2)).unwrap();
+        assert!(pattern.is_match("the final release"));
+        assert!(!pattern.is_match("the final rehearsal"));
+        assert!(!pattern.is_literal());
+        assert_eq!(
+            pattern.literal_plan(),
+            &LiteralPlan::Fuzzy("relaese".to_string(), 2)
+        );
+    }
+}
@@ -0,0 +1,580 @@
+use crate::index::metadata::types::MetadataIndexError;
+use roaring::RoaringBitmap;
+use std::collections::HashMap;
+
+/// A boolean text query typed by a user.
+/// # Description
+/// A query is made of words, quoted phrases and the operators `AND`, `OR` and
+/// `NOT`, grouped with parentheses. Terms next to each other are combined
+/// with `AND`, which binds tighter than `OR`.
+/// - `cat` matches documents that contain the word "cat", but not "catalog".
+/// - `cat*` matches documents with a word that starts with "cat". `*` can
+///   only end a word.
+/// - `"black cat"` matches documents that contain the words "black cat".
+/// - `"black cat"~3` matches the words "black" and "cat" in any order with at
+///   most three other words between them.
+///
+/// Terms match at the boundaries of words, which are the characters other
+/// than letters and digits, so `cat` matches "cat." and "(cat". Words are
+/// separated by whitespace when proximity counts them. Matching is case
+/// sensitive. The index finds the documents that contain the text of the
+/// terms, and only those documents are read to check the boundaries.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TextQuery {
+    Phrase(Phrase),
+    And(Vec<TextQuery>),
+    Or(Vec<TextQuery>),
+    Not(Box<TextQuery>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Phrase {
+    words: Vec<String>,
+    // The number of other words allowed between the words of the phrase, in
+    // any order. None if the phrase has to be in the text as is.
+    slop: Option<u32>,
+    // Whether the last word can be the start of a longer word.
+    prefix: bool,
+}
+
+/// The literals a document has to contain to match a query, which the full
+/// text index looks up to narrow down the documents to verify.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum LiteralPlan {
+    // Any document can match.
+    All,
+    Literal(String),
+    And(Vec<LiteralPlan>),
+    Or(Vec<LiteralPlan>),
+    // A substring within a number of edits of the value, which the index
+    // approximates by the n-grams the value shares with the document.
+    Fuzzy(String, u32),
+}
+
+impl TextQuery {
+    pub(crate) fn parse(query: &str) -> Result<Self, MetadataIndexError> {
+        let tokens = lex(query)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            index: 0,
+        };
+        let text_query = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(text_query),
+            Some(token) => Err(invalid(format!("unexpected {}", token))),
+        }
+    }
+
+    pub(crate) fn is_match(&self, document: &str) -> bool {
+        match self {
+            TextQuery::Phrase(phrase) => {
+                let positions: Vec<Vec<u32>> = phrase
+                    .lookups()
+                    .iter()
+                    .map(|text| positions_of(document, text))
+                    .collect();
+                phrase.is_match_at(document, &positions)
+            }
+            TextQuery::And(children) => children.iter().all(|child| child.is_match(document)),
+            TextQuery::Or(children) => children.iter().any(|child| child.is_match(document)),
+            TextQuery::Not(child) => !child.is_match(document),
+        }
+    }
+
+    pub(crate) fn literal_plan(&self) -> LiteralPlan {
+        match self {
+            TextQuery::Phrase(phrase) => match phrase.slop {
+                None => LiteralPlan::Literal(phrase.text()),
+                Some(_) => LiteralPlan::And(
+                    phrase
+                        .words
+                        .iter()
+                        .map(|word| LiteralPlan::Literal(word.clone()))
+                        .collect(),
+                ),
+            },
+            TextQuery::And(children) => {
+                LiteralPlan::And(children.iter().map(TextQuery::literal_plan).collect())
+            }
+            TextQuery::Or(children) => {
+                LiteralPlan::Or(children.iter().map(TextQuery::literal_plan).collect())
+            }
+            // The documents without a literal can't be looked up.
+            TextQuery::Not(_) => LiteralPlan::All,
+        }
+    }
+}
+
+impl Phrase {
+    /// The words of the phrase separated by single spaces.
+    pub(crate) fn text(&self) -> String {
+        self.words.join(" ")
+    }
+
+    /// The texts the positions of which decide whether the phrase matches: the
+    /// phrase as is, or each of its distinct words with a slop.
+    pub(crate) fn lookups(&self) -> Vec<String> {
+        match self.slop {
+            None => vec![self.text()],
+            Some(_) => self
+                .distinct_words()
+                .into_iter()
+                .map(|(word, _)| word.to_string())
+                .collect(),
+        }
+    }
+
+    /// Whether the phrase matches the document, given the sorted byte offsets
+    /// of each of its lookups in the document.
+    pub(crate) fn is_match_at(&self, document: &str, positions: &[Vec<u32>]) -> bool {
+        match self.slop {
+            None => {
+                let text = self.text();
+                positions.first().is_some_and(|positions| {
+                    positions
+                        .iter()
+                        .any(|position| self.is_term_at(document, *position, &text))
+                })
+            }
+            Some(_) => {
+                let positions: Vec<Vec<u32>> = self
+                    .distinct_words()
+                    .iter()
+                    .zip(positions)
+                    .map(|((word, _), positions)| {
+                        positions
+                            .iter()
+                            .copied()
+                            .filter(|position| self.is_term_at(document, *position, word))
+                            .collect()
+                    })
+                    .collect();
+                self.is_within_slop(document, &positions)
+            }
+        }
+    }
+
+    // Whether the text at the byte offset starts a word of the document and,
+    // unless the phrase is a prefix, ends one.
+    fn is_term_at(&self, document: &str, position: u32, text: &str) -> bool {
+        let position = position as usize;
+        let (before, after) = match (
+            document.get(..position),
+            document.get(position + text.len()..),
+        ) {
+            (Some(before), Some(after)) => (before, after),
+            _ => return false,
+        };
+        let starts = !text.starts_with(is_word_char) || !before.ends_with(is_word_char);
+        let ends = self.prefix || !text.ends_with(is_word_char) || !after.starts_with(is_word_char);
+        starts && ends
+    }
+
+    /// The distinct words of the phrase with the number of times each occurs.
+    pub(crate) fn distinct_words(&self) -> Vec<(&str, usize)> {
+        let mut distinct_words: Vec<(&str, usize)> = Vec::new();
+        for word in self.words.iter() {
+            match distinct_words
+                .iter_mut()
+                .find(|(distinct_word, _)| *distinct_word == word.as_str())
+            {
+                Some((_, count)) => *count += 1,
+                None => distinct_words.push((word.as_str(), 1)),
+            }
+        }
+        distinct_words
+    }
+
+    // Whether the document has a span with every word of the phrase, as often
+    // as the phrase has it, and at most `slop` other words. `positions` holds
+    // the sorted byte offsets of each of the distinct words in the document.
+    fn is_within_slop(&self, document: &str, positions: &[Vec<u32>]) -> bool {
+        let distinct_words = self.distinct_words();
+        let slop = self.slop.unwrap_or(0) as usize;
+        let mut occurrences: Vec<(u32, usize)> = positions
+            .iter()
+            .enumerate()
+            .flat_map(|(word, positions)| positions.iter().map(move |position| (*position, word)))
+            .collect();
+        occurrences.sort_unstable();
+        // The shortest spans that end at each occurrence, by sliding their
+        // start forward while they still have every word.
+        let mut counts = vec![0; distinct_words.len()];
+        let mut missing = distinct_words.len();
+        let mut start = 0;
+        for end in 0..occurrences.len() {
+            let word = occurrences[end].1;
+            counts[word] += 1;
+            if counts[word] == distinct_words[word].1 {
+                missing -= 1;
+            }
+            while missing == 0 {
+                let span_end = occurrences[start..=end]
+                    .iter()
+                    .map(|(position, word)| *position as usize + distinct_words[*word].0.len())
+                    .max()
+                    .unwrap_or_default();
+                // A word of the document can hold more than one word of the
+                // phrase, like "e-mail" holds "e" and "mail".
+                let span_words = document
+                    .get(occurrences[start].0 as usize..span_end)
+                    .map_or(0, |span| span.split_whitespace().count());
+                if span_words.saturating_sub(self.words.len()) <= slop {
+                    return true;
+                }
+                let word = occurrences[start].1;
+                if counts[word] == distinct_words[word].1 {
+                    missing += 1;
+                }
+                counts[word] -= 1;
+                start += 1;
+            }
+        }
+        false
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric()
+}
+
+/// The byte offsets where the text occurs in the document, in order.
+/// Occurrences can overlap, like the ones the n-gram index finds.
+pub(crate) fn positions_of(document: &str, text: &str) -> Vec<u32> {
+    document
+        .char_indices()
+        .filter(|(position, _)| document[*position..].starts_with(text))
+        .map(|(position, _)| position as u32)
+        .collect()
+}
+
+/// The documents that contain the text of every lookup of a phrase, given the
+/// positions of each lookup in the documents that contain it.
+pub(crate) fn phrase_candidates(positions: &[HashMap<u32, Vec<u32>>]) -> RoaringBitmap {
+    let mut documents: Option<RoaringBitmap> = None;
+    for word_positions in positions {
+        let word_documents: RoaringBitmap = word_positions.keys().copied().collect();
+        documents = Some(match documents {
+            Some(documents) => documents & word_documents,
+            None => word_documents,
+        });
+    }
+    documents.unwrap_or_default()
+}
+
+/// The documents where a phrase matches, given the positions of each of its
+/// lookups and the text of the candidate documents.
+pub(crate) fn phrase_matches(
+    phrase: &Phrase,
+    positions: &[HashMap<u32, Vec<u32>>],
+    documents: &HashMap<u32, &str>,
+) -> RoaringBitmap {
+    documents
+        .iter()
+        .filter(|(offset_id, document)| {
+            let document_positions: Option<Vec<Vec<u32>>> = positions
+                .iter()
+                .map(|word_positions| word_positions.get(offset_id).cloned())
+                .collect();
+            document_positions
+                .is_some_and(|document_positions| phrase.is_match_at(document, &document_positions))
+        })
+        .map(|(offset_id, _)| *offset_id)
+        .collect()
+}
+
+fn invalid(message: String) -> MetadataIndexError {
+    MetadataIndexError::InvalidOperand(format!("Invalid text query: {}", message))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    // A phrase or a word with its words and the slop of a phrase.
+    Term(Phrase),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Term(phrase) => write!(f, "\"{}\"", phrase.text()),
+        }
+    }
+}
+
+fn lex(query: &str) -> Result<Vec<Token>, MetadataIndexError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::LeftParen),
+            ')' => tokens.push(Token::RightParen),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => return Err(invalid(String::from("unterminated phrase"))),
+                    }
+                }
+                let slop = match chars.peek() {
+                    Some('~') => {
+                        chars.next();
+                        let mut digits = String::new();
+                        while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                            digits.push(*c);
+                            chars.next();
+                        }
+                        match digits.parse::<u32>() {
+                            Ok(slop) => Some(slop),
+                            Err(_) => {
+                                return Err(invalid(format!(
+                                    "invalid proximity after \"{}\"",
+                                    text
+                                )))
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+                tokens.push(Token::Term(term(&text, slop, false)?));
+            }
+            c => {
+                let mut text = String::from(c);
+                while let Some(c) = chars
+                    .peek()
+                    .filter(|c| !c.is_whitespace() && !matches!(c, '(' | ')' | '"'))
+                {
+                    text.push(*c);
+                    chars.next();
+                }
+                tokens.push(match text.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => {
+                        let word = text.strip_suffix('*').unwrap_or(&text);
+                        if word.contains('*') {
+                            return Err(invalid(format!("\"{}\" has * before its end", text)));
+                        }
+                        Token::Term(term(word, None, word.len() < text.len())?)
+                    }
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn term(text: &str, slop: Option<u32>, prefix: bool) -> Result<Phrase, MetadataIndexError> {
+    let words: Vec<String> = text.split_whitespace().map(String::from).collect();
+    if words.is_empty() {
+        return Err(invalid(format!("\"{}\" has no words", text)));
+    }
+    Ok(Phrase {
+        words,
+        slop,
+        prefix,
+    })
+}
+
+struct Parser<'me> {
+    tokens: &'me [Token],
+    index: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn parse_or(&mut self) -> Result<TextQuery, MetadataIndexError> {
+        let mut children = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.index += 1;
+            children.push(self.parse_and()?);
+        }
+        Ok(match children.len() {
+            1 => children.remove(0),
+            _ => TextQuery::Or(children),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<TextQuery, MetadataIndexError> {
+        let mut children = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::RightParen) => break,
+                Some(Token::And) => {
+                    self.index += 1;
+                    children.push(self.parse_unary()?);
+                }
+                Some(_) => children.push(self.parse_unary()?),
+            }
+        }
+        Ok(match children.len() {
+            1 => children.remove(0),
+            _ => TextQuery::And(children),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<TextQuery, MetadataIndexError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.index += 1;
+                Ok(TextQuery::Not(Box::new(self.parse_unary()?)))
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<TextQuery, MetadataIndexError> {
+        let tokens = self.tokens;
+        let token = match tokens.get(self.index) {
+            Some(token) => token,
+            None => return Err(invalid(String::from("unexpected end of query"))),
+        };
+        self.index += 1;
+        match token {
+            Token::LeftParen => {
+                let text_query = self.parse_or()?;
+                match self.peek() {
+                    Some(Token::RightParen) => {
+                        self.index += 1;
+                        Ok(text_query)
+                    }
+                    _ => Err(invalid(String::from("unclosed parenthesis"))),
+                }
+            }
+            Token::Term(phrase) => Ok(TextQuery::Phrase(phrase.clone())),
+            token => Err(invalid(format!("unexpected {}", token))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{ChromaError, ErrorCodes};
+
+    fn phrase(words: &[&str], slop: Option<u32>) -> TextQuery {
+        TextQuery::Phrase(Phrase {
+            words: words.iter().map(|word| word.to_string()).collect(),
+            slop,
+            prefix: false,
+        })
+    }
+
+    fn prefix(word: &str) -> TextQuery {
+        TextQuery::Phrase(Phrase {
+            words: vec![word.to_string()],
+            slop: None,
+            prefix: true,
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            TextQuery::parse(r#"cat AND "black dog"~2 OR NOT (fish* bird)"#).unwrap(),
+            TextQuery::Or(vec![
+                TextQuery::And(vec![
+                    phrase(&["cat"], None),
+                    phrase(&["black", "dog"], Some(2)),
+                ]),
+                TextQuery::Not(Box::new(TextQuery::And(vec![
+                    prefix("fish"),
+                    phrase(&["bird"], None),
+                ]))),
+            ])
+        );
+        // Only whitespace separates the words of a phrase.
+        assert_eq!(
+            TextQuery::parse("\"e-mail  me\"").unwrap(),
+            phrase(&["e-mail", "me"], None)
+        );
+
+        for query in [
+            "", "cat AND", "(cat", "cat)", "\"cat", "\"cat\"~", "OR cat", "*", "\"\"",
+        ] {
+            let err = TextQuery::parse(query).unwrap_err();
+            assert_eq!(err.code(), ErrorCodes::InvalidArgument, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_is_match() {
+        let document = "The quick brown fox jumps over the lazy dog.";
+        let cases = [
+            ("fox", true),
+            ("fo", false),
+            ("fo*", true),
+            ("ox*", false),
+            ("row", false),
+            ("dog", true),
+            ("og", false),
+            ("Fox", false),
+            ("fox dog", true),
+            ("fox AND cat", false),
+            ("fox OR cat", true),
+            ("NOT cat", true),
+            ("fox NOT dog", false),
+            ("\"quick brown\"", true),
+            ("\"brown quick\"", false),
+            ("\"dog.\"", true),
+            ("\"lazy do\"", false),
+            ("\"he quick\"", false),
+            ("\"quick fox\"", false),
+            // "brown" is between the words
+            ("\"quick fox\"~0", false),
+            ("\"quick fox\"~1", true),
+            ("\"fox quick\"~1", true),
+            ("\"quick dog\"~5", false),
+            ("\"quick dog\"~6", true),
+            ("\"fox over\"~0", false),
+            ("\"fox over\"~1", true),
+            ("\"fox over\"", false),
+            // The document has "the" once
+            ("\"the lazy the\"~10", false),
+            // "brown" is not the word "brow"
+            ("\"brow fox\"~1", false),
+            ("(cat OR dog) jump*", true),
+            ("(cat OR dog) jump", false),
+        ];
+        for (query, expected) in cases {
+            let text_query = TextQuery::parse(query).unwrap();
+            assert_eq!(text_query.is_match(document), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_literal_plan() {
+        let text_query =
+            TextQuery::parse("(\"black cat\" OR \"white cat\"~2 OR dog*) NOT fish").unwrap();
+        assert_eq!(
+            text_query.literal_plan(),
+            LiteralPlan::And(vec![
+                LiteralPlan::Or(vec![
+                    LiteralPlan::Literal(String::from("black cat")),
+                    LiteralPlan::And(vec![
+                        LiteralPlan::Literal(String::from("white")),
+                        LiteralPlan::Literal(String::from("cat")),
+                    ]),
+                    LiteralPlan::Literal(String::from("dog")),
+                ]),
+                LiteralPlan::All,
+            ])
+        );
+    }
+}
@@ -3,6 +3,7 @@ use crate::blockstore::positional_posting_list_value::{
 };
 use crate::blockstore::{BlockfileFlusher, BlockfileReader, BlockfileWriter};
 use crate::errors::{ChromaError, ErrorCodes};
+use crate::index::fulltext::pattern::DocumentPattern;
 use crate::index::fulltext::tokenizer::ChromaTokenizer;
 use crate::index::metadata::types::MetadataIndexError;
 use crate::types::{BooleanOperator, WhereDocument, WhereDocumentOperator};
@@ -10,6 +11,7 @@ use crate::utils::{merge_sorted_vecs_conjunction, merge_sorted_vecs_disjunction}
 
 use arrow::array::Int32Array;
 use parking_lot::Mutex;
+use roaring::RoaringBitmap;
 use std::collections::HashMap;
 use std::sync::Arc;
 use thiserror::Error;
@@ -397,6 +399,17 @@ impl FullTextIndexFlusher {
     }
 }
 
+// Saturates the term frequency component of BM25. The index keeps no
+// document lengths, so documents are not normalized by length.
+const MATCH_SATURATION: f32 = 1.2;
+
+/// Scores a document by the number of times it matches a query. The score
+/// grows with the matches but each match adds less than the one before.
+pub(crate) fn match_score(matches: usize) -> f32 {
+    let matches = matches as f32;
+    matches * (MATCH_SATURATION + 1.0) / (matches + MATCH_SATURATION)
+}
+
 #[derive(Clone)]
 pub(crate) struct FullTextIndexReader<'me> {
     posting_lists_blockfile_reader: BlockfileReader<'me, u32, Int32Array>,
@@ -423,117 +436,233 @@ impl<'me> FullTextIndexReader<'me> {
         tokens
     }
 
-    pub async fn search(&self, query: &str) -> Result<Vec<i32>, FullTextIndexError> {
+    /// An upper bound on the number of documents that contain the query, the
+    /// frequency of its rarest token. None if the query is too short to have
+    /// any tokens.
+    pub async fn estimate_matches(&self, query: &str) -> Result<Option<u32>, FullTextIndexError> {
         let binding = self.encode_tokens(query);
         let tokens = binding.get_tokens();
-
-        // Get query tokens sorted by frequency.
-        let mut token_frequencies: Vec<(String, u32)> = vec![];
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        let mut estimate = u32::MAX;
         for token in tokens {
-            // TODO better error matching (NotFoundError should return Ok(vec![])) but some others should error.
             let res = self
                 .frequencies_blockfile_reader
                 .get_by_prefix(token.text.as_str())
                 .await?;
-            if res.len() == 0 {
-                return Ok(vec![]);
+            // Frequencies are stored in the keys.
+            let frequency = res.first().map_or(0, |(_, frequency, _)| *frequency);
+            estimate = estimate.min(frequency);
+            if estimate == 0 {
+                break;
             }
-            if res.len() > 1 {
-                panic!("Invariant violation. Multiple frequency values found for a token.");
+        }
+        Ok(Some(estimate))
+    }
+
+    pub async fn search(&self, query: &str) -> Result<Vec<i32>, FullTextIndexError> {
+        let candidates = self.match_positions(query, None).await?;
+        Ok(candidates.into_keys().map(|doc_id| doc_id as i32).collect())
+    }
+
+    /// The sorted byte offsets where the query starts in each document that
+    /// contains it. When `offset_ids` is given only those documents have their
+    /// positions checked.
+    pub(crate) async fn search_positions(
+        &self,
+        query: &str,
+        offset_ids: Option<&RoaringBitmap>,
+    ) -> Result<HashMap<u32, Vec<u32>>, FullTextIndexError> {
+        let candidates = self.match_positions(query, offset_ids).await?;
+        Ok(candidates
+            .into_iter()
+            .map(|(offset_id, positions)| {
+                let mut positions: Vec<u32> = positions
+                    .into_iter()
+                    .map(|position| position as u32)
+                    .collect();
+                positions.sort();
+                positions.dedup();
+                (offset_id, positions)
+            })
+            .collect())
+    }
+
+    /// The documents that can contain a substring within `max_edits` edits
+    /// of the query. An edit changes at most as many n-grams of the query as
+    /// there are characters in an n-gram, so a matching document shares the
+    /// other n-grams with the query. None when the edits can change every
+    /// n-gram, any document can match then.
+    pub(crate) async fn fuzzy_candidates(
+        &self,
+        query: &str,
+        max_edits: u32,
+    ) -> Result<Option<RoaringBitmap>, FullTextIndexError> {
+        let binding = self.encode_tokens(query);
+        let tokens = binding.get_tokens();
+        // All n-grams have the same number of characters.
+        let ngram_len = tokens.first().map_or(0, |token| token.text.chars().count());
+        let shared_needed = tokens.len() as i64 - max_edits as i64 * ngram_len as i64;
+        if shared_needed <= 0 {
+            return Ok(None);
+        }
+
+        let mut token_counts: HashMap<&str, u32> = HashMap::new();
+        for token in tokens {
+            *token_counts.entry(token.text.as_str()).or_default() += 1;
+        }
+        let mut token_frequencies = Vec::with_capacity(token_counts.len());
+        for (token, count) in token_counts {
+            let frequency = self.get_frequencies_for_token(token).await?;
+            // The n-gram is in no document, typos make that common.
+            if frequency > 0 {
+                token_frequencies.push((token, count, frequency));
             }
-            let res = res[0];
-            if res.1 <= 0 {
-                panic!("Invariant violation. Zero frequency token found.");
+        }
+        // Prefix filtering: a document that shares too few of the rarer
+        // n-grams cannot make up for it with the most common ones, so those
+        // are never read.
+        token_frequencies.sort_by_key(|(_, _, frequency)| *frequency);
+        let mut skipped = 0;
+        while let Some((_, count, _)) = token_frequencies.last() {
+            if skipped + *count as i64 >= shared_needed {
+                break;
             }
-            // Throw away the "value" since we store frequencies in the keys.
-            token_frequencies.push((token.text.to_string(), res.1));
+            skipped += *count as i64;
+            token_frequencies.pop();
         }
-        // TODO sort by frequency. This adds an additional layer of complexity
-        // with repeat characters where we need to keep track of which positions
-        // for the character have been seen/used in the matching algorithm. By
-        // leaving them ordered per the query, we can stick to the more straightforward
-        // but less efficient matching algorithm.
-        // token_frequencies.sort_by(|a, b| a.1.cmp(&b.1));
-
-        // Populate initial candidates with the least-frequent token's posting list.
-        // doc ID -> possible starting locations for the query.
-        let mut candidates: HashMap<u32, Vec<i32>> = HashMap::new();
-        let first_token = token_frequencies[0].0.as_str();
-        let first_token_offset = tokens[0].offset_from as i32;
-        let first_token_positional_posting_list = self
-            .posting_lists_blockfile_reader
-            .get_by_prefix(first_token)
-            .await
-            .unwrap();
-        for (_, doc_id, positions) in first_token_positional_posting_list.iter() {
-            let positions_vec: Vec<i32> = positions
-                .iter()
-                .map(|x| x.unwrap() - first_token_offset)
-                .collect();
-            candidates.insert(*doc_id, positions_vec);
+        let shared_needed = shared_needed - skipped;
+
+        // A document shares a repeated n-gram of the query at most as many
+        // times as it contains the n-gram.
+        let mut shared: HashMap<u32, u32> = HashMap::new();
+        for (token, count, _) in token_frequencies {
+            let positional_posting_list = self
+                .posting_lists_blockfile_reader
+                .get_by_prefix(token)
+                .await?;
+            for (_, doc_id, positions) in positional_posting_list.iter() {
+                *shared.entry(*doc_id).or_default() += count.min(positions.len() as u32);
+            }
         }
+        Ok(Some(
+            shared
+                .into_iter()
+                .filter(|(_, shared)| *shared as i64 >= shared_needed)
+                .map(|(doc_id, _)| doc_id)
+                .collect(),
+        ))
+    }
 
-        // Iterate through the rest of the tokens, intersecting the posting lists with the candidates.
-        let mut token_offset = 0;
-        for (token, _) in token_frequencies[1..].iter() {
-            token_offset += 1;
+    // Returns the start positions of the query in every document that
+    // contains it, or in those of `offset_ids` when given.
+    async fn match_positions(
+        &self,
+        query: &str,
+        offset_ids: Option<&RoaringBitmap>,
+    ) -> Result<HashMap<u32, Vec<i32>>, FullTextIndexError> {
+        let binding = self.encode_tokens(query);
+        let tokens = binding.get_tokens();
+        // A query shorter than the n-gram size has no tokens to look up, the
+        // caller has to scan the documents instead.
+        if tokens.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        // Every distinct token of the query with the byte offsets it occurs
+        // at. A repeated token has to be found at each of its offsets.
+        let mut token_offsets: Vec<(&str, Vec<i32>)> = vec![];
+        for token in tokens {
+            let offset = token.offset_from as i32;
+            match token_offsets
+                .iter_mut()
+                .find(|(text, _)| *text == token.text.as_str())
+            {
+                Some((_, offsets)) => offsets.push(offset),
+                None => token_offsets.push((token.text.as_str(), vec![offset])),
+            }
+        }
+
+        // The rarest tokens narrow the candidates down the most, so they are
+        // looked up first.
+        let mut token_frequencies = Vec::with_capacity(token_offsets.len());
+        for (token, offsets) in token_offsets {
+            let frequency = self.get_frequencies_for_token(token).await?;
+            if frequency == 0 {
+                return Ok(HashMap::new());
+            }
+            token_frequencies.push((token, offsets, frequency));
+        }
+        token_frequencies.sort_by_key(|(_, _, frequency)| *frequency);
+
+        // Intersect the documents of the tokens before checking positions.
+        let mut doc_ids: Option<RoaringBitmap> = offset_ids.cloned();
+        let mut posting_lists = Vec::with_capacity(token_frequencies.len());
+        for (token, offsets, _) in token_frequencies {
             let positional_posting_list = self
                 .posting_lists_blockfile_reader
-                .get_by_prefix(token.as_str())
-                .await
-                .unwrap();
-            // TODO once we sort by frequency, we need to find the token position
-            // here, taking into account which positions for repeats of the same
-            // token have already been used up.
-            // let token_offset = tokens
-            //     .iter()
-            //     .find(|t| t.text == *token)
-            //     .unwrap()
-            //     .offset_from as i32;
-            let mut new_candidates: HashMap<u32, Vec<i32>> = HashMap::new();
-            for (doc_id, positions) in candidates.iter() {
-                let mut new_positions = vec![];
-                for position in positions {
-                    if let Some(positions) =
-                        // Find the positional posting list with second item = to doc_id
-                        positional_posting_list
-                            .iter()
-                            .find(|x| x.1 == *doc_id)
-                            .map(|x| &x.2)
-                    {
-                        for pos in positions.iter() {
-                            match pos {
-                                None => {
-                                    // This should never happen since we only store positions for the doc_id
-                                    // in the positional posting list.
-                                    return Err(
-                                        FullTextIndexError::EmptyValueInPositionalPostingList,
-                                    );
-                                }
-                                Some(pos) => {
-                                    if pos == position + token_offset {
-                                        new_positions.push(*position);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                if !new_positions.is_empty() {
-                    new_candidates.insert((*doc_id) as u32, new_positions);
+                .get_by_prefix(token)
+                .await?;
+            let token_doc_ids: RoaringBitmap = positional_posting_list
+                .iter()
+                .map(|(_, doc_id, _)| *doc_id)
+                .collect();
+            let intersection = match doc_ids {
+                Some(doc_ids) => doc_ids & token_doc_ids,
+                None => token_doc_ids,
+            };
+            if intersection.is_empty() {
+                return Ok(HashMap::new());
+            }
+            let posting_list: HashMap<u32, Int32Array> = positional_posting_list
+                .into_iter()
+                .filter(|(_, doc_id, _)| intersection.contains(*doc_id))
+                .map(|(_, doc_id, positions)| (doc_id, positions))
+                .collect();
+            doc_ids = Some(intersection);
+            posting_lists.push((offsets, posting_list));
+        }
+
+        // A document matches where the query can start so that every token is
+        // found at its offset. The start positions come from the rarest token.
+        let mut candidates: HashMap<u32, Vec<i32>> = HashMap::new();
+        for doc_id in doc_ids.unwrap_or_default() {
+            let mut starts: Option<Vec<i32>> = None;
+            for (offsets, posting_list) in posting_lists.iter() {
+                let positions = match posting_list.get(&doc_id) {
+                    Some(positions) => positions,
+                    None => return Err(FullTextIndexError::InvariantViolation),
+                };
+                let mut positions = positions_of(positions)?;
+                positions.sort_unstable();
+                let mut token_starts = match starts {
+                    Some(starts) => starts,
+                    None => positions
+                        .iter()
+                        .map(|position| position - offsets[0])
+                        .collect(),
+                };
+                token_starts.retain(|start| {
+                    offsets
+                        .iter()
+                        .all(|offset| positions.binary_search(&(start + offset)).is_ok())
+                });
+                let done = token_starts.is_empty();
+                starts = Some(token_starts);
+                if done {
+                    break;
                 }
             }
-            if new_candidates.is_empty() {
-                return Ok(vec![]);
+            match starts {
+                Some(starts) if !starts.is_empty() => {
+                    candidates.insert(doc_id, starts);
+                }
+                _ => {}
             }
-            candidates = new_candidates;
         }
 
-        let mut results = vec![];
-        for (doc_id, _) in candidates.drain() {
-            results.push(doc_id as i32);
-        }
-        return Ok(results);
+        Ok(candidates)
     }
 
     // We use this to implement deletes in the Writer. A delete() is implemented
@@ -549,8 +678,7 @@ impl<'me> FullTextIndexReader<'me> {
             .await?;
         let mut results = vec![];
         for (_, doc_id, positions) in positional_posting_list.iter() {
-            let positions_vec: Vec<i32> = positions.iter().map(|x| x.unwrap()).collect();
-            results.push((*doc_id, positions_vec));
+            results.push((*doc_id, positions_of(positions)?));
         }
         Ok(results)
     }
@@ -562,46 +690,71 @@ impl<'me> FullTextIndexReader<'me> {
             .frequencies_blockfile_reader
             .get_by_prefix(token)
             .await?;
-        if res.len() == 0 {
-            return Ok(0);
-        }
-        if res.len() > 1 {
-            panic!("Invariant violation. Multiple frequency values found for a token.");
+        match res.as_slice() {
+            [] => Ok(0),
+            // Frequencies are stored in the keys.
+            [(_, frequency, _)] => Ok(*frequency),
+            _ => {
+                tracing::error!("Multiple frequency values found for token {}", token);
+                Err(FullTextIndexError::InvariantViolation)
+            }
         }
-        Ok(res[0].1)
     }
 }
 
+// Positions are stored for every document of a posting list, so a missing
+// value means the posting list is corrupt.
+fn positions_of(positions: &Int32Array) -> Result<Vec<i32>, FullTextIndexError> {
+    positions
+        .iter()
+        .map(|position| position.ok_or(FullTextIndexError::EmptyValueInPositionalPostingList))
+        .collect()
+}
+
+/// Evaluates a where document clause, asking the callback for the sorted
+/// offset ids whose document matches each pattern. `$not_contains` is the
+/// complement of the `$contains` matches within the sorted offset ids that
+/// the universe callback returns.
 pub(crate) fn process_where_document_clause_with_callback<
-    F: Fn(&str, WhereDocumentOperator) -> Vec<i32>,
+    F: Fn(&DocumentPattern) -> Vec<i32>,
+    G: Fn() -> Vec<i32>,
 >(
     where_document_clause: &WhereDocument,
     callback: &F,
+    universe_callback: &G,
 ) -> Result<Vec<usize>, MetadataIndexError> {
     let mut results = vec![];
     match where_document_clause {
         WhereDocument::DirectWhereDocumentComparison(direct_document_comparison) => {
             match &direct_document_comparison.operator {
-                WhereDocumentOperator::Contains => {
-                    let result = callback(
+                WhereDocumentOperator::NotContains => {
+                    let pattern = DocumentPattern::new(
                         &direct_document_comparison.document,
-                        WhereDocumentOperator::Contains,
-                    );
-                    results = result.iter().map(|x| *x as usize).collect();
+                        &WhereDocumentOperator::Contains,
+                    )?;
+                    let contains = callback(&pattern);
+                    results = universe_callback()
+                        .into_iter()
+                        .filter(|x| contains.binary_search(x).is_err())
+                        .map(|x| x as usize)
+                        .collect();
                 }
-                WhereDocumentOperator::NotContains => {
-                    todo!();
+                operator => {
+                    let pattern =
+                        DocumentPattern::new(&direct_document_comparison.document, operator)?;
+                    let result = callback(&pattern);
+                    results = result.iter().map(|x| *x as usize).collect();
                 }
             }
         }
         WhereDocument::WhereDocumentChildren(where_document_children) => {
             let mut first_iteration = true;
             for child in where_document_children.children.iter() {
-                let child_results: Vec<usize> =
-                    match process_where_document_clause_with_callback(&child, callback) {
-                        Ok(result) => result,
-                        Err(_) => vec![],
-                    };
+                let child_results = process_where_document_clause_with_callback(
+                    child,
+                    callback,
+                    universe_callback,
+                )?;
                 if first_iteration {
                     results = child_results;
                     first_iteration = false;
@@ -622,11 +775,33 @@ pub(crate) fn process_where_document_clause_with_callback<
     return Ok(results);
 }
 
+/// The `$contains` queries of a where document clause, which are the matches
+/// worth highlighting in the documents of the results.
+pub(crate) fn contains_queries(where_document_clause: &WhereDocument) -> Vec<String> {
+    match where_document_clause {
+        WhereDocument::DirectWhereDocumentComparison(direct_document_comparison) => {
+            match direct_document_comparison.operator {
+                WhereDocumentOperator::Contains => {
+                    vec![direct_document_comparison.document.clone()]
+                }
+                _ => vec![],
+            }
+        }
+        WhereDocument::WhereDocumentChildren(where_document_children) => where_document_children
+            .children
+            .iter()
+            .flat_map(contains_queries)
+            .collect(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::blockstore::provider::BlockfileProvider;
     use crate::index::fulltext::tokenizer::TantivyChromaTokenizer;
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::types::{DirectDocumentComparison, WhereDocumentChildren};
     use tantivy::tokenizer::NgramTokenizer;
 
     #[test]
@@ -882,6 +1057,258 @@ mod tests {
         assert_eq!(res, vec![1]);
     }
 
+    #[tokio::test]
+    async fn test_search_positions() {
+        let provider = BlockfileProvider::new_memory();
+        let pl_blockfile_writer = provider.create::<u32, &Int32Array>().unwrap();
+        let freq_blockfile_writer = provider.create::<u32, &str>().unwrap();
+        let pl_blockfile_id = pl_blockfile_writer.id();
+        let freq_blockfile_id = freq_blockfile_writer.id();
+
+        let tokenizer = Box::new(TantivyChromaTokenizer::new(Box::new(
+            NgramTokenizer::new(1, 1, false).unwrap(),
+        )));
+        let mut index_writer =
+            FullTextIndexWriter::new(None, pl_blockfile_writer, freq_blockfile_writer, tokenizer);
+        index_writer
+            .add_document("hello world hello", 1)
+            .await
+            .unwrap();
+        index_writer.add_document("    hello ", 2).await.unwrap();
+        index_writer.add_document("héllo hello", 3).await.unwrap();
+        index_writer.write_to_blockfiles().await.unwrap();
+        let flusher = index_writer.commit().unwrap();
+        flusher.flush().await.unwrap();
+
+        let freq_blockfile_reader = provider.open::<u32, u32>(&freq_blockfile_id).await.unwrap();
+        let pl_blockfile_reader = provider
+            .open::<u32, Int32Array>(&pl_blockfile_id)
+            .await
+            .unwrap();
+        let tokenizer = Box::new(TantivyChromaTokenizer::new(Box::new(
+            NgramTokenizer::new(1, 1, false).unwrap(),
+        )));
+        let index_reader =
+            FullTextIndexReader::new(pl_blockfile_reader, freq_blockfile_reader, tokenizer);
+
+        let res = index_reader.search_positions("hello", None).await.unwrap();
+        assert_eq!(
+            res,
+            HashMap::from([
+                (1, vec![0, 12]),
+                (2, vec![4]),
+                // Positions are byte offsets.
+                (3, vec![7]),
+            ])
+        );
+        assert!(match_score(2) > match_score(1));
+        assert!(match_score(2) < 2.0 * match_score(1));
+
+        let res = index_reader
+            .search_positions("hello hello", None)
+            .await
+            .unwrap();
+        assert!(res.is_empty());
+
+        // Only the given documents are searched
+        let res = index_reader
+            .search_positions("hello", Some(&RoaringBitmap::from_iter([1, 3])))
+            .await
+            .unwrap();
+        assert_eq!(res, HashMap::from([(1, vec![0, 12]), (3, vec![7])]));
+        let res = index_reader
+            .search_positions("hello", Some(&RoaringBitmap::new()))
+            .await
+            .unwrap();
+        assert!(res.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fuzzy_candidates() {
+        // The memory blockfile fails to read n-grams that are in no document,
+        // the arrow blockfile finds no documents for them.
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let provider = BlockfileProvider::new_arrow(storage);
+        let pl_blockfile_writer = provider.create::<u32, &Int32Array>().unwrap();
+        let freq_blockfile_writer = provider.create::<u32, u32>().unwrap();
+        let pl_blockfile_id = pl_blockfile_writer.id();
+        let freq_blockfile_id = freq_blockfile_writer.id();
+
+        let tokenizer = Box::new(TantivyChromaTokenizer::new(Box::new(
+            NgramTokenizer::new(3, 3, false).unwrap(),
+        )));
+        let mut index_writer =
+            FullTextIndexWriter::new(None, pl_blockfile_writer, freq_blockfile_writer, tokenizer);
+        index_writer
+            .add_document("the final release", 1)
+            .await
+            .unwrap();
+        index_writer
+            .add_document("a quick brown fox", 2)
+            .await
+            .unwrap();
+        index_writer.add_document("released", 3).await.unwrap();
+        index_writer.write_to_blockfiles().await.unwrap();
+        let flusher = index_writer.commit().unwrap();
+        flusher.flush().await.unwrap();
+
+        let freq_blockfile_reader = provider.open::<u32, u32>(&freq_blockfile_id).await.unwrap();
+        let pl_blockfile_reader = provider
+            .open::<u32, Int32Array>(&pl_blockfile_id)
+            .await
+            .unwrap();
+        let tokenizer = Box::new(TantivyChromaTokenizer::new(Box::new(
+            NgramTokenizer::new(3, 3, false).unwrap(),
+        )));
+        let index_reader =
+            FullTextIndexReader::new(pl_blockfile_reader, freq_blockfile_reader, tokenizer);
+
+        // "relase" shares "rel" and "ase" with "release", the other n-grams
+        // are in no document.
+        let res = index_reader.fuzzy_candidates("relase", 1).await.unwrap();
+        assert_eq!(res, Some(RoaringBitmap::from_iter([1, 3])));
+        let res = index_reader.fuzzy_candidates("relase", 0).await.unwrap();
+        assert_eq!(res, Some(RoaringBitmap::new()));
+        // Only the rarer n-grams are read, a matching document shares at least
+        // one of them.
+        let res = index_reader.fuzzy_candidates("the fnal", 1).await.unwrap();
+        assert_eq!(res, Some(RoaringBitmap::from_iter([1])));
+        // Two edits can change all four n-grams of the query, and a query
+        // shorter than an n-gram has none.
+        let res = index_reader.fuzzy_candidates("relase", 2).await.unwrap();
+        assert_eq!(res, None);
+        let res = index_reader.fuzzy_candidates("re", 0).await.unwrap();
+        assert_eq!(res, None);
+    }
+
+    #[tokio::test]
+    async fn test_rarest_token_repeated_in_query() {
+        let provider = BlockfileProvider::new_memory();
+        let pl_blockfile_writer = provider.create::<u32, &Int32Array>().unwrap();
+        let freq_blockfile_writer = provider.create::<u32, &str>().unwrap();
+        let pl_blockfile_id = pl_blockfile_writer.id();
+        let freq_blockfile_id = freq_blockfile_writer.id();
+
+        let tokenizer = Box::new(TantivyChromaTokenizer::new(Box::new(
+            NgramTokenizer::new(1, 1, false).unwrap(),
+        )));
+        let mut index_writer =
+            FullTextIndexWriter::new(None, pl_blockfile_writer, freq_blockfile_writer, tokenizer);
+        index_writer.add_document("aaaaab", 1).await.unwrap();
+        index_writer.add_document("aab", 2).await.unwrap();
+        index_writer.add_document("ab aab ba", 3).await.unwrap();
+        index_writer.add_document("bab", 4).await.unwrap();
+        index_writer.add_document("aaa", 5).await.unwrap();
+        index_writer.write_to_blockfiles().await.unwrap();
+        let flusher = index_writer.commit().unwrap();
+        flusher.flush().await.unwrap();
+
+        let freq_blockfile_reader = provider.open::<u32, u32>(&freq_blockfile_id).await.unwrap();
+        let pl_blockfile_reader = provider
+            .open::<u32, Int32Array>(&pl_blockfile_id)
+            .await
+            .unwrap();
+        let tokenizer = Box::new(TantivyChromaTokenizer::new(Box::new(
+            NgramTokenizer::new(1, 1, false).unwrap(),
+        )));
+        let index_reader =
+            FullTextIndexReader::new(pl_blockfile_reader, freq_blockfile_reader, tokenizer);
+
+        // "b" is rarer than "a" but comes last in the query.
+        let positions = |matches: HashMap<u32, Vec<u32>>| {
+            let mut positions: Vec<(u32, Vec<u32>)> = matches.into_iter().collect();
+            positions.sort();
+            positions
+        };
+        let res = index_reader.search_positions("aab", None).await.unwrap();
+        assert_eq!(
+            positions(res),
+            vec![(1, vec![3]), (2, vec![0]), (3, vec![3])]
+        );
+
+        // "b" occurs at both ends of the query.
+        let res = index_reader.search_positions("bab", None).await.unwrap();
+        assert_eq!(positions(res), vec![(4, vec![0])]);
+
+        let res = index_reader.search_positions("ba", None).await.unwrap();
+        assert_eq!(positions(res), vec![(3, vec![7]), (4, vec![0])]);
+    }
+
+    #[test]
+    fn test_contains_queries() {
+        let direct = |document: &str, operator| {
+            WhereDocument::DirectWhereDocumentComparison(DirectDocumentComparison {
+                document: document.to_string(),
+                operator,
+            })
+        };
+        let where_document = WhereDocument::WhereDocumentChildren(WhereDocumentChildren {
+            children: vec![
+                direct("hello", WhereDocumentOperator::Contains),
+                direct("world", WhereDocumentOperator::NotContains),
+                WhereDocument::WhereDocumentChildren(WhereDocumentChildren {
+                    children: vec![
+                        direct("cat", WhereDocumentOperator::Contains),
+                        direct("d.g", WhereDocumentOperator::Regex),
+                    ],
+                    operator: BooleanOperator::Or,
+                }),
+            ],
+            operator: BooleanOperator::And,
+        });
+        assert_eq!(
+            contains_queries(&where_document),
+            vec![String::from("hello"), String::from("cat")]
+        );
+    }
+
+    #[test]
+    fn test_process_not_contains_with_callback() {
+        let documents = [(1, "hello world"), (2, "hello"), (4, "world"), (5, "cat")];
+        let callback = |pattern: &DocumentPattern| {
+            documents
+                .iter()
+                .filter(|(_, document)| pattern.is_match(document))
+                .map(|(offset_id, _)| *offset_id)
+                .collect::<Vec<i32>>()
+        };
+        // Offset id 3 has no document and 5 is left out of the universe.
+        let universe_callback = || vec![1, 2, 3, 4];
+        let direct = |document: &str, operator| {
+            WhereDocument::DirectWhereDocumentComparison(DirectDocumentComparison {
+                document: document.to_string(),
+                operator,
+            })
+        };
+        let not_contains = direct("world", WhereDocumentOperator::NotContains);
+        assert_eq!(
+            process_where_document_clause_with_callback(
+                &not_contains,
+                &callback,
+                &universe_callback
+            )
+            .unwrap(),
+            vec![2, 3]
+        );
+        let where_document = WhereDocument::WhereDocumentChildren(WhereDocumentChildren {
+            children: vec![
+                direct("hello", WhereDocumentOperator::Contains),
+                not_contains,
+            ],
+            operator: BooleanOperator::And,
+        });
+        assert_eq!(
+            process_where_document_clause_with_callback(
+                &where_document,
+                &callback,
+                &universe_callback
+            )
+            .unwrap(),
+            vec![2]
+        );
+    }
+
     #[tokio::test]
     async fn test_multiple_simple_documents() {
         let provider = BlockfileProvider::new_memory();
@@ -1,14 +1,45 @@
 use std::ffi::CString;
 use std::ffi::{c_char, c_int};
 
+use crate::distance::binary::{pack_words, unpack_words};
+use crate::distance::DistanceFunction;
 use crate::errors::{ChromaError, ErrorCodes};
 
 use super::{Index, IndexConfig, PersistentIndex};
-use crate::types::{Metadata, MetadataValue, MetadataValueConversionError, Segment};
+use crate::types::{Metadata, MetadataValueConversionError, Segment};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 use thiserror::Error;
 use uuid::Uuid;
 
 const DEFAULT_MAX_ELEMENTS: usize = 10000;
+const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 100;
+const DEFAULT_EF_SEARCH: usize = 10;
+
+// The version of the persisted index files. Loading fails for versions newer
+// than this one.
+const HNSW_FORMAT_VERSION: u32 = 1;
+
+/// The file persisted next to the hnswlib files that describes the index.
+pub(crate) const HNSW_CONFIG_FILE: &str = "index_config.json";
+
+// Multi-vector records add each of their vectors to the graph under its own label,
+// the offset id of the record in the high bits and the position of the vector in
+// the low bits.
+const MULTI_VECTOR_POSITION_BITS: usize = 32;
+
+/// The offset id of the record that an element of a multi-vector index belongs to.
+pub(crate) fn multi_vector_offset_id(label: usize) -> usize {
+    label >> MULTI_VECTOR_POSITION_BITS
+}
+
+/// Whether `len` values make up a whole, non-zero number of vectors of `dimensionality`.
+// usize::is_multiple_of needs a newer toolchain than the worker builds with.
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+pub(crate) fn is_valid_multi_vector(len: usize, dimensionality: usize) -> bool {
+    dimensionality != 0 && len != 0 && len % dimensionality == 0
+}
 
 // https://doc.rust-lang.org/nomicon/ffi.html#representing-opaque-structs
 #[repr(C)]
@@ -37,6 +68,8 @@ pub(crate) struct HnswIndexConfig {
 pub(crate) enum HnswIndexFromSegmentError {
     #[error("Missing config `{0}`")]
     MissingConfig(String),
+    #[error("Invalid config `{0}`, it must be positive")]
+    InvalidConfig(String),
     #[error("Invalid metadata value")]
     MetadataValueError(#[from] MetadataValueConversionError),
 }
@@ -48,6 +81,9 @@ impl ChromaError for HnswIndexFromSegmentError {
 }
 
 impl HnswIndexConfig {
+    /// Reads the config from the hnsw metadata of the segment. Segments without
+    /// metadata take the defaults that indexes were always built with, segments
+    /// with metadata must set every hnsw key.
     pub(crate) fn from_segment(
         segment: &Segment,
         persist_path: &std::path::Path,
@@ -63,54 +99,182 @@ impl HnswIndexConfig {
         let metadata = match &segment.metadata {
             Some(metadata) => metadata,
             None => {
-                // TODO: This should error, but the configuration is not stored correctly
-                // after the configuration is refactored to be always stored and doesn't rely on defaults we can fix this
                 return Ok(HnswIndexConfig {
-                    max_elements: 1000,
-                    m: 16,
-                    ef_construction: 100,
-                    ef_search: 10,
+                    max_elements: DEFAULT_MAX_ELEMENTS,
+                    m: DEFAULT_M,
+                    ef_construction: DEFAULT_EF_CONSTRUCTION,
+                    ef_search: DEFAULT_EF_SEARCH,
                     random_seed: 0,
                     persist_path: persist_path.to_string(),
-                });
-                // return Err(Box::new(HnswIndexFromSegmentError::MissingConfig(
-                //     "metadata".to_string(),
-                // )))
+                })
             }
         };
 
-        fn get_metadata_value_as<'a, T>(
-            metadata: &'a Metadata,
+        fn get_metadata_value(
+            metadata: &Metadata,
             key: &str,
-        ) -> Result<T, Box<HnswIndexFromSegmentError>>
-        where
-            T: TryFrom<&'a MetadataValue, Error = MetadataValueConversionError>,
-        {
-            let res = match metadata.get(key) {
-                Some(value) => T::try_from(value),
+        ) -> Result<usize, Box<HnswIndexFromSegmentError>> {
+            let value = match metadata.get(key) {
+                Some(value) => value,
                 None => {
                     return Err(Box::new(HnswIndexFromSegmentError::MissingConfig(
                         key.to_string(),
                     )))
                 }
             };
-            match res {
-                Ok(value) => Ok(value),
+            match i32::try_from(value) {
+                Ok(value) if value > 0 => Ok(value as usize),
+                Ok(_) => Err(Box::new(HnswIndexFromSegmentError::InvalidConfig(
+                    key.to_string(),
+                ))),
                 Err(e) => Err(Box::new(HnswIndexFromSegmentError::MetadataValueError(e))),
             }
         }
 
-        let m = get_metadata_value_as::<i32>(metadata, "hnsw:M")?;
-        let ef_construction = get_metadata_value_as::<i32>(metadata, "hnsw:construction_ef")?;
-        let ef_search = get_metadata_value_as::<i32>(metadata, "hnsw:search_ef")?;
-        return Ok(HnswIndexConfig {
+        Ok(HnswIndexConfig {
             max_elements: DEFAULT_MAX_ELEMENTS,
-            m: m as usize,
-            ef_construction: ef_construction as usize,
-            ef_search: ef_search as usize,
+            m: get_metadata_value(metadata, "hnsw:M")?,
+            ef_construction: get_metadata_value(metadata, "hnsw:construction_ef")?,
+            ef_search: get_metadata_value(metadata, "hnsw:search_ef")?,
             random_seed: 0,
             persist_path: persist_path.to_string(),
-        });
+        })
+    }
+}
+
+/// The configuration an index was built with, persisted along with it so that
+/// it can be loaded without relying on the segment metadata.
+/// # Notes
+/// Indexes persisted before the configuration was saved do not have it. They
+/// are loaded with the configuration of the collection, which is persisted on
+/// their next save.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HnswPersistedConfig {
+    pub(crate) format_version: u32,
+    pub(crate) dimensionality: i32,
+    pub(crate) space: String,
+    pub(crate) multi_vector: bool,
+    pub(crate) m: usize,
+    pub(crate) ef_construction: usize,
+    pub(crate) ef_search: usize,
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum HnswPersistedConfigError {
+    #[error("Error reading or writing the index config: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Invalid index config: {0}")]
+    SerializationError(#[from] serde_json::Error),
+    #[error("Index format version {0} is newer than the supported version {HNSW_FORMAT_VERSION}")]
+    UnsupportedFormatVersion(u32),
+    #[error(
+        "Index has dimensionality {persisted} but the collection has dimensionality {expected}"
+    )]
+    DimensionalityMismatch { persisted: i32, expected: i32 },
+    #[error("Index has space `{persisted}` but the collection has space `{expected}`")]
+    SpaceMismatch { persisted: String, expected: String },
+    #[error("Index multi_vector is {persisted} but the collection multi_vector is {expected}")]
+    MultiVectorMismatch { persisted: bool, expected: bool },
+    #[error("Index has M {persisted} but the collection has M {expected}")]
+    MMismatch { persisted: usize, expected: usize },
+    #[error(
+        "Index has construction_ef {persisted} but the collection has construction_ef {expected}"
+    )]
+    EfConstructionMismatch { persisted: usize, expected: usize },
+}
+
+impl ChromaError for HnswPersistedConfigError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            HnswPersistedConfigError::IOError(_) => ErrorCodes::Internal,
+            HnswPersistedConfigError::SerializationError(_) => ErrorCodes::DataLoss,
+            HnswPersistedConfigError::UnsupportedFormatVersion(_) => ErrorCodes::FailedPrecondition,
+            HnswPersistedConfigError::DimensionalityMismatch { .. } => {
+                ErrorCodes::FailedPrecondition
+            }
+            HnswPersistedConfigError::SpaceMismatch { .. } => ErrorCodes::FailedPrecondition,
+            HnswPersistedConfigError::MultiVectorMismatch { .. } => ErrorCodes::FailedPrecondition,
+            HnswPersistedConfigError::MMismatch { .. } => ErrorCodes::FailedPrecondition,
+            HnswPersistedConfigError::EfConstructionMismatch { .. } => {
+                ErrorCodes::FailedPrecondition
+            }
+        }
+    }
+}
+
+impl HnswPersistedConfig {
+    fn new(index_config: &IndexConfig, hnsw_config: &HnswIndexConfig) -> Self {
+        HnswPersistedConfig {
+            format_version: HNSW_FORMAT_VERSION,
+            dimensionality: index_config.dimensionality,
+            space: index_config.distance_function.clone().into(),
+            multi_vector: index_config.multi_vector,
+            m: hnsw_config.m,
+            ef_construction: hnsw_config.ef_construction,
+            ef_search: hnsw_config.ef_search,
+        }
+    }
+
+    // Returns None for indexes persisted without a config
+    fn read(path: &Path) -> Result<Option<Self>, HnswPersistedConfigError> {
+        let bytes = match std::fs::read(path.join(HNSW_CONFIG_FILE)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(HnswPersistedConfigError::IOError(e)),
+        };
+        Ok(Some(serde_json::from_slice(&bytes)?))
+    }
+
+    fn write(&self, path: &Path) -> Result<(), HnswPersistedConfigError> {
+        std::fs::write(path.join(HNSW_CONFIG_FILE), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Checks that the index can be loaded for a collection with the config.
+    /// The ef of a query can change after the index was built, its other
+    /// parameters can not.
+    pub(crate) fn validate(
+        &self,
+        index_config: &IndexConfig,
+        hnsw_config: &HnswIndexConfig,
+    ) -> Result<(), HnswPersistedConfigError> {
+        if self.format_version > HNSW_FORMAT_VERSION {
+            return Err(HnswPersistedConfigError::UnsupportedFormatVersion(
+                self.format_version,
+            ));
+        }
+        if self.dimensionality != index_config.dimensionality {
+            return Err(HnswPersistedConfigError::DimensionalityMismatch {
+                persisted: self.dimensionality,
+                expected: index_config.dimensionality,
+            });
+        }
+        let space: String = index_config.distance_function.clone().into();
+        if self.space != space {
+            return Err(HnswPersistedConfigError::SpaceMismatch {
+                persisted: self.space.clone(),
+                expected: space,
+            });
+        }
+        if self.multi_vector != index_config.multi_vector {
+            return Err(HnswPersistedConfigError::MultiVectorMismatch {
+                persisted: self.multi_vector,
+                expected: index_config.multi_vector,
+            });
+        }
+        if self.m != hnsw_config.m {
+            return Err(HnswPersistedConfigError::MMismatch {
+                persisted: self.m,
+                expected: hnsw_config.m,
+            });
+        }
+        if self.ef_construction != hnsw_config.ef_construction {
+            return Err(HnswPersistedConfigError::EfConstructionMismatch {
+                persisted: self.ef_construction,
+                expected: hnsw_config.ef_construction,
+            });
+        }
+        Ok(())
     }
 }
 
@@ -124,6 +288,10 @@ impl HnswIndexConfig {
 pub(crate) struct HnswIndex {
     ffi_ptr: *const IndexPtrFFI,
     dimensionality: i32,
+    distance_function: DistanceFunction,
+    multi_vector: bool,
+    persist_path: String,
+    persisted_config: HnswPersistedConfig,
     pub(crate) id: Uuid,
 }
 
@@ -157,10 +325,7 @@ impl Index<HnswIndexConfig> for HnswIndex {
         match hnsw_config {
             None => return Err(Box::new(HnswIndexInitError::NoConfigProvided)),
             Some(config) => {
-                let distance_function_string: String =
-                    index_config.distance_function.clone().into();
-
-                let space_name = match CString::new(distance_function_string) {
+                let space_name = match CString::new(index_config.distance_function.hnsw_space()) {
                     Ok(space_name) => space_name,
                     Err(e) => {
                         return Err(Box::new(HnswIndexInitError::InvalidDistanceFunction(
@@ -193,6 +358,10 @@ impl Index<HnswIndexConfig> for HnswIndex {
                 let hnsw_index = HnswIndex {
                     ffi_ptr: ffi_ptr,
                     dimensionality: index_config.dimensionality,
+                    distance_function: index_config.distance_function.clone(),
+                    multi_vector: index_config.multi_vector,
+                    persist_path: config.persist_path.clone(),
+                    persisted_config: HnswPersistedConfig::new(index_config, config),
                     id,
                 };
                 hnsw_index.set_ef(config.ef_search);
@@ -202,7 +371,13 @@ impl Index<HnswIndexConfig> for HnswIndex {
     }
 
     fn add(&self, id: usize, vector: &[f32]) {
-        unsafe { add_item(self.ffi_ptr, vector.as_ptr(), id, true) }
+        // The binary spaces store packed words, see `DistanceFunction::hnsw_space`.
+        if self.distance_function.is_binary() {
+            let words = pack_words(vector);
+            unsafe { add_item(self.ffi_ptr, words.as_ptr() as *const f32, id, true) }
+        } else {
+            unsafe { add_item(self.ffi_ptr, vector.as_ptr(), id, true) }
+        }
     }
 
     fn delete(&self, id: usize) {
@@ -216,31 +391,16 @@ impl Index<HnswIndexConfig> for HnswIndex {
         allowed_ids: &[usize],
         disallowed_ids: &[usize],
     ) -> (Vec<usize>, Vec<f32>) {
-        let actual_k = std::cmp::min(k, self.len());
-        let mut ids = vec![0usize; actual_k];
-        let mut distance = vec![0.0f32; actual_k];
-        let mut total_result = actual_k;
-        unsafe {
-            total_result = knn_query(
-                self.ffi_ptr,
-                vector.as_ptr(),
-                k,
-                ids.as_mut_ptr(),
-                distance.as_mut_ptr(),
-                allowed_ids.as_ptr(),
-                allowed_ids.len(),
-                disallowed_ids.as_ptr(),
-                disallowed_ids.len(),
-            ) as usize;
-        }
-        if total_result < actual_k {
-            ids.truncate(total_result);
-            distance.truncate(total_result);
-        }
-        return (ids, distance);
+        self.query_with_ef(vector, k, None, allowed_ids, disallowed_ids)
     }
 
     fn get(&self, id: usize) -> Option<Vec<f32>> {
+        if self.distance_function.is_binary() {
+            let dimensionality = self.dimensionality as usize;
+            let mut words = vec![0u64; dimensionality.div_ceil(64)];
+            unsafe { get_item(self.ffi_ptr, id, words.as_mut_ptr() as *mut f32) };
+            return Some(unpack_words(&words, dimensionality));
+        }
         unsafe {
             let mut data: Vec<f32> = vec![0.0f32; self.dimensionality as usize];
             get_item(self.ffi_ptr, id, data.as_mut_ptr());
@@ -252,16 +412,32 @@ impl Index<HnswIndexConfig> for HnswIndex {
 impl PersistentIndex<HnswIndexConfig> for HnswIndex {
     fn save(&self) -> Result<(), Box<dyn ChromaError>> {
         unsafe { persist_dirty(self.ffi_ptr) };
+        // The ef of the index may have changed since it was built
+        let persisted_config = HnswPersistedConfig {
+            ef_search: self.get_ef(),
+            ..self.persisted_config.clone()
+        };
+        if let Err(e) = persisted_config.write(Path::new(&self.persist_path)) {
+            return Err(Box::new(e));
+        }
         Ok(())
     }
 
     fn load(
         path: &str,
         index_config: &IndexConfig,
+        hnsw_config: &HnswIndexConfig,
         id: Uuid,
     ) -> Result<Self, Box<dyn ChromaError>> {
-        let distance_function_string: String = index_config.distance_function.clone().into();
-        let space_name = match CString::new(distance_function_string) {
+        let persisted_config = match HnswPersistedConfig::read(Path::new(path)) {
+            Ok(Some(persisted_config)) => persisted_config,
+            Ok(None) => HnswPersistedConfig::new(index_config, hnsw_config),
+            Err(e) => return Err(Box::new(e)),
+        };
+        if let Err(e) = persisted_config.validate(index_config, hnsw_config) {
+            return Err(Box::new(e));
+        }
+        let space_name = match CString::new(index_config.distance_function.hnsw_space()) {
             Ok(space_name) => space_name,
             Err(e) => {
                 return Err(Box::new(HnswIndexInitError::InvalidDistanceFunction(
@@ -270,23 +446,73 @@ impl PersistentIndex<HnswIndexConfig> for HnswIndex {
             }
         };
         let ffi_ptr = unsafe { create_index(space_name.as_ptr(), index_config.dimensionality) };
-        let path = match CString::new(path.to_string()) {
+        let path_c = match CString::new(path.to_string()) {
             Ok(path) => path,
             Err(e) => return Err(Box::new(HnswIndexInitError::InvalidPath(e.to_string()))),
         };
         unsafe {
-            load_index(ffi_ptr, path.as_ptr(), true, true);
+            load_index(ffi_ptr, path_c.as_ptr(), true, true);
         }
         let hnsw_index = HnswIndex {
             ffi_ptr: ffi_ptr,
             dimensionality: index_config.dimensionality,
+            distance_function: index_config.distance_function.clone(),
+            multi_vector: index_config.multi_vector,
+            persist_path: path.to_string(),
+            persisted_config,
             id,
         };
+        // hnswlib does not persist the ef
+        hnsw_index.set_ef(hnsw_index.persisted_config.ef_search);
         Ok(hnsw_index)
     }
 }
 
 impl HnswIndex {
+    /// Queries the index exploring `ef` candidates, or the ef of the index if
+    /// None. The ef of the index stays the same for the other queries.
+    pub(crate) fn query_with_ef(
+        &self,
+        vector: &[f32],
+        k: usize,
+        ef: Option<usize>,
+        allowed_ids: &[usize],
+        disallowed_ids: &[usize],
+    ) -> (Vec<usize>, Vec<f32>) {
+        let words;
+        let vector = match self.distance_function.is_binary() {
+            true => {
+                words = pack_words(vector);
+                words.as_ptr() as *const f32
+            }
+            false => vector.as_ptr(),
+        };
+        let actual_k = std::cmp::min(k, self.len());
+        let mut ids = vec![0usize; actual_k];
+        let mut distance = vec![0.0f32; actual_k];
+        let mut total_result = actual_k;
+        unsafe {
+            total_result = knn_query(
+                self.ffi_ptr,
+                vector,
+                k,
+                // The bindings search with the ef of the index for an ef of 0
+                ef.unwrap_or(0),
+                ids.as_mut_ptr(),
+                distance.as_mut_ptr(),
+                allowed_ids.as_ptr(),
+                allowed_ids.len(),
+                disallowed_ids.as_ptr(),
+                disallowed_ids.len(),
+            ) as usize;
+        }
+        if total_result < actual_k {
+            ids.truncate(total_result);
+            distance.truncate(total_result);
+        }
+        return (ids, distance);
+    }
+
     pub fn set_ef(&self, ef: usize) {
         unsafe { set_ef(self.ffi_ptr, ef as c_int) }
     }
@@ -299,6 +525,11 @@ impl HnswIndex {
         unsafe { len(self.ffi_ptr) as usize }
     }
 
+    /// The number of elements that were deleted but are still present in the graph.
+    pub fn deleted_count(&self) -> usize {
+        unsafe { deleted_count(self.ffi_ptr) as usize }
+    }
+
     pub fn capacity(&self) -> usize {
         unsafe { capacity(self.ffi_ptr) as usize }
     }
@@ -306,12 +537,77 @@ impl HnswIndex {
     pub fn resize(&mut self, new_size: usize) {
         unsafe { resize_index(self.ffi_ptr, new_size) }
     }
+
+    pub(crate) fn is_multi_vector(&self) -> bool {
+        self.multi_vector
+    }
+
+    pub(crate) fn dimensionality(&self) -> usize {
+        self.dimensionality as usize
+    }
+
+    /// Whether the embedding can be added to or query the index. Multi-vector
+    /// embeddings must be a whole number of vectors, see `is_valid_multi_vector`.
+    pub(crate) fn accepts(&self, embedding: &[f32]) -> bool {
+        !self.multi_vector || is_valid_multi_vector(embedding.len(), self.dimensionality())
+    }
+
+    /// Splits an embedding into the vectors that are added to the graph, the
+    /// embedding itself unless the index is multi-vector. Values past the last
+    /// whole vector are ignored, callers check `accepts` first.
+    pub(crate) fn vectors<'a>(&self, embedding: &'a [f32]) -> Vec<&'a [f32]> {
+        match self.multi_vector {
+            true => embedding
+                .chunks_exact(self.dimensionality as usize)
+                .collect(),
+            false => vec![embedding],
+        }
+    }
+
+    /// The labels of the elements that a record with `num_vectors` vectors takes up.
+    pub(crate) fn labels(&self, offset_id: usize, num_vectors: usize) -> Vec<usize> {
+        match self.multi_vector {
+            true => (0..num_vectors)
+                .map(|position| (offset_id << MULTI_VECTOR_POSITION_BITS) | position)
+                .collect(),
+            false => vec![offset_id],
+        }
+    }
+
+    /// Adds every vector of a record, replacing the ones it had.
+    pub(crate) fn add_record(&self, offset_id: usize, embedding: &[f32]) {
+        let vectors = self.vectors(embedding);
+        for (label, vector) in self
+            .labels(offset_id, vectors.len())
+            .into_iter()
+            .zip(vectors)
+        {
+            self.add(label, vector);
+        }
+    }
+
+    /// Deletes the elements of a record that had the given embedding. Vectors at
+    /// positions below `keep` are left in place, for updates that replace them.
+    pub(crate) fn delete_record(&self, offset_id: usize, embedding: &[f32], keep: usize) {
+        let num_vectors = self.vectors(embedding).len();
+        for label in self.labels(offset_id, num_vectors).into_iter().skip(keep) {
+            self.delete(label);
+        }
+    }
+}
+
+impl Drop for HnswIndex {
+    fn drop(&mut self) {
+        unsafe { free_index(self.ffi_ptr) }
+    }
 }
 
 #[link(name = "bindings", kind = "static")]
 extern "C" {
     fn create_index(space_name: *const c_char, dim: c_int) -> *const IndexPtrFFI;
 
+    fn free_index(index: *const IndexPtrFFI);
+
     fn init_index(
         index: *const IndexPtrFFI,
         max_elements: usize,
@@ -339,6 +635,7 @@ extern "C" {
         index: *const IndexPtrFFI,
         query_vector: *const f32,
         k: usize,
+        ef: usize,
         ids: *mut usize,
         distance: *mut f32,
         allowed_ids: *const usize,
@@ -350,6 +647,7 @@ extern "C" {
     fn get_ef(index: *const IndexPtrFFI) -> c_int;
     fn set_ef(index: *const IndexPtrFFI, ef: c_int);
     fn len(index: *const IndexPtrFFI) -> c_int;
+    fn deleted_count(index: *const IndexPtrFFI) -> c_int;
     fn capacity(index: *const IndexPtrFFI) -> c_int;
     fn resize_index(index: *const IndexPtrFFI, new_size: usize);
 }
@@ -360,10 +658,12 @@ pub mod test {
 
     use crate::distance::DistanceFunction;
     use crate::index::utils;
+    use crate::types::{MetadataValue, SegmentScope, SegmentType};
     use rand::seq::IteratorRandom;
     use rand::Rng;
     use rayon::prelude::*;
     use rayon::ThreadPoolBuilder;
+    use std::collections::HashMap;
     use tempfile::tempdir;
 
     #[test]
@@ -377,6 +677,7 @@ pub mod test {
             &IndexConfig {
                 dimensionality: d as i32,
                 distance_function: distance_function,
+                multi_vector: false,
             },
             Some(&HnswIndexConfig {
                 max_elements: n,
@@ -398,6 +699,47 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn it_queries_with_the_ef_of_the_query() {
+        let n = 1000;
+        let d: usize = 16;
+        let tmp_dir = tempdir().unwrap();
+        let persist_path = tmp_dir.path().to_str().unwrap().to_string();
+        let index = HnswIndex::init(
+            &IndexConfig {
+                dimensionality: d as i32,
+                distance_function: DistanceFunction::Euclidean,
+                multi_vector: false,
+            },
+            Some(&HnswIndexConfig {
+                max_elements: n,
+                m: 16,
+                ef_construction: 100,
+                ef_search: 10,
+                random_seed: 0,
+                persist_path,
+            }),
+            Uuid::new_v4(),
+        )
+        .unwrap();
+        let data = utils::generate_random_data(n, d);
+        for i in 0..n {
+            index.add(i, &data[i * d..(i + 1) * d]);
+        }
+
+        let query = &data[0..d];
+        let (ids, _) = index.query_with_ef(query, 10, Some(n), &[], &[]);
+        let (default_ids, _) = index.query(query, 10, &[], &[]);
+        assert_eq!(ids.len(), 10);
+        assert_eq!(ids[0], 0);
+        assert_eq!(default_ids.len(), 10);
+        // An ef below k still returns k results
+        let (ids, _) = index.query_with_ef(query, 10, Some(1), &[], &[]);
+        assert_eq!(ids.len(), 10);
+        // The ef of the index is the same for the other queries
+        assert_eq!(index.get_ef(), 10);
+    }
+
     #[test]
     fn it_can_add_parallel() {
         let n: usize = 100;
@@ -409,6 +751,7 @@ pub mod test {
             &IndexConfig {
                 dimensionality: d as i32,
                 distance_function: distance_function,
+                multi_vector: false,
             },
             Some(&HnswIndexConfig {
                 max_elements: n,
@@ -480,6 +823,7 @@ pub mod test {
             &IndexConfig {
                 dimensionality: d as i32,
                 distance_function: distance_function,
+                multi_vector: false,
             },
             Some(&HnswIndexConfig {
                 max_elements: n,
@@ -549,6 +893,7 @@ pub mod test {
             &IndexConfig {
                 dimensionality: d as i32,
                 distance_function: distance_function,
+                multi_vector: false,
             },
             Some(&HnswIndexConfig {
                 max_elements: n,
@@ -585,6 +930,7 @@ pub mod test {
         }
 
         assert_eq!(index.len(), n - delete_ids.len());
+        assert_eq!(index.deleted_count(), delete_ids.len());
 
         let allow_ids = &[];
         let disallow_ids = &[];
@@ -606,19 +952,21 @@ pub mod test {
         let tmp_dir = tempdir().unwrap();
         let persist_path = tmp_dir.path().to_str().unwrap().to_string();
         let id = Uuid::new_v4();
+        let hnsw_config = HnswIndexConfig {
+            max_elements: n,
+            m: 32,
+            ef_construction: 100,
+            ef_search: 100,
+            random_seed: 0,
+            persist_path: persist_path.clone(),
+        };
         let index = HnswIndex::init(
             &IndexConfig {
                 dimensionality: d as i32,
                 distance_function: distance_function.clone(),
+                multi_vector: false,
             },
-            Some(&HnswIndexConfig {
-                max_elements: n,
-                m: 32,
-                ef_construction: 100,
-                ef_search: 100,
-                random_seed: 0,
-                persist_path: persist_path.clone(),
-            }),
+            Some(&hnsw_config),
             id,
         );
 
@@ -648,7 +996,9 @@ pub mod test {
             &IndexConfig {
                 dimensionality: d as i32,
                 distance_function: distance_function,
+                multi_vector: false,
             },
+            &hnsw_config,
             id,
         );
 
@@ -656,8 +1006,7 @@ pub mod test {
             Err(e) => panic!("Error loading index: {}", e),
             Ok(index) => index,
         };
-        // TODO: This should be set by the load
-        index.set_ef(100);
+        assert_eq!(index.get_ef(), 100);
         assert_eq!(index.id, id);
 
         // Query the data
@@ -687,6 +1036,230 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn it_validates_the_persisted_config_on_load() {
+        let n = 100;
+        let d: usize = 16;
+        let tmp_dir = tempdir().unwrap();
+        let persist_path = tmp_dir.path().to_str().unwrap().to_string();
+        let index_config = IndexConfig {
+            dimensionality: d as i32,
+            distance_function: DistanceFunction::Euclidean,
+            multi_vector: false,
+        };
+        let id = Uuid::new_v4();
+        let hnsw_config = HnswIndexConfig {
+            max_elements: n,
+            m: 16,
+            ef_construction: 100,
+            ef_search: 50,
+            random_seed: 0,
+            persist_path: persist_path.clone(),
+        };
+        let index = HnswIndex::init(&index_config, Some(&hnsw_config), id).unwrap();
+        let data: Vec<f32> = utils::generate_random_data(n, d);
+        for i in 0..n {
+            index.add(i, &data[i * d..(i + 1) * d]);
+        }
+        index.save().unwrap();
+
+        let persisted_config = HnswPersistedConfig::read(tmp_dir.path()).unwrap().unwrap();
+        assert_eq!(persisted_config.format_version, HNSW_FORMAT_VERSION);
+        assert_eq!(persisted_config.dimensionality, d as i32);
+        assert_eq!(persisted_config.space, "l2");
+        assert_eq!(persisted_config.m, 16);
+        assert_eq!(persisted_config.ef_construction, 100);
+        assert_eq!(persisted_config.ef_search, 50);
+
+        let wrong_dimensionality = IndexConfig {
+            dimensionality: 2 * d as i32,
+            ..index_config.clone()
+        };
+        assert!(HnswIndex::load(&persist_path, &wrong_dimensionality, &hnsw_config, id).is_err());
+        let wrong_space = IndexConfig {
+            distance_function: DistanceFunction::Cosine,
+            ..index_config.clone()
+        };
+        assert!(HnswIndex::load(&persist_path, &wrong_space, &hnsw_config, id).is_err());
+        let wrong_multi_vector = IndexConfig {
+            multi_vector: true,
+            ..index_config.clone()
+        };
+        assert!(HnswIndex::load(&persist_path, &wrong_multi_vector, &hnsw_config, id).is_err());
+        // The ef of queries can change, the graph parameters can not
+        let other_ef_search = HnswIndexConfig {
+            ef_search: 20,
+            ..hnsw_config.clone()
+        };
+        assert!(HnswIndex::load(&persist_path, &index_config, &other_ef_search, id).is_ok());
+
+        let newer_version = HnswPersistedConfig {
+            format_version: HNSW_FORMAT_VERSION + 1,
+            ..persisted_config.clone()
+        };
+        newer_version.write(tmp_dir.path()).unwrap();
+        assert!(HnswIndex::load(&persist_path, &index_config, &hnsw_config, id).is_err());
+
+        // Indexes persisted before the config was saved load with the config of
+        // the collection and persist it on their next save
+        std::fs::remove_file(tmp_dir.path().join(HNSW_CONFIG_FILE)).unwrap();
+        let collection_config = HnswIndexConfig {
+            m: 32,
+            ef_construction: 200,
+            ef_search: 20,
+            ..hnsw_config.clone()
+        };
+        let index = HnswIndex::load(&persist_path, &index_config, &collection_config, id).unwrap();
+        assert_eq!(index.len(), n);
+        assert_eq!(index.get_ef(), 20);
+        index.save().unwrap();
+        assert_eq!(
+            HnswPersistedConfig::read(tmp_dir.path()).unwrap(),
+            Some(HnswPersistedConfig {
+                m: 32,
+                ef_construction: 200,
+                ef_search: 20,
+                ..persisted_config.clone()
+            })
+        );
+        let wrong_space = IndexConfig {
+            distance_function: DistanceFunction::Cosine,
+            ..index_config.clone()
+        };
+        assert!(HnswIndex::load(&persist_path, &wrong_space, &hnsw_config, id).is_err());
+    }
+
+    #[test]
+    fn it_rejects_an_index_built_with_another_m() {
+        let tmp_dir = tempdir().unwrap();
+        let persist_path = tmp_dir.path().to_str().unwrap().to_string();
+        let index_config = IndexConfig {
+            dimensionality: 2,
+            distance_function: DistanceFunction::Euclidean,
+            multi_vector: false,
+        };
+        let hnsw_config = HnswIndexConfig {
+            max_elements: 10,
+            m: 16,
+            ef_construction: 100,
+            ef_search: 10,
+            random_seed: 0,
+            persist_path: persist_path.clone(),
+        };
+        let id = Uuid::new_v4();
+        let index = HnswIndex::init(&index_config, Some(&hnsw_config), id).unwrap();
+        index.add(0, &[0.0, 1.0]);
+        index.save().unwrap();
+
+        let other_m = HnswIndexConfig {
+            m: 32,
+            ..hnsw_config.clone()
+        };
+        let err = match HnswIndex::load(&persist_path, &index_config, &other_m, id) {
+            Err(e) => e,
+            Ok(_) => panic!("Expected the index to be rejected"),
+        };
+        assert_eq!(err.code(), ErrorCodes::FailedPrecondition);
+        assert!(matches!(
+            HnswPersistedConfig::read(tmp_dir.path())
+                .unwrap()
+                .unwrap()
+                .validate(&index_config, &other_m),
+            Err(HnswPersistedConfigError::MMismatch {
+                persisted: 16,
+                expected: 32
+            })
+        ));
+    }
+
+    #[test]
+    fn it_rejects_an_index_built_with_another_ef_construction() {
+        let tmp_dir = tempdir().unwrap();
+        let persist_path = tmp_dir.path().to_str().unwrap().to_string();
+        let index_config = IndexConfig {
+            dimensionality: 2,
+            distance_function: DistanceFunction::Euclidean,
+            multi_vector: false,
+        };
+        let hnsw_config = HnswIndexConfig {
+            max_elements: 10,
+            m: 16,
+            ef_construction: 100,
+            ef_search: 10,
+            random_seed: 0,
+            persist_path: persist_path.clone(),
+        };
+        let id = Uuid::new_v4();
+        let index = HnswIndex::init(&index_config, Some(&hnsw_config), id).unwrap();
+        index.add(0, &[0.0, 1.0]);
+        index.save().unwrap();
+
+        let other_ef_construction = HnswIndexConfig {
+            ef_construction: 200,
+            ..hnsw_config.clone()
+        };
+        let err = match HnswIndex::load(&persist_path, &index_config, &other_ef_construction, id) {
+            Err(e) => e,
+            Ok(_) => panic!("Expected the index to be rejected"),
+        };
+        assert_eq!(err.code(), ErrorCodes::FailedPrecondition);
+        assert!(matches!(
+            HnswPersistedConfig::read(tmp_dir.path())
+                .unwrap()
+                .unwrap()
+                .validate(&index_config, &other_ef_construction),
+            Err(HnswPersistedConfigError::EfConstructionMismatch {
+                persisted: 100,
+                expected: 200
+            })
+        ));
+    }
+
+    #[test]
+    fn it_adds_and_deletes_every_vector_of_a_multi_vector_record() {
+        let d: usize = 2;
+        let tmp_dir = tempdir().unwrap();
+        let persist_path = tmp_dir.path().to_str().unwrap().to_string();
+        let index = HnswIndex::init(
+            &IndexConfig {
+                dimensionality: d as i32,
+                distance_function: DistanceFunction::Euclidean,
+                multi_vector: true,
+            },
+            Some(&HnswIndexConfig {
+                max_elements: 10,
+                m: 16,
+                ef_construction: 100,
+                ef_search: 100,
+                random_seed: 0,
+                persist_path: persist_path,
+            }),
+            Uuid::new_v4(),
+        )
+        .unwrap();
+
+        assert!(index.accepts(&[0.0, 1.0, 2.0, 3.0]));
+        assert!(!index.accepts(&[0.0, 1.0, 2.0]));
+        assert!(!index.accepts(&[]));
+        // A trailing partial vector never reaches the graph
+        assert_eq!(index.vectors(&[0.0, 1.0, 2.0]), vec![&[0.0, 1.0][..]]);
+
+        let first = [0.0, 0.0, 10.0, 10.0, 20.0, 20.0];
+        let second = [5.0, 5.0];
+        index.add_record(1, &first);
+        index.add_record(2, &second);
+        assert_eq!(index.len(), 4);
+
+        let (labels, _) = index.query(&[20.0, 20.0], 1, &[], &[]);
+        assert_eq!(labels, vec![index.labels(1, 3)[2]]);
+        assert_eq!(multi_vector_offset_id(labels[0]), 1);
+
+        index.delete_record(1, &first, 0);
+        assert_eq!(index.deleted_count(), 3);
+        let (labels, _) = index.query(&[20.0, 20.0], 1, &[], &[]);
+        assert_eq!(labels, index.labels(2, 1));
+    }
+
     #[test]
     fn it_can_add_and_query_with_allowed_and_disallowed_ids() {
         let n = 1000;
@@ -698,6 +1271,7 @@ pub mod test {
             &IndexConfig {
                 dimensionality: d as i32,
                 distance_function: distance_function,
+                multi_vector: false,
             },
             Some(&HnswIndexConfig {
                 max_elements: n,
@@ -732,6 +1306,109 @@ pub mod test {
         assert_eq!(distances.len(), 2);
     }
 
+    #[test]
+    fn it_indexes_binary_vectors_as_packed_words() {
+        // 70 dimensions span two words
+        let d: usize = 70;
+        let vectors: Vec<Vec<f32>> = vec![
+            (0..d).map(|i| (i < 10) as u8 as f32).collect(),
+            (0..d).map(|i| (i < 20) as u8 as f32).collect(),
+            (0..d).map(|i| (i >= 65) as u8 as f32).collect(),
+        ];
+        let query: Vec<f32> = (0..d).map(|i| (i < 12) as u8 as f32 * 3.0).collect();
+        for (distance_function, expected) in [
+            (
+                DistanceFunction::Hamming,
+                vec![(0, 2.0), (1, 8.0), (2, 17.0)],
+            ),
+            (
+                DistanceFunction::Jaccard,
+                vec![(0, 1.0 - 10.0 / 12.0), (1, 1.0 - 12.0 / 20.0), (2, 1.0)],
+            ),
+        ] {
+            let tmp_dir = tempdir().unwrap();
+            let persist_path = tmp_dir.path().to_str().unwrap().to_string();
+            let index_config = IndexConfig {
+                dimensionality: d as i32,
+                distance_function: distance_function.clone(),
+                multi_vector: false,
+            };
+            let hnsw_config = HnswIndexConfig {
+                max_elements: 10,
+                m: 16,
+                ef_construction: 100,
+                ef_search: 100,
+                random_seed: 0,
+                persist_path: persist_path.clone(),
+            };
+            let index = HnswIndex::init(&index_config, Some(&hnsw_config), Uuid::new_v4()).unwrap();
+            for (id, vector) in vectors.iter().enumerate() {
+                index.add(id, vector);
+            }
+            index.save().unwrap();
+            let index = HnswIndex::load(&persist_path, &index_config, &hnsw_config, Uuid::new_v4())
+                .unwrap();
+
+            let (ids, distances) = index.query(&query, 3, &[], &[]);
+            let actual: Vec<(usize, f32)> = ids.into_iter().zip(distances).collect();
+            assert_eq!(actual.len(), expected.len());
+            for ((id, distance), (expected_id, expected_distance)) in actual.iter().zip(&expected) {
+                assert_eq!(id, expected_id);
+                assert!((distance - expected_distance).abs() < 1e-6);
+                assert_eq!(
+                    *distance,
+                    distance_function.distance(&query, &vectors[*id]).unwrap()
+                );
+            }
+            assert_eq!(index.get(1), Some(vectors[1].clone()));
+        }
+    }
+
+    #[test]
+    fn it_reads_the_config_from_the_segment() {
+        let persist_path = Path::new("/tmp/index");
+        let mut segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::HnswDistributed,
+            scope: SegmentScope::VECTOR,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let config = HnswIndexConfig::from_segment(&segment, persist_path).unwrap();
+        assert_eq!(config.m, DEFAULT_M);
+        assert_eq!(config.ef_construction, DEFAULT_EF_CONSTRUCTION);
+        assert_eq!(config.ef_search, DEFAULT_EF_SEARCH);
+
+        // Segments with metadata must set every key
+        segment.metadata = Some(HashMap::from([
+            (
+                "hnsw:space".to_string(),
+                MetadataValue::Str("ip".to_string()),
+            ),
+            ("hnsw:M".to_string(), MetadataValue::Int(32)),
+        ]));
+        assert!(matches!(
+            HnswIndexConfig::from_segment(&segment, persist_path).map_err(|e| *e),
+            Err(HnswIndexFromSegmentError::MissingConfig(key)) if key == "hnsw:construction_ef"
+        ));
+        segment.metadata.as_mut().unwrap().extend([
+            ("hnsw:construction_ef".to_string(), MetadataValue::Int(200)),
+            ("hnsw:search_ef".to_string(), MetadataValue::Int(20)),
+        ]);
+        let config = HnswIndexConfig::from_segment(&segment, persist_path).unwrap();
+        assert_eq!(config.m, 32);
+        assert_eq!(config.ef_construction, 200);
+        assert_eq!(config.ef_search, 20);
+
+        segment
+            .metadata
+            .as_mut()
+            .unwrap()
+            .insert("hnsw:search_ef".to_string(), MetadataValue::Int(0));
+        assert!(HnswIndexConfig::from_segment(&segment, persist_path).is_err());
+    }
+
     #[test]
     fn it_can_resize() {
         let n = 1000;
@@ -743,6 +1420,7 @@ pub mod test {
             &IndexConfig {
                 dimensionality: d as i32,
                 distance_function: distance_function,
+                multi_vector: false,
             },
             Some(&HnswIndexConfig {
                 max_elements: n,
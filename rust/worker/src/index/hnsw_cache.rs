@@ -0,0 +1,216 @@
+use super::hnsw_manifest::HnswManifest;
+use super::HnswIndex;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+struct HnswCacheEntry {
+    index: Arc<RwLock<HnswIndex>>,
+    // The manifest the index was loaded from or last flushed to, if any.
+    manifest: Option<HnswManifest>,
+    // The approximate number of bytes the index keeps resident in memory.
+    size_bytes: usize,
+    last_used: u64,
+}
+
+/// The HnswIndexCache struct.
+/// # Description
+/// Holds the hnsw indexes that are resident in memory and evicts the least recently
+/// used ones once their combined size exceeds the configured budget.
+/// # Notes
+/// An index is only evicted while the cache holds the sole reference to it, so
+/// indexes that are being written to or queried are never dropped from under their
+/// users. The cache can therefore temporarily exceed its budget.
+pub(crate) struct HnswIndexCache {
+    entries: HashMap<Uuid, HnswCacheEntry>,
+    capacity_bytes: usize,
+    size_bytes: usize,
+    clock: u64,
+}
+
+impl HnswIndexCache {
+    pub(crate) fn new(capacity_bytes: usize) -> Self {
+        HnswIndexCache {
+            entries: HashMap::new(),
+            capacity_bytes,
+            size_bytes: 0,
+            clock: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    pub(crate) fn get(&mut self, id: &Uuid) -> Option<Arc<RwLock<HnswIndex>>> {
+        let now = self.tick();
+        match self.entries.get_mut(id) {
+            Some(entry) => {
+                entry.last_used = now;
+                Some(entry.index.clone())
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn manifest(&self, id: &Uuid) -> Option<HnswManifest> {
+        match self.entries.get(id) {
+            Some(entry) => entry.manifest.clone(),
+            None => None,
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: &Uuid) -> Option<Arc<RwLock<HnswIndex>>> {
+        match self.entries.remove(id) {
+            Some(entry) => {
+                self.size_bytes -= entry.size_bytes;
+                Some(entry.index)
+            }
+            None => None,
+        }
+    }
+
+    /// Inserts an index and returns the ids of the indexes evicted to make room for it.
+    pub(crate) fn insert(
+        &mut self,
+        id: Uuid,
+        index: Arc<RwLock<HnswIndex>>,
+        manifest: Option<HnswManifest>,
+        size_bytes: usize,
+    ) -> Vec<Uuid> {
+        let now = self.tick();
+        if let Some(old) = self.entries.insert(
+            id,
+            HnswCacheEntry {
+                index,
+                manifest,
+                size_bytes,
+                last_used: now,
+            },
+        ) {
+            self.size_bytes -= old.size_bytes;
+        }
+        self.size_bytes += size_bytes;
+        self.evict(&id)
+    }
+
+    /// Records the manifest and resident size of an index after it was flushed and
+    /// returns the ids of any indexes evicted because it grew.
+    pub(crate) fn update(
+        &mut self,
+        id: &Uuid,
+        manifest: HnswManifest,
+        size_bytes: usize,
+    ) -> Vec<Uuid> {
+        match self.entries.get_mut(id) {
+            Some(entry) => {
+                self.size_bytes = self.size_bytes - entry.size_bytes + size_bytes;
+                entry.size_bytes = size_bytes;
+                entry.manifest = Some(manifest);
+            }
+            None => return Vec::new(),
+        }
+        self.evict(id)
+    }
+
+    fn evict(&mut self, keep: &Uuid) -> Vec<Uuid> {
+        let mut evicted = Vec::new();
+        while self.size_bytes > self.capacity_bytes {
+            let candidate = self
+                .entries
+                .iter()
+                .filter(|(id, entry)| *id != keep && Arc::strong_count(&entry.index) == 1)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| *id);
+            match candidate {
+                Some(id) => {
+                    if let Some(entry) = self.entries.remove(&id) {
+                        self.size_bytes -= entry.size_bytes;
+                    }
+                    evicted.push(id);
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::DistanceFunction;
+    use crate::index::{HnswIndexConfig, Index, IndexConfig};
+
+    fn new_index(path: &std::path::Path) -> Arc<RwLock<HnswIndex>> {
+        let index = HnswIndex::init(
+            &IndexConfig {
+                dimensionality: 4,
+                distance_function: DistanceFunction::Euclidean,
+                multi_vector: false,
+            },
+            Some(&HnswIndexConfig {
+                max_elements: 10,
+                m: 16,
+                ef_construction: 100,
+                ef_search: 10,
+                random_seed: 0,
+                persist_path: path.to_str().unwrap().to_string(),
+            }),
+            Uuid::new_v4(),
+        )
+        .unwrap();
+        Arc::new(RwLock::new(index))
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let mut cache = HnswIndexCache::new(250);
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        let third = Uuid::new_v4();
+        assert!(cache
+            .insert(first, new_index(tmp_dir.path()), None, 100)
+            .is_empty());
+        assert!(cache
+            .insert(second, new_index(tmp_dir.path()), None, 100)
+            .is_empty());
+        // Touch the first index so that the second is the least recently used
+        cache.get(&first);
+        let evicted = cache.insert(third, new_index(tmp_dir.path()), None, 100);
+        assert_eq!(evicted, vec![second]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size_bytes(), 200);
+    }
+
+    #[test]
+    fn test_does_not_evict_indexes_in_use() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let mut cache = HnswIndexCache::new(150);
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        cache.insert(first, new_index(tmp_dir.path()), None, 100);
+        let in_use = cache.get(&first).unwrap();
+        let evicted = cache.insert(second, new_index(tmp_dir.path()), None, 100);
+        assert!(evicted.is_empty());
+        assert_eq!(cache.size_bytes(), 200);
+
+        // Once released, the next insert brings the cache back within budget
+        drop(in_use);
+        let third = Uuid::new_v4();
+        let evicted = cache.insert(third, new_index(tmp_dir.path()), None, 10);
+        assert_eq!(evicted, vec![first]);
+        assert_eq!(cache.size_bytes(), 110);
+    }
+}
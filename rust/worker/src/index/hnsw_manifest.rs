@@ -0,0 +1,194 @@
+use murmur3::murmur3_x64_128;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::ops::Range;
+use uuid::Uuid;
+
+// hnswlib lays elements out at fixed offsets in its files, so splitting the files
+// into fixed size chunks means that appends and in-place updates only touch the
+// chunks covering the affected element ranges.
+pub(crate) const HNSW_CHUNK_SIZE: usize = 1024 * 1024;
+const HNSW_MANIFEST_VERSION: u32 = 1;
+
+/// A chunk of a persisted hnswlib file.
+/// # Fields
+/// - key: The storage key the chunk lives at. This may belong to an ancestor index
+///   if the chunk was unchanged when this index was flushed.
+/// - length: The length of the chunk in bytes.
+/// - hash: The murmur3 hash of the chunk contents, used to detect unchanged chunks.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HnswChunk {
+    pub(crate) key: String,
+    pub(crate) length: usize,
+    pub(crate) hash: String,
+}
+
+impl HnswChunk {
+    /// Whether the data has the contents the chunk was written with.
+    pub(crate) fn matches(&self, data: &[u8]) -> bool {
+        self.length == data.len() && self.hash == hash_chunk(data)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HnswFileManifest {
+    pub(crate) name: String,
+    pub(crate) length: usize,
+    pub(crate) chunks: Vec<HnswChunk>,
+}
+
+/// The HnswManifest struct.
+/// # Description
+/// Describes how the files of a persisted hnsw index are laid out in storage as a
+/// list of immutable chunks per file. Chunks are only ever written once, so a
+/// manifest can reference chunks written by the index it was forked from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HnswManifest {
+    pub(crate) version: u32,
+    pub(crate) index_id: String,
+    pub(crate) files: Vec<HnswFileManifest>,
+}
+
+/// A chunk that is referenced by a manifest but not yet present in storage.
+#[derive(Debug, PartialEq)]
+pub(crate) struct PendingChunk {
+    pub(crate) key: String,
+    pub(crate) range: Range<usize>,
+}
+
+impl HnswManifest {
+    pub(crate) fn new(index_id: Uuid) -> Self {
+        HnswManifest {
+            version: HNSW_MANIFEST_VERSION,
+            index_id: index_id.to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    pub(crate) fn format_chunk_key(index_id: &str, file: &str, chunk_index: usize) -> String {
+        format!("hnsw/{}/chunks/{}/{}", index_id, file, chunk_index)
+    }
+
+    pub(crate) fn file(&self, name: &str) -> Option<&HnswFileManifest> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    /// Splits the contents of `name` into chunks and records them in the manifest.
+    /// Chunks that are identical to the chunk at the same position in `base` are
+    /// referenced by their existing key. Returns the chunks that need to be uploaded.
+    pub(crate) fn add_file(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        base: Option<&HnswManifest>,
+    ) -> Vec<PendingChunk> {
+        let base_file = match base {
+            Some(base) => base.file(name),
+            None => None,
+        };
+        let mut chunks = Vec::new();
+        let mut pending = Vec::new();
+        for (chunk_index, start) in (0..data.len()).step_by(HNSW_CHUNK_SIZE).enumerate() {
+            let end = std::cmp::min(start + HNSW_CHUNK_SIZE, data.len());
+            let hash = hash_chunk(&data[start..end]);
+            let base_chunk = match base_file {
+                Some(base_file) => base_file.chunks.get(chunk_index),
+                None => None,
+            };
+            match base_chunk {
+                Some(base_chunk) if base_chunk.hash == hash && base_chunk.length == end - start => {
+                    chunks.push(base_chunk.clone());
+                }
+                _ => {
+                    let key = Self::format_chunk_key(&self.index_id, name, chunk_index);
+                    pending.push(PendingChunk {
+                        key: key.clone(),
+                        range: start..end,
+                    });
+                    chunks.push(HnswChunk {
+                        key,
+                        length: end - start,
+                        hash,
+                    });
+                }
+            }
+        }
+        self.files.retain(|file| file.name != name);
+        self.files.push(HnswFileManifest {
+            name: name.to_string(),
+            length: data.len(),
+            chunks,
+        });
+        pending
+    }
+}
+
+fn hash_chunk(data: &[u8]) -> String {
+    // Reading from an in memory cursor cannot fail
+    let hash = murmur3_x64_128(&mut Cursor::new(data), 0).unwrap_or_default();
+    format!("{:032x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_file_without_base_uploads_every_chunk() {
+        let id = Uuid::new_v4();
+        let mut manifest = HnswManifest::new(id);
+        let data = vec![7u8; HNSW_CHUNK_SIZE * 2 + 10];
+        let pending = manifest.add_file("data_level0.bin", &data, None);
+        assert_eq!(pending.len(), 3);
+        assert_eq!(
+            pending[2].range,
+            HNSW_CHUNK_SIZE * 2..HNSW_CHUNK_SIZE * 2 + 10
+        );
+        let file = manifest.file("data_level0.bin").unwrap();
+        assert_eq!(file.length, data.len());
+        assert_eq!(file.chunks.len(), 3);
+        assert_eq!(
+            file.chunks[0].key,
+            HnswManifest::format_chunk_key(&id.to_string(), "data_level0.bin", 0)
+        );
+    }
+
+    #[test]
+    fn test_add_file_reuses_unchanged_chunks() {
+        let base_id = Uuid::new_v4();
+        let mut base = HnswManifest::new(base_id);
+        let mut data = vec![1u8; HNSW_CHUNK_SIZE * 3];
+        base.add_file("data_level0.bin", &data, None);
+
+        // Modify the middle chunk and append a few bytes
+        data[HNSW_CHUNK_SIZE + 5] = 2;
+        data.extend_from_slice(&[3u8; 16]);
+
+        let mut manifest = HnswManifest::new(Uuid::new_v4());
+        let pending = manifest.add_file("data_level0.bin", &data, Some(&base));
+        let pending_ranges: Vec<Range<usize>> = pending.into_iter().map(|p| p.range).collect();
+        assert_eq!(
+            pending_ranges,
+            vec![
+                HNSW_CHUNK_SIZE..HNSW_CHUNK_SIZE * 2,
+                HNSW_CHUNK_SIZE * 3..HNSW_CHUNK_SIZE * 3 + 16
+            ]
+        );
+
+        let file = manifest.file("data_level0.bin").unwrap();
+        let base_file = base.file("data_level0.bin").unwrap();
+        assert_eq!(file.chunks[0], base_file.chunks[0]);
+        assert_ne!(file.chunks[1], base_file.chunks[1]);
+        assert_eq!(file.chunks[2], base_file.chunks[2]);
+        assert_eq!(file.chunks.len(), 4);
+    }
+
+    #[test]
+    fn test_manifest_round_trips_through_json() {
+        let mut manifest = HnswManifest::new(Uuid::new_v4());
+        manifest.add_file("header.bin", &[1, 2, 3], None);
+        let bytes = serde_json::to_vec(&manifest).unwrap();
+        let decoded: HnswManifest = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(manifest, decoded);
+    }
+}
@@ -1,15 +1,18 @@
+use super::hnsw_cache::HnswIndexCache;
+use super::hnsw_manifest::HnswManifest;
 use super::{
     HnswIndex, HnswIndexConfig, HnswIndexFromSegmentError, Index, IndexConfig,
-    IndexConfigFromSegmentError,
+    IndexConfigFromSegmentError, HNSW_CONFIG_FILE,
 };
 use crate::errors::ErrorCodes;
 use crate::index::types::PersistentIndex;
 use crate::{errors::ChromaError, storage::Storage, types::Segment};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::fmt::Debug;
 use std::path::Path;
 use std::{collections::HashMap, path::PathBuf, sync::Arc};
 use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use uuid::Uuid;
 
 // These are the files hnswlib writes to disk. This is strong coupling, but we need to know
@@ -22,42 +25,57 @@ const FILES: [&'static str; 4] = [
     "link_lists.bin",
 ];
 
+// The key in the segment file paths under which the storage key of the manifest
+// describing the chunked hnsw files is recorded. Segments flushed before chunked
+// persistence existed do not have it and store each file whole.
+pub(crate) const HNSW_MANIFEST: &str = "hnsw_manifest";
+
 #[derive(Clone)]
 pub(crate) struct HnswIndexProvider {
-    cache: Arc<RwLock<HashMap<Uuid, Arc<RwLock<HnswIndex>>>>>,
+    // The resident indexes along with the manifest each was loaded from or last
+    // flushed to. Flushing an index only uploads the chunks that differ from it.
+    cache: Arc<Mutex<HnswIndexCache>>,
+    // Ensures that concurrent opens of the same index load it only once.
+    open_locks: Arc<Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>>,
     pub(crate) temporary_storage_path: PathBuf,
     storage: Storage,
 }
 
 impl Debug for HnswIndexProvider {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cache = self.cache.lock();
         write!(
             f,
-            "HnswIndexProvider {{ temporary_storage_path: {:?}, cache: {} }}",
+            "HnswIndexProvider {{ temporary_storage_path: {:?}, cache: {}, cache_size_bytes: {} }}",
             self.temporary_storage_path,
-            self.cache.read().len(),
+            cache.len(),
+            cache.size_bytes(),
         )
     }
 }
 
 impl HnswIndexProvider {
-    pub(crate) fn new(storage: Storage, storage_path: PathBuf) -> Self {
+    pub(crate) fn new(storage: Storage, storage_path: PathBuf, cache_size_bytes: usize) -> Self {
         Self {
-            cache: Arc::new(RwLock::new(HashMap::new())),
+            cache: Arc::new(Mutex::new(HnswIndexCache::new(cache_size_bytes))),
+            open_locks: Arc::new(Mutex::new(HashMap::new())),
             storage,
             temporary_storage_path: storage_path,
         }
     }
 
     pub(crate) fn get(&self, id: &Uuid) -> Option<Arc<RwLock<HnswIndex>>> {
-        let cache = self.cache.read();
-        cache.get(id).cloned()
+        self.cache.lock().get(id)
     }
 
     fn format_key(&self, id: &Uuid, file: &str) -> String {
         format!("hnsw/{}/{}", id, file)
     }
 
+    fn format_manifest_key(&self, id: &Uuid) -> String {
+        format!("hnsw/{}/manifest.json", id)
+    }
+
     pub(crate) async fn fork(
         &self,
         source_id: &Uuid,
@@ -73,15 +91,23 @@ impl HnswIndexProvider {
             }
         }
 
-        match self
-            .load_hnsw_segment_into_directory(source_id, &new_storage_path)
+        // The source is usually resident, so its local files spare fetching
+        // every chunk that did not change since it was flushed.
+        let source_storage_path = self.temporary_storage_path.join(source_id.to_string());
+        let manifest = match self
+            .load_hnsw_segment_into_directory(
+                source_id,
+                segment,
+                Some(&source_storage_path),
+                &new_storage_path,
+            )
             .await
         {
-            Ok(_) => {}
+            Ok(manifest) => manifest,
             Err(e) => {
                 return Err(Box::new(HnswIndexProviderForkError::FileError(*e)));
             }
-        }
+        };
 
         let index_config = IndexConfig::from_segment(&segment, dimensionality);
 
@@ -109,11 +135,12 @@ impl HnswIndexProvider {
             }
         };
 
-        match HnswIndex::load(storage_path_str, &index_config, new_id) {
+        match HnswIndex::load(storage_path_str, &index_config, &hnsw_config, new_id) {
             Ok(index) => {
                 let index = Arc::new(RwLock::new(index));
-                let mut cache = self.cache.write();
-                cache.insert(new_id, index.clone());
+                // The fork starts out identical to its source, so its first flush
+                // can reference every chunk it did not modify.
+                self.insert_into_cache(new_id, index.clone(), manifest);
                 Ok(index)
             }
             Err(e) => Err(Box::new(HnswIndexProviderForkError::IndexLoadError(e))),
@@ -121,6 +148,145 @@ impl HnswIndexProvider {
     }
 
     async fn load_hnsw_segment_into_directory(
+        &self,
+        source_id: &Uuid,
+        segment: &Segment,
+        local_storage_path: Option<&Path>,
+        index_storage_path: &Path,
+    ) -> Result<Option<HnswManifest>, Box<HnswIndexProviderFileError>> {
+        let manifest_key = match segment.file_path.get(HNSW_MANIFEST) {
+            Some(keys) => keys.first(),
+            None => None,
+        };
+        match manifest_key {
+            Some(manifest_key) => {
+                let manifest = self.get_manifest(manifest_key).await?;
+                self.load_hnsw_chunks_into_directory(
+                    &manifest,
+                    local_storage_path,
+                    index_storage_path,
+                )
+                .await?;
+                Ok(Some(manifest))
+            }
+            None => {
+                self.load_hnsw_files_into_directory(source_id, index_storage_path)
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn get_manifest(
+        &self,
+        manifest_key: &str,
+    ) -> Result<HnswManifest, Box<HnswIndexProviderFileError>> {
+        let mut reader = match self.storage.get(manifest_key).await {
+            Ok(reader) => reader,
+            Err(e) => {
+                return Err(Box::new(HnswIndexProviderFileError::StorageGetError(e)));
+            }
+        };
+        let mut bytes = Vec::new();
+        match reader.read_to_end(&mut bytes).await {
+            Ok(_) => {}
+            Err(e) => {
+                return Err(Box::new(HnswIndexProviderFileError::IOError(e)));
+            }
+        }
+        match serde_json::from_slice(&bytes) {
+            Ok(manifest) => Ok(manifest),
+            Err(e) => Err(Box::new(HnswIndexProviderFileError::ManifestError(e))),
+        }
+    }
+
+    // Writes the files of the manifest into the directory and returns the number
+    // of chunks fetched from storage. The chunks of the files in
+    // `local_storage_path` that still hash to what the manifest records are
+    // copied from there instead.
+    async fn load_hnsw_chunks_into_directory(
+        &self,
+        manifest: &HnswManifest,
+        local_storage_path: Option<&Path>,
+        index_storage_path: &Path,
+    ) -> Result<usize, Box<HnswIndexProviderFileError>> {
+        let mut fetched_chunks = 0;
+        let mut total_chunks = 0;
+        for file in FILES.iter().chain([&HNSW_CONFIG_FILE]) {
+            let file_manifest = match manifest.file(file) {
+                Some(file_manifest) => file_manifest,
+                // Indexes flushed before the config was persisted do not have it
+                None if *file == HNSW_CONFIG_FILE => continue,
+                None => {
+                    return Err(Box::new(HnswIndexProviderFileError::MissingFile(
+                        file.to_string(),
+                    )));
+                }
+            };
+            // A local file that is missing, such as after an eviction, or being
+            // saved over only means that more chunks are fetched.
+            let local_data = match local_storage_path {
+                Some(local_storage_path) => {
+                    tokio::fs::read(local_storage_path.join(file)).await.ok()
+                }
+                None => None,
+            };
+            let file_path = index_storage_path.join(file);
+            let mut file_handle = match tokio::fs::File::create(&file_path).await {
+                Ok(file) => file,
+                Err(e) => {
+                    return Err(Box::new(HnswIndexProviderFileError::IOError(e)));
+                }
+            };
+            let mut start = 0;
+            for chunk in file_manifest.chunks.iter() {
+                let end = start + chunk.length;
+                let local_chunk = local_data
+                    .as_ref()
+                    .and_then(|data| data.get(start..end))
+                    .filter(|data| chunk.matches(data));
+                start = end;
+                if let Some(data) = local_chunk {
+                    match file_handle.write_all(data).await {
+                        Ok(_) => {}
+                        Err(e) => {
+                            return Err(Box::new(HnswIndexProviderFileError::IOError(e)));
+                        }
+                    }
+                    continue;
+                }
+                let mut reader = match self.storage.get(&chunk.key).await {
+                    Ok(reader) => reader,
+                    Err(e) => {
+                        return Err(Box::new(HnswIndexProviderFileError::StorageGetError(e)));
+                    }
+                };
+                match tokio::io::copy(&mut reader, &mut file_handle).await {
+                    Ok(_) => {}
+                    Err(e) => {
+                        return Err(Box::new(HnswIndexProviderFileError::IOError(e)));
+                    }
+                }
+                fetched_chunks += 1;
+            }
+            match file_handle.flush().await {
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(Box::new(HnswIndexProviderFileError::IOError(e)));
+                }
+            }
+            total_chunks += file_manifest.chunks.len();
+        }
+        tracing::debug!(
+            "Loaded hnsw index {} fetching {} of {} chunks from storage",
+            manifest.index_id,
+            fetched_chunks,
+            total_chunks
+        );
+        Ok(fetched_chunks)
+    }
+
+    async fn load_hnsw_files_into_directory(
         &self,
         source_id: &Uuid,
         index_storage_path: &Path,
@@ -168,11 +334,47 @@ impl HnswIndexProvider {
         Ok(())
     }
 
+    /// Returns the index with the given id, loading it from storage if it is not
+    /// resident. Concurrent opens of the same index share a single load.
     pub(crate) async fn open(
         &self,
         id: &Uuid,
         segment: &Segment,
         dimensionality: i32,
+    ) -> Result<Arc<RwLock<HnswIndex>>, Box<HnswIndexProviderOpenError>> {
+        if let Some(index) = self.get(id) {
+            return Ok(index);
+        }
+
+        let open_lock = self
+            .open_locks
+            .lock()
+            .entry(*id)
+            .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(())))
+            .clone();
+        let res = {
+            let _guard = open_lock.lock().await;
+            // Another open may have loaded the index while we were waiting
+            match self.get(id) {
+                Some(index) => Ok(index),
+                None => self.load(id, segment, dimensionality).await,
+            }
+        };
+
+        // The lock is only needed while someone is waiting on it. The map holds
+        // one reference and we hold the other.
+        let mut open_locks = self.open_locks.lock();
+        if Arc::strong_count(&open_lock) == 2 {
+            open_locks.remove(id);
+        }
+        res
+    }
+
+    async fn load(
+        &self,
+        id: &Uuid,
+        segment: &Segment,
+        dimensionality: i32,
     ) -> Result<Arc<RwLock<HnswIndex>>, Box<HnswIndexProviderOpenError>> {
         let index_storage_path = self.temporary_storage_path.join(id.to_string());
 
@@ -183,15 +385,15 @@ impl HnswIndexProvider {
             }
         }
 
-        match self
-            .load_hnsw_segment_into_directory(id, &index_storage_path)
+        let manifest = match self
+            .load_hnsw_segment_into_directory(id, segment, None, &index_storage_path)
             .await
         {
-            Ok(_) => {}
+            Ok(manifest) => manifest,
             Err(e) => {
                 return Err(Box::new(HnswIndexProviderOpenError::FileError(*e)));
             }
-        }
+        };
 
         let index_config = IndexConfig::from_segment(&segment, dimensionality);
         let index_config = match index_config {
@@ -210,11 +412,15 @@ impl HnswIndexProvider {
         };
 
         // TODO: don't unwrap path conv here
-        match HnswIndex::load(index_storage_path.to_str().unwrap(), &index_config, *id) {
+        match HnswIndex::load(
+            index_storage_path.to_str().unwrap(),
+            &index_config,
+            &hnsw_config,
+            *id,
+        ) {
             Ok(index) => {
                 let index = Arc::new(RwLock::new(index));
-                let mut cache = self.cache.write();
-                cache.insert(*id, index.clone());
+                self.insert_into_cache(*id, index.clone(), manifest);
                 Ok(index)
             }
             Err(e) => Err(Box::new(HnswIndexProviderOpenError::IndexLoadError(e))),
@@ -262,7 +468,6 @@ impl HnswIndexProvider {
             }
         };
 
-        let mut cache = self.cache.write();
         let index = match HnswIndex::init(&index_config, Some(&hnsw_config), id) {
             Ok(index) => index,
             Err(e) => {
@@ -270,13 +475,12 @@ impl HnswIndexProvider {
             }
         };
         let index = Arc::new(RwLock::new(index));
-        cache.insert(id, index.clone());
+        self.insert_into_cache(id, index.clone(), None);
         Ok(index)
     }
 
     pub(crate) fn commit(&self, id: &Uuid) -> Result<(), Box<HnswIndexProviderCommitError>> {
-        let cache = self.cache.read();
-        let index = match cache.get(id) {
+        let index = match self.get(id) {
             Some(index) => index,
             None => {
                 return Err(Box::new(HnswIndexProviderCommitError::NoIndexFound(*id)));
@@ -293,12 +497,17 @@ impl HnswIndexProvider {
         Ok(())
     }
 
-    pub(crate) async fn flush(&self, id: &Uuid) -> Result<(), Box<HnswIndexProviderFlushError>> {
+    /// Persists the index to storage and returns the storage key of its manifest.
+    /// Only the chunks that changed since the manifest the index was loaded from,
+    /// or last flushed to, are uploaded.
+    pub(crate) async fn flush(
+        &self,
+        id: &Uuid,
+    ) -> Result<String, Box<HnswIndexProviderFlushError>> {
         // Scope to drop the cache lock before we await to write to s3
         // TODO: since we commit(), we don't need to save the index here
         {
-            let cache = self.cache.read();
-            let index = match cache.get(id) {
+            let index = match self.get(id) {
                 Some(index) => index,
                 None => {
                     return Err(Box::new(HnswIndexProviderFlushError::NoIndexFound(*id)));
@@ -312,24 +521,111 @@ impl HnswIndexProvider {
             };
         }
 
+        let base_manifest = self.cache.lock().manifest(id);
+        let mut manifest = HnswManifest::new(*id);
         let index_storage_path = self.temporary_storage_path.join(id.to_string());
-        for file in FILES.iter() {
+        let mut uploaded_chunks = 0;
+        for file in FILES.iter().chain([&HNSW_CONFIG_FILE]) {
             let file_path = index_storage_path.join(file);
-            let key = self.format_key(id, file);
-            let res = self
-                .storage
-                .put_file(&key, file_path.to_str().unwrap())
-                .await;
-            match res {
+            let data = match tokio::fs::read(&file_path).await {
+                Ok(data) => data,
+                // Indexes loaded without a config are flushed without one
+                Err(e) if *file == HNSW_CONFIG_FILE && e.kind() == std::io::ErrorKind::NotFound => {
+                    continue
+                }
+                Err(e) => {
+                    return Err(Box::new(HnswIndexProviderFlushError::IOError(e)));
+                }
+            };
+            let pending_chunks = manifest.add_file(file, &data, base_manifest.as_ref());
+            for chunk in pending_chunks {
+                let res = self
+                    .storage
+                    .put_bytes(&chunk.key, data[chunk.range].to_vec())
+                    .await;
+                match res {
+                    Ok(_) => {
+                        uploaded_chunks += 1;
+                    }
+                    Err(e) => {
+                        return Err(Box::new(HnswIndexProviderFlushError::StoragePutError(e)));
+                    }
+                }
+            }
+        }
+
+        let manifest_bytes = match serde_json::to_vec(&manifest) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return Err(Box::new(HnswIndexProviderFlushError::ManifestError(e)));
+            }
+        };
+        let manifest_key = self.format_manifest_key(id);
+        match self.storage.put_bytes(&manifest_key, manifest_bytes).await {
+            Ok(_) => {}
+            Err(e) => {
+                return Err(Box::new(HnswIndexProviderFlushError::StoragePutError(e)));
+            }
+        }
+        tracing::info!(
+            "Flushed hnsw index {} uploading {} chunks",
+            id,
+            uploaded_chunks
+        );
+        let size_bytes = self.index_size_bytes(&index_storage_path);
+        let mut cache = self.cache.lock();
+        let evicted = cache.update(id, manifest, size_bytes);
+        self.remove_local_files(evicted);
+        Ok(manifest_key)
+    }
+
+    /// Drops an index that will not be flushed, such as a fork that was replaced
+    /// before being written to, from the cache and removes its local files.
+    pub(crate) fn remove(&self, id: &Uuid) {
+        let mut cache = self.cache.lock();
+        if cache.remove(id).is_some() {
+            self.remove_local_files(vec![*id]);
+        }
+    }
+
+    fn insert_into_cache(
+        &self,
+        id: Uuid,
+        index: Arc<RwLock<HnswIndex>>,
+        manifest: Option<HnswManifest>,
+    ) {
+        let size_bytes = self.index_size_bytes(&self.temporary_storage_path.join(id.to_string()));
+        let mut cache = self.cache.lock();
+        let evicted = cache.insert(id, index, manifest, size_bytes);
+        self.remove_local_files(evicted);
+    }
+
+    // hnswlib keeps the whole index in memory, so the size of its files is a good
+    // approximation of its resident size. Indexes that have not been persisted yet
+    // count as empty until they are flushed.
+    fn index_size_bytes(&self, index_storage_path: &Path) -> usize {
+        FILES
+            .iter()
+            .filter_map(|file| std::fs::metadata(index_storage_path.join(file)).ok())
+            .map(|metadata| metadata.len() as usize)
+            .sum()
+    }
+
+    // Evicted indexes are reloaded from storage on their next open, so their local
+    // files are no longer needed. Callers hold the cache lock while removing them so
+    // that an open of the same index cannot start loading into the directory first.
+    fn remove_local_files(&self, ids: Vec<Uuid>) {
+        for id in ids {
+            let index_storage_path = self.temporary_storage_path.join(id.to_string());
+            match std::fs::remove_dir_all(&index_storage_path) {
                 Ok(_) => {
-                    println!("Flushed hnsw index file: {}", file);
+                    tracing::info!("Removed hnsw index {} from cache", id);
                 }
                 Err(e) => {
-                    return Err(Box::new(HnswIndexProviderFlushError::StoragePutError(e)));
+                    tracing::error!("Failed to remove local files of hnsw index {}: {}", id, e);
                 }
             }
         }
-        Ok(())
     }
 
     fn create_dir_all(&self, path: &PathBuf) -> Result<(), Box<HnswIndexProviderFileError>> {
@@ -437,6 +733,10 @@ pub(crate) enum HnswIndexProviderFlushError {
     HnswSaveError(#[from] Box<dyn ChromaError>),
     #[error("Storage Put Error")]
     StoragePutError(#[from] crate::storage::PutError),
+    #[error("IO Error")]
+    IOError(#[from] std::io::Error),
+    #[error("Manifest serialization error")]
+    ManifestError(#[from] serde_json::Error),
 }
 
 impl ChromaError for HnswIndexProviderFlushError {
@@ -445,6 +745,8 @@ impl ChromaError for HnswIndexProviderFlushError {
             HnswIndexProviderFlushError::NoIndexFound(_) => ErrorCodes::NotFound,
             HnswIndexProviderFlushError::HnswSaveError(e) => e.code(),
             HnswIndexProviderFlushError::StoragePutError(e) => e.code(),
+            HnswIndexProviderFlushError::IOError(_) => ErrorCodes::Internal,
+            HnswIndexProviderFlushError::ManifestError(_) => ErrorCodes::Internal,
         }
     }
 }
@@ -457,6 +759,10 @@ pub(crate) enum HnswIndexProviderFileError {
     StorageGetError(#[from] crate::storage::GetError),
     #[error("Storage Put Error")]
     StoragePutError(#[from] crate::storage::PutError),
+    #[error("Invalid manifest")]
+    ManifestError(#[from] serde_json::Error),
+    #[error("Manifest has no entry for file: {0}")]
+    MissingFile(String),
 }
 
 #[cfg(test)]
@@ -477,8 +783,8 @@ mod tests {
 
         let storage = Storage::Local(LocalStorage::new(storage_dir.to_str().unwrap()));
 
-        let provider = HnswIndexProvider::new(storage, hnsw_tmp_path);
-        let segment = Segment {
+        let provider = HnswIndexProvider::new(storage, hnsw_tmp_path, 1024 * 1024 * 1024);
+        let mut segment = Segment {
             id: Uuid::new_v4(),
             r#type: SegmentType::HnswDistributed,
             scope: crate::types::SegmentScope::VECTOR,
@@ -491,6 +797,11 @@ mod tests {
         let created_index = provider.create(&segment, dimensionality).unwrap();
         let created_index_id = created_index.read().id;
 
+        let manifest_key = provider.flush(&created_index_id).await.unwrap();
+        segment
+            .file_path
+            .insert(HNSW_MANIFEST.to_string(), vec![manifest_key]);
+
         let forked_index = provider
             .fork(&created_index_id, &segment, dimensionality)
             .await
@@ -499,4 +810,233 @@ mod tests {
 
         assert_ne!(created_index_id, forked_index_id);
     }
+
+    #[tokio::test]
+    async fn test_flush_after_fork_reuses_unchanged_chunks() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let hnsw_tmp_path = storage_dir.path().join("hnsw");
+        std::fs::create_dir_all(&hnsw_tmp_path).unwrap();
+        let storage = Storage::Local(LocalStorage::new(storage_dir.path().to_str().unwrap()));
+        let provider = HnswIndexProvider::new(storage, hnsw_tmp_path, 1024 * 1024 * 1024);
+        let mut segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::HnswDistributed,
+            scope: crate::types::SegmentScope::VECTOR,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+
+        // Enough elements for the level 0 data to span several chunks
+        let n = 4000;
+        let dimensionality = 128;
+        let created_index = provider.create(&segment, dimensionality).unwrap();
+        let created_index_id = created_index.read().id;
+        {
+            let mut index = created_index.write();
+            index.resize(n + 1);
+            for id in 0..n {
+                index.add(id, &vec![id as f32; dimensionality as usize]);
+            }
+        }
+        let manifest_key = provider.flush(&created_index_id).await.unwrap();
+        segment
+            .file_path
+            .insert(HNSW_MANIFEST.to_string(), vec![manifest_key]);
+        let base_manifest = provider.cache.lock().manifest(&created_index_id).unwrap();
+        let base_chunks = &base_manifest.file("data_level0.bin").unwrap().chunks;
+        assert!(base_chunks.len() > 1);
+
+        let forked_index = provider
+            .fork(&created_index_id, &segment, dimensionality)
+            .await
+            .unwrap();
+        let forked_index_id = forked_index.read().id;
+        forked_index
+            .read()
+            .add(n, &vec![n as f32; dimensionality as usize]);
+        provider.flush(&forked_index_id).await.unwrap();
+
+        let manifest = provider.cache.lock().manifest(&forked_index_id).unwrap();
+        let chunks = &manifest.file("data_level0.bin").unwrap().chunks;
+        // The leading chunks are untouched by the append and are referenced from
+        // the source index, while the tail was rewritten by the fork.
+        assert_eq!(chunks[0].key, base_chunks[0].key);
+        assert!(chunks
+            .last()
+            .unwrap()
+            .key
+            .starts_with(&format!("hnsw/{}/", forked_index_id)));
+
+        // The forked index can be loaded back from its manifest
+        segment.file_path.insert(
+            HNSW_MANIFEST.to_string(),
+            vec![provider.format_manifest_key(&forked_index_id)],
+        );
+        let reopened = provider
+            .fork(&forked_index_id, &segment, dimensionality)
+            .await
+            .unwrap();
+        assert_eq!(reopened.read().len(), n + 1);
+    }
+
+    #[tokio::test]
+    async fn test_fork_fetches_only_chunks_that_differ_locally() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let hnsw_tmp_path = storage_dir.path().join("hnsw");
+        std::fs::create_dir_all(&hnsw_tmp_path).unwrap();
+        let storage = Storage::Local(LocalStorage::new(storage_dir.path().to_str().unwrap()));
+        let provider = HnswIndexProvider::new(storage, hnsw_tmp_path.clone(), 1024 * 1024 * 1024);
+        let mut segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::HnswDistributed,
+            scope: crate::types::SegmentScope::VECTOR,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+
+        // Enough elements for the level 0 data to span several chunks
+        let n = 4000;
+        let dimensionality = 128;
+        let created_index = provider.create(&segment, dimensionality).unwrap();
+        let created_index_id = created_index.read().id;
+        {
+            let mut index = created_index.write();
+            index.resize(n);
+            for id in 0..n {
+                index.add(id, &vec![id as f32; dimensionality as usize]);
+            }
+        }
+        let manifest_key = provider.flush(&created_index_id).await.unwrap();
+        segment
+            .file_path
+            .insert(HNSW_MANIFEST.to_string(), vec![manifest_key.clone()]);
+        let manifest = provider.cache.lock().manifest(&created_index_id).unwrap();
+        let source_path = hnsw_tmp_path.join(created_index_id.to_string());
+
+        // Every chunk of the resident source is unchanged.
+        let target_path = hnsw_tmp_path.join("unchanged");
+        std::fs::create_dir_all(&target_path).unwrap();
+        let fetched = provider
+            .load_hnsw_chunks_into_directory(&manifest, Some(&source_path), &target_path)
+            .await
+            .unwrap();
+        assert_eq!(fetched, 0);
+        assert_eq!(
+            std::fs::read(target_path.join("data_level0.bin")).unwrap(),
+            std::fs::read(source_path.join("data_level0.bin")).unwrap()
+        );
+
+        // A local chunk that no longer hashes to the manifest is fetched, as is
+        // every chunk of a file that is gone.
+        let data_level0_path = source_path.join("data_level0.bin");
+        let mut data = std::fs::read(&data_level0_path).unwrap();
+        data[0] = data[0].wrapping_add(1);
+        std::fs::write(&data_level0_path, &data).unwrap();
+        std::fs::remove_file(source_path.join("link_lists.bin")).unwrap();
+        let target_path = hnsw_tmp_path.join("changed");
+        std::fs::create_dir_all(&target_path).unwrap();
+        let fetched = provider
+            .load_hnsw_chunks_into_directory(&manifest, Some(&source_path), &target_path)
+            .await
+            .unwrap();
+        let link_lists_chunks = manifest.file("link_lists.bin").unwrap().chunks.len();
+        assert_eq!(fetched, 1 + link_lists_chunks);
+        data[0] = data[0].wrapping_sub(1);
+        assert_eq!(
+            std::fs::read(target_path.join("data_level0.bin")).unwrap(),
+            data
+        );
+
+        // Without the local files every chunk is fetched.
+        let target_path = hnsw_tmp_path.join("remote");
+        std::fs::create_dir_all(&target_path).unwrap();
+        let fetched = provider
+            .load_hnsw_chunks_into_directory(&manifest, None, &target_path)
+            .await
+            .unwrap();
+        let total_chunks: usize = manifest.files.iter().map(|file| file.chunks.len()).sum();
+        assert_eq!(fetched, total_chunks);
+
+        // The fork loads from the local files of its source.
+        let forked_index = provider
+            .fork(&created_index_id, &segment, dimensionality)
+            .await
+            .unwrap();
+        assert_eq!(forked_index.read().len(), n);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_open_loads_index_once() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let hnsw_tmp_path = storage_dir.path().join("hnsw");
+        std::fs::create_dir_all(&hnsw_tmp_path).unwrap();
+        let storage = Storage::Local(LocalStorage::new(storage_dir.path().to_str().unwrap()));
+        let writer_provider =
+            HnswIndexProvider::new(storage.clone(), hnsw_tmp_path.join("writer"), 1024 * 1024);
+        let mut segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::HnswDistributed,
+            scope: crate::types::SegmentScope::VECTOR,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let dimensionality = 4;
+        let created_index = writer_provider.create(&segment, dimensionality).unwrap();
+        let index_id = created_index.read().id;
+        created_index.read().add(1, &[1.0, 2.0, 3.0, 4.0]);
+        let manifest_key = writer_provider.flush(&index_id).await.unwrap();
+        segment
+            .file_path
+            .insert(HNSW_MANIFEST.to_string(), vec![manifest_key]);
+
+        let provider = HnswIndexProvider::new(storage, hnsw_tmp_path.join("reader"), 1024 * 1024);
+        let (first, second) = tokio::join!(
+            provider.open(&index_id, &segment, dimensionality),
+            provider.open(&index_id, &segment, dimensionality)
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.read().len(), 1);
+        assert!(provider.open_locks.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_evicts_unused_indexes_over_budget() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let hnsw_tmp_path = storage_dir.path().join("cache");
+        std::fs::create_dir_all(&hnsw_tmp_path).unwrap();
+        let storage = Storage::Local(LocalStorage::new(storage_dir.path().to_str().unwrap()));
+        // Small enough that only one flushed index fits
+        let provider = HnswIndexProvider::new(storage, hnsw_tmp_path.clone(), 1);
+        let segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::HnswDistributed,
+            scope: crate::types::SegmentScope::VECTOR,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let dimensionality = 4;
+
+        let first_id = {
+            let index = provider.create(&segment, dimensionality).unwrap();
+            let id = index.read().id;
+            provider.flush(&id).await.unwrap();
+            id
+        };
+        assert!(provider.get(&first_id).is_some());
+
+        let second = provider.create(&segment, dimensionality).unwrap();
+        let second_id = second.read().id;
+        provider.flush(&second_id).await.unwrap();
+
+        // The first index is no longer in use, so flushing the second evicts it
+        // while the second stays resident because we still hold it.
+        assert!(provider.get(&first_id).is_none());
+        assert!(!hnsw_tmp_path.join(first_id.to_string()).exists());
+        assert!(provider.get(&second_id).is_some());
+    }
 }
@@ -0,0 +1,645 @@
+use super::{Index, IndexConfig, PersistentIndex};
+use crate::distance::DistanceFunction;
+use crate::errors::{ChromaError, ErrorCodes};
+use crate::execution::operators::normalize_vectors::normalize;
+use crate::types::{MetadataValueConversionError, Segment};
+use parking_lot::RwLock;
+use roaring::RoaringBitmap;
+use std::collections::HashMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+const DEFAULT_NLIST: usize = 100;
+const DEFAULT_NPROBE: usize = 8;
+const KMEANS_ITERATIONS: usize = 10;
+// The centroids are trained on a sample of at most this many vectors per list
+const TRAINING_SIZE_PER_LIST: usize = 64;
+
+/// The configuration of an ivf index.
+/// # Fields
+/// - nlist: The number of centroids and so of inverted lists.
+/// - nprobe: The number of inverted lists a query scans, the closest lists to the
+///   query are scanned. Queries can override it.
+/// - centroids: The centroids the index starts with, empty for an untrained index.
+///   An ivf segment reads them from its centroids blockfile.
+/// - training_size: The number of vectors the centroids were trained on.
+#[derive(Clone)]
+pub(crate) struct IvfIndexConfig {
+    pub(crate) nlist: usize,
+    pub(crate) nprobe: usize,
+    pub(crate) centroids: Vec<Vec<f32>>,
+    pub(crate) training_size: usize,
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum IvfIndexFromSegmentError {
+    #[error("Invalid config `{0}`, it must be positive")]
+    InvalidConfig(String),
+    #[error("Invalid metadata value")]
+    MetadataValueError(#[from] MetadataValueConversionError),
+}
+
+impl ChromaError for IvfIndexFromSegmentError {
+    fn code(&self) -> ErrorCodes {
+        ErrorCodes::InvalidArgument
+    }
+}
+
+impl IvfIndexConfig {
+    pub(crate) fn from_segment(
+        segment: &Segment,
+    ) -> Result<IvfIndexConfig, Box<IvfIndexFromSegmentError>> {
+        fn get_metadata_value_or(
+            segment: &Segment,
+            key: &str,
+            default: usize,
+        ) -> Result<usize, Box<IvfIndexFromSegmentError>> {
+            let value = match segment.metadata.as_ref().and_then(|m| m.get(key)) {
+                Some(value) => value,
+                None => return Ok(default),
+            };
+            match i32::try_from(value) {
+                Ok(value) if value > 0 => Ok(value as usize),
+                Ok(_) => Err(Box::new(IvfIndexFromSegmentError::InvalidConfig(
+                    key.to_string(),
+                ))),
+                Err(e) => Err(Box::new(IvfIndexFromSegmentError::MetadataValueError(e))),
+            }
+        }
+
+        Ok(IvfIndexConfig {
+            nlist: get_metadata_value_or(segment, "ivf:nlist", DEFAULT_NLIST)?,
+            nprobe: get_metadata_value_or(segment, "ivf:nprobe", DEFAULT_NPROBE)?,
+            centroids: Vec::new(),
+            training_size: 0,
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum IvfIndexError {
+    #[error("No config provided")]
+    NoConfigProvided,
+    #[error("Invalid centroids blockfile id: {0}")]
+    InvalidPath(String),
+    #[error("A centroid has dimensionality {0}, expected {1}")]
+    DimensionalityMismatch(usize, usize),
+}
+
+impl ChromaError for IvfIndexError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            IvfIndexError::NoConfigProvided => ErrorCodes::InvalidArgument,
+            IvfIndexError::InvalidPath(_) => ErrorCodes::Internal,
+            IvfIndexError::DimensionalityMismatch(_, _) => ErrorCodes::Internal,
+        }
+    }
+}
+
+// The vectors held by an index, by the list of their closest centroid.
+#[derive(Default)]
+struct InvertedLists {
+    lists: HashMap<usize, HashMap<usize, Vec<f32>>>,
+    // The list each id is in
+    assignments: HashMap<usize, usize>,
+}
+
+impl InvertedLists {
+    fn insert(&mut self, list: usize, id: usize, vector: Vec<f32>) {
+        self.remove(id);
+        self.lists.entry(list).or_default().insert(id, vector);
+        self.assignments.insert(id, list);
+    }
+
+    fn remove(&mut self, id: usize) -> Option<(usize, Vec<f32>)> {
+        let list = self.assignments.remove(&id)?;
+        let vector = self.lists.get_mut(&list)?.remove(&id)?;
+        Some((list, vector))
+    }
+}
+
+/// The IvfIndex struct.
+/// # Description
+/// An inverted file index with flat (uncompressed) vectors. Vectors are grouped
+/// into lists by their closest k-means centroid and queries only compute exact
+/// distances to the vectors in the lists closest to the query.
+/// # Notes
+/// The index holds the centroids and the vectors it is given. An ivf segment
+/// stores the lists in a blockfile and gives its index only the vectors a
+/// compaction writes or the lists a query probes.
+/// Until the centroids are trained every vector is in a single list.
+/// Vectors are normalized when the distance function is cosine.
+pub(crate) struct IvfIndex {
+    dimensionality: usize,
+    distance_function: DistanceFunction,
+    nlist: usize,
+    nprobe: usize,
+    centroids: RwLock<Vec<Vec<f32>>>,
+    // The number of vectors the centroids were trained on
+    training_size: RwLock<usize>,
+    inverted_lists: RwLock<InvertedLists>,
+}
+
+impl Index<IvfIndexConfig> for IvfIndex {
+    fn init(
+        index_config: &IndexConfig,
+        ivf_config: Option<&IvfIndexConfig>,
+        _id: Uuid,
+    ) -> Result<Self, Box<dyn ChromaError>> {
+        let ivf_config = match ivf_config {
+            Some(ivf_config) => ivf_config,
+            None => return Err(Box::new(IvfIndexError::NoConfigProvided)),
+        };
+        let dimensionality = index_config.dimensionality as usize;
+        if let Some(centroid) = ivf_config
+            .centroids
+            .iter()
+            .find(|centroid| centroid.len() != dimensionality)
+        {
+            return Err(Box::new(IvfIndexError::DimensionalityMismatch(
+                centroid.len(),
+                dimensionality,
+            )));
+        }
+        Ok(IvfIndex {
+            dimensionality,
+            distance_function: index_config.distance_function.clone(),
+            nlist: ivf_config.nlist,
+            nprobe: ivf_config.nprobe,
+            centroids: RwLock::new(ivf_config.centroids.clone()),
+            training_size: RwLock::new(ivf_config.training_size),
+            inverted_lists: RwLock::new(InvertedLists::default()),
+        })
+    }
+
+    fn add(&self, id: usize, vector: &[f32]) {
+        let vector = self.prepare(vector);
+        // Hold the centroids so that training can not move lists in between
+        let centroids = self.centroids.read();
+        let list = self.nearest_lists(&centroids, &vector, 1)[0];
+        self.inverted_lists.write().insert(list, id, vector);
+    }
+
+    fn delete(&self, id: usize) {
+        self.inverted_lists.write().remove(id);
+    }
+
+    fn query(
+        &self,
+        vector: &[f32],
+        k: usize,
+        allowed_ids: &[usize],
+        disallowed_ids: &[usize],
+    ) -> (Vec<usize>, Vec<f32>) {
+        let to_bitmap = |ids: &[usize]| RoaringBitmap::from_iter(ids.iter().map(|id| *id as u32));
+        self.query_with_nprobe(
+            vector,
+            k,
+            self.nprobe,
+            &to_bitmap(allowed_ids),
+            &to_bitmap(disallowed_ids),
+        )
+    }
+
+    fn get(&self, id: usize) -> Option<Vec<f32>> {
+        let inverted_lists = self.inverted_lists.read();
+        let list = inverted_lists.assignments.get(&id)?;
+        inverted_lists.lists.get(list)?.get(&id).cloned()
+    }
+}
+
+// The centroids and the lists of an ivf segment are in its blockfiles, which
+// the segment reads and writes itself through the async blockfile api. The
+// index is loaded with the centroids the segment read.
+impl PersistentIndex<IvfIndexConfig> for IvfIndex {
+    /// The index has no state of its own to save, its segment writes the
+    /// `centroids()` and the vectors to its blockfiles.
+    fn save(&self) -> Result<(), Box<dyn ChromaError>> {
+        Ok(())
+    }
+
+    /// Loads the index with the centroids of `ivf_config`, read from the
+    /// centroids blockfile with the id `path`.
+    fn load(
+        path: &str,
+        index_config: &IndexConfig,
+        ivf_config: &IvfIndexConfig,
+        id: Uuid,
+    ) -> Result<Self, Box<dyn ChromaError>> {
+        if Uuid::parse_str(path).is_err() {
+            return Err(Box::new(IvfIndexError::InvalidPath(path.to_string())));
+        }
+        IvfIndex::init(index_config, Some(ivf_config), id)
+    }
+}
+
+impl IvfIndex {
+    pub(crate) fn nprobe(&self) -> usize {
+        self.nprobe
+    }
+
+    pub(crate) fn is_trained(&self) -> bool {
+        !self.centroids.read().is_empty()
+    }
+
+    pub(crate) fn centroids(&self) -> Vec<Vec<f32>> {
+        self.centroids.read().clone()
+    }
+
+    /// The number of vectors the centroids were trained on.
+    pub(crate) fn training_size(&self) -> usize {
+        *self.training_size.read()
+    }
+
+    /// The most vectors worth training the centroids on, larger collections
+    /// are sampled.
+    pub(crate) fn max_training_size(&self) -> usize {
+        self.nlist * TRAINING_SIZE_PER_LIST
+    }
+
+    /// Replaces the centroids with centroids trained on `training_size` vectors.
+    /// The vectors in the index are moved to the list of their closest new
+    /// centroid.
+    pub(crate) fn set_centroids(&self, centroids: Vec<Vec<f32>>, training_size: usize) {
+        let mut current = self.centroids.write();
+        *current = centroids;
+        *self.training_size.write() = training_size;
+        self.reassign(&current);
+    }
+
+    /// Trains the centroids with k-means on the vectors, replacing the current
+    /// ones. Does nothing if there are no vectors.
+    pub(crate) fn train(&self, vectors: &[&[f32]]) {
+        if vectors.is_empty() {
+            return;
+        }
+        let vectors: Vec<Vec<f32>> = vectors.iter().map(|vector| self.prepare(vector)).collect();
+        let centroids = self.kmeans(&vectors);
+        self.set_centroids(centroids, vectors.len());
+    }
+
+    /// Adds a vector, as it is stored in the index, to a list without looking
+    /// for its closest centroid, such as a vector read back from a list.
+    pub(crate) fn insert(&self, list: usize, id: usize, vector: Vec<f32>) {
+        self.inverted_lists.write().insert(list, id, vector);
+    }
+
+    /// The list the index holds a vector in.
+    pub(crate) fn list(&self, id: usize) -> Option<usize> {
+        self.inverted_lists.read().assignments.get(&id).cloned()
+    }
+
+    /// The list, id and vector of every vector the index holds.
+    pub(crate) fn vectors(&self) -> Vec<(usize, usize, Vec<f32>)> {
+        let inverted_lists = self.inverted_lists.read();
+        inverted_lists
+            .lists
+            .iter()
+            .flat_map(|(list, vectors)| {
+                vectors
+                    .iter()
+                    .map(|(id, vector)| (*list, *id, vector.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the ids of the up to `n` lists whose centroids are closest to the
+    /// vector, closest first.
+    pub(crate) fn nearest_centroids(&self, vector: &[f32], n: usize) -> Vec<usize> {
+        let vector = self.prepare(vector);
+        self.nearest_lists(&self.centroids.read(), &vector, n)
+    }
+
+    /// Queries the `nprobe` lists closest to the vector instead of the default
+    /// of the index. Every id is allowed when `allowed_ids` is empty.
+    pub(crate) fn query_with_nprobe(
+        &self,
+        vector: &[f32],
+        k: usize,
+        nprobe: usize,
+        allowed_ids: &RoaringBitmap,
+        disallowed_ids: &RoaringBitmap,
+    ) -> (Vec<usize>, Vec<f32>) {
+        let vector = self.prepare(vector);
+        let centroids = self.centroids.read();
+        let inverted_lists = self.inverted_lists.read();
+        let mut results = Vec::new();
+        for list in self.nearest_lists(&centroids, &vector, nprobe) {
+            let vectors = match inverted_lists.lists.get(&list) {
+                Some(vectors) => vectors,
+                None => continue,
+            };
+            for (id, list_vector) in vectors.iter() {
+                let offset_id = *id as u32;
+                if disallowed_ids.contains(offset_id)
+                    || (!allowed_ids.is_empty() && !allowed_ids.contains(offset_id))
+                {
+                    continue;
+                }
+                results.push((*id, self.distance(&vector, list_vector)));
+            }
+        }
+        results.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        results.truncate(k);
+        results.into_iter().unzip()
+    }
+
+    /// The distance between two vectors as compared by the index. Vectors of
+    /// the wrong dimensionality are infinitely far.
+    pub(crate) fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        self.distance_function
+            .distance(a, b)
+            .unwrap_or(f32::INFINITY)
+    }
+
+    /// The vector as it is stored in the index.
+    pub(crate) fn prepare(&self, vector: &[f32]) -> Vec<f32> {
+        match self.distance_function {
+            DistanceFunction::Cosine => normalize(vector),
+            _ => vector.to_vec(),
+        }
+    }
+
+    // Moves every vector to the list of its closest centroid
+    fn reassign(&self, centroids: &[Vec<f32>]) {
+        let mut inverted_lists = self.inverted_lists.write();
+        let vectors: Vec<(usize, Vec<f32>)> = inverted_lists
+            .lists
+            .drain()
+            .flat_map(|(_, vectors)| vectors)
+            .collect();
+        inverted_lists.assignments.clear();
+        for (id, vector) in vectors {
+            let list = self.nearest_lists(centroids, &vector, 1)[0];
+            inverted_lists.insert(list, id, vector);
+        }
+    }
+
+    // An untrained index has a single list
+    fn nearest_lists(&self, centroids: &[Vec<f32>], vector: &[f32], n: usize) -> Vec<usize> {
+        if centroids.is_empty() {
+            return vec![0];
+        }
+        let mut distances: Vec<(usize, f32)> = centroids
+            .iter()
+            .enumerate()
+            .map(|(list, centroid)| (list, self.distance(vector, centroid)))
+            .collect();
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+        distances.truncate(std::cmp::max(n, 1));
+        distances.into_iter().map(|(list, _)| list).collect()
+    }
+
+    // Lloyd's algorithm. It is seeded with the first vector and then, one at a
+    // time, the vector farthest from the seeds chosen so far.
+    fn kmeans(&self, vectors: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let k = std::cmp::min(self.nlist, vectors.len());
+        let mut centroids = vec![vectors[0].clone()];
+        let mut seed_distances: Vec<f32> = vectors
+            .iter()
+            .map(|vector| self.distance(vector, &centroids[0]))
+            .collect();
+        while centroids.len() < k {
+            let (farthest, _) = seed_distances
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .unwrap();
+            let seed = vectors[farthest].clone();
+            for (vector, distance) in vectors.iter().zip(seed_distances.iter_mut()) {
+                *distance = distance.min(self.distance(vector, &seed));
+            }
+            centroids.push(seed);
+        }
+        let mut assignments = vec![usize::MAX; vectors.len()];
+        for _ in 0..KMEANS_ITERATIONS {
+            let mut changed = false;
+            for (vector, assignment) in vectors.iter().zip(assignments.iter_mut()) {
+                let nearest = self.nearest_lists(&centroids, vector, 1)[0];
+                if nearest != *assignment {
+                    *assignment = nearest;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+            let mut sums = vec![vec![0.0_f32; self.dimensionality]; k];
+            let mut counts = vec![0_usize; k];
+            for (vector, assignment) in vectors.iter().zip(assignments.iter()) {
+                for (sum, value) in sums[*assignment].iter_mut().zip(vector) {
+                    *sum += value;
+                }
+                counts[*assignment] += 1;
+            }
+            for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+                // Empty clusters keep their centroid
+                if count == 0 {
+                    continue;
+                }
+                let mean: Vec<f32> = sum.iter().map(|value| value / count as f32).collect();
+                *centroid = match self.distance_function {
+                    DistanceFunction::Cosine => normalize(&mean),
+                    // The majority of the cluster for each bit
+                    DistanceFunction::Hamming | DistanceFunction::Jaccard => mean
+                        .iter()
+                        .map(|value| (*value >= 0.5) as u8 as f32)
+                        .collect(),
+                    _ => mean,
+                };
+            }
+        }
+        centroids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MetadataValue, SegmentScope};
+
+    fn index_config(distance_function: DistanceFunction) -> IndexConfig {
+        IndexConfig {
+            dimensionality: 2,
+            distance_function,
+            multi_vector: false,
+        }
+    }
+
+    fn ivf_config(nlist: usize, nprobe: usize) -> IvfIndexConfig {
+        IvfIndexConfig {
+            nlist,
+            nprobe,
+            centroids: Vec::new(),
+            training_size: 0,
+        }
+    }
+
+    // Two clusters of vectors around (0, 0) and (10, 10)
+    fn clustered_vectors() -> Vec<Vec<f32>> {
+        (0..20)
+            .map(|i| {
+                let offset = (i / 2) as f32 * 0.1;
+                match i % 2 {
+                    0 => vec![offset, offset],
+                    _ => vec![10.0 + offset, 10.0 - offset],
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ivf_index_config_from_segment() {
+        let mut segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: crate::types::SegmentType::BlockfileIvf,
+            scope: SegmentScope::VECTOR,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let config = IvfIndexConfig::from_segment(&segment).unwrap();
+        assert_eq!(config.nlist, DEFAULT_NLIST);
+        assert_eq!(config.nprobe, DEFAULT_NPROBE);
+
+        segment.metadata = Some(HashMap::from([
+            ("ivf:nlist".to_string(), MetadataValue::Int(16)),
+            ("ivf:nprobe".to_string(), MetadataValue::Int(2)),
+        ]));
+        let config = IvfIndexConfig::from_segment(&segment).unwrap();
+        assert_eq!(config.nlist, 16);
+        assert_eq!(config.nprobe, 2);
+
+        segment.metadata = Some(HashMap::from([(
+            "ivf:nprobe".to_string(),
+            MetadataValue::Int(0),
+        )]));
+        assert!(IvfIndexConfig::from_segment(&segment).is_err());
+    }
+
+    #[test]
+    fn test_ivf_index_trains() {
+        let ivf_config = ivf_config(2, 1);
+        let index = IvfIndex::init(
+            &index_config(DistanceFunction::Euclidean),
+            Some(&ivf_config),
+            Uuid::new_v4(),
+        )
+        .unwrap();
+        // Every vector is in the single list of an untrained index
+        assert!(!index.is_trained());
+        assert_eq!(index.nearest_centroids(&[10.0, 10.0], 2), vec![0]);
+
+        index.train(&[]);
+        assert!(!index.is_trained());
+
+        let vectors = clustered_vectors();
+        let samples: Vec<&[f32]> = vectors.iter().map(|v| v.as_slice()).collect();
+        index.train(&samples[..1]);
+        assert_eq!(index.centroids().len(), 1);
+        assert_eq!(index.training_size(), 1);
+
+        // Training again replaces the centroids
+        index.train(&samples);
+        assert_eq!(index.centroids().len(), 2);
+        assert_eq!(index.training_size(), 20);
+        assert_eq!(index.max_training_size(), 2 * TRAINING_SIZE_PER_LIST);
+        let near_origin = index.nearest_centroids(&[0.0, 0.0], 2);
+        let near_cluster = index.nearest_centroids(&[10.0, 10.0], 2);
+        assert_ne!(near_origin[0], near_cluster[0]);
+        assert_eq!(near_origin.len(), 2);
+        for (id, vector) in vectors.iter().enumerate() {
+            let expected = match id % 2 {
+                0 => near_origin[0],
+                _ => near_cluster[0],
+            };
+            assert_eq!(index.nearest_centroids(vector, 1), vec![expected]);
+        }
+
+        let copy = IvfIndex::init(
+            &index_config(DistanceFunction::Euclidean),
+            Some(&ivf_config),
+            Uuid::new_v4(),
+        )
+        .unwrap();
+        copy.set_centroids(index.centroids(), index.training_size());
+        assert_eq!(
+            copy.nearest_centroids(&[10.0, 10.0], 1),
+            vec![near_cluster[0]]
+        );
+        assert_eq!(copy.training_size(), 20);
+    }
+
+    #[test]
+    fn test_ivf_index_add_delete_query() {
+        let index = IvfIndex::init(
+            &index_config(DistanceFunction::Euclidean),
+            Some(&ivf_config(2, 1)),
+            Uuid::new_v4(),
+        )
+        .unwrap();
+        let vectors = clustered_vectors();
+        for (id, vector) in vectors.iter().enumerate() {
+            index.add(id, vector);
+        }
+        let samples: Vec<&[f32]> = vectors.iter().map(|v| v.as_slice()).collect();
+        index.train(&samples);
+        // Training moves the vectors to the lists of the new centroids
+        for (id, vector) in vectors.iter().enumerate() {
+            assert_eq!(index.list(id), Some(index.nearest_centroids(vector, 1)[0]));
+        }
+        assert_eq!(index.get(2), Some(vectors[2].clone()));
+
+        // Only the closest list is probed
+        let (ids, distances) = index.query(&[0.0, 0.0], 3, &[], &[]);
+        assert_eq!(ids, vec![0, 2, 4]);
+        assert_eq!(distances[0], 0.0);
+        let (ids, _) = index.query(&[0.0, 0.0], 20, &[], &[]);
+        assert!(ids.iter().all(|id| id % 2 == 0));
+        let (ids, _) = index.query_with_nprobe(
+            &[0.0, 0.0],
+            20,
+            2,
+            &RoaringBitmap::new(),
+            &RoaringBitmap::new(),
+        );
+        assert_eq!(ids.len(), 20);
+
+        let (ids, _) = index.query(&[0.0, 0.0], 3, &[2, 4, 5], &[4]);
+        assert_eq!(ids, vec![2]);
+        index.delete(2);
+        assert_eq!(index.get(2), None);
+        let (ids, _) = index.query(&[0.0, 0.0], 2, &[], &[]);
+        assert_eq!(ids, vec![0, 4]);
+    }
+
+    #[test]
+    fn test_ivf_index_save_load() {
+        let index_config = index_config(DistanceFunction::Euclidean);
+        let index = IvfIndex::init(&index_config, Some(&ivf_config(2, 1)), Uuid::new_v4()).unwrap();
+        let vectors = clustered_vectors();
+        let samples: Vec<&[f32]> = vectors.iter().map(|v| v.as_slice()).collect();
+        index.train(&samples);
+        index.save().unwrap();
+
+        let centroids_id = Uuid::new_v4().to_string();
+        let ivf_config = IvfIndexConfig {
+            centroids: index.centroids(),
+            training_size: index.training_size(),
+            ..ivf_config(2, 1)
+        };
+        let loaded =
+            IvfIndex::load(&centroids_id, &index_config, &ivf_config, Uuid::new_v4()).unwrap();
+        assert!(loaded.is_trained());
+        assert_eq!(loaded.centroids(), index.centroids());
+        assert_eq!(loaded.training_size(), 20);
+        assert!(IvfIndex::load("centroids", &index_config, &ivf_config, Uuid::new_v4()).is_err());
+
+        // The centroids must match the dimensionality of the collection
+        let index_config = IndexConfig {
+            dimensionality: 3,
+            ..index_config
+        };
+        assert!(IvfIndex::load(&centroids_id, &index_config, &ivf_config, Uuid::new_v4()).is_err());
+    }
+}
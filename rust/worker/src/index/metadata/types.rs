@@ -1,7 +1,12 @@
-use crate::blockstore::{key::KeyWrapper, BlockfileFlusher, BlockfileReader, BlockfileWriter};
+use crate::blockstore::{
+    key::KeyWrapper, BlockfileFlusher, BlockfileReader, BlockfileWriter, PrefixChunks,
+};
 use crate::errors::{ChromaError, ErrorCodes};
 use crate::index::fulltext::types::FullTextIndexError;
-use crate::types::{BooleanOperator, MetadataType, Where, WhereClauseComparator, WhereComparison};
+use crate::types::{
+    BooleanOperator, DirectComparison, MetadataType, MetadataValue, Where, WhereClauseComparator,
+    WhereClauseListOperator, WhereComparison,
+};
 use crate::utils::{merge_sorted_vecs_conjunction, merge_sorted_vecs_disjunction};
 use thiserror::Error;
 use uuid::Uuid;
@@ -9,7 +14,7 @@ use uuid::Uuid;
 use core::ops::BitOr;
 use parking_lot::Mutex;
 use roaring::RoaringBitmap;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
 use std::sync::Arc;
 
@@ -21,11 +26,19 @@ pub(crate) enum MetadataIndexError {
     BlockfileError(#[from] Box<dyn ChromaError>),
     #[error("Full text index error: {0}")]
     FullTextError(#[from] FullTextIndexError),
+    #[error("Comparator {0:?} is not supported for {1:?} metadata")]
+    UnsupportedComparator(WhereClauseComparator, MetadataType),
+    #[error("Invalid where clause operand: {0}")]
+    InvalidOperand(String),
 }
 
 impl ChromaError for MetadataIndexError {
     fn code(&self) -> crate::errors::ErrorCodes {
-        ErrorCodes::InvalidArgument
+        match self {
+            MetadataIndexError::BlockfileError(e) => e.code(),
+            MetadataIndexError::FullTextError(e) => e.code(),
+            _ => ErrorCodes::InvalidArgument,
+        }
     }
 }
 
@@ -51,11 +64,11 @@ pub(crate) enum MetadataIndexWriter<'me> {
         Option<MetadataIndexReader<'me>>,
         Arc<tokio::sync::Mutex<HashMap<String, HashMap<String, RoaringBitmap>>>>,
     ),
-    U32MetadataIndexWriter(
+    I64MetadataIndexWriter(
         BlockfileWriter,
         // We use this to implement updates which require read-then-write semantics.
         Option<MetadataIndexReader<'me>>,
-        Arc<tokio::sync::Mutex<HashMap<String, HashMap<u32, RoaringBitmap>>>>,
+        Arc<tokio::sync::Mutex<HashMap<String, HashMap<i64, RoaringBitmap>>>>,
     ),
     // We use a Vec<(KeyWrapper, RoaringBitmap)> instead of a HashMap because
     // f32 doesn't implement Eq or Hash. Eq is trivial since we disallow
@@ -76,292 +89,187 @@ pub(crate) enum MetadataIndexWriter<'me> {
     ),
 }
 
+/// A direct where comparison resolved into lookups on the metadata index of
+/// its key. The comparator is applied to every operand and the resulting
+/// bitmaps are combined with the boolean operator, so `$in` is a union of
+/// equalities and `$nin` an intersection of inequalities.
+pub(crate) struct MetadataIndexLookups {
+    pub(crate) metadata_type: MetadataType,
+    pub(crate) comparator: WhereClauseComparator,
+    pub(crate) operands: Vec<KeyWrapper>,
+    pub(crate) operator: BooleanOperator,
+}
+
+impl MetadataIndexLookups {
+    pub(crate) fn combine(&self, bitmaps: Vec<RoaringBitmap>) -> RoaringBitmap {
+        let mut bitmaps = bitmaps.into_iter();
+        let first = bitmaps.next().unwrap_or_default();
+        bitmaps.fold(first, |result, bitmap| match self.operator {
+            BooleanOperator::And => result & bitmap,
+            BooleanOperator::Or => result | bitmap,
+        })
+    }
+}
+
+fn validate_double_operand(operand: f64) -> Result<KeyWrapper, MetadataIndexError> {
+    if operand.is_nan() {
+        return Err(MetadataIndexError::InvalidOperand(
+            "NaN is not comparable".to_string(),
+        ));
+    }
+    Ok((operand as f32).into())
+}
+
+fn list_lookups(
+    metadata_type: MetadataType,
+    operands: Vec<KeyWrapper>,
+    list_operator: &WhereClauseListOperator,
+) -> Result<MetadataIndexLookups, MetadataIndexError> {
+    if operands.is_empty() {
+        return Err(MetadataIndexError::InvalidOperand(
+            "list operands must not be empty".to_string(),
+        ));
+    }
+    let (comparator, operator) = match list_operator {
+        WhereClauseListOperator::In => (WhereClauseComparator::Equal, BooleanOperator::Or),
+        WhereClauseListOperator::NotIn => (WhereClauseComparator::NotEqual, BooleanOperator::And),
+        WhereClauseListOperator::ContainsAny => (WhereClauseComparator::Equal, BooleanOperator::Or),
+        WhereClauseListOperator::ContainsAll => {
+            (WhereClauseComparator::Equal, BooleanOperator::And)
+        }
+    };
+    Ok(MetadataIndexLookups {
+        metadata_type,
+        comparator,
+        operands,
+        operator,
+    })
+}
+
+/// Validates a direct where comparison and resolves it into index lookups.
+/// Strings compare lexicographically, booleans only support equality.
+/// List values are indexed per element, so containment is an equality lookup.
+pub(crate) fn resolve_where_comparison(
+    comparison: &WhereComparison,
+) -> Result<MetadataIndexLookups, MetadataIndexError> {
+    let single = |metadata_type, operand, comparator: &WhereClauseComparator| {
+        let comparator = match comparator {
+            WhereClauseComparator::Contains => WhereClauseComparator::Equal,
+            comparator => comparator.clone(),
+        };
+        Ok(MetadataIndexLookups {
+            metadata_type,
+            comparator,
+            operands: vec![operand],
+            operator: BooleanOperator::And,
+        })
+    };
+    match comparison {
+        WhereComparison::SingleStringComparison(operand, comparator) => single(
+            MetadataType::StringType,
+            operand.as_str().into(),
+            comparator,
+        ),
+        WhereComparison::SingleBoolComparison(operand, comparator) => match comparator {
+            WhereClauseComparator::Equal
+            | WhereClauseComparator::NotEqual
+            | WhereClauseComparator::Contains => {
+                single(MetadataType::BoolType, (*operand).into(), comparator)
+            }
+            _ => Err(MetadataIndexError::UnsupportedComparator(
+                comparator.clone(),
+                MetadataType::BoolType,
+            )),
+        },
+        WhereComparison::SingleIntComparison(operand, comparator) => {
+            single(MetadataType::IntType, (*operand).into(), comparator)
+        }
+        WhereComparison::SingleDoubleComparison(operand, comparator) => single(
+            MetadataType::DoubleType,
+            validate_double_operand(*operand)?,
+            comparator,
+        ),
+        WhereComparison::StringListComparison(operands, list_operator) => list_lookups(
+            MetadataType::StringType,
+            operands
+                .iter()
+                .map(|operand| operand.as_str().into())
+                .collect(),
+            list_operator,
+        ),
+        WhereComparison::IntListComparison(operands, list_operator) => list_lookups(
+            MetadataType::IntType,
+            operands.iter().map(|operand| (*operand).into()).collect(),
+            list_operator,
+        ),
+        WhereComparison::DoubleListComparison(operands, list_operator) => list_lookups(
+            MetadataType::DoubleType,
+            operands
+                .iter()
+                .map(|operand| validate_double_operand(*operand))
+                .collect::<Result<_, _>>()?,
+            list_operator,
+        ),
+        WhereComparison::BoolListComparison(operands, list_operator) => list_lookups(
+            MetadataType::BoolType,
+            operands.iter().map(|operand| (*operand).into()).collect(),
+            list_operator,
+        ),
+        WhereComparison::Exists(_) => Err(MetadataIndexError::InvalidOperand(
+            "$exists does not compare values".to_string(),
+        )),
+    }
+}
+
+/// Evaluates a where clause, asking the callback for the offset ids that
+/// satisfy each single comparison. The callback must answer `NotEqual` with
+/// every offset id it knows of that does not have the value, including those
+/// without the key. `$exists` asks the exists callback for the offset ids
+/// that do or do not have the key.
 pub(crate) fn process_where_clause_with_callback<
     F: Fn(&str, &KeyWrapper, MetadataType, WhereClauseComparator) -> RoaringBitmap,
+    G: Fn(&str, bool) -> RoaringBitmap,
 >(
     where_clause: &Where,
     callback: &F,
+    exists_callback: &G,
 ) -> Result<Vec<usize>, MetadataIndexError> {
     let mut results = vec![];
     match where_clause {
+        Where::DirectWhereComparison(DirectComparison {
+            key,
+            comparison: WhereComparison::Exists(exists),
+        }) => {
+            results = exists_callback(key, *exists)
+                .iter()
+                .map(|x| x as usize)
+                .collect();
+        }
         Where::DirectWhereComparison(direct_where_comparison) => {
-            match &direct_where_comparison.comparison {
-                WhereComparison::SingleStringComparison(operand, comparator) => {
-                    match comparator {
-                        WhereClauseComparator::Equal => {
-                            let metadata_value_keywrapper = operand.as_str().try_into();
-                            match metadata_value_keywrapper {
-                                Ok(keywrapper) => {
-                                    let result = callback(
-                                        &direct_where_comparison.key,
-                                        &keywrapper,
-                                        MetadataType::StringType,
-                                        WhereClauseComparator::Equal,
-                                    );
-                                    results = result.iter().map(|x| x as usize).collect();
-                                }
-                                Err(_) => {
-                                    panic!("Error converting string to keywrapper")
-                                }
-                            }
-                        }
-                        WhereClauseComparator::NotEqual => {
-                            todo!();
-                        }
-                        // We don't allow these comparators for strings.
-                        WhereClauseComparator::LessThan => {
-                            unimplemented!();
-                        }
-                        WhereClauseComparator::LessThanOrEqual => {
-                            unimplemented!();
-                        }
-                        WhereClauseComparator::GreaterThan => {
-                            unimplemented!();
-                        }
-                        WhereClauseComparator::GreaterThanOrEqual => {
-                            unimplemented!();
-                        }
-                    }
-                }
-                WhereComparison::SingleBoolComparison(operand, comparator) => {
-                    match comparator {
-                        WhereClauseComparator::Equal => {
-                            let metadata_value_keywrapper = (*operand).try_into();
-                            match metadata_value_keywrapper {
-                                Ok(keywrapper) => {
-                                    let result = callback(
-                                        &direct_where_comparison.key,
-                                        &keywrapper,
-                                        MetadataType::BoolType,
-                                        WhereClauseComparator::Equal,
-                                    );
-                                    results = result.iter().map(|x| x as usize).collect();
-                                }
-                                Err(_) => {
-                                    panic!("Error converting bool to keywrapper")
-                                }
-                            }
-                        }
-                        WhereClauseComparator::NotEqual => {
-                            todo!();
-                        }
-                        // We don't allow these comparators for booleans.
-                        WhereClauseComparator::LessThan => {
-                            unimplemented!();
-                        }
-                        WhereClauseComparator::LessThanOrEqual => {
-                            unimplemented!();
-                        }
-                        WhereClauseComparator::GreaterThan => {
-                            unimplemented!();
-                        }
-                        WhereClauseComparator::GreaterThanOrEqual => {
-                            unimplemented!();
-                        }
-                    }
-                }
-                WhereComparison::SingleIntComparison(operand, comparator) => match comparator {
-                    WhereClauseComparator::Equal => {
-                        let metadata_value_keywrapper = (*operand).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::IntType,
-                                    WhereClauseComparator::Equal,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting int to keywrapper")
-                            }
-                        }
-                    }
-                    WhereClauseComparator::NotEqual => {
-                        todo!();
-                    }
-                    WhereClauseComparator::LessThan => {
-                        let metadata_value_keywrapper = (*operand).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::IntType,
-                                    WhereClauseComparator::LessThan,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting int to keywrapper")
-                            }
-                        }
-                    }
-                    WhereClauseComparator::LessThanOrEqual => {
-                        let metadata_value_keywrapper = (*operand).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::IntType,
-                                    WhereClauseComparator::LessThanOrEqual,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting int to keywrapper")
-                            }
-                        }
-                    }
-                    WhereClauseComparator::GreaterThan => {
-                        let metadata_value_keywrapper = (*operand).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::IntType,
-                                    WhereClauseComparator::GreaterThan,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting int to keywrapper")
-                            }
-                        }
-                    }
-                    WhereClauseComparator::GreaterThanOrEqual => {
-                        let metadata_value_keywrapper = (*operand).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::IntType,
-                                    WhereClauseComparator::GreaterThanOrEqual,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting int to keywrapper")
-                            }
-                        }
-                    }
-                },
-                WhereComparison::SingleDoubleComparison(operand, comparator) => match comparator {
-                    WhereClauseComparator::Equal => {
-                        let metadata_value_keywrapper = (*operand as f32).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::DoubleType,
-                                    WhereClauseComparator::Equal,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting double to keywrapper")
-                            }
-                        }
-                    }
-                    WhereClauseComparator::NotEqual => {
-                        todo!();
-                    }
-                    WhereClauseComparator::LessThan => {
-                        let metadata_value_keywrapper = (*operand as f32).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::DoubleType,
-                                    WhereClauseComparator::LessThan,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting double to keywrapper")
-                            }
-                        }
-                    }
-                    WhereClauseComparator::LessThanOrEqual => {
-                        let metadata_value_keywrapper = (*operand as f32).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::DoubleType,
-                                    WhereClauseComparator::LessThanOrEqual,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting double to keywrapper")
-                            }
-                        }
-                    }
-                    WhereClauseComparator::GreaterThan => {
-                        let metadata_value_keywrapper = (*operand as f32).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::DoubleType,
-                                    WhereClauseComparator::GreaterThan,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting double to keywrapper")
-                            }
-                        }
-                    }
-                    WhereClauseComparator::GreaterThanOrEqual => {
-                        let metadata_value_keywrapper = (*operand as f32).try_into();
-                        match metadata_value_keywrapper {
-                            Ok(keywrapper) => {
-                                let result = callback(
-                                    &direct_where_comparison.key,
-                                    &keywrapper,
-                                    MetadataType::DoubleType,
-                                    WhereClauseComparator::GreaterThanOrEqual,
-                                );
-                                results = result.iter().map(|x| x as usize).collect();
-                            }
-                            Err(_) => {
-                                panic!("Error converting double to keywrapper")
-                            }
-                        }
-                    }
-                },
-                WhereComparison::StringListComparison(operand, list_operator) => {
-                    todo!();
-                }
-                WhereComparison::IntListComparison(..) => {
-                    todo!();
-                }
-                WhereComparison::DoubleListComparison(..) => {
-                    todo!();
-                }
-                WhereComparison::BoolListComparison(..) => {
-                    todo!();
-                }
-            }
+            let lookups = resolve_where_comparison(&direct_where_comparison.comparison)?;
+            let bitmaps = lookups
+                .operands
+                .iter()
+                .map(|operand| {
+                    callback(
+                        &direct_where_comparison.key,
+                        operand,
+                        lookups.metadata_type.clone(),
+                        lookups.comparator.clone(),
+                    )
+                })
+                .collect();
+            results = lookups
+                .combine(bitmaps)
+                .iter()
+                .map(|x| x as usize)
+                .collect();
         }
         Where::WhereChildren(where_children) => {
             let mut first_iteration = true;
             for child in where_children.children.iter() {
-                let child_results: Vec<usize> =
-                    match process_where_clause_with_callback(&child, callback) {
-                        Ok(result) => result,
-                        Err(_) => vec![],
-                    };
+                let child_results =
+                    process_where_clause_with_callback(child, callback, exists_callback)?;
                 if first_iteration {
                     results = child_results;
                     first_iteration = false;
@@ -394,13 +302,13 @@ impl<'me> MetadataIndexWriter<'me> {
         )
     }
 
-    pub fn new_u32(
+    pub fn new_i64(
         init_blockfile_writer: BlockfileWriter,
-        int_metadata_index_reader: Option<MetadataIndexReader<'me>>,
+        i64_metadata_index_reader: Option<MetadataIndexReader<'me>>,
     ) -> Self {
-        MetadataIndexWriter::U32MetadataIndexWriter(
+        MetadataIndexWriter::I64MetadataIndexWriter(
             init_blockfile_writer,
-            int_metadata_index_reader,
+            i64_metadata_index_reader,
             Arc::new(tokio::sync::Mutex::new(HashMap::new())),
         )
     }
@@ -460,8 +368,8 @@ impl<'me> MetadataIndexWriter<'me> {
                     _ => return Err(MetadataIndexError::InvalidKeyType),
                 }
             }
-            MetadataIndexWriter::U32MetadataIndexWriter(_, reader, uncommitted_rbms) => match key {
-                KeyWrapper::Uint32(k) => {
+            MetadataIndexWriter::I64MetadataIndexWriter(_, reader, uncommitted_rbms) => match key {
+                KeyWrapper::Int64(k) => {
                     let mut uncommitted_rbms = uncommitted_rbms.lock().await;
                     if !uncommitted_rbms.contains_key(prefix) {
                         uncommitted_rbms.insert(prefix.to_string(), HashMap::new());
@@ -554,8 +462,8 @@ impl<'me> MetadataIndexWriter<'me> {
                 }
                 _ => return Err(MetadataIndexError::InvalidKeyType),
             },
-            MetadataIndexWriter::U32MetadataIndexWriter(_, _, uncommitted_rbms) => match key {
-                KeyWrapper::Uint32(k) => {
+            MetadataIndexWriter::I64MetadataIndexWriter(_, _, uncommitted_rbms) => match key {
+                KeyWrapper::Int64(k) => {
                     let mut uncommitted_rbms = uncommitted_rbms.lock().await;
                     let rbms = uncommitted_rbms.get_mut(prefix).unwrap();
                     let rbm = rbms.get_mut(&k).unwrap();
@@ -604,8 +512,8 @@ impl<'me> MetadataIndexWriter<'me> {
                 }
                 _ => return Err(MetadataIndexError::InvalidKeyType),
             },
-            MetadataIndexWriter::U32MetadataIndexWriter(_, _, uncommitted_rbms) => match key {
-                KeyWrapper::Uint32(k) => {
+            MetadataIndexWriter::I64MetadataIndexWriter(_, _, uncommitted_rbms) => match key {
+                KeyWrapper::Int64(k) => {
                     let mut uncommitted_rbms = uncommitted_rbms.lock().await;
                     let rbms = uncommitted_rbms.get_mut(prefix).unwrap();
                     let rbm = rbms.get_mut(&k).unwrap();
@@ -657,7 +565,7 @@ impl<'me> MetadataIndexWriter<'me> {
                     }
                 }
             }
-            MetadataIndexWriter::U32MetadataIndexWriter(blockfile_writer, _, uncommitted_rbms) => {
+            MetadataIndexWriter::I64MetadataIndexWriter(blockfile_writer, _, uncommitted_rbms) => {
                 let mut uncommitted_rbms = uncommitted_rbms.lock().await;
                 for (prefix, rbms) in uncommitted_rbms.drain() {
                     for (key, rbm) in rbms.iter() {
@@ -702,9 +610,9 @@ impl<'me> MetadataIndexWriter<'me> {
                     Err(e) => Err(MetadataIndexError::BlockfileError(e)),
                 }
             }
-            MetadataIndexWriter::U32MetadataIndexWriter(blockfile_writer, _, _) => {
-                match blockfile_writer.commit::<u32, &RoaringBitmap>() {
-                    Ok(flusher) => Ok(MetadataIndexFlusher::U32MetadataIndexFlusher(flusher)),
+            MetadataIndexWriter::I64MetadataIndexWriter(blockfile_writer, _, _) => {
+                match blockfile_writer.commit::<i64, &RoaringBitmap>() {
+                    Ok(flusher) => Ok(MetadataIndexFlusher::I64MetadataIndexFlusher(flusher)),
                     Err(e) => Err(MetadataIndexError::BlockfileError(e)),
                 }
             }
@@ -726,7 +634,7 @@ impl<'me> MetadataIndexWriter<'me> {
 
 pub(crate) enum MetadataIndexFlusher {
     StringMetadataIndexFlusher(BlockfileFlusher),
-    U32MetadataIndexFlusher(BlockfileFlusher),
+    I64MetadataIndexFlusher(BlockfileFlusher),
     F32MetadataIndexFlusher(BlockfileFlusher),
     BoolMetadataIndexFlusher(BlockfileFlusher),
 }
@@ -740,8 +648,8 @@ impl MetadataIndexFlusher {
                     Err(e) => Err(MetadataIndexError::BlockfileError(e)),
                 }
             }
-            MetadataIndexFlusher::U32MetadataIndexFlusher(flusher) => {
-                match flusher.flush::<u32, &RoaringBitmap>().await {
+            MetadataIndexFlusher::I64MetadataIndexFlusher(flusher) => {
+                match flusher.flush::<i64, &RoaringBitmap>().await {
                     Ok(_) => Ok(()),
                     Err(e) => Err(MetadataIndexError::BlockfileError(e)),
                 }
@@ -764,7 +672,7 @@ impl MetadataIndexFlusher {
     pub fn id(&self) -> Uuid {
         match self {
             MetadataIndexFlusher::StringMetadataIndexFlusher(flusher) => flusher.id(),
-            MetadataIndexFlusher::U32MetadataIndexFlusher(flusher) => flusher.id(),
+            MetadataIndexFlusher::I64MetadataIndexFlusher(flusher) => flusher.id(),
             MetadataIndexFlusher::F32MetadataIndexFlusher(flusher) => flusher.id(),
             MetadataIndexFlusher::BoolMetadataIndexFlusher(flusher) => flusher.id(),
         }
@@ -774,9 +682,48 @@ impl MetadataIndexFlusher {
 #[derive(Clone)]
 pub(crate) enum MetadataIndexReader<'me> {
     StringMetadataIndexReader(BlockfileReader<'me, &'me str, RoaringBitmap>),
-    U32MetadataIndexReader(BlockfileReader<'me, u32, RoaringBitmap>),
+    I64MetadataIndexReader(BlockfileReader<'me, i64, RoaringBitmap>),
     F32MetadataIndexReader(BlockfileReader<'me, f32, RoaringBitmap>),
     BoolMetadataIndexReader(BlockfileReader<'me, bool, RoaringBitmap>),
+    // Segments written before integers were 64-bit store i32 values as u32
+    // keys until their next compaction migrates them.
+    LegacyU32MetadataIndexReader(BlockfileReader<'me, u32, RoaringBitmap>),
+}
+
+// The values of a key of a legacy u32 index as the i64 values they stand for,
+// in ascending order. The u32 keys don't sort negative values first, so
+// ranges are answered from the whole key.
+async fn legacy_u32_values<'me>(
+    blockfile_reader: &'me BlockfileReader<'me, u32, RoaringBitmap>,
+    metadata_key: &str,
+) -> Result<Vec<(i64, RoaringBitmap)>, MetadataIndexError> {
+    let mut values: Vec<(i64, RoaringBitmap)> = blockfile_reader
+        .get_by_prefix(metadata_key)
+        .await?
+        .into_iter()
+        .map(|(_, k, rbm)| (k as i32 as i64, rbm))
+        .collect();
+    values.sort_by_key(|(k, _)| *k);
+    Ok(values)
+}
+
+async fn legacy_u32_range<'me>(
+    blockfile_reader: &'me BlockfileReader<'me, u32, RoaringBitmap>,
+    metadata_key: &str,
+    metadata_value: &KeyWrapper,
+    keep: fn(std::cmp::Ordering) -> bool,
+) -> Result<RoaringBitmap, MetadataIndexError> {
+    let value = match metadata_value {
+        KeyWrapper::Int64(value) => *value,
+        _ => return Err(MetadataIndexError::InvalidKeyType),
+    };
+    let mut result = RoaringBitmap::new();
+    for (k, rbm) in legacy_u32_values(blockfile_reader, metadata_key).await? {
+        if keep(k.cmp(&value)) {
+            result |= rbm;
+        }
+    }
+    Ok(result)
 }
 
 impl<'me> MetadataIndexReader<'me> {
@@ -786,8 +733,8 @@ impl<'me> MetadataIndexReader<'me> {
         MetadataIndexReader::StringMetadataIndexReader(init_blockfile_reader)
     }
 
-    pub fn new_u32(init_blockfile_reader: BlockfileReader<'me, u32, RoaringBitmap>) -> Self {
-        MetadataIndexReader::U32MetadataIndexReader(init_blockfile_reader)
+    pub fn new_i64(init_blockfile_reader: BlockfileReader<'me, i64, RoaringBitmap>) -> Self {
+        MetadataIndexReader::I64MetadataIndexReader(init_blockfile_reader)
     }
 
     pub fn new_f32(init_blockfile_reader: BlockfileReader<'me, f32, RoaringBitmap>) -> Self {
@@ -798,6 +745,10 @@ impl<'me> MetadataIndexReader<'me> {
         MetadataIndexReader::BoolMetadataIndexReader(init_blockfile_reader)
     }
 
+    pub fn new_legacy_u32(init_blockfile_reader: BlockfileReader<'me, u32, RoaringBitmap>) -> Self {
+        MetadataIndexReader::LegacyU32MetadataIndexReader(init_blockfile_reader)
+    }
+
     pub async fn get(
         &'me self,
         metadata_key: &str,
@@ -819,8 +770,8 @@ impl<'me> MetadataIndexReader<'me> {
                     _ => return Err(MetadataIndexError::InvalidKeyType),
                 }
             }
-            MetadataIndexReader::U32MetadataIndexReader(blockfile_reader) => match metadata_value {
-                KeyWrapper::Uint32(k) => {
+            MetadataIndexReader::I64MetadataIndexReader(blockfile_reader) => match metadata_value {
+                KeyWrapper::Int64(k) => {
                     if !blockfile_reader.contains(metadata_key, *k).await {
                         return Ok(RoaringBitmap::new());
                     }
@@ -860,6 +811,22 @@ impl<'me> MetadataIndexReader<'me> {
                     _ => return Err(MetadataIndexError::InvalidKeyType),
                 }
             }
+            MetadataIndexReader::LegacyU32MetadataIndexReader(blockfile_reader) => {
+                match metadata_value {
+                    KeyWrapper::Int64(k) => {
+                        // Only values that fit an i32 were stored.
+                        let k = match i32::try_from(*k) {
+                            Ok(k) => k as u32,
+                            Err(_) => return Ok(RoaringBitmap::new()),
+                        };
+                        if !blockfile_reader.contains(metadata_key, k).await {
+                            return Ok(RoaringBitmap::new());
+                        }
+                        Ok(blockfile_reader.get(metadata_key, k).await?)
+                    }
+                    _ => Err(MetadataIndexError::InvalidKeyType),
+                }
+            }
         }
     }
 
@@ -869,8 +836,8 @@ impl<'me> MetadataIndexReader<'me> {
         metadata_value: &'me KeyWrapper,
     ) -> Result<RoaringBitmap, MetadataIndexError> {
         match self {
-            MetadataIndexReader::U32MetadataIndexReader(blockfile_reader) => match metadata_value {
-                KeyWrapper::Uint32(k) => {
+            MetadataIndexReader::I64MetadataIndexReader(blockfile_reader) => match metadata_value {
+                KeyWrapper::Int64(k) => {
                     let read = blockfile_reader.get_lt(metadata_key, *k).await;
                     match read {
                         Ok(records) => {
@@ -901,6 +868,33 @@ impl<'me> MetadataIndexReader<'me> {
                 }
                 _ => return Err(MetadataIndexError::InvalidKeyType),
             },
+            MetadataIndexReader::StringMetadataIndexReader(blockfile_reader) => {
+                match metadata_value {
+                    KeyWrapper::String(k) => {
+                        let read = blockfile_reader.get_lt(metadata_key, k.as_str()).await;
+                        match read {
+                            Ok(records) => {
+                                let mut result = RoaringBitmap::new();
+                                for (_, _, rbm) in records {
+                                    result = result.bitor(&rbm);
+                                }
+                                Ok(result)
+                            }
+                            Err(e) => Err(MetadataIndexError::BlockfileError(e)),
+                        }
+                    }
+                    _ => Err(MetadataIndexError::InvalidKeyType),
+                }
+            }
+            MetadataIndexReader::LegacyU32MetadataIndexReader(blockfile_reader) => {
+                legacy_u32_range(
+                    blockfile_reader,
+                    metadata_key,
+                    metadata_value,
+                    std::cmp::Ordering::is_lt,
+                )
+                .await
+            }
             _ => return Err(MetadataIndexError::InvalidKeyType),
         }
     }
@@ -911,8 +905,8 @@ impl<'me> MetadataIndexReader<'me> {
         metadata_value: &'me KeyWrapper,
     ) -> Result<RoaringBitmap, MetadataIndexError> {
         match self {
-            MetadataIndexReader::U32MetadataIndexReader(blockfile_reader) => match metadata_value {
-                KeyWrapper::Uint32(k) => {
+            MetadataIndexReader::I64MetadataIndexReader(blockfile_reader) => match metadata_value {
+                KeyWrapper::Int64(k) => {
                     let read = blockfile_reader.get_lte(metadata_key, *k).await;
                     match read {
                         Ok(records) => {
@@ -929,7 +923,7 @@ impl<'me> MetadataIndexReader<'me> {
             },
             MetadataIndexReader::F32MetadataIndexReader(blockfile_reader) => match metadata_value {
                 KeyWrapper::Float32(k) => {
-                    let read = blockfile_reader.get_lt(metadata_key, *k).await;
+                    let read = blockfile_reader.get_lte(metadata_key, *k).await;
                     match read {
                         Ok(records) => {
                             let mut result = RoaringBitmap::new();
@@ -943,6 +937,33 @@ impl<'me> MetadataIndexReader<'me> {
                 }
                 _ => return Err(MetadataIndexError::InvalidKeyType),
             },
+            MetadataIndexReader::StringMetadataIndexReader(blockfile_reader) => {
+                match metadata_value {
+                    KeyWrapper::String(k) => {
+                        let read = blockfile_reader.get_lte(metadata_key, k.as_str()).await;
+                        match read {
+                            Ok(records) => {
+                                let mut result = RoaringBitmap::new();
+                                for (_, _, rbm) in records {
+                                    result = result.bitor(&rbm);
+                                }
+                                Ok(result)
+                            }
+                            Err(e) => Err(MetadataIndexError::BlockfileError(e)),
+                        }
+                    }
+                    _ => Err(MetadataIndexError::InvalidKeyType),
+                }
+            }
+            MetadataIndexReader::LegacyU32MetadataIndexReader(blockfile_reader) => {
+                legacy_u32_range(
+                    blockfile_reader,
+                    metadata_key,
+                    metadata_value,
+                    std::cmp::Ordering::is_le,
+                )
+                .await
+            }
             _ => return Err(MetadataIndexError::InvalidKeyType),
         }
     }
@@ -953,8 +974,8 @@ impl<'me> MetadataIndexReader<'me> {
         metadata_value: &'me KeyWrapper,
     ) -> Result<RoaringBitmap, MetadataIndexError> {
         match self {
-            MetadataIndexReader::U32MetadataIndexReader(blockfile_reader) => match metadata_value {
-                KeyWrapper::Uint32(k) => {
+            MetadataIndexReader::I64MetadataIndexReader(blockfile_reader) => match metadata_value {
+                KeyWrapper::Int64(k) => {
                     let read = blockfile_reader.get_gt(metadata_key, *k).await;
                     match read {
                         Ok(records) => {
@@ -985,6 +1006,33 @@ impl<'me> MetadataIndexReader<'me> {
                 }
                 _ => return Err(MetadataIndexError::InvalidKeyType),
             },
+            MetadataIndexReader::StringMetadataIndexReader(blockfile_reader) => {
+                match metadata_value {
+                    KeyWrapper::String(k) => {
+                        let read = blockfile_reader.get_gt(metadata_key, k.as_str()).await;
+                        match read {
+                            Ok(records) => {
+                                let mut result = RoaringBitmap::new();
+                                for (_, _, rbm) in records {
+                                    result = result.bitor(&rbm);
+                                }
+                                Ok(result)
+                            }
+                            Err(e) => Err(MetadataIndexError::BlockfileError(e)),
+                        }
+                    }
+                    _ => Err(MetadataIndexError::InvalidKeyType),
+                }
+            }
+            MetadataIndexReader::LegacyU32MetadataIndexReader(blockfile_reader) => {
+                legacy_u32_range(
+                    blockfile_reader,
+                    metadata_key,
+                    metadata_value,
+                    std::cmp::Ordering::is_gt,
+                )
+                .await
+            }
             _ => return Err(MetadataIndexError::InvalidKeyType),
         }
     }
@@ -995,8 +1043,8 @@ impl<'me> MetadataIndexReader<'me> {
         metadata_value: &'me KeyWrapper,
     ) -> Result<RoaringBitmap, MetadataIndexError> {
         match self {
-            MetadataIndexReader::U32MetadataIndexReader(blockfile_reader) => match metadata_value {
-                KeyWrapper::Uint32(k) => {
+            MetadataIndexReader::I64MetadataIndexReader(blockfile_reader) => match metadata_value {
+                KeyWrapper::Int64(k) => {
                     let read = blockfile_reader.get_gte(metadata_key, *k).await;
                     match read {
                         Ok(records) => {
@@ -1027,9 +1075,184 @@ impl<'me> MetadataIndexReader<'me> {
                 }
                 _ => return Err(MetadataIndexError::InvalidKeyType),
             },
+            MetadataIndexReader::StringMetadataIndexReader(blockfile_reader) => {
+                match metadata_value {
+                    KeyWrapper::String(k) => {
+                        let read = blockfile_reader.get_gte(metadata_key, k.as_str()).await;
+                        match read {
+                            Ok(records) => {
+                                let mut result = RoaringBitmap::new();
+                                for (_, _, rbm) in records {
+                                    result = result.bitor(&rbm);
+                                }
+                                Ok(result)
+                            }
+                            Err(e) => Err(MetadataIndexError::BlockfileError(e)),
+                        }
+                    }
+                    _ => Err(MetadataIndexError::InvalidKeyType),
+                }
+            }
+            MetadataIndexReader::LegacyU32MetadataIndexReader(blockfile_reader) => {
+                legacy_u32_range(
+                    blockfile_reader,
+                    metadata_key,
+                    metadata_value,
+                    std::cmp::Ordering::is_ge,
+                )
+                .await
+            }
             _ => return Err(MetadataIndexError::InvalidKeyType),
         }
     }
+
+    /// Returns the offset ids of the records that have each metadata key in
+    /// the index, whatever the value.
+    pub async fn offset_ids_by_key(
+        &'me self,
+    ) -> Result<HashMap<String, RoaringBitmap>, MetadataIndexError> {
+        let mut offset_ids_by_key: HashMap<String, RoaringBitmap> = HashMap::new();
+        match self {
+            MetadataIndexReader::StringMetadataIndexReader(blockfile_reader) => {
+                for index in 0..blockfile_reader.count().await? {
+                    let (prefix, _, rbm) = blockfile_reader.get_at_index(index).await?;
+                    *offset_ids_by_key.entry(prefix.to_string()).or_default() |= rbm;
+                }
+            }
+            MetadataIndexReader::I64MetadataIndexReader(blockfile_reader) => {
+                for index in 0..blockfile_reader.count().await? {
+                    let (prefix, _, rbm) = blockfile_reader.get_at_index(index).await?;
+                    *offset_ids_by_key.entry(prefix.to_string()).or_default() |= rbm;
+                }
+            }
+            MetadataIndexReader::F32MetadataIndexReader(blockfile_reader) => {
+                for index in 0..blockfile_reader.count().await? {
+                    let (prefix, _, rbm) = blockfile_reader.get_at_index(index).await?;
+                    *offset_ids_by_key.entry(prefix.to_string()).or_default() |= rbm;
+                }
+            }
+            MetadataIndexReader::BoolMetadataIndexReader(blockfile_reader) => {
+                for index in 0..blockfile_reader.count().await? {
+                    let (prefix, _, rbm) = blockfile_reader.get_at_index(index).await?;
+                    *offset_ids_by_key.entry(prefix.to_string()).or_default() |= rbm;
+                }
+            }
+            MetadataIndexReader::LegacyU32MetadataIndexReader(blockfile_reader) => {
+                for index in 0..blockfile_reader.count().await? {
+                    let (prefix, _, rbm) = blockfile_reader.get_at_index(index).await?;
+                    *offset_ids_by_key.entry(prefix.to_string()).or_default() |= rbm;
+                }
+            }
+        }
+        Ok(offset_ids_by_key)
+    }
+
+    /// Returns the values of a metadata key in ascending or descending order,
+    /// with the offset ids of the records that hold them. The index is read
+    /// as the values are consumed.
+    pub async fn sorted_values(
+        &'me self,
+        metadata_key: &str,
+        descending: bool,
+    ) -> Result<SortedValues<'me>, MetadataIndexError> {
+        let chunks = match self {
+            MetadataIndexReader::StringMetadataIndexReader(blockfile_reader) => {
+                ValueChunks::Str(blockfile_reader.get_by_prefix_chunks(metadata_key, descending))
+            }
+            MetadataIndexReader::I64MetadataIndexReader(blockfile_reader) => {
+                ValueChunks::I64(blockfile_reader.get_by_prefix_chunks(metadata_key, descending))
+            }
+            MetadataIndexReader::F32MetadataIndexReader(blockfile_reader) => {
+                ValueChunks::F32(blockfile_reader.get_by_prefix_chunks(metadata_key, descending))
+            }
+            MetadataIndexReader::BoolMetadataIndexReader(blockfile_reader) => {
+                ValueChunks::Bool(blockfile_reader.get_by_prefix_chunks(metadata_key, descending))
+            }
+            MetadataIndexReader::LegacyU32MetadataIndexReader(blockfile_reader) => {
+                let mut values: Vec<(MetadataValue, RoaringBitmap)> =
+                    legacy_u32_values(blockfile_reader, metadata_key)
+                        .await?
+                        .into_iter()
+                        .map(|(k, rbm)| (MetadataValue::Int(k), rbm))
+                        .collect();
+                if descending {
+                    values.reverse();
+                }
+                ValueChunks::Loaded(Some(values))
+            }
+        };
+        Ok(SortedValues {
+            chunks,
+            buffered: VecDeque::new(),
+        })
+    }
+}
+
+/// The values of a metadata key in one index, in the order asked of
+/// `MetadataIndexReader::sorted_values`, with the offset ids that hold them.
+pub(crate) struct SortedValues<'me> {
+    chunks: ValueChunks<'me>,
+    buffered: VecDeque<(MetadataValue, RoaringBitmap)>,
+}
+
+enum ValueChunks<'me> {
+    Str(PrefixChunks<'me, &'me str, RoaringBitmap>),
+    I64(PrefixChunks<'me, i64, RoaringBitmap>),
+    F32(PrefixChunks<'me, f32, RoaringBitmap>),
+    Bool(PrefixChunks<'me, bool, RoaringBitmap>),
+    // The u32 keys of a legacy index are not in value order, so it is read
+    // whole.
+    Loaded(Option<Vec<(MetadataValue, RoaringBitmap)>>),
+}
+
+impl<'me> SortedValues<'me> {
+    /// Returns the next value without consuming it.
+    pub(crate) async fn peek(
+        &mut self,
+    ) -> Result<Option<&(MetadataValue, RoaringBitmap)>, MetadataIndexError> {
+        while self.buffered.is_empty() {
+            let chunk: Option<Vec<(MetadataValue, RoaringBitmap)>> = match &mut self.chunks {
+                ValueChunks::Str(chunks) => chunks.next_chunk().await?.map(|chunk| {
+                    chunk
+                        .into_iter()
+                        .map(|(k, rbm)| (MetadataValue::Str(k.to_string()), rbm))
+                        .collect()
+                }),
+                ValueChunks::I64(chunks) => chunks.next_chunk().await?.map(|chunk| {
+                    chunk
+                        .into_iter()
+                        .map(|(k, rbm)| (MetadataValue::Int(k), rbm))
+                        .collect()
+                }),
+                ValueChunks::F32(chunks) => chunks.next_chunk().await?.map(|chunk| {
+                    chunk
+                        .into_iter()
+                        .map(|(k, rbm)| (MetadataValue::Float(k as f64), rbm))
+                        .collect()
+                }),
+                ValueChunks::Bool(chunks) => chunks.next_chunk().await?.map(|chunk| {
+                    chunk
+                        .into_iter()
+                        .map(|(k, rbm)| (MetadataValue::Bool(k), rbm))
+                        .collect()
+                }),
+                ValueChunks::Loaded(values) => values.take(),
+            };
+            match chunk {
+                Some(chunk) => self.buffered.extend(chunk),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.buffered.front())
+    }
+
+    /// Returns the next value.
+    pub(crate) async fn next(
+        &mut self,
+    ) -> Result<Option<(MetadataValue, RoaringBitmap)>, MetadataIndexError> {
+        self.peek().await?;
+        Ok(self.buffered.pop_front())
+    }
 }
 
 #[cfg(test)]
@@ -1045,10 +1268,10 @@ mod test {
     }
 
     #[tokio::test]
-    async fn test_new_u32_writer() {
+    async fn test_new_i64_writer() {
         let provider = BlockfileProvider::new_memory();
-        let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
-        let _writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
+        let _writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
     }
 
     #[tokio::test]
@@ -1083,20 +1306,20 @@ mod test {
     }
 
     #[tokio::test]
-    async fn test_new_u32_writer_then_reader() {
+    async fn test_new_i64_writer_then_reader() {
         let provider = BlockfileProvider::new_memory();
-        let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
         let writer_id = blockfile_writer.id();
-        let mut md_writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
+        let mut md_writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
         md_writer.write_to_blockfile().await.unwrap();
         let flusher = md_writer.commit().unwrap();
         flusher.flush().await.unwrap();
 
         let blockfile_reader = provider
-            .open::<u32, RoaringBitmap>(&writer_id)
+            .open::<i64, RoaringBitmap>(&writer_id)
             .await
             .unwrap();
-        let _reader = MetadataIndexReader::new_u32(blockfile_reader);
+        let _reader = MetadataIndexReader::new_i64(blockfile_reader);
     }
 
     #[tokio::test]
@@ -1155,22 +1378,22 @@ mod test {
     }
 
     #[tokio::test]
-    async fn test_u32_metadata_index_set_get() {
+    async fn test_i64_metadata_index_set_get() {
         let provider = BlockfileProvider::new_memory();
-        let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
         let writer_id = blockfile_writer.id();
-        let mut writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
-        writer.set("key", 1, 1).await.unwrap();
+        let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
+        writer.set("key", 1i64, 1).await.unwrap();
         writer.write_to_blockfile().await.unwrap();
         let flusher = writer.commit().unwrap();
         flusher.flush().await.unwrap();
 
         let blockfile_reader = provider
-            .open::<u32, RoaringBitmap>(&writer_id)
+            .open::<i64, RoaringBitmap>(&writer_id)
             .await
             .unwrap();
-        let reader = MetadataIndexReader::new_u32(blockfile_reader);
-        let bitmap = reader.get("key", &1.into()).await.unwrap();
+        let reader = MetadataIndexReader::new_i64(blockfile_reader);
+        let bitmap = reader.get("key", &1i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 1);
         assert!(bitmap.contains(1));
     }
@@ -1247,30 +1470,30 @@ mod test {
     }
 
     #[tokio::test]
-    async fn test_u32_metadata_multiple_keys() {
+    async fn test_i64_metadata_multiple_keys() {
         let provider = BlockfileProvider::new_memory();
-        let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
         let writer_id = blockfile_writer.id();
-        let mut writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
-        writer.set("key1", 1, 1).await.unwrap();
-        writer.set("key1", 1, 2).await.unwrap();
-        writer.set("key2", 1, 3).await.unwrap();
-        writer.set("key2", 2, 4).await.unwrap();
+        let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
+        writer.set("key1", 1i64, 1).await.unwrap();
+        writer.set("key1", 1i64, 2).await.unwrap();
+        writer.set("key2", 1i64, 3).await.unwrap();
+        writer.set("key2", 2i64, 4).await.unwrap();
         writer.write_to_blockfile().await.unwrap();
         let flusher = writer.commit().unwrap();
         flusher.flush().await.unwrap();
 
         let blockfile_reader = provider
-            .open::<u32, RoaringBitmap>(&writer_id)
+            .open::<i64, RoaringBitmap>(&writer_id)
             .await
             .unwrap();
-        let reader = MetadataIndexReader::new_u32(blockfile_reader);
-        let bitmap = reader.get("key1", &1.into()).await.unwrap();
+        let reader = MetadataIndexReader::new_i64(blockfile_reader);
+        let bitmap = reader.get("key1", &1i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 2);
         assert!(bitmap.contains(1));
         assert!(bitmap.contains(2));
 
-        let bitmap = reader.get("key2", &1.into()).await.unwrap();
+        let bitmap = reader.get("key2", &1i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 1);
         assert!(bitmap.contains(3));
     }
@@ -1334,139 +1557,172 @@ mod test {
     }
 
     #[tokio::test]
-    async fn test_u32_metadata_lt_operator() {
+    async fn test_i64_metadata_lt_operator() {
         let provider = BlockfileProvider::new_memory();
-        let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
         let writer_id = blockfile_writer.id();
-        let mut writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
-        writer.set("key1", 1, 1).await.unwrap();
-        writer.set("key1", 2, 2).await.unwrap();
-        writer.set("key1", 3, 3).await.unwrap();
-        writer.set("key1", 4, 4).await.unwrap();
-        writer.set("key2", 5, 5).await.unwrap();
+        let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
+        writer.set("key1", 1i64, 1).await.unwrap();
+        writer.set("key1", 2i64, 2).await.unwrap();
+        writer.set("key1", 3i64, 3).await.unwrap();
+        writer.set("key1", 4i64, 4).await.unwrap();
+        writer.set("key2", 5i64, 5).await.unwrap();
         writer.write_to_blockfile().await.unwrap();
         let flusher = writer.commit().unwrap();
         flusher.flush().await.unwrap();
 
         let blockfile_reader = provider
-            .open::<u32, RoaringBitmap>(&writer_id)
+            .open::<i64, RoaringBitmap>(&writer_id)
             .await
             .unwrap();
-        let reader = MetadataIndexReader::new_u32(blockfile_reader);
-        let bitmap = reader.lt("key1", &3.into()).await.unwrap();
+        let reader = MetadataIndexReader::new_i64(blockfile_reader);
+        let bitmap = reader.lt("key1", &3i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 2);
         assert!(bitmap.contains(1));
         assert!(bitmap.contains(2));
 
-        let bitmap = reader.lt("key2", &6.into()).await.unwrap();
+        let bitmap = reader.lt("key2", &6i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 1);
         assert!(bitmap.contains(5));
 
-        let bitmap = reader.lt("key2", &5.into()).await;
+        let bitmap = reader.lt("key2", &5i64.into()).await;
         assert!(bitmap.is_err());
     }
 
     #[tokio::test]
-    async fn test_u32_value_metadata_lte_operator() {
+    async fn test_i64_value_metadata_lte_operator() {
         let provider = BlockfileProvider::new_memory();
-        let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
         let writer_id = blockfile_writer.id();
-        let mut writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
-        writer.set("key1", 1, 1).await.unwrap();
-        writer.set("key1", 2, 2).await.unwrap();
-        writer.set("key1", 3, 3).await.unwrap();
-        writer.set("key1", 4, 4).await.unwrap();
-        writer.set("key2", 5, 5).await.unwrap();
+        let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
+        writer.set("key1", 1i64, 1).await.unwrap();
+        writer.set("key1", 2i64, 2).await.unwrap();
+        writer.set("key1", 3i64, 3).await.unwrap();
+        writer.set("key1", 4i64, 4).await.unwrap();
+        writer.set("key2", 5i64, 5).await.unwrap();
         writer.write_to_blockfile().await.unwrap();
         let flusher = writer.commit().unwrap();
         flusher.flush().await.unwrap();
 
         let blockfile_reader = provider
-            .open::<u32, RoaringBitmap>(&writer_id)
+            .open::<i64, RoaringBitmap>(&writer_id)
             .await
             .unwrap();
-        let reader = MetadataIndexReader::new_u32(blockfile_reader);
-        let bitmap = reader.lte("key1", &3.into()).await.unwrap();
+        let reader = MetadataIndexReader::new_i64(blockfile_reader);
+        let bitmap = reader.lte("key1", &3i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 3);
         assert!(bitmap.contains(1));
         assert!(bitmap.contains(2));
         assert!(bitmap.contains(3));
 
-        let bitmap = reader.lte("key2", &5.into()).await.unwrap();
+        let bitmap = reader.lte("key2", &5i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 1);
         assert!(bitmap.contains(5));
 
-        let bitmap = reader.lte("key2", &4.into()).await;
+        let bitmap = reader.lte("key2", &4i64.into()).await;
         assert!(bitmap.is_err());
     }
 
     #[tokio::test]
-    async fn test_u32_value_metadata_gt_operator() {
+    async fn test_i64_value_metadata_gt_operator() {
         let provider = BlockfileProvider::new_memory();
-        let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
         let writer_id = blockfile_writer.id();
-        let mut writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
-        writer.set("key1", 1, 1).await.unwrap();
-        writer.set("key1", 2, 2).await.unwrap();
-        writer.set("key1", 3, 3).await.unwrap();
-        writer.set("key1", 4, 4).await.unwrap();
-        writer.set("key2", 5, 5).await.unwrap();
+        let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
+        writer.set("key1", 1i64, 1).await.unwrap();
+        writer.set("key1", 2i64, 2).await.unwrap();
+        writer.set("key1", 3i64, 3).await.unwrap();
+        writer.set("key1", 4i64, 4).await.unwrap();
+        writer.set("key2", 5i64, 5).await.unwrap();
         writer.write_to_blockfile().await.unwrap();
         let flusher = writer.commit().unwrap();
         flusher.flush().await.unwrap();
 
         let blockfile_reader = provider
-            .open::<u32, RoaringBitmap>(&writer_id)
+            .open::<i64, RoaringBitmap>(&writer_id)
             .await
             .unwrap();
-        let reader = MetadataIndexReader::new_u32(blockfile_reader);
-        let bitmap = reader.gt("key1", &2.into()).await.unwrap();
+        let reader = MetadataIndexReader::new_i64(blockfile_reader);
+        let bitmap = reader.gt("key1", &2i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 2);
         assert!(bitmap.contains(3));
         assert!(bitmap.contains(4));
 
-        let bitmap = reader.gt("key2", &4.into()).await.unwrap();
+        let bitmap = reader.gt("key2", &4i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 1);
         assert!(bitmap.contains(5));
 
-        let bitmap = reader.gt("key2", &5.into()).await;
+        let bitmap = reader.gt("key2", &5i64.into()).await;
         assert!(bitmap.is_err());
     }
 
     #[tokio::test]
-    async fn test_u32_value_metadata_gte_operator() {
+    async fn test_i64_value_metadata_gte_operator() {
         let provider = BlockfileProvider::new_memory();
-        let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
         let writer_id = blockfile_writer.id();
-        let mut writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
-        writer.set("key1", 1, 1).await.unwrap();
-        writer.set("key1", 2, 2).await.unwrap();
-        writer.set("key1", 3, 3).await.unwrap();
-        writer.set("key1", 4, 4).await.unwrap();
-        writer.set("key2", 5, 5).await.unwrap();
+        let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
+        writer.set("key1", 1i64, 1).await.unwrap();
+        writer.set("key1", 2i64, 2).await.unwrap();
+        writer.set("key1", 3i64, 3).await.unwrap();
+        writer.set("key1", 4i64, 4).await.unwrap();
+        writer.set("key2", 5i64, 5).await.unwrap();
         writer.write_to_blockfile().await.unwrap();
         let flusher = writer.commit().unwrap();
         flusher.flush().await.unwrap();
 
         let blockfile_reader = provider
-            .open::<u32, RoaringBitmap>(&writer_id)
+            .open::<i64, RoaringBitmap>(&writer_id)
             .await
             .unwrap();
-        let reader = MetadataIndexReader::new_u32(blockfile_reader);
-        let bitmap = reader.gte("key1", &2.into()).await.unwrap();
+        let reader = MetadataIndexReader::new_i64(blockfile_reader);
+        let bitmap = reader.gte("key1", &2i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 3);
         assert!(bitmap.contains(2));
         assert!(bitmap.contains(3));
         assert!(bitmap.contains(4));
 
-        let bitmap = reader.gte("key2", &5.into()).await.unwrap();
+        let bitmap = reader.gte("key2", &5i64.into()).await.unwrap();
         assert_eq!(bitmap.len(), 1);
         assert!(bitmap.contains(5));
 
-        let bitmap = reader.gte("key2", &6.into()).await;
+        let bitmap = reader.gte("key2", &6i64.into()).await;
         assert!(bitmap.is_err());
     }
 
+    #[tokio::test]
+    async fn test_i64_metadata_signed_range_operators() {
+        let provider = BlockfileProvider::new_memory();
+        let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
+        let writer_id = blockfile_writer.id();
+        let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
+        writer.set("key1", i64::MIN, 1).await.unwrap();
+        writer.set("key1", -5i64, 2).await.unwrap();
+        writer.set("key1", 0i64, 3).await.unwrap();
+        writer.set("key1", 1i64 << 31, 4).await.unwrap();
+        writer.set("key1", i64::MAX, 5).await.unwrap();
+        writer.write_to_blockfile().await.unwrap();
+        let flusher = writer.commit().unwrap();
+        flusher.flush().await.unwrap();
+
+        let blockfile_reader = provider
+            .open::<i64, RoaringBitmap>(&writer_id)
+            .await
+            .unwrap();
+        let reader = MetadataIndexReader::new_i64(blockfile_reader);
+        let bitmap = reader.lt("key1", &0i64.into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![1, 2]);
+
+        let bitmap = reader.gte("key1", &(-5i64).into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![2, 3, 4, 5]);
+
+        let bitmap = reader.gt("key1", &(i32::MAX as i64).into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![4, 5]);
+
+        let bitmap = reader.get("key1", &(1i64 << 31).into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![4]);
+    }
+
     #[tokio::test]
     async fn test_f32_metadata_lt_operator() {
         let provider = BlockfileProvider::new_memory();
@@ -1603,42 +1859,110 @@ mod test {
         assert!(bitmap.is_err());
     }
 
+    #[tokio::test]
+    async fn test_string_metadata_range_operators() {
+        let provider = BlockfileProvider::new_memory();
+        let blockfile_writer = provider.create::<&str, &RoaringBitmap>().unwrap();
+        let writer_id = blockfile_writer.id();
+        let mut writer = MetadataIndexWriter::new_string(blockfile_writer, None);
+        writer.set("key1", "apple", 1).await.unwrap();
+        writer.set("key1", "banana", 2).await.unwrap();
+        writer.set("key1", "cherry", 3).await.unwrap();
+        writer.set("key2", "apple", 4).await.unwrap();
+        writer.write_to_blockfile().await.unwrap();
+        let flusher = writer.commit().unwrap();
+        flusher.flush().await.unwrap();
+
+        let blockfile_reader = provider
+            .open::<&str, RoaringBitmap>(&writer_id)
+            .await
+            .unwrap();
+        let reader = MetadataIndexReader::new_string(blockfile_reader);
+        let bitmap = reader.lt("key1", &"banana".into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![1]);
+        let bitmap = reader.lte("key1", &"banana".into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![1, 2]);
+        let bitmap = reader.gt("key1", &"b".into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![2, 3]);
+        let bitmap = reader.gte("key1", &"cherry".into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn test_resolve_where_comparison() {
+        let lookups = resolve_where_comparison(&WhereComparison::IntListComparison(
+            vec![1, 2],
+            WhereClauseListOperator::NotIn,
+        ))
+        .unwrap();
+        assert_eq!(lookups.comparator, WhereClauseComparator::NotEqual);
+        assert_eq!(lookups.operator, BooleanOperator::And);
+        assert_eq!(lookups.operands.len(), 2);
+
+        let lookups = resolve_where_comparison(&WhereComparison::StringListComparison(
+            vec!["a".to_string(), "b".to_string()],
+            WhereClauseListOperator::ContainsAll,
+        ))
+        .unwrap();
+        assert_eq!(lookups.comparator, WhereClauseComparator::Equal);
+        assert_eq!(lookups.operator, BooleanOperator::And);
+
+        let lookups = resolve_where_comparison(&WhereComparison::SingleBoolComparison(
+            true,
+            WhereClauseComparator::Contains,
+        ))
+        .unwrap();
+        assert_eq!(lookups.comparator, WhereClauseComparator::Equal);
+
+        let unsupported = [
+            WhereComparison::SingleBoolComparison(true, WhereClauseComparator::LessThan),
+            WhereComparison::BoolListComparison(vec![], WhereClauseListOperator::In),
+            WhereComparison::DoubleListComparison(vec![f64::NAN], WhereClauseListOperator::In),
+        ];
+        for comparison in unsupported.iter() {
+            match resolve_where_comparison(comparison) {
+                Err(e) => assert_eq!(e.code(), ErrorCodes::InvalidArgument),
+                Ok(_) => panic!("Expected {:?} to be rejected", comparison),
+            }
+        }
+    }
+
     // TODO enable this test once fork() is enabled for MemoryBlockfiles.
     // #[tokio::test]
     // async fn test_set_get_set_delete() {
     //     let provider = BlockfileProvider::new_memory();
-    //     let blockfile_writer = provider.create::<u32, &RoaringBitmap>().unwrap();
+    //     let blockfile_writer = provider.create::<i64, &RoaringBitmap>().unwrap();
     //     let writer_id = blockfile_writer.id();
-    //     let mut writer = MetadataIndexWriter::new_u32(blockfile_writer, None);
+    //     let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, None);
     //     writer.set("key1", 1, 1).await.unwrap();
     //     writer.write_to_blockfile().await.unwrap();
     //     let flusher = writer.commit().unwrap();
     //     flusher.flush().await.unwrap();
 
     //     let blockfile_reader = provider
-    //         .open::<u32, RoaringBitmap>(&writer_id)
+    //         .open::<i64, RoaringBitmap>(&writer_id)
     //         .await
     //         .unwrap();
-    //     let reader = MetadataIndexReader::new_u32(blockfile_reader);
+    //     let reader = MetadataIndexReader::new_i64(blockfile_reader);
     //     let bitmap = reader.get("key1", &1.into()).await.unwrap();
     //     assert_eq!(bitmap.len(), 1);
     //     assert!(bitmap.contains(1));
 
     //     let blockfile_writer = provider
-    //         .fork::<u32, &RoaringBitmap>(&writer_id)
+    //         .fork::<i64, &RoaringBitmap>(&writer_id)
     //         .await
     //         .unwrap();
-    //     let mut writer = MetadataIndexWriter::new_u32(blockfile_writer, Some(reader));
+    //     let mut writer = MetadataIndexWriter::new_i64(blockfile_writer, Some(reader));
     //     writer.set("key1", 1, 2).await.unwrap();
     //     writer.write_to_blockfile().await.unwrap();
     //     let flusher = writer.commit().unwrap();
     //     flusher.flush().await.unwrap();
 
     //     let blockfile_reader = provider
-    //         .open::<u32, RoaringBitmap>(&writer_id)
+    //         .open::<i64, RoaringBitmap>(&writer_id)
     //         .await
     //         .unwrap();
-    //     let reader = MetadataIndexReader::new_u32(blockfile_reader);
+    //     let reader = MetadataIndexReader::new_i64(blockfile_reader);
     //     let bitmap = reader.get("key1", &1.into()).await.unwrap();
     //     assert_eq!(bitmap.len(), 2);
     //     assert!(bitmap.contains(1));
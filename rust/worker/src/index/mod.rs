@@ -1,6 +1,10 @@
+pub(crate) mod config;
 pub(crate) mod fulltext;
 mod hnsw;
+mod hnsw_cache;
+pub(crate) mod hnsw_manifest;
 pub(crate) mod hnsw_provider;
+pub(crate) mod ivf;
 pub(crate) mod metadata;
 mod types;
 mod utils;
@@ -8,6 +8,9 @@ use uuid::Uuid;
 pub(crate) struct IndexConfig {
     pub(crate) dimensionality: i32,
     pub(crate) distance_function: DistanceFunction,
+    // Whether each record holds a bag of vectors of `dimensionality` values,
+    // concatenated into one embedding, instead of a single vector.
+    pub(crate) multi_vector: bool,
 }
 
 #[derive(Error, Debug)]
@@ -36,10 +39,18 @@ impl IndexConfig {
             },
             None => "l2",
         };
+        let multi_vector = match segment.metadata {
+            Some(ref metadata) => matches!(
+                metadata.get("hnsw:multi_vector"),
+                Some(MetadataValue::Bool(true))
+            ),
+            None => false,
+        };
         match DistanceFunction::try_from(space) {
             Ok(distance_function) => Ok(IndexConfig {
                 dimensionality,
                 distance_function,
+                multi_vector,
             }),
             Err(e) => Err(Box::new(
                 IndexConfigFromSegmentError::InvalidDistanceFunction(e),
@@ -85,10 +96,17 @@ pub(crate) trait Index<C> {
 /// - `load` - Load the index from a given path.
 /// # Notes
 /// This defines a rudimentary interface for saving and loading indices.
-/// TODO: Right now load() takes IndexConfig because we don't implement save/load of the config.
+/// Indices persist the configuration they were built with, load() takes the
+/// IndexConfig of the collection to validate it against. Indices persisted
+/// without their configuration are loaded with `config`.
 pub(crate) trait PersistentIndex<C>: Index<C> {
     fn save(&self) -> Result<(), Box<dyn ChromaError>>;
-    fn load(path: &str, index_config: &IndexConfig, id: Uuid) -> Result<Self, Box<dyn ChromaError>>
+    fn load(
+        path: &str,
+        index_config: &IndexConfig,
+        config: &C,
+        id: Uuid,
+    ) -> Result<Self, Box<dyn ChromaError>>
     where
         Self: Sized;
 }
@@ -42,6 +42,11 @@ pub async fn query_service_entrypoint() {
         &config.service_name,
         &config.otel_endpoint,
     );
+    println!(
+        "Using {} distance kernels and {} binary distance kernels",
+        distance::distance_kernels_name(),
+        distance::binary_distance_kernels_name()
+    );
 
     let system: system::System = system::System::new();
     let dispatcher =
@@ -102,6 +107,11 @@ pub async fn compaction_service_entrypoint() {
         &config.service_name,
         &config.otel_endpoint,
     );
+    println!(
+        "Using {} distance kernels and {} binary distance kernels",
+        distance::distance_kernels_name(),
+        distance::binary_distance_kernels_name()
+    );
 
     let system: system::System = system::System::new();
 
@@ -1,18 +1,19 @@
-use super::record_segment::ApplyMaterializedLogError;
+use super::record_segment::{ApplyMaterializedLogError, RecordSegmentReader};
 use super::{SegmentFlusher, SegmentWriter};
 use crate::errors::{ChromaError, ErrorCodes};
 use crate::index::hnsw_provider::{
     HnswIndexProvider, HnswIndexProviderCommitError, HnswIndexProviderCreateError,
     HnswIndexProviderFlushError, HnswIndexProviderForkError, HnswIndexProviderOpenError,
+    HNSW_MANIFEST,
 };
 use crate::index::{
-    HnswIndex, HnswIndexConfig, HnswIndexFromSegmentError, Index, IndexConfig,
-    IndexConfigFromSegmentError,
+    multi_vector_offset_id, HnswIndex, HnswIndexConfig, HnswIndexFromSegmentError, Index,
+    IndexConfig, IndexConfigFromSegmentError,
 };
 use crate::types::{LogRecord, Operation, Segment};
 use async_trait::async_trait;
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::sync::Arc;
 use thiserror::Error;
@@ -51,6 +52,8 @@ pub enum DistributedHNSWSegmentFromSegmentError {
     HnswIndexProviderForkError(#[from] HnswIndexProviderForkError),
     #[error("HNSW index provider create error")]
     HnswIndexProviderCreateError(#[from] HnswIndexProviderCreateError),
+    #[error("Error reading record segment")]
+    RecordSegmentReadError(Box<dyn ChromaError>),
 }
 
 impl ChromaError for DistributedHNSWSegmentFromSegmentError {
@@ -64,6 +67,7 @@ impl ChromaError for DistributedHNSWSegmentFromSegmentError {
             DistributedHNSWSegmentFromSegmentError::HnswIndexProviderOpenError(e) => e.code(),
             DistributedHNSWSegmentFromSegmentError::HnswIndexProviderForkError(e) => e.code(),
             DistributedHNSWSegmentFromSegmentError::HnswIndexProviderCreateError(e) => e.code(),
+            DistributedHNSWSegmentFromSegmentError::RecordSegmentReadError(e) => e.code(),
         }
     }
 }
@@ -171,6 +175,72 @@ impl DistributedHNSWSegmentWriter {
             )))
         }
     }
+
+    /// The fraction of the elements in the graph that are deleted. Deleted elements
+    /// are only marked as such, so they keep using capacity and slow down searches.
+    pub(crate) fn tombstone_ratio(&self) -> f32 {
+        let index = self.index.read();
+        let deleted = index.deleted_count();
+        let total = index.len() + deleted;
+        if total == 0 {
+            return 0.0;
+        }
+        deleted as f32 / total as f32
+    }
+
+    /// Replaces the index of this writer with one built from the live records in the
+    /// record segment, dropping every tombstone. The record segment must be at the same
+    /// version as the index being replaced.
+    pub(crate) async fn rebuild_from_record_segment(
+        self,
+        segment: &Segment,
+        dimensionality: usize,
+        record_segment_reader: &RecordSegmentReader<'_>,
+    ) -> Result<Box<DistributedHNSWSegmentWriter>, Box<DistributedHNSWSegmentFromSegmentError>>
+    {
+        let records = match record_segment_reader.get_all_data_with_offset_ids().await {
+            Ok(records) => records,
+            Err(e) => {
+                return Err(Box::new(
+                    DistributedHNSWSegmentFromSegmentError::RecordSegmentReadError(e),
+                ))
+            }
+        };
+        let index = match self
+            .hnsw_index_provider
+            .create(segment, dimensionality as i32)
+        {
+            Ok(index) => index,
+            Err(e) => {
+                return Err(Box::new(
+                    DistributedHNSWSegmentFromSegmentError::HnswIndexProviderCreateError(*e),
+                ))
+            }
+        };
+        {
+            let mut index = index.write();
+            let num_elements: usize = records
+                .iter()
+                .map(|(_, record)| index.vectors(&record.embedding).len())
+                .sum();
+            if num_elements > index.capacity() {
+                index.resize(num_elements);
+            }
+            for (offset_id, record) in records.iter() {
+                index.add_record(*offset_id as usize, &record.embedding);
+            }
+        }
+        // The replaced index is never flushed, so there is no reason to keep it around
+        let replaced_index_id = self.index.read().id;
+        let hnsw_index_provider = self.hnsw_index_provider;
+        drop(self.index);
+        hnsw_index_provider.remove(&replaced_index_id);
+        Ok(Box::new(DistributedHNSWSegmentWriter::new(
+            index,
+            hnsw_index_provider,
+            segment.id,
+        )))
+    }
 }
 
 impl<'a> SegmentWriter<'a> for DistributedHNSWSegmentWriter {
@@ -187,7 +257,7 @@ impl<'a> SegmentWriter<'a> for DistributedHNSWSegmentWriter {
                     let embedding = match record.final_embedding {
                         Some(e) => e,
                         None => match record.data_record.as_ref() {
-                            Some(record) => record.embedding,
+                            Some(record) => &record.embedding,
                             None => {
                                 tracing::error!("Embedding not set for record {:?}", record);
                                 return Err(ApplyMaterializedLogError::EmbeddingNotSet);
@@ -196,16 +266,24 @@ impl<'a> SegmentWriter<'a> for DistributedHNSWSegmentWriter {
                     };
 
                     let mut index = self.index.upgradable_read();
+                    if !index.accepts(embedding) {
+                        return Err(ApplyMaterializedLogError::InvalidMultiVector(
+                            embedding.len(),
+                            index.dimensionality(),
+                        ));
+                    }
                     let index_len = index.len();
                     let index_capacity = index.capacity();
-                    if index_len + 1 > index_capacity {
+                    let num_vectors = index.vectors(embedding).len();
+                    if index_len + num_vectors > index_capacity {
                         index.with_upgraded(|index| {
                             // Bump allocation by 2x
-                            index.resize(index_capacity * 2);
+                            index
+                                .resize(std::cmp::max(index_capacity * 2, index_len + num_vectors));
                         });
                     }
 
-                    index.add(record.offset_id as usize, embedding);
+                    index.add_record(record.offset_id as usize, embedding);
                 }
                 // This shouldn't be reached since materialization always derefs
                 // upserts into either updates or inserts.
@@ -221,7 +299,7 @@ impl<'a> SegmentWriter<'a> for DistributedHNSWSegmentWriter {
                     let embedding = match record.final_embedding {
                         Some(e) => e,
                         None => match record.data_record.as_ref() {
-                            Some(record) => record.embedding,
+                            Some(record) => &record.embedding,
                             None => {
                                 panic!("Invariant violation. Embedding not found on storage");
                             }
@@ -232,14 +310,54 @@ impl<'a> SegmentWriter<'a> for DistributedHNSWSegmentWriter {
                     // perform any validation on its own and assumes that the
                     // offset ids are correct (i.e. pertaining to records that
                     // are actually meant to be updated).
-                    self.index.read().add(record.offset_id as usize, embedding);
+                    let mut index = self.index.upgradable_read();
+                    if !index.accepts(embedding) {
+                        return Err(ApplyMaterializedLogError::InvalidMultiVector(
+                            embedding.len(),
+                            index.dimensionality(),
+                        ));
+                    }
+                    if index.is_multi_vector() {
+                        // The record may now have fewer vectors than before, so
+                        // the ones past the end of the new embedding are deleted.
+                        let num_vectors = index.vectors(embedding).len();
+                        if let Some(data_record) = record.data_record.as_ref() {
+                            index.delete_record(
+                                record.offset_id as usize,
+                                &data_record.embedding,
+                                num_vectors,
+                            );
+                        }
+                        let index_len = index.len();
+                        let index_capacity = index.capacity();
+                        if index_len + num_vectors > index_capacity {
+                            index.with_upgraded(|index| {
+                                index.resize(std::cmp::max(
+                                    index_capacity * 2,
+                                    index_len + num_vectors,
+                                ));
+                            });
+                        }
+                    }
+                    index.add_record(record.offset_id as usize, embedding);
                 }
                 Operation::Delete => {
                     // HNSW segment does not perform validation of any sort. So,
                     // the assumption here is that the materialized log records
                     // contain the correct offset ids pertaining to records that
                     // are actually meant to be deleted.
-                    self.index.read().delete(record.offset_id as usize);
+                    // Deletes are only materialized for records in the record
+                    // segment, whose stored embedding gives every label the
+                    // record takes up.
+                    let embedding = match record.data_record.as_ref() {
+                        Some(data_record) => &data_record.embedding,
+                        None => {
+                            panic!("Invariant violation. Embedding not found on storage");
+                        }
+                    };
+                    self.index
+                        .read()
+                        .delete_record(record.offset_id as usize, embedding, 0);
                 }
             }
         }
@@ -260,12 +378,13 @@ impl<'a> SegmentWriter<'a> for DistributedHNSWSegmentWriter {
 impl SegmentFlusher for DistributedHNSWSegmentWriter {
     async fn flush(self) -> Result<HashMap<String, Vec<String>>, Box<dyn ChromaError>> {
         let hnsw_index_id = self.index.read().id;
-        match self.hnsw_index_provider.flush(&hnsw_index_id).await {
-            Ok(_) => {}
+        let manifest_key = match self.hnsw_index_provider.flush(&hnsw_index_id).await {
+            Ok(manifest_key) => manifest_key,
             Err(e) => return Err(e),
-        }
+        };
         let mut flushed_files = HashMap::new();
         flushed_files.insert(HNSW_INDEX.to_string(), vec![hnsw_index_id.to_string()]);
+        flushed_files.insert(HNSW_MANIFEST.to_string(), vec![manifest_key]);
         Ok(flushed_files)
     }
 }
@@ -369,14 +488,183 @@ impl DistributedHNSWSegmentReader {
         }
     }
 
+    /// Queries the index exploring `ef` candidates, or the `hnsw:search_ef`
+    /// of the segment if None.
     pub(crate) fn query(
         &self,
         vector: &[f32],
         k: usize,
+        ef: Option<usize>,
         allowed_ids: &[usize],
         disallowd_ids: &[usize],
     ) -> (Vec<usize>, Vec<f32>) {
         let index = self.index.read();
-        index.query(vector, k, allowed_ids, disallowd_ids)
+        index.query_with_ef(vector, k, ef, allowed_ids, disallowd_ids)
+    }
+
+    pub(crate) fn is_multi_vector(&self) -> bool {
+        self.index.read().is_multi_vector()
+    }
+
+    pub(crate) fn dimensionality(&self) -> usize {
+        self.index.read().dimensionality()
+    }
+
+    /// The labels of the elements that a record with the given embedding takes up.
+    pub(crate) fn labels(&self, offset_id: usize, embedding: &[f32]) -> Vec<usize> {
+        let index = self.index.read();
+        index.labels(offset_id, index.vectors(embedding).len())
+    }
+
+    /// Finds the candidate records for a multi-vector query, the records that have
+    /// a vector among the `k` nearest neighbors of any vector of the query. The
+    /// allowed and disallowed ids are element labels, see `labels`.
+    pub(crate) fn query_multi_vector(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef: Option<usize>,
+        allowed_labels: &[usize],
+        disallowed_labels: &[usize],
+    ) -> Vec<usize> {
+        let index = self.index.read();
+        let mut seen = HashSet::new();
+        let mut offset_ids = Vec::new();
+        for vector in index.vectors(query) {
+            let (labels, _) = index.query_with_ef(vector, k, ef, allowed_labels, disallowed_labels);
+            for label in labels {
+                let offset_id = multi_vector_offset_id(label);
+                if seen.insert(offset_id) {
+                    offset_ids.push(offset_id);
+                }
+            }
+        }
+        offset_ids
+    }
+
+    /// The number of live elements in the index.
+    pub(crate) fn len(&self) -> usize {
+        self.index.read().len()
+    }
+
+    /// The number of deleted elements that are still present in the index.
+    pub(crate) fn deleted_count(&self) -> usize {
+        self.index.read().deleted_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockstore::arrow::provider::ArrowBlockfileProvider;
+    use crate::blockstore::provider::BlockfileProvider;
+    use crate::execution::data::data_chunk::Chunk;
+    use crate::segment::record_segment::RecordSegmentWriter;
+    use crate::segment::LogMaterializer;
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::types::{OperationRecord, SegmentScope, SegmentType};
+
+    fn log_record(log_offset: i64, id: &str, embedding: Option<Vec<f32>>) -> LogRecord {
+        let operation = match embedding {
+            Some(_) => Operation::Add,
+            None => Operation::Delete,
+        };
+        LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding,
+                encoding: None,
+                sparse_vector: None,
+                metadata: None,
+                document: None,
+                operation,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_from_record_segment_drops_tombstones() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage.clone()));
+        let hnsw_index_provider =
+            HnswIndexProvider::new(storage, tmp_dir.path().join("hnsw"), 1024 * 1024 * 1024);
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let hnsw_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::HnswDistributed,
+            scope: SegmentScope::VECTOR,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let hnsw_segment_writer =
+            DistributedHNSWSegmentWriter::from_segment(&hnsw_segment, 3, hnsw_index_provider)
+                .await
+                .expect("Error creating hnsw segment writer");
+
+        let batches = vec![
+            vec![
+                log_record(1, "embedding_id_1", Some(vec![1.0, 2.0, 3.0])),
+                log_record(2, "embedding_id_2", Some(vec![4.0, 5.0, 6.0])),
+                log_record(3, "embedding_id_3", Some(vec![7.0, 8.0, 9.0])),
+            ],
+            vec![log_record(4, "embedding_id_2", None)],
+        ];
+        for batch in batches {
+            let record_segment_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .expect("Error creating record segment writer");
+            let record_segment_reader =
+                RecordSegmentReader::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .ok();
+            let materializer =
+                LogMaterializer::new(record_segment_reader, Chunk::new(batch.into()), None);
+            let records = materializer
+                .materialize()
+                .await
+                .expect("Log materialization failed");
+            record_segment_writer
+                .apply_materialized_log_chunk(records.clone())
+                .await
+                .expect("Apply materialized log to record segment failed");
+            hnsw_segment_writer
+                .apply_materialized_log_chunk(records)
+                .await
+                .expect("Apply materialized log to hnsw segment failed");
+            let flusher = record_segment_writer
+                .commit()
+                .expect("Commit for record segment writer failed");
+            record_segment.file_path = flusher.flush().await.expect("Flush segment writer failed");
+        }
+        assert!(hnsw_segment_writer.tombstone_ratio() > 0.3);
+
+        let record_segment_reader =
+            RecordSegmentReader::from_segment(&record_segment, &blockfile_provider)
+                .await
+                .expect("Error creating record segment reader");
+        let rebuilt = hnsw_segment_writer
+            .rebuild_from_record_segment(&hnsw_segment, 3, &record_segment_reader)
+            .await
+            .expect("Error rebuilding hnsw segment");
+        assert_eq!(rebuilt.tombstone_ratio(), 0.0);
+        let offset_id = record_segment_reader
+            .get_offset_id_for_user_id("embedding_id_3")
+            .await
+            .unwrap();
+        let index = rebuilt.index.read();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(offset_id as usize), Some(vec![7.0, 8.0, 9.0]));
     }
 }
@@ -0,0 +1,914 @@
+use super::types::{MaterializedLogRecord, SegmentWriter};
+use super::SegmentFlusher;
+use crate::blockstore::provider::{BlockfileProvider, CreateError, OpenError};
+use crate::blockstore::{BlockfileFlusher, BlockfileReader, BlockfileWriter};
+use crate::errors::{ChromaError, ErrorCodes};
+use crate::execution::data::data_chunk::Chunk;
+use crate::index::ivf::{IvfIndex, IvfIndexConfig};
+use crate::index::{Index, IndexConfig, PersistentIndex};
+use crate::segment::record_segment::ApplyMaterializedLogError;
+use crate::types::{Operation, Segment, SegmentType};
+use arrow::array::Int32Array;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use roaring::RoaringBitmap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+// The centroids of the index, keyed by their list id, and the statistics of
+// the segment, keyed by `IVF_STATS_PREFIX`.
+const IVF_CENTROIDS: &str = "ivf_centroids";
+// The inverted lists, keyed by the list id as the prefix and the offset id as
+// the key.
+const IVF_LISTS: &str = "ivf_lists";
+// The prefix of the statistics in the centroids blockfile, each is a single
+// value. The centroids have no prefix.
+const IVF_STATS_PREFIX: &str = "stats";
+// The stats key of the number of vectors in the segment
+const NUM_VECTORS_KEY: u32 = 0;
+// The stats key of the number of vectors the centroids were trained on
+const TRAINING_SIZE_KEY: u32 = 1;
+// The centroids are retrained once the collection is this many times larger
+// than the sample they were trained on.
+const RETRAIN_GROWTH: usize = 2;
+
+#[derive(Error, Debug)]
+pub enum IvfSegmentError {
+    #[error("Invalid segment type")]
+    InvalidSegmentType,
+    #[error("Segment uninitialized")]
+    UninitializedSegment,
+    #[error("Incorrect number of files")]
+    IncorrectNumberOfFiles,
+    #[error("Missing file: {0}")]
+    MissingFile(String),
+    #[error("Invalid Uuid for file: {0}")]
+    InvalidUuid(String),
+    #[error("Invalid index config")]
+    IndexConfigError(Box<dyn ChromaError>),
+    #[error("Blockfile Creation Error")]
+    BlockfileCreateError(#[from] Box<CreateError>),
+    #[error("Blockfile Open Error")]
+    BlockfileOpenError(#[from] Box<OpenError>),
+    #[error("Error reading the centroids")]
+    CentroidsReadError(Box<dyn ChromaError>),
+    #[error("Error writing the centroids")]
+    CentroidsWriteError,
+    #[error("Error writing the segment stats")]
+    StatsWriteError,
+    #[error("Error initializing or loading the index")]
+    IndexError(Box<dyn ChromaError>),
+}
+
+impl ChromaError for IvfSegmentError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            IvfSegmentError::InvalidSegmentType => ErrorCodes::InvalidArgument,
+            IvfSegmentError::UninitializedSegment => ErrorCodes::InvalidArgument,
+            IvfSegmentError::IncorrectNumberOfFiles => ErrorCodes::Internal,
+            IvfSegmentError::MissingFile(_) => ErrorCodes::Internal,
+            IvfSegmentError::InvalidUuid(_) => ErrorCodes::Internal,
+            IvfSegmentError::IndexConfigError(e) => e.code(),
+            IvfSegmentError::BlockfileCreateError(e) => e.code(),
+            IvfSegmentError::BlockfileOpenError(e) => e.code(),
+            IvfSegmentError::CentroidsReadError(e) => e.code(),
+            IvfSegmentError::CentroidsWriteError => ErrorCodes::Internal,
+            IvfSegmentError::StatsWriteError => ErrorCodes::Internal,
+            IvfSegmentError::IndexError(e) => e.code(),
+        }
+    }
+}
+
+/// Encodes a vector as a blockstore value. The blockstore has no f32 values
+/// so vectors are stored as their bits.
+fn encode_vector(vector: &[f32]) -> Int32Array {
+    Int32Array::from(
+        vector
+            .iter()
+            .map(|value| value.to_bits() as i32)
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn decode_vector(values: &Int32Array) -> Vec<f32> {
+    values
+        .values()
+        .iter()
+        .map(|value| f32::from_bits(*value as u32))
+        .collect()
+}
+
+fn get_file_id(segment: &Segment, file: &str) -> Result<Uuid, IvfSegmentError> {
+    let file_id = match segment.file_path.get(file) {
+        Some(file_ids) => match file_ids.first() {
+            Some(file_id) => file_id,
+            None => return Err(IvfSegmentError::MissingFile(file.to_string())),
+        },
+        None => return Err(IvfSegmentError::MissingFile(file.to_string())),
+    };
+    match Uuid::parse_str(file_id) {
+        Ok(uuid) => Ok(uuid),
+        Err(_) => Err(IvfSegmentError::InvalidUuid(file.to_string())),
+    }
+}
+
+// The index of the segment, with its centroids, and the number of vectors in
+// the segment. The vectors are in the lists blockfile.
+async fn load_index(
+    segment: &Segment,
+    dimensionality: usize,
+    blockfile_provider: &BlockfileProvider,
+) -> Result<(IvfIndex, usize), IvfSegmentError> {
+    let index_config = match IndexConfig::from_segment(segment, dimensionality as i32) {
+        Ok(index_config) => index_config,
+        Err(e) => return Err(IvfSegmentError::IndexConfigError(e)),
+    };
+    let mut ivf_config = match IvfIndexConfig::from_segment(segment) {
+        Ok(ivf_config) => ivf_config,
+        Err(e) => return Err(IvfSegmentError::IndexConfigError(e)),
+    };
+    if segment.file_path.is_empty() {
+        return match IvfIndex::init(&index_config, Some(&ivf_config), segment.id) {
+            Ok(index) => Ok((index, 0)),
+            Err(e) => Err(IvfSegmentError::IndexError(e)),
+        };
+    }
+    let centroids_id = get_file_id(segment, IVF_CENTROIDS)?;
+    let centroids_reader = match blockfile_provider
+        .open::<u32, Int32Array>(&centroids_id)
+        .await
+    {
+        Ok(reader) => reader,
+        Err(e) => return Err(IvfSegmentError::BlockfileOpenError(e)),
+    };
+    // Centroids are sorted by their list id
+    ivf_config.centroids = match centroids_reader.get_by_prefix("").await {
+        Ok(centroids) => centroids
+            .iter()
+            .map(|(_, _, centroid)| decode_vector(centroid))
+            .collect(),
+        Err(e) => return Err(IvfSegmentError::CentroidsReadError(e)),
+    };
+    let stats = match centroids_reader.get_by_prefix(IVF_STATS_PREFIX).await {
+        Ok(stats) => stats,
+        Err(e) => return Err(IvfSegmentError::CentroidsReadError(e)),
+    };
+    let get_stat = |key: u32| {
+        stats
+            .iter()
+            .find(|(_, stat_key, _)| *stat_key == key)
+            .and_then(|(_, _, value)| value.values().first())
+            .map_or(0, |value| *value as u32 as usize)
+    };
+    ivf_config.training_size = get_stat(TRAINING_SIZE_KEY);
+    let num_vectors = get_stat(NUM_VECTORS_KEY);
+    match IvfIndex::load(
+        &centroids_id.to_string(),
+        &index_config,
+        &ivf_config,
+        segment.id,
+    ) {
+        Ok(index) => Ok((index, num_vectors)),
+        Err(e) => Err(IvfSegmentError::IndexError(e)),
+    }
+}
+
+/// Writes an ivf segment. The centroids are trained with k-means on a sample
+/// of the collection at its first compaction, and retrained on a new sample
+/// whenever the collection outgrows the sample they were trained on.
+/// The index holds the vectors this compaction writes, the other vectors are
+/// only in the lists blockfile.
+#[derive(Clone)]
+pub(crate) struct IvfSegmentWriter {
+    // These are Option<> so that we can take() them when we commit
+    centroids: Option<BlockfileWriter>,
+    lists: Option<BlockfileWriter>,
+    index: Arc<IvfIndex>,
+    // The lists of the segment before this compaction and their number, read
+    // to retrain
+    previous_lists: Option<(Uuid, usize)>,
+    // Held while a chunk is applied, so no chunk writes to lists that another
+    // chunk is retraining
+    state: Arc<tokio::sync::Mutex<IvfWriterState>>,
+    blockfile_provider: BlockfileProvider,
+    pub(crate) id: Uuid,
+}
+
+#[derive(Default)]
+struct IvfWriterState {
+    num_vectors: usize,
+    // The vectors this compaction deleted from the lists
+    deleted: HashSet<u32>,
+}
+
+impl Debug for IvfSegmentWriter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "IvfSegmentWriter")
+    }
+}
+
+impl IvfSegmentWriter {
+    pub(crate) async fn from_segment(
+        segment: &Segment,
+        dimensionality: usize,
+        blockfile_provider: &BlockfileProvider,
+    ) -> Result<IvfSegmentWriter, IvfSegmentError> {
+        if segment.r#type != SegmentType::BlockfileIvf {
+            return Err(IvfSegmentError::InvalidSegmentType);
+        }
+        // All of the centroids are written to a new blockfile every compaction
+        let centroids = match blockfile_provider.create::<u32, &Int32Array>() {
+            Ok(centroids) => centroids,
+            Err(e) => return Err(IvfSegmentError::BlockfileCreateError(e)),
+        };
+        let (lists, previous_lists) = match segment.file_path.len() {
+            0 => match blockfile_provider.create::<u32, &Int32Array>() {
+                Ok(lists) => (lists, None),
+                Err(e) => return Err(IvfSegmentError::BlockfileCreateError(e)),
+            },
+            2 => {
+                let lists_id = get_file_id(segment, IVF_LISTS)?;
+                match blockfile_provider.fork::<u32, &Int32Array>(&lists_id).await {
+                    Ok(lists) => (lists, Some(lists_id)),
+                    Err(e) => return Err(IvfSegmentError::BlockfileCreateError(e)),
+                }
+            }
+            _ => return Err(IvfSegmentError::IncorrectNumberOfFiles),
+        };
+        let (index, num_vectors) = load_index(segment, dimensionality, blockfile_provider).await?;
+        let previous_num_lists = std::cmp::max(index.centroids().len(), 1);
+        let writer = IvfSegmentWriter {
+            centroids: Some(centroids),
+            lists: Some(lists),
+            index: Arc::new(index),
+            previous_lists: previous_lists.map(|lists_id| (lists_id, previous_num_lists)),
+            state: Arc::new(tokio::sync::Mutex::new(IvfWriterState {
+                num_vectors,
+                ..Default::default()
+            })),
+            blockfile_provider: blockfile_provider.clone(),
+            id: segment.id,
+        };
+        // Kept for compactions that apply no records
+        if writer.write_centroids().await.is_err() {
+            return Err(IvfSegmentError::CentroidsWriteError);
+        }
+        if writer
+            .write_stat(NUM_VECTORS_KEY, num_vectors)
+            .await
+            .is_err()
+        {
+            return Err(IvfSegmentError::StatsWriteError);
+        }
+        Ok(writer)
+    }
+
+    // Writes the centroids of the index and the number of vectors they were
+    // trained on. Retraining never leaves fewer centroids, so every centroid
+    // written before is overwritten.
+    async fn write_centroids(&self) -> Result<(), ApplyMaterializedLogError> {
+        for (list, centroid) in self.index.centroids().iter().enumerate() {
+            if self
+                .centroids
+                .as_ref()
+                .unwrap()
+                .set::<u32, &Int32Array>("", list as u32, &encode_vector(centroid))
+                .await
+                .is_err()
+            {
+                return Err(ApplyMaterializedLogError::BlockfileSetError);
+            }
+        }
+        self.write_stat(TRAINING_SIZE_KEY, self.index.training_size())
+            .await
+    }
+
+    async fn write_stat(&self, key: u32, value: usize) -> Result<(), ApplyMaterializedLogError> {
+        match self
+            .centroids
+            .as_ref()
+            .unwrap()
+            .set::<u32, &Int32Array>(
+                IVF_STATS_PREFIX,
+                key,
+                &Int32Array::from(vec![value as u32 as i32]),
+            )
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(_) => Err(ApplyMaterializedLogError::BlockfileSetError),
+        }
+    }
+
+    // Trains the centroids on a sample of the whole collection, the vectors of
+    // the segment that this compaction keeps and the embeddings it writes. The
+    // vectors of the segment are moved to the lists of their new closest
+    // centroids, so deletes and updates find them.
+    async fn train(
+        &self,
+        state: &mut IvfWriterState,
+        records: &Chunk<MaterializedLogRecord<'_>>,
+    ) -> Result<(), ApplyMaterializedLogError> {
+        // The vectors of the segment that the index does not hold
+        let mut previous_vectors: Vec<(usize, u32, Vec<f32>)> = Vec::new();
+        if let Some((previous_lists, previous_num_lists)) = &self.previous_lists {
+            let reader = match self
+                .blockfile_provider
+                .open::<u32, Int32Array>(previous_lists)
+                .await
+            {
+                Ok(reader) => reader,
+                Err(_) => return Err(ApplyMaterializedLogError::BlockfileReadError),
+            };
+            for list in 0..*previous_num_lists {
+                let list_vectors = match reader.get_by_prefix(&list.to_string()).await {
+                    Ok(list_vectors) => list_vectors,
+                    Err(_) => return Err(ApplyMaterializedLogError::BlockfileReadError),
+                };
+                previous_vectors.extend(
+                    list_vectors
+                        .iter()
+                        .filter(|(_, offset_id, _)| {
+                            !state.deleted.contains(offset_id)
+                                && self.index.get(*offset_id as usize).is_none()
+                        })
+                        .map(|(_, offset_id, vector)| (list, *offset_id, decode_vector(vector))),
+                );
+            }
+        }
+        let held_vectors = self.index.vectors();
+        let replaced: HashSet<u32> = records
+            .iter()
+            .filter(|(record, _)| match record.final_operation {
+                Operation::Delete => true,
+                Operation::Update => record.final_embedding.is_some(),
+                _ => false,
+            })
+            .map(|(record, _)| record.offset_id)
+            .collect();
+        let mut samples: Vec<&[f32]> = previous_vectors
+            .iter()
+            .map(|(_, offset_id, vector)| (*offset_id, vector))
+            .chain(
+                held_vectors
+                    .iter()
+                    .map(|(_, id, vector)| (*id as u32, vector)),
+            )
+            .filter(|(offset_id, _)| !replaced.contains(offset_id))
+            .map(|(_, vector)| vector.as_slice())
+            .collect();
+        samples.extend(
+            records
+                .iter()
+                .filter_map(|(record, _)| match record.final_operation {
+                    Operation::Add | Operation::Update => record.final_embedding,
+                    _ => None,
+                }),
+        );
+        // Evenly spaced samples, so they span the whole collection
+        let max_training_size = self.index.max_training_size();
+        if samples.len() > max_training_size {
+            let step = samples.len() / max_training_size;
+            samples = samples
+                .into_iter()
+                .step_by(step)
+                .take(max_training_size)
+                .collect();
+        }
+        // Training moves the vectors the index holds
+        self.index.train(&samples);
+        self.write_centroids().await?;
+        for (list, id, vector) in held_vectors.iter() {
+            match self.index.list(*id) {
+                Some(new_list) if new_list != *list => {
+                    self.delete_from_list(state, *list, *id as u32).await?;
+                    self.set_in_list(new_list, *id as u32, vector).await?;
+                }
+                _ => (),
+            }
+        }
+        // The index holds the vectors of the segment that moved
+        for (list, offset_id, vector) in previous_vectors.iter() {
+            self.index.add(*offset_id as usize, vector);
+            match self.index.list(*offset_id as usize) {
+                Some(new_list) if new_list != *list => {
+                    self.delete_from_list(state, *list, *offset_id).await?;
+                    self.set_in_list(new_list, *offset_id, vector).await?;
+                }
+                _ => self.index.delete(*offset_id as usize),
+            }
+        }
+        Ok(())
+    }
+
+    // Sets a vector, as it is stored in the index, in a list.
+    async fn set_in_list(
+        &self,
+        list: usize,
+        offset_id: u32,
+        vector: &[f32],
+    ) -> Result<(), ApplyMaterializedLogError> {
+        match self
+            .lists
+            .as_ref()
+            .unwrap()
+            .set::<u32, &Int32Array>(&list.to_string(), offset_id, &encode_vector(vector))
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(_) => Err(ApplyMaterializedLogError::BlockfileSetError),
+        }
+    }
+
+    async fn delete_from_list(
+        &self,
+        state: &mut IvfWriterState,
+        list: usize,
+        offset_id: u32,
+    ) -> Result<(), ApplyMaterializedLogError> {
+        match self
+            .lists
+            .as_ref()
+            .unwrap()
+            .delete::<u32, &Int32Array>(&list.to_string(), offset_id)
+            .await
+        {
+            Ok(()) => {
+                state.deleted.insert(offset_id);
+                Ok(())
+            }
+            Err(_) => Err(ApplyMaterializedLogError::BlockfileDeleteError),
+        }
+    }
+
+    async fn set_vector(
+        &self,
+        offset_id: u32,
+        embedding: &[f32],
+    ) -> Result<(), ApplyMaterializedLogError> {
+        self.index.add(offset_id as usize, embedding);
+        match (
+            self.index.list(offset_id as usize),
+            self.index.get(offset_id as usize),
+        ) {
+            (Some(list), Some(vector)) => self.set_in_list(list, offset_id, &vector).await,
+            _ => Ok(()),
+        }
+    }
+
+    // The vectors the index does not hold are in the list of their closest
+    // centroid, training moves them when the centroids change.
+    async fn delete_vector(
+        &self,
+        state: &mut IvfWriterState,
+        offset_id: u32,
+        old_embedding: &[f32],
+    ) -> Result<(), ApplyMaterializedLogError> {
+        let list = match self.index.list(offset_id as usize) {
+            Some(list) => list,
+            None => self.index.nearest_centroids(old_embedding, 1)[0],
+        };
+        self.index.delete(offset_id as usize);
+        self.delete_from_list(state, list, offset_id).await
+    }
+}
+
+impl<'a> SegmentWriter<'a> for IvfSegmentWriter {
+    async fn apply_materialized_log_chunk(
+        &self,
+        records: Chunk<MaterializedLogRecord<'a>>,
+    ) -> Result<(), ApplyMaterializedLogError> {
+        let mut state = self.state.lock().await;
+        for (record, _) in records.iter() {
+            match record.final_operation {
+                Operation::Add => state.num_vectors += 1,
+                Operation::Delete if record.data_record.is_some() => {
+                    state.num_vectors = state.num_vectors.saturating_sub(1)
+                }
+                _ => (),
+            }
+        }
+        let training_size = self.index.training_size();
+        let outgrown = state.num_vectors >= RETRAIN_GROWTH * training_size
+            && training_size < self.index.max_training_size();
+        if state.num_vectors > 0 && (!self.index.is_trained() || outgrown) {
+            self.train(&mut state, &records).await?;
+        }
+        self.write_stat(NUM_VECTORS_KEY, state.num_vectors).await?;
+        for (record, _) in records.iter() {
+            match record.final_operation {
+                Operation::Add => {
+                    let embedding = match record.final_embedding {
+                        Some(embedding) => embedding,
+                        None => {
+                            tracing::error!("Embedding not set for record {:?}", record);
+                            return Err(ApplyMaterializedLogError::EmbeddingNotSet);
+                        }
+                    };
+                    self.set_vector(record.offset_id, embedding).await?;
+                }
+                Operation::Update => {
+                    // Updates that do not set an embedding keep the old one
+                    if let Some(embedding) = record.final_embedding {
+                        if let Some(data_record) = record.data_record.as_ref() {
+                            self.delete_vector(
+                                &mut state,
+                                record.offset_id,
+                                &data_record.embedding,
+                            )
+                            .await?;
+                        }
+                        self.set_vector(record.offset_id, embedding).await?;
+                    }
+                }
+                Operation::Delete => {
+                    if let Some(data_record) = record.data_record.as_ref() {
+                        self.delete_vector(&mut state, record.offset_id, &data_record.embedding)
+                            .await?;
+                    }
+                }
+                Operation::Upsert => {
+                    // MaterializedLogRecord already converts upserts into either updates or inserts
+                    // so here we expect to not have any records of this type.
+                    panic!("Invariant violation. After log materialization there shouldn't be any upserts.");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn commit(mut self) -> Result<impl SegmentFlusher, Box<dyn ChromaError>> {
+        self.index.save()?;
+        let centroids_flusher = self
+            .centroids
+            .take()
+            .unwrap()
+            .commit::<u32, &Int32Array>()?;
+        let lists_flusher = self.lists.take().unwrap().commit::<u32, &Int32Array>()?;
+        Ok(IvfSegmentFlusher {
+            centroids_flusher,
+            lists_flusher,
+        })
+    }
+}
+
+pub(crate) struct IvfSegmentFlusher {
+    centroids_flusher: BlockfileFlusher,
+    lists_flusher: BlockfileFlusher,
+}
+
+impl Debug for IvfSegmentFlusher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "IvfSegmentFlusher")
+    }
+}
+
+#[async_trait]
+impl SegmentFlusher for IvfSegmentFlusher {
+    async fn flush(self) -> Result<HashMap<String, Vec<String>>, Box<dyn ChromaError>> {
+        let centroids_id = self.centroids_flusher.id();
+        let lists_id = self.lists_flusher.id();
+        self.centroids_flusher.flush::<u32, &Int32Array>().await?;
+        self.lists_flusher.flush::<u32, &Int32Array>().await?;
+        let mut flushed_files = HashMap::new();
+        flushed_files.insert(IVF_CENTROIDS.to_string(), vec![centroids_id.to_string()]);
+        flushed_files.insert(IVF_LISTS.to_string(), vec![lists_id.to_string()]);
+        Ok(flushed_files)
+    }
+}
+
+/// Reads an ivf segment. The index is loaded with the centroids, the lists a
+/// query probes are read into it from the lists blockfile through the block
+/// cache.
+pub(crate) struct IvfSegmentReader<'me> {
+    index: IvfIndex,
+    lists: BlockfileReader<'me, u32, Int32Array>,
+    // The lists read into the index
+    loaded_lists: Mutex<HashSet<usize>>,
+}
+
+impl<'me> IvfSegmentReader<'me> {
+    pub(crate) async fn from_segment(
+        segment: &Segment,
+        dimensionality: usize,
+        blockfile_provider: &BlockfileProvider,
+    ) -> Result<IvfSegmentReader<'me>, Box<IvfSegmentError>> {
+        if segment.r#type != SegmentType::BlockfileIvf {
+            return Err(Box::new(IvfSegmentError::InvalidSegmentType));
+        }
+        match segment.file_path.len() {
+            0 => return Err(Box::new(IvfSegmentError::UninitializedSegment)),
+            2 => (),
+            _ => return Err(Box::new(IvfSegmentError::IncorrectNumberOfFiles)),
+        }
+        let lists_id = match get_file_id(segment, IVF_LISTS) {
+            Ok(lists_id) => lists_id,
+            Err(e) => return Err(Box::new(e)),
+        };
+        let lists = match blockfile_provider.open::<u32, Int32Array>(&lists_id).await {
+            Ok(lists) => lists,
+            Err(e) => return Err(Box::new(IvfSegmentError::BlockfileOpenError(e))),
+        };
+        let index = match load_index(segment, dimensionality, blockfile_provider).await {
+            Ok((index, _)) => index,
+            Err(e) => return Err(Box::new(e)),
+        };
+        Ok(IvfSegmentReader {
+            index,
+            lists,
+            loaded_lists: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// The number of lists queries probe unless they set their own.
+    pub(crate) fn nprobe(&self) -> usize {
+        self.index.nprobe()
+    }
+
+    /// Returns the offset ids and distances of the k closest vectors in the
+    /// `nprobe` lists closest to the query, sorted by distance. Every offset id is
+    /// allowed when `allowed_offset_ids` is empty.
+    pub(crate) async fn query(
+        &'me self,
+        query: &[f32],
+        k: usize,
+        nprobe: usize,
+        allowed_offset_ids: &RoaringBitmap,
+        disallowed_offset_ids: &RoaringBitmap,
+    ) -> Result<(Vec<usize>, Vec<f32>), Box<dyn ChromaError>> {
+        for list in self.index.nearest_centroids(query, nprobe) {
+            if self.loaded_lists.lock().contains(&list) {
+                continue;
+            }
+            let vectors = self.lists.get_by_prefix(&list.to_string()).await?;
+            for (_, offset_id, vector) in vectors.iter() {
+                self.index
+                    .insert(list, *offset_id as usize, decode_vector(vector));
+            }
+            self.loaded_lists.lock().insert(list);
+        }
+        Ok(self.index.query_with_nprobe(
+            query,
+            k,
+            nprobe,
+            allowed_offset_ids,
+            disallowed_offset_ids,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::record_segment::{RecordSegmentReader, RecordSegmentWriter};
+    use crate::segment::LogMaterializer;
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::types::{LogRecord, MetadataValue, OperationRecord, SegmentScope};
+
+    fn log_record(
+        log_offset: i64,
+        id: &str,
+        operation: Operation,
+        embedding: Option<Vec<f32>>,
+    ) -> LogRecord {
+        LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding,
+                encoding: None,
+                sparse_vector: None,
+                metadata: None,
+                document: None,
+                operation,
+            },
+        }
+    }
+
+    // Compacts the logs into the record and ivf segments and returns them with
+    // their new files.
+    async fn compact(
+        blockfile_provider: &BlockfileProvider,
+        record_segment: &mut Segment,
+        ivf_segment: &mut Segment,
+        logs: Vec<LogRecord>,
+    ) {
+        let record_writer = RecordSegmentWriter::from_segment(record_segment, blockfile_provider)
+            .await
+            .unwrap();
+        let ivf_writer = IvfSegmentWriter::from_segment(ivf_segment, 2, blockfile_provider)
+            .await
+            .unwrap();
+        let record_reader = RecordSegmentReader::from_segment(record_segment, blockfile_provider)
+            .await
+            .ok();
+        let logs = Chunk::new(logs.into());
+        let materializer = LogMaterializer::new(record_reader, logs, None);
+        let records = materializer.materialize().await.unwrap();
+        record_writer
+            .apply_materialized_log_chunk(records.clone())
+            .await
+            .unwrap();
+        ivf_writer
+            .apply_materialized_log_chunk(records)
+            .await
+            .unwrap();
+        record_segment.file_path = record_writer.commit().unwrap().flush().await.unwrap();
+        ivf_segment.file_path = ivf_writer.commit().unwrap().flush().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ivf_segment_lists() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider = BlockfileProvider::new_arrow(storage);
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut ivf_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileIvf,
+            scope: SegmentScope::VECTOR,
+            collection: record_segment.collection,
+            metadata: Some(HashMap::from([(
+                "ivf:nlist".to_string(),
+                MetadataValue::Int(2),
+            )])),
+            file_path: HashMap::new(),
+        };
+        let reader = IvfSegmentReader::from_segment(&ivf_segment, 2, &blockfile_provider).await;
+        assert!(matches!(
+            reader.err().map(|e| *e),
+            Some(IvfSegmentError::UninitializedSegment)
+        ));
+
+        compact(
+            &blockfile_provider,
+            &mut record_segment,
+            &mut ivf_segment,
+            vec![
+                log_record(1, "a", Operation::Add, Some(vec![0.0, 0.0])),
+                log_record(2, "b", Operation::Add, Some(vec![1.0, 0.0])),
+                log_record(3, "c", Operation::Add, Some(vec![10.0, 10.0])),
+            ],
+        )
+        .await;
+        let reader = IvfSegmentReader::from_segment(&ivf_segment, 2, &blockfile_provider)
+            .await
+            .unwrap();
+        let (offset_ids, distances) = reader
+            .query(
+                &[0.0, 0.0],
+                3,
+                1,
+                &RoaringBitmap::new(),
+                &RoaringBitmap::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(offset_ids, vec![1, 2]);
+        assert_eq!(distances, vec![0.0, 1.0]);
+        let (offset_ids, _) = reader
+            .query(
+                &[0.0, 0.0],
+                3,
+                2,
+                &RoaringBitmap::new(),
+                &RoaringBitmap::from_iter([2u32]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(offset_ids, vec![1, 3]);
+
+        // Move a to the other cluster and delete b. The centroids stay fixed.
+        compact(
+            &blockfile_provider,
+            &mut record_segment,
+            &mut ivf_segment,
+            vec![
+                log_record(4, "a", Operation::Update, Some(vec![9.0, 10.0])),
+                log_record(5, "b", Operation::Delete, None),
+            ],
+        )
+        .await;
+        let reader = IvfSegmentReader::from_segment(&ivf_segment, 2, &blockfile_provider)
+            .await
+            .unwrap();
+        let (offset_ids, _) = reader
+            .query(
+                &[0.0, 0.0],
+                3,
+                1,
+                &RoaringBitmap::new(),
+                &RoaringBitmap::new(),
+            )
+            .await
+            .unwrap();
+        assert!(offset_ids.is_empty());
+        let (offset_ids, distances) = reader
+            .query(
+                &[10.0, 10.0],
+                3,
+                1,
+                &RoaringBitmap::new(),
+                &RoaringBitmap::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(offset_ids, vec![3, 1]);
+        assert_eq!(distances, vec![0.0, 1.0]);
+    }
+
+    #[tokio::test]
+    async fn test_ivf_segment_retrains() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider = BlockfileProvider::new_arrow(storage);
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut ivf_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileIvf,
+            scope: SegmentScope::VECTOR,
+            collection: record_segment.collection,
+            metadata: Some(HashMap::from([(
+                "ivf:nlist".to_string(),
+                MetadataValue::Int(2),
+            )])),
+            file_path: HashMap::new(),
+        };
+
+        // A single vector trains a single centroid
+        compact(
+            &blockfile_provider,
+            &mut record_segment,
+            &mut ivf_segment,
+            vec![log_record(1, "a", Operation::Add, Some(vec![0.0, 0.0]))],
+        )
+        .await;
+        let reader = IvfSegmentReader::from_segment(&ivf_segment, 2, &blockfile_provider)
+            .await
+            .unwrap();
+        assert_eq!(reader.index.centroids().len(), 1);
+
+        // The collection outgrows its training set, so the centroids are
+        // retrained on all of it.
+        compact(
+            &blockfile_provider,
+            &mut record_segment,
+            &mut ivf_segment,
+            vec![
+                log_record(2, "b", Operation::Add, Some(vec![10.0, 10.0])),
+                log_record(3, "c", Operation::Add, Some(vec![10.5, 10.0])),
+            ],
+        )
+        .await;
+        let reader = IvfSegmentReader::from_segment(&ivf_segment, 2, &blockfile_provider)
+            .await
+            .unwrap();
+        assert_eq!(reader.index.centroids().len(), 2);
+        assert_eq!(reader.index.training_size(), 3);
+        let empty = RoaringBitmap::new();
+        let (offset_ids, _) = reader
+            .query(&[10.0, 10.0], 3, 1, &empty, &empty)
+            .await
+            .unwrap();
+        assert_eq!(offset_ids, vec![2, 3]);
+        let (offset_ids, _) = reader
+            .query(&[0.0, 0.0], 3, 1, &empty, &empty)
+            .await
+            .unwrap();
+        assert_eq!(offset_ids, vec![1]);
+
+        // Deletes find the vectors in the lists of the retrained centroids
+        compact(
+            &blockfile_provider,
+            &mut record_segment,
+            &mut ivf_segment,
+            vec![log_record(4, "a", Operation::Delete, None)],
+        )
+        .await;
+        let reader = IvfSegmentReader::from_segment(&ivf_segment, 2, &blockfile_provider)
+            .await
+            .unwrap();
+        let (offset_ids, _) = reader
+            .query(&[0.0, 0.0], 3, 2, &empty, &empty)
+            .await
+            .unwrap();
+        assert_eq!(offset_ids, vec![2, 3]);
+    }
+}
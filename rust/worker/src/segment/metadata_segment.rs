@@ -11,34 +11,42 @@ use tantivy::tokenizer::NgramTokenizer;
 use thiserror::Error;
 use uuid::Uuid;
 
-use super::record_segment::ApplyMaterializedLogError;
+use super::record_segment::{ApplyMaterializedLogError, RecordSegmentReader};
 use super::types::{MaterializedLogRecord, SegmentWriter};
 use super::SegmentFlusher;
 use crate::blockstore::key::KeyWrapper;
 use crate::blockstore::provider::{BlockfileProvider, CreateError, OpenError};
 use crate::errors::{ChromaError, ErrorCodes};
+use crate::index::fulltext::pattern::DocumentPattern;
+use crate::index::fulltext::query::{
+    phrase_candidates, phrase_matches, positions_of, LiteralPlan, TextQuery,
+};
 use crate::index::fulltext::tokenizer::TantivyChromaTokenizer;
 use crate::index::fulltext::types::{
-    process_where_document_clause_with_callback, FullTextIndexError, FullTextIndexFlusher,
-    FullTextIndexReader, FullTextIndexWriter,
+    FullTextIndexError, FullTextIndexFlusher, FullTextIndexReader, FullTextIndexWriter,
 };
 use crate::index::metadata::types::{
-    process_where_clause_with_callback, MetadataIndexError, MetadataIndexFlusher,
-    MetadataIndexReader, MetadataIndexWriter,
+    resolve_where_comparison, MetadataIndexError, MetadataIndexFlusher, MetadataIndexReader,
+    MetadataIndexWriter, SortedValues,
 };
+use crate::types::SegmentType;
 use crate::types::{
-    BooleanOperator, MetadataValue, Operation, Segment, Where, WhereClauseComparator,
-    WhereDocument, WhereDocumentOperator,
+    BooleanOperator, DirectComparison, DirectDocumentComparison, MetadataType, MetadataValue,
+    Operation, OrderBy, Segment, Where, WhereClauseComparator, WhereComparison, WhereDocument,
+    WhereDocumentOperator,
 };
-use crate::types::{SegmentType, WhereComparison};
-use crate::utils::{merge_sorted_vecs_conjunction, merge_sorted_vecs_disjunction};
 
 const FULL_TEXT_PLS: &str = "full_text_pls";
 const FULL_TEXT_FREQS: &str = "full_text_freqs";
 const STRING_METADATA: &str = "string_metadata";
 const BOOL_METADATA: &str = "bool_metadata";
 const F32_METADATA: &str = "f32_metadata";
-const U32_METADATA: &str = "u32_metadata";
+const I64_METADATA: &str = "i64_metadata";
+// Integer metadata was indexed as u32 before it became 64-bit.
+const LEGACY_U32_METADATA: &str = "u32_metadata";
+// The offset ids of the records that have each metadata key, stored under the
+// key as the prefix and `true` as the key.
+const KEY_PRESENCE: &str = "key_presence";
 
 #[derive(Clone)]
 pub(crate) struct MetadataSegmentWriter<'me> {
@@ -46,7 +54,8 @@ pub(crate) struct MetadataSegmentWriter<'me> {
     pub(crate) string_metadata_index_writer: Option<MetadataIndexWriter<'me>>,
     pub(crate) bool_metadata_index_writer: Option<MetadataIndexWriter<'me>>,
     pub(crate) f32_metadata_index_writer: Option<MetadataIndexWriter<'me>>,
-    pub(crate) u32_metadata_index_writer: Option<MetadataIndexWriter<'me>>,
+    pub(crate) i64_metadata_index_writer: Option<MetadataIndexWriter<'me>>,
+    pub(crate) key_presence_index_writer: Option<MetadataIndexWriter<'me>>,
     pub(crate) id: Uuid,
 }
 
@@ -83,19 +92,94 @@ pub enum MetadataSegmentError {
     EmptyPathVector,
     #[error("Failed to write to blockfile")]
     BlockfileWriteError,
-    #[error("Limit and offset are not currently supported")]
-    LimitOffsetNotSupported,
     #[error("Could not query metadata index {0}")]
     MetadataIndexQueryError(#[from] MetadataIndexError),
     #[error("Attempted to delete a document that does not exist")]
     DocumentDoesNotExist,
+    #[error("Could not read legacy integer metadata {0}")]
+    LegacyMetadataReadError(Box<dyn ChromaError>),
 }
 
 impl ChromaError for MetadataSegmentError {
     fn code(&self) -> ErrorCodes {
-        // TODO
-        ErrorCodes::Internal
+        match self {
+            MetadataSegmentError::MetadataIndexQueryError(e) => e.code(),
+            // TODO
+            _ => ErrorCodes::Internal,
+        }
+    }
+}
+
+// Copies the integer metadata of a segment written before integers were
+// 64-bit into the i64 index. The legacy keys are i32 values stored as u32.
+async fn migrate_legacy_u32_metadata(
+    legacy_path: &[String],
+    blockfile_provider: &BlockfileProvider,
+    i64_metadata_index_writer: &MetadataIndexWriter<'_>,
+    key_presence_index_writer: &MetadataIndexWriter<'_>,
+) -> Result<(), MetadataSegmentError> {
+    let legacy_uuid = match legacy_path.first() {
+        Some(legacy_uuid) => match Uuid::parse_str(legacy_uuid) {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return Err(MetadataSegmentError::UuidParseError(
+                    legacy_uuid.to_string(),
+                ))
+            }
+        },
+        None => return Err(MetadataSegmentError::EmptyPathVector),
+    };
+    let legacy_reader = match blockfile_provider
+        .open::<u32, RoaringBitmap>(&legacy_uuid)
+        .await
+    {
+        Ok(reader) => reader,
+        Err(e) => return Err(MetadataSegmentError::BlockfileOpenError(*e)),
+    };
+    let count = legacy_reader
+        .count()
+        .await
+        .map_err(MetadataSegmentError::LegacyMetadataReadError)?;
+    for index in 0..count {
+        let (prefix, key, offset_ids) = legacy_reader
+            .get_at_index(index)
+            .await
+            .map_err(MetadataSegmentError::LegacyMetadataReadError)?;
+        let key = key as i32 as i64;
+        for offset_id in offset_ids.iter() {
+            i64_metadata_index_writer
+                .set(prefix, key, offset_id)
+                .await?;
+            key_presence_index_writer
+                .set(prefix, true, offset_id)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+// Builds the key presence index of a segment written before keys were tracked
+// from the postings of its value indices.
+async fn backfill_key_presence(
+    segment: &Segment,
+    blockfile_provider: &BlockfileProvider,
+    key_presence_index_writer: &MetadataIndexWriter<'_>,
+) -> Result<(), MetadataSegmentError> {
+    let reader = MetadataSegmentReader::from_segment(segment, blockfile_provider).await?;
+    let index_readers = [
+        &reader.string_metadata_index_reader,
+        &reader.bool_metadata_index_reader,
+        &reader.f32_metadata_index_reader,
+        &reader.i64_metadata_index_reader,
+    ];
+    for index_reader in index_readers.into_iter().flatten() {
+        for (key, offset_ids) in index_reader.offset_ids_by_key().await? {
+            for offset_id in offset_ids {
+                key_presence_index_writer.set(&key, true, offset_id).await?;
+            }
+        }
     }
+    Ok(())
 }
 
 impl<'me> MetadataSegmentWriter<'me> {
@@ -317,50 +401,104 @@ impl<'me> MetadataSegmentWriter<'me> {
         let f32_metadata_index_writer =
             MetadataIndexWriter::new_f32(f32_metadata_writer, f32_metadata_index_reader);
 
-        let (u32_metadata_writer, u32_metadata_index_reader) =
-            match segment.file_path.get(U32_METADATA) {
-                Some(u32_metadata_path) => match u32_metadata_path.get(0) {
-                    Some(u32_metadata_uuid) => {
-                        let u32_metadata_uuid = match Uuid::parse_str(u32_metadata_uuid) {
+        let (i64_metadata_writer, i64_metadata_index_reader) =
+            match segment.file_path.get(I64_METADATA) {
+                Some(i64_metadata_path) => match i64_metadata_path.get(0) {
+                    Some(i64_metadata_uuid) => {
+                        let i64_metadata_uuid = match Uuid::parse_str(i64_metadata_uuid) {
+                            Ok(uuid) => uuid,
+                            Err(_) => {
+                                return Err(MetadataSegmentError::UuidParseError(
+                                    i64_metadata_uuid.to_string(),
+                                ))
+                            }
+                        };
+                        let i64_metadata_writer = match blockfile_provider
+                            .fork::<i64, &RoaringBitmap>(&i64_metadata_uuid)
+                            .await
+                        {
+                            Ok(writer) => writer,
+                            Err(e) => return Err(MetadataSegmentError::BlockfileError(*e)),
+                        };
+                        let i64_metadata_index_reader = match blockfile_provider
+                            .open::<i64, RoaringBitmap>(&i64_metadata_uuid)
+                            .await
+                        {
+                            Ok(reader) => MetadataIndexReader::new_i64(reader),
+                            Err(e) => return Err(MetadataSegmentError::BlockfileOpenError(*e)),
+                        };
+                        (i64_metadata_writer, Some(i64_metadata_index_reader))
+                    }
+                    None => return Err(MetadataSegmentError::EmptyPathVector),
+                },
+                None => match blockfile_provider.create::<i64, &RoaringBitmap>() {
+                    Ok(writer) => (writer, None),
+                    Err(e) => return Err(MetadataSegmentError::BlockfileError(*e)),
+                },
+            };
+        let i64_metadata_index_writer =
+            MetadataIndexWriter::new_i64(i64_metadata_writer, i64_metadata_index_reader);
+
+        let (key_presence_writer, key_presence_index_reader) =
+            match segment.file_path.get(KEY_PRESENCE) {
+                Some(key_presence_path) => match key_presence_path.first() {
+                    Some(key_presence_uuid) => {
+                        let key_presence_uuid = match Uuid::parse_str(key_presence_uuid) {
                             Ok(uuid) => uuid,
                             Err(_) => {
                                 return Err(MetadataSegmentError::UuidParseError(
-                                    u32_metadata_uuid.to_string(),
+                                    key_presence_uuid.to_string(),
                                 ))
                             }
                         };
-                        let u32_metadata_writer = match blockfile_provider
-                            .fork::<u32, &RoaringBitmap>(&u32_metadata_uuid)
+                        let key_presence_writer = match blockfile_provider
+                            .fork::<bool, &RoaringBitmap>(&key_presence_uuid)
                             .await
                         {
                             Ok(writer) => writer,
                             Err(e) => return Err(MetadataSegmentError::BlockfileError(*e)),
                         };
-                        let u32_metadata_index_reader = match blockfile_provider
-                            .open::<u32, RoaringBitmap>(&u32_metadata_uuid)
+                        let key_presence_index_reader = match blockfile_provider
+                            .open::<bool, RoaringBitmap>(&key_presence_uuid)
                             .await
                         {
-                            Ok(reader) => MetadataIndexReader::new_u32(reader),
+                            Ok(reader) => MetadataIndexReader::new_bool(reader),
                             Err(e) => return Err(MetadataSegmentError::BlockfileOpenError(*e)),
                         };
-                        (u32_metadata_writer, Some(u32_metadata_index_reader))
+                        (key_presence_writer, Some(key_presence_index_reader))
                     }
                     None => return Err(MetadataSegmentError::EmptyPathVector),
                 },
-                None => match blockfile_provider.create::<u32, &RoaringBitmap>() {
+                None => match blockfile_provider.create::<bool, &RoaringBitmap>() {
                     Ok(writer) => (writer, None),
                     Err(e) => return Err(MetadataSegmentError::BlockfileError(*e)),
                 },
             };
-        let u32_metadata_index_writer =
-            MetadataIndexWriter::new_u32(u32_metadata_writer, u32_metadata_index_reader);
+        let key_presence_index_writer =
+            MetadataIndexWriter::new_bool(key_presence_writer, key_presence_index_reader);
+        if !segment.file_path.contains_key(KEY_PRESENCE) {
+            backfill_key_presence(segment, blockfile_provider, &key_presence_index_writer).await?;
+        }
+
+        if !segment.file_path.contains_key(I64_METADATA) {
+            if let Some(legacy_path) = segment.file_path.get(LEGACY_U32_METADATA) {
+                migrate_legacy_u32_metadata(
+                    legacy_path,
+                    blockfile_provider,
+                    &i64_metadata_index_writer,
+                    &key_presence_index_writer,
+                )
+                .await?;
+            }
+        }
 
         Ok(MetadataSegmentWriter {
             full_text_index_writer: Some(full_text_index_writer),
             string_metadata_index_writer: Some(string_metadata_index_writer),
             bool_metadata_index_writer: Some(bool_metadata_index_writer),
             f32_metadata_index_writer: Some(f32_metadata_index_writer),
-            u32_metadata_index_writer: Some(u32_metadata_index_writer),
+            i64_metadata_index_writer: Some(i64_metadata_index_writer),
+            key_presence_index_writer: Some(key_presence_index_writer),
             id: segment.id,
         })
     }
@@ -410,12 +548,23 @@ impl<'me> MetadataSegmentWriter<'me> {
             Err(_) => return Err(MetadataSegmentError::BlockfileWriteError),
         }
 
-        let mut u32_metadata_index_writer = self
-            .u32_metadata_index_writer
+        let mut i64_metadata_index_writer = self
+            .i64_metadata_index_writer
+            .take()
+            .ok_or_else(|| MetadataSegmentError::NoWriter)?;
+        let res = i64_metadata_index_writer.write_to_blockfile().await;
+        self.i64_metadata_index_writer = Some(i64_metadata_index_writer);
+        match res {
+            Ok(_) => {}
+            Err(_) => return Err(MetadataSegmentError::BlockfileWriteError),
+        }
+
+        let mut key_presence_index_writer = self
+            .key_presence_index_writer
             .take()
             .ok_or_else(|| MetadataSegmentError::NoWriter)?;
-        let res = u32_metadata_index_writer.write_to_blockfile().await;
-        self.u32_metadata_index_writer = Some(u32_metadata_index_writer);
+        let res = key_presence_index_writer.write_to_blockfile().await;
+        self.key_presence_index_writer = Some(key_presence_index_writer);
         match res {
             Ok(_) => {}
             Err(_) => return Err(MetadataSegmentError::BlockfileWriteError),
@@ -425,6 +574,78 @@ impl<'me> MetadataSegmentWriter<'me> {
     }
 }
 
+impl<'me> MetadataSegmentWriter<'me> {
+    // The index writer of a scalar metadata value and its key in that index.
+    fn index_entry(
+        &self,
+        value: &MetadataValue,
+    ) -> Option<(&MetadataIndexWriter<'me>, KeyWrapper)> {
+        match value {
+            MetadataValue::Str(value) => self
+                .string_metadata_index_writer
+                .as_ref()
+                .map(|writer| (writer, value.as_str().into())),
+            MetadataValue::Float(value) => self
+                .f32_metadata_index_writer
+                .as_ref()
+                .map(|writer| (writer, (*value as f32).into())),
+            MetadataValue::Int(value) => self
+                .i64_metadata_index_writer
+                .as_ref()
+                .map(|writer| (writer, (*value).into())),
+            MetadataValue::Bool(value) => self
+                .bool_metadata_index_writer
+                .as_ref()
+                .map(|writer| (writer, (*value).into())),
+            _ => None,
+        }
+    }
+
+    // List values have one posting per element in the index of the element
+    // type, so an element matches the same lookups as a scalar value.
+    async fn set_elements(
+        &self,
+        key: &str,
+        value: &MetadataValue,
+        offset_id: u32,
+    ) -> Result<(), MetadataIndexError> {
+        for element in value.elements() {
+            if let Some((writer, element_key)) = self.index_entry(&element) {
+                writer.set(key, element_key, offset_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Tracks whether the record has the key, whatever the type of its value.
+    async fn set_key_presence(
+        &self,
+        key: &str,
+        present: bool,
+        offset_id: u32,
+    ) -> Result<(), MetadataIndexError> {
+        match &self.key_presence_index_writer {
+            Some(writer) if present => writer.set(key, true, offset_id).await,
+            Some(writer) => writer.delete(key, true, offset_id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn delete_elements(
+        &self,
+        key: &str,
+        value: &MetadataValue,
+        offset_id: u32,
+    ) -> Result<(), MetadataIndexError> {
+        for element in value.elements() {
+            if let Some((writer, element_key)) = self.index_entry(&element) {
+                writer.delete(key, element_key, offset_id).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
     async fn apply_materialized_log_chunk(
         &self,
@@ -439,6 +660,9 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                     match &record.0.metadata_to_be_merged {
                         Some(metadata) => {
                             for (key, value) in metadata.iter() {
+                                self.set_key_presence(key, true, segment_offset_id)
+                                    .await
+                                    .map_err(|_| ApplyMaterializedLogError::BlockfileSetError)?;
                                 match value {
                                     MetadataValue::Str(value) => {
                                         match &self.string_metadata_index_writer {
@@ -461,10 +685,10 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                         }
                                     }
                                     MetadataValue::Int(value) => {
-                                        match &self.u32_metadata_index_writer {
+                                        match &self.i64_metadata_index_writer {
                                             Some(writer) => {
                                                 let _ = writer
-                                                    .set(key, *value as u32, segment_offset_id)
+                                                    .set(key, *value, segment_offset_id)
                                                     .await;
                                             }
                                             None => {}
@@ -480,6 +704,10 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                             None => {}
                                         }
                                     }
+                                    list => {
+                                        let _ =
+                                            self.set_elements(key, list, segment_offset_id).await;
+                                    }
                                 }
                             }
                         }
@@ -502,6 +730,11 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                         match &data_record.metadata {
                             Some(metadata) => {
                                 for (key, value) in metadata.iter() {
+                                    self.set_key_presence(key, false, segment_offset_id)
+                                        .await
+                                        .map_err(|_| {
+                                            ApplyMaterializedLogError::BlockfileDeleteError
+                                        })?;
                                     match value {
                                         MetadataValue::Str(value) => {
                                             match &self.string_metadata_index_writer {
@@ -536,14 +769,10 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                             }
                                         }
                                         MetadataValue::Int(value) => {
-                                            match &self.u32_metadata_index_writer {
+                                            match &self.i64_metadata_index_writer {
                                                 Some(writer) => {
                                                     let _ = writer
-                                                        .delete(
-                                                            key,
-                                                            *value as u32,
-                                                            segment_offset_id,
-                                                        )
+                                                        .delete(key, *value, segment_offset_id)
                                                         .await;
                                                 }
                                                 None => {
@@ -563,6 +792,11 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                                 }
                                             }
                                         }
+                                        list => {
+                                            let _ = self
+                                                .delete_elements(key, list, segment_offset_id)
+                                                .await;
+                                        }
                                     }
                                 }
                             }
@@ -589,6 +823,17 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                     let metadata_delta = record.0.metadata_delta();
                     // Updates.
                     for (update_key, (old_value, new_value)) in metadata_delta.metadata_to_update {
+                        // Lists are replaced as a whole, including changes
+                        // between a scalar and a list.
+                        if old_value.is_list() || new_value.is_list() {
+                            self.delete_elements(update_key, old_value, segment_offset_id)
+                                .await
+                                .map_err(|_| ApplyMaterializedLogError::BlockfileUpdateError)?;
+                            self.set_elements(update_key, new_value, segment_offset_id)
+                                .await
+                                .map_err(|_| ApplyMaterializedLogError::BlockfileUpdateError)?;
+                            continue;
+                        }
                         match new_value {
                             MetadataValue::Str(new_val_str) => match old_value {
                                 MetadataValue::Str(old_val_str) => {
@@ -648,13 +893,13 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                             },
                             MetadataValue::Int(new_val_int) => match old_value {
                                 MetadataValue::Int(old_val_int) => {
-                                    match &self.u32_metadata_index_writer {
+                                    match &self.i64_metadata_index_writer {
                                         Some(writer) => {
                                             match writer
                                                 .update(
                                                     update_key,
-                                                    (*old_val_int as u32).into(),
-                                                    (*new_val_int as u32).into(),
+                                                    (*old_val_int).into(),
+                                                    (*new_val_int).into(),
                                                     segment_offset_id,
                                                 )
                                                 .await
@@ -666,7 +911,7 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                             }
                                         }
                                         None => {
-                                            panic!("Invariant violation. u32 metadata index writer should be set");
+                                            panic!("Invariant violation. i64 metadata index writer should be set");
                                         }
                                     }
                                 }
@@ -702,10 +947,16 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                     return Err(ApplyMaterializedLogError::MetadataUpdateNotValid);
                                 }
                             },
+                            _ => {
+                                panic!("Invariant violation. List updates are handled above");
+                            }
                         }
                     }
                     // Inserts.
                     for (insert_key, new_value) in metadata_delta.metadata_to_insert {
+                        self.set_key_presence(insert_key, true, segment_offset_id)
+                            .await
+                            .map_err(|_| ApplyMaterializedLogError::BlockfileSetError)?;
                         match new_value {
                             MetadataValue::Str(new_val_str) => {
                                 match &self.string_metadata_index_writer {
@@ -756,10 +1007,10 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                 }
                             }
                             MetadataValue::Int(new_val_int) => {
-                                match &self.u32_metadata_index_writer {
+                                match &self.i64_metadata_index_writer {
                                     Some(writer) => {
                                         match writer
-                                            .set(insert_key, *new_val_int as u32, segment_offset_id)
+                                            .set(insert_key, *new_val_int, segment_offset_id)
                                             .await
                                         {
                                             Ok(()) => {}
@@ -795,10 +1046,18 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                     }
                                 }
                             }
+                            list => {
+                                self.set_elements(insert_key, list, segment_offset_id)
+                                    .await
+                                    .map_err(|_| ApplyMaterializedLogError::BlockfileSetError)?;
+                            }
                         }
                     }
                     // Deletes.
                     for (delete_key, old_value) in metadata_delta.metadata_to_delete {
+                        self.set_key_presence(delete_key, false, segment_offset_id)
+                            .await
+                            .map_err(|_| ApplyMaterializedLogError::BlockfileDeleteError)?;
                         match old_value {
                             MetadataValue::Str(old_val_str) => {
                                 match &self.string_metadata_index_writer {
@@ -849,14 +1108,10 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                 }
                             }
                             MetadataValue::Int(old_val_int) => {
-                                match &self.u32_metadata_index_writer {
+                                match &self.i64_metadata_index_writer {
                                     Some(writer) => {
                                         match writer
-                                            .delete(
-                                                delete_key,
-                                                *old_val_int as u32,
-                                                segment_offset_id,
-                                            )
+                                            .delete(delete_key, *old_val_int, segment_offset_id)
                                             .await
                                         {
                                             Ok(()) => {}
@@ -892,6 +1147,11 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
                                     }
                                 }
                             }
+                            list => {
+                                self.delete_elements(delete_key, list, segment_offset_id)
+                                    .await
+                                    .map_err(|_| ApplyMaterializedLogError::BlockfileDeleteError)?;
+                            }
                         }
                     }
                 }
@@ -936,7 +1196,15 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
             None => return Err(Box::new(MetadataSegmentError::NoWriter)),
         };
 
-        let u32_metadata_flusher = match self.u32_metadata_index_writer {
+        let i64_metadata_flusher = match self.i64_metadata_index_writer {
+            Some(flusher) => match flusher.commit() {
+                Ok(flusher) => flusher,
+                Err(e) => return Err(Box::new(e)),
+            },
+            None => return Err(Box::new(MetadataSegmentError::NoWriter)),
+        };
+
+        let key_presence_flusher = match self.key_presence_index_writer {
             Some(flusher) => match flusher.commit() {
                 Ok(flusher) => flusher,
                 Err(e) => return Err(Box::new(e)),
@@ -949,7 +1217,8 @@ impl<'log_records> SegmentWriter<'log_records> for MetadataSegmentWriter<'_> {
             string_metadata_index_flusher: string_metadata_flusher,
             bool_metadata_index_flusher: bool_metadata_flusher,
             f32_metadata_index_flusher: f32_metadata_flusher,
-            u32_metadata_index_flusher: u32_metadata_flusher,
+            i64_metadata_index_flusher: i64_metadata_flusher,
+            key_presence_index_flusher: key_presence_flusher,
         })
     }
 }
@@ -959,7 +1228,8 @@ pub(crate) struct MetadataSegmentFlusher {
     pub(crate) string_metadata_index_flusher: MetadataIndexFlusher,
     pub(crate) bool_metadata_index_flusher: MetadataIndexFlusher,
     pub(crate) f32_metadata_index_flusher: MetadataIndexFlusher,
-    pub(crate) u32_metadata_index_flusher: MetadataIndexFlusher,
+    pub(crate) i64_metadata_index_flusher: MetadataIndexFlusher,
+    pub(crate) key_presence_index_flusher: MetadataIndexFlusher,
 }
 
 #[async_trait]
@@ -970,7 +1240,8 @@ impl SegmentFlusher for MetadataSegmentFlusher {
         let string_metadata_id = self.string_metadata_index_flusher.id();
         let bool_metadata_id = self.bool_metadata_index_flusher.id();
         let f32_metadata_id = self.f32_metadata_index_flusher.id();
-        let u32_metadata_id = self.u32_metadata_index_flusher.id();
+        let i64_metadata_id = self.i64_metadata_index_flusher.id();
+        let key_presence_id = self.key_presence_index_flusher.id();
 
         let mut flushed = HashMap::new();
 
@@ -1007,11 +1278,17 @@ impl SegmentFlusher for MetadataSegmentFlusher {
         }
         flushed.insert(F32_METADATA.to_string(), vec![f32_metadata_id.to_string()]);
 
-        match self.u32_metadata_index_flusher.flush().await.map_err(|e| e) {
+        match self.i64_metadata_index_flusher.flush().await.map_err(|e| e) {
+            Ok(_) => {}
+            Err(e) => return Err(Box::new(e)),
+        }
+        flushed.insert(I64_METADATA.to_string(), vec![i64_metadata_id.to_string()]);
+
+        match self.key_presence_index_flusher.flush().await {
             Ok(_) => {}
             Err(e) => return Err(Box::new(e)),
         }
-        flushed.insert(U32_METADATA.to_string(), vec![u32_metadata_id.to_string()]);
+        flushed.insert(KEY_PRESENCE.to_string(), vec![key_presence_id.to_string()]);
 
         match self
             .string_metadata_index_flusher
@@ -1031,12 +1308,105 @@ impl SegmentFlusher for MetadataSegmentFlusher {
     }
 }
 
+/// The offset ids of every record in the collection, read from the record
+/// segment the first time a negation needs them.
+pub(crate) struct OffsetIdUniverse<'me> {
+    record_segment_reader: Option<&'me RecordSegmentReader<'me>>,
+    offset_ids: tokio::sync::OnceCell<RoaringBitmap>,
+}
+
+impl<'me> OffsetIdUniverse<'me> {
+    pub(crate) fn new(record_segment_reader: Option<&'me RecordSegmentReader<'me>>) -> Self {
+        Self {
+            record_segment_reader,
+            offset_ids: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    /// An upper bound on the number of records, exact once the offset ids
+    /// have been read.
+    pub(crate) fn size_estimate(&self) -> u64 {
+        match (self.offset_ids.get(), self.record_segment_reader) {
+            (Some(offset_ids), _) => offset_ids.len(),
+            (None, Some(reader)) => reader
+                .get_current_max_offset_id()
+                .load(std::sync::atomic::Ordering::SeqCst)
+                as u64,
+            (None, None) => 0,
+        }
+    }
+
+    pub(crate) async fn get(&self) -> Result<&RoaringBitmap, MetadataIndexError> {
+        self.offset_ids
+            .get_or_try_init(|| async {
+                match self.record_segment_reader {
+                    Some(reader) => Ok(reader.get_all_offset_ids().await?),
+                    // The record segment is uninitialized, there are no records.
+                    None => Ok(RoaringBitmap::new()),
+                }
+            })
+            .await
+    }
+}
+
+/// The values of a metadata key across the metadata indices of a segment,
+/// merged in the order of an `OrderBy`.
+pub(crate) struct SortedMetadataValues<'me> {
+    order_by: OrderBy,
+    values: Vec<SortedValues<'me>>,
+    peeked: Option<(MetadataValue, RoaringBitmap)>,
+}
+
+impl SortedMetadataValues<'_> {
+    /// Returns the next value without consuming it.
+    pub(crate) async fn peek(
+        &mut self,
+    ) -> Result<Option<&(MetadataValue, RoaringBitmap)>, MetadataSegmentError> {
+        if self.peeked.is_none() {
+            self.peeked = self.next_merged().await?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    /// Returns the next value.
+    pub(crate) async fn next(
+        &mut self,
+    ) -> Result<Option<(MetadataValue, RoaringBitmap)>, MetadataSegmentError> {
+        match self.peeked.take() {
+            Some(value) => Ok(Some(value)),
+            None => self.next_merged().await,
+        }
+    }
+
+    async fn next_merged(
+        &mut self,
+    ) -> Result<Option<(MetadataValue, RoaringBitmap)>, MetadataSegmentError> {
+        let mut first: Option<(usize, MetadataValue)> = None;
+        for (index, values) in self.values.iter_mut().enumerate() {
+            if let Some((value, _)) = values.peek().await? {
+                let comes_first = match &first {
+                    Some((_, first_value)) => self.order_by.compare(value, first_value).is_lt(),
+                    None => true,
+                };
+                if comes_first {
+                    first = Some((index, value.clone()));
+                }
+            }
+        }
+        match first {
+            Some((index, _)) => Ok(self.values[index].next().await?),
+            None => Ok(None),
+        }
+    }
+}
+
 pub(crate) struct MetadataSegmentReader<'me> {
     pub(crate) full_text_index_reader: Option<FullTextIndexReader<'me>>,
     pub(crate) string_metadata_index_reader: Option<MetadataIndexReader<'me>>,
     pub(crate) bool_metadata_index_reader: Option<MetadataIndexReader<'me>>,
     pub(crate) f32_metadata_index_reader: Option<MetadataIndexReader<'me>>,
-    pub(crate) u32_metadata_index_reader: Option<MetadataIndexReader<'me>>,
+    pub(crate) i64_metadata_index_reader: Option<MetadataIndexReader<'me>>,
+    pub(crate) key_presence_index_reader: Option<MetadataIndexReader<'me>>,
 }
 
 impl MetadataSegmentReader<'_> {
@@ -1177,33 +1547,57 @@ impl MetadataSegmentReader<'_> {
             None => None,
         };
 
-        let u32_metadata_reader = match segment.file_path.get(U32_METADATA) {
-            Some(u32_metadata_path) => match u32_metadata_path.get(0) {
-                Some(u32_metadata_uuid) => {
-                    let u32_metadata_uuid = match Uuid::parse_str(u32_metadata_uuid) {
+        let i64_metadata_reader = match segment.file_path.get(I64_METADATA) {
+            Some(i64_metadata_path) => match i64_metadata_path.get(0) {
+                Some(i64_metadata_uuid) => {
+                    let i64_metadata_uuid = match Uuid::parse_str(i64_metadata_uuid) {
                         Ok(uuid) => uuid,
                         Err(_) => {
                             return Err(MetadataSegmentError::UuidParseError(
-                                u32_metadata_uuid.to_string(),
+                                i64_metadata_uuid.to_string(),
                             ))
                         }
                     };
-                    let u32_metadata_reader = match blockfile_provider
-                        .open::<u32, RoaringBitmap>(&u32_metadata_uuid)
+                    let i64_metadata_reader = match blockfile_provider
+                        .open::<i64, RoaringBitmap>(&i64_metadata_uuid)
                         .await
                     {
                         Ok(reader) => Some(reader),
                         Err(e) => return Err(MetadataSegmentError::BlockfileOpenError(*e)),
                     };
-                    u32_metadata_reader
+                    i64_metadata_reader
                 }
                 None => None,
             },
             None => None,
         };
-        let u32_metadata_index_reader = match u32_metadata_reader {
-            Some(reader) => Some(MetadataIndexReader::new_u32(reader)),
-            None => None,
+        let i64_metadata_index_reader = match i64_metadata_reader {
+            Some(reader) => Some(MetadataIndexReader::new_i64(reader)),
+            // The legacy index is migrated on the next compaction, until then
+            // it answers the integer filters.
+            None => match segment.file_path.get(LEGACY_U32_METADATA) {
+                Some(legacy_path) => match legacy_path.first() {
+                    Some(legacy_uuid) => {
+                        let legacy_uuid = match Uuid::parse_str(legacy_uuid) {
+                            Ok(uuid) => uuid,
+                            Err(_) => {
+                                return Err(MetadataSegmentError::UuidParseError(
+                                    legacy_uuid.to_string(),
+                                ))
+                            }
+                        };
+                        match blockfile_provider
+                            .open::<u32, RoaringBitmap>(&legacy_uuid)
+                            .await
+                        {
+                            Ok(reader) => Some(MetadataIndexReader::new_legacy_u32(reader)),
+                            Err(e) => return Err(MetadataSegmentError::BlockfileOpenError(*e)),
+                        }
+                    }
+                    None => None,
+                },
+                None => None,
+            },
         };
 
         let f32_metadata_reader = match segment.file_path.get(F32_METADATA) {
@@ -1235,12 +1629,39 @@ impl MetadataSegmentReader<'_> {
             None => None,
         };
 
+        let key_presence_reader = match segment.file_path.get(KEY_PRESENCE) {
+            Some(key_presence_path) => match key_presence_path.first() {
+                Some(key_presence_uuid) => {
+                    let key_presence_uuid = match Uuid::parse_str(key_presence_uuid) {
+                        Ok(uuid) => uuid,
+                        Err(_) => {
+                            return Err(MetadataSegmentError::UuidParseError(
+                                key_presence_uuid.to_string(),
+                            ))
+                        }
+                    };
+                    let key_presence_reader = match blockfile_provider
+                        .open::<bool, RoaringBitmap>(&key_presence_uuid)
+                        .await
+                    {
+                        Ok(reader) => Some(reader),
+                        Err(e) => return Err(MetadataSegmentError::BlockfileOpenError(*e)),
+                    };
+                    key_presence_reader
+                }
+                None => None,
+            },
+            None => None,
+        };
+        let key_presence_index_reader = key_presence_reader.map(MetadataIndexReader::new_bool);
+
         Ok(MetadataSegmentReader {
             full_text_index_reader,
             string_metadata_index_reader,
             bool_metadata_index_reader,
             f32_metadata_index_reader,
-            u32_metadata_index_reader,
+            i64_metadata_index_reader,
+            key_presence_index_reader,
         })
     }
 
@@ -1248,690 +1669,1228 @@ impl MetadataSegmentReader<'_> {
         &self,
         where_clause: Option<&Where>,
         where_document_clause: Option<&WhereDocument>,
-        _allowed_ids: Option<&Vec<usize>>,
-        limit: usize,
-        offset: usize,
+        record_segment_reader: Option<&RecordSegmentReader<'_>>,
     ) -> Result<Option<Vec<usize>>, MetadataSegmentError> {
-        if limit != 0 || offset != 0 {
-            return Err(MetadataSegmentError::LimitOffsetNotSupported);
+        let universe = OffsetIdUniverse::new(record_segment_reader);
+        let cache = FilterCache::default();
+        // Where and WhereDocument are implicitly ANDed, so the planner treats
+        // them like the children of an AND clause.
+        let mut clauses = vec![];
+        if let Some(where_clause) = where_clause {
+            clauses.push(FilterClause::Where(where_clause));
+        }
+        if let Some(where_document_clause) = where_document_clause {
+            clauses.push(FilterClause::WhereDocument(where_document_clause));
+        }
+        if clauses.is_empty() {
+            tracing::info!("No where or where document clause to filter the metadata segment");
+            return Ok(None);
+        }
+        match self
+            .evaluate_children(clauses, &BooleanOperator::And, None, &universe, &cache)
+            .await
+        {
+            Ok(results) => {
+                tracing::info!("Filtered {} records from metadata segment", results.len());
+                Ok(Some(results.iter().map(|x| x as usize).collect()))
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Error fetching results from metadata segment based on where clause {:?}",
+                    e
+                );
+                Err(MetadataSegmentError::MetadataIndexQueryError(e))
+            }
+        }
+    }
+
+    /// Returns the values of a metadata key in the order of `order_by`, with
+    /// the offset ids of the records that hold them. A record with a list
+    /// value holds each of its elements. The indices are read as the values
+    /// are consumed.
+    pub(crate) async fn sorted_values(
+        &self,
+        order_by: &OrderBy,
+    ) -> Result<SortedMetadataValues<'_>, MetadataSegmentError> {
+        let readers = [
+            &self.bool_metadata_index_reader,
+            &self.i64_metadata_index_reader,
+            &self.f32_metadata_index_reader,
+            &self.string_metadata_index_reader,
+        ];
+        let mut values = vec![];
+        for reader in readers.into_iter().flatten() {
+            values.push(
+                reader
+                    .sorted_values(&order_by.key, order_by.descending)
+                    .await?,
+            );
         }
-        // TODO we can do lots of clever query planning here. For now, just
-        // run through the Where and WhereDocument clauses sequentially.
-        let where_results = match where_clause {
-            Some(where_clause) => {
-                match self.process_where_clause(where_clause).await.map_err(|e| e) {
-                    Ok(results) => {
-                        tracing::info!(
-                            "Filtered {} records from metadata segment based on where clause",
-                            results.len()
+        Ok(SortedMetadataValues {
+            order_by: order_by.clone(),
+            values,
+            peeked: None,
+        })
+    }
+
+    // Estimates the number of records that match the clause. Equality lookups
+    // are point reads, so their estimates are exact and the bitmaps they read
+    // are cached for `evaluate`. The document estimates are bounded by the
+    // frequency of the rarest token of the query.
+    fn estimate<'me>(
+        &'me self,
+        clause: FilterClause<'me>,
+        universe: &'me OffsetIdUniverse<'me>,
+        cache: &'me FilterCache,
+    ) -> BoxFuture<'me, Result<u64, MetadataIndexError>> {
+        async move {
+            if let Some(estimate) = cache.estimates.lock().get(&clause.address()) {
+                return Ok(*estimate);
+            }
+            let estimate = match clause {
+                FilterClause::Where(Where::DirectWhereComparison(direct_where_comparison)) => {
+                    self.estimate_where_comparison(direct_where_comparison, universe, cache)
+                        .await?
+                }
+                FilterClause::Where(Where::WhereChildren(where_children)) => {
+                    let mut estimates = Vec::with_capacity(where_children.children.len());
+                    for child in where_children.children.iter() {
+                        estimates.push(
+                            self.estimate(FilterClause::Where(child), universe, cache)
+                                .await?,
                         );
-                        Some(results)
                     }
-                    Err(e) => {
-                        tracing::error!(
-                            "Error fetching results from metadata segment based on where clause {:?}",
-                            e
+                    combine_estimates(estimates, &where_children.operator, universe)
+                }
+                FilterClause::WhereDocument(WhereDocument::DirectWhereDocumentComparison(
+                    direct_document_comparison,
+                )) => {
+                    self.estimate_document_comparison(direct_document_comparison, universe)
+                        .await?
+                }
+                FilterClause::WhereDocument(WhereDocument::WhereDocumentChildren(
+                    where_document_children,
+                )) => {
+                    let mut estimates = Vec::with_capacity(where_document_children.children.len());
+                    for child in where_document_children.children.iter() {
+                        estimates.push(
+                            self.estimate(FilterClause::WhereDocument(child), universe, cache)
+                                .await?,
                         );
-                        return Err(MetadataSegmentError::MetadataIndexQueryError(e));
                     }
+                    combine_estimates(estimates, &where_document_children.operator, universe)
                 }
-            }
-            None => {
-                tracing::info!("No where clause to filter anything from metadata segment");
-                None
-            }
-        };
-        // Where and WhereDocument are implicitly ANDed, so if we have nothing
-        // for the Where query we can just return.
-        match &where_results {
-            Some(results) => {
-                if results.is_empty() {
-                    return Ok(where_results);
+            };
+            cache.estimates.lock().insert(clause.address(), estimate);
+            Ok(estimate)
+        }
+        .boxed()
+    }
+
+    async fn estimate_where_comparison(
+        &self,
+        direct_where_comparison: &DirectComparison,
+        universe: &OffsetIdUniverse<'_>,
+        cache: &FilterCache,
+    ) -> Result<u64, MetadataIndexError> {
+        let address = direct_where_comparison as *const DirectComparison as usize;
+        let universe_size = universe.size_estimate();
+        if let WhereComparison::Exists(exists) = direct_where_comparison.comparison {
+            let present = self.key_presence(&direct_where_comparison.key).await?;
+            let estimate = match exists {
+                true => present.len(),
+                false => universe_size.saturating_sub(present.len()),
+            };
+            cache.bitmaps.lock().insert((address, 0), present);
+            return Ok(estimate);
+        }
+        let lookups = resolve_where_comparison(&direct_where_comparison.comparison)?;
+        let mut estimates = Vec::with_capacity(lookups.operands.len());
+        for (position, operand) in lookups.operands.iter().enumerate() {
+            let estimate = match lookups.comparator {
+                WhereClauseComparator::Equal
+                | WhereClauseComparator::Contains
+                | WhereClauseComparator::NotEqual => {
+                    let equal = self
+                        .lookup(
+                            &direct_where_comparison.key,
+                            operand,
+                            &lookups.metadata_type,
+                            &WhereClauseComparator::Equal,
+                            universe,
+                        )
+                        .await?;
+                    let estimate = match lookups.comparator {
+                        WhereClauseComparator::NotEqual => {
+                            universe_size.saturating_sub(equal.len())
+                        }
+                        _ => equal.len(),
+                    };
+                    cache.bitmaps.lock().insert((address, position), equal);
+                    estimate
                 }
-            }
-            None => (),
+                _ => (universe_size as f64 * RANGE_SELECTIVITY) as u64,
+            };
+            estimates.push(estimate);
+        }
+        Ok(combine_estimates(estimates, &lookups.operator, universe))
+    }
+
+    async fn estimate_document_comparison(
+        &self,
+        direct_document_comparison: &DirectDocumentComparison,
+        universe: &OffsetIdUniverse<'_>,
+    ) -> Result<u64, MetadataIndexError> {
+        let reader = match &self.full_text_index_reader {
+            Some(reader) => reader,
+            None => return Ok(0),
         };
-        let where_document_results = match where_document_clause {
-            Some(where_document_clause) => {
-                match self
-                    .process_where_document_clause(where_document_clause)
+        let mut estimate = universe.size_estimate();
+        if direct_document_comparison.operator == WhereDocumentOperator::NotContains {
+            return Ok(estimate);
+        }
+        let pattern = DocumentPattern::new(
+            &direct_document_comparison.document,
+            &direct_document_comparison.operator,
+        )?;
+        if let Some(matches) = estimate_literal_plan(reader, pattern.literal_plan()).await? {
+            estimate = estimate.min(matches);
+        }
+        Ok(estimate)
+    }
+
+    // Evaluates the clause, reusing the bitmaps its estimate read if it was
+    // estimated. Only the candidates are checked against their documents, so
+    // the result is exact within the candidates, or the universe if there are
+    // none, and the caller intersects it with them.
+    fn evaluate<'me>(
+        &'me self,
+        clause: FilterClause<'me>,
+        candidates: Option<&'me RoaringBitmap>,
+        universe: &'me OffsetIdUniverse<'me>,
+        cache: &'me FilterCache,
+    ) -> BoxFuture<'me, Result<RoaringBitmap, MetadataIndexError>> {
+        async move {
+            match clause {
+                FilterClause::Where(Where::DirectWhereComparison(
+                    direct_where_comparison @ DirectComparison {
+                        key,
+                        comparison: WhereComparison::Exists(exists),
+                    },
+                )) => {
+                    let address = direct_where_comparison as *const DirectComparison as usize;
+                    let cached = cache.bitmaps.lock().remove(&(address, 0));
+                    let present = match cached {
+                        Some(present) => present,
+                        None => self.key_presence(key).await?,
+                    };
+                    match exists {
+                        true => Ok(present),
+                        false => Ok(universe.get().await? - present),
+                    }
+                }
+                FilterClause::Where(Where::DirectWhereComparison(direct_where_comparison)) => {
+                    let address = direct_where_comparison as *const DirectComparison as usize;
+                    let lookups = resolve_where_comparison(&direct_where_comparison.comparison)?;
+                    let mut bitmaps = Vec::with_capacity(lookups.operands.len());
+                    for (position, operand) in lookups.operands.iter().enumerate() {
+                        let cached = cache.bitmaps.lock().remove(&(address, position));
+                        let bitmap = match (cached, &lookups.comparator) {
+                            (Some(equal), WhereClauseComparator::NotEqual) => {
+                                universe.get().await? - equal
+                            }
+                            (Some(equal), _) => equal,
+                            (None, comparator) => {
+                                self.lookup(
+                                    &direct_where_comparison.key,
+                                    operand,
+                                    &lookups.metadata_type,
+                                    comparator,
+                                    universe,
+                                )
+                                .await?
+                            }
+                        };
+                        bitmaps.push(bitmap);
+                    }
+                    Ok(lookups.combine(bitmaps))
+                }
+                FilterClause::Where(Where::WhereChildren(where_children)) => {
+                    let children = where_children
+                        .children
+                        .iter()
+                        .map(FilterClause::Where)
+                        .collect();
+                    self.evaluate_children(
+                        children,
+                        &where_children.operator,
+                        candidates,
+                        universe,
+                        cache,
+                    )
                     .await
-                {
-                    Ok(results) => {
-                        tracing::info!(
-                            "Filtered {} records from metadata segment based on where document",
-                            results.len()
-                        );
-                        Some(results)
+                }
+                FilterClause::WhereDocument(WhereDocument::DirectWhereDocumentComparison(
+                    direct_document_comparison,
+                )) => match &direct_document_comparison.operator {
+                    WhereDocumentOperator::NotContains => {
+                        let pattern = DocumentPattern::new(
+                            &direct_document_comparison.document,
+                            &WhereDocumentOperator::Contains,
+                        )?;
+                        let contains = self
+                            .match_document_pattern(&pattern, candidates, universe)
+                            .await?;
+                        Ok(universe.get().await? - contains)
                     }
-                    Err(e) => {
-                        tracing::error!(
-                            "Error fetching results from metadata segment based on where clause {:?}",
-                            e
-                        );
-                        return Err(MetadataSegmentError::MetadataIndexQueryError(e));
+                    operator => {
+                        let pattern =
+                            DocumentPattern::new(&direct_document_comparison.document, operator)?;
+                        self.match_document_pattern(&pattern, candidates, universe)
+                            .await
                     }
+                },
+                FilterClause::WhereDocument(WhereDocument::WhereDocumentChildren(
+                    where_document_children,
+                )) => {
+                    let children = where_document_children
+                        .children
+                        .iter()
+                        .map(FilterClause::WhereDocument)
+                        .collect();
+                    self.evaluate_children(
+                        children,
+                        &where_document_children.operator,
+                        candidates,
+                        universe,
+                        cache,
+                    )
+                    .await
                 }
             }
-            None => {
-                tracing::info!("No where document to filter anything from metadata segment");
-                None
+        }
+        .boxed()
+    }
+
+    // The children of an AND are evaluated from the most to the least
+    // selective, and each child only checks the documents of the records that
+    // matched the children before it, so the selective clauses limit the
+    // expensive ones.
+    async fn evaluate_children<'me>(
+        &'me self,
+        children: Vec<FilterClause<'me>>,
+        operator: &BooleanOperator,
+        candidates: Option<&'me RoaringBitmap>,
+        universe: &'me OffsetIdUniverse<'me>,
+        cache: &'me FilterCache,
+    ) -> Result<RoaringBitmap, MetadataIndexError> {
+        match operator {
+            BooleanOperator::And => {
+                let mut estimated_children = Vec::with_capacity(children.len());
+                for child in children {
+                    estimated_children.push((self.estimate(child, universe, cache).await?, child));
+                }
+                estimated_children.sort_by_key(|(estimate, _)| *estimate);
+                let mut results: Option<RoaringBitmap> = None;
+                for (_, child) in estimated_children {
+                    let child_candidates = results.as_ref().or(candidates);
+                    let child_results = self
+                        .evaluate(child, child_candidates, universe, cache)
+                        .await?;
+                    let merged = match results {
+                        Some(results) => results & child_results,
+                        None => child_results,
+                    };
+                    if merged.is_empty() {
+                        return Ok(merged);
+                    }
+                    results = Some(merged);
+                }
+                Ok(results.unwrap_or_default())
             }
-        };
-        match &where_document_results {
-            Some(results) => {
-                if results.is_empty() {
-                    return Ok(where_document_results);
+            BooleanOperator::Or => {
+                let mut results = RoaringBitmap::new();
+                for child in children {
+                    results |= self.evaluate(child, candidates, universe, cache).await?;
                 }
+                Ok(results)
             }
-            None => (),
-        };
+        }
+    }
 
-        if where_results.is_none() && where_document_results.is_none() {
-            return Ok(None);
-        } else if where_results.is_none() && where_document_results.is_some() {
-            return Ok(where_document_results);
-        } else if where_results.is_some() && where_document_results.is_none() {
-            return Ok(where_results);
-        } else {
-            return Ok(Some(merge_sorted_vecs_conjunction(
-                &where_results.expect("Checked just now that it is not none"),
-                &where_document_results.expect("Checked just now that it is not none"),
-            )));
+    // The offset ids of the records that have the key. Segments written before
+    // keys were tracked have no presence index until their next compaction,
+    // their value indices hold the same offset ids.
+    async fn key_presence(&self, metadata_key: &str) -> Result<RoaringBitmap, MetadataIndexError> {
+        if let Some(reader) = &self.key_presence_index_reader {
+            return reader.get(metadata_key, &true.into()).await;
+        }
+        let readers = [
+            &self.bool_metadata_index_reader,
+            &self.i64_metadata_index_reader,
+            &self.f32_metadata_index_reader,
+            &self.string_metadata_index_reader,
+        ];
+        let mut present = RoaringBitmap::new();
+        for reader in readers.into_iter().flatten() {
+            let mut values = reader.sorted_values(metadata_key, false).await?;
+            while let Some((_, offset_ids)) = values.next().await? {
+                present |= offset_ids;
+            }
         }
+        Ok(present)
     }
 
-    fn process_where_clause<'me>(
-        &'me self,
-        where_clause: &'me Where,
-    ) -> BoxFuture<Result<Vec<usize>, MetadataIndexError>> {
-        async move {
-            let mut results = vec![];
-            match where_clause {
-                Where::DirectWhereComparison(direct_where_comparison) => {
-                    match &direct_where_comparison.comparison {
-                        WhereComparison::SingleStringComparison(operand, comparator) => {
-                            match comparator {
-                                WhereClauseComparator::Equal => {
-                                    let metadata_value_keywrapper = operand.as_str().try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.string_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .get(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting string to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::NotEqual => {
-                                    todo!();
-                                }
-                                // We don't allow these comparators for strings.
-                                WhereClauseComparator::LessThan => {
-                                    unimplemented!();
-                                }
-                                WhereClauseComparator::LessThanOrEqual => {
-                                    unimplemented!();
-                                }
-                                WhereClauseComparator::GreaterThan => {
-                                    unimplemented!();
-                                }
-                                WhereClauseComparator::GreaterThanOrEqual => {
-                                    unimplemented!();
-                                }
-                            }
-                        }
-                        WhereComparison::SingleBoolComparison(operand, comparator) => {
-                            match comparator {
-                                WhereClauseComparator::Equal => {
-                                    let metadata_value_keywrapper = (*operand).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.bool_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .get(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting bool to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::NotEqual => {
-                                    todo!();
-                                }
-                                // We don't allow these comparators for bools.
-                                WhereClauseComparator::LessThan => {
-                                    unimplemented!();
-                                }
-                                WhereClauseComparator::LessThanOrEqual => {
-                                    unimplemented!();
-                                }
-                                WhereClauseComparator::GreaterThan => {
-                                    unimplemented!();
-                                }
-                                WhereClauseComparator::GreaterThanOrEqual => {
-                                    unimplemented!();
-                                }
-                            }
-                        }
-                        WhereComparison::SingleIntComparison(operand, comparator) => {
-                            match comparator {
-                                WhereClauseComparator::Equal => {
-                                    let metadata_value_keywrapper = (*operand).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.u32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .get(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting int to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::NotEqual => {
-                                    todo!();
-                                }
-                                WhereClauseComparator::LessThan => {
-                                    let metadata_value_keywrapper = (*operand).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.u32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .lt(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting int to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::LessThanOrEqual => {
-                                    let metadata_value_keywrapper = (*operand).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.u32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .lte(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting int to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::GreaterThan => {
-                                    let metadata_value_keywrapper = (*operand).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.u32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .gt(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting int to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::GreaterThanOrEqual => {
-                                    let metadata_value_keywrapper = (*operand).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.u32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .gte(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting int to keywrapper")
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        WhereComparison::SingleDoubleComparison(operand, comparator) => {
-                            match comparator {
-                                WhereClauseComparator::Equal => {
-                                    let metadata_value_keywrapper = (*operand as f32).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.f32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .get(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting double to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::NotEqual => {
-                                    todo!();
-                                }
-                                WhereClauseComparator::LessThan => {
-                                    let metadata_value_keywrapper = (*operand as f32).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.f32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .lt(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting double to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::LessThanOrEqual => {
-                                    let metadata_value_keywrapper = (*operand as f32).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.f32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .lte(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting double to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::GreaterThan => {
-                                    let metadata_value_keywrapper = (*operand as f32).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.f32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .gt(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting double to keywrapper")
-                                        }
-                                    }
-                                }
-                                WhereClauseComparator::GreaterThanOrEqual => {
-                                    let metadata_value_keywrapper = (*operand as f32).try_into();
-                                    match metadata_value_keywrapper {
-                                        Ok(keywrapper) => {
-                                            match &self.f32_metadata_index_reader {
-                                                Some(reader) => {
-                                                    let result = reader
-                                                        .gte(
-                                                            &direct_where_comparison.key,
-                                                            &keywrapper,
-                                                        )
-                                                        .await;
-                                                    match result {
-                                                        Ok(r) => {
-                                                            results = r
-                                                                .iter()
-                                                                .map(|x| x as usize)
-                                                                .collect();
-                                                        }
-                                                        Err(e) => {
-                                                            return Err(e);
-                                                        }
-                                                    }
-                                                }
-                                                // This is expected. Before the first ever compaction
-                                                // the reader will be uninitialized, hence an empty vector
-                                                // here since nothing has been written to storage yet.
-                                                None => results = vec![],
-                                            }
-                                        }
-                                        Err(_) => {
-                                            panic!("Error converting double to keywrapper")
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        WhereComparison::StringListComparison(operand, list_operator) => {
-                            todo!();
-                        }
-                        WhereComparison::IntListComparison(..) => {
-                            todo!();
-                        }
-                        WhereComparison::DoubleListComparison(..) => {
-                            todo!();
+    // Looks up the offset ids whose value for the key satisfies the comparator.
+    // Negations are answered from the universe of offset ids, so records that
+    // do not have the key at all satisfy them.
+    async fn lookup(
+        &self,
+        metadata_key: &str,
+        metadata_value: &KeyWrapper,
+        metadata_type: &MetadataType,
+        comparator: &WhereClauseComparator,
+        universe: &OffsetIdUniverse<'_>,
+    ) -> Result<RoaringBitmap, MetadataIndexError> {
+        let reader = match metadata_type {
+            MetadataType::StringType => &self.string_metadata_index_reader,
+            MetadataType::IntType => &self.i64_metadata_index_reader,
+            MetadataType::DoubleType => &self.f32_metadata_index_reader,
+            MetadataType::BoolType => &self.bool_metadata_index_reader,
+            _ => {
+                return Err(MetadataIndexError::UnsupportedComparator(
+                    comparator.clone(),
+                    metadata_type.clone(),
+                ))
+            }
+        };
+        let reader = match reader {
+            Some(reader) => reader,
+            // This is expected. Before the first ever compaction the reader
+            // will be uninitialized since nothing has been written to storage
+            // yet, hence no record has any value.
+            None => {
+                return match comparator {
+                    WhereClauseComparator::NotEqual => Ok(universe.get().await?.clone()),
+                    _ => Ok(RoaringBitmap::new()),
+                }
+            }
+        };
+        match comparator {
+            WhereClauseComparator::Equal | WhereClauseComparator::Contains => {
+                reader.get(metadata_key, metadata_value).await
+            }
+            WhereClauseComparator::NotEqual => {
+                let equal = reader.get(metadata_key, metadata_value).await?;
+                Ok(universe.get().await? - equal)
+            }
+            WhereClauseComparator::LessThan => reader.lt(metadata_key, metadata_value).await,
+            WhereClauseComparator::LessThanOrEqual => {
+                reader.lte(metadata_key, metadata_value).await
+            }
+            WhereClauseComparator::GreaterThan => reader.gt(metadata_key, metadata_value).await,
+            WhereClauseComparator::GreaterThanOrEqual => {
+                reader.gte(metadata_key, metadata_value).await
+            }
+        }
+    }
+
+    // Narrows the candidates down to the documents that hold the literals of
+    // the pattern, then verifies them against the record segment. Literals
+    // shorter than an n-gram can't be looked up so the candidates are the
+    // whole universe if the plan has nothing else to look up.
+    async fn match_document_pattern(
+        &self,
+        pattern: &DocumentPattern,
+        candidates: Option<&RoaringBitmap>,
+        universe: &OffsetIdUniverse<'_>,
+    ) -> Result<RoaringBitmap, MetadataIndexError> {
+        let reader = match &self.full_text_index_reader {
+            Some(reader) => reader,
+            // This is expected. Before the first ever compaction
+            // the reader will be uninitialized, hence an empty bitmap
+            // here since nothing has been written to storage yet.
+            None => return Ok(RoaringBitmap::new()),
+        };
+        if let Some(text_query) = pattern.text_query() {
+            return self
+                .search_text_query(reader, text_query, candidates, universe)
+                .await;
+        }
+        let literal_candidates = search_literal_plan(reader, pattern.literal_plan()).await?;
+        let candidates = match (literal_candidates, candidates) {
+            // A phrase search for the whole literal is already exact.
+            (Some(literal_candidates), _) if pattern.is_literal() => return Ok(literal_candidates),
+            (Some(literal_candidates), Some(candidates)) => literal_candidates & candidates,
+            (Some(literal_candidates), None) => literal_candidates,
+            (None, Some(candidates)) => candidates.clone(),
+            (None, None) => universe.get().await?.clone(),
+        };
+        let record_segment_reader = match universe.record_segment_reader {
+            Some(reader) => reader,
+            None => return Ok(RoaringBitmap::new()),
+        };
+        let mut results = RoaringBitmap::new();
+        for offset_id in candidates {
+            let record = record_segment_reader
+                .get_data_for_offset_id(offset_id)
+                .await?;
+            if record
+                .document
+                .is_some_and(|document| pattern.is_match(document))
+            {
+                results.insert(offset_id);
+            }
+        }
+        Ok(results)
+    }
+
+    // Evaluates a text query from the positions of its terms in the index and
+    // the documents that have the text of every term. Like `evaluate`, only the
+    // documents of the candidates are read.
+    fn search_text_query<'me>(
+        &'me self,
+        reader: &'me FullTextIndexReader<'me>,
+        text_query: &'me TextQuery,
+        candidates: Option<&'me RoaringBitmap>,
+        universe: &'me OffsetIdUniverse<'me>,
+    ) -> BoxFuture<'me, Result<RoaringBitmap, MetadataIndexError>> {
+        async move {
+            match text_query {
+                TextQuery::Phrase(phrase) => {
+                    let mut positions = Vec::new();
+                    for text in phrase.lookups() {
+                        let text_positions = self
+                            .text_positions(reader, &text, candidates, universe)
+                            .await?;
+                        if text_positions.is_empty() {
+                            return Ok(RoaringBitmap::new());
                         }
-                        WhereComparison::BoolListComparison(..) => {
-                            todo!();
+                        positions.push(text_positions);
+                    }
+                    // The boundaries of the words, and the words between them
+                    // for proximity, are checked in the documents that have
+                    // the text of every lookup.
+                    let record_segment_reader = match universe.record_segment_reader {
+                        Some(reader) => reader,
+                        None => return Ok(RoaringBitmap::new()),
+                    };
+                    let mut offset_ids = phrase_candidates(&positions);
+                    if let Some(candidates) = candidates {
+                        offset_ids &= candidates;
+                    }
+                    let mut documents = HashMap::new();
+                    for offset_id in offset_ids {
+                        let record = record_segment_reader
+                            .get_data_for_offset_id(offset_id)
+                            .await?;
+                        if let Some(document) = record.document {
+                            documents.insert(offset_id, document);
                         }
                     }
+                    Ok(phrase_matches(phrase, &positions, &documents))
                 }
-                Where::WhereChildren(where_children) => {
-                    let mut first_iteration = true;
-                    for child in where_children.children.iter() {
-                        let child_results: Vec<usize> =
-                            match self.process_where_clause(&child).await {
-                                Ok(result) => result,
-                                Err(_) => vec![],
-                            };
-                        if first_iteration {
-                            results = child_results;
-                            first_iteration = false;
-                        } else {
-                            match where_children.operator {
-                                BooleanOperator::And => {
-                                    results =
-                                        merge_sorted_vecs_conjunction(&results, &child_results);
-                                }
-                                BooleanOperator::Or => {
-                                    results =
-                                        merge_sorted_vecs_disjunction(&results, &child_results);
-                                }
-                            }
+                TextQuery::And(children) => {
+                    let mut results: Option<RoaringBitmap> = None;
+                    for child in children {
+                        let child_candidates = results.as_ref().or(candidates);
+                        let child_results = self
+                            .search_text_query(reader, child, child_candidates, universe)
+                            .await?;
+                        let child_results = match results {
+                            Some(results) => results & child_results,
+                            None => child_results,
+                        };
+                        if child_results.is_empty() {
+                            return Ok(child_results);
                         }
+                        results = Some(child_results);
                     }
+                    Ok(results.unwrap_or_default())
+                }
+                TextQuery::Or(children) => {
+                    let mut results = RoaringBitmap::new();
+                    for child in children {
+                        results |= self
+                            .search_text_query(reader, child, candidates, universe)
+                            .await?;
+                    }
+                    Ok(results)
+                }
+                TextQuery::Not(child) => {
+                    let excluded = self
+                        .search_text_query(reader, child, candidates, universe)
+                        .await?;
+                    Ok(universe.get().await? - excluded)
                 }
             }
-            return Ok(results);
         }
         .boxed()
     }
 
-    fn process_where_document_clause<'me>(
-        &'me self,
-        where_document_clause: &'me WhereDocument,
-    ) -> BoxFuture<Result<Vec<usize>, MetadataIndexError>> {
-        async move {
-            let mut results = vec![];
-            match where_document_clause {
-                WhereDocument::DirectWhereDocumentComparison(direct_document_comparison) => {
-                    match &direct_document_comparison.operator {
-                        WhereDocumentOperator::Contains => {
-                            match &self.full_text_index_reader {
-                                Some(reader) => {
-                                    let result =
-                                        reader.search(&direct_document_comparison.document).await;
-                                    match result {
-                                        Ok(r) => {
-                                            results = r.iter().map(|x| *x as usize).collect();
-                                        }
-                                        Err(e) => {
-                                            return Err(MetadataIndexError::FullTextError(e));
-                                        }
-                                    }
-                                }
-                                // This is expected. Before the first ever compaction
-                                // the reader will be uninitialized, hence an empty vector
-                                // here since nothing has been written to storage yet.
-                                None => results = vec![],
-                            }
-                        }
-                        WhereDocumentOperator::NotContains => {
-                            todo!();
-                        }
+    // The sorted byte offsets of the text in each document that contains it.
+    // Text shorter than an n-gram can't be looked up, so the documents of the
+    // candidates, or the universe, are scanned for it.
+    async fn text_positions(
+        &self,
+        reader: &FullTextIndexReader<'_>,
+        text: &str,
+        candidates: Option<&RoaringBitmap>,
+        universe: &OffsetIdUniverse<'_>,
+    ) -> Result<HashMap<u32, Vec<u32>>, MetadataIndexError> {
+        if !reader.encode_tokens(text).get_tokens().is_empty() {
+            return Ok(reader.search_positions(text, None).await?);
+        }
+        let record_segment_reader = match universe.record_segment_reader {
+            Some(reader) => reader,
+            None => return Ok(HashMap::new()),
+        };
+        let candidates = match candidates {
+            Some(candidates) => candidates,
+            None => universe.get().await?,
+        };
+        let mut positions = HashMap::new();
+        for offset_id in candidates {
+            let record = record_segment_reader
+                .get_data_for_offset_id(offset_id)
+                .await?;
+            if let Some(document) = record.document {
+                let document_positions = positions_of(document, text);
+                if !document_positions.is_empty() {
+                    positions.insert(offset_id, document_positions);
+                }
+            }
+        }
+        Ok(positions)
+    }
+}
+
+// The documents that can match a literal plan, None if any document can.
+fn search_literal_plan<'me>(
+    reader: &'me FullTextIndexReader<'me>,
+    literal_plan: &'me LiteralPlan,
+) -> BoxFuture<'me, Result<Option<RoaringBitmap>, MetadataIndexError>> {
+    async move {
+        match literal_plan {
+            LiteralPlan::All => Ok(None),
+            LiteralPlan::Literal(literal) => {
+                if reader.encode_tokens(literal).get_tokens().is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(
+                    reader
+                        .search(literal)
+                        .await?
+                        .into_iter()
+                        .map(|offset_id| offset_id as u32)
+                        .collect(),
+                ))
+            }
+            LiteralPlan::And(children) => {
+                let mut candidates: Option<RoaringBitmap> = None;
+                for child in children {
+                    if let Some(matches) = search_literal_plan(reader, child).await? {
+                        candidates = Some(match candidates {
+                            Some(candidates) => candidates & matches,
+                            None => matches,
+                        });
                     }
                 }
-                WhereDocument::WhereDocumentChildren(where_document_children) => {
-                    let mut first_iteration = true;
-                    for child in where_document_children.children.iter() {
-                        let child_results: Vec<usize> =
-                            match self.process_where_document_clause(&child).await {
-                                Ok(result) => result,
-                                Err(_) => vec![],
-                            };
-                        if first_iteration {
-                            results = child_results;
-                            first_iteration = false;
-                        } else {
-                            match where_document_children.operator {
-                                BooleanOperator::And => {
-                                    results =
-                                        merge_sorted_vecs_conjunction(&results, &child_results);
-                                }
-                                BooleanOperator::Or => {
-                                    results =
-                                        merge_sorted_vecs_disjunction(&results, &child_results);
-                                }
-                            }
-                        }
+                Ok(candidates)
+            }
+            LiteralPlan::Or(children) => {
+                let mut candidates = RoaringBitmap::new();
+                for child in children {
+                    match search_literal_plan(reader, child).await? {
+                        Some(matches) => candidates |= matches,
+                        None => return Ok(None),
                     }
                 }
+                Ok(Some(candidates))
+            }
+            LiteralPlan::Fuzzy(query, max_edits) => {
+                Ok(reader.fuzzy_candidates(query, *max_edits).await?)
+            }
+        }
+    }
+    .boxed()
+}
+
+// An upper bound on the number of documents that can match a literal plan,
+// None if any document can.
+fn estimate_literal_plan<'me>(
+    reader: &'me FullTextIndexReader<'me>,
+    literal_plan: &'me LiteralPlan,
+) -> BoxFuture<'me, Result<Option<u64>, MetadataIndexError>> {
+    async move {
+        match literal_plan {
+            LiteralPlan::All => Ok(None),
+            LiteralPlan::Literal(literal) => Ok(reader
+                .estimate_matches(literal)
+                .await?
+                .map(|matches| matches as u64)),
+            LiteralPlan::And(children) => {
+                let mut estimate: Option<u64> = None;
+                for child in children {
+                    if let Some(matches) = estimate_literal_plan(reader, child).await? {
+                        estimate = Some(estimate.map_or(matches, |estimate| estimate.min(matches)));
+                    }
+                }
+                Ok(estimate)
+            }
+            LiteralPlan::Or(children) => {
+                let mut estimate = 0;
+                for child in children {
+                    match estimate_literal_plan(reader, child).await? {
+                        Some(matches) => estimate += matches,
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(estimate))
+            }
+            // Counting shared n-grams reads the posting lists, which is the
+            // cost the estimate is meant to avoid.
+            LiteralPlan::Fuzzy(..) => Ok(None),
+        }
+    }
+    .boxed()
+}
+
+// A clause the planner estimates and evaluates.
+#[derive(Clone, Copy)]
+enum FilterClause<'me> {
+    Where(&'me Where),
+    WhereDocument(&'me WhereDocument),
+}
+
+impl FilterClause<'_> {
+    // Clauses are borrowed for the whole query, so their addresses identify them.
+    fn address(&self) -> usize {
+        match self {
+            FilterClause::Where(where_clause) => *where_clause as *const Where as usize,
+            FilterClause::WhereDocument(where_document_clause) => {
+                *where_document_clause as *const WhereDocument as usize
+            }
+        }
+    }
+}
+
+// What the planner learned about the clauses of a query: the estimates, so
+// nested clauses are estimated once, and the bitmaps the estimates read, keyed
+// by the address of the comparison and the position of the operand, so
+// evaluating a clause doesn't read them again.
+#[derive(Default)]
+struct FilterCache {
+    estimates: parking_lot::Mutex<HashMap<usize, u64>>,
+    bitmaps: parking_lot::Mutex<HashMap<(usize, usize), RoaringBitmap>>,
+}
+
+// Range comparisons are assumed to match a third of the records, since the
+// metadata index keeps no histograms to estimate them from.
+const RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+fn combine_estimates(
+    estimates: Vec<u64>,
+    operator: &BooleanOperator,
+    universe: &OffsetIdUniverse<'_>,
+) -> u64 {
+    match operator {
+        BooleanOperator::And => estimates.into_iter().min().unwrap_or(0),
+        BooleanOperator::Or => estimates
+            .into_iter()
+            .sum::<u64>()
+            .min(universe.size_estimate()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockstore::arrow::provider::ArrowBlockfileProvider;
+    use crate::execution::data::data_chunk::Chunk;
+    use crate::segment::record_segment::RecordSegmentWriter;
+    use crate::segment::LogMaterializer;
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::types::{
+        LogRecord, OperationRecord, SegmentScope, UpdateMetadataValue, WhereChildren,
+        WhereComparison,
+    };
+
+    #[tokio::test]
+    async fn test_legacy_u32_metadata_migration() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+
+        // Legacy segments stored i32 values cast to u32
+        let legacy_writer = blockfile_provider.create::<u32, &RoaringBitmap>().unwrap();
+        let legacy_id = legacy_writer.id();
+        legacy_writer
+            .set("size", -5i32 as u32, &RoaringBitmap::from_iter([1u32, 2]))
+            .await
+            .unwrap();
+        legacy_writer
+            .set("size", 7u32, &RoaringBitmap::from_iter([3u32]))
+            .await
+            .unwrap();
+        legacy_writer
+            .commit::<u32, &RoaringBitmap>()
+            .unwrap()
+            .flush::<u32, &RoaringBitmap>()
+            .await
+            .unwrap();
+
+        let segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileMetadata,
+            scope: SegmentScope::METADATA,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::from([(
+                LEGACY_U32_METADATA.to_string(),
+                vec![legacy_id.to_string()],
+            )]),
+        };
+        let mut writer = MetadataSegmentWriter::from_segment(&segment, &blockfile_provider)
+            .await
+            .unwrap();
+        writer.write_to_blockfiles().await.unwrap();
+        let file_path = writer.commit().unwrap().flush().await.unwrap();
+        assert!(!file_path.contains_key(LEGACY_U32_METADATA));
+
+        let i64_metadata_id = Uuid::parse_str(&file_path[I64_METADATA][0]).unwrap();
+        let reader = MetadataIndexReader::new_i64(
+            blockfile_provider
+                .open::<i64, RoaringBitmap>(&i64_metadata_id)
+                .await
+                .unwrap(),
+        );
+        let bitmap = reader.lt("size", &0i64.into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![1, 2]);
+        let bitmap = reader.get("size", &7i64.into()).await.unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<u32>>(), vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_u32_metadata_reader() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+
+        let legacy_writer = blockfile_provider.create::<u32, &RoaringBitmap>().unwrap();
+        let legacy_id = legacy_writer.id();
+        for (value, offset_ids) in [(-5i32, vec![1u32, 2]), (7, vec![3]), (20, vec![4])] {
+            legacy_writer
+                .set("size", value as u32, &RoaringBitmap::from_iter(offset_ids))
+                .await
+                .unwrap();
+        }
+        legacy_writer
+            .commit::<u32, &RoaringBitmap>()
+            .unwrap()
+            .flush::<u32, &RoaringBitmap>()
+            .await
+            .unwrap();
+
+        // The segment has not been compacted since, so it is read as is
+        let segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileMetadata,
+            scope: SegmentScope::METADATA,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::from([(
+                LEGACY_U32_METADATA.to_string(),
+                vec![legacy_id.to_string()],
+            )]),
+        };
+        let reader = MetadataSegmentReader::from_segment(&segment, &blockfile_provider)
+            .await
+            .unwrap();
+        let size = |value: i64, comparator| {
+            Where::DirectWhereComparison(DirectComparison {
+                key: String::from("size"),
+                comparison: WhereComparison::SingleIntComparison(value, comparator),
+            })
+        };
+        let cases = vec![
+            (size(-5, WhereClauseComparator::Equal), vec![1, 2]),
+            (size(7, WhereClauseComparator::Equal), vec![3]),
+            (size(1 << 40, WhereClauseComparator::Equal), vec![]),
+            (size(0, WhereClauseComparator::LessThan), vec![1, 2]),
+            (
+                size(7, WhereClauseComparator::LessThanOrEqual),
+                vec![1, 2, 3],
+            ),
+            (size(7, WhereClauseComparator::GreaterThan), vec![4]),
+            (
+                size(-5, WhereClauseComparator::GreaterThanOrEqual),
+                vec![1, 2, 3, 4],
+            ),
+        ];
+        for (where_clause, expected) in cases {
+            assert_eq!(
+                reader.query(Some(&where_clause), None, None).await.unwrap(),
+                Some(expected)
+            );
+        }
+        for descending in [false, true] {
+            let mut sorted_values = reader
+                .sorted_values(&OrderBy {
+                    key: "size".to_string(),
+                    descending,
+                })
+                .await
+                .unwrap();
+            let mut values = vec![];
+            while let Some((value, _)) = sorted_values.next().await.unwrap() {
+                values.push(value);
+            }
+            let mut expected = vec![
+                MetadataValue::Int(-5),
+                MetadataValue::Int(7),
+                MetadataValue::Int(20),
+            ];
+            if descending {
+                expected.reverse();
+            }
+            assert_eq!(values, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_planner_estimates() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileMetadata,
+            scope: SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let data: Vec<LogRecord> = (1..=5)
+            .map(|i| LogRecord {
+                log_offset: i,
+                record: OperationRecord {
+                    id: format!("embedding_id_{}", i),
+                    embedding: Some(vec![1.0, 2.0, 3.0]),
+                    encoding: None,
+                    sparse_vector: None,
+                    metadata: Some(HashMap::from([
+                        (
+                            String::from("color"),
+                            UpdateMetadataValue::Str(String::from(if i < 5 {
+                                "red"
+                            } else {
+                                "blue"
+                            })),
+                        ),
+                        (String::from("size"), UpdateMetadataValue::Int(i)),
+                    ])),
+                    document: Some(String::from(if i < 5 {
+                        "some common words"
+                    } else {
+                        "a rare zebra"
+                    })),
+                    operation: Operation::Add,
+                },
+            })
+            .collect();
+        let record_writer = RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+            .await
+            .unwrap();
+        let mut metadata_writer =
+            MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                .await
+                .unwrap();
+        let materializer = LogMaterializer::new(None, Chunk::new(data.into()), None);
+        let mat_records = materializer.materialize().await.unwrap();
+        metadata_writer
+            .apply_materialized_log_chunk(mat_records.clone())
+            .await
+            .unwrap();
+        metadata_writer.write_to_blockfiles().await.unwrap();
+        record_writer
+            .apply_materialized_log_chunk(mat_records)
+            .await
+            .unwrap();
+        record_segment.file_path = record_writer.commit().unwrap().flush().await.unwrap();
+        metadata_segment.file_path = metadata_writer.commit().unwrap().flush().await.unwrap();
+
+        let record_segment_reader =
+            RecordSegmentReader::from_segment(&record_segment, &blockfile_provider)
+                .await
+                .unwrap();
+        let reader = MetadataSegmentReader::from_segment(&metadata_segment, &blockfile_provider)
+            .await
+            .unwrap();
+        let universe = OffsetIdUniverse::new(Some(&record_segment_reader));
+        assert_eq!(universe.size_estimate(), 5);
+
+        let color = |value: &str, comparator| {
+            Where::DirectWhereComparison(DirectComparison {
+                key: String::from("color"),
+                comparison: WhereComparison::SingleStringComparison(value.to_string(), comparator),
+            })
+        };
+        let size = |value: i64, comparator| {
+            Where::DirectWhereComparison(DirectComparison {
+                key: String::from("size"),
+                comparison: WhereComparison::SingleIntComparison(value, comparator),
+            })
+        };
+        let contains = |document: &str| {
+            WhereDocument::DirectWhereDocumentComparison(DirectDocumentComparison {
+                document: document.to_string(),
+                operator: WhereDocumentOperator::Contains,
+            })
+        };
+        let red = color("red", WhereClauseComparator::Equal);
+        let blue = color("blue", WhereClauseComparator::Equal);
+        let not_red = color("red", WhereClauseComparator::NotEqual);
+        let large = size(3, WhereClauseComparator::GreaterThanOrEqual);
+        let either = Where::WhereChildren(WhereChildren {
+            children: vec![blue.clone(), size(1, WhereClauseComparator::Equal)],
+            operator: BooleanOperator::Or,
+        });
+        let both = Where::WhereChildren(WhereChildren {
+            children: vec![red.clone(), large.clone()],
+            operator: BooleanOperator::And,
+        });
+        let zebra = contains("zebra");
+        let words = contains("words");
+        let cases = vec![
+            (FilterClause::Where(&red), 4),
+            (FilterClause::Where(&blue), 1),
+            (FilterClause::Where(&not_red), 1),
+            (FilterClause::Where(&large), 1),
+            (FilterClause::Where(&either), 2),
+            (FilterClause::Where(&both), 1),
+            (FilterClause::WhereDocument(&zebra), 1),
+            (FilterClause::WhereDocument(&words), 4),
+        ];
+        for (clause, expected) in cases {
+            let cache = FilterCache::default();
+            assert_eq!(
+                reader.estimate(clause, &universe, &cache).await.unwrap(),
+                expected
+            );
+        }
+
+        // Evaluating reuses the bitmaps the estimates read and nested clauses
+        // are estimated once.
+        let cache = FilterCache::default();
+        let both_clause = FilterClause::Where(&both);
+        reader
+            .estimate(both_clause, &universe, &cache)
+            .await
+            .unwrap();
+        assert_eq!(cache.estimates.lock().len(), 3);
+        assert_eq!(cache.bitmaps.lock().len(), 1);
+        let results = reader
+            .evaluate(both_clause, None, &universe, &cache)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(cache.bitmaps.lock().is_empty());
+        assert_eq!(cache.estimates.lock().len(), 3);
+
+        // Document patterns only check the documents of the candidates.
+        let candidates = RoaringBitmap::from_iter([1, 5]);
+        for operator in [
+            WhereDocumentOperator::Regex,
+            WhereDocumentOperator::Search,
+            WhereDocumentOperator::Fuzzy(1),
+        ] {
+            let pattern = WhereDocument::DirectWhereDocumentComparison(DirectDocumentComparison {
+                document: String::from("words"),
+                operator: operator.clone(),
+            });
+            let clause = FilterClause::WhereDocument(&pattern);
+            let cache = FilterCache::default();
+            let results = reader
+                .evaluate(clause, None, &universe, &cache)
+                .await
+                .unwrap();
+            assert_eq!(results, RoaringBitmap::from_iter(1..=4), "{:?}", operator);
+            let results = reader
+                .evaluate(clause, Some(&candidates), &universe, &cache)
+                .await
+                .unwrap();
+            assert_eq!(results, RoaringBitmap::from_iter([1]), "{:?}", operator);
+        }
+
+        let query = |where_clause, where_document_clause| {
+            reader.query(
+                where_clause,
+                where_document_clause,
+                Some(&record_segment_reader),
+            )
+        };
+        assert_eq!(
+            query(Some(&both), Some(&words)).await.unwrap(),
+            Some(vec![3, 4])
+        );
+        assert_eq!(query(Some(&red), Some(&zebra)).await.unwrap(), Some(vec![]));
+        assert_eq!(query(Some(&either), None).await.unwrap(), Some(vec![1, 5]));
+        assert_eq!(query(None, None).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_key_presence() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider =
+            BlockfileProvider::ArrowBlockfileProvider(ArrowBlockfileProvider::new(storage));
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut metadata_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileMetadata,
+            scope: SegmentScope::METADATA,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let log_record =
+            |log_offset, id, author: Option<UpdateMetadataValue>, operation| LogRecord {
+                log_offset,
+                record: OperationRecord {
+                    id: format!("embedding_id_{}", id),
+                    embedding: Some(vec![1.0, 2.0, 3.0]),
+                    encoding: None,
+                    sparse_vector: None,
+                    metadata: author
+                        .map(|author| HashMap::from([(String::from("author"), author)])),
+                    document: Some(String::from("some document")),
+                    operation,
+                },
+            };
+        let exists = |exists| {
+            Where::DirectWhereComparison(DirectComparison {
+                key: String::from("author"),
+                comparison: WhereComparison::Exists(exists),
+            })
+        };
+        let has_author = exists(true);
+        let no_author = exists(false);
+
+        // Records 1 and 3 have an author, of different types.
+        let first_batch = vec![
+            log_record(
+                1,
+                1,
+                Some(UpdateMetadataValue::Str(String::from("ann"))),
+                Operation::Add,
+            ),
+            log_record(2, 2, None, Operation::Add),
+            log_record(3, 3, Some(UpdateMetadataValue::Int(7)), Operation::Add),
+            log_record(4, 4, None, Operation::Add),
+        ];
+        // Record 1 loses its author, record 2 gains one and record 3 is deleted.
+        let second_batch = vec![
+            log_record(5, 1, Some(UpdateMetadataValue::None), Operation::Update),
+            log_record(
+                6,
+                2,
+                Some(UpdateMetadataValue::StrList(vec![String::from("bob")])),
+                Operation::Update,
+            ),
+            log_record(7, 3, None, Operation::Delete),
+        ];
+        for (batch, log) in [first_batch, second_batch].into_iter().enumerate() {
+            let record_segment_reader =
+                match RecordSegmentReader::from_segment(&record_segment, &blockfile_provider).await
+                {
+                    Ok(reader) => Some(reader),
+                    Err(_) => None,
+                };
+            let record_writer =
+                RecordSegmentWriter::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .unwrap();
+            let mut metadata_writer =
+                MetadataSegmentWriter::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .unwrap();
+            let materializer =
+                LogMaterializer::new(record_segment_reader, Chunk::new(log.into()), None);
+            let mat_records = materializer.materialize().await.unwrap();
+            metadata_writer
+                .apply_materialized_log_chunk(mat_records.clone())
+                .await
+                .unwrap();
+            metadata_writer.write_to_blockfiles().await.unwrap();
+            record_writer
+                .apply_materialized_log_chunk(mat_records)
+                .await
+                .unwrap();
+            record_segment.file_path = record_writer.commit().unwrap().flush().await.unwrap();
+            metadata_segment.file_path = metadata_writer.commit().unwrap().flush().await.unwrap();
+
+            let record_segment_reader =
+                RecordSegmentReader::from_segment(&record_segment, &blockfile_provider)
+                    .await
+                    .unwrap();
+            let reader =
+                MetadataSegmentReader::from_segment(&metadata_segment, &blockfile_provider)
+                    .await
+                    .unwrap();
+            let query =
+                |where_clause| reader.query(Some(where_clause), None, Some(&record_segment_reader));
+            let (present, missing) = match batch {
+                0 => (vec![1, 3], vec![2, 4]),
+                _ => (vec![2], vec![1, 4]),
+            };
+            assert_eq!(query(&has_author).await.unwrap(), Some(present));
+            assert_eq!(query(&no_author).await.unwrap(), Some(missing));
+            let universe = OffsetIdUniverse::new(Some(&record_segment_reader));
+            let estimate = reader
+                .estimate(
+                    FilterClause::Where(&has_author),
+                    &universe,
+                    &FilterCache::default(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(estimate, if batch == 0 { 2 } else { 1 });
+
+            // Segments written before keys were tracked answer from their
+            // value indices and are backfilled on the next compaction.
+            if batch == 0 {
+                metadata_segment.file_path.remove(KEY_PRESENCE);
+                let reader =
+                    MetadataSegmentReader::from_segment(&metadata_segment, &blockfile_provider)
+                        .await
+                        .unwrap();
+                assert_eq!(
+                    reader
+                        .query(Some(&has_author), None, Some(&record_segment_reader))
+                        .await
+                        .unwrap(),
+                    Some(vec![1, 3])
+                );
             }
-            results.sort();
-            return Ok(results);
         }
-        .boxed()
     }
 }
@@ -1,7 +1,9 @@
 pub(crate) mod config;
 pub(crate) mod distributed_hnsw_segment;
+pub(crate) mod ivf_segment;
 pub(crate) mod metadata_segment;
 pub(crate) mod record_segment;
+pub(crate) mod sparse_vector_segment;
 pub(crate) mod types;
 
 pub(crate) use types::*;
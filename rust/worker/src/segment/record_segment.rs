@@ -6,6 +6,8 @@ use crate::errors::{ChromaError, ErrorCodes};
 use crate::execution::data::data_chunk::Chunk;
 use crate::types::{Operation, Segment, SegmentType};
 use async_trait::async_trait;
+use roaring::RoaringBitmap;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::{self, Debug, Formatter};
 use std::sync::atomic::AtomicU32;
@@ -75,7 +77,8 @@ impl RecordSegmentWriter {
         // Time to create a data record now.
         let data_record = DataRecord {
             id: user_id,
-            embedding: updated_embeddings,
+            embedding: Cow::Borrowed(updated_embeddings),
+            encoding: mat_record.merged_encoding(),
             metadata: final_metadata_opt,
             document: updated_document,
         };
@@ -293,8 +296,12 @@ pub enum ApplyMaterializedLogError {
     BlockfileDeleteError,
     #[error("Error updating blockfile")]
     BlockfileUpdateError,
+    #[error("Error reading from blockfile")]
+    BlockfileReadError,
     #[error("Embedding not set in the user write")]
     EmbeddingNotSet,
+    #[error("Multi-vector of {0} values is not a whole number of vectors of dimensionality {1}")]
+    InvalidMultiVector(usize, usize),
     #[error("Metadata update not valid")]
     MetadataUpdateNotValid,
 }
@@ -305,8 +312,10 @@ impl ChromaError for ApplyMaterializedLogError {
             ApplyMaterializedLogError::BlockfileSetError => ErrorCodes::Internal,
             ApplyMaterializedLogError::BlockfileDeleteError => ErrorCodes::Internal,
             ApplyMaterializedLogError::BlockfileUpdateError => ErrorCodes::Internal,
+            ApplyMaterializedLogError::BlockfileReadError => ErrorCodes::Internal,
             ApplyMaterializedLogError::MetadataUpdateNotValid => ErrorCodes::Internal,
             ApplyMaterializedLogError::EmbeddingNotSet => ErrorCodes::InvalidArgument,
+            ApplyMaterializedLogError::InvalidMultiVector(_, _) => ErrorCodes::InvalidArgument,
         }
     }
 }
@@ -738,6 +747,16 @@ impl RecordSegmentReader<'_> {
         self.id_to_data.get("", offset_id).await
     }
 
+    /// Returns the data of the records with the given offset ids, sorted by
+    /// offset id. Only the blocks that hold them are read, each of them once.
+    pub(crate) async fn get_data_for_offset_ids(
+        &self,
+        offset_ids: &RoaringBitmap,
+    ) -> Result<Vec<(u32, DataRecord)>, Box<dyn ChromaError>> {
+        let offset_ids: Vec<u32> = offset_ids.iter().collect();
+        self.id_to_data.get_many("", &offset_ids).await
+    }
+
     pub(crate) async fn get_data_and_offset_id_for_user_id(
         &self,
         user_id: &str,
@@ -790,7 +809,33 @@ impl RecordSegmentReader<'_> {
         Ok(data)
     }
 
+    /// Returns all data in the record segment along with its offset id,
+    /// sorted by embedding id
+    pub(crate) async fn get_all_data_with_offset_ids(
+        &self,
+    ) -> Result<Vec<(u32, DataRecord<'_>)>, Box<dyn ChromaError>> {
+        let mut data = Vec::new();
+        let max_size = self.user_id_to_id.count().await?;
+        for i in 0..max_size {
+            let (_, _, offset_id) = self.user_id_to_id.get_at_index(i).await?;
+            let data_record = self.id_to_data.get("", offset_id).await?;
+            data.push((offset_id, data_record));
+        }
+        Ok(data)
+    }
+
     pub(crate) async fn count(&self) -> Result<usize, Box<dyn ChromaError>> {
         self.id_to_data.count().await
     }
+
+    /// Returns the offset ids of all records in the segment
+    pub(crate) async fn get_all_offset_ids(&self) -> Result<RoaringBitmap, Box<dyn ChromaError>> {
+        let mut offset_ids = RoaringBitmap::new();
+        let max_size = self.id_to_user_id.count().await?;
+        for i in 0..max_size {
+            let (_, offset_id, _) = self.id_to_user_id.get_at_index(i).await?;
+            offset_ids.insert(offset_id);
+        }
+        Ok(offset_ids)
+    }
 }
@@ -0,0 +1,813 @@
+use super::types::{MaterializedLogRecord, SegmentWriter};
+use super::SegmentFlusher;
+use crate::blockstore::provider::{BlockfileProvider, CreateError, OpenError};
+use crate::blockstore::{BlockfileFlusher, BlockfileReader, BlockfileWriter};
+use crate::errors::{ChromaError, ErrorCodes};
+use crate::execution::data::data_chunk::Chunk;
+use crate::segment::record_segment::ApplyMaterializedLogError;
+use crate::types::{Operation, Segment, SegmentType, SparseVector};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use roaring::RoaringBitmap;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+// Posting lists are split into blocks of offset ids. The postings of a block
+// are keyed by the dimension and the block as the prefix and the offset id as
+// the key. The blockstore has no f32 values so weights are stored as their bits.
+const SPARSE_POSTINGS: &str = "sparse_postings";
+// The dimensions of each offset id, to find its postings on update and delete.
+const SPARSE_DIMENSIONS: &str = "sparse_dimensions";
+// The largest value of each block of a posting list, keyed by the dimension as
+// the prefix and the block as the key, so that a search can skip the blocks
+// that can't make the top k without reading them.
+const SPARSE_BLOCK_MAXIMA: &str = "sparse_block_maxima";
+
+/// The number of offset ids in a block of a posting list.
+pub(crate) const POSTING_BLOCK_SIZE: u32 = 1024;
+
+fn postings_prefix(dimension: u32, block: u32) -> String {
+    format!("{}/{}", dimension, block)
+}
+
+// The largest negated values are under their own prefix, they bound the
+// contribution of a block to the score of a negative query value.
+fn block_maxima_prefix(dimension: u32, negated: bool) -> String {
+    match negated {
+        false => dimension.to_string(),
+        true => format!("-{}", dimension),
+    }
+}
+
+/// The bounds of the values of a dimension in a block of a posting list.
+/// Deletes leave the bounds of a block as they are, so they can be loose.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PostingBlock {
+    pub(crate) block: u32,
+    // The largest value and the largest negated value, both at least zero.
+    pub(crate) max_value: f32,
+    pub(crate) max_negated_value: f32,
+}
+
+impl PostingBlock {
+    /// The first offset id of the block.
+    pub(crate) fn start(&self) -> u32 {
+        self.block.saturating_mul(POSTING_BLOCK_SIZE)
+    }
+
+    /// The first offset id after the block.
+    pub(crate) fn end(&self) -> u32 {
+        self.block
+            .saturating_add(1)
+            .saturating_mul(POSTING_BLOCK_SIZE)
+    }
+
+    /// The largest contribution of a posting of the block to a score, at least
+    /// zero so that the bound of a record is never too small.
+    pub(crate) fn upper_bound(&self, query_value: f32) -> f32 {
+        match query_value < 0.0 {
+            true => -query_value * self.max_negated_value,
+            false => query_value * self.max_value,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SparseVectorSegmentWriter<'me> {
+    // These are Option<> so that we can take() them when we commit
+    postings: Option<BlockfileWriter>,
+    dimensions: Option<BlockfileWriter>,
+    block_maxima: Option<BlockfileWriter>,
+    // The dimensions and block maxima as of the last compaction. Blockfile
+    // writers can not be read, so the postings of updated and deleted records
+    // and the maxima that new postings raise are found here.
+    dimensions_reader: Option<BlockfileReader<'me, u32, RoaringBitmap>>,
+    block_maxima_reader: Option<BlockfileReader<'me, u32, u32>>,
+    // The maxima of the blocks that this compaction sets postings in, by
+    // dimension and block.
+    updated_block_maxima: Arc<Mutex<HashMap<(u32, u32), PostingBlock>>>,
+    pub(crate) id: Uuid,
+}
+
+impl Debug for SparseVectorSegmentWriter<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "SparseVectorSegmentWriter")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SparseVectorSegmentError {
+    #[error("Invalid segment type")]
+    InvalidSegmentType,
+    #[error("Segment uninitialized")]
+    UninitializedSegment,
+    #[error("Incorrect number of files")]
+    IncorrectNumberOfFiles,
+    #[error("Missing file: {0}")]
+    MissingFile(String),
+    #[error("Invalid Uuid for file: {0}")]
+    InvalidUuid(String),
+    #[error("Blockfile Creation Error")]
+    BlockfileCreateError(#[from] Box<CreateError>),
+    #[error("Blockfile Open Error")]
+    BlockfileOpenError(#[from] Box<OpenError>),
+}
+
+impl ChromaError for SparseVectorSegmentError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            SparseVectorSegmentError::InvalidSegmentType => ErrorCodes::InvalidArgument,
+            SparseVectorSegmentError::UninitializedSegment => ErrorCodes::InvalidArgument,
+            SparseVectorSegmentError::IncorrectNumberOfFiles => ErrorCodes::Internal,
+            SparseVectorSegmentError::MissingFile(_) => ErrorCodes::Internal,
+            SparseVectorSegmentError::InvalidUuid(_) => ErrorCodes::Internal,
+            SparseVectorSegmentError::BlockfileCreateError(e) => e.code(),
+            SparseVectorSegmentError::BlockfileOpenError(e) => e.code(),
+        }
+    }
+}
+
+fn get_file_id(segment: &Segment, file: &str) -> Result<Uuid, SparseVectorSegmentError> {
+    let file_id = match segment.file_path.get(file) {
+        Some(file_ids) => match file_ids.first() {
+            Some(file_id) => file_id,
+            None => return Err(SparseVectorSegmentError::MissingFile(file.to_string())),
+        },
+        None => return Err(SparseVectorSegmentError::MissingFile(file.to_string())),
+    };
+    match Uuid::parse_str(file_id) {
+        Ok(uuid) => Ok(uuid),
+        Err(_) => Err(SparseVectorSegmentError::InvalidUuid(file.to_string())),
+    }
+}
+
+impl<'me> SparseVectorSegmentWriter<'me> {
+    pub(crate) async fn from_segment(
+        segment: &Segment,
+        blockfile_provider: &BlockfileProvider,
+    ) -> Result<SparseVectorSegmentWriter<'me>, SparseVectorSegmentError> {
+        if segment.r#type != SegmentType::BlockfileSparseVector {
+            return Err(SparseVectorSegmentError::InvalidSegmentType);
+        }
+        let (postings, dimensions, block_maxima, dimensions_reader, block_maxima_reader) =
+            match segment.file_path.len() {
+                0 => {
+                    let postings = match blockfile_provider.create::<u32, u32>() {
+                        Ok(postings) => postings,
+                        Err(e) => return Err(SparseVectorSegmentError::BlockfileCreateError(e)),
+                    };
+                    let dimensions = match blockfile_provider.create::<u32, &RoaringBitmap>() {
+                        Ok(dimensions) => dimensions,
+                        Err(e) => return Err(SparseVectorSegmentError::BlockfileCreateError(e)),
+                    };
+                    let block_maxima = match blockfile_provider.create::<u32, u32>() {
+                        Ok(block_maxima) => block_maxima,
+                        Err(e) => return Err(SparseVectorSegmentError::BlockfileCreateError(e)),
+                    };
+                    (postings, dimensions, block_maxima, None, None)
+                }
+                3 => {
+                    let postings_id = get_file_id(segment, SPARSE_POSTINGS)?;
+                    let dimensions_id = get_file_id(segment, SPARSE_DIMENSIONS)?;
+                    let block_maxima_id = get_file_id(segment, SPARSE_BLOCK_MAXIMA)?;
+                    let postings = match blockfile_provider.fork::<u32, u32>(&postings_id).await {
+                        Ok(postings) => postings,
+                        Err(e) => return Err(SparseVectorSegmentError::BlockfileCreateError(e)),
+                    };
+                    let dimensions = match blockfile_provider
+                        .fork::<u32, &RoaringBitmap>(&dimensions_id)
+                        .await
+                    {
+                        Ok(dimensions) => dimensions,
+                        Err(e) => return Err(SparseVectorSegmentError::BlockfileCreateError(e)),
+                    };
+                    let dimensions_reader = match blockfile_provider
+                        .open::<u32, RoaringBitmap>(&dimensions_id)
+                        .await
+                    {
+                        Ok(reader) => reader,
+                        Err(e) => return Err(SparseVectorSegmentError::BlockfileOpenError(e)),
+                    };
+                    let block_maxima = match blockfile_provider
+                        .fork::<u32, u32>(&block_maxima_id)
+                        .await
+                    {
+                        Ok(block_maxima) => block_maxima,
+                        Err(e) => return Err(SparseVectorSegmentError::BlockfileCreateError(e)),
+                    };
+                    let block_maxima_reader =
+                        match blockfile_provider.open::<u32, u32>(&block_maxima_id).await {
+                            Ok(reader) => reader,
+                            Err(e) => return Err(SparseVectorSegmentError::BlockfileOpenError(e)),
+                        };
+                    (
+                        postings,
+                        dimensions,
+                        block_maxima,
+                        Some(dimensions_reader),
+                        Some(block_maxima_reader),
+                    )
+                }
+                _ => return Err(SparseVectorSegmentError::IncorrectNumberOfFiles),
+            };
+        Ok(SparseVectorSegmentWriter {
+            postings: Some(postings),
+            dimensions: Some(dimensions),
+            block_maxima: Some(block_maxima),
+            dimensions_reader,
+            block_maxima_reader,
+            updated_block_maxima: Arc::new(Mutex::new(HashMap::new())),
+            id: segment.id,
+        })
+    }
+
+    // The maxima of a block before this compaction raised them, zero for a
+    // block that had no postings.
+    async fn previous_block_maxima(
+        &self,
+        dimension: u32,
+        block: u32,
+    ) -> Result<PostingBlock, ApplyMaterializedLogError> {
+        let mut posting_block = PostingBlock {
+            block,
+            max_value: 0.0,
+            max_negated_value: 0.0,
+        };
+        let reader = match &self.block_maxima_reader {
+            Some(reader) => reader,
+            None => return Ok(posting_block),
+        };
+        let prefix = block_maxima_prefix(dimension, false);
+        if !reader.contains(&prefix, block).await {
+            return Ok(posting_block);
+        }
+        posting_block.max_value = match reader.get(&prefix, block).await {
+            Ok(value) => f32::from_bits(value),
+            Err(_) => return Err(ApplyMaterializedLogError::BlockfileSetError),
+        };
+        posting_block.max_negated_value = match reader
+            .get(&block_maxima_prefix(dimension, true), block)
+            .await
+        {
+            Ok(value) => f32::from_bits(value),
+            Err(_) => return Err(ApplyMaterializedLogError::BlockfileSetError),
+        };
+        Ok(posting_block)
+    }
+
+    // Raises the maxima of the block of a new posting when the value is above
+    // them.
+    async fn raise_block_maxima(
+        &self,
+        dimension: u32,
+        offset_id: u32,
+        value: f32,
+    ) -> Result<(), ApplyMaterializedLogError> {
+        let block = offset_id / POSTING_BLOCK_SIZE;
+        let updated = self
+            .updated_block_maxima
+            .lock()
+            .get(&(dimension, block))
+            .copied();
+        let mut posting_block = match updated {
+            Some(posting_block) => posting_block,
+            None => self.previous_block_maxima(dimension, block).await?,
+        };
+        if value <= posting_block.max_value && -value <= posting_block.max_negated_value {
+            return Ok(());
+        }
+        posting_block.max_value = posting_block.max_value.max(value);
+        posting_block.max_negated_value = posting_block.max_negated_value.max(-value);
+        let block_maxima = self.block_maxima.as_ref().unwrap();
+        for (negated, value) in [
+            (false, posting_block.max_value),
+            (true, posting_block.max_negated_value),
+        ] {
+            if block_maxima
+                .set::<u32, u32>(
+                    &block_maxima_prefix(dimension, negated),
+                    block,
+                    value.to_bits(),
+                )
+                .await
+                .is_err()
+            {
+                return Err(ApplyMaterializedLogError::BlockfileSetError);
+            }
+        }
+        self.updated_block_maxima
+            .lock()
+            .insert((dimension, block), posting_block);
+        Ok(())
+    }
+
+    async fn set_sparse_vector(
+        &self,
+        offset_id: u32,
+        sparse_vector: &SparseVector,
+    ) -> Result<(), ApplyMaterializedLogError> {
+        for (dimension, value) in sparse_vector.iter() {
+            let prefix = postings_prefix(dimension, offset_id / POSTING_BLOCK_SIZE);
+            match self
+                .postings
+                .as_ref()
+                .unwrap()
+                .set::<u32, u32>(&prefix, offset_id, value.to_bits())
+                .await
+            {
+                Ok(()) => (),
+                Err(_) => return Err(ApplyMaterializedLogError::BlockfileSetError),
+            }
+            self.raise_block_maxima(dimension, offset_id, value).await?;
+        }
+        // The indices of a sparse vector are sorted and unique
+        let dimensions = match RoaringBitmap::from_sorted_iter(sparse_vector.indices.clone()) {
+            Ok(dimensions) => dimensions,
+            Err(_) => return Err(ApplyMaterializedLogError::BlockfileSetError),
+        };
+        match self
+            .dimensions
+            .as_ref()
+            .unwrap()
+            .set::<u32, &RoaringBitmap>("", offset_id, &dimensions)
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(_) => Err(ApplyMaterializedLogError::BlockfileSetError),
+        }
+    }
+
+    // Removes the postings the record had as of the last compaction. Records
+    // without a sparse vector have nothing to remove.
+    async fn delete_sparse_vector(&self, offset_id: u32) -> Result<(), ApplyMaterializedLogError> {
+        let dimensions_reader = match &self.dimensions_reader {
+            Some(reader) => reader,
+            None => return Ok(()),
+        };
+        if !dimensions_reader.contains("", offset_id).await {
+            return Ok(());
+        }
+        let dimensions = match dimensions_reader.get("", offset_id).await {
+            Ok(dimensions) => dimensions,
+            Err(_) => return Err(ApplyMaterializedLogError::BlockfileDeleteError),
+        };
+        for dimension in dimensions.iter() {
+            let prefix = postings_prefix(dimension, offset_id / POSTING_BLOCK_SIZE);
+            match self
+                .postings
+                .as_ref()
+                .unwrap()
+                .delete::<u32, u32>(&prefix, offset_id)
+                .await
+            {
+                Ok(()) => (),
+                Err(_) => return Err(ApplyMaterializedLogError::BlockfileDeleteError),
+            }
+        }
+        match self
+            .dimensions
+            .as_ref()
+            .unwrap()
+            .delete::<u32, &RoaringBitmap>("", offset_id)
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(_) => Err(ApplyMaterializedLogError::BlockfileDeleteError),
+        }
+    }
+}
+
+impl<'a> SegmentWriter<'a> for SparseVectorSegmentWriter<'_> {
+    async fn apply_materialized_log_chunk(
+        &self,
+        records: Chunk<MaterializedLogRecord<'a>>,
+    ) -> Result<(), ApplyMaterializedLogError> {
+        for (record, _) in records.iter() {
+            match record.final_operation {
+                Operation::Add => {
+                    if let Some(sparse_vector) = record.final_sparse_vector {
+                        self.set_sparse_vector(record.offset_id, sparse_vector)
+                            .await?;
+                    }
+                }
+                Operation::Update => {
+                    // Updates that do not set a sparse vector keep the old one
+                    if let Some(sparse_vector) = record.final_sparse_vector {
+                        self.delete_sparse_vector(record.offset_id).await?;
+                        self.set_sparse_vector(record.offset_id, sparse_vector)
+                            .await?;
+                    }
+                }
+                Operation::Delete => {
+                    self.delete_sparse_vector(record.offset_id).await?;
+                }
+                Operation::Upsert => {
+                    // MaterializedLogRecord already converts upserts into either updates or inserts
+                    // so here we expect to not have any records of this type.
+                    panic!("Invariant violation. After log materialization there shouldn't be any upserts.");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn commit(mut self) -> Result<impl SegmentFlusher, Box<dyn ChromaError>> {
+        let postings_flusher = self.postings.take().unwrap().commit::<u32, u32>()?;
+        let dimensions_flusher = self
+            .dimensions
+            .take()
+            .unwrap()
+            .commit::<u32, &RoaringBitmap>()?;
+        let block_maxima_flusher = self.block_maxima.take().unwrap().commit::<u32, u32>()?;
+        Ok(SparseVectorSegmentFlusher {
+            postings_flusher,
+            dimensions_flusher,
+            block_maxima_flusher,
+        })
+    }
+}
+
+pub(crate) struct SparseVectorSegmentFlusher {
+    postings_flusher: BlockfileFlusher,
+    dimensions_flusher: BlockfileFlusher,
+    block_maxima_flusher: BlockfileFlusher,
+}
+
+impl Debug for SparseVectorSegmentFlusher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "SparseVectorSegmentFlusher")
+    }
+}
+
+#[async_trait]
+impl SegmentFlusher for SparseVectorSegmentFlusher {
+    async fn flush(self) -> Result<HashMap<String, Vec<String>>, Box<dyn ChromaError>> {
+        let postings_id = self.postings_flusher.id();
+        let dimensions_id = self.dimensions_flusher.id();
+        let block_maxima_id = self.block_maxima_flusher.id();
+        self.postings_flusher.flush::<u32, u32>().await?;
+        self.dimensions_flusher
+            .flush::<u32, &RoaringBitmap>()
+            .await?;
+        self.block_maxima_flusher.flush::<u32, u32>().await?;
+        let mut flushed_files = HashMap::new();
+        flushed_files.insert(SPARSE_POSTINGS.to_string(), vec![postings_id.to_string()]);
+        flushed_files.insert(
+            SPARSE_DIMENSIONS.to_string(),
+            vec![dimensions_id.to_string()],
+        );
+        flushed_files.insert(
+            SPARSE_BLOCK_MAXIMA.to_string(),
+            vec![block_maxima_id.to_string()],
+        );
+        Ok(flushed_files)
+    }
+}
+
+pub(crate) struct SparseVectorSegmentReader<'me> {
+    postings: BlockfileReader<'me, u32, u32>,
+    block_maxima: BlockfileReader<'me, u32, u32>,
+}
+
+impl<'me> SparseVectorSegmentReader<'me> {
+    pub(crate) async fn from_segment(
+        segment: &Segment,
+        blockfile_provider: &BlockfileProvider,
+    ) -> Result<SparseVectorSegmentReader<'me>, Box<SparseVectorSegmentError>> {
+        if segment.r#type != SegmentType::BlockfileSparseVector {
+            return Err(Box::new(SparseVectorSegmentError::InvalidSegmentType));
+        }
+        match segment.file_path.len() {
+            0 => Err(Box::new(SparseVectorSegmentError::UninitializedSegment)),
+            3 => {
+                let postings_id = match get_file_id(segment, SPARSE_POSTINGS) {
+                    Ok(postings_id) => postings_id,
+                    Err(e) => return Err(Box::new(e)),
+                };
+                let block_maxima_id = match get_file_id(segment, SPARSE_BLOCK_MAXIMA) {
+                    Ok(block_maxima_id) => block_maxima_id,
+                    Err(e) => return Err(Box::new(e)),
+                };
+                let postings = match blockfile_provider.open::<u32, u32>(&postings_id).await {
+                    Ok(postings) => postings,
+                    Err(e) => {
+                        return Err(Box::new(SparseVectorSegmentError::BlockfileOpenError(e)))
+                    }
+                };
+                match blockfile_provider.open::<u32, u32>(&block_maxima_id).await {
+                    Ok(block_maxima) => Ok(SparseVectorSegmentReader {
+                        postings,
+                        block_maxima,
+                    }),
+                    Err(e) => Err(Box::new(SparseVectorSegmentError::BlockfileOpenError(e))),
+                }
+            }
+            _ => Err(Box::new(SparseVectorSegmentError::IncorrectNumberOfFiles)),
+        }
+    }
+
+    /// Returns the blocks of the posting list of the dimension with the bounds
+    /// of their values, sorted by block. Only the maxima are read.
+    pub(crate) async fn get_posting_blocks(
+        &'me self,
+        dimension: u32,
+    ) -> Result<Vec<PostingBlock>, Box<dyn ChromaError>> {
+        let max_values = self
+            .block_maxima
+            .get_by_prefix(&block_maxima_prefix(dimension, false))
+            .await?;
+        let max_negated_values = self
+            .block_maxima
+            .get_by_prefix(&block_maxima_prefix(dimension, true))
+            .await?;
+        // Both maxima of a block are always written together.
+        Ok(max_values
+            .into_iter()
+            .zip(max_negated_values)
+            .map(
+                |((_, block, max_value), (_, _, max_negated_value))| PostingBlock {
+                    block,
+                    max_value: f32::from_bits(max_value),
+                    max_negated_value: f32::from_bits(max_negated_value),
+                },
+            )
+            .collect())
+    }
+
+    /// Returns the offset ids and values of the records in a block that have
+    /// the dimension, sorted by offset id.
+    pub(crate) async fn get_posting_block(
+        &'me self,
+        dimension: u32,
+        block: u32,
+    ) -> Result<Vec<(u32, f32)>, Box<dyn ChromaError>> {
+        let postings = self
+            .postings
+            .get_by_prefix(&postings_prefix(dimension, block))
+            .await?;
+        Ok(postings
+            .into_iter()
+            .map(|(_, offset_id, value)| (offset_id, f32::from_bits(value)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::record_segment::{RecordSegmentReader, RecordSegmentWriter};
+    use crate::segment::LogMaterializer;
+    use crate::storage::{local::LocalStorage, Storage};
+    use crate::types::{LogRecord, OperationRecord, SegmentScope};
+
+    fn log_record(
+        log_offset: i64,
+        id: &str,
+        operation: Operation,
+        sparse_vector: Option<SparseVector>,
+    ) -> LogRecord {
+        LogRecord {
+            log_offset,
+            record: OperationRecord {
+                id: id.to_string(),
+                embedding: match operation {
+                    Operation::Delete => None,
+                    _ => Some(vec![1.0, 2.0]),
+                },
+                encoding: None,
+                sparse_vector,
+                metadata: None,
+                document: None,
+                operation,
+            },
+        }
+    }
+
+    // Compacts the logs into the record and sparse vector segments and returns
+    // them with their new files.
+    async fn compact(
+        blockfile_provider: &BlockfileProvider,
+        record_segment: &mut Segment,
+        sparse_segment: &mut Segment,
+        logs: Vec<LogRecord>,
+    ) {
+        let record_writer = RecordSegmentWriter::from_segment(record_segment, blockfile_provider)
+            .await
+            .unwrap();
+        let sparse_writer =
+            SparseVectorSegmentWriter::from_segment(sparse_segment, blockfile_provider)
+                .await
+                .unwrap();
+        let record_reader = RecordSegmentReader::from_segment(record_segment, blockfile_provider)
+            .await
+            .ok();
+        let logs = Chunk::new(logs.into());
+        let materializer = LogMaterializer::new(record_reader, logs, None);
+        let records = materializer.materialize().await.unwrap();
+        record_writer
+            .apply_materialized_log_chunk(records.clone())
+            .await
+            .unwrap();
+        sparse_writer
+            .apply_materialized_log_chunk(records)
+            .await
+            .unwrap();
+        record_segment.file_path = record_writer.commit().unwrap().flush().await.unwrap();
+        sparse_segment.file_path = sparse_writer.commit().unwrap().flush().await.unwrap();
+    }
+
+    // Reads every block of the posting list of the dimension.
+    async fn posting_list<'me>(
+        reader: &'me SparseVectorSegmentReader<'me>,
+        dimension: u32,
+    ) -> Vec<(u32, f32)> {
+        let mut postings = Vec::new();
+        for posting_block in reader.get_posting_blocks(dimension).await.unwrap() {
+            postings.extend(
+                reader
+                    .get_posting_block(dimension, posting_block.block)
+                    .await
+                    .unwrap(),
+            );
+        }
+        postings
+    }
+
+    #[tokio::test]
+    async fn test_sparse_vector_segment_postings() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider = BlockfileProvider::new_arrow(storage);
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut sparse_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileSparseVector,
+            scope: SegmentScope::VECTOR,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+
+        compact(
+            &blockfile_provider,
+            &mut record_segment,
+            &mut sparse_segment,
+            vec![
+                log_record(
+                    1,
+                    "a",
+                    Operation::Add,
+                    Some(SparseVector {
+                        indices: vec![1, 5],
+                        values: vec![0.5, 2.0],
+                    }),
+                ),
+                log_record(
+                    2,
+                    "b",
+                    Operation::Add,
+                    Some(SparseVector {
+                        indices: vec![5, 9],
+                        values: vec![1.5, 3.0],
+                    }),
+                ),
+                log_record(3, "c", Operation::Add, None),
+            ],
+        )
+        .await;
+        let reader = SparseVectorSegmentReader::from_segment(&sparse_segment, &blockfile_provider)
+            .await
+            .unwrap();
+        assert_eq!(posting_list(&reader, 1).await, vec![(1, 0.5)]);
+        assert_eq!(posting_list(&reader, 5).await, vec![(1, 2.0), (2, 1.5)]);
+        assert!(posting_list(&reader, 7).await.is_empty());
+        assert_eq!(
+            reader.get_posting_blocks(5).await.unwrap(),
+            vec![PostingBlock {
+                block: 0,
+                max_value: 2.0,
+                max_negated_value: 0.0,
+            }]
+        );
+
+        // Replace the sparse vector of a and delete b
+        compact(
+            &blockfile_provider,
+            &mut record_segment,
+            &mut sparse_segment,
+            vec![
+                log_record(
+                    4,
+                    "a",
+                    Operation::Update,
+                    Some(SparseVector {
+                        indices: vec![9],
+                        values: vec![-4.0],
+                    }),
+                ),
+                log_record(5, "b", Operation::Delete, None),
+            ],
+        )
+        .await;
+        let reader = SparseVectorSegmentReader::from_segment(&sparse_segment, &blockfile_provider)
+            .await
+            .unwrap();
+        assert!(posting_list(&reader, 1).await.is_empty());
+        assert!(posting_list(&reader, 5).await.is_empty());
+        assert_eq!(posting_list(&reader, 9).await, vec![(1, -4.0)]);
+        // The maximum of the deleted value of b is kept, it still bounds the
+        // block.
+        assert_eq!(
+            reader.get_posting_blocks(9).await.unwrap(),
+            vec![PostingBlock {
+                block: 0,
+                max_value: 3.0,
+                max_negated_value: 4.0,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sparse_vector_segment_blocks() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let blockfile_provider = BlockfileProvider::new_arrow(storage);
+        let mut record_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileRecord,
+            scope: SegmentScope::RECORD,
+            collection: Some(Uuid::new_v4()),
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+        let mut sparse_segment = Segment {
+            id: Uuid::new_v4(),
+            r#type: SegmentType::BlockfileSparseVector,
+            scope: SegmentScope::VECTOR,
+            collection: record_segment.collection,
+            metadata: None,
+            file_path: HashMap::new(),
+        };
+
+        // Offset ids start at one, so the last record is in the second block.
+        let logs = (1..=POSTING_BLOCK_SIZE as i64)
+            .map(|log_offset| {
+                log_record(
+                    log_offset,
+                    &log_offset.to_string(),
+                    Operation::Add,
+                    Some(SparseVector {
+                        indices: vec![1],
+                        values: vec![log_offset as f32],
+                    }),
+                )
+            })
+            .collect();
+        compact(
+            &blockfile_provider,
+            &mut record_segment,
+            &mut sparse_segment,
+            logs,
+        )
+        .await;
+        let reader = SparseVectorSegmentReader::from_segment(&sparse_segment, &blockfile_provider)
+            .await
+            .unwrap();
+        let blocks = reader.get_posting_blocks(1).await.unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                PostingBlock {
+                    block: 0,
+                    max_value: (POSTING_BLOCK_SIZE - 1) as f32,
+                    max_negated_value: 0.0,
+                },
+                PostingBlock {
+                    block: 1,
+                    max_value: POSTING_BLOCK_SIZE as f32,
+                    max_negated_value: 0.0,
+                },
+            ]
+        );
+        assert_eq!(blocks[1].start(), POSTING_BLOCK_SIZE);
+        assert_eq!(blocks[1].end(), 2 * POSTING_BLOCK_SIZE);
+        assert_eq!(
+            reader.get_posting_block(1, 1).await.unwrap(),
+            vec![(POSTING_BLOCK_SIZE, POSTING_BLOCK_SIZE as f32)]
+        );
+        assert_eq!(
+            reader.get_posting_block(1, 0).await.unwrap().len(),
+            POSTING_BLOCK_SIZE as usize - 1
+        );
+    }
+}
@@ -2,14 +2,19 @@ use crate::errors::{ChromaError, ErrorCodes};
 use crate::execution::data::data_chunk::Chunk;
 use crate::types::{
     DeletedMetadata, LogRecord, Metadata, MetadataDelta, MetadataValue,
-    MetadataValueConversionError, Operation, OperationRecord, UpdateMetadata, UpdateMetadataValue,
+    MetadataValueConversionError, Operation, OperationRecord, ScalarEncoding, SparseVector,
+    UpdateMetadata, UpdateMetadataValue,
 };
 use async_trait::async_trait;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::sync::atomic::AtomicU32;
 use std::sync::Arc;
 use thiserror::Error;
+use uuid::Uuid;
 
+use super::distributed_hnsw_segment::DistributedHNSWSegmentWriter;
+use super::ivf_segment::IvfSegmentWriter;
 use super::record_segment::{ApplyMaterializedLogError, RecordSegmentReader};
 
 // Materializes metadata from update metadata, populating the delete list
@@ -166,6 +171,13 @@ pub(crate) struct MaterializedLogRecord<'referred_data> {
     // E.g. if log has [Insert(emb0), Update(emb1), Update(emb2), Update()]
     // then this will contain emb2. None if final operation is Delete.
     pub(crate) final_embedding: Option<&'referred_data [f32]>,
+    // The encoding of final_embedding as it came in the log. Set whenever
+    // final_embedding is set.
+    pub(crate) final_encoding: Option<ScalarEncoding>,
+    // The sparse vector from the last operation in the log that had one. The
+    // record segment does not store sparse vectors, so None means the sparse
+    // vector segment keeps whatever it has for this record.
+    pub(crate) final_sparse_vector: Option<&'referred_data SparseVector>,
 }
 
 impl<'referred_data> MaterializedLogRecord<'referred_data> {
@@ -340,11 +352,22 @@ impl<'referred_data> MaterializedLogRecord<'referred_data> {
         return match self.final_embedding {
             Some(embed) => embed,
             None => match self.data_record.as_ref() {
-                Some(data_record) => data_record.embedding,
+                Some(data_record) => &data_record.embedding,
                 None => panic!("Expected at least one source of embedding"),
             },
         };
     }
+
+    // The encoding the merged embedding should be stored with.
+    pub(crate) fn merged_encoding(&self) -> ScalarEncoding {
+        match (&self.final_embedding, &self.final_encoding) {
+            (Some(_), Some(encoding)) => encoding.clone(),
+            _ => match self.data_record.as_ref() {
+                Some(data_record) => data_record.encoding.clone(),
+                None => ScalarEncoding::FLOAT32,
+            },
+        }
+    }
 }
 
 impl<'referred_data> From<(DataRecord<'referred_data>, u32)>
@@ -362,6 +385,8 @@ impl<'referred_data> From<(DataRecord<'referred_data>, u32)>
             metadata_to_be_deleted: None,
             final_document: None,
             final_embedding: None,
+            final_encoding: None,
+            final_sparse_vector: None,
         }
     }
 }
@@ -419,6 +444,8 @@ impl<'referred_data> TryFrom<(&'referred_data OperationRecord, u32, &'referred_d
             metadata_to_be_deleted: deleted_metadata,
             final_document: document,
             final_embedding: embedding,
+            final_encoding: log_record.encoding.clone(),
+            final_sparse_vector: log_record.sparse_vector.as_ref(),
         })
     }
 }
@@ -559,6 +586,8 @@ impl<'me> LogMaterializer<'me> {
                         record_from_map.final_operation = Operation::Delete;
                         record_from_map.final_document = None;
                         record_from_map.final_embedding = None;
+                        record_from_map.final_encoding = None;
+                        record_from_map.final_sparse_vector = None;
                         record_from_map.metadata_to_be_merged = None;
                         record_from_map.metadata_to_be_deleted = None;
                         record_from_map.user_id = None;
@@ -605,6 +634,11 @@ impl<'me> LogMaterializer<'me> {
                     if log_record.record.embedding.is_some() {
                         record_from_map.final_embedding =
                             Some(log_record.record.embedding.as_ref().unwrap().as_slice());
+                        record_from_map.final_encoding = log_record.record.encoding.clone();
+                    }
+                    if log_record.record.sparse_vector.is_some() {
+                        record_from_map.final_sparse_vector =
+                            log_record.record.sparse_vector.as_ref();
                     }
                     // Only update the operation state for records that were not created
                     // from the log.
@@ -640,6 +674,11 @@ impl<'me> LogMaterializer<'me> {
                         if log_record.record.embedding.is_some() {
                             record_from_map.final_embedding =
                                 Some(log_record.record.embedding.as_ref().unwrap().as_slice());
+                            record_from_map.final_encoding = log_record.record.encoding.clone();
+                        }
+                        if log_record.record.sparse_vector.is_some() {
+                            record_from_map.final_sparse_vector =
+                                log_record.record.sparse_vector.as_ref();
                         }
                         // We implicitly convert all upsert operations to either update
                         // or insert depending on whether it already existed in storage or not.
@@ -671,6 +710,11 @@ impl<'me> LogMaterializer<'me> {
                         if log_record.record.embedding.is_some() {
                             record_from_map.final_embedding =
                                 Some(log_record.record.embedding.as_ref().unwrap().as_slice());
+                            record_from_map.final_encoding = log_record.record.encoding.clone();
+                        }
+                        if log_record.record.sparse_vector.is_some() {
+                            record_from_map.final_sparse_vector =
+                                log_record.record.sparse_vector.as_ref();
                         }
                         // This record is not present on storage yet hence final operation is
                         // Add.
@@ -710,7 +754,11 @@ impl<'me> LogMaterializer<'me> {
 #[derive(Debug, Clone)]
 pub(crate) struct DataRecord<'a> {
     pub(crate) id: &'a str,
-    pub(crate) embedding: &'a [f32],
+    // Borrowed when the stored embedding is f32, owned when it was widened from
+    // a half precision encoding.
+    pub(crate) embedding: Cow<'a, [f32]>,
+    // The encoding the embedding is stored with.
+    pub(crate) encoding: ScalarEncoding,
     pub(crate) metadata: Option<Metadata>,
     pub(crate) document: Option<&'a str>,
 }
@@ -718,7 +766,7 @@ pub(crate) struct DataRecord<'a> {
 impl DataRecord<'_> {
     pub(crate) fn get_size(&self) -> usize {
         let id_size = self.id.len();
-        let embedding_size = self.embedding.len() * std::mem::size_of::<f32>();
+        let embedding_size = self.encoding.encoded_size(self.embedding.len());
         // TODO: use serialized_metadata size to calculate the size
         let metadata_size = 0;
         let document_size = match self.document {
@@ -742,6 +790,44 @@ pub(crate) trait SegmentFlusher {
     async fn flush(self) -> Result<HashMap<String, Vec<String>>, Box<dyn ChromaError>>;
 }
 
+/// The writer of the vector segment of a collection, which is either an hnsw
+/// or an ivf segment.
+#[derive(Clone, Debug)]
+pub(crate) enum VectorSegmentWriter {
+    Hnsw(Box<DistributedHNSWSegmentWriter>),
+    Ivf(Box<IvfSegmentWriter>),
+}
+
+impl VectorSegmentWriter {
+    pub(crate) fn id(&self) -> Uuid {
+        match self {
+            VectorSegmentWriter::Hnsw(writer) => writer.id,
+            VectorSegmentWriter::Ivf(writer) => writer.id,
+        }
+    }
+
+    pub(crate) async fn apply_materialized_log_chunk(
+        &self,
+        records: Chunk<MaterializedLogRecord<'_>>,
+    ) -> Result<(), ApplyMaterializedLogError> {
+        match self {
+            VectorSegmentWriter::Hnsw(writer) => writer.apply_materialized_log_chunk(records).await,
+            VectorSegmentWriter::Ivf(writer) => writer.apply_materialized_log_chunk(records).await,
+        }
+    }
+
+    // The flushers of the two segment types are different types so they are
+    // committed and flushed in one step.
+    pub(crate) async fn commit_and_flush(
+        self,
+    ) -> Result<HashMap<String, Vec<String>>, Box<dyn ChromaError>> {
+        match self {
+            VectorSegmentWriter::Hnsw(writer) => writer.commit()?.flush().await,
+            VectorSegmentWriter::Ivf(writer) => writer.commit()?.flush().await,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use uuid::Uuid;
@@ -793,6 +879,7 @@ mod tests {
                         id: "embedding_id_1".to_string(),
                         embedding: Some(vec![1.0, 2.0, 3.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata.clone()),
                         document: Some(String::from("doc1")),
                         operation: Operation::Add,
@@ -804,6 +891,7 @@ mod tests {
                         id: "embedding_id_2".to_string(),
                         embedding: Some(vec![4.0, 5.0, 6.0]),
                         encoding: None,
+                        sparse_vector: None,
                         metadata: Some(update_metadata),
                         document: Some(String::from("doc2")),
                         operation: Operation::Add,
@@ -862,6 +950,7 @@ mod tests {
                     id: "embedding_id_1".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata.clone()),
                     document: None,
                     operation: Operation::Update,
@@ -873,6 +962,7 @@ mod tests {
                     id: "embedding_id_3".to_string(),
                     embedding: Some(vec![7.0, 8.0, 9.0]),
                     encoding: None,
+                    sparse_vector: None,
                     metadata: Some(update_metadata),
                     document: Some(String::from("doc3")),
                     operation: Operation::Add,
@@ -884,6 +974,7 @@ mod tests {
                     id: "embedding_id_2".to_string(),
                     embedding: None,
                     encoding: None,
+                    sparse_vector: None,
                     metadata: None,
                     document: None,
                     operation: Operation::Delete,
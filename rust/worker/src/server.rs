@@ -3,17 +3,19 @@ use std::path::PathBuf;
 
 use crate::blockstore::provider::BlockfileProvider;
 use crate::chroma_proto::{
-    self, CountRecordsRequest, CountRecordsResponse, QueryMetadataRequest, QueryMetadataResponse,
+    self, AggregateRequest, AggregateResponse, CountRecordsRequest, CountRecordsResponse,
+    QueryMetadataRequest, QueryMetadataResponse,
 };
 use crate::chroma_proto::{
-    GetVectorsRequest, GetVectorsResponse, QueryVectorsRequest, QueryVectorsResponse,
+    GetVectorsRequest, GetVectorsResponse, QuerySparseVectorsRequest, QueryVectorsRequest,
+    QueryVectorsResponse,
 };
 use crate::config::{Configurable, QueryServiceConfig};
-use crate::errors::ChromaError;
+use crate::errors::{ChromaError, ErrorCodes};
 use crate::execution::operator::TaskMessage;
 use crate::execution::orchestration::{
-    CountQueryOrchestrator, GetVectorsOrchestrator, HnswQueryOrchestrator,
-    MetadataQueryOrchestrator,
+    AggregateQueryOrchestrator, CountQueryOrchestrator, GetVectorsOrchestrator,
+    HnswQueryOrchestrator, MetadataQueryOrchestrator, SparseQueryOrchestrator,
 };
 use crate::index::hnsw_provider::HnswIndexProvider;
 use crate::log::log::Log;
@@ -21,7 +23,14 @@ use crate::sysdb::sysdb::SysDb;
 use crate::system::{Receiver, System};
 use crate::tracing::util::wrap_span_with_parent_context;
 use crate::types::MetadataValue;
+use crate::types::OrderBy;
 use crate::types::ScalarEncoding;
+use crate::types::SearchOptions;
+use crate::types::SparseVector;
+use crate::types::Where;
+use crate::types::WhereDocument;
+use crate::types::DEFAULT_HISTOGRAM_BUCKETS;
+use crate::types::MAX_HISTOGRAM_BUCKETS;
 use async_trait::async_trait;
 use tokio::signal::unix::{signal, SignalKind};
 use tonic::{transport::Server, Request, Response, Status};
@@ -70,14 +79,18 @@ impl Configurable<QueryServiceConfig> for WorkerServer {
         };
         // TODO: inject hnsw index provider somehow
         // TODO: inject blockfile provider somehow
-        // TODO: real path
-        let path = PathBuf::from("~/tmp");
+        let hnsw_provider_config = &config.hnsw_provider;
+        let path = PathBuf::from(&hnsw_provider_config.hnsw_temporary_path);
         Ok(WorkerServer {
             dispatcher: None,
             system: None,
             sysdb,
             log,
-            hnsw_index_provider: HnswIndexProvider::new(storage.clone(), path),
+            hnsw_index_provider: HnswIndexProvider::new(
+                storage.clone(),
+                path,
+                hnsw_provider_config.hnsw_cache_size_bytes,
+            ),
             blockfile_provider: BlockfileProvider::new_arrow(storage),
             port: config.my_port,
         })
@@ -136,6 +149,16 @@ impl WorkerServer {
             }
         };
 
+        let search_options = match request.options {
+            Some(proto_options) => match SearchOptions::try_from(proto_options) {
+                Ok(options) => options,
+                Err(e) => {
+                    return Err(Status::invalid_argument(e.to_string()));
+                }
+            },
+            None => SearchOptions::default(),
+        };
+
         let mut proto_results_for_all = Vec::new();
 
         let parse_vectors_span = trace_span!("Input vectors parsing");
@@ -171,6 +194,7 @@ impl WorkerServer {
                     request.allowed_ids,
                     request.include_embeddings,
                     segment_uuid,
+                    search_options,
                     self.log.clone(),
                     self.sysdb.clone(),
                     self.hnsw_index_provider.clone(),
@@ -231,6 +255,89 @@ impl WorkerServer {
         return Ok(Response::new(resp));
     }
 
+    pub(crate) async fn query_sparse_vectors_instrumented(
+        &self,
+        request: Request<QuerySparseVectorsRequest>,
+    ) -> Result<Response<QueryVectorsResponse>, Status> {
+        let request = request.into_inner();
+        let segment_uuid = match Uuid::parse_str(&request.segment_id) {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return Err(Status::invalid_argument("Invalid Segment UUID"));
+            }
+        };
+        if request.k < 0 {
+            return Err(Status::invalid_argument("k must not be negative"));
+        }
+
+        let mut query_vectors = Vec::with_capacity(request.vectors.len());
+        for proto_query_vector in request.vectors {
+            match SparseVector::try_from(proto_query_vector) {
+                Ok(query_vector) => query_vectors.push(query_vector),
+                Err(e) => {
+                    return Err(Status::invalid_argument(format!(
+                        "Error converting sparse vector: {}",
+                        e
+                    )));
+                }
+            }
+        }
+
+        let dispatcher = match self.dispatcher {
+            Some(ref dispatcher) => dispatcher,
+            None => {
+                return Err(Status::internal("No dispatcher found"));
+            }
+        };
+
+        let result = match self.system {
+            Some(ref system) => {
+                let orchestrator = SparseQueryOrchestrator::new(
+                    system.clone(),
+                    query_vectors,
+                    request.k,
+                    request.allowed_ids,
+                    segment_uuid,
+                    self.log.clone(),
+                    self.sysdb.clone(),
+                    dispatcher.clone(),
+                    self.blockfile_provider.clone(),
+                );
+                orchestrator.run().await
+            }
+            None => {
+                return Err(Status::internal("No system found"));
+            }
+        };
+
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                return Err(Status::internal(format!(
+                    "Error running orchestrator: {}",
+                    e
+                )));
+            }
+        };
+
+        let results = result
+            .into_iter()
+            .map(|result_set| chroma_proto::VectorQueryResults {
+                results: result_set
+                    .into_iter()
+                    .map(|query_result| chroma_proto::VectorQueryResult {
+                        id: query_result.id,
+                        distance: query_result.distance,
+                        vector: None,
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Response::new(chroma_proto::QueryVectorsResponse {
+            results,
+        }))
+    }
+
     async fn get_vectors_instrumented(
         &self,
         request: Request<GetVectorsRequest>,
@@ -330,11 +437,24 @@ impl WorkerServer {
             }
         };
 
-        // For now we don't support limit/offset/where/where document
-        if request.limit.is_some() || request.offset.is_some() {
-            tracing::error!("Limit and offset not supported");
-            return Err(Status::unimplemented("Limit and offset not supported"));
-        }
+        let limit = match request.limit {
+            Some(limit) => match u32::try_from(limit) {
+                Ok(limit) => Some(limit),
+                Err(_) => {
+                    tracing::error!("Invalid limit {}", limit);
+                    return Err(Status::invalid_argument("Limit must not be negative"));
+                }
+            },
+            None => None,
+        };
+        let offset = match u32::try_from(request.offset.unwrap_or(0)) {
+            Ok(offset) => offset,
+            Err(_) => {
+                tracing::error!("Invalid offset {:?}", request.offset);
+                return Err(Status::invalid_argument("Offset must not be negative"));
+            }
+        };
+        let order_by = request.order_by.map(OrderBy::from);
 
         // If no ids are provided, pass None to the orchestrator
         let query_ids = match request.ids.len() {
@@ -342,25 +462,23 @@ impl WorkerServer {
             _ => Some(request.ids),
         };
 
-        let where_clause = match request.r#where {
+        let where_clause: Option<Where> = match request.r#where {
             Some(where_clause) => match where_clause.try_into() {
                 Ok(where_clause) => Some(where_clause),
-                Err(_) => {
-                    tracing::error!("Error converting where clause");
-                    return Err(Status::internal(format!("Error converting where clause",)));
+                Err(e) => {
+                    tracing::error!("Error converting where clause: {}", e);
+                    return Err(Status::invalid_argument(e.to_string()));
                 }
             },
             None => None,
         };
 
-        let where_document_clause = match request.where_document {
+        let where_document_clause: Option<WhereDocument> = match request.where_document {
             Some(where_document_clause) => match where_document_clause.try_into() {
                 Ok(where_document_clause) => Some(where_document_clause),
-                Err(_) => {
-                    tracing::error!("Error converting where document clause");
-                    return Err(Status::internal(format!(
-                        "Error converting where document clause",
-                    )));
+                Err(e) => {
+                    tracing::error!("Error converting where document clause: {}", e);
+                    return Err(Status::invalid_argument(e.to_string()));
                 }
             },
             None => None,
@@ -376,6 +494,10 @@ impl WorkerServer {
             self.blockfile_provider.clone(),
             where_clause,
             where_document_clause,
+            limit,
+            offset,
+            order_by,
+            request.include_snippets,
         );
 
         let result = orchestrator.run().await;
@@ -383,19 +505,20 @@ impl WorkerServer {
             Ok(result) => result,
             Err(e) => {
                 tracing::error!("Error running orchestrator: {}", e);
-                return Err(Status::internal(format!(
-                    "Error running orchestrator: {}",
-                    e
-                )));
+                return Err(match e.code() {
+                    ErrorCodes::InvalidArgument => Status::invalid_argument(e.to_string()),
+                    _ => Status::internal(format!("Error running orchestrator: {}", e)),
+                });
             }
         };
 
         let mut output = Vec::new();
-        let (ids, metadatas, documents) = result;
-        for ((id, metadata), document) in ids
+        let (ids, metadatas, documents, highlights) = result;
+        for (((id, metadata), document), highlight) in ids
             .into_iter()
             .zip(metadatas.into_iter())
             .zip(documents.into_iter())
+            .zip(highlights)
         {
             // The transport layer assumes the document exists in the metadata
             // with the special key "chroma:document"
@@ -413,6 +536,7 @@ impl WorkerServer {
             let record = chroma_proto::MetadataEmbeddingRecord {
                 id,
                 metadata: Some(chroma_proto::UpdateMetadata::from(output_metadata)),
+                highlight: highlight.map(chroma_proto::DocumentHighlight::from),
             };
             output.push(record);
         }
@@ -421,6 +545,103 @@ impl WorkerServer {
         let response = chroma_proto::QueryMetadataResponse { records: output };
         Ok(Response::new(response))
     }
+
+    async fn aggregate_instrumented(
+        &self,
+        request: Request<AggregateRequest>,
+    ) -> Result<Response<AggregateResponse>, Status> {
+        let request = request.into_inner();
+        let segment_uuid = match Uuid::parse_str(&request.segment_id) {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                tracing::error!("Invalid Segment UUID");
+                return Err(Status::invalid_argument("Invalid Segment UUID"));
+            }
+        };
+
+        let dispatcher = match self.dispatcher {
+            Some(ref dispatcher) => dispatcher,
+            None => {
+                tracing::error!("No dispatcher found");
+                return Err(Status::internal("No dispatcher found"));
+            }
+        };
+
+        let system = match self.system {
+            Some(ref system) => system,
+            None => {
+                tracing::error!("No system found");
+                return Err(Status::internal("No system found"));
+            }
+        };
+
+        let histogram_buckets = match request.histogram_buckets {
+            Some(0) => {
+                return Err(Status::invalid_argument(
+                    "Histogram buckets must be positive",
+                ));
+            }
+            Some(histogram_buckets) if histogram_buckets > MAX_HISTOGRAM_BUCKETS => {
+                return Err(Status::invalid_argument(format!(
+                    "Histogram buckets must be at most {}",
+                    MAX_HISTOGRAM_BUCKETS
+                )));
+            }
+            Some(histogram_buckets) => histogram_buckets,
+            None => DEFAULT_HISTOGRAM_BUCKETS,
+        };
+
+        let where_clause: Option<Where> = match request.r#where {
+            Some(where_clause) => match where_clause.try_into() {
+                Ok(where_clause) => Some(where_clause),
+                Err(e) => {
+                    tracing::error!("Error converting where clause: {}", e);
+                    return Err(Status::invalid_argument(e.to_string()));
+                }
+            },
+            None => None,
+        };
+
+        let where_document_clause: Option<WhereDocument> = match request.where_document {
+            Some(where_document_clause) => match where_document_clause.try_into() {
+                Ok(where_document_clause) => Some(where_document_clause),
+                Err(e) => {
+                    tracing::error!("Error converting where document clause: {}", e);
+                    return Err(Status::invalid_argument(e.to_string()));
+                }
+            },
+            None => None,
+        };
+
+        let orchestrator = AggregateQueryOrchestrator::new(
+            system.clone(),
+            &segment_uuid,
+            self.log.clone(),
+            self.sysdb.clone(),
+            dispatcher.clone(),
+            self.blockfile_provider.clone(),
+            where_clause,
+            where_document_clause,
+            request.keys,
+            histogram_buckets,
+        );
+
+        let aggregates = match orchestrator.run().await {
+            Ok(aggregates) => aggregates,
+            Err(e) => {
+                tracing::error!("Error running orchestrator: {}", e);
+                return Err(match e.code() {
+                    ErrorCodes::InvalidArgument => Status::invalid_argument(e.to_string()),
+                    _ => Status::internal(format!("Error running orchestrator: {}", e)),
+                });
+            }
+        };
+
+        let response = AggregateResponse {
+            aggregates: aggregates.into_iter().map(Into::into).collect(),
+        };
+        Ok(Response::new(response))
+    }
 }
 
 #[tonic::async_trait]
@@ -461,6 +682,22 @@ impl chroma_proto::vector_reader_server::VectorReader for WorkerServer {
             .instrument(instrumented_span)
             .await
     }
+
+    async fn query_sparse_vectors(
+        &self,
+        request: Request<QuerySparseVectorsRequest>,
+    ) -> Result<Response<QueryVectorsResponse>, Status> {
+        let query_span = trace_span!(
+            "Query sparse vectors",
+            k = request.get_ref().k,
+            segment_id = request.get_ref().segment_id,
+            allowed_ids = ?request.get_ref().allowed_ids
+        );
+        let instrumented_span = wrap_span_with_parent_context(query_span, request.metadata());
+        self.query_sparse_vectors_instrumented(request)
+            .instrument(instrumented_span)
+            .await
+    }
 }
 
 #[tonic::async_trait]
@@ -491,6 +728,28 @@ impl chroma_proto::metadata_reader_server::MetadataReader for WorkerServer {
             }
         };
 
+        let where_clause: Option<Where> = match request.r#where {
+            Some(where_clause) => match where_clause.try_into() {
+                Ok(where_clause) => Some(where_clause),
+                Err(e) => {
+                    tracing::error!("Error converting where clause: {}", e);
+                    return Err(Status::invalid_argument(e.to_string()));
+                }
+            },
+            None => None,
+        };
+
+        let where_document_clause: Option<WhereDocument> = match request.where_document {
+            Some(where_document_clause) => match where_document_clause.try_into() {
+                Ok(where_document_clause) => Some(where_document_clause),
+                Err(e) => {
+                    tracing::error!("Error converting where document clause: {}", e);
+                    return Err(Status::invalid_argument(e.to_string()));
+                }
+            },
+            None => None,
+        };
+
         let orchestrator = CountQueryOrchestrator::new(
             system.clone(),
             &segment_uuid,
@@ -498,6 +757,8 @@ impl chroma_proto::metadata_reader_server::MetadataReader for WorkerServer {
             self.sysdb.clone(),
             dispatcher.clone(),
             self.blockfile_provider.clone(),
+            where_clause,
+            where_document_clause,
         );
 
         let result = orchestrator.run().await;
@@ -507,9 +768,11 @@ impl chroma_proto::metadata_reader_server::MetadataReader for WorkerServer {
                 r
             }
             Err(e) => {
-                println!("Error! {:?}", e);
-                // TODO: Return 0 for now but should return an error at some point.
-                0
+                tracing::error!("Error running orchestrator: {}", e);
+                return Err(match e.code() {
+                    ErrorCodes::InvalidArgument => Status::invalid_argument(e.to_string()),
+                    _ => Status::internal(format!("Error running orchestrator: {}", e)),
+                });
             }
         };
         let response = CountRecordsResponse { count: c as u32 };
@@ -526,8 +789,28 @@ impl chroma_proto::metadata_reader_server::MetadataReader for WorkerServer {
             .instrument(instrumented_span)
             .await
     }
+
+    async fn aggregate(
+        &self,
+        request: Request<AggregateRequest>,
+    ) -> Result<Response<AggregateResponse>, Status> {
+        let aggregate_span = trace_span!(
+            "Aggregate metadata",
+            segment_id = request.get_ref().segment_id,
+            keys = ?request.get_ref().keys
+        );
+        let instrumented_span = wrap_span_with_parent_context(aggregate_span, request.metadata());
+        self.aggregate_instrumented(request)
+            .instrument(instrumented_span)
+            .await
+    }
 }
 
+#[cfg(debug_assertions)]
+use crate::execution::orchestration::{
+    EvaluationQueries, HnswSegmentEvaluator, LatencyPercentiles,
+};
+
 #[cfg(debug_assertions)]
 #[tonic::async_trait]
 impl chroma_proto::debug_server::Debug for WorkerServer {
@@ -558,19 +841,107 @@ impl chroma_proto::debug_server::Debug for WorkerServer {
             panic!("Intentional panic triggered");
         })
     }
+
+    async fn evaluate_hnsw_segment(
+        &self,
+        request: Request<chroma_proto::EvaluateHnswSegmentRequest>,
+    ) -> Result<Response<chroma_proto::EvaluateHnswSegmentResponse>, Status> {
+        let request = request.into_inner();
+        let segment_uuid = match Uuid::parse_str(&request.segment_id) {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return Err(Status::invalid_argument("Invalid Segment UUID"));
+            }
+        };
+        if request.k <= 0 {
+            return Err(Status::invalid_argument("k must be positive"));
+        }
+
+        let search_options = match request.options {
+            Some(proto_options) => match SearchOptions::try_from(proto_options) {
+                Ok(options) => options,
+                Err(e) => {
+                    return Err(Status::invalid_argument(e.to_string()));
+                }
+            },
+            None => SearchOptions::default(),
+        };
+
+        let queries = if request.query_vectors.is_empty() {
+            if request.num_samples <= 0 {
+                return Err(Status::invalid_argument(
+                    "Either query vectors or a positive number of samples must be provided",
+                ));
+            }
+            EvaluationQueries::Sample(request.num_samples as usize)
+        } else {
+            let mut query_vectors = Vec::new();
+            for proto_query_vector in request.query_vectors {
+                match proto_query_vector.try_into() {
+                    Ok((vector, _encoding)) => query_vectors.push(vector),
+                    Err(e) => {
+                        return Err(Status::invalid_argument(format!(
+                            "Error converting vector: {}",
+                            e
+                        )));
+                    }
+                }
+            }
+            EvaluationQueries::Vectors(query_vectors)
+        };
+
+        let evaluator = HnswSegmentEvaluator::new(
+            self.sysdb.clone(),
+            self.hnsw_index_provider.clone(),
+            self.blockfile_provider.clone(),
+        );
+        let report = match evaluator
+            .evaluate(&segment_uuid, queries, request.k as usize, search_options)
+            .instrument(trace_span!("Evaluate hnsw segment"))
+            .await
+        {
+            Ok(report) => report,
+            Err(e) => {
+                return Err(match e.code() {
+                    ErrorCodes::InvalidArgument => Status::invalid_argument(e.to_string()),
+                    _ => Status::internal(format!("Error evaluating segment: {}", e)),
+                });
+            }
+        };
+
+        let latency_to_proto = |latency: &LatencyPercentiles| chroma_proto::LatencyPercentiles {
+            p50_ms: latency.p50.as_secs_f32() * 1000.0,
+            p90_ms: latency.p90.as_secs_f32() * 1000.0,
+            p99_ms: latency.p99.as_secs_f32() * 1000.0,
+        };
+        let response = chroma_proto::EvaluateHnswSegmentResponse {
+            num_queries: report.num_queries as i32,
+            k: report.k as i32,
+            recall: report.recall,
+            hnsw_latency: Some(latency_to_proto(&report.hnsw_latency)),
+            brute_force_latency: Some(latency_to_proto(&report.brute_force_latency)),
+            num_elements: report.num_elements as i32,
+            num_deleted: report.num_deleted as i32,
+        };
+        Ok(Response::new(response))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::execution::dispatcher;
-    use crate::log::log::InMemoryLog;
+    use crate::log::log::{InMemoryLog, InternalLogRecord};
     use crate::storage::local::LocalStorage;
     use crate::storage::Storage;
     use crate::sysdb::test_sysdb::TestSysDb;
     use crate::system;
+    use crate::types::{
+        Collection, LogRecord, Operation, OperationRecord, Segment, SegmentScope, SegmentType,
+    };
 
     use super::*;
     use chroma_proto::debug_client::DebugClient;
+    use chroma_proto::metadata_reader_server::MetadataReader;
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -589,6 +960,7 @@ mod tests {
             hnsw_index_provider: HnswIndexProvider::new(
                 storage.clone(),
                 tmp_dir.path().to_path_buf(),
+                1024 * 1024 * 1024,
             ),
             blockfile_provider: BlockfileProvider::new_arrow(storage),
             port,
@@ -617,4 +989,152 @@ mod tests {
         let response = client.get_info(Request::new(())).await;
         assert!(response.is_ok());
     }
+
+    // A server for a collection with empty segments and a logged record.
+    fn log_only_server(tmp_dir: &tempfile::TempDir, metadata_segment_id: Uuid) -> WorkerServer {
+        let collection_id = Uuid::new_v4();
+        let mut sysdb = TestSysDb::new();
+        sysdb.add_collection(Collection {
+            id: collection_id,
+            name: "test".to_string(),
+            metadata: None,
+            dimension: Some(3),
+            tenant: "tenant".to_string(),
+            database: "database".to_string(),
+            log_position: -1,
+            version: 0,
+        });
+        for (id, r#type, scope) in [
+            (
+                Uuid::new_v4(),
+                SegmentType::BlockfileRecord,
+                SegmentScope::RECORD,
+            ),
+            (
+                metadata_segment_id,
+                SegmentType::BlockfileMetadata,
+                SegmentScope::METADATA,
+            ),
+        ] {
+            sysdb.add_segment(Segment {
+                id,
+                r#type,
+                scope,
+                collection: Some(collection_id),
+                metadata: None,
+                file_path: HashMap::new(),
+            });
+        }
+        let mut log = InMemoryLog::new();
+        log.add_log(
+            collection_id,
+            Box::new(InternalLogRecord {
+                collection_id,
+                log_offset: 0,
+                log_ts: 1,
+                record: LogRecord {
+                    log_offset: 0,
+                    record: OperationRecord {
+                        id: "id1".to_string(),
+                        embedding: Some(vec![1.0, 2.0, 3.0]),
+                        encoding: None,
+                        sparse_vector: None,
+                        metadata: None,
+                        document: Some("Version 1.5 (beta) is out".to_string()),
+                        operation: Operation::Add,
+                    },
+                },
+            }),
+        );
+        let storage = Storage::Local(LocalStorage::new(tmp_dir.path().to_str().unwrap()));
+        let mut server = WorkerServer {
+            dispatcher: None,
+            system: None,
+            sysdb: Box::new(SysDb::Test(sysdb)),
+            log: Box::new(Log::InMemory(log)),
+            hnsw_index_provider: HnswIndexProvider::new(
+                storage.clone(),
+                tmp_dir.path().to_path_buf(),
+                1024 * 1024 * 1024,
+            ),
+            blockfile_provider: BlockfileProvider::new_arrow(storage),
+            port: 0,
+        };
+        let system = system::System::new();
+        let dispatcher = system.start_component(dispatcher::Dispatcher::new(4, 10, 10));
+        server.set_system(system);
+        server.set_dispatcher(dispatcher.receiver());
+        server
+    }
+
+    fn where_document(
+        document: &str,
+        operator: chroma_proto::WhereDocumentOperator,
+    ) -> Option<chroma_proto::WhereDocument> {
+        Some(chroma_proto::WhereDocument {
+            where_document: Some(chroma_proto::where_document::WhereDocument::Direct(
+                chroma_proto::DirectWhereDocument {
+                    document: document.to_string(),
+                    operator: operator as i32,
+                    max_edits: None,
+                },
+            )),
+        })
+    }
+
+    #[tokio::test]
+    async fn count_records_rejects_invalid_filters() {
+        let tmp_dir = tempdir().unwrap();
+        let segment_id = Uuid::new_v4();
+        let server = log_only_server(&tmp_dir, segment_id);
+        let count = |where_document| {
+            server.count_records(Request::new(CountRecordsRequest {
+                segment_id: segment_id.to_string(),
+                r#where: None,
+                where_document,
+            }))
+        };
+
+        let response = count(where_document(
+            "beta",
+            chroma_proto::WhereDocumentOperator::Contains,
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.into_inner().count, 1);
+
+        // The pattern fails to compile when the filter is evaluated.
+        for (document, operator) in [
+            ("(beta", chroma_proto::WhereDocumentOperator::Regex),
+            ("(beta", chroma_proto::WhereDocumentOperator::Search),
+        ] {
+            let status = count(where_document(document, operator)).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument, "{}", document);
+        }
+    }
+
+    #[tokio::test]
+    async fn aggregate_rejects_invalid_histogram_buckets() {
+        let tmp_dir = tempdir().unwrap();
+        let segment_id = Uuid::new_v4();
+        let server = log_only_server(&tmp_dir, segment_id);
+        for histogram_buckets in [0, MAX_HISTOGRAM_BUCKETS + 1, u32::MAX] {
+            let status = server
+                .aggregate(Request::new(AggregateRequest {
+                    segment_id: segment_id.to_string(),
+                    r#where: None,
+                    where_document: None,
+                    keys: vec!["size".to_string()],
+                    histogram_buckets: Some(histogram_buckets),
+                }))
+                .await
+                .unwrap_err();
+            assert_eq!(
+                status.code(),
+                tonic::Code::InvalidArgument,
+                "{}",
+                histogram_buckets
+            );
+        }
+    }
 }
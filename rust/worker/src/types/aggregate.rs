@@ -0,0 +1,290 @@
+use super::MetadataValue;
+use crate::chroma_proto;
+use std::collections::HashMap;
+
+/// The number of histogram buckets of a numeric key when the request does
+/// not ask for a number.
+pub(crate) const DEFAULT_HISTOGRAM_BUCKETS: u32 = 10;
+
+/// The most histogram buckets a request can ask for, since every numeric key
+/// gets that many buckets.
+pub(crate) const MAX_HISTOGRAM_BUCKETS: u32 = 1000;
+
+/// The aggregate of the values of a metadata key over a set of records.
+/// # Description
+/// String and bool values are counted as facets, ints and floats are
+/// summarized together. Every element of a list value counts as a value.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MetadataAggregate {
+    pub(crate) key: String,
+    pub(crate) facets: Vec<FacetCount>,
+    pub(crate) numeric: Option<NumericAggregate>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FacetCount {
+    pub(crate) value: MetadataValue,
+    pub(crate) count: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct NumericAggregate {
+    pub(crate) min: f64,
+    pub(crate) max: f64,
+    pub(crate) sum: f64,
+    pub(crate) count: u32,
+    pub(crate) buckets: Vec<HistogramBucket>,
+}
+
+/// Counts the values from the lower bound up to but excluding the upper
+/// bound. The last bucket of a histogram includes its upper bound.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HistogramBucket {
+    pub(crate) lower: f64,
+    pub(crate) upper: f64,
+    pub(crate) count: u32,
+}
+
+/// Accumulates the values of a metadata key. A value can be added with the
+/// number of records that hold it, so the postings of the metadata index are
+/// aggregated without reading the records.
+#[derive(Debug)]
+pub(crate) struct MetadataAggregator {
+    key: String,
+    strings: HashMap<String, u32>,
+    bools: HashMap<bool, u32>,
+    numbers: Vec<(f64, u32)>,
+}
+
+impl MetadataAggregator {
+    pub(crate) fn new(key: String) -> Self {
+        MetadataAggregator {
+            key,
+            strings: HashMap::new(),
+            bools: HashMap::new(),
+            numbers: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, value: &MetadataValue, count: u32) {
+        if count == 0 {
+            return;
+        }
+        let elements = value.elements();
+        // The metadata index holds a posting per distinct element, repeated
+        // elements of a list count once.
+        for (index, element) in elements.iter().enumerate() {
+            if elements[..index].contains(element) {
+                continue;
+            }
+            match element.clone() {
+                MetadataValue::Str(value) => *self.strings.entry(value).or_default() += count,
+                MetadataValue::Bool(value) => *self.bools.entry(value).or_default() += count,
+                MetadataValue::Int(value) => self.numbers.push((value as f64, count)),
+                MetadataValue::Float(value) => self.numbers.push((value, count)),
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn finish(self, histogram_buckets: u32) -> MetadataAggregate {
+        let mut facets: Vec<FacetCount> = self
+            .bools
+            .into_iter()
+            .map(|(value, count)| FacetCount {
+                value: MetadataValue::Bool(value),
+                count,
+            })
+            .chain(self.strings.into_iter().map(|(value, count)| FacetCount {
+                value: MetadataValue::Str(value),
+                count,
+            }))
+            .collect();
+        // Ties are broken by value so the order is deterministic.
+        facets.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| match (&a.value, &b.value) {
+                    (MetadataValue::Bool(a), MetadataValue::Bool(b)) => a.cmp(b),
+                    (MetadataValue::Str(a), MetadataValue::Str(b)) => a.cmp(b),
+                    (MetadataValue::Bool(_), _) => std::cmp::Ordering::Less,
+                    _ => std::cmp::Ordering::Greater,
+                })
+        });
+        MetadataAggregate {
+            key: self.key,
+            facets,
+            numeric: summarize(&self.numbers, histogram_buckets.max(1)),
+        }
+    }
+}
+
+fn summarize(numbers: &[(f64, u32)], histogram_buckets: u32) -> Option<NumericAggregate> {
+    if numbers.is_empty() {
+        return None;
+    }
+    let min = numbers
+        .iter()
+        .map(|(value, _)| *value)
+        .fold(f64::MAX, f64::min);
+    let max = numbers
+        .iter()
+        .map(|(value, _)| *value)
+        .fold(f64::MIN, f64::max);
+    let sum = numbers
+        .iter()
+        .map(|(value, count)| value * *count as f64)
+        .sum();
+    let count = numbers.iter().map(|(_, count)| count).sum();
+    // A single value has a single bucket, there is no width to divide.
+    let bucket_count = if min == max { 1 } else { histogram_buckets };
+    let width = (max - min) / bucket_count as f64;
+    let mut buckets: Vec<HistogramBucket> = (0..bucket_count)
+        .map(|index| HistogramBucket {
+            lower: min + width * index as f64,
+            upper: if index + 1 == bucket_count {
+                max
+            } else {
+                min + width * (index + 1) as f64
+            },
+            count: 0,
+        })
+        .collect();
+    for (value, count) in numbers {
+        let index = match width {
+            width if width > 0.0 => ((value - min) / width) as usize,
+            _ => 0,
+        };
+        buckets[index.min(bucket_count as usize - 1)].count += count;
+    }
+    Some(NumericAggregate {
+        min,
+        max,
+        sum,
+        count,
+        buckets,
+    })
+}
+
+impl From<MetadataAggregate> for chroma_proto::MetadataAggregate {
+    fn from(aggregate: MetadataAggregate) -> Self {
+        chroma_proto::MetadataAggregate {
+            key: aggregate.key,
+            facets: aggregate
+                .facets
+                .into_iter()
+                .filter_map(|facet| {
+                    let value = match facet.value {
+                        MetadataValue::Str(value) => {
+                            chroma_proto::facet_count::Value::StringValue(value)
+                        }
+                        MetadataValue::Bool(value) => {
+                            chroma_proto::facet_count::Value::BoolValue(value)
+                        }
+                        _ => return None,
+                    };
+                    Some(chroma_proto::FacetCount {
+                        value: Some(value),
+                        count: facet.count,
+                    })
+                })
+                .collect(),
+            numeric: aggregate
+                .numeric
+                .map(|numeric| chroma_proto::NumericAggregate {
+                    min: numeric.min,
+                    max: numeric.max,
+                    sum: numeric.sum,
+                    count: numeric.count,
+                    buckets: numeric
+                        .buckets
+                        .into_iter()
+                        .map(|bucket| chroma_proto::HistogramBucket {
+                            lower: bucket.lower,
+                            upper: bucket.upper,
+                            count: bucket.count,
+                        })
+                        .collect(),
+                }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_facets() {
+        let mut aggregator = MetadataAggregator::new(String::from("color"));
+        aggregator.add(&MetadataValue::Str(String::from("red")), 2);
+        aggregator.add(&MetadataValue::Bool(true), 2);
+        aggregator.add(
+            &MetadataValue::StrList(vec![String::from("blue"), String::from("red")]),
+            1,
+        );
+        aggregator.add(&MetadataValue::Str(String::from("green")), 0);
+        let aggregate = aggregator.finish(DEFAULT_HISTOGRAM_BUCKETS);
+        assert_eq!(
+            aggregate.facets,
+            vec![
+                FacetCount {
+                    value: MetadataValue::Str(String::from("red")),
+                    count: 3,
+                },
+                FacetCount {
+                    value: MetadataValue::Bool(true),
+                    count: 2,
+                },
+                FacetCount {
+                    value: MetadataValue::Str(String::from("blue")),
+                    count: 1,
+                },
+            ]
+        );
+        assert_eq!(aggregate.numeric, None);
+    }
+
+    #[test]
+    fn test_numeric() {
+        let mut aggregator = MetadataAggregator::new(String::from("size"));
+        aggregator.add(&MetadataValue::Int(0), 3);
+        aggregator.add(&MetadataValue::Float(2.5), 1);
+        aggregator.add(&MetadataValue::IntList(vec![9, 10, 9]), 1);
+        let numeric = aggregator.finish(2).numeric.unwrap();
+        assert_eq!(numeric.min, 0.0);
+        assert_eq!(numeric.max, 10.0);
+        assert_eq!(numeric.sum, 21.5);
+        assert_eq!(numeric.count, 6);
+        assert_eq!(
+            numeric.buckets,
+            vec![
+                HistogramBucket {
+                    lower: 0.0,
+                    upper: 5.0,
+                    count: 4,
+                },
+                HistogramBucket {
+                    lower: 5.0,
+                    upper: 10.0,
+                    count: 2,
+                },
+            ]
+        );
+
+        let mut aggregator = MetadataAggregator::new(String::from("size"));
+        aggregator.add(&MetadataValue::Int(4), 2);
+        let numeric = aggregator
+            .finish(DEFAULT_HISTOGRAM_BUCKETS)
+            .numeric
+            .unwrap();
+        assert_eq!(
+            numeric.buckets,
+            vec![HistogramBucket {
+                lower: 4.0,
+                upper: 4.0,
+                count: 2,
+            }]
+        );
+    }
+}
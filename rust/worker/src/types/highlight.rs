@@ -0,0 +1,125 @@
+use crate::chroma_proto;
+
+// The snippet starts this many bytes before the first match and spans at
+// least this many bytes, both rounded to character boundaries.
+const SNIPPET_CONTEXT: usize = 40;
+const SNIPPET_LENGTH: usize = 160;
+
+/// Why a document matched the `$contains` queries of a where document clause.
+/// # Description
+/// The snippet is a window of the document around its first match, the
+/// highlights are the byte ranges of the matches within the snippet.
+/// Overlapping matches are merged into one highlight, and a match that runs
+/// past the end of the snippet is cut at its end.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DocumentHighlight {
+    pub(crate) snippet: String,
+    pub(crate) highlights: Vec<(u32, u32)>,
+    pub(crate) score: f32,
+}
+
+impl DocumentHighlight {
+    /// Builds the highlight of a document from the byte ranges of its
+    /// matches. None if the document has no matches.
+    pub(crate) fn new(
+        document: &str,
+        mut matches: Vec<(usize, usize)>,
+        score: f32,
+    ) -> Option<Self> {
+        matches.sort();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(matches.len());
+        for (start, end) in matches {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        let first = *merged.first()?;
+        let start = floor_char_boundary(document, first.0.saturating_sub(SNIPPET_CONTEXT));
+        let end = ceil_char_boundary(document, first.1.max(start + SNIPPET_LENGTH));
+        let highlights = merged
+            .into_iter()
+            .filter(|(match_start, _)| *match_start < end)
+            .map(|(match_start, match_end)| {
+                (
+                    (match_start - start) as u32,
+                    (match_end.min(end) - start) as u32,
+                )
+            })
+            .collect();
+        Some(DocumentHighlight {
+            snippet: document[start..end].to_string(),
+            highlights,
+            score,
+        })
+    }
+}
+
+fn floor_char_boundary(document: &str, mut index: usize) -> usize {
+    while !document.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(document: &str, index: usize) -> usize {
+    let mut index = index.min(document.len());
+    while !document.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+impl From<DocumentHighlight> for chroma_proto::DocumentHighlight {
+    fn from(highlight: DocumentHighlight) -> Self {
+        chroma_proto::DocumentHighlight {
+            snippet: highlight.snippet,
+            ranges: highlight
+                .highlights
+                .into_iter()
+                .map(|(start, end)| chroma_proto::HighlightRange { start, end })
+                .collect(),
+            score: highlight.score,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(DocumentHighlight::new("hello world", vec![], 0.0), None);
+
+        let highlight =
+            DocumentHighlight::new("hello world, hello", vec![(13, 18), (0, 5), (3, 8)], 1.5)
+                .unwrap();
+        assert_eq!(highlight.snippet, "hello world, hello");
+        assert_eq!(highlight.highlights, vec![(0, 8), (13, 18)]);
+        assert_eq!(highlight.score, 1.5);
+    }
+
+    #[test]
+    fn test_snippet_window() {
+        // The match is preceded by 50 bytes of two byte characters.
+        let document = format!("{}needle{}", "é".repeat(25), "x".repeat(200));
+        let highlight = DocumentHighlight::new(&document, vec![(50, 56)], 1.0).unwrap();
+        assert_eq!(highlight.snippet.len(), SNIPPET_LENGTH);
+        assert!(highlight.snippet.starts_with(&"é".repeat(5)));
+        assert_eq!(highlight.highlights, vec![(40, 46)]);
+        assert_eq!(&highlight.snippet[40..46], "needle");
+    }
+
+    #[test]
+    fn test_match_past_snippet_end() {
+        // The second match starts in the snippet and ends after it, the third
+        // starts after it.
+        let document = format!("needle{}needle{}needle", "x".repeat(151), "x".repeat(20));
+        let highlight =
+            DocumentHighlight::new(&document, vec![(0, 6), (157, 163), (183, 189)], 1.0).unwrap();
+        assert_eq!(highlight.snippet.len(), SNIPPET_LENGTH);
+        assert_eq!(highlight.highlights, vec![(0, 6), (157, 160)]);
+        assert_eq!(&highlight.snippet[157..160], "nee");
+    }
+}
@@ -7,16 +7,22 @@ use thiserror::Error;
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum UpdateMetadataValue {
-    Int(i32),
+    Int(i64),
     Float(f64),
     Str(String),
     Bool(bool),
+    IntList(Vec<i64>),
+    FloatList(Vec<f64>),
+    StrList(Vec<String>),
+    BoolList(Vec<bool>),
     None,
 }
 
 #[derive(Error, Debug)]
 pub(crate) enum UpdateMetadataValueConversionError {
-    #[error("Invalid metadata value, valid values are: Int, Float, Str, Bool, None")]
+    #[error(
+        "Invalid metadata value, valid values are: Int, Float, Str, Bool, lists of them, None"
+    )]
     InvalidValue,
 }
 
@@ -34,7 +40,7 @@ impl TryFrom<&chroma_proto::UpdateMetadataValue> for UpdateMetadataValue {
     fn try_from(value: &chroma_proto::UpdateMetadataValue) -> Result<Self, Self::Error> {
         match &value.value {
             Some(chroma_proto::update_metadata_value::Value::IntValue(value)) => {
-                Ok(UpdateMetadataValue::Int(*value as i32))
+                Ok(UpdateMetadataValue::Int(*value))
             }
             Some(chroma_proto::update_metadata_value::Value::FloatValue(value)) => {
                 Ok(UpdateMetadataValue::Float(*value))
@@ -45,6 +51,18 @@ impl TryFrom<&chroma_proto::UpdateMetadataValue> for UpdateMetadataValue {
             Some(chroma_proto::update_metadata_value::Value::BoolValue(value)) => {
                 Ok(UpdateMetadataValue::Bool(*value))
             }
+            Some(chroma_proto::update_metadata_value::Value::IntListValue(value)) => {
+                Ok(UpdateMetadataValue::IntList(value.values.clone()))
+            }
+            Some(chroma_proto::update_metadata_value::Value::FloatListValue(value)) => {
+                Ok(UpdateMetadataValue::FloatList(value.values.clone()))
+            }
+            Some(chroma_proto::update_metadata_value::Value::StringListValue(value)) => {
+                Ok(UpdateMetadataValue::StrList(value.values.clone()))
+            }
+            Some(chroma_proto::update_metadata_value::Value::BoolListValue(value)) => {
+                Ok(UpdateMetadataValue::BoolList(value.values.clone()))
+            }
             // Used to communicate that the user wants to delete this key.
             None => Ok(UpdateMetadataValue::None),
             _ => Err(UpdateMetadataValueConversionError::InvalidValue),
@@ -56,9 +74,7 @@ impl From<UpdateMetadataValue> for chroma_proto::UpdateMetadataValue {
     fn from(value: UpdateMetadataValue) -> Self {
         let proto_value = match value {
             UpdateMetadataValue::Int(value) => chroma_proto::UpdateMetadataValue {
-                value: Some(chroma_proto::update_metadata_value::Value::IntValue(
-                    value as i64,
-                )),
+                value: Some(chroma_proto::update_metadata_value::Value::IntValue(value)),
             },
             UpdateMetadataValue::Float(value) => chroma_proto::UpdateMetadataValue {
                 value: Some(chroma_proto::update_metadata_value::Value::FloatValue(
@@ -73,6 +89,26 @@ impl From<UpdateMetadataValue> for chroma_proto::UpdateMetadataValue {
             UpdateMetadataValue::Bool(value) => chroma_proto::UpdateMetadataValue {
                 value: Some(chroma_proto::update_metadata_value::Value::BoolValue(value)),
             },
+            UpdateMetadataValue::IntList(values) => chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::IntListValue(
+                    chroma_proto::IntListValue { values },
+                )),
+            },
+            UpdateMetadataValue::FloatList(values) => chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::FloatListValue(
+                    chroma_proto::DoubleListValue { values },
+                )),
+            },
+            UpdateMetadataValue::StrList(values) => chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::StringListValue(
+                    chroma_proto::StringListValue { values },
+                )),
+            },
+            UpdateMetadataValue::BoolList(values) => chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::BoolListValue(
+                    chroma_proto::BoolListValue { values },
+                )),
+            },
             UpdateMetadataValue::None => chroma_proto::UpdateMetadataValue { value: None },
         };
         proto_value
@@ -88,6 +124,10 @@ impl TryFrom<&UpdateMetadataValue> for MetadataValue {
             UpdateMetadataValue::Float(value) => Ok(MetadataValue::Float(*value)),
             UpdateMetadataValue::Str(value) => Ok(MetadataValue::Str(value.clone())),
             UpdateMetadataValue::Bool(value) => Ok(MetadataValue::Bool(*value)),
+            UpdateMetadataValue::IntList(values) => Ok(MetadataValue::IntList(values.clone())),
+            UpdateMetadataValue::FloatList(values) => Ok(MetadataValue::FloatList(values.clone())),
+            UpdateMetadataValue::StrList(values) => Ok(MetadataValue::StrList(values.clone())),
+            UpdateMetadataValue::BoolList(values) => Ok(MetadataValue::BoolList(values.clone())),
             UpdateMetadataValue::None => Err(MetadataValueConversionError::InvalidValue),
         }
     }
@@ -101,13 +141,52 @@ MetadataValue
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum MetadataValue {
-    Int(i32),
+    Int(i64),
     Float(f64),
     Str(String),
     Bool(bool),
+    IntList(Vec<i64>),
+    FloatList(Vec<f64>),
+    StrList(Vec<String>),
+    BoolList(Vec<bool>),
 }
 
-impl TryFrom<&MetadataValue> for i32 {
+impl MetadataValue {
+    pub(crate) fn is_list(&self) -> bool {
+        matches!(
+            self,
+            MetadataValue::IntList(_)
+                | MetadataValue::FloatList(_)
+                | MetadataValue::StrList(_)
+                | MetadataValue::BoolList(_)
+        )
+    }
+
+    /// The values that are indexed for this value, one per element for lists.
+    pub(crate) fn elements(&self) -> Vec<MetadataValue> {
+        match self {
+            MetadataValue::IntList(values) => values
+                .iter()
+                .map(|value| MetadataValue::Int(*value))
+                .collect(),
+            MetadataValue::FloatList(values) => values
+                .iter()
+                .map(|value| MetadataValue::Float(*value))
+                .collect(),
+            MetadataValue::StrList(values) => values
+                .iter()
+                .map(|value| MetadataValue::Str(value.clone()))
+                .collect(),
+            MetadataValue::BoolList(values) => values
+                .iter()
+                .map(|value| MetadataValue::Bool(*value))
+                .collect(),
+            value => vec![value.clone()],
+        }
+    }
+}
+
+impl TryFrom<&MetadataValue> for i64 {
     type Error = MetadataValueConversionError;
 
     fn try_from(value: &MetadataValue) -> Result<Self, Self::Error> {
@@ -118,6 +197,19 @@ impl TryFrom<&MetadataValue> for i32 {
     }
 }
 
+impl TryFrom<&MetadataValue> for i32 {
+    type Error = MetadataValueConversionError;
+
+    fn try_from(value: &MetadataValue) -> Result<Self, Self::Error> {
+        match value {
+            MetadataValue::Int(value) => {
+                i32::try_from(*value).map_err(|_| MetadataValueConversionError::InvalidValue)
+            }
+            _ => Err(MetadataValueConversionError::InvalidValue),
+        }
+    }
+}
+
 impl TryFrom<&MetadataValue> for f64 {
     type Error = MetadataValueConversionError;
 
@@ -153,7 +245,7 @@ impl TryFrom<&MetadataValue> for String {
 
 #[derive(Error, Debug)]
 pub(crate) enum MetadataValueConversionError {
-    #[error("Invalid metadata value, valid values are: Int, Float, Str")]
+    #[error("Invalid metadata value, valid values are: Int, Float, Str, Bool and lists of them")]
     InvalidValue,
 }
 
@@ -171,7 +263,7 @@ impl TryFrom<&chroma_proto::UpdateMetadataValue> for MetadataValue {
     fn try_from(value: &chroma_proto::UpdateMetadataValue) -> Result<Self, Self::Error> {
         match &value.value {
             Some(chroma_proto::update_metadata_value::Value::IntValue(value)) => {
-                Ok(MetadataValue::Int(*value as i32))
+                Ok(MetadataValue::Int(*value))
             }
             Some(chroma_proto::update_metadata_value::Value::FloatValue(value)) => {
                 Ok(MetadataValue::Float(*value))
@@ -182,6 +274,18 @@ impl TryFrom<&chroma_proto::UpdateMetadataValue> for MetadataValue {
             Some(chroma_proto::update_metadata_value::Value::BoolValue(value)) => {
                 Ok(MetadataValue::Bool(*value))
             }
+            Some(chroma_proto::update_metadata_value::Value::IntListValue(value)) => {
+                Ok(MetadataValue::IntList(value.values.clone()))
+            }
+            Some(chroma_proto::update_metadata_value::Value::FloatListValue(value)) => {
+                Ok(MetadataValue::FloatList(value.values.clone()))
+            }
+            Some(chroma_proto::update_metadata_value::Value::StringListValue(value)) => {
+                Ok(MetadataValue::StrList(value.values.clone()))
+            }
+            Some(chroma_proto::update_metadata_value::Value::BoolListValue(value)) => {
+                Ok(MetadataValue::BoolList(value.values.clone()))
+            }
             _ => Err(MetadataValueConversionError::InvalidValue),
         }
     }
@@ -191,9 +295,7 @@ impl From<MetadataValue> for chroma_proto::UpdateMetadataValue {
     fn from(value: MetadataValue) -> Self {
         let proto_value = match value {
             MetadataValue::Int(value) => chroma_proto::UpdateMetadataValue {
-                value: Some(chroma_proto::update_metadata_value::Value::IntValue(
-                    value as i64,
-                )),
+                value: Some(chroma_proto::update_metadata_value::Value::IntValue(value)),
             },
             MetadataValue::Float(value) => chroma_proto::UpdateMetadataValue {
                 value: Some(chroma_proto::update_metadata_value::Value::FloatValue(
@@ -208,6 +310,26 @@ impl From<MetadataValue> for chroma_proto::UpdateMetadataValue {
             MetadataValue::Bool(value) => chroma_proto::UpdateMetadataValue {
                 value: Some(chroma_proto::update_metadata_value::Value::BoolValue(value)),
             },
+            MetadataValue::IntList(values) => chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::IntListValue(
+                    chroma_proto::IntListValue { values },
+                )),
+            },
+            MetadataValue::FloatList(values) => chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::FloatListValue(
+                    chroma_proto::DoubleListValue { values },
+                )),
+            },
+            MetadataValue::StrList(values) => chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::StringListValue(
+                    chroma_proto::StringListValue { values },
+                )),
+            },
+            MetadataValue::BoolList(values) => chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::BoolListValue(
+                    chroma_proto::BoolListValue { values },
+                )),
+            },
         };
         proto_value
     }
@@ -330,16 +452,18 @@ pub(crate) struct DirectComparison {
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum WhereComparison {
     SingleStringComparison(String, WhereClauseComparator),
-    SingleIntComparison(u32, WhereClauseComparator),
+    SingleIntComparison(i64, WhereClauseComparator),
     SingleDoubleComparison(f64, WhereClauseComparator),
     StringListComparison(Vec<String>, WhereClauseListOperator),
-    IntListComparison(Vec<u32>, WhereClauseListOperator),
+    IntListComparison(Vec<i64>, WhereClauseListOperator),
     DoubleListComparison(Vec<f64>, WhereClauseListOperator),
     BoolListComparison(Vec<bool>, WhereClauseListOperator),
     SingleBoolComparison(bool, WhereClauseComparator),
+    // Whether the record has the key, whatever the type of its value.
+    Exists(bool),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum MetadataType {
     StringType,
     IntType,
@@ -355,6 +479,8 @@ pub(crate) enum MetadataType {
 pub(crate) enum WhereClauseComparator {
     Equal,
     NotEqual,
+    // A list value has the operand as an element, a scalar value equals it.
+    Contains,
     GreaterThan,
     GreaterThanOrEqual,
     LessThan,
@@ -365,6 +491,8 @@ pub(crate) enum WhereClauseComparator {
 pub(crate) enum WhereClauseListOperator {
     In,
     NotIn,
+    ContainsAny,
+    ContainsAll,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -395,18 +523,29 @@ pub(crate) struct DirectDocumentComparison {
 pub(crate) enum WhereDocumentOperator {
     Contains,
     NotContains,
+    Regex,
+    Like,
+    Search,
+    // The number of edits allowed between the value and the document.
+    Fuzzy(u32),
 }
 
+// The edit budget of a fuzzy comparison that does not set one.
+const DEFAULT_MAX_EDITS: u32 = 1;
+
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) struct WhereDocumentChildren {
     pub children: Vec<WhereDocument>,
     pub operator: BooleanOperator,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Error, Clone, Debug, PartialEq)]
 pub(crate) enum WhereConversionError {
+    #[error("Invalid where clause, it has no comparison or children")]
     InvalidWhere,
+    #[error("Invalid where comparison, its value or operator is not supported")]
     InvalidWhereComparison,
+    #[error("Invalid where children, their operator is not supported")]
     InvalidWhereChildren,
 }
 
@@ -502,7 +641,7 @@ impl TryFrom<chroma_proto::DirectComparison> for WhereComparison {
                     None => WhereClauseComparator::Equal,
                 };
                 Ok(WhereComparison::SingleIntComparison(
-                    proto_int.value as u32,
+                    proto_int.value,
                     comparator,
                 ))
             }
@@ -559,7 +698,7 @@ impl TryFrom<chroma_proto::DirectComparison> for WhereComparison {
                         Err(_) => return Err(WhereConversionError::InvalidWhereComparison),
                     };
                 Ok(WhereComparison::IntListComparison(
-                    proto_list.values.into_iter().map(|v| v as u32).collect(),
+                    proto_list.values,
                     list_operator.try_into()?,
                 ))
             }
@@ -587,6 +726,9 @@ impl TryFrom<chroma_proto::DirectComparison> for WhereComparison {
                     list_operator.try_into()?,
                 ))
             }
+            Some(chroma_proto::direct_comparison::Comparison::ExistsOperand(proto_exists)) => {
+                Ok(WhereComparison::Exists(proto_exists.exists))
+            }
             None => Err(WhereConversionError::InvalidWhereComparison),
         }
     }
@@ -612,6 +754,7 @@ impl TryFrom<chroma_proto::GenericComparator> for WhereClauseComparator {
         match proto_comparator {
             chroma_proto::GenericComparator::Eq => Ok(WhereClauseComparator::Equal),
             chroma_proto::GenericComparator::Ne => Ok(WhereClauseComparator::NotEqual),
+            chroma_proto::GenericComparator::ArrayContains => Ok(WhereClauseComparator::Contains),
         }
     }
 }
@@ -623,6 +766,8 @@ impl TryFrom<chroma_proto::ListOperator> for WhereClauseListOperator {
         match proto_operator {
             chroma_proto::ListOperator::In => Ok(WhereClauseListOperator::In),
             chroma_proto::ListOperator::Nin => Ok(WhereClauseListOperator::NotIn),
+            chroma_proto::ListOperator::ContainsAny => Ok(WhereClauseListOperator::ContainsAny),
+            chroma_proto::ListOperator::ContainsAll => Ok(WhereClauseListOperator::ContainsAll),
         }
     }
 }
@@ -668,9 +813,15 @@ impl TryFrom<chroma_proto::WhereDocument> for WhereDocument {
                     Ok(operator) => operator,
                     Err(_) => return Err(WhereConversionError::InvalidWhereComparison),
                 };
+                let operator = match (operator.try_into()?, proto_comparison.max_edits) {
+                    (WhereDocumentOperator::Fuzzy(_), Some(max_edits)) => {
+                        WhereDocumentOperator::Fuzzy(max_edits)
+                    }
+                    (operator, _) => operator,
+                };
                 let comparison = DirectDocumentComparison {
                     document: proto_comparison.document,
-                    operator: operator.try_into()?,
+                    operator,
                 };
                 Ok(WhereDocument::DirectWhereDocumentComparison(comparison))
             }
@@ -705,6 +856,12 @@ impl TryFrom<chroma_proto::WhereDocumentOperator> for WhereDocumentOperator {
             chroma_proto::WhereDocumentOperator::NotContains => {
                 Ok(WhereDocumentOperator::NotContains)
             }
+            chroma_proto::WhereDocumentOperator::Regex => Ok(WhereDocumentOperator::Regex),
+            chroma_proto::WhereDocumentOperator::Like => Ok(WhereDocumentOperator::Like),
+            chroma_proto::WhereDocumentOperator::Search => Ok(WhereDocumentOperator::Search),
+            chroma_proto::WhereDocumentOperator::Fuzzy => {
+                Ok(WhereDocumentOperator::Fuzzy(DEFAULT_MAX_EDITS))
+            }
         }
     }
 }
@@ -754,6 +911,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_int_metadata_is_64_bit() {
+        for int in [i64::MIN, -1, 1 << 40, i64::MAX] {
+            let proto_value = chroma_proto::UpdateMetadataValue {
+                value: Some(chroma_proto::update_metadata_value::Value::IntValue(int)),
+            };
+            let value: MetadataValue = (&proto_value).try_into().unwrap();
+            assert_eq!(value, MetadataValue::Int(int));
+            let proto_value: chroma_proto::UpdateMetadataValue = value.clone().into();
+            assert_eq!(
+                proto_value.value,
+                Some(chroma_proto::update_metadata_value::Value::IntValue(int))
+            );
+            assert_eq!(i64::try_from(&value).unwrap(), int);
+        }
+        assert!(i32::try_from(&MetadataValue::Int(1 << 40)).is_err());
+    }
+
     #[test]
     fn test_metadata_try_from() {
         let mut proto_metadata = chroma_proto::UpdateMetadata {
@@ -827,6 +1002,125 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_list_metadata_try_from() {
+        let proto_value = chroma_proto::UpdateMetadataValue {
+            value: Some(chroma_proto::update_metadata_value::Value::StringListValue(
+                chroma_proto::StringListValue {
+                    values: vec!["a".to_string(), "b".to_string()],
+                },
+            )),
+        };
+        let value: MetadataValue = (&proto_value).try_into().unwrap();
+        assert_eq!(
+            value,
+            MetadataValue::StrList(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            value.elements(),
+            vec![
+                MetadataValue::Str("a".to_string()),
+                MetadataValue::Str("b".to_string())
+            ]
+        );
+        let round_trip: chroma_proto::UpdateMetadataValue = value.into();
+        assert_eq!(round_trip, proto_value);
+
+        let update_value = UpdateMetadataValue::IntList(vec![-1, 1 << 40]);
+        let proto_value: chroma_proto::UpdateMetadataValue = update_value.clone().into();
+        assert_eq!(
+            UpdateMetadataValue::try_from(&proto_value).unwrap(),
+            update_value
+        );
+        assert_eq!(
+            MetadataValue::try_from(&update_value).unwrap(),
+            MetadataValue::IntList(vec![-1, 1 << 40])
+        );
+    }
+
+    #[test]
+    fn test_where_clause_contains_from() {
+        let proto_where = chroma_proto::Where {
+            r#where: Some(chroma_proto::r#where::Where::DirectComparison(
+                chroma_proto::DirectComparison {
+                    key: "tags".to_string(),
+                    comparison: Some(
+                        chroma_proto::direct_comparison::Comparison::StringListOperand(
+                            chroma_proto::StringListComparison {
+                                values: vec!["a".to_string(), "b".to_string()],
+                                list_operator: chroma_proto::ListOperator::ContainsAll as i32,
+                            },
+                        ),
+                    ),
+                },
+            )),
+        };
+        let where_clause: Where = proto_where.try_into().unwrap();
+        assert_eq!(
+            where_clause,
+            Where::DirectWhereComparison(DirectComparison {
+                key: "tags".to_string(),
+                comparison: WhereComparison::StringListComparison(
+                    vec!["a".to_string(), "b".to_string()],
+                    WhereClauseListOperator::ContainsAll,
+                ),
+            })
+        );
+
+        let proto_where = chroma_proto::Where {
+            r#where: Some(chroma_proto::r#where::Where::DirectComparison(
+                chroma_proto::DirectComparison {
+                    key: "tags".to_string(),
+                    comparison: Some(
+                        chroma_proto::direct_comparison::Comparison::SingleIntOperand(
+                            chroma_proto::SingleIntComparison {
+                                value: 7,
+                                comparator: Some(
+                                    chroma_proto::single_int_comparison::Comparator::GenericComparator(
+                                        chroma_proto::GenericComparator::ArrayContains as i32,
+                                    ),
+                                ),
+                            },
+                        ),
+                    ),
+                },
+            )),
+        };
+        let where_clause: Where = proto_where.try_into().unwrap();
+        assert_eq!(
+            where_clause,
+            Where::DirectWhereComparison(DirectComparison {
+                key: "tags".to_string(),
+                comparison: WhereComparison::SingleIntComparison(
+                    7,
+                    WhereClauseComparator::Contains
+                ),
+            })
+        );
+    }
+
+    #[test]
+    fn test_where_clause_exists_from() {
+        let proto_where = chroma_proto::Where {
+            r#where: Some(chroma_proto::r#where::Where::DirectComparison(
+                chroma_proto::DirectComparison {
+                    key: "author".to_string(),
+                    comparison: Some(chroma_proto::direct_comparison::Comparison::ExistsOperand(
+                        chroma_proto::ExistsComparison { exists: false },
+                    )),
+                },
+            )),
+        };
+        let where_clause: Where = proto_where.try_into().unwrap();
+        assert_eq!(
+            where_clause,
+            Where::DirectWhereComparison(DirectComparison {
+                key: "author".to_string(),
+                comparison: WhereComparison::Exists(false),
+            })
+        );
+    }
+
     #[test]
     fn test_where_clause_with_children() {
         let proto_where = chroma_proto::Where {
@@ -887,6 +1181,7 @@ mod tests {
                     operator: chroma_proto::WhereDocumentOperator::Contains
                         .try_into()
                         .unwrap(),
+                    max_edits: None,
                 },
             )),
         };
@@ -900,6 +1195,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_where_document_fuzzy() {
+        let proto_comparison = |max_edits| chroma_proto::WhereDocument {
+            r#where_document: Some(chroma_proto::where_document::WhereDocument::Direct(
+                chroma_proto::DirectWhereDocument {
+                    document: "foo".to_string(),
+                    operator: chroma_proto::WhereDocumentOperator::Fuzzy.into(),
+                    max_edits,
+                },
+            )),
+        };
+        for (max_edits, expected) in [(None, 1), (Some(0), 0), (Some(2), 2)] {
+            let where_document: WhereDocument = proto_comparison(max_edits).try_into().unwrap();
+            match where_document {
+                WhereDocument::DirectWhereDocumentComparison(comparison) => {
+                    assert_eq!(comparison.operator, WhereDocumentOperator::Fuzzy(expected));
+                }
+                _ => panic!("Invalid where document type"),
+            }
+        }
+    }
+
     #[test]
     fn test_where_document_with_children() {
         let proto_where = chroma_proto::WhereDocument {
@@ -914,6 +1231,7 @@ mod tests {
                                         operator: chroma_proto::WhereDocumentOperator::Contains
                                             .try_into()
                                             .unwrap(),
+                                        max_edits: None,
                                     },
                                 ),
                             ),
@@ -926,6 +1244,7 @@ mod tests {
                                         operator: chroma_proto::WhereDocumentOperator::Contains
                                             .try_into()
                                             .unwrap(),
+                                        max_edits: None,
                                     },
                                 ),
                             ),
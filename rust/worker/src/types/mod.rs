@@ -1,23 +1,33 @@
 #[macro_use]
 mod types;
+mod aggregate;
 mod collection;
 mod flush;
+mod highlight;
 mod metadata;
 mod operation;
+mod order_by;
 mod record;
 mod scalar_encoding;
+mod search_options;
 mod segment;
 mod segment_scope;
+mod sparse_vector;
 mod tenant;
 
 // Re-export the types module, so that we can use it as a single import in other modules.
+pub(crate) use aggregate::*;
 pub(crate) use collection::*;
 pub(crate) use flush::*;
+pub(crate) use highlight::*;
 pub(crate) use metadata::*;
 pub(crate) use operation::*;
+pub(crate) use order_by::*;
 pub(crate) use record::*;
 pub(crate) use scalar_encoding::*;
+pub(crate) use search_options::*;
 pub(crate) use segment::*;
 pub(crate) use segment_scope::*;
+pub(crate) use sparse_vector::*;
 pub(crate) use tenant::*;
 pub(crate) use types::*;
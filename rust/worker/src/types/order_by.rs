@@ -0,0 +1,99 @@
+use super::MetadataValue;
+use crate::chroma_proto;
+use std::cmp::Ordering;
+
+/// Orders the records of a metadata query by the value of a metadata key.
+/// # Description
+/// Values are ordered by type first, booleans before numbers before strings,
+/// and then by value. Ints and floats compare as numbers. A record with a list
+/// value is ordered by the element that comes first. Records without the key
+/// come last in either direction.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OrderBy {
+    pub(crate) key: String,
+    pub(crate) descending: bool,
+}
+
+impl OrderBy {
+    /// Compares two scalar values of the key in the order of the results.
+    pub(crate) fn compare(&self, a: &MetadataValue, b: &MetadataValue) -> Ordering {
+        let ordering = type_rank(a).cmp(&type_rank(b)).then_with(|| match (a, b) {
+            (MetadataValue::Bool(a), MetadataValue::Bool(b)) => a.cmp(b),
+            (MetadataValue::Str(a), MetadataValue::Str(b)) => a.cmp(b),
+            (MetadataValue::Int(a), MetadataValue::Int(b)) => a.cmp(b),
+            (a, b) => as_f64(a).total_cmp(&as_f64(b)),
+        });
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+fn type_rank(value: &MetadataValue) -> u8 {
+    match value {
+        MetadataValue::Bool(_) | MetadataValue::BoolList(_) => 0,
+        MetadataValue::Str(_) | MetadataValue::StrList(_) => 2,
+        _ => 1,
+    }
+}
+
+fn as_f64(value: &MetadataValue) -> f64 {
+    match value {
+        MetadataValue::Int(value) => *value as f64,
+        MetadataValue::Float(value) => *value,
+        _ => f64::NAN,
+    }
+}
+
+impl From<chroma_proto::OrderBy> for OrderBy {
+    fn from(proto_order_by: chroma_proto::OrderBy) -> Self {
+        OrderBy {
+            key: proto_order_by.key,
+            descending: proto_order_by.descending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let ascending = OrderBy {
+            key: String::from("key"),
+            descending: false,
+        };
+        let mut values = vec![
+            MetadataValue::Str(String::from("b")),
+            MetadataValue::Float(1.5),
+            MetadataValue::Int(-3),
+            MetadataValue::Bool(true),
+            MetadataValue::Int(2),
+            MetadataValue::Str(String::from("a")),
+        ];
+        values.sort_by(|a, b| ascending.compare(a, b));
+        assert_eq!(
+            values,
+            vec![
+                MetadataValue::Bool(true),
+                MetadataValue::Int(-3),
+                MetadataValue::Float(1.5),
+                MetadataValue::Int(2),
+                MetadataValue::Str(String::from("a")),
+                MetadataValue::Str(String::from("b")),
+            ]
+        );
+
+        let descending = OrderBy {
+            key: String::from("key"),
+            descending: true,
+        };
+        assert_eq!(
+            descending.compare(&MetadataValue::Int(1), &MetadataValue::Float(2.0)),
+            Ordering::Greater
+        );
+    }
+}
@@ -1,19 +1,26 @@
 use super::{
     ConversionError, Operation, OperationConversionError, ScalarEncoding,
-    ScalarEncodingConversionError, UpdateMetadata, UpdateMetadataValue,
-    UpdateMetadataValueConversionError,
+    ScalarEncodingConversionError, SparseVector, SparseVectorConversionError, UpdateMetadata,
+    UpdateMetadataValue, UpdateMetadataValueConversionError,
 };
 use crate::{
     chroma_proto,
+    distance::{pack_bits, unpack_bits},
     errors::{ChromaError, ErrorCodes},
 };
+use half::{bf16, f16};
 use thiserror::Error;
 
 #[derive(Clone, Debug)]
 pub(crate) struct OperationRecord {
     pub(crate) id: String,
-    pub(crate) embedding: Option<Vec<f32>>, // NOTE: we only support float32 embeddings for now so this ignores the encoding
+    // Half precision embeddings are widened to f32, the encoding records how they
+    // should be stored.
+    pub(crate) embedding: Option<Vec<f32>>,
     pub(crate) encoding: Option<ScalarEncoding>,
+    // Records in collections with a sparse vector segment may also carry a
+    // sparse vector next to the dense embedding.
+    pub(crate) sparse_vector: Option<SparseVector>,
     pub(crate) metadata: Option<UpdateMetadata>,
     // Document is implemented in the python code as a special key "chroma:document" in the metadata
     // This is ugly and clunky. In the rust code we choose to make it a separate field and
@@ -42,6 +49,8 @@ pub(crate) enum RecordConversionError {
     UpdateMetadataValueConversionError(#[from] UpdateMetadataValueConversionError),
     #[error(transparent)]
     VectorConversionError(#[from] VectorConversionError),
+    #[error(transparent)]
+    SparseVectorConversionError(#[from] SparseVectorConversionError),
 }
 
 impl_base_convert_error!(RecordConversionError, {
@@ -50,6 +59,7 @@ impl_base_convert_error!(RecordConversionError, {
     RecordConversionError::ScalarEncodingConversionError(inner) => inner.code(),
     RecordConversionError::UpdateMetadataValueConversionError(inner) => inner.code(),
     RecordConversionError::VectorConversionError(inner) => inner.code(),
+    RecordConversionError::SparseVectorConversionError(inner) => inner.code(),
 });
 
 impl TryFrom<chroma_proto::OperationRecord> for OperationRecord {
@@ -72,6 +82,14 @@ impl TryFrom<chroma_proto::OperationRecord> for OperationRecord {
             None => (None, None),
         };
 
+        let sparse_vector = match operation_record_proto.sparse_vector {
+            Some(proto_sparse_vector) => match proto_sparse_vector.try_into() {
+                Ok(sparse_vector) => Some(sparse_vector),
+                Err(e) => return Err(RecordConversionError::SparseVectorConversionError(e)),
+            },
+            None => None,
+        };
+
         let (metadata, document) = match operation_record_proto.metadata {
             Some(proto_metadata) => match UpdateMetadata::try_from(proto_metadata) {
                 Ok(mut metadata) => {
@@ -92,6 +110,7 @@ impl TryFrom<chroma_proto::OperationRecord> for OperationRecord {
             id: operation_record_proto.id,
             embedding,
             encoding,
+            sparse_vector,
             metadata,
             document,
             operation,
@@ -132,24 +151,29 @@ impl TryFrom<chroma_proto::Vector> for (Vec<f32>, ScalarEncoding) {
             Err(e) => return Err(VectorConversionError::ScalarEncodingConversionError(e)),
         };
 
-        if out_encoding != ScalarEncoding::FLOAT32 {
-            // We only support float32 embeddings for now
-            return Err(VectorConversionError::UnsupportedEncoding);
-        }
-
-        let out_vector = vec_to_f32(&proto_vector.vector);
-        match (out_vector, out_encoding) {
-            (Ok(vector), encoding) => Ok((vector.to_vec(), encoding)),
-            _ => Err(VectorConversionError::DecodeError(
-                ConversionError::DecodeError,
-            )),
-        }
+        let out_vector = match out_encoding {
+            ScalarEncoding::FLOAT32 | ScalarEncoding::FLOAT16 | ScalarEncoding::BFLOAT16 => {
+                bytes_to_f32(&proto_vector.vector, &out_encoding)?
+            }
+            // Binary vectors are widened to zeros and ones, the dimension is the
+            // number of packed bits.
+            ScalarEncoding::BINARY => {
+                let dimension = proto_vector.dimension as usize;
+                if proto_vector.vector.len() != out_encoding.encoded_size(dimension) {
+                    return Err(VectorConversionError::InvalidByteLength);
+                }
+                unpack_bits(&proto_vector.vector, dimension)
+            }
+            // We only support floating point embeddings for now
+            ScalarEncoding::INT32 => return Err(VectorConversionError::UnsupportedEncoding),
+        };
+        Ok((out_vector, out_encoding))
     }
 }
 
 #[derive(Error, Debug)]
 pub(crate) enum VectorConversionError {
-    #[error("Invalid byte length, must be divisible by 4")]
+    #[error("Invalid byte length, must be divisible by the size of the encoding")]
     InvalidByteLength,
     #[error(transparent)]
     ScalarEncodingConversionError(#[from] ScalarEncodingConversionError),
@@ -165,36 +189,66 @@ impl_base_convert_error!(VectorConversionError, {
     VectorConversionError::ScalarEncodingConversionError(inner) => inner.code(),
 });
 
-/// Converts a vector of bytes to a vector of f32s
-/// # WARNING
-/// - This will only work if the machine is little endian since protobufs are little endian
-/// - TODO: convert to big endian if the machine is big endian
+/// Decodes the little endian bytes of a proto vector into f32s.
 /// # Notes
-/// This method internally uses unsafe code to convert the bytes to f32s
-fn vec_to_f32(bytes: &[u8]) -> Result<&[f32], VectorConversionError> {
-    // Transmutes a vector of bytes into vector of f32s
-
-    if bytes.len() % 4 != 0 {
+/// Protobuf bytes are little endian regardless of the machine, so each scalar is
+/// decoded explicitly rather than reinterpreting the buffer in place. Half precision
+/// scalars are widened to f32.
+fn bytes_to_f32(
+    bytes: &[u8],
+    encoding: &ScalarEncoding,
+) -> Result<Vec<f32>, VectorConversionError> {
+    let scalar_size = encoding.encoded_size(1);
+    // usize::is_multiple_of needs a newer toolchain than the worker builds with.
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    if bytes.len() % scalar_size != 0 {
         return Err(VectorConversionError::InvalidByteLength);
     }
-
-    unsafe {
-        let (pre, mid, post) = bytes.align_to::<f32>();
-        if pre.len() != 0 || post.len() != 0 {
-            return Err(VectorConversionError::InvalidByteLength);
+    let chunks = bytes.chunks_exact(scalar_size);
+    let vector = match encoding {
+        ScalarEncoding::FLOAT32 => chunks
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect(),
+        ScalarEncoding::FLOAT16 => chunks
+            .map(|chunk| f16::from_le_bytes([chunk[0], chunk[1]]).to_f32())
+            .collect(),
+        ScalarEncoding::BFLOAT16 => chunks
+            .map(|chunk| bf16::from_le_bytes([chunk[0], chunk[1]]).to_f32())
+            .collect(),
+        ScalarEncoding::INT32 | ScalarEncoding::BINARY => {
+            return Err(VectorConversionError::UnsupportedEncoding)
         }
-        return Ok(mid);
-    }
+    };
+    Ok(vector)
 }
 
-fn f32_to_vec(vector: &[f32]) -> Vec<u8> {
-    unsafe {
-        std::slice::from_raw_parts(
-            vector.as_ptr() as *const u8,
-            vector.len() * std::mem::size_of::<f32>(),
-        )
+/// Encodes f32s as the little endian bytes of a proto vector, narrowing them to
+/// half precision or packing them into bits if the encoding asks for it.
+fn f32_to_bytes(
+    vector: &[f32],
+    encoding: &ScalarEncoding,
+) -> Result<Vec<u8>, VectorConversionError> {
+    let mut bytes = Vec::with_capacity(encoding.encoded_size(vector.len()));
+    match encoding {
+        ScalarEncoding::FLOAT32 => {
+            for value in vector {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        ScalarEncoding::FLOAT16 => {
+            for value in vector {
+                bytes.extend_from_slice(&f16::from_f32(*value).to_le_bytes());
+            }
+        }
+        ScalarEncoding::BFLOAT16 => {
+            for value in vector {
+                bytes.extend_from_slice(&bf16::from_f32(*value).to_le_bytes());
+            }
+        }
+        ScalarEncoding::BINARY => bytes = pack_bits(vector),
+        ScalarEncoding::INT32 => return Err(VectorConversionError::UnsupportedEncoding),
     }
-    .to_vec()
+    Ok(bytes)
 }
 
 impl TryFrom<(Vec<f32>, ScalarEncoding, usize)> for chroma_proto::Vector {
@@ -204,7 +258,7 @@ impl TryFrom<(Vec<f32>, ScalarEncoding, usize)> for chroma_proto::Vector {
         (vector, encoding, dimension): (Vec<f32>, ScalarEncoding, usize),
     ) -> Result<Self, Self::Error> {
         let proto_vector = chroma_proto::Vector {
-            vector: f32_to_vec(&vector),
+            vector: f32_to_bytes(&vector, &encoding)?,
             encoding: encoding as i32,
             dimension: dimension as i32,
         };
@@ -266,6 +320,7 @@ impl From<MetadataEmbeddingRecord> for chroma_proto::MetadataEmbeddingRecord {
         chroma_proto::MetadataEmbeddingRecord {
             id: record.id,
             metadata: Some(record.metadata.into()),
+            highlight: None,
         }
     }
 }
@@ -319,6 +374,7 @@ mod tests {
             vector: Some(proto_vector),
             metadata: Some(metadata),
             operation: chroma_proto::Operation::Add as i32,
+            sparse_vector: None,
         };
         let converted_operation_record = OperationRecord::try_from(proto_submit).unwrap();
         assert_eq!(converted_operation_record.id, Uuid::nil().to_string());
@@ -364,6 +420,7 @@ mod tests {
             vector: Some(proto_vector),
             metadata: Some(metadata),
             operation: chroma_proto::Operation::Add as i32,
+            sparse_vector: None,
         };
         let record_log = chroma_proto::LogRecord {
             log_offset: 42,
@@ -385,4 +442,53 @@ mod tests {
         assert_eq!(metadata.get("foo").unwrap(), &UpdateMetadataValue::Int(42));
         assert_eq!(converted_log_record.record.operation, Operation::Add);
     }
+
+    #[test]
+    fn test_vector_half_precision_round_trip() {
+        let vector = vec![1.0, -2.5, 0.125];
+        for encoding in [ScalarEncoding::FLOAT16, ScalarEncoding::BFLOAT16] {
+            let proto_vector: chroma_proto::Vector =
+                (vector.clone(), encoding.clone(), 3).try_into().unwrap();
+            assert_eq!(proto_vector.vector.len(), 6);
+            let (converted_vector, converted_encoding): (Vec<f32>, ScalarEncoding) =
+                proto_vector.try_into().unwrap();
+            assert_eq!(converted_vector, vector);
+            assert_eq!(converted_encoding, encoding);
+        }
+
+        let proto_vector = chroma_proto::Vector {
+            vector: vec![0, 60, 0],
+            encoding: chroma_proto::ScalarEncoding::Float16 as i32,
+            dimension: 2,
+        };
+        let result: Result<(Vec<f32>, ScalarEncoding), _> = proto_vector.try_into();
+        assert!(matches!(
+            result,
+            Err(VectorConversionError::InvalidByteLength)
+        ));
+    }
+
+    #[test]
+    fn test_vector_binary_round_trip() {
+        let vector = vec![1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0];
+        let proto_vector: chroma_proto::Vector = (vector.clone(), ScalarEncoding::BINARY, 10)
+            .try_into()
+            .unwrap();
+        assert_eq!(proto_vector.vector, vec![0b0000_1101, 0b0000_0011]);
+        let (converted_vector, converted_encoding): (Vec<f32>, ScalarEncoding) =
+            proto_vector.try_into().unwrap();
+        assert_eq!(converted_vector, vector);
+        assert_eq!(converted_encoding, ScalarEncoding::BINARY);
+
+        let proto_vector = chroma_proto::Vector {
+            vector: vec![0b0000_1101],
+            encoding: chroma_proto::ScalarEncoding::Binary as i32,
+            dimension: 10,
+        };
+        let result: Result<(Vec<f32>, ScalarEncoding), _> = proto_vector.try_into();
+        assert!(matches!(
+            result,
+            Err(VectorConversionError::InvalidByteLength)
+        ));
+    }
 }
@@ -5,15 +5,32 @@ use crate::{
 };
 use thiserror::Error;
 
+// The variants follow the proto enum names.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum ScalarEncoding {
     FLOAT32,
     INT32,
+    FLOAT16,
+    BFLOAT16,
+    BINARY,
+}
+
+impl ScalarEncoding {
+    /// The number of bytes a vector of the given dimensionality takes in this
+    /// encoding. Binary vectors pack eight dimensions into each byte.
+    pub(crate) fn encoded_size(&self, dimensionality: usize) -> usize {
+        match self {
+            ScalarEncoding::FLOAT32 | ScalarEncoding::INT32 => dimensionality * 4,
+            ScalarEncoding::FLOAT16 | ScalarEncoding::BFLOAT16 => dimensionality * 2,
+            ScalarEncoding::BINARY => dimensionality.div_ceil(8),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
 pub(crate) enum ScalarEncodingConversionError {
-    #[error("Invalid encoding, valid encodings are: Float32, Int32")]
+    #[error("Invalid encoding, valid encodings are: Float32, Int32, Float16, BFloat16, Binary")]
     InvalidEncoding,
     #[error(transparent)]
     DecodeError(#[from] ConversionError),
@@ -30,7 +47,9 @@ impl TryFrom<chroma_proto::ScalarEncoding> for ScalarEncoding {
         match encoding {
             chroma_proto::ScalarEncoding::Float32 => Ok(ScalarEncoding::FLOAT32),
             chroma_proto::ScalarEncoding::Int32 => Ok(ScalarEncoding::INT32),
-            _ => Err(ScalarEncodingConversionError::InvalidEncoding),
+            chroma_proto::ScalarEncoding::Float16 => Ok(ScalarEncoding::FLOAT16),
+            chroma_proto::ScalarEncoding::Bfloat16 => Ok(ScalarEncoding::BFLOAT16),
+            chroma_proto::ScalarEncoding::Binary => Ok(ScalarEncoding::BINARY),
         }
     }
 }
@@ -41,14 +60,10 @@ impl TryFrom<i32> for ScalarEncoding {
     fn try_from(encoding: i32) -> Result<Self, Self::Error> {
         let maybe_encoding = chroma_proto::ScalarEncoding::try_from(encoding);
         match maybe_encoding {
-            Ok(encoding) => match encoding {
-                chroma_proto::ScalarEncoding::Float32 => Ok(ScalarEncoding::FLOAT32),
-                chroma_proto::ScalarEncoding::Int32 => Ok(ScalarEncoding::INT32),
-                _ => Err(ScalarEncodingConversionError::InvalidEncoding),
-            },
-            Err(_) => Err(ScalarEncodingConversionError::DecodeError(
-                ConversionError::DecodeError,
-            )),
+            Ok(encoding) => encoding.try_into(),
+            // Every encoding in the proto is known, so an unknown value was not
+            // produced by a client that shares our proto.
+            Err(_) => Err(ScalarEncodingConversionError::InvalidEncoding),
         }
     }
 }
@@ -62,5 +77,26 @@ mod tests {
         let proto_encoding = chroma_proto::ScalarEncoding::Float32;
         let converted_encoding: ScalarEncoding = proto_encoding.try_into().unwrap();
         assert_eq!(converted_encoding, ScalarEncoding::FLOAT32);
+        let converted_encoding: ScalarEncoding = (chroma_proto::ScalarEncoding::Bfloat16 as i32)
+            .try_into()
+            .unwrap();
+        assert_eq!(converted_encoding, ScalarEncoding::BFLOAT16);
+        let converted_encoding: ScalarEncoding = (chroma_proto::ScalarEncoding::Binary as i32)
+            .try_into()
+            .unwrap();
+        assert_eq!(converted_encoding, ScalarEncoding::BINARY);
+        let converted_encoding: Result<ScalarEncoding, _> = 42.try_into();
+        assert!(matches!(
+            converted_encoding,
+            Err(ScalarEncodingConversionError::InvalidEncoding)
+        ));
+    }
+
+    #[test]
+    fn test_scalar_encoding_encoded_size() {
+        assert_eq!(ScalarEncoding::FLOAT32.encoded_size(3), 12);
+        assert_eq!(ScalarEncoding::BFLOAT16.encoded_size(3), 6);
+        assert_eq!(ScalarEncoding::BINARY.encoded_size(8), 1);
+        assert_eq!(ScalarEncoding::BINARY.encoded_size(9), 2);
     }
 }
@@ -0,0 +1,146 @@
+use crate::{
+    chroma_proto,
+    errors::{ChromaError, ErrorCodes},
+};
+use thiserror::Error;
+
+/// Per query search parameters.
+/// # Fields
+/// - ef_search: The number of candidates the hnsw search explores instead of the
+///   `hnsw:search_ef` of the segment, lower for latency or higher for recall.
+/// - oversampling: When set, k * oversampling candidates are fetched from the index
+///   and re-ranked using the embeddings stored in the record segment.
+/// - brute_force: Skip the index and compute exact distances to every record.
+/// - nprobe: The number of inverted lists an ivf search probes. Defaults to the
+///   `ivf:nprobe` of the segment.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SearchOptions {
+    pub(crate) ef_search: Option<usize>,
+    pub(crate) oversampling: Option<f32>,
+    pub(crate) brute_force: bool,
+    pub(crate) nprobe: Option<usize>,
+}
+
+impl SearchOptions {
+    /// The number of candidates to fetch from the index in order to return k results.
+    pub(crate) fn num_candidates(&self, k: usize) -> usize {
+        let oversampled = match self.oversampling {
+            Some(oversampling) => (k as f32 * oversampling).ceil() as usize,
+            None => k,
+        };
+        std::cmp::max(k, oversampled)
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum SearchOptionsConversionError {
+    #[error("ef_search must be positive, got {0}")]
+    InvalidEfSearch(i32),
+    #[error("oversampling must be a finite number of at least 1, got {0}")]
+    InvalidOversampling(f32),
+    #[error("nprobe must be positive, got {0}")]
+    NonPositiveNprobe(i32),
+}
+
+impl ChromaError for SearchOptionsConversionError {
+    fn code(&self) -> ErrorCodes {
+        match self {
+            SearchOptionsConversionError::InvalidEfSearch(_) => ErrorCodes::InvalidArgument,
+            SearchOptionsConversionError::InvalidOversampling(_) => ErrorCodes::InvalidArgument,
+            SearchOptionsConversionError::NonPositiveNprobe(_) => ErrorCodes::InvalidArgument,
+        }
+    }
+}
+
+impl TryFrom<chroma_proto::SearchOptions> for SearchOptions {
+    type Error = SearchOptionsConversionError;
+
+    fn try_from(proto_options: chroma_proto::SearchOptions) -> Result<Self, Self::Error> {
+        let ef_search = match proto_options.ef_search {
+            Some(ef_search) if ef_search <= 0 => {
+                return Err(SearchOptionsConversionError::InvalidEfSearch(ef_search))
+            }
+            Some(ef_search) => Some(ef_search as usize),
+            None => None,
+        };
+        let oversampling = match proto_options.oversampling {
+            Some(oversampling) if !oversampling.is_finite() || oversampling < 1.0 => {
+                return Err(SearchOptionsConversionError::InvalidOversampling(
+                    oversampling,
+                ))
+            }
+            oversampling => oversampling,
+        };
+        let nprobe = match proto_options.nprobe {
+            Some(nprobe) if nprobe <= 0 => {
+                return Err(SearchOptionsConversionError::NonPositiveNprobe(nprobe))
+            }
+            Some(nprobe) => Some(nprobe as usize),
+            None => None,
+        };
+        Ok(SearchOptions {
+            ef_search,
+            oversampling,
+            brute_force: proto_options.brute_force,
+            nprobe,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_options_try_from() {
+        let proto_options = chroma_proto::SearchOptions {
+            ef_search: Some(200),
+            oversampling: Some(2.0),
+            brute_force: false,
+            nprobe: Some(4),
+        };
+        let options: SearchOptions = proto_options.try_into().unwrap();
+        assert_eq!(options.ef_search, Some(200));
+        assert_eq!(options.nprobe, Some(4));
+        assert_eq!(options.oversampling, Some(2.0));
+        assert!(!options.brute_force);
+    }
+
+    #[test]
+    fn test_search_options_rejects_invalid_values() {
+        let proto_options = chroma_proto::SearchOptions {
+            ef_search: Some(0),
+            oversampling: None,
+            brute_force: false,
+            nprobe: None,
+        };
+        assert!(SearchOptions::try_from(proto_options).is_err());
+        let proto_options = chroma_proto::SearchOptions {
+            ef_search: None,
+            oversampling: Some(0.5),
+            brute_force: false,
+            nprobe: None,
+        };
+        assert!(SearchOptions::try_from(proto_options).is_err());
+        let proto_options = chroma_proto::SearchOptions {
+            ef_search: None,
+            oversampling: None,
+            brute_force: false,
+            nprobe: Some(0),
+        };
+        assert!(SearchOptions::try_from(proto_options).is_err());
+    }
+
+    #[test]
+    fn test_num_candidates() {
+        assert_eq!(SearchOptions::default().num_candidates(10), 10);
+        let options = SearchOptions {
+            ef_search: Some(50),
+            oversampling: Some(1.5),
+            brute_force: false,
+            nprobe: None,
+        };
+        assert_eq!(options.num_candidates(10), 15);
+        assert_eq!(options.num_candidates(40), 60);
+    }
+}
@@ -12,6 +12,8 @@ pub(crate) enum SegmentType {
     HnswDistributed,
     BlockfileMetadata,
     BlockfileRecord,
+    BlockfileSparseVector,
+    BlockfileIvf,
     Sqlite,
 }
 
@@ -24,6 +26,10 @@ impl From<SegmentType> for String {
             SegmentType::BlockfileRecord => "urn:chroma:segment/record/blockfile".to_string(),
             SegmentType::Sqlite => "urn:chroma:segment/metadata/sqlite".to_string(),
             SegmentType::BlockfileMetadata => "urn:chroma:segment/metadata/blockfile".to_string(),
+            SegmentType::BlockfileSparseVector => {
+                "urn:chroma:segment/vector/sparse-blockfile".to_string()
+            }
+            SegmentType::BlockfileIvf => "urn:chroma:segment/vector/ivf-blockfile".to_string(),
         }
     }
 }
@@ -37,6 +43,8 @@ impl TryFrom<&str> for SegmentType {
             "urn:chroma:segment/record/blockfile" => Ok(SegmentType::BlockfileRecord),
             "urn:chroma:segment/metadata/sqlite" => Ok(SegmentType::Sqlite),
             "urn:chroma:segment/metadata/blockfile" => Ok(SegmentType::BlockfileMetadata),
+            "urn:chroma:segment/vector/sparse-blockfile" => Ok(SegmentType::BlockfileSparseVector),
+            "urn:chroma:segment/vector/ivf-blockfile" => Ok(SegmentType::BlockfileIvf),
             _ => Err(SegmentConversionError::InvalidSegmentType),
         }
     }
@@ -110,6 +118,8 @@ impl TryFrom<chroma_proto::Segment> for Segment {
             "urn:chroma:segment/record/blockfile" => SegmentType::BlockfileRecord,
             "urn:chroma:segment/metadata/sqlite" => SegmentType::Sqlite,
             "urn:chroma:segment/metadata/blockfile" => SegmentType::BlockfileMetadata,
+            "urn:chroma:segment/vector/sparse-blockfile" => SegmentType::BlockfileSparseVector,
+            "urn:chroma:segment/vector/ivf-blockfile" => SegmentType::BlockfileIvf,
             _ => {
                 println!("Invalid segment type: {}", proto_segment.r#type);
                 return Err(SegmentConversionError::InvalidSegmentType);
@@ -0,0 +1,146 @@
+use super::ConversionError;
+use crate::{
+    chroma_proto,
+    errors::{ChromaError, ErrorCodes},
+};
+use thiserror::Error;
+
+/// The SparseVector struct.
+/// # Description
+/// The nonzero values of a vector with many dimensions, such as learned sparse
+/// or bm25 term weights.
+/// # Notes
+/// The indices are unique and sorted in ascending order, conversions from the
+/// proto sort them so that dot products can merge two vectors in one pass.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SparseVector {
+    pub(crate) indices: Vec<u32>,
+    pub(crate) values: Vec<f32>,
+}
+
+impl SparseVector {
+    /// Computes the dot product with another sparse vector.
+    pub(crate) fn dot(&self, other: &SparseVector) -> f32 {
+        let mut result = 0.0;
+        let (mut i, mut j) = (0, 0);
+        while i < self.indices.len() && j < other.indices.len() {
+            match self.indices[i].cmp(&other.indices[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    result += self.values[i] * other.values[j];
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        result
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.indices
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum SparseVectorConversionError {
+    #[error("Sparse vector has {0} indices and {1} values")]
+    LengthMismatch(usize, usize),
+    #[error("Sparse vector has duplicate index {0}")]
+    DuplicateIndex(u32),
+    #[error(transparent)]
+    DecodeError(#[from] ConversionError),
+}
+
+impl_base_convert_error!(SparseVectorConversionError, {
+    SparseVectorConversionError::LengthMismatch(_, _) => ErrorCodes::InvalidArgument,
+    SparseVectorConversionError::DuplicateIndex(_) => ErrorCodes::InvalidArgument,
+});
+
+impl TryFrom<chroma_proto::SparseVector> for SparseVector {
+    type Error = SparseVectorConversionError;
+
+    fn try_from(proto_vector: chroma_proto::SparseVector) -> Result<Self, Self::Error> {
+        if proto_vector.indices.len() != proto_vector.values.len() {
+            return Err(SparseVectorConversionError::LengthMismatch(
+                proto_vector.indices.len(),
+                proto_vector.values.len(),
+            ));
+        }
+        let mut pairs: Vec<(u32, f32)> = proto_vector
+            .indices
+            .into_iter()
+            .zip(proto_vector.values)
+            .collect();
+        pairs.sort_by_key(|(index, _)| *index);
+        for window in pairs.windows(2) {
+            if window[0].0 == window[1].0 {
+                return Err(SparseVectorConversionError::DuplicateIndex(window[0].0));
+            }
+        }
+        let (indices, values) = pairs.into_iter().unzip();
+        Ok(SparseVector { indices, values })
+    }
+}
+
+impl From<SparseVector> for chroma_proto::SparseVector {
+    fn from(sparse_vector: SparseVector) -> Self {
+        chroma_proto::SparseVector {
+            indices: sparse_vector.indices,
+            values: sparse_vector.values,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_vector_try_from() {
+        let proto_vector = chroma_proto::SparseVector {
+            indices: vec![7, 2, 40],
+            values: vec![0.5, 1.0, 2.0],
+        };
+        let sparse_vector: SparseVector = proto_vector.try_into().unwrap();
+        assert_eq!(sparse_vector.indices, vec![2, 7, 40]);
+        assert_eq!(sparse_vector.values, vec![1.0, 0.5, 2.0]);
+
+        let proto_vector = chroma_proto::SparseVector {
+            indices: vec![7, 2, 7],
+            values: vec![0.5, 1.0, 2.0],
+        };
+        let result: Result<SparseVector, _> = proto_vector.try_into();
+        assert!(matches!(
+            result,
+            Err(SparseVectorConversionError::DuplicateIndex(7))
+        ));
+
+        let proto_vector = chroma_proto::SparseVector {
+            indices: vec![7, 2],
+            values: vec![0.5],
+        };
+        let result: Result<SparseVector, _> = proto_vector.try_into();
+        assert!(matches!(
+            result,
+            Err(SparseVectorConversionError::LengthMismatch(2, 1))
+        ));
+    }
+
+    #[test]
+    fn test_sparse_vector_dot() {
+        let a = SparseVector {
+            indices: vec![1, 4, 9],
+            values: vec![1.0, 2.0, 3.0],
+        };
+        let b = SparseVector {
+            indices: vec![0, 4, 9, 12],
+            values: vec![5.0, 0.5, 2.0, 1.0],
+        };
+        assert_eq!(a.dot(&b), 7.0);
+        assert_eq!(b.dot(&a), 7.0);
+    }
+}